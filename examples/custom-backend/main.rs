@@ -21,7 +21,8 @@ use jujutsu::cli_util::{create_ui, handle_command_result, parse_args, CommandErr
 use jujutsu::commands::{default_app, run_command};
 use jujutsu::ui::Ui;
 use jujutsu_lib::backend::{
-    Backend, BackendResult, Commit, CommitId, Conflict, ConflictId, FileId, SymlinkId, Tree, TreeId,
+    Backend, BackendResult, Commit, CommitId, Conflict, ConflictId, FileId, SigningFn, SymlinkId,
+    Tree, TreeId,
 };
 use jujutsu_lib::git_backend::GitBackend;
 use jujutsu_lib::repo::BackendFactories;
@@ -144,7 +145,11 @@ impl Backend for JitBackend {
         self.inner.read_commit(id)
     }
 
-    fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId> {
-        self.inner.write_commit(contents)
+    fn write_commit(
+        &self,
+        contents: Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<(CommitId, Commit)> {
+        self.inner.write_commit(contents, sign_with)
     }
 }
@@ -12,10 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt::{Debug, Error, Formatter};
 use std::io::Read;
 use std::result::Result;
+use std::time::SystemTime;
 use std::vec::Vec;
 
 use thiserror::Error;
@@ -257,6 +258,16 @@ pub struct Signature {
     pub timestamp: Timestamp,
 }
 
+/// A cryptographic signature of a commit, together with the bytes it signs.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct SecureSig {
+    pub data: Vec<u8>,
+    pub sig: Vec<u8>,
+}
+
+/// Signs the given commit data, returning the signature.
+pub type SigningFn<'a> = dyn FnMut(&[u8]) -> BackendResult<Vec<u8>> + 'a;
+
 #[derive(Debug, Clone)]
 pub struct Commit {
     pub parents: Vec<CommitId>,
@@ -267,13 +278,18 @@ pub struct Commit {
     pub author: Signature,
     pub committer: Signature,
     pub is_open: bool,
+    /// Set by the backend when the commit is read or written. Ignored when
+    /// writing.
+    pub secure_sig: Option<SecureSig>,
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ConflictPart {
-    // TODO: Store e.g. CommitId here too? Labels (theirs/ours/base)? Would those still be
-    //       useful e.g. after rebasing this conflict?
     pub value: TreeValue,
+    /// Where this part came from, such as the commit it was taken from. It's
+    /// shown in conflict markers so users can tell the sides apart. Parts keep
+    /// their labels when a conflict is rebased again.
+    pub label: Option<String>,
 }
 
 #[derive(Default, Debug, PartialEq, Eq, Clone)]
@@ -290,6 +306,10 @@ pub struct Conflict {
 pub enum BackendError {
     #[error("Object not found")]
     NotFound,
+    #[error("The {} object {} is corrupt", .0.type_name(), .0.hex())]
+    Corrupt(StoreObjectId),
+    #[error("Failed to sign the commit: {0}")]
+    SigningFailed(String),
     #[error("Error: {0}")]
     Other(String),
 }
@@ -339,7 +359,7 @@ impl<'a> Iterator for TreeEntriesNonRecursiveIterator<'a> {
     }
 }
 
-#[derive(Default, Debug, Clone)]
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct Tree {
     entries: BTreeMap<RepoPathComponent, TreeValue>,
 }
@@ -394,16 +414,81 @@ pub fn make_root_commit(empty_tree_id: TreeId) -> Commit {
         author: signature.clone(),
         committer: signature,
         is_open: false,
+        secure_sig: None,
     }
 }
 
+/// The objects that are reachable from some operation, and which must therefore
+/// be kept by `Backend::gc()`.
+#[derive(Default, Debug)]
+pub struct ReachableObjects {
+    pub commits: HashSet<CommitId>,
+    pub trees: HashSet<TreeId>,
+    pub files: HashSet<FileId>,
+    pub symlinks: HashSet<SymlinkId>,
+    pub conflicts: HashSet<ConflictId>,
+}
+
+/// Identifies an object of any type in the store.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum StoreObjectId {
+    Commit(CommitId),
+    Tree(TreeId),
+    File(FileId),
+    Symlink(SymlinkId),
+    Conflict(ConflictId),
+}
+
+impl StoreObjectId {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            StoreObjectId::Commit(_) => "commit",
+            StoreObjectId::Tree(_) => "tree",
+            StoreObjectId::File(_) => "file",
+            StoreObjectId::Symlink(_) => "symlink",
+            StoreObjectId::Conflict(_) => "conflict",
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            StoreObjectId::Commit(id) => id.as_bytes(),
+            StoreObjectId::Tree(id) => id.as_bytes(),
+            StoreObjectId::File(id) => id.as_bytes(),
+            StoreObjectId::Symlink(id) => id.as_bytes(),
+            StoreObjectId::Conflict(id) => id.as_bytes(),
+        }
+    }
+
+    pub fn hex(&self) -> String {
+        hex::encode(self.as_bytes())
+    }
+}
+
+/// What `Backend::gc()` removed, or would have removed in a dry run.
+#[derive(Default, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GcStats {
+    pub removed_objects: usize,
+    pub removed_bytes: u64,
+}
+
+/// Storage for commits, trees, files, symlinks, and conflicts. The local and
+/// Git backends are the built-in implementations. Other implementations can be
+/// plugged in by registering them in `BackendFactories`.
+///
+/// Objects are immutable and content-addressed, so implementations don't need
+/// to coordinate concurrent writers beyond making each write atomic. The IDs
+/// returned by the `write_*()` functions must be `hash_length()` bytes long.
 pub trait Backend: Send + Sync + Debug {
     /// A unique name that identifies this backend. Written to
     /// `.jj/repo/store/backend` when the repo is created.
     fn name(&self) -> &str;
 
+    /// The length of commit, tree, and other object IDs, in bytes.
     fn hash_length(&self) -> usize;
 
+    /// The Git repo backing this store, if any. Used by the Git-specific
+    /// commands.
     fn git_repo(&self) -> Option<git2::Repository>;
 
     fn read_file(&self, path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>>;
@@ -428,5 +513,37 @@ pub trait Backend: Send + Sync + Debug {
 
     fn read_commit(&self, id: &CommitId) -> BackendResult<Commit>;
 
-    fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId>;
+    /// Writes the commit, signing it with `sign_with` if given. Returns the
+    /// commit as written, including its signature.
+    fn write_commit(
+        &self,
+        contents: Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<(CommitId, Commit)>;
+
+    /// Removes the objects that aren't in `reachable` and that were written
+    /// before `keep_newer` (objects written by concurrent commands may not be
+    /// reachable yet). If `dry_run` is set, nothing is removed, but the
+    /// returned stats still say what would have been.
+    fn gc(
+        &self,
+        _reachable: &ReachableObjects,
+        _keep_newer: SystemTime,
+        _dry_run: bool,
+    ) -> BackendResult<GcStats> {
+        Err(BackendError::Other(format!(
+            "The {} backend doesn't support garbage collection",
+            self.name()
+        )))
+    }
+
+    /// Checks that the object's stored contents still match its ID. Returns
+    /// `BackendError::Corrupt` if they don't, including if the contents can't
+    /// be decoded, and `BackendError::NotFound` if the object is missing.
+    fn verify(&self, _object: &StoreObjectId) -> BackendResult<()> {
+        Err(BackendError::Other(format!(
+            "The {} backend doesn't support verifying objects",
+            self.name()
+        )))
+    }
 }
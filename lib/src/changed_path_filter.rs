@@ -0,0 +1,173 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Bloom filters of the paths changed by a commit, stored in the index so
+//! path-limited queries can skip most commits without diffing their trees.
+//! Like Git's changed-path filters, the filter also contains the directories
+//! the changed files are in, so it can be queried for a whole directory.
+
+use std::collections::HashSet;
+
+use crate::commit::Commit;
+use crate::matchers::EverythingMatcher;
+use crate::repo_path::RepoPath;
+
+/// Commits that change more paths than this don't get a filter, since it would
+/// be large and match most queries anyway.
+pub const MAX_CHANGED_PATHS: usize = 512;
+
+const BITS_PER_PATH: usize = 10;
+const MIN_FILTER_BYTES: usize = 8;
+const NUM_HASHES: u32 = 7;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ChangedPathFilter {
+    bits: Vec<u8>,
+}
+
+impl ChangedPathFilter {
+    /// Creates a filter containing the given files and their parent
+    /// directories. Returns `None` if there are too many of them.
+    pub fn new<'a>(files: impl IntoIterator<Item = &'a RepoPath>) -> Option<Self> {
+        let mut paths = HashSet::new();
+        for file in files {
+            let mut path = Some(file.clone());
+            while let Some(current) = path {
+                if current.is_root() || !paths.insert(current.to_internal_file_string()) {
+                    break;
+                }
+                path = current.parent();
+            }
+            if paths.len() > MAX_CHANGED_PATHS {
+                return None;
+            }
+        }
+        let num_bytes = ((paths.len() * BITS_PER_PATH + 7) / 8).max(MIN_FILTER_BYTES);
+        let mut filter = ChangedPathFilter {
+            bits: vec![0; num_bytes],
+        };
+        for path in &paths {
+            for bit in filter.bit_positions(path) {
+                filter.bits[bit / 8] |= 1 << (bit % 8);
+            }
+        }
+        Some(filter)
+    }
+
+    /// Creates a filter with the paths changed by the commit compared to its
+    /// parent. Merge commits don't get a filter, since what they change depends
+    /// on how their parents are merged.
+    pub fn for_commit(commit: &Commit) -> Option<Self> {
+        let store = commit.store();
+        let parent_tree = match commit.parent_ids() {
+            [] => store
+                .get_tree(&RepoPath::root(), store.empty_tree_id())
+                .ok()?,
+            [parent_id] => store.get_commit(parent_id).ok()?.tree(),
+            _ => return None,
+        };
+        let changed_files = parent_tree
+            .diff(&commit.tree(), &EverythingMatcher)
+            .map(|(path, _diff)| path)
+            .collect::<Vec<_>>();
+        ChangedPathFilter::new(&changed_files)
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Self {
+        ChangedPathFilter {
+            bits: bytes.to_vec(),
+        }
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bits
+    }
+
+    /// Returns false if the path, or anything under it if it's a directory,
+    /// was definitely not changed. Returns true if it may have been.
+    pub fn may_contain(&self, path: &RepoPath) -> bool {
+        if path.is_root() {
+            // Any change is under the root
+            return true;
+        }
+        self.bit_positions(&path.to_internal_file_string())
+            .all(|bit| self.bits[bit / 8] & (1 << (bit % 8)) != 0)
+    }
+
+    fn bit_positions(&self, path: &str) -> impl Iterator<Item = usize> {
+        // Double hashing, with the halves of a 64-bit FNV-1a hash
+        let mut hash: u64 = 0xcbf29ce484222325;
+        for byte in path.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x100000001b3);
+        }
+        let hash1 = hash as u32;
+        let hash2 = (hash >> 32) as u32 | 1;
+        let num_bits = self.bits.len() * 8;
+        (0..NUM_HASHES).map(move |i| hash1.wrapping_add(i.wrapping_mul(hash2)) as usize % num_bits)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn path(value: &str) -> RepoPath {
+        RepoPath::from_internal_string(value)
+    }
+
+    #[test]
+    fn test_empty_filter() {
+        let filter = ChangedPathFilter::new(&[]).unwrap();
+        assert_eq!(filter.as_bytes().len(), MIN_FILTER_BYTES);
+        assert!(!filter.may_contain(&path("file")));
+        assert!(filter.may_contain(&RepoPath::root()));
+    }
+
+    #[test]
+    fn test_filter_contains_parent_dirs() {
+        let filter = ChangedPathFilter::new(&[path("dir/sub/file"), path("other")]).unwrap();
+        assert!(filter.may_contain(&path("dir/sub/file")));
+        assert!(filter.may_contain(&path("dir/sub")));
+        assert!(filter.may_contain(&path("dir")));
+        assert!(filter.may_contain(&path("other")));
+        assert!(!filter.may_contain(&path("dir/file")));
+        assert!(!filter.may_contain(&path("dir/sub/file2")));
+        let filter = ChangedPathFilter::from_bytes(filter.as_bytes());
+        assert!(filter.may_contain(&path("dir/sub/file")));
+    }
+
+    #[test]
+    fn test_filter_false_positive_rate() {
+        let changed = (0..100)
+            .map(|i| path(&format!("dir/file{}", i)))
+            .collect::<Vec<_>>();
+        let filter = ChangedPathFilter::new(&changed).unwrap();
+        assert!(changed.iter().all(|file| filter.may_contain(file)));
+        let false_positives = (0..1000)
+            .filter(|i| filter.may_contain(&path(&format!("other/file{}", i))))
+            .count();
+        // About 1% is expected with 10 bits per path
+        assert!(false_positives < 50);
+    }
+
+    #[test]
+    fn test_too_many_paths() {
+        let changed = (0..MAX_CHANGED_PATHS)
+            .map(|i| path(&format!("dir/file{}", i)))
+            .collect::<Vec<_>>();
+        assert_eq!(ChangedPathFilter::new(&changed), None);
+        assert!(ChangedPathFilter::new(&changed[1..]).is_some());
+    }
+}
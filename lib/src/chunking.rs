@@ -0,0 +1,131 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Content-defined chunking of large files, based on FastCDC. Chunk boundaries
+//! depend only on the nearby content, so an edit in one part of a file leaves
+//! the chunks in the rest of the file unchanged.
+
+pub const MIN_CHUNK_SIZE: usize = 64 * 1024;
+pub const AVG_CHUNK_SIZE: usize = 256 * 1024;
+pub const MAX_CHUNK_SIZE: usize = 1024 * 1024;
+
+// The gear hash is shifted left for each byte, so its high bits depend on the
+// most bytes. The mask used before the average size has more bits set, which
+// makes boundaries less likely there and normalizes the chunk sizes.
+const MASK_SMALL: u64 = !0 << 44;
+const MASK_LARGE: u64 = !0 << 48;
+
+const fn gear_table() -> [u64; 256] {
+    // Fill the table using splitmix64 so it's the same on every platform
+    let mut table = [0; 256];
+    let mut state: u64 = 0;
+    let mut i = 0;
+    while i < 256 {
+        state = state.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        table[i] = z ^ (z >> 31);
+        i += 1;
+    }
+    table
+}
+
+static GEAR: [u64; 256] = gear_table();
+
+/// Returns the length of the first chunk in `data`. If no boundary is found
+/// before `MAX_CHUNK_SIZE`, the chunk is cut there, and if `data` is shorter
+/// than that, the whole of it is returned.
+pub fn first_chunk_len(data: &[u8]) -> usize {
+    if data.len() <= MIN_CHUNK_SIZE {
+        return data.len();
+    }
+    let end = data.len().min(MAX_CHUNK_SIZE);
+    let normal_end = end.min(AVG_CHUNK_SIZE);
+    let mut hash: u64 = 0;
+    for (i, byte) in data.iter().enumerate().take(end).skip(MIN_CHUNK_SIZE) {
+        hash = (hash << 1).wrapping_add(GEAR[*byte as usize]);
+        let mask = if i < normal_end {
+            MASK_SMALL
+        } else {
+            MASK_LARGE
+        };
+        if hash & mask == 0 {
+            return i + 1;
+        }
+    }
+    end
+}
+
+/// Splits `data` into content-defined chunks.
+pub fn split_chunks(mut data: &[u8]) -> Vec<&[u8]> {
+    let mut chunks = vec![];
+    while !data.is_empty() {
+        let (chunk, rest) = data.split_at(first_chunk_len(data));
+        chunks.push(chunk);
+        data = rest;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_split_small_input() {
+        assert!(split_chunks(b"").is_empty());
+        assert_eq!(split_chunks(b"abc"), vec![b"abc"]);
+    }
+
+    #[test]
+    fn test_chunk_sizes() {
+        let data = pseudo_random_bytes(8 * 1024 * 1024, 1);
+        let chunks = split_chunks(&data);
+        assert_eq!(chunks.concat(), data);
+        let (last, rest) = chunks.split_last().unwrap();
+        assert!(last.len() <= MAX_CHUNK_SIZE);
+        for chunk in rest {
+            assert!(chunk.len() > MIN_CHUNK_SIZE);
+            assert!(chunk.len() <= MAX_CHUNK_SIZE);
+        }
+        // The sizes should be somewhere around the average
+        assert!(chunks.len() > 8);
+        assert!(chunks.len() < 64);
+    }
+
+    #[test]
+    fn test_insertion_keeps_later_chunks() {
+        let data = pseudo_random_bytes(4 * 1024 * 1024, 2);
+        let mut edited = b"inserted at the start".to_vec();
+        edited.extend_from_slice(&data);
+        let chunks = split_chunks(&data);
+        let edited_chunks = split_chunks(&edited);
+        // Only the first chunk should be affected by the insertion
+        assert_ne!(chunks[0], edited_chunks[0]);
+        assert_eq!(chunks[1..], edited_chunks[1..]);
+    }
+}
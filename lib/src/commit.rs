@@ -18,7 +18,7 @@ use std::hash::{Hash, Hasher};
 use std::sync::Arc;
 
 use crate::backend;
-use crate::backend::{ChangeId, CommitId, Signature, TreeId};
+use crate::backend::{ChangeId, CommitId, SecureSig, Signature, TreeId};
 use crate::repo_path::RepoPath;
 use crate::store::Store;
 use crate::tree::Tree;
@@ -121,6 +121,10 @@ impl Commit {
         self.data.is_open
     }
 
+    pub fn secure_sig(&self) -> Option<&SecureSig> {
+        self.data.secure_sig.as_ref()
+    }
+
     pub fn is_empty(&self) -> bool {
         let parents = self.parents();
         // TODO: Perhaps the root commit should also be considered empty.
@@ -15,15 +15,30 @@
 use uuid::Uuid;
 
 use crate::backend;
-use crate::backend::{ChangeId, CommitId, Signature, TreeId};
+use crate::backend::{
+    BackendError, BackendResult, ChangeId, CommitId, Signature, SigningFn, TreeId,
+};
 use crate::commit::Commit;
+use crate::git;
 use crate::repo::MutableRepo;
 use crate::settings::UserSettings;
+use crate::signing::Signer;
 
 #[derive(Debug)]
 pub struct CommitBuilder {
     commit: backend::Commit,
     rewrite_source: Option<Commit>,
+    signer: Option<Signer>,
+}
+
+/// Returns the signer to sign all new commits with, if `signing.sign-all` is
+/// enabled.
+fn default_signer(settings: &UserSettings) -> Option<Signer> {
+    if settings.sign_all() {
+        Signer::from_settings(settings)
+    } else {
+        None
+    }
 }
 
 pub fn new_change_id() -> ChangeId {
@@ -47,10 +62,12 @@ impl CommitBuilder {
             author: signature.clone(),
             committer: signature,
             is_open: false,
+            secure_sig: None,
         };
         CommitBuilder {
             commit,
             rewrite_source: None,
+            signer: default_signer(settings),
         }
     }
 
@@ -66,9 +83,18 @@ impl CommitBuilder {
         if commit.author.email == UserSettings::user_email_placeholder() {
             commit.author.email = commit.committer.email.clone();
         }
+        // The old signature doesn't cover the new commit, so re-sign commits that
+        // were signed. Writing the commit fails if it can't be signed (e.g. if the
+        // key isn't available), rather than silently dropping the signature.
+        let signer = if predecessor.secure_sig().is_some() {
+            Signer::from_settings(settings)
+        } else {
+            default_signer(settings)
+        };
         CommitBuilder {
             commit,
             rewrite_source: Some(predecessor.clone()),
+            signer,
         }
     }
 
@@ -87,10 +113,12 @@ impl CommitBuilder {
             author: signature.clone(),
             committer: signature,
             is_open: true,
+            secure_sig: None,
         };
         CommitBuilder {
             commit,
             rewrite_source: None,
+            signer: default_signer(settings),
         }
     }
 
@@ -140,17 +168,50 @@ impl CommitBuilder {
         self
     }
 
+    /// Signs the commit with `signer` instead of according to
+    /// `signing.sign-all`.
+    pub fn set_signer(mut self, signer: Option<Signer>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Writes the commit, panicking if that fails. Commits may be signed, which
+    /// can fail, so use `try_write_to_repo()` unless signing is known to be
+    /// disabled (e.g. in tests).
     pub fn write_to_repo(self, repo: &mut MutableRepo) -> Commit {
+        self.try_write_to_repo(repo).unwrap()
+    }
+
+    /// Like `write_to_repo()` but returns an error if the commit couldn't be
+    /// written (e.g. because signing it failed).
+    pub fn try_write_to_repo(self, repo: &mut MutableRepo) -> BackendResult<Commit> {
         let mut rewrite_source_id = None;
         if let Some(rewrite_source) = self.rewrite_source {
             if *rewrite_source.change_id() == self.commit.change_id {
                 rewrite_source_id.replace(rewrite_source.id().clone());
             }
         }
-        let commit = repo.write_commit(self.commit);
+        let mut sign = self
+            .signer
+            .map(|signer| move |data: &[u8]| signer.sign(data).map_err(BackendError::from));
+        let commit = repo.write_commit(
+            self.commit,
+            sign.as_mut().map(|sign| sign as &mut SigningFn),
+        )?;
         if let Some(rewrite_source_id) = rewrite_source_id {
+            if let Some(git_repo) = repo.store().git_repo() {
+                // The notes are only metadata, so failing to copy them shouldn't fail the
+                // rewrite
+                git::copy_notes_for_rewrite(
+                    &git_repo,
+                    &rewrite_source_id,
+                    commit.id(),
+                    commit.committer(),
+                )
+                .ok();
+            }
             repo.record_rewritten_commit(rewrite_source_id, commit.id().clone())
         }
-        commit
+        Ok(commit)
     }
 }
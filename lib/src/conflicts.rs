@@ -12,63 +12,256 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::fmt::{Display, Formatter};
 use std::io::{Cursor, Write};
 
 use itertools::Itertools;
 
-use crate::backend::{BackendResult, Conflict, ConflictId, ConflictPart, TreeValue};
+use crate::backend::{BackendResult, Conflict, ConflictId, ConflictPart, TreeId, TreeValue};
 use crate::diff::{find_line_ranges, Diff, DiffHunk};
 use crate::files;
 use crate::files::{MergeHunk, MergeResult};
-use crate::repo_path::RepoPath;
+use crate::repo_path::{RepoPath, RepoPathJoin};
 use crate::store::Store;
 
-const CONFLICT_START_LINE: &[u8] = b"<<<<<<<\n";
-const CONFLICT_END_LINE: &[u8] = b">>>>>>>\n";
-const CONFLICT_DIFF_LINE: &[u8] = b"%%%%%%%\n";
-const CONFLICT_MINUS_LINE: &[u8] = b"-------\n";
-const CONFLICT_PLUS_LINE: &[u8] = b"+++++++\n";
+// These markers are followed by a description of the sides if the conflict's
+// parts are labeled.
+const CONFLICT_START_MARKER: &[u8] = b"<<<<<<<";
+const CONFLICT_END_MARKER: &[u8] = b">>>>>>>";
+const CONFLICT_DIFF_MARKER: &[u8] = b"%%%%%%%";
+const CONFLICT_MINUS_MARKER: &[u8] = b"-------";
+const CONFLICT_PLUS_MARKER: &[u8] = b"+++++++";
+// Markers used by the Git-like styles
+const CONFLICT_BASE_MARKER: &[u8] = b"|||||||";
+const CONFLICT_SEPARATOR_MARKER: &[u8] = b"=======";
 
-fn describe_conflict_part(part: &ConflictPart) -> String {
+/// How conflicts between regular files are written with conflict markers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictMarkerStyle {
+    /// The changes from a base to one side as a diff, followed by the contents
+    /// of the other side.
+    Diff,
+    /// The contents of the two sides, without the base, like Git's default
+    /// `merge` style.
+    Minimal,
+    /// The contents of the two sides with the base between them, like Git's
+    /// `diff3` style.
+    Diff3,
+    /// Like `Diff3`, but lines that the two sides have in common at the start
+    /// and end of a conflict are moved out of it, like Git's `zdiff3` style.
+    Zdiff3,
+}
+
+impl Default for ConflictMarkerStyle {
+    fn default() -> Self {
+        ConflictMarkerStyle::Diff
+    }
+}
+
+/// What kind of conflict a path has, for listing conflicts to the user.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConflictKind {
+    /// The sides changed the contents of the path in different ways.
+    Content,
+    /// Some sides deleted the path while others modified it.
+    ModifyDelete,
+    /// The parts have different types at the path, e.g. one side turned a
+    /// file into a directory while the other side edited the file. Holds the
+    /// names of the types involved.
+    Type(Vec<&'static str>),
+}
+
+impl Display for ConflictKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConflictKind::Content => write!(f, "content"),
+            ConflictKind::ModifyDelete => write!(f, "modify/delete"),
+            ConflictKind::Type(type_names) => write!(f, "{}", type_names.join("/")),
+        }
+    }
+}
+
+/// The name of the type of a part in descriptions of conflicts. Executable
+/// and non-executable files are both "file".
+fn part_type_name(part: &ConflictPart) -> &'static str {
     match &part.value {
-        TreeValue::Normal {
-            id,
-            executable: false,
-        } => {
-            format!("file with id {}", id.hex())
+        TreeValue::Normal { .. } => "file",
+        TreeValue::Symlink(_) => "symlink",
+        TreeValue::Tree(_) => "directory",
+        TreeValue::GitSubmodule(_) => "submodule",
+        TreeValue::Conflict(_) => "conflict",
+    }
+}
+
+/// Classifies a conflict. Conflicts where a side deleted the path are
+/// modify/delete conflicts even if the other sides have different types.
+pub fn conflict_kind(conflict: &Conflict) -> ConflictKind {
+    if conflict.adds.len() <= conflict.removes.len() {
+        return ConflictKind::ModifyDelete;
+    }
+    let type_names = ["file", "symlink", "directory", "submodule", "conflict"]
+        .into_iter()
+        .filter(|type_name| {
+            conflict
+                .removes
+                .iter()
+                .chain(&conflict.adds)
+                .any(|part| part_type_name(part) == *type_name)
+        })
+        .collect_vec();
+    if type_names.len() > 1 {
+        ConflictKind::Type(type_names)
+    } else {
+        ConflictKind::Content
+    }
+}
+
+/// Describes a part on one line, followed by its contents indented below it:
+/// the lines of a file, the target of a symlink, or the files in a directory.
+fn describe_conflict_part(
+    store: &Store,
+    path: &RepoPath,
+    verb: &str,
+    part: &ConflictPart,
+    file: &mut dyn Write,
+) -> std::io::Result<()> {
+    let origin = match &part.label {
+        Some(label) => format!(" (from {label})"),
+        None => String::new(),
+    };
+    match &part.value {
+        TreeValue::GitSubmodule(id) => {
+            writeln!(
+                file,
+                "  {verb} Git submodule at commit {}{origin}",
+                id.hex()
+            )?;
+        }
+        TreeValue::Conflict(id) => {
+            writeln!(file, "  {verb} conflict with id {}{origin}", id.hex())?;
         }
         TreeValue::Normal {
-            id,
-            executable: true,
+            executable: true, ..
         } => {
-            format!("executable file with id {}", id.hex())
+            writeln!(file, "  {verb} executable file{origin}:")?;
+        }
+        _ => {
+            writeln!(file, "  {verb} {}{origin}:", part_type_name(part))?;
+        }
+    }
+    match &part.value {
+        TreeValue::Normal { id, .. } => {
+            let mut content = vec![];
+            store
+                .read_file(path, id)
+                .unwrap()
+                .read_to_end(&mut content)?;
+            if content.contains(&0) {
+                file.write_all(b"    (binary)\n")?;
+                return Ok(());
+            }
+            for line in content.split_inclusive(|b| *b == b'\n') {
+                file.write_all(b"    ")?;
+                file.write_all(line)?;
+            }
+            if !content.is_empty() && !content.ends_with(b"\n") {
+                file.write_all(b"\n")?;
+            }
         }
         TreeValue::Symlink(id) => {
-            format!("symlink with id {}", id.hex())
+            let target = store.read_symlink(path, id).unwrap();
+            writeln!(file, "    -> {target}")?;
         }
         TreeValue::Tree(id) => {
-            format!("tree with id {}", id.hex())
-        }
-        TreeValue::GitSubmodule(id) => {
-            format!("Git submodule with id {}", id.hex())
+            write_tree_files(store, path, id, file)?;
         }
-        TreeValue::Conflict(id) => {
-            format!("Conflict with id {}", id.hex())
+        TreeValue::GitSubmodule(_) | TreeValue::Conflict(_) => {}
+    }
+    Ok(())
+}
+
+fn write_tree_files(
+    store: &Store,
+    dir: &RepoPath,
+    id: &TreeId,
+    file: &mut dyn Write,
+) -> std::io::Result<()> {
+    let tree = store.get_backend_tree(dir, id).unwrap();
+    for entry in tree.entries() {
+        let entry_path = dir.join(entry.name());
+        match entry.value() {
+            TreeValue::Tree(subdir_id) => write_tree_files(store, &entry_path, subdir_id, file)?,
+            _ => writeln!(file, "    {}", entry_path.to_internal_file_string())?,
         }
     }
+    Ok(())
 }
 
-fn describe_conflict(conflict: &Conflict, file: &mut dyn Write) -> std::io::Result<()> {
-    file.write_all(b"Conflict:\n")?;
+fn describe_conflict(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Conflict,
+    file: &mut dyn Write,
+) -> std::io::Result<()> {
+    writeln!(file, "Conflict ({}):", conflict_kind(conflict))?;
     for part in &conflict.removes {
-        file.write_all(format!("  Removing {}\n", describe_conflict_part(part)).as_bytes())?;
+        describe_conflict_part(store, path, "Removing", part, file)?;
     }
     for part in &conflict.adds {
-        file.write_all(format!("  Adding {}\n", describe_conflict_part(part)).as_bytes())?;
+        describe_conflict_part(store, path, "Adding", part, file)?;
     }
     Ok(())
 }
 
+/// The labels of the parts to show in conflict markers, or `None` if none of
+/// the parts are labeled. Unlabeled parts are described by their position.
+fn conflict_marker_labels(conflict: &Conflict) -> Option<(Vec<String>, Vec<String>)> {
+    if conflict
+        .removes
+        .iter()
+        .chain(&conflict.adds)
+        .all(|part| part.label.is_none())
+    {
+        return None;
+    }
+    let labels = |parts: &[ConflictPart], kind: &str| {
+        parts
+            .iter()
+            .enumerate()
+            .map(|(i, part)| {
+                part.label
+                    .clone()
+                    .unwrap_or_else(|| format!("{kind} #{}", i + 1))
+            })
+            .collect_vec()
+    };
+    Some((
+        labels(&conflict.removes, "base"),
+        labels(&conflict.adds, "side"),
+    ))
+}
+
+fn write_marker(
+    output: &mut dyn Write,
+    marker: &[u8],
+    description: Option<String>,
+) -> std::io::Result<()> {
+    output.write_all(marker)?;
+    if let Some(description) = description {
+        output.write_all(b" ")?;
+        output.write_all(description.as_bytes())?;
+    }
+    output.write_all(b"\n")
+}
+
+/// Whether the line is the marker, optionally followed by a description.
+fn is_marker_line(line: &[u8], marker: &[u8]) -> bool {
+    match line.strip_prefix(marker) {
+        Some(rest) => rest == b"\n" || (rest.starts_with(b" ") && rest.ends_with(b"\n")),
+        None => false,
+    }
+}
+
 fn file_parts(parts: &[ConflictPart]) -> Vec<&ConflictPart> {
     parts
         .iter()
@@ -130,6 +323,7 @@ pub fn materialize_conflict(
     store: &Store,
     path: &RepoPath,
     conflict: &Conflict,
+    style: ConflictMarkerStyle,
     output: &mut dyn Write,
 ) -> std::io::Result<()> {
     let file_adds = file_parts(&conflict.adds);
@@ -137,7 +331,7 @@ pub fn materialize_conflict(
     if file_adds.len() != conflict.adds.len() || file_removes.len() != conflict.removes.len() {
         // Unless all parts are regular files, we can't do much better than to try to
         // describe the conflict.
-        describe_conflict(conflict, output)?;
+        describe_conflict(store, path, conflict, output)?;
         return Ok(());
     }
 
@@ -152,7 +346,9 @@ pub fn materialize_conflict(
     let removed_slices = removed_content.iter().map(Vec::as_slice).collect_vec();
     let added_slices = added_content.iter().map(Vec::as_slice).collect_vec();
 
-    let merge_result = files::merge(&removed_slices, &added_slices);
+    let marker_labels = conflict_marker_labels(conflict);
+    let merge_result =
+        files::merge_with_algorithm(&removed_slices, &added_slices, store.merge_algorithm(path));
     match merge_result {
         MergeResult::Resolved(content) => {
             output.write_all(&content)?;
@@ -163,40 +359,23 @@ pub fn materialize_conflict(
                     MergeHunk::Resolved(content) => {
                         output.write_all(&content)?;
                     }
-                    MergeHunk::Conflict {
-                        mut removes,
-                        mut adds,
-                    } => {
-                        output.write_all(CONFLICT_START_LINE)?;
-                        while !removes.is_empty() && !adds.is_empty() {
-                            let left = &removes[0];
-                            let mut diffs = vec![];
-                            for right in &adds {
-                                diffs.push(
-                                    Diff::for_tokenizer(&[left, right], &find_line_ranges)
-                                        .hunks()
-                                        .collect_vec(),
-                                );
-                            }
-                            let min_diff_index = diffs
-                                .iter()
-                                .position_min_by_key(|diff| diff_size(diff))
-                                .unwrap();
-                            output.write_all(CONFLICT_DIFF_LINE)?;
-                            write_diff_hunks(&diffs[min_diff_index], output)?;
-                            removes.remove(0);
-                            adds.remove(min_diff_index);
-                        }
-
-                        for slice in removes {
-                            output.write_all(CONFLICT_MINUS_LINE)?;
-                            output.write_all(&slice)?;
-                        }
-                        for slice in adds {
-                            output.write_all(CONFLICT_PLUS_LINE)?;
-                            output.write_all(&slice)?;
-                        }
-                        output.write_all(CONFLICT_END_LINE)?;
+                    // The Git-like styles can only show conflicts between two sides
+                    MergeHunk::Conflict { removes, adds }
+                        if style != ConflictMarkerStyle::Diff
+                            && removes.len() == 1
+                            && adds.len() == 2 =>
+                    {
+                        write_git_style_conflict(
+                            &removes[0],
+                            &adds[0],
+                            &adds[1],
+                            marker_labels.as_ref(),
+                            style,
+                            output,
+                        )?;
+                    }
+                    MergeHunk::Conflict { removes, adds } => {
+                        write_diff_style_conflict(removes, adds, marker_labels.as_ref(), output)?;
                     }
                 }
             }
@@ -205,6 +384,115 @@ pub fn materialize_conflict(
     Ok(())
 }
 
+fn write_diff_style_conflict(
+    mut removes: Vec<Vec<u8>>,
+    mut adds: Vec<Vec<u8>>,
+    marker_labels: Option<&(Vec<String>, Vec<String>)>,
+    output: &mut dyn Write,
+) -> std::io::Result<()> {
+    let (mut remove_labels, mut add_labels) = match marker_labels {
+        Some((remove_labels, add_labels)) => (
+            remove_labels.iter().cloned().map(Some).collect_vec(),
+            add_labels.iter().cloned().map(Some).collect_vec(),
+        ),
+        None => (vec![None; removes.len()], vec![None; adds.len()]),
+    };
+    write_marker(output, CONFLICT_START_MARKER, None)?;
+    while !removes.is_empty() && !adds.is_empty() {
+        let left = &removes[0];
+        let mut diffs = vec![];
+        for right in &adds {
+            diffs.push(
+                Diff::for_tokenizer(&[left, right], &find_line_ranges)
+                    .hunks()
+                    .collect_vec(),
+            );
+        }
+        let min_diff_index = diffs
+            .iter()
+            .position_min_by_key(|diff| diff_size(diff))
+            .unwrap();
+        let remove_label = remove_labels.remove(0);
+        let add_label = add_labels.remove(min_diff_index);
+        let description = remove_label
+            .zip(add_label)
+            .map(|(remove_label, add_label)| format!("Changes from {remove_label} to {add_label}"));
+        write_marker(output, CONFLICT_DIFF_MARKER, description)?;
+        write_diff_hunks(&diffs[min_diff_index], output)?;
+        removes.remove(0);
+        adds.remove(min_diff_index);
+    }
+
+    for (slice, label) in removes.iter().zip(remove_labels) {
+        let description = label.map(|label| format!("Contents of {label}"));
+        write_marker(output, CONFLICT_MINUS_MARKER, description)?;
+        output.write_all(slice)?;
+    }
+    for (slice, label) in adds.iter().zip(add_labels) {
+        let description = label.map(|label| format!("Contents of {label}"));
+        write_marker(output, CONFLICT_PLUS_MARKER, description)?;
+        output.write_all(slice)?;
+    }
+    write_marker(output, CONFLICT_END_MARKER, None)
+}
+
+fn write_git_style_conflict(
+    base: &[u8],
+    side1: &[u8],
+    side2: &[u8],
+    marker_labels: Option<&(Vec<String>, Vec<String>)>,
+    style: ConflictMarkerStyle,
+    output: &mut dyn Write,
+) -> std::io::Result<()> {
+    let (base_label, side1_label, side2_label) = match marker_labels {
+        Some((remove_labels, add_labels)) => (
+            Some(remove_labels[0].clone()),
+            Some(add_labels[0].clone()),
+            Some(add_labels[1].clone()),
+        ),
+        None => (None, None, None),
+    };
+    let (prefix_len, suffix_len) = if style == ConflictMarkerStyle::Zdiff3 {
+        common_line_affixes(side1, side2)
+    } else {
+        (0, 0)
+    };
+    output.write_all(&side1[..prefix_len])?;
+    write_marker(output, CONFLICT_START_MARKER, side1_label)?;
+    output.write_all(&side1[prefix_len..side1.len() - suffix_len])?;
+    if style != ConflictMarkerStyle::Minimal {
+        write_marker(output, CONFLICT_BASE_MARKER, base_label)?;
+        output.write_all(base)?;
+    }
+    write_marker(output, CONFLICT_SEPARATOR_MARKER, None)?;
+    output.write_all(&side2[prefix_len..side2.len() - suffix_len])?;
+    write_marker(output, CONFLICT_END_MARKER, side2_label)?;
+    output.write_all(&side1[side1.len() - suffix_len..])
+}
+
+/// The lengths in bytes of the lines that `left` and `right` have in common at
+/// their start and at their end. They don't overlap.
+fn common_line_affixes(left: &[u8], right: &[u8]) -> (usize, usize) {
+    let left_lines = left.split_inclusive(|b| *b == b'\n').collect_vec();
+    let right_lines = right.split_inclusive(|b| *b == b'\n').collect_vec();
+    let prefix_count = left_lines
+        .iter()
+        .zip(&right_lines)
+        .take_while(|(left_line, right_line)| left_line == right_line)
+        .count();
+    let suffix_count = left_lines[prefix_count..]
+        .iter()
+        .rev()
+        .zip(right_lines[prefix_count..].iter().rev())
+        .take_while(|(left_line, right_line)| left_line == right_line)
+        .count();
+    let total_len = |lines: &[&[u8]]| lines.iter().map(|line| line.len()).sum();
+    (
+        total_len(&left_lines[..prefix_count]),
+        total_len(&left_lines[left_lines.len() - suffix_count..]),
+    )
+}
+
 fn diff_size(hunks: &[DiffHunk]) -> usize {
     hunks
         .iter()
@@ -221,7 +509,14 @@ pub fn conflict_to_materialized_value(
     conflict: &Conflict,
 ) -> TreeValue {
     let mut buf = vec![];
-    materialize_conflict(store, path, conflict, &mut buf).unwrap();
+    materialize_conflict(
+        store,
+        path,
+        conflict,
+        ConflictMarkerStyle::default(),
+        &mut buf,
+    )
+    .unwrap();
     let file_id = store.write_file(path, &mut Cursor::new(&buf)).unwrap();
     TreeValue::Normal {
         id: file_id,
@@ -232,7 +527,9 @@ pub fn conflict_to_materialized_value(
 /// Parses conflict markers from a slice. Returns None if there were no valid
 /// conflict markers. The caller has to provide the expected number of removed
 /// and added inputs to the conflicts. Conflict markers that are otherwise valid
-/// will be considered invalid if they don't have the expected arity.
+/// will be considered invalid if they don't have the expected arity. Markers
+/// in any of the `ConflictMarkerStyle`s can be parsed. Markers in the minimal
+/// style don't include the base, so they have no removed inputs.
 // TODO: "parse" is not usually the opposite of "materialize", so maybe we
 // should rename them to "serialize" and "deserialize"?
 pub fn parse_conflict(input: &[u8], num_removes: usize, num_adds: usize) -> Option<Vec<MergeHunk>> {
@@ -243,11 +540,13 @@ pub fn parse_conflict(input: &[u8], num_removes: usize, num_adds: usize) -> Opti
     let mut pos = 0;
     let mut resolved_start = 0;
     let mut conflict_start = None;
+    let mut conflict_start_line_len = 0;
     for line in input.split_inclusive(|b| *b == b'\n') {
-        if line == CONFLICT_START_LINE {
+        if is_marker_line(line, CONFLICT_START_MARKER) {
             conflict_start = Some(pos);
-        } else if conflict_start.is_some() && line == CONFLICT_END_LINE {
-            let conflict_body = &input[conflict_start.unwrap() + CONFLICT_START_LINE.len()..pos];
+            conflict_start_line_len = line.len();
+        } else if conflict_start.is_some() && is_marker_line(line, CONFLICT_END_MARKER) {
+            let conflict_body = &input[conflict_start.unwrap() + conflict_start_line_len..pos];
             let hunk = parse_conflict_hunk(conflict_body);
             match &hunk {
                 MergeHunk::Conflict { removes, adds }
@@ -278,6 +577,22 @@ pub fn parse_conflict(input: &[u8], num_removes: usize, num_adds: usize) -> Opti
 }
 
 fn parse_conflict_hunk(input: &[u8]) -> MergeHunk {
+    let first_line = input.split_inclusive(|b| *b == b'\n').next().unwrap_or(b"");
+    if [
+        CONFLICT_DIFF_MARKER,
+        CONFLICT_MINUS_MARKER,
+        CONFLICT_PLUS_MARKER,
+    ]
+    .iter()
+    .any(|marker| is_marker_line(first_line, marker))
+    {
+        parse_diff_style_conflict_hunk(input)
+    } else {
+        parse_git_style_conflict_hunk(input)
+    }
+}
+
+fn parse_diff_style_conflict_hunk(input: &[u8]) -> MergeHunk {
     enum State {
         Diff,
         Minus,
@@ -288,25 +603,20 @@ fn parse_conflict_hunk(input: &[u8]) -> MergeHunk {
     let mut removes = vec![];
     let mut adds = vec![];
     for line in input.split_inclusive(|b| *b == b'\n') {
-        match line {
-            CONFLICT_DIFF_LINE => {
-                state = State::Diff;
-                removes.push(vec![]);
-                adds.push(vec![]);
-                continue;
-            }
-            CONFLICT_MINUS_LINE => {
-                state = State::Minus;
-                removes.push(vec![]);
-                continue;
-            }
-            CONFLICT_PLUS_LINE => {
-                state = State::Plus;
-                adds.push(vec![]);
-                continue;
-            }
-            _ => {}
-        };
+        if is_marker_line(line, CONFLICT_DIFF_MARKER) {
+            state = State::Diff;
+            removes.push(vec![]);
+            adds.push(vec![]);
+            continue;
+        } else if is_marker_line(line, CONFLICT_MINUS_MARKER) {
+            state = State::Minus;
+            removes.push(vec![]);
+            continue;
+        } else if is_marker_line(line, CONFLICT_PLUS_MARKER) {
+            state = State::Plus;
+            adds.push(vec![]);
+            continue;
+        }
         match state {
             State::Diff => {
                 if let Some(rest) = line.strip_prefix(b"-") {
@@ -337,11 +647,106 @@ fn parse_conflict_hunk(input: &[u8]) -> MergeHunk {
     MergeHunk::Conflict { removes, adds }
 }
 
+/// Parses a conflict between two sides in the minimal, diff3 or zdiff3 style.
+fn parse_git_style_conflict_hunk(input: &[u8]) -> MergeHunk {
+    enum State {
+        Side1,
+        Base,
+        Side2,
+    }
+    let mut state = State::Side1;
+    let mut side1 = vec![];
+    let mut base = None;
+    let mut side2 = vec![];
+    for line in input.split_inclusive(|b| *b == b'\n') {
+        match state {
+            State::Side1 if is_marker_line(line, CONFLICT_BASE_MARKER) => {
+                state = State::Base;
+                base = Some(vec![]);
+            }
+            State::Side1 | State::Base if is_marker_line(line, CONFLICT_SEPARATOR_MARKER) => {
+                state = State::Side2;
+            }
+            State::Side1 => side1.extend_from_slice(line),
+            State::Base => base.as_mut().unwrap().extend_from_slice(line),
+            State::Side2 => side2.extend_from_slice(line),
+        }
+    }
+    if !matches!(state, State::Side2) {
+        // Doesn't look like a conflict
+        return MergeHunk::Resolved(vec![]);
+    }
+
+    MergeHunk::Conflict {
+        removes: base.into_iter().collect(),
+        adds: vec![side1, side2],
+    }
+}
+
+/// Adds the base from `conflict` to the conflict hunks parsed from markers in
+/// the minimal style, which don't include it. If some of the conflicts were
+/// resolved, the remaining ones are matched with the conflicts of `conflict`
+/// that have the same sides.
+fn add_bases_to_hunks(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Conflict,
+    hunks: Vec<MergeHunk>,
+) -> Option<Vec<MergeHunk>> {
+    let removed_content = get_file_contents(store, path, &conflict.removes[0]);
+    let added_content = conflict
+        .adds
+        .iter()
+        .map(|part| get_file_contents(store, path, part))
+        .collect_vec();
+    let added_slices = added_content.iter().map(Vec::as_slice).collect_vec();
+    let old_conflicts = match files::merge_with_algorithm(
+        &[&removed_content],
+        &added_slices,
+        store.merge_algorithm(path),
+    ) {
+        MergeResult::Resolved(_) => vec![],
+        MergeResult::Conflict(old_hunks) => old_hunks
+            .into_iter()
+            .filter_map(|hunk| match hunk {
+                MergeHunk::Resolved(_) => None,
+                MergeHunk::Conflict { mut removes, adds } => Some((removes.remove(0), adds)),
+            })
+            .collect_vec(),
+    };
+    let num_conflicts = hunks
+        .iter()
+        .filter(|hunk| matches!(hunk, MergeHunk::Conflict { .. }))
+        .count();
+    // If no conflict was resolved, the sides may have been edited too
+    let match_in_order = num_conflicts == old_conflicts.len();
+    let mut old_conflicts = old_conflicts.into_iter();
+    let mut new_hunks = vec![];
+    for hunk in hunks {
+        match hunk {
+            MergeHunk::Resolved(content) => new_hunks.push(MergeHunk::Resolved(content)),
+            MergeHunk::Conflict { adds, .. } => {
+                let base = if match_in_order {
+                    old_conflicts.next().unwrap().0
+                } else {
+                    old_conflicts.find(|(_, old_adds)| *old_adds == adds)?.0
+                };
+                new_hunks.push(MergeHunk::Conflict {
+                    removes: vec![base],
+                    adds,
+                });
+            }
+        }
+    }
+    Some(new_hunks)
+}
+
 pub fn update_conflict_from_content(
     store: &Store,
     path: &RepoPath,
     conflict_id: &ConflictId,
     content: &[u8],
+    style: ConflictMarkerStyle,
 ) -> BackendResult<Option<ConflictId>> {
     let mut conflict = store.read_conflict(path, conflict_id)?;
 
@@ -351,14 +756,31 @@ pub fn update_conflict_from_content(
     // conflicts (for example) are not converted to regular files in the working
     // copy.
     let mut old_content = Vec::with_capacity(content.len());
-    materialize_conflict(store, path, &conflict, &mut old_content).unwrap();
+    materialize_conflict(store, path, &conflict, style, &mut old_content).unwrap();
     if content == old_content {
         return Ok(Some(conflict_id.clone()));
     }
 
+    // Conflicts involving non-files are materialized as a description, so any
+    // change to it resolves the conflict.
+    if file_parts(&conflict.adds).len() != conflict.adds.len()
+        || file_parts(&conflict.removes).len() != conflict.removes.len()
+    {
+        return Ok(None);
+    }
+
     let mut removed_content = vec![vec![]; conflict.removes.len()];
     let mut added_content = vec![vec![]; conflict.adds.len()];
-    if let Some(hunks) = parse_conflict(content, conflict.removes.len(), conflict.adds.len()) {
+    let hunks = match parse_conflict(content, conflict.removes.len(), conflict.adds.len()) {
+        Some(hunks) => Some(hunks),
+        // The markers may be in the minimal style, which leaves out the base
+        None if conflict.removes.len() == 1 && conflict.adds.len() == 2 => {
+            parse_conflict(content, 0, 2)
+                .and_then(|hunks| add_bases_to_hunks(store, path, &conflict, hunks))
+        }
+        None => None,
+    };
+    if let Some(hunks) = hunks {
         for hunk in hunks {
             match hunk {
                 MergeHunk::Resolved(slice) => {
@@ -387,9 +809,6 @@ pub fn update_conflict_from_content(
             if let TreeValue::Normal { id, executable: _ } = &mut conflict.removes[i].value {
                 *id = file_id;
             } else {
-                // TODO: This can actually happen. We should check earlier
-                // that the we only attempt to parse the conflicts if it's a
-                // file-only conflict.
                 panic!("Found conflict markers in merge of non-files");
             }
         }
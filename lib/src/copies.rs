@@ -0,0 +1,315 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Detection of renamed and copied files in tree diffs. Like Git, we don't
+//! record renames, so they're found by comparing the contents of the files
+//! that were removed (and, for copies, modified) with the added files.
+
+use std::cmp::{max, Reverse};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+
+use crate::backend::{BackendError, BackendResult, FileId, TreeValue};
+use crate::repo_path::RepoPath;
+use crate::store::Store;
+use crate::tree::Diff;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum CopyOperation {
+    /// The source path was removed.
+    Rename,
+    /// The source path still exists.
+    Copy,
+}
+
+/// A file that was renamed or copied to `target` from `source`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct CopyRecord {
+    pub source: RepoPath,
+    pub target: RepoPath,
+    pub operation: CopyOperation,
+    /// How similar the contents are, in percent.
+    pub similarity: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyDetectionOptions {
+    /// Whether to look for copies of modified files, and of removed files
+    /// that were also renamed.
+    pub find_copies: bool,
+    /// How similar (in percent) the contents of two files have to be for one
+    /// to be considered a rename or copy of the other. 100 means that only
+    /// files with identical contents are paired up.
+    pub min_similarity: u32,
+    /// Files aren't compared by content if there are more sources or targets
+    /// than this, since it takes quadratic time. Identical files are still
+    /// detected.
+    pub rename_limit: usize,
+}
+
+impl Default for CopyDetectionOptions {
+    fn default() -> Self {
+        CopyDetectionOptions {
+            find_copies: false,
+            min_similarity: 50,
+            rename_limit: 1000,
+        }
+    }
+}
+
+struct Source<'a> {
+    path: &'a RepoPath,
+    value: &'a TreeValue,
+    removed: bool,
+}
+
+/// Finds the added files in `diffs` that were renamed or copied from other
+/// files in `diffs`. The result is sorted by target path.
+pub fn find_copies(
+    store: &Store,
+    diffs: &[(RepoPath, Diff<TreeValue>)],
+    options: &CopyDetectionOptions,
+) -> BackendResult<Vec<CopyRecord>> {
+    let mut sources = vec![];
+    let mut targets = vec![];
+    for (path, diff) in diffs {
+        match diff {
+            Diff::Removed(value) => sources.push(Source {
+                path,
+                value,
+                removed: true,
+            }),
+            Diff::Modified(value, _) if options.find_copies => sources.push(Source {
+                path,
+                value,
+                removed: false,
+            }),
+            Diff::Added(value) => targets.push((path, value)),
+            _ => {}
+        }
+    }
+    if sources.is_empty() || targets.is_empty() {
+        return Ok(vec![]);
+    }
+
+    let mut contents = ContentCache::new(store);
+    let mut renamed_sources = HashSet::new();
+    let mut records = vec![];
+
+    // First pair up files with identical contents, preferring sources that were
+    // removed so they become renames
+    let mut exact_sources: HashMap<ExactKey, Vec<&Source>> = HashMap::new();
+    for source in &sources {
+        if let Some(key) = exact_key(source.value) {
+            exact_sources.entry(key).or_default().push(source);
+        }
+    }
+    let mut remaining_targets = vec![];
+    for (target, value) in targets {
+        if let TreeValue::Normal { id, .. } = value {
+            // Empty files are not considered renames of each other
+            if contents.get(target, id)?.is_empty() {
+                continue;
+            }
+        }
+        let source = exact_key(value)
+            .and_then(|key| exact_sources.get(&key))
+            .and_then(|sources| {
+                sources
+                    .iter()
+                    .find(|source| source.removed && !renamed_sources.contains(source.path))
+                    .or_else(|| sources.first())
+            });
+        let record = source
+            .and_then(|source| new_record(&mut renamed_sources, options, source, target, 100));
+        match record {
+            Some(record) => records.push(record),
+            None => remaining_targets.push((target, value)),
+        }
+    }
+
+    // Then compare the contents of the remaining files
+    let similar_sources = sources
+        .iter()
+        .filter(|source| {
+            matches!(source.value, TreeValue::Normal { .. })
+                && (options.find_copies || !renamed_sources.contains(source.path))
+        })
+        .collect::<Vec<_>>();
+    let similar_targets = remaining_targets
+        .into_iter()
+        .filter(|(_, value)| matches!(value, TreeValue::Normal { .. }))
+        .collect::<Vec<_>>();
+    if options.min_similarity < 100
+        && !similar_sources.is_empty()
+        && !similar_targets.is_empty()
+        && max(similar_sources.len(), similar_targets.len()) <= options.rename_limit
+    {
+        for source in &similar_sources {
+            contents.get(source.path, file_id(source.value))?;
+        }
+        for (target, value) in &similar_targets {
+            contents.get(target, file_id(value))?;
+        }
+        let mut candidates = vec![];
+        for (target_index, (_, target_value)) in similar_targets.iter().enumerate() {
+            let target_content = contents.loaded(file_id(target_value));
+            for (source_index, source) in similar_sources.iter().enumerate() {
+                let source_content = contents.loaded(file_id(source.value));
+                let similarity = content_similarity(source_content, target_content);
+                if similarity >= options.min_similarity {
+                    candidates.push((similarity, target_index, source_index));
+                }
+            }
+        }
+        // Pair up the most similar files first
+        candidates.sort_by_key(|(similarity, target_index, source_index)| {
+            (Reverse(*similarity), *target_index, *source_index)
+        });
+        let mut matched_targets = HashSet::new();
+        for (similarity, target_index, source_index) in candidates {
+            if matched_targets.contains(&target_index) {
+                continue;
+            }
+            let (target, _) = similar_targets[target_index];
+            if let Some(record) = new_record(
+                &mut renamed_sources,
+                options,
+                similar_sources[source_index],
+                target,
+                similarity,
+            ) {
+                records.push(record);
+                matched_targets.insert(target_index);
+            }
+        }
+    }
+
+    records.sort_by(|record1, record2| record1.target.cmp(&record2.target));
+    Ok(records)
+}
+
+/// Returns a record of the target being renamed from the source, or copied if
+/// the source was not removed or was already renamed, if copies are wanted.
+fn new_record(
+    renamed_sources: &mut HashSet<RepoPath>,
+    options: &CopyDetectionOptions,
+    source: &Source,
+    target: &RepoPath,
+    similarity: u32,
+) -> Option<CopyRecord> {
+    let operation = if source.removed && renamed_sources.insert(source.path.clone()) {
+        CopyOperation::Rename
+    } else if options.find_copies {
+        CopyOperation::Copy
+    } else {
+        return None;
+    };
+    Some(CopyRecord {
+        source: source.path.clone(),
+        target: target.clone(),
+        operation,
+        similarity,
+    })
+}
+
+#[derive(PartialEq, Eq, Hash)]
+enum ExactKey {
+    File(FileId),
+    Symlink(String),
+}
+
+fn exact_key(value: &TreeValue) -> Option<ExactKey> {
+    match value {
+        TreeValue::Normal { id, .. } => Some(ExactKey::File(id.clone())),
+        TreeValue::Symlink(id) => Some(ExactKey::Symlink(id.hex())),
+        _ => None,
+    }
+}
+
+fn file_id(value: &TreeValue) -> &FileId {
+    match value {
+        TreeValue::Normal { id, .. } => id,
+        _ => panic!("expected a file, got {:?}", value),
+    }
+}
+
+struct ContentCache<'a> {
+    store: &'a Store,
+    contents: HashMap<FileId, Vec<u8>>,
+}
+
+impl<'a> ContentCache<'a> {
+    fn new(store: &'a Store) -> Self {
+        ContentCache {
+            store,
+            contents: HashMap::new(),
+        }
+    }
+
+    fn get(&mut self, path: &RepoPath, id: &FileId) -> BackendResult<&Vec<u8>> {
+        if !self.contents.contains_key(id) {
+            let mut content = vec![];
+            self.store
+                .read_file(path, id)?
+                .read_to_end(&mut content)
+                .map_err(|err| BackendError::Other(err.to_string()))?;
+            self.contents.insert(id.clone(), content);
+        }
+        Ok(&self.contents[id])
+    }
+
+    fn loaded(&self, id: &FileId) -> &[u8] {
+        &self.contents[id]
+    }
+}
+
+/// Returns how similar two contents are, in percent, as the number of bytes
+/// in the lines they have in common compared to the size of the larger one.
+pub fn content_similarity(content1: &[u8], content2: &[u8]) -> u32 {
+    let total = max(content1.len(), content2.len());
+    if total == 0 {
+        return 100;
+    }
+    let mut line_counts: HashMap<&[u8], usize> = HashMap::new();
+    for line in content1.split_inclusive(|b| *b == b'\n') {
+        *line_counts.entry(line).or_default() += 1;
+    }
+    let mut common = 0;
+    for line in content2.split_inclusive(|b| *b == b'\n') {
+        if let Some(count) = line_counts.get_mut(line) {
+            if *count > 0 {
+                *count -= 1;
+                common += line.len();
+            }
+        }
+    }
+    (common * 100 / total) as u32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_content_similarity() {
+        assert_eq!(content_similarity(b"", b""), 100);
+        assert_eq!(content_similarity(b"a\nb\n", b"a\nb\n"), 100);
+        assert_eq!(content_similarity(b"a\nb\n", b""), 0);
+        assert_eq!(content_similarity(b"a\nb\n", b"a\nc\n"), 50);
+        assert_eq!(content_similarity(b"a\nb\n", b"b\na\n"), 100);
+        // Repeated lines are only counted as often as they appear in both
+        assert_eq!(content_similarity(b"a\na\na\na\n", b"a\nb\nc\nd\n"), 25);
+    }
+}
@@ -291,6 +291,259 @@ pub(crate) fn unchanged_ranges(
     result
 }
 
+/// The algorithm for finding the unchanged tokens between two inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffAlgorithm {
+    /// Finds the longest common subsequence of the least common tokens, and
+    /// recurses into the regions between them.
+    Lcs,
+    /// Like `Lcs`, but only tokens that occur once in each input are matched
+    /// directly. Common tokens at the start and end of each region are matched
+    /// first.
+    Patience,
+    /// Matches the longest run of common tokens around the least common
+    /// token, and recurses into the regions before and after it. This keeps
+    /// blocks of code together when they're moved.
+    Histogram,
+}
+
+impl Default for DiffAlgorithm {
+    fn default() -> Self {
+        DiffAlgorithm::Lcs
+    }
+}
+
+fn unchanged_ranges_with_algorithm(
+    left: &[u8],
+    right: &[u8],
+    left_ranges: &[Range<usize>],
+    right_ranges: &[Range<usize>],
+    algorithm: DiffAlgorithm,
+) -> Vec<(Range<usize>, Range<usize>)> {
+    match algorithm {
+        DiffAlgorithm::Lcs => unchanged_ranges(left, right, left_ranges, right_ranges),
+        DiffAlgorithm::Patience => {
+            patience_unchanged_ranges(left, right, left_ranges, right_ranges)
+        }
+        DiffAlgorithm::Histogram => {
+            histogram_unchanged_ranges(left, right, left_ranges, right_ranges)
+        }
+    }
+}
+
+fn patience_unchanged_ranges(
+    left: &[u8],
+    right: &[u8],
+    left_ranges: &[Range<usize>],
+    right_ranges: &[Range<usize>],
+) -> Vec<(Range<usize>, Range<usize>)> {
+    if left_ranges.is_empty() || right_ranges.is_empty() {
+        return vec![];
+    }
+
+    let tokens_match = |left_range: &Range<usize>, right_range: &Range<usize>| {
+        left[left_range.clone()] == right[right_range.clone()]
+    };
+    let prefix_len = left_ranges
+        .iter()
+        .zip(right_ranges)
+        .take_while(|&(left_range, right_range)| tokens_match(left_range, right_range))
+        .count();
+    if prefix_len > 0 {
+        let mut result = left_ranges[..prefix_len]
+            .iter()
+            .cloned()
+            .zip(right_ranges[..prefix_len].iter().cloned())
+            .collect_vec();
+        result.extend(patience_unchanged_ranges(
+            left,
+            right,
+            &left_ranges[prefix_len..],
+            &right_ranges[prefix_len..],
+        ));
+        return result;
+    }
+    let suffix_len = left_ranges
+        .iter()
+        .rev()
+        .zip(right_ranges.iter().rev())
+        .take_while(|&(left_range, right_range)| tokens_match(left_range, right_range))
+        .count();
+    if suffix_len > 0 {
+        let left_end = left_ranges.len() - suffix_len;
+        let right_end = right_ranges.len() - suffix_len;
+        let mut result = patience_unchanged_ranges(
+            left,
+            right,
+            &left_ranges[..left_end],
+            &right_ranges[..right_end],
+        );
+        result.extend(
+            left_ranges[left_end..]
+                .iter()
+                .cloned()
+                .zip(right_ranges[right_end..].iter().cloned()),
+        );
+        return result;
+    }
+
+    // Find the tokens that occur exactly once in each input. The value is the
+    // number of occurrences and the position of the last one in each input.
+    let mut occurrences: HashMap<&[u8], (usize, usize, usize, usize)> = HashMap::new();
+    for (i, range) in left_ranges.iter().enumerate() {
+        let entry = occurrences.entry(&left[range.clone()]).or_default();
+        entry.0 += 1;
+        entry.1 = i;
+    }
+    for (i, range) in right_ranges.iter().enumerate() {
+        if let Some(entry) = occurrences.get_mut(&right[range.clone()]) {
+            entry.2 += 1;
+            entry.3 = i;
+        }
+    }
+    let mut unique_positions = occurrences
+        .values()
+        .filter(|(left_count, _, right_count, _)| *left_count == 1 && *right_count == 1)
+        .map(|(_, left_position, _, right_position)| (*left_position, *right_position))
+        .collect_vec();
+    if unique_positions.is_empty() {
+        return vec![];
+    }
+
+    // Find the longest sequence of unique tokens that are in the same order in
+    // both inputs, like in `unchanged_ranges()`.
+    unique_positions.sort();
+    let left_index_by_position: HashMap<usize, usize> = unique_positions
+        .iter()
+        .enumerate()
+        .map(|(i, (left_position, _))| (*left_position, i))
+        .collect();
+    let mut right_sorted_positions = unique_positions.clone();
+    right_sorted_positions.sort_by_key(|(_, right_position)| *right_position);
+    let left_index_by_right_index = right_sorted_positions
+        .iter()
+        .map(|(left_position, _)| left_index_by_position[left_position])
+        .collect_vec();
+    let lcs = find_lcs(&left_index_by_right_index);
+
+    let mut result = vec![];
+    let mut previous_left_position = 0;
+    let mut previous_right_position = 0;
+    for (left_index, _right_index) in lcs {
+        let (left_position, right_position) = unique_positions[left_index];
+        result.extend(patience_unchanged_ranges(
+            left,
+            right,
+            &left_ranges[previous_left_position..left_position],
+            &right_ranges[previous_right_position..right_position],
+        ));
+        result.push((
+            left_ranges[left_position].clone(),
+            right_ranges[right_position].clone(),
+        ));
+        previous_left_position = left_position + 1;
+        previous_right_position = right_position + 1;
+    }
+    result.extend(patience_unchanged_ranges(
+        left,
+        right,
+        &left_ranges[previous_left_position..],
+        &right_ranges[previous_right_position..],
+    ));
+    result
+}
+
+fn histogram_unchanged_ranges(
+    left: &[u8],
+    right: &[u8],
+    left_ranges: &[Range<usize>],
+    right_ranges: &[Range<usize>],
+) -> Vec<(Range<usize>, Range<usize>)> {
+    if left_ranges.is_empty() || right_ranges.is_empty() {
+        return vec![];
+    }
+
+    // Tokens occurring more often than this aren't used to find a region, like
+    // in Git
+    let max_occurrences = 64;
+    let mut left_positions: HashMap<&[u8], Vec<usize>> = HashMap::new();
+    for (i, range) in left_ranges.iter().enumerate() {
+        left_positions
+            .entry(&left[range.clone()])
+            .or_default()
+            .push(i);
+    }
+    let tokens_match = |left_position: usize, right_position: usize| {
+        left[left_ranges[left_position].clone()] == right[right_ranges[right_position].clone()]
+    };
+
+    // The best region so far, as the number of occurrences in `left` of the
+    // token it was found from, its start in each input, and its length. Regions
+    // found from less common tokens are preferred, and then longer ones.
+    let mut best: Option<(usize, usize, usize, usize)> = None;
+    for (right_position, range) in right_ranges.iter().enumerate() {
+        let positions = match left_positions.get(&right[range.clone()]) {
+            Some(positions) => positions,
+            None => continue,
+        };
+        let count = positions.len();
+        if count > max_occurrences || matches!(best, Some((best_count, ..)) if count > best_count) {
+            continue;
+        }
+        for &left_position in positions {
+            let mut left_start = left_position;
+            let mut right_start = right_position;
+            while left_start > 0 && right_start > 0 && tokens_match(left_start - 1, right_start - 1)
+            {
+                left_start -= 1;
+                right_start -= 1;
+            }
+            let mut left_end = left_position + 1;
+            let mut right_end = right_position + 1;
+            while left_end < left_ranges.len()
+                && right_end < right_ranges.len()
+                && tokens_match(left_end, right_end)
+            {
+                left_end += 1;
+                right_end += 1;
+            }
+            let len = left_end - left_start;
+            let is_better = match best {
+                None => true,
+                Some((best_count, _, _, best_len)) => count < best_count || len > best_len,
+            };
+            if is_better {
+                best = Some((count, left_start, right_start, len));
+            }
+        }
+    }
+    let (_, left_start, right_start, len) = match best {
+        Some(best) => best,
+        // Nothing in common, or only very common tokens
+        None => return vec![],
+    };
+
+    let mut result = histogram_unchanged_ranges(
+        left,
+        right,
+        &left_ranges[..left_start],
+        &right_ranges[..right_start],
+    );
+    result.extend(
+        left_ranges[left_start..left_start + len]
+            .iter()
+            .cloned()
+            .zip(right_ranges[right_start..right_start + len].iter().cloned()),
+    );
+    result.extend(histogram_unchanged_ranges(
+        left,
+        right,
+        &left_ranges[left_start + len..],
+        &right_ranges[right_start + len..],
+    ));
+    result
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 struct UnchangedRange {
     base_range: Range<usize>,
@@ -387,6 +640,16 @@ impl<'input> Diff<'input> {
     pub fn for_tokenizer(
         inputs: &[&'input [u8]],
         tokenizer: &impl Fn(&[u8]) -> Vec<Range<usize>>,
+    ) -> Self {
+        Diff::for_tokenizer_with_algorithm(inputs, tokenizer, DiffAlgorithm::default())
+    }
+
+    /// Like `for_tokenizer()`, but finds the unchanged tokens between the base
+    /// and each other input with the given algorithm.
+    pub fn for_tokenizer_with_algorithm(
+        inputs: &[&'input [u8]],
+        tokenizer: &impl Fn(&[u8]) -> Vec<Range<usize>>,
+        algorithm: DiffAlgorithm,
     ) -> Self {
         assert!(!inputs.is_empty());
         let base_input = inputs[0];
@@ -407,11 +670,12 @@ impl<'input> Diff<'input> {
             offsets: vec![],
         }];
         for (i, other_token_ranges) in other_token_ranges.iter().enumerate() {
-            let unchanged_diff_ranges = unchanged_ranges(
+            let unchanged_diff_ranges = unchanged_ranges_with_algorithm(
                 base_input,
                 other_inputs[i],
                 &base_token_ranges,
                 other_token_ranges,
+                algorithm,
             );
             unchanged_regions = intersect_regions(unchanged_regions, &unchanged_diff_ranges);
         }
@@ -775,6 +1039,59 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_patience_unchanged_ranges_swapped() {
+        // The common first and last tokens are matched, and then one of the
+        // swapped unique tokens
+        assert_eq!(
+            patience_unchanged_ranges(
+                b"a b c a",
+                b"a c b a",
+                &[0..1, 2..3, 4..5, 6..7],
+                &[0..1, 2..3, 4..5, 6..7],
+            ),
+            vec![(0..1, 0..1), (4..5, 2..3), (6..7, 6..7)]
+        );
+    }
+
+    #[test]
+    fn test_patience_unchanged_ranges_repeated_tokens() {
+        // Repeated tokens ("a") aren't matched directly
+        assert_eq!(
+            patience_unchanged_ranges(
+                b"b a a c",
+                b"c a a b",
+                &[0..1, 2..3, 4..5, 6..7],
+                &[0..1, 2..3, 4..5, 6..7],
+            ),
+            vec![(6..7, 0..1)]
+        );
+    }
+
+    #[test]
+    fn test_histogram_unchanged_ranges_moved_block() {
+        // The default algorithm only matches "c", which is the least common
+        // token
+        assert_eq!(
+            unchanged_ranges(
+                b"a b a c",
+                b"c a b a",
+                &[0..1, 2..3, 4..5, 6..7],
+                &[0..1, 2..3, 4..5, 6..7],
+            ),
+            vec![(6..7, 0..1)]
+        );
+        assert_eq!(
+            histogram_unchanged_ranges(
+                b"a b a c",
+                b"c a b a",
+                &[0..1, 2..3, 4..5, 6..7],
+                &[0..1, 2..3, 4..5, 6..7],
+            ),
+            vec![(0..1, 2..3), (2..3, 4..5), (4..5, 6..7)]
+        );
+    }
+
     #[test]
     fn test_intersect_regions_existing_empty() {
         let actual = intersect_regions(vec![], &[(20..25, 55..60)]);
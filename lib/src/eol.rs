@@ -0,0 +1,167 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Line-ending conversion between the working copy and the store, similar to
+//! Git's `core.autocrlf`.
+
+use std::sync::Arc;
+
+use crate::gitignore::GitIgnoreFile;
+use crate::repo_path::RepoPath;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EolConversionMode {
+    /// Files are written and read unchanged.
+    None,
+    /// CRLF is converted to LF when snapshotting. Files are written unchanged.
+    Input,
+    /// CRLF is converted to LF when snapshotting, and LF to CRLF when writing
+    /// files to the working copy.
+    InputOutput,
+}
+
+/// Which files to convert line endings for, and how.
+#[derive(Debug, Clone)]
+pub struct EolConversion {
+    mode: EolConversionMode,
+    // Files to convert, or `None` for all files
+    patterns: Option<Arc<GitIgnoreFile>>,
+}
+
+impl Default for EolConversion {
+    fn default() -> Self {
+        EolConversion::new(EolConversionMode::None, &[])
+    }
+}
+
+impl EolConversion {
+    /// Creates a conversion for the files matching `patterns`, which use the
+    /// `.gitignore` syntax. All files are converted if `patterns` is empty.
+    pub fn new(mode: EolConversionMode, patterns: &[String]) -> Self {
+        let patterns = if patterns.is_empty() {
+            None
+        } else {
+            Some(GitIgnoreFile::empty().chain("", patterns.join("\n").as_bytes()))
+        };
+        EolConversion { mode, patterns }
+    }
+
+    pub fn mode(&self) -> EolConversionMode {
+        self.mode
+    }
+
+    fn applies_to(&self, path: &RepoPath, contents: &[u8]) -> bool {
+        let path_matches = match &self.patterns {
+            Some(patterns) => patterns.matches_file(&path.to_internal_file_string()),
+            None => true,
+        };
+        // Like Git, consider files with NUL bytes binary and leave them alone
+        path_matches && !contents.contains(&b'\0')
+    }
+
+    /// Converts file contents read from the working copy before they're
+    /// written to the store.
+    pub fn convert_input(&self, path: &RepoPath, contents: Vec<u8>) -> Vec<u8> {
+        if self.mode == EolConversionMode::None || !self.applies_to(path, &contents) {
+            return contents;
+        }
+        let mut output = Vec::with_capacity(contents.len());
+        let mut iter = contents.iter().peekable();
+        while let Some(&byte) = iter.next() {
+            if byte == b'\r' && iter.peek() == Some(&&b'\n') {
+                continue;
+            }
+            output.push(byte);
+        }
+        output
+    }
+
+    /// Converts file contents read from the store before they're written to
+    /// the working copy.
+    pub fn convert_output(&self, path: &RepoPath, contents: Vec<u8>) -> Vec<u8> {
+        if self.mode != EolConversionMode::InputOutput || !self.applies_to(path, &contents) {
+            return contents;
+        }
+        let mut output = Vec::with_capacity(contents.len());
+        let mut previous = None;
+        for &byte in &contents {
+            if byte == b'\n' && previous != Some(b'\r') {
+                output.push(b'\r');
+            }
+            output.push(byte);
+            previous = Some(byte);
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert_input(conversion: &EolConversion, path: &str, contents: &str) -> String {
+        let path = RepoPath::from_internal_string(path);
+        String::from_utf8(conversion.convert_input(&path, contents.as_bytes().to_vec())).unwrap()
+    }
+
+    fn convert_output(conversion: &EolConversion, path: &str, contents: &str) -> String {
+        let path = RepoPath::from_internal_string(path);
+        String::from_utf8(conversion.convert_output(&path, contents.as_bytes().to_vec())).unwrap()
+    }
+
+    #[test]
+    fn test_eol_conversion_modes() {
+        let none = EolConversion::new(EolConversionMode::None, &[]);
+        assert_eq!(convert_input(&none, "file", "a\r\nb\n"), "a\r\nb\n");
+        assert_eq!(convert_output(&none, "file", "a\nb\n"), "a\nb\n");
+
+        let input = EolConversion::new(EolConversionMode::Input, &[]);
+        assert_eq!(convert_input(&input, "file", "a\r\nb\n\r"), "a\nb\n\r");
+        assert_eq!(convert_output(&input, "file", "a\nb\n"), "a\nb\n");
+
+        let input_output = EolConversion::new(EolConversionMode::InputOutput, &[]);
+        assert_eq!(convert_input(&input_output, "file", "a\r\nb\n"), "a\nb\n");
+        assert_eq!(
+            convert_output(&input_output, "file", "a\nb\r\n"),
+            "a\r\nb\r\n"
+        );
+    }
+
+    #[test]
+    fn test_eol_conversion_skips_binary_files() {
+        let conversion = EolConversion::new(EolConversionMode::InputOutput, &[]);
+        assert_eq!(convert_input(&conversion, "file", "a\0\r\n"), "a\0\r\n");
+        assert_eq!(convert_output(&conversion, "file", "a\0\n"), "a\0\n");
+    }
+
+    #[test]
+    fn test_eol_conversion_patterns() {
+        let conversion = EolConversion::new(
+            EolConversionMode::InputOutput,
+            &[
+                "*.txt".to_string(),
+                "!/dir/*.txt".to_string(),
+                "!/vendor/".to_string(),
+            ],
+        );
+        assert_eq!(convert_output(&conversion, "file.txt", "a\n"), "a\r\n");
+        assert_eq!(convert_output(&conversion, "sub/file.txt", "a\n"), "a\r\n");
+        assert_eq!(convert_output(&conversion, "dir/file.txt", "a\n"), "a\n");
+        assert_eq!(convert_output(&conversion, "file.sh", "a\n"), "a\n");
+        assert_eq!(
+            convert_output(&conversion, "vendor/sub/file.txt", "a\n"),
+            "a\n"
+        );
+    }
+}
@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::fs::File;
+use std::io;
 use std::path::Path;
 
 use tempfile::{NamedTempFile, PersistError};
@@ -35,6 +36,119 @@ pub fn persist_content_addressed_temp_file<P: AsRef<Path>>(
     }
 }
 
+/// Returns whether symlinks can be created in `dir`. This is checked by
+/// creating one, since Windows only allows it in developer mode, and some file
+/// systems don't support them at all.
+pub fn check_symlink_support(dir: &Path) -> io::Result<bool> {
+    let temp_dir = tempfile::tempdir_in(dir)?;
+    let link_path = temp_dir.path().join("symlink");
+    #[cfg(unix)]
+    let result = std::os::unix::fs::symlink("target", &link_path);
+    #[cfg(windows)]
+    let result = std::os::windows::fs::symlink_file("target", &link_path);
+    Ok(result.is_ok())
+}
+
+/// Returns whether file names in `dir` are case-sensitive. They typically
+/// aren't on macOS and Windows.
+pub fn check_case_sensitivity(dir: &Path) -> io::Result<bool> {
+    let temp_dir = tempfile::tempdir_in(dir)?;
+    File::create(temp_dir.path().join("CASE"))?;
+    Ok(!temp_dir.path().join("case").exists())
+}
+
+/// Returns whether the executable bit of files in `dir` can be changed. It
+/// can't be on Windows, and some file systems (such as FAT) report all files
+/// as executable, or none.
+#[cfg(unix)]
+pub fn check_executable_bit_support(dir: &Path) -> io::Result<bool> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let temp_file = NamedTempFile::new_in(dir)?;
+    let is_executable = |mode: u32| -> io::Result<bool> {
+        temp_file
+            .as_file()
+            .set_permissions(std::fs::Permissions::from_mode(mode))?;
+        Ok(temp_file.as_file().metadata()?.permissions().mode() & 0o111 != 0)
+    };
+    Ok(is_executable(0o755)? && !is_executable(0o644)?)
+}
+
+#[cfg(windows)]
+pub fn check_executable_bit_support(_dir: &Path) -> io::Result<bool> {
+    Ok(false)
+}
+
+/// Creates `dst` as a copy-on-write clone of `src`, so the two files share
+/// their data blocks until one of them is modified. `dst` must not exist. Fails
+/// with `io::ErrorKind::Unsupported` if the platform has no cloning support;
+/// other errors typically mean the file system doesn't support it.
+pub fn reflink(src: &Path, dst: &Path) -> io::Result<()> {
+    reflink_impl(src, dst)
+}
+
+#[cfg(target_os = "linux")]
+fn reflink_impl(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::fs::OpenOptions;
+    use std::os::unix::io::AsRawFd;
+
+    // From <linux/fs.h>: _IOW(0x94, 9, int)
+    const FICLONE: u32 = 0x40049409;
+
+    let src_file = File::open(src)?;
+    let dst_file = OpenOptions::new().write(true).create_new(true).open(dst)?;
+    // SAFETY: Both file descriptors are valid for the duration of the call.
+    let ret = unsafe { libc::ioctl(dst_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) };
+    if ret == -1 {
+        let err = io::Error::last_os_error();
+        drop(dst_file);
+        std::fs::remove_file(dst).ok();
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(target_os = "macos")]
+fn reflink_impl(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::ffi::CString;
+    use std::os::unix::ffi::OsStrExt;
+
+    extern "C" {
+        fn clonefile(
+            src: *const libc::c_char,
+            dst: *const libc::c_char,
+            flags: libc::c_int,
+        ) -> libc::c_int;
+    }
+
+    let to_c_string = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))
+    };
+    let src = to_c_string(src)?;
+    let dst = to_c_string(dst)?;
+    // SAFETY: Both paths are valid NUL-terminated strings.
+    let ret = unsafe { clonefile(src.as_ptr(), dst.as_ptr(), 0) };
+    if ret == -1 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+fn reflink_impl(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
+
+/// Returns whether files in `dir` can be cloned with `reflink()`, as on Btrfs,
+/// XFS and APFS.
+pub fn check_reflink_support(dir: &Path) -> io::Result<bool> {
+    let temp_dir = tempfile::tempdir_in(dir)?;
+    let src = temp_dir.path().join("src");
+    std::fs::write(&src, b"contents")?;
+    Ok(reflink(&src, &temp_dir.path().join("dst")).is_ok())
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::Write;
@@ -69,4 +183,21 @@ mod tests {
 
         assert!(persist_content_addressed_temp_file(temp_file, &target).is_ok());
     }
+
+    #[test]
+    fn test_reflink() {
+        let temp_dir = testutils::new_temp_dir();
+        let src = temp_dir.path().join("src");
+        let dst = temp_dir.path().join("dst");
+        std::fs::write(&src, b"contents").unwrap();
+        // Whether cloning works depends on the file system the tests run on
+        if reflink(&src, &dst).is_ok() {
+            assert_eq!(std::fs::read(&dst).unwrap(), b"contents");
+            // The destination must not already exist
+            assert!(reflink(&src, &dst).is_err());
+            assert_eq!(std::fs::read(&dst).unwrap(), b"contents");
+        } else {
+            assert!(!dst.exists());
+        }
+    }
 }
@@ -15,11 +15,14 @@
 use std::collections::{HashSet, VecDeque};
 use std::fmt::{Debug, Error, Formatter};
 use std::ops::Range;
+use std::sync::Arc;
 
 use itertools::Itertools;
 
 use crate::diff;
-use crate::diff::{Diff, DiffHunk};
+use crate::diff::{Diff, DiffAlgorithm, DiffHunk};
+use crate::gitignore::GitIgnoreFile;
+use crate::repo_path::RepoPath;
 
 #[derive(PartialEq, Eq, Clone, Debug)]
 pub struct DiffLine<'a> {
@@ -196,6 +199,53 @@ impl Debug for MergeResult {
     }
 }
 
+/// Which diff algorithm to merge the contents of each file with.
+#[derive(Debug, Clone, Default)]
+pub struct MergeAlgorithms {
+    default: DiffAlgorithm,
+    // Files matching the patterns use the algorithm instead. The last match
+    // wins.
+    overrides: Vec<(Arc<GitIgnoreFile>, DiffAlgorithm)>,
+}
+
+impl MergeAlgorithms {
+    /// Merges all files with `algorithm`, unless overridden for some files by
+    /// `add_patterns()`.
+    pub fn new(algorithm: DiffAlgorithm) -> Self {
+        MergeAlgorithms {
+            default: algorithm,
+            overrides: vec![],
+        }
+    }
+
+    /// Merges the files matching `patterns`, which use the `.gitignore`
+    /// syntax, with `algorithm`.
+    pub fn add_patterns(&mut self, patterns: &[String], algorithm: DiffAlgorithm) {
+        if !patterns.is_empty() {
+            let patterns = GitIgnoreFile::empty().chain("", patterns.join("\n").as_bytes());
+            self.overrides.push((patterns, algorithm));
+        }
+    }
+
+    /// Whether all files are merged with the default algorithm.
+    pub fn is_default(&self) -> bool {
+        self.default == DiffAlgorithm::default()
+            && self
+                .overrides
+                .iter()
+                .all(|(_, algorithm)| *algorithm == DiffAlgorithm::default())
+    }
+
+    pub fn for_path(&self, path: &RepoPath) -> DiffAlgorithm {
+        let path = path.to_internal_file_string();
+        self.overrides
+            .iter()
+            .rev()
+            .find(|(patterns, _)| patterns.matches_file(&path))
+            .map_or(self.default, |(_, algorithm)| *algorithm)
+    }
+}
+
 /// A region where the base and two sides match.
 #[derive(Debug, PartialEq, Eq, Clone)]
 struct SyncRegion {
@@ -209,6 +259,16 @@ struct SyncRegion {
 // strings in `removes` or `adds` to make it true. Maybe we should have to
 // caller make it explicitly that way.
 pub fn merge(removes: &[&[u8]], adds: &[&[u8]]) -> MergeResult {
+    merge_with_algorithm(removes, adds, DiffAlgorithm::default())
+}
+
+/// Like `merge()`, but finds the lines each input has in common with the base
+/// with the given diff algorithm.
+pub fn merge_with_algorithm(
+    removes: &[&[u8]],
+    adds: &[&[u8]],
+    algorithm: DiffAlgorithm,
+) -> MergeResult {
     let num_removes = removes.len();
     // TODO: Using the first remove as base (first in the inputs) is how it's
     // usually done for 3-way conflicts. Are there better heuristics when there are
@@ -216,7 +276,7 @@ pub fn merge(removes: &[&[u8]], adds: &[&[u8]]) -> MergeResult {
     let mut diff_inputs = removes.to_vec();
     diff_inputs.extend(adds);
 
-    let diff = Diff::for_tokenizer(&diff_inputs, &diff::find_line_ranges);
+    let diff = Diff::for_tokenizer_with_algorithm(&diff_inputs, &diff::find_line_ranges, algorithm);
     let mut resolved_hunk: Vec<u8> = vec![];
     let mut merge_hunks: Vec<MergeHunk> = vec![];
     for diff_hunk in diff.hunks() {
@@ -296,6 +356,23 @@ pub fn merge(removes: &[&[u8]], adds: &[&[u8]]) -> MergeResult {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_merge_histogram_moved_line() {
+        // One side moved the last line to the top, and the other side changed
+        // a line between repeated lines
+        let base: &[u8] = b"a\nb\na\nc\n";
+        let side1: &[u8] = b"c\na\nb\na\n";
+        let side2: &[u8] = b"a\nB\na\nc\n";
+        assert!(matches!(
+            merge(&[base], &[side1, side2]),
+            MergeResult::Conflict(_)
+        ));
+        assert_eq!(
+            merge_with_algorithm(&[base], &[side1, side2], DiffAlgorithm::Histogram),
+            MergeResult::Resolved(b"c\na\nB\na\n".to_vec())
+        );
+    }
+
     #[test]
     fn test_merge() {
         // Unchanged and empty on all sides
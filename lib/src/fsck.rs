@@ -0,0 +1,190 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Verification of all objects in the store that are reachable from any
+//! operation.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::backend::{BackendError, CommitId, StoreObjectId, TreeId, TreeValue};
+use crate::gc::find_view_commit_ids;
+use crate::op_store::OpStoreError;
+use crate::repo::ReadonlyRepo;
+use crate::repo_path::{RepoPath, RepoPathJoin};
+use crate::store::Store;
+
+#[derive(Debug, Error)]
+pub enum FsckError {
+    #[error(transparent)]
+    BackendError(#[from] BackendError),
+    #[error(transparent)]
+    OpStoreError(#[from] OpStoreError),
+}
+
+/// An object that is missing from the store or doesn't match its ID.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CorruptObject {
+    pub object: StoreObjectId,
+    /// Whether the object is missing rather than corrupt.
+    pub missing: bool,
+    /// The commits that contain the object, sorted by ID.
+    pub commits: Vec<CommitId>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FsckReport {
+    /// The number of distinct objects that were verified.
+    pub checked_objects: usize,
+    /// The problems found, sorted by object ID.
+    pub corrupt_objects: Vec<CorruptObject>,
+}
+
+/// Verifies every commit reachable from the views of all operations, and the
+/// trees, files, symlinks and conflicts in them. The parents and predecessors
+/// of a corrupt commit can't be found, so they're only checked if they're
+/// reachable some other way.
+pub fn check_repo(repo: &ReadonlyRepo) -> Result<FsckReport, FsckError> {
+    let mut checker = Checker {
+        store: repo.store(),
+        checked_objects: 0,
+        failed: BTreeMap::new(),
+        problems: HashMap::new(),
+    };
+    let mut commits_by_object: HashMap<StoreObjectId, Vec<CommitId>> = HashMap::new();
+    let mut visited: HashSet<CommitId> = HashSet::new();
+    let mut commit_ids = find_view_commit_ids(repo)?;
+    while let Some(commit_id) = commit_ids.pop() {
+        if !visited.insert(commit_id.clone()) {
+            continue;
+        }
+        let problems = if checker.verify(StoreObjectId::Commit(commit_id.clone()))? {
+            let commit = checker.store.get_commit(&commit_id)?;
+            commit_ids.extend(commit.parent_ids().iter().cloned());
+            commit_ids.extend(commit.predecessor_ids().iter().cloned());
+            checker.check_tree(&RepoPath::root(), commit.tree_id())?
+        } else {
+            vec![StoreObjectId::Commit(commit_id.clone())]
+        };
+        // The same object may be found in several places in the tree
+        let problems: HashSet<StoreObjectId> = problems.into_iter().collect();
+        for object in problems {
+            commits_by_object
+                .entry(object)
+                .or_default()
+                .push(commit_id.clone());
+        }
+    }
+
+    let corrupt_objects = checker
+        .failed
+        .into_iter()
+        .map(|(object, missing)| {
+            let mut commits = commits_by_object.remove(&object).unwrap_or_default();
+            commits.sort();
+            CorruptObject {
+                object,
+                missing,
+                commits,
+            }
+        })
+        .collect();
+    Ok(FsckReport {
+        checked_objects: checker.checked_objects,
+        corrupt_objects,
+    })
+}
+
+struct Checker<'a> {
+    store: &'a Arc<Store>,
+    checked_objects: usize,
+    /// The objects that failed verification, mapped to whether they're missing.
+    failed: BTreeMap<StoreObjectId, bool>,
+    /// The objects that failed verification within each object that has been
+    /// checked, including the object itself.
+    problems: HashMap<StoreObjectId, Vec<StoreObjectId>>,
+}
+
+impl Checker<'_> {
+    /// Returns whether the object is intact, recording it if it's not.
+    fn verify(&mut self, object: StoreObjectId) -> Result<bool, BackendError> {
+        self.checked_objects += 1;
+        match self.store.verify(&object) {
+            Ok(()) => Ok(true),
+            Err(BackendError::NotFound) => {
+                self.failed.insert(object, true);
+                Ok(false)
+            }
+            Err(BackendError::Corrupt(_)) => {
+                self.failed.insert(object, false);
+                Ok(false)
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    fn check_tree(
+        &mut self,
+        dir: &RepoPath,
+        id: &TreeId,
+    ) -> Result<Vec<StoreObjectId>, BackendError> {
+        let object = StoreObjectId::Tree(id.clone());
+        if let Some(problems) = self.problems.get(&object) {
+            return Ok(problems.clone());
+        }
+        let problems = if self.verify(object.clone())? {
+            let tree = self.store.get_tree(dir, id)?;
+            let mut problems = vec![];
+            for entry in tree.data().entries() {
+                let path = dir.join(entry.name());
+                problems.extend(self.check_value(&path, entry.value())?);
+            }
+            problems
+        } else {
+            vec![object.clone()]
+        };
+        self.problems.insert(object, problems.clone());
+        Ok(problems)
+    }
+
+    fn check_value(
+        &mut self,
+        path: &RepoPath,
+        value: &TreeValue,
+    ) -> Result<Vec<StoreObjectId>, BackendError> {
+        let object = match value {
+            TreeValue::Normal { id, .. } => StoreObjectId::File(id.clone()),
+            TreeValue::Symlink(id) => StoreObjectId::Symlink(id.clone()),
+            TreeValue::Tree(id) => return self.check_tree(path, id),
+            TreeValue::GitSubmodule(_) => return Ok(vec![]),
+            TreeValue::Conflict(id) => StoreObjectId::Conflict(id.clone()),
+        };
+        if let Some(problems) = self.problems.get(&object) {
+            return Ok(problems.clone());
+        }
+        let mut problems = vec![];
+        if !self.verify(object.clone())? {
+            problems.push(object.clone());
+        } else if let TreeValue::Conflict(id) = value {
+            let conflict = self.store.read_conflict(path, id)?;
+            for part in conflict.removes.iter().chain(&conflict.adds) {
+                problems.extend(self.check_value(path, &part.value)?);
+            }
+        }
+        self.problems.insert(object, problems.clone());
+        Ok(problems)
+    }
+}
@@ -0,0 +1,162 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Filesystem monitors, which let the working copy find changed files without
+//! walking the whole tree.
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use serde_json::{json, Value};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FsMonitorError {
+    #[error("Failed to run {program}: {err}")]
+    ExecuteError {
+        program: String,
+        err: std::io::Error,
+    },
+    #[error("Unexpected response from {program}: {message}")]
+    InvalidResponse { program: String, message: String },
+}
+
+/// The result of querying a filesystem monitor.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FsMonitorChanges {
+    /// Opaque token to pass to the next query.
+    pub clock: String,
+    /// Paths relative to the working copy root that may have changed since the
+    /// clock passed to the query, or `None` if every file may have changed.
+    pub changed_files: Option<Vec<PathBuf>>,
+}
+
+pub trait FsMonitor {
+    /// Returns the files that may have changed since `since`, which is a clock
+    /// returned by a previous query. If `since` is `None`, all files should be
+    /// considered changed.
+    fn query_changed_files(
+        &mut self,
+        since: Option<&str>,
+    ) -> Result<FsMonitorChanges, FsMonitorError>;
+}
+
+/// Queries Watchman (https://facebook.github.io/watchman/) through its command
+/// line client.
+#[derive(Debug, Clone)]
+pub struct Watchman {
+    program: String,
+    root: PathBuf,
+}
+
+impl Watchman {
+    pub fn new(program: String, root: &Path) -> Self {
+        Watchman {
+            program,
+            root: root.to_owned(),
+        }
+    }
+
+    fn invalid_response(&self, message: impl Into<String>) -> FsMonitorError {
+        FsMonitorError::InvalidResponse {
+            program: self.program.clone(),
+            message: message.into(),
+        }
+    }
+
+    fn run(&self, request: Value) -> Result<Value, FsMonitorError> {
+        let execute_error = |err| FsMonitorError::ExecuteError {
+            program: self.program.clone(),
+            err,
+        };
+        let mut child = Command::new(&self.program)
+            .args(["-j", "--no-pretty"])
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(execute_error)?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(request.to_string().as_bytes())
+            .map_err(execute_error)?;
+        let output = child.wait_with_output().map_err(execute_error)?;
+        if !output.status.success() {
+            return Err(self.invalid_response(String::from_utf8_lossy(&output.stderr).trim_end()));
+        }
+        let response: Value = serde_json::from_slice(&output.stdout)
+            .map_err(|err| self.invalid_response(err.to_string()))?;
+        if let Some(error) = response.get("error") {
+            return Err(self.invalid_response(error.to_string()));
+        }
+        Ok(response)
+    }
+
+    fn get_string<'a>(&self, response: &'a Value, key: &str) -> Result<&'a str, FsMonitorError> {
+        response[key]
+            .as_str()
+            .ok_or_else(|| self.invalid_response(format!("missing \"{key}\"")))
+    }
+}
+
+impl FsMonitor for Watchman {
+    fn query_changed_files(
+        &mut self,
+        since: Option<&str>,
+    ) -> Result<FsMonitorChanges, FsMonitorError> {
+        let watch = self.run(json!(["watch-project", self.root]))?;
+        let watch_root = self.get_string(&watch, "watch")?;
+        let since = match since {
+            Some(since) => since,
+            None => {
+                let response = self.run(json!(["clock", watch_root]))?;
+                return Ok(FsMonitorChanges {
+                    clock: self.get_string(&response, "clock")?.to_string(),
+                    changed_files: None,
+                });
+            }
+        };
+        let mut query = json!({"since": since, "fields": ["name"]});
+        if let Some(relative_path) = watch.get("relative_path") {
+            query["relative_root"] = relative_path.clone();
+        }
+        let response = self.run(json!(["query", watch_root, query]))?;
+        let clock = self.get_string(&response, "clock")?.to_string();
+        if response["is_fresh_instance"].as_bool().unwrap_or(false) {
+            // Watchman has restarted, so it doesn't know what changed since `since`
+            return Ok(FsMonitorChanges {
+                clock,
+                changed_files: None,
+            });
+        }
+        let files = response["files"]
+            .as_array()
+            .ok_or_else(|| self.invalid_response("missing \"files\""))?;
+        let changed_files = files
+            .iter()
+            .map(|file| {
+                file.as_str()
+                    .map(PathBuf::from)
+                    .ok_or_else(|| self.invalid_response("file name is not a string"))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(FsMonitorChanges {
+            clock,
+            changed_files: Some(changed_files),
+        })
+    }
+}
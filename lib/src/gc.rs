@@ -0,0 +1,153 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Garbage collection of store objects that aren't reachable from any
+//! operation.
+
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+use thiserror::Error;
+
+use crate::backend::{BackendError, CommitId, GcStats, ReachableObjects, TreeId, TreeValue};
+use crate::op_store::{OpStoreError, OperationId, RefTarget};
+use crate::repo::ReadonlyRepo;
+use crate::repo_path::{RepoPath, RepoPathJoin};
+use crate::store::Store;
+
+#[derive(Debug, Error)]
+pub enum GcError {
+    #[error(transparent)]
+    BackendError(#[from] BackendError),
+    #[error(transparent)]
+    OpStoreError(#[from] OpStoreError),
+}
+
+/// Removes the objects in the repo's store that aren't reachable from the view
+/// of any operation, including old operations that can still be restored.
+/// Objects written after `keep_newer` are kept since they may have been written
+/// by a command that hasn't finished yet. If `dry_run` is set, only reports
+/// what would be removed.
+pub fn collect_garbage(
+    repo: &ReadonlyRepo,
+    keep_newer: SystemTime,
+    dry_run: bool,
+) -> Result<GcStats, GcError> {
+    let reachable = find_reachable_objects(repo)?;
+    Ok(repo.store().gc(&reachable, keep_newer, dry_run)?)
+}
+
+/// Finds the objects reachable from the views of all operations.
+pub fn find_reachable_objects(repo: &ReadonlyRepo) -> Result<ReachableObjects, GcError> {
+    let mut commit_ids = find_view_commit_ids(repo)?;
+    let store = repo.store();
+    let mut reachable = ReachableObjects::default();
+    while let Some(commit_id) = commit_ids.pop() {
+        if reachable.commits.contains(&commit_id) {
+            continue;
+        }
+        let commit = store.get_commit(&commit_id)?;
+        add_tree(store, &RepoPath::root(), commit.tree_id(), &mut reachable)?;
+        commit_ids.extend(commit.parent_ids().iter().cloned());
+        commit_ids.extend(commit.predecessor_ids().iter().cloned());
+        reachable.commits.insert(commit_id);
+    }
+    Ok(reachable)
+}
+
+/// Returns the commits referenced by the views of all operations. The list may
+/// contain duplicates.
+pub fn find_view_commit_ids(repo: &ReadonlyRepo) -> Result<Vec<CommitId>, OpStoreError> {
+    let op_store = repo.op_store();
+    let mut visited_ops: HashSet<OperationId> = HashSet::new();
+    let mut op_ids = repo.op_heads_store().get_op_heads();
+    let mut commit_ids: Vec<CommitId> = vec![];
+    while let Some(op_id) = op_ids.pop() {
+        if !visited_ops.insert(op_id.clone()) {
+            continue;
+        }
+        let operation = op_store.read_operation(&op_id)?;
+        let view = op_store.read_view(&operation.view_id)?;
+        commit_ids.extend(view.head_ids);
+        commit_ids.extend(view.public_head_ids);
+        for branch_target in view.branches.into_values() {
+            commit_ids.extend(branch_target.local_target.iter().flat_map(ref_target_ids));
+            commit_ids.extend(
+                branch_target
+                    .remote_targets
+                    .values()
+                    .flat_map(ref_target_ids),
+            );
+        }
+        commit_ids.extend(view.tags.values().flat_map(ref_target_ids));
+        commit_ids.extend(view.git_refs.values().flat_map(ref_target_ids));
+        commit_ids.extend(view.git_head);
+        commit_ids.extend(view.wc_commit_ids.into_values());
+        op_ids.extend(operation.parents);
+    }
+    Ok(commit_ids)
+}
+
+fn ref_target_ids(target: &RefTarget) -> Vec<CommitId> {
+    let mut ids = target.adds();
+    ids.extend(target.removes());
+    ids
+}
+
+fn add_tree(
+    store: &Arc<Store>,
+    dir: &RepoPath,
+    id: &TreeId,
+    reachable: &mut ReachableObjects,
+) -> Result<(), BackendError> {
+    if !reachable.trees.insert(id.clone()) {
+        return Ok(());
+    }
+    let tree = store.get_tree(dir, id)?;
+    for entry in tree.data().entries() {
+        let path = dir.join(entry.name());
+        add_value(store, &path, entry.value(), reachable)?;
+    }
+    Ok(())
+}
+
+fn add_value(
+    store: &Arc<Store>,
+    path: &RepoPath,
+    value: &TreeValue,
+    reachable: &mut ReachableObjects,
+) -> Result<(), BackendError> {
+    match value {
+        TreeValue::Normal { id, .. } => {
+            reachable.files.insert(id.clone());
+        }
+        TreeValue::Symlink(id) => {
+            reachable.symlinks.insert(id.clone());
+        }
+        TreeValue::Tree(id) => {
+            add_tree(store, path, id, reachable)?;
+        }
+        TreeValue::GitSubmodule(_) => {}
+        TreeValue::Conflict(id) => {
+            if reachable.conflicts.insert(id.clone()) {
+                let conflict = store.read_conflict(path, id)?;
+                for part in conflict.removes.iter().chain(&conflict.adds) {
+                    add_value(store, path, &part.value, reachable)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -12,17 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, BTreeSet, HashSet};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fs::OpenOptions;
 use std::io::{Read, Write};
+use std::path::PathBuf;
+use std::process::Command;
+use std::rc::Rc;
 use std::sync::Arc;
 
-use git2::{Oid, RemoteCallbacks};
+use git2::Oid;
 use itertools::Itertools;
 use thiserror::Error;
 
-use crate::backend::CommitId;
+use crate::backend::{CommitId, Signature};
 use crate::commit::Commit;
+use crate::git_backend::read_shallow_commits;
 use crate::op_store::{OperationId, RefTarget};
 use crate::operation::Operation;
 use crate::repo::{MutableRepo, ReadonlyRepo, RepoRef};
@@ -106,11 +111,17 @@ pub fn import_refs(
     }
     for (full_name, (old_git_target, new_git_target)) in changed_git_refs {
         if let Some(ref_name) = parse_git_ref(&full_name) {
+            let tracked = match &ref_name {
+                RefName::RemoteBranch { branch, remote } => {
+                    mut_repo.is_remote_branch_tracked(branch, remote)
+                }
+                _ => false,
+            };
             // Apply the change that happened in git since last time we imported refs
             mut_repo.merge_single_ref(&ref_name, old_git_target.as_ref(), new_git_target.as_ref());
-            // If a git remote-tracking branch changed, apply the change to the local branch
-            // as well
-            if let RefName::RemoteBranch { branch, remote: _ } = ref_name {
+            // If a tracked git remote-tracking branch changed, apply the change to the local
+            // branch as well
+            if let (true, RefName::RemoteBranch { branch, remote: _ }) = (tracked, ref_name) {
                 mut_repo.merge_single_ref(
                     &RefName::LocalBranch(branch),
                     old_git_target.as_ref(),
@@ -152,6 +163,33 @@ pub fn import_refs(
     Ok(())
 }
 
+/// Adds the commits checked out in the linked worktrees of the Git repo to the
+/// visible heads. Returns the checked-out commits by worktree name. Worktrees
+/// whose directory is gone and worktrees on an unborn branch are skipped.
+pub fn import_worktree_heads(
+    mut_repo: &mut MutableRepo,
+    git_repo: &git2::Repository,
+) -> Result<BTreeMap<String, CommitId>, GitImportError> {
+    let mut heads = BTreeMap::new();
+    for name in git_repo.worktrees()?.iter().flatten() {
+        let worktree = git_repo.find_worktree(name)?;
+        if worktree.validate().is_err() {
+            continue;
+        }
+        let worktree_repo = git2::Repository::open_from_worktree(&worktree)?;
+        let head_result = worktree_repo
+            .head()
+            .and_then(|head_ref| head_ref.peel_to_commit());
+        if let Ok(head_git_commit) = head_result {
+            let head_commit_id = CommitId::from_bytes(head_git_commit.id().as_bytes());
+            let head_commit = mut_repo.store().get_commit(&head_commit_id).unwrap();
+            mut_repo.add_head(&head_commit);
+            heads.insert(name.to_string(), head_commit_id);
+        }
+    }
+    Ok(heads)
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum GitExportError {
     #[error("Cannot export conflicted branch '{0}'")]
@@ -226,6 +264,33 @@ pub fn export_changes(
     Ok(())
 }
 
+/// Reflect the changes made in a transaction in the underlying Git repo, and
+/// record the exported refs in the transaction's view so they're not imported
+/// back as new changes.
+pub fn export_changes_and_record(
+    mut_repo: &mut MutableRepo,
+    git_repo: &git2::Repository,
+) -> Result<(), GitExportError> {
+    let base_repo = mut_repo.base_repo().clone();
+    export_changes(base_repo.as_repo_ref(), mut_repo.as_repo_ref(), git_repo)?;
+    let changed_branches = base_repo
+        .view()
+        .branches()
+        .keys()
+        .chain(mut_repo.view().branches().keys())
+        .filter(|name| base_repo.view().get_local_branch(name) != mut_repo.get_local_branch(name))
+        .cloned()
+        .collect::<BTreeSet<_>>();
+    for branch_name in changed_branches {
+        let git_ref_name = format!("refs/heads/{}", branch_name);
+        match mut_repo.get_local_branch(&branch_name) {
+            Some(target) => mut_repo.set_git_ref(git_ref_name, target),
+            None => mut_repo.remove_git_ref(&git_ref_name),
+        }
+    }
+    Ok(())
+}
+
 /// Reflect changes made in the Jujutsu repo since last export in the underlying
 /// Git repo. If this is the first export, nothing will be exported. The
 /// exported state's operation ID is recorded in the repo
@@ -262,15 +327,204 @@ pub fn export_refs(
 pub enum GitFetchError {
     #[error("No git remote named '{0}'")]
     NoSuchRemote(String),
+    #[error("Authentication with the remote failed: {0}")]
+    AuthenticationFailed(String),
+    #[error("Host key verification failed: {0}")]
+    HostKeyVerificationFailed(String),
+    #[error("Failed to run git: {0}")]
+    GitCommandFailed(String),
     // TODO: I'm sure there are other errors possible, such as transport-level errors.
     #[error("Unexpected git error when fetching: {0}")]
     InternalGitError(#[from] git2::Error),
 }
 
+#[derive(Error, Debug, PartialEq, Eq)]
+#[error("Invalid refspec '{0}'")]
+pub struct RefSpecParseError(pub String);
+
+/// A mapping from refs to refs, like Git's `<source>:<destination>` refspecs.
+/// Both sides may contain one `*`, which matches any string in the source and
+/// is replaced by the matched string in the destination.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RefSpec {
+    pub source: String,
+    pub destination: String,
+}
+
+impl RefSpec {
+    /// Parses a refspec for fetching from `remote_name`. Names that don't start
+    /// with `refs/` are branch names, so `main` is short for
+    /// `refs/heads/main:refs/remotes/<remote_name>/main`. A leading `+` is
+    /// accepted but has no effect, since remote-tracking branches are always
+    /// updated even if it's not a fast-forward.
+    pub fn parse_fetch(remote_name: &str, spec: &str) -> Result<Self, RefSpecParseError> {
+        let (source, destination) = split_refspec(spec)?;
+        let source = qualify_ref_name("refs/heads/", source);
+        let destination = match destination {
+            Some(destination) => {
+                qualify_ref_name(&format!("refs/remotes/{}/", remote_name), destination)
+            }
+            None => match source.strip_prefix("refs/heads/") {
+                Some(branch) => format!("refs/remotes/{}/{}", remote_name, branch),
+                None => source.clone(),
+            },
+        };
+        RefSpec::new(spec, source, destination)
+    }
+
+    /// Parses a refspec for pushing. The source is a local branch name and the
+    /// destination is the ref to update on the remote, where names that don't
+    /// start with `refs/` are branch names. Without a destination, the branch
+    /// is pushed to the branch with the same name.
+    pub fn parse_push(spec: &str) -> Result<Self, RefSpecParseError> {
+        let (source, destination) = split_refspec(spec)?;
+        if spec.starts_with('+') || source.starts_with("refs/") {
+            return Err(RefSpecParseError(spec.to_string()));
+        }
+        let destination = qualify_ref_name("refs/heads/", destination.unwrap_or(source));
+        RefSpec::new(spec, source.to_string(), destination)
+    }
+
+    fn new(spec: &str, source: String, destination: String) -> Result<Self, RefSpecParseError> {
+        if source.matches('*').count() > 1
+            || source.matches('*').count() != destination.matches('*').count()
+        {
+            return Err(RefSpecParseError(spec.to_string()));
+        }
+        Ok(RefSpec {
+            source,
+            destination,
+        })
+    }
+
+    /// Returns the destination for `name` if it matches the source.
+    pub fn map(&self, name: &str) -> Option<String> {
+        match self.source.split_once('*') {
+            None => (name == self.source).then(|| self.destination.clone()),
+            Some((prefix, suffix)) => {
+                let matched = name.strip_prefix(prefix)?.strip_suffix(suffix)?;
+                Some(self.destination.replacen('*', matched, 1))
+            }
+        }
+    }
+}
+
+fn split_refspec(spec: &str) -> Result<(&str, Option<&str>), RefSpecParseError> {
+    let (source, destination) = match spec.strip_prefix('+').unwrap_or(spec).split_once(':') {
+        Some((source, destination)) => (source, Some(destination)),
+        None => (spec.strip_prefix('+').unwrap_or(spec), None),
+    };
+    if source.is_empty() || destination == Some("") {
+        return Err(RefSpecParseError(spec.to_string()));
+    }
+    Ok((source, destination))
+}
+
+fn qualify_ref_name(prefix: &str, name: &str) -> String {
+    if name.starts_with("refs/") {
+        name.to_string()
+    } else {
+        format!("{}{}", prefix, name)
+    }
+}
+
+/// Returns the Git note attached to the commit in `notes_ref`, or in the
+/// default notes ref (`core.notesRef`, or else `refs/notes/commits`) if it's
+/// `None`.
+pub fn get_note(
+    git_repo: &git2::Repository,
+    notes_ref: Option<&str>,
+    commit_id: &CommitId,
+) -> Option<String> {
+    let oid = Oid::from_bytes(commit_id.as_bytes()).ok()?;
+    let note = git_repo.find_note(notes_ref, oid).ok()?;
+    note.message().map(str::to_string)
+}
+
+/// Copies the Git notes of a rewritten commit to the new commit, in the notes
+/// refs matching Git's `notes.rewriteRef`, like `git rebase` does. Notes the new
+/// commit already has are kept.
+pub fn copy_notes_for_rewrite(
+    git_repo: &git2::Repository,
+    old_id: &CommitId,
+    new_id: &CommitId,
+    committer: &Signature,
+) -> Result<(), git2::Error> {
+    let config = git_repo.config()?;
+    let mut rewrite_ref_patterns = vec![];
+    if let Ok(mut entries) = config.multivar("notes.rewriteRef", None) {
+        while let Some(entry) = entries.next() {
+            if let Some(pattern) = entry?.value() {
+                rewrite_ref_patterns.push(pattern.to_string());
+            }
+        }
+    }
+    if rewrite_ref_patterns.is_empty() {
+        return Ok(());
+    }
+    let old_oid = Oid::from_bytes(old_id.as_bytes())?;
+    let new_oid = Oid::from_bytes(new_id.as_bytes())?;
+    let signature = git2::Signature::now(&committer.name, &committer.email)?;
+    for pattern in rewrite_ref_patterns {
+        for notes_ref in git_repo.references_glob(&pattern)? {
+            let notes_ref = notes_ref?;
+            let notes_ref_name = match notes_ref.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            let message = match git_repo.find_note(Some(notes_ref_name), old_oid) {
+                Ok(note) => note.message_bytes().to_vec(),
+                Err(_) => continue,
+            };
+            if git_repo.find_note(Some(notes_ref_name), new_oid).is_ok() {
+                continue;
+            }
+            git_repo.note(
+                &signature,
+                &signature,
+                Some(notes_ref_name),
+                new_oid,
+                &String::from_utf8_lossy(&message),
+                false,
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Fetches from the remote and imports the updated refs, telling the remote
+/// that we already have the visible heads of the repo so objects we got some
+/// other way (e.g. from another remote) are not downloaded again. If `refspecs`
+/// is empty, the refspecs configured for the remote in Git are used.
 pub fn fetch(
     mut_repo: &mut MutableRepo,
     git_repo: &git2::Repository,
     remote_name: &str,
+    refspecs: &[RefSpec],
+    callbacks: RemoteCallbacks<'_>,
+) -> Result<Option<String>, GitFetchError> {
+    let negotiation_tips = mut_repo.view().heads().iter().cloned().collect_vec();
+    fetch_with_negotiation_tips(
+        mut_repo,
+        git_repo,
+        remote_name,
+        refspecs,
+        &negotiation_tips,
+        callbacks,
+    )
+}
+
+/// Like `fetch()`, but advertises `negotiation_tips` (and their ancestors) to
+/// the remote as commits we already have. libgit2 always advertises the
+/// commits pointed to by the refs in the Git repo too, so the tips can only add
+/// to what's advertised.
+pub fn fetch_with_negotiation_tips(
+    mut_repo: &mut MutableRepo,
+    git_repo: &git2::Repository,
+    remote_name: &str,
+    refspecs: &[RefSpec],
+    negotiation_tips: &[CommitId],
+    callbacks: RemoteCallbacks<'_>,
 ) -> Result<Option<String>, GitFetchError> {
     let mut remote =
         git_repo
@@ -288,10 +542,24 @@ pub fn fetch(
     let mut proxy_options = git2::ProxyOptions::new();
     proxy_options.auto();
     fetch_options.proxy_options(proxy_options);
-    let callbacks = create_remote_callbacks();
+    let (callbacks, failure) = callbacks.into_git(git_repo, remote.url());
     fetch_options.remote_callbacks(callbacks);
-    let refspec: &[&str] = &[];
-    remote.download(refspec, Some(&mut fetch_options))?;
+    let refspecs = refspecs
+        .iter()
+        .map(|refspec| format!("+{}:{}", refspec.source, refspec.destination))
+        .collect_vec();
+    // libgit2 has no API for negotiation tips, but it advertises every commit
+    // pointed to by a ref (other than tags) as a "have", so point temporary refs
+    // at the tips for the duration of the download.
+    let negotiation_refs = create_negotiation_refs(git_repo, negotiation_tips)?;
+    let download_result = remote.download(&refspecs, Some(&mut fetch_options));
+    remove_negotiation_refs(negotiation_refs)?;
+    download_result.map_err(|err| {
+        RemoteFailure::take(&failure, &err).map_or(
+            GitFetchError::InternalGitError(err),
+            RemoteFailure::into_fetch_error,
+        )
+    })?;
     remote.update_tips(None, false, git2::AutotagOption::Unspecified, None)?;
     remote.prune(None)?;
     // TODO: We could make it optional to get the default branch since we only care
@@ -313,6 +581,135 @@ pub fn fetch(
     Ok(default_branch)
 }
 
+/// The result of `fetch_with_depth()`.
+#[derive(Debug, Default)]
+pub struct DepthLimitedFetch {
+    /// The remote's default branch, if it has one.
+    pub default_branch: Option<String>,
+    /// Whether commits that were already in the repo gained or lost parents
+    /// because the shallow boundary moved. The index still has their old
+    /// parents until it's rebuilt with `ReadonlyRepo::reindex()`.
+    pub index_outdated: bool,
+}
+
+/// Like `fetch_with_negotiation_tips()`, but only fetches the last `depth`
+/// commits of each fetched branch. The commits at the boundary are treated as
+/// root commits. Fetching again with a larger depth deepens the history.
+///
+/// libgit2 can't fetch shallow history, so this runs `git fetch --depth`
+/// instead, which uses the credentials configured for Git.
+pub fn fetch_with_depth(
+    mut_repo: &mut MutableRepo,
+    git_repo: &git2::Repository,
+    remote_name: &str,
+    refspecs: &[RefSpec],
+    negotiation_tips: &[CommitId],
+    depth: u32,
+) -> Result<DepthLimitedFetch, GitFetchError> {
+    if git_repo.find_remote(remote_name).is_err() {
+        return Err(GitFetchError::NoSuchRemote(remote_name.to_string()));
+    }
+    let old_shallow_commits = read_shallow_commits(git_repo.path());
+    let mut command = Command::new("git");
+    command
+        .arg("--git-dir")
+        .arg(git_repo.path())
+        .args(["fetch", "--quiet", "--prune"])
+        .arg(format!("--depth={depth}"));
+    for commit_id in negotiation_tips {
+        // Skip the virtual root commit and any other commits that aren't in the
+        // Git repo
+        match Oid::from_bytes(commit_id.as_bytes()) {
+            Ok(oid) if git_repo.find_commit(oid).is_ok() => {
+                command.arg(format!("--negotiation-tip={oid}"));
+            }
+            _ => {}
+        }
+    }
+    command.arg(remote_name);
+    for refspec in refspecs {
+        command.arg(format!("+{}:{}", refspec.source, refspec.destination));
+    }
+    run_git_command(&mut command)?;
+
+    let new_shallow_commits = read_shallow_commits(git_repo.path());
+    let changed_commits = old_shallow_commits
+        .symmetric_difference(&new_shallow_commits)
+        .filter(|id| mut_repo.index().has_id(id))
+        .cloned()
+        .collect_vec();
+    mut_repo.store().forget_commits(&changed_commits);
+
+    let mut command = Command::new("git");
+    command.arg("--git-dir").arg(git_repo.path()).args([
+        "ls-remote",
+        "--symref",
+        remote_name,
+        "HEAD",
+    ]);
+    let output = run_git_command(&mut command)?;
+    // The output starts with a line like "ref: refs/heads/main\tHEAD"
+    let default_branch = String::from_utf8_lossy(&output)
+        .lines()
+        .find_map(|line| line.strip_prefix("ref: ")?.strip_suffix("\tHEAD"))
+        .and_then(|default_ref| match parse_git_ref(default_ref) {
+            // LocalBranch here is the local branch on the remote, so it's really the remote
+            // branch
+            Some(RefName::LocalBranch(branch_name)) => Some(branch_name),
+            _ => None,
+        });
+
+    import_refs(mut_repo, git_repo).map_err(|err| match err {
+        GitImportError::InternalGitError(source) => GitFetchError::InternalGitError(source),
+    })?;
+    Ok(DepthLimitedFetch {
+        default_branch,
+        index_outdated: !changed_commits.is_empty(),
+    })
+}
+
+/// Runs the `git` command and returns its stdout.
+fn run_git_command(command: &mut Command) -> Result<Vec<u8>, GitFetchError> {
+    let output = command
+        .output()
+        .map_err(|err| GitFetchError::GitCommandFailed(err.to_string()))?;
+    if !output.status.success() {
+        return Err(GitFetchError::GitCommandFailed(
+            String::from_utf8_lossy(&output.stderr)
+                .trim_end()
+                .to_string(),
+        ));
+    }
+    Ok(output.stdout)
+}
+
+const NEGOTIATION_REF_NAMESPACE: &str = "refs/jj/negotiation/";
+
+fn create_negotiation_refs<'repo>(
+    git_repo: &'repo git2::Repository,
+    negotiation_tips: &[CommitId],
+) -> Result<Vec<git2::Reference<'repo>>, git2::Error> {
+    let mut refs = vec![];
+    for (i, commit_id) in negotiation_tips.iter().enumerate() {
+        // Skip the virtual root commit and any other commits that aren't in the
+        // Git repo
+        let oid = match Oid::from_bytes(commit_id.as_bytes()) {
+            Ok(oid) if git_repo.find_commit(oid).is_ok() => oid,
+            _ => continue,
+        };
+        let ref_name = format!("{NEGOTIATION_REF_NAMESPACE}{i}");
+        refs.push(git_repo.reference(&ref_name, oid, true, "negotiation tip for fetch")?);
+    }
+    Ok(refs)
+}
+
+fn remove_negotiation_refs(refs: Vec<git2::Reference<'_>>) -> Result<(), git2::Error> {
+    for mut git_ref in refs {
+        git_ref.delete()?;
+    }
+    Ok(())
+}
+
 #[derive(Error, Debug, PartialEq)]
 pub enum GitPushError {
     #[error("No git remote named '{0}'")]
@@ -321,6 +718,12 @@ pub enum GitPushError {
     NotFastForward,
     #[error("Remote rejected the update of some refs (do you have permission to push to {0:?}?)")]
     RefUpdateRejected(Vec<String>),
+    #[error("Some refs have moved on the remote: {0:?}")]
+    UnexpectedRemoteRefs(Vec<String>),
+    #[error("Authentication with the remote failed: {0}")]
+    AuthenticationFailed(String),
+    #[error("Host key verification failed: {0}")]
+    HostKeyVerificationFailed(String),
     // TODO: I'm sure there are other errors possible, such as transport-level errors,
     // and errors caused by the remote rejecting the push.
     #[error("Unexpected git error when pushing: {0}")]
@@ -336,6 +739,7 @@ pub fn push_commit(
     // It's a blunt "force" option instead until git2-rs supports the "push negotiation" callback
     // (https://github.com/rust-lang/git2-rs/issues/733).
     force: bool,
+    callbacks: RemoteCallbacks<'_>,
 ) -> Result<(), GitPushError> {
     push_updates(
         git_repo,
@@ -343,17 +747,24 @@ pub fn push_commit(
         &[GitRefUpdate {
             qualified_name: format!("refs/heads/{}", remote_branch),
             force,
+            expected_current_target: None,
             new_target: Some(target.id().clone()),
         }],
+        callbacks,
     )
 }
 
 pub struct GitRefUpdate {
     pub qualified_name: String,
-    // TODO: We want this to be a `current_target: Option<CommitId>` for the expected current
-    // commit on the remote. It's a blunt "force" option instead until git2-rs supports the
-    // "push negotiation" callback (https://github.com/rust-lang/git2-rs/issues/733).
+    /// Whether to update the ref even if it's not a fast-forward.
     pub force: bool,
+    /// If set, the ref is only updated if it currently points to this commit on
+    /// the remote, or doesn't exist there for `Some(None)`, like with Git's
+    /// `--force-with-lease`.
+    // TODO: The remote is checked just before pushing. Use the "push negotiation"
+    // callback instead once git2-rs supports it
+    // (https://github.com/rust-lang/git2-rs/issues/733).
+    pub expected_current_target: Option<Option<CommitId>>,
     pub new_target: Option<CommitId>,
 }
 
@@ -361,12 +772,17 @@ pub fn push_updates(
     git_repo: &git2::Repository,
     remote_name: &str,
     updates: &[GitRefUpdate],
+    callbacks: RemoteCallbacks<'_>,
 ) -> Result<(), GitPushError> {
     let mut temp_refs = vec![];
     let mut qualified_remote_refs = vec![];
+    let mut expected_remote_refs = vec![];
     let mut refspecs = vec![];
     for update in updates {
         qualified_remote_refs.push(update.qualified_name.as_str());
+        if let Some(expected_target) = &update.expected_current_target {
+            expected_remote_refs.push((update.qualified_name.as_str(), expected_target.as_ref()));
+        }
         if let Some(new_target) = &update.new_target {
             // Create a temporary ref to work around https://github.com/libgit2/libgit2/issues/3178
             let temp_ref_name = format!("refs/jj/git-push/{}", new_target.hex());
@@ -386,7 +802,14 @@ pub fn push_updates(
             refspecs.push(format!(":{}", update.qualified_name));
         }
     }
-    let result = push_refs(git_repo, remote_name, &qualified_remote_refs, &refspecs);
+    let result = push_refs(
+        git_repo,
+        remote_name,
+        &qualified_remote_refs,
+        &expected_remote_refs,
+        &refspecs,
+        callbacks,
+    );
     for mut temp_ref in temp_refs {
         // TODO: Figure out how to do the equivalent of absl::Cleanup for
         // temp_ref.delete().
@@ -406,7 +829,9 @@ fn push_refs(
     git_repo: &git2::Repository,
     remote_name: &str,
     qualified_remote_refs: &[&str],
+    expected_remote_refs: &[(&str, Option<&CommitId>)],
     refspecs: &[String],
+    mut callbacks: RemoteCallbacks<'_>,
 ) -> Result<(), GitPushError> {
     let mut remote =
         git_repo
@@ -420,12 +845,68 @@ fn push_refs(
                 }
                 _ => GitPushError::InternalGitError(err),
             })?;
+    // Progress is reported by the callbacks for the push itself, not for connecting
+    let progress = callbacks.progress.take();
+    let (callbacks, failure) = callbacks.into_git(git_repo, remote.url());
+    let map_remote_err = |err: git2::Error| {
+        RemoteFailure::take(&failure, &err).map_or(
+            GitPushError::InternalGitError(err),
+            RemoteFailure::into_push_error,
+        )
+    };
+    // Connect first to see where the refs are on the remote. The push then reuses
+    // the connection, so there's little time for them to move in between.
+    let mut proxy_options = git2::ProxyOptions::new();
+    proxy_options.auto();
+    let mut connection = remote
+        .connect_auth(git2::Direction::Push, Some(callbacks), Some(proxy_options))
+        .map_err(map_remote_err)?;
+    // Branches that aren't expected on the remote are pushed without "+", so Git
+    // already refuses to overwrite them. Skipping the listing in that case also
+    // avoids git2's `list()`, which builds a slice from a null pointer when the
+    // remote has no refs yet.
+    let remote_heads: HashMap<_, _> = if expected_remote_refs
+        .iter()
+        .any(|(_, expected_target)| expected_target.is_some())
+    {
+        connection
+            .list()?
+            .iter()
+            .map(|head| (head.name().to_string(), head.oid()))
+            .collect()
+    } else {
+        HashMap::new()
+    };
+    let moved_remote_refs = expected_remote_refs
+        .iter()
+        .filter(|(name, expected_target)| {
+            let expected_oid =
+                expected_target.map(|id| git2::Oid::from_bytes(id.as_bytes()).unwrap());
+            remote_heads.get(*name).copied() != expected_oid
+        })
+        .map(|(name, _)| name.to_string())
+        .sorted()
+        .collect_vec();
+    if !moved_remote_refs.is_empty() {
+        return Err(GitPushError::UnexpectedRemoteRefs(moved_remote_refs));
+    }
+
     let mut remaining_remote_refs: HashSet<_> = qualified_remote_refs.iter().copied().collect();
     let mut push_options = git2::PushOptions::new();
     let mut proxy_options = git2::ProxyOptions::new();
     proxy_options.auto();
     push_options.proxy_options(proxy_options);
-    let mut callbacks = create_remote_callbacks();
+    let mut callbacks = git2::RemoteCallbacks::new();
+    if let Some(progress) = progress {
+        callbacks.push_transfer_progress(move |current, total, bytes| {
+            progress(&Progress {
+                stage: TransferStage::Sending,
+                processed: current,
+                total,
+                bytes,
+            });
+        });
+    }
     callbacks.push_update_reference(|refname, status| {
         // The status is Some if the ref update was rejected
         if status.is_none() {
@@ -434,13 +915,14 @@ fn push_refs(
         Ok(())
     });
     push_options.remote_callbacks(callbacks);
-    remote
+    connection
+        .remote()
         .push(refspecs, Some(&mut push_options))
         .map_err(|err| match (err.class(), err.code()) {
             (git2::ErrorClass::Reference, git2::ErrorCode::NotFastForward) => {
                 GitPushError::NotFastForward
             }
-            _ => GitPushError::InternalGitError(err),
+            _ => map_remote_err(err),
         })?;
     drop(push_options);
     if remaining_remote_refs.is_empty() {
@@ -456,23 +938,331 @@ fn push_refs(
     }
 }
 
-fn create_remote_callbacks() -> RemoteCallbacks<'static> {
-    let mut callbacks = git2::RemoteCallbacks::new();
-    // TODO: We should expose the callbacks to the caller instead -- the library
-    // crate shouldn't look in $HOME etc.
-    callbacks.credentials(|_url, username_from_url, allowed_types| {
-        if allowed_types.contains(git2::CredentialType::SSH_KEY) {
-            if std::env::var("SSH_AUTH_SOCK").is_ok() || std::env::var("SSH_AGENT_PID").is_ok() {
-                return git2::Cred::ssh_key_from_agent(username_from_url.unwrap());
+/// Callbacks for talking to a remote, so the caller can interact with the user.
+#[derive(Default)]
+pub struct RemoteCallbacks<'a> {
+    /// Asks for the username and password to use for the URL, e.g. by prompting
+    /// the user. The username is passed if it's known from the URL. This is
+    /// only called if the Git credential helpers don't provide credentials.
+    #[allow(clippy::type_complexity)]
+    pub get_username_password:
+        Option<&'a mut dyn FnMut(&str, Option<&str>) -> Option<(String, String)>>,
+    /// Called as objects are transferred to or from the remote.
+    pub progress: Option<&'a mut dyn FnMut(&Progress)>,
+    /// How to authenticate with and verify ssh remotes.
+    pub ssh_options: SshOptions,
+}
+
+/// Progress of transferring objects to or from a remote.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Progress {
+    pub stage: TransferStage,
+    pub processed: usize,
+    pub total: usize,
+    /// The number of bytes transferred so far.
+    pub bytes: usize,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TransferStage {
+    Receiving,
+    /// Resolving the deltas in the received objects, after all of them have
+    /// been received.
+    ResolvingDeltas,
+    Sending,
+}
+
+impl Progress {
+    fn from_git(stats: &git2::Progress) -> Self {
+        if stats.received_objects() < stats.total_objects() || stats.total_deltas() == 0 {
+            Progress {
+                stage: TransferStage::Receiving,
+                processed: stats.received_objects(),
+                total: stats.total_objects(),
+                bytes: stats.received_bytes(),
+            }
+        } else {
+            Progress {
+                stage: TransferStage::ResolvingDeltas,
+                processed: stats.indexed_deltas(),
+                total: stats.total_deltas(),
+                bytes: stats.received_bytes(),
             }
-            if let Ok(home_dir) = std::env::var("HOME") {
-                let key_path = std::path::Path::new(&home_dir).join(".ssh").join("id_rsa");
-                if key_path.is_file() {
-                    return git2::Cred::ssh_key(username_from_url.unwrap(), None, &key_path, None);
+        }
+    }
+}
+
+/// How to authenticate with ssh remotes and verify their host keys.
+#[derive(Clone, Debug)]
+pub struct SshOptions {
+    /// Whether to authenticate with the keys in ssh-agent, if it's running.
+    pub use_agent: bool,
+    /// A private key file to authenticate with if ssh-agent isn't used or none
+    /// of its keys are accepted. The key can't have a passphrase.
+    pub key_path: Option<PathBuf>,
+    /// How to verify the remote's host key.
+    pub host_key_checking: HostKeyChecking,
+}
+
+impl Default for SshOptions {
+    fn default() -> Self {
+        SshOptions {
+            use_agent: true,
+            key_path: None,
+            host_key_checking: HostKeyChecking::KnownHosts,
+        }
+    }
+}
+
+/// How the host key of an ssh remote is verified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostKeyChecking {
+    /// The key must be in `~/.ssh/known_hosts`, as checked by libgit2.
+    KnownHosts,
+    /// The key must have one of these SHA-256 fingerprints, written like
+    /// `ssh-keygen -l` prints them (`SHA256:<base64>`). The known hosts file
+    /// isn't used.
+    Fingerprints(Vec<String>),
+    /// Any key is accepted.
+    No,
+}
+
+/// Why connecting to a remote failed, as recorded by the callbacks, since the
+/// errors from libgit2 don't tell.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum RemoteFailure {
+    Authentication(String),
+    HostKeyVerification(String),
+}
+
+impl RemoteFailure {
+    /// Returns why the operation failed with `err`, if it was because of
+    /// authentication or host key verification.
+    fn take(failure: &RefCell<Option<RemoteFailure>>, err: &git2::Error) -> Option<Self> {
+        failure.take().or_else(|| {
+            // libgit2's own check against the known hosts file failed
+            (err.class() == git2::ErrorClass::Ssh && err.code() == git2::ErrorCode::Certificate)
+                .then(|| {
+                    RemoteFailure::HostKeyVerification(
+                        "The remote's host key isn't in ~/.ssh/known_hosts, or doesn't match the \
+                         key recorded there"
+                            .to_string(),
+                    )
+                })
+        })
+    }
+
+    fn into_fetch_error(self) -> GitFetchError {
+        match self {
+            RemoteFailure::Authentication(message) => GitFetchError::AuthenticationFailed(message),
+            RemoteFailure::HostKeyVerification(message) => {
+                GitFetchError::HostKeyVerificationFailed(message)
+            }
+        }
+    }
+
+    fn into_push_error(self) -> GitPushError {
+        match self {
+            RemoteFailure::Authentication(message) => GitPushError::AuthenticationFailed(message),
+            RemoteFailure::HostKeyVerification(message) => {
+                GitPushError::HostKeyVerificationFailed(message)
+            }
+        }
+    }
+}
+
+impl<'a> RemoteCallbacks<'a> {
+    /// Converts to git2's callbacks for a remote with the given URL. Failures to
+    /// authenticate or to verify the host key are recorded in the returned
+    /// cell.
+    fn into_git(
+        self,
+        git_repo: &git2::Repository,
+        remote_url: Option<&str>,
+    ) -> (
+        git2::RemoteCallbacks<'a>,
+        Rc<RefCell<Option<RemoteFailure>>>,
+    ) {
+        let mut get_username_password = self.get_username_password;
+        let ssh_options = self.ssh_options;
+        let mut callbacks = git2::RemoteCallbacks::new();
+        if let Some(progress) = self.progress {
+            callbacks.transfer_progress(move |stats| {
+                progress(&Progress::from_git(&stats));
+                true
+            });
+        }
+        let git_config = git_repo.config().ok();
+        let failure = Rc::new(RefCell::new(None));
+        let mut tried_ssh_agent = false;
+        let mut tried_ssh_key = false;
+        let mut tried_credential_helper = false;
+        let mut tried_username_password = false;
+        let credentials_failure = failure.clone();
+        let key_path = ssh_options.key_path;
+        callbacks.credentials(move |url, username_from_url, allowed_types| {
+            if allowed_types.contains(git2::CredentialType::SSH_KEY) {
+                let username = username_from_url.unwrap();
+                // This is called again if the key is rejected, so try each source only once
+                if !tried_ssh_agent {
+                    tried_ssh_agent = true;
+                    if ssh_options.use_agent
+                        && (std::env::var("SSH_AUTH_SOCK").is_ok()
+                            || std::env::var("SSH_AGENT_PID").is_ok())
+                    {
+                        return git2::Cred::ssh_key_from_agent(username);
+                    }
+                }
+                if !tried_ssh_key {
+                    tried_ssh_key = true;
+                    if let Some(key_path) = key_path.as_deref().filter(|path| path.is_file()) {
+                        return git2::Cred::ssh_key(username, None, key_path, None);
+                    }
+                }
+                let mut sources = vec![];
+                if ssh_options.use_agent {
+                    sources.push("ssh-agent".to_string());
+                }
+                if let Some(key_path) = &key_path {
+                    sources.push(key_path.display().to_string());
+                }
+                let message = if sources.is_empty() {
+                    format!("No ssh key to authenticate to {url} with")
+                } else {
+                    format!(
+                        "No ssh key was accepted by {url} for user {username} (tried {})",
+                        sources.join(" and ")
+                    )
+                };
+                *credentials_failure.borrow_mut() =
+                    Some(RemoteFailure::Authentication(message.clone()));
+                return Err(git2::Error::from_str(&message));
+            }
+            if allowed_types.contains(git2::CredentialType::USER_PASS_PLAINTEXT) {
+                // This is called again if the credentials are rejected, so try each source
+                // only once
+                if !tried_credential_helper {
+                    tried_credential_helper = true;
+                    if let Some(git_config) = &git_config {
+                        if let Ok(cred) =
+                            git2::Cred::credential_helper(git_config, url, username_from_url)
+                        {
+                            return Ok(cred);
+                        }
+                    }
+                }
+                if !tried_username_password {
+                    tried_username_password = true;
+                    if let Some(get_username_password) = &mut get_username_password {
+                        if let Some((username, password)) =
+                            get_username_password(url, username_from_url)
+                        {
+                            return git2::Cred::userpass_plaintext(&username, &password);
+                        }
+                    }
                 }
+                let message = format!(
+                    "No valid credentials for {url} (configure a Git credential helper or set \
+                     GIT_ASKPASS)"
+                );
+                *credentials_failure.borrow_mut() =
+                    Some(RemoteFailure::Authentication(message.clone()));
+                return Err(git2::Error::from_str(&message));
             }
+            git2::Cred::default()
+        });
+        // git2 accepts any certificate for which the callback returns true, including
+        // invalid TLS certificates, so only install it for ssh remotes
+        if ssh_options.host_key_checking != HostKeyChecking::KnownHosts
+            && remote_url.map_or(false, is_ssh_url)
+        {
+            let host_key_checking = ssh_options.host_key_checking;
+            let certificate_failure = failure.clone();
+            callbacks.certificate_check(move |cert, hostname| {
+                let fingerprints = match &host_key_checking {
+                    HostKeyChecking::Fingerprints(fingerprints) => fingerprints,
+                    _ => return true,
+                };
+                let fingerprint = match cert.as_hostkey().and_then(|key| key.hash_sha256()) {
+                    Some(hash) => format!("SHA256:{}", base64_encode(hash).trim_end_matches('=')),
+                    None => {
+                        *certificate_failure.borrow_mut() =
+                            Some(RemoteFailure::HostKeyVerification(format!(
+                                "{hostname} didn't send a host key with a SHA-256 fingerprint"
+                            )));
+                        return false;
+                    }
+                };
+                if fingerprints
+                    .iter()
+                    .any(|trusted| trusted.trim_end_matches('=') == fingerprint)
+                {
+                    return true;
+                }
+                *certificate_failure.borrow_mut() =
+                    Some(RemoteFailure::HostKeyVerification(format!(
+                        "The host key of {hostname} has fingerprint {fingerprint}, which isn't \
+                         one of the trusted fingerprints"
+                    )));
+                false
+            });
         }
-        git2::Cred::default()
-    });
-    callbacks
+        (callbacks, failure)
+    }
+}
+
+/// Whether the URL is for the ssh transport, either as `ssh://` or in the
+/// scp-like syntax, `[user@]host:path`.
+fn is_ssh_url(url: &str) -> bool {
+    if let Some((scheme, _)) = url.split_once("://") {
+        matches!(scheme, "ssh" | "git+ssh" | "ssh+git")
+    } else {
+        matches!(url.split_once(':'), Some((host, _)) if !host.is_empty() && !host.contains('/'))
+    }
+}
+
+fn base64_encode(data: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity((data.len() + 2) / 3 * 4);
+    for chunk in data.chunks(3) {
+        let bytes = [
+            chunk[0],
+            *chunk.get(1).unwrap_or(&0),
+            *chunk.get(2).unwrap_or(&0),
+        ];
+        let bits = u32::from_be_bytes([0, bytes[0], bytes[1], bytes[2]]);
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[((bits >> (18 - 6 * i)) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ssh_url() {
+        assert!(is_ssh_url("ssh://git@example.com/repo.git"));
+        assert!(is_ssh_url("git+ssh://example.com:2222/repo.git"));
+        assert!(is_ssh_url("git@github.com:martinvonz/jj.git"));
+        assert!(!is_ssh_url("https://github.com/martinvonz/jj"));
+        assert!(!is_ssh_url("file:///tmp/repo.git"));
+        assert!(!is_ssh_url("/tmp/repo.git"));
+        assert!(!is_ssh_url("../some:path"));
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        assert_eq!(base64_encode(b""), "");
+        assert_eq!(base64_encode(b"f"), "Zg==");
+        assert_eq!(base64_encode(b"fo"), "Zm8=");
+        assert_eq!(base64_encode(b"foo"), "Zm9v");
+        assert_eq!(base64_encode(b"foobar"), "Zm9vYmFy");
+    }
 }
@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
 use std::fmt::{Debug, Error, Formatter};
 use std::fs::File;
 use std::io::{Cursor, Read, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
+use std::time::SystemTime;
 
 use git2::Oid;
 use itertools::Itertools;
@@ -25,16 +27,20 @@ use uuid::Uuid;
 
 use crate::backend::{
     make_root_commit, Backend, BackendError, BackendResult, ChangeId, Commit, CommitId, Conflict,
-    ConflictId, ConflictPart, FileId, MillisSinceEpoch, Signature, SymlinkId, Timestamp, Tree,
-    TreeId, TreeValue,
+    ConflictId, ConflictPart, FileId, MillisSinceEpoch, SecureSig, Signature, SigningFn,
+    StoreObjectId, SymlinkId, Timestamp, Tree, TreeId, TreeValue,
 };
 use crate::repo_path::{RepoPath, RepoPathComponent};
 use crate::stacked_table::{TableSegment, TableStore};
 
-const HASH_LENGTH: usize = 20;
+pub(crate) const HASH_LENGTH: usize = 20;
 /// Ref namespace used only for preventing GC.
 const NO_GC_REF_NAMESPACE: &str = "refs/jj/keep/";
-const CONFLICT_SUFFIX: &str = ".jjconflict";
+pub(crate) const CONFLICT_SUFFIX: &str = ".jjconflict";
+/// Suffix of the tree written next to each conflict, which contains the sides of
+/// the conflict so they're reachable from the commit (and thus pushed and fetched
+/// along with it).
+pub(crate) const CONFLICT_SIDES_SUFFIX: &str = ".jjconflict-sides";
 
 impl From<git2::Error> for BackendError {
     fn from(err: git2::Error) -> Self {
@@ -50,17 +56,22 @@ pub struct GitBackend {
     root_commit_id: CommitId,
     empty_tree_id: TreeId,
     extra_metadata_store: TableStore,
+    /// Commits at the boundary of a shallow clone. Their parents are missing
+    /// from the repo, so they're treated as root commits.
+    shallow_commits: ShallowCommits,
 }
 
 impl GitBackend {
     fn new(repo: git2::Repository, extra_metadata_store: TableStore) -> Self {
         let root_commit_id = CommitId::from_bytes(&[0; HASH_LENGTH]);
         let empty_tree_id = TreeId::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        let shallow_commits = ShallowCommits::load(repo.path());
         GitBackend {
             repo: Mutex::new(repo),
             root_commit_id,
             empty_tree_id,
             extra_metadata_store,
+            shallow_commits,
         }
     }
 
@@ -87,17 +98,76 @@ impl GitBackend {
     }
 
     pub fn load(store_path: &Path) -> Self {
-        let mut git_target_file = File::open(store_path.join("git_target")).unwrap();
-        let mut buf = Vec::new();
-        git_target_file.read_to_end(&mut buf).unwrap();
-        let git_repo_path_str = String::from_utf8(buf).unwrap();
-        let git_repo_path = store_path.join(git_repo_path_str).canonicalize().unwrap();
-        let repo = git2::Repository::open(git_repo_path).unwrap();
+        let repo = git2::Repository::open(read_git_target(store_path)).unwrap();
         let extra_metadata_store = TableStore::load(store_path.join("extra"), HASH_LENGTH);
         GitBackend::new(repo, extra_metadata_store)
     }
 }
 
+/// Returns the path to the Git repo recorded in the store's `git_target` file.
+pub(crate) fn read_git_target(store_path: &Path) -> PathBuf {
+    let mut git_target_file = File::open(store_path.join("git_target")).unwrap();
+    let mut buf = Vec::new();
+    git_target_file.read_to_end(&mut buf).unwrap();
+    let git_repo_path_str = String::from_utf8(buf).unwrap();
+    store_path.join(git_repo_path_str).canonicalize().unwrap()
+}
+
+/// The commits listed in the Git repo's `shallow` file. The file is read again
+/// when it changes, so fetching with a different `--depth` is picked up.
+pub(crate) struct ShallowCommits {
+    path: PathBuf,
+    state: Mutex<ShallowCommitsState>,
+}
+
+#[derive(Default)]
+struct ShallowCommitsState {
+    // The modification time and size of the file when it was last read
+    stamp: Option<(SystemTime, u64)>,
+    commits: HashSet<CommitId>,
+}
+
+impl ShallowCommits {
+    pub(crate) fn load(git_dir: &Path) -> Self {
+        let shallow_commits = ShallowCommits {
+            path: git_dir.join("shallow"),
+            state: Mutex::new(ShallowCommitsState::default()),
+        };
+        shallow_commits.refresh();
+        shallow_commits
+    }
+
+    fn refresh(&self) {
+        let stamp = std::fs::metadata(&self.path)
+            .ok()
+            .and_then(|metadata| Some((metadata.modified().ok()?, metadata.len())));
+        let mut state = self.state.lock().unwrap();
+        if state.stamp != stamp {
+            *state = ShallowCommitsState {
+                stamp,
+                commits: read_shallow_commits(self.path.parent().unwrap()),
+            };
+        }
+    }
+
+    pub(crate) fn contains(&self, id: &CommitId) -> bool {
+        self.refresh();
+        self.state.lock().unwrap().commits.contains(id)
+    }
+}
+
+/// Reads the commits listed in the Git repo's `shallow` file, which Git writes
+/// when cloning or fetching with `--depth`.
+pub(crate) fn read_shallow_commits(git_dir: &Path) -> HashSet<CommitId> {
+    let content = std::fs::read_to_string(git_dir.join("shallow")).unwrap_or_default();
+    content
+        .lines()
+        .filter_map(|line| hex::decode(line.trim()).ok())
+        .filter(|bytes| bytes.len() == HASH_LENGTH)
+        .map(CommitId::new)
+        .collect()
+}
+
 fn signature_from_git(signature: git2::Signature) -> Signature {
     let name = signature.name().unwrap_or("<no name>").to_owned();
     let email = signature.email().unwrap_or("<no email>").to_owned();
@@ -123,6 +193,22 @@ fn signature_to_git(signature: &Signature) -> git2::Signature {
     git2::Signature::new(name, email, &time).unwrap()
 }
 
+// We reverse the bits of the commit id to create the change id. We don't want
+// to use the first bytes unmodified because then it would be ambiguous
+// if a given hash prefix refers to the commit id or the change id. It
+// would have been enough to pick the last 16 bytes instead of the
+// leading 16 bytes to address that. We also reverse the bits to make it less
+// likely that users depend on any relationship between the two ids.
+pub(crate) fn change_id_from_commit_id(id: &CommitId) -> ChangeId {
+    ChangeId::new(
+        id.as_bytes()[4..HASH_LENGTH]
+            .iter()
+            .rev()
+            .map(|b| b.reverse_bits())
+            .collect(),
+    )
+}
+
 fn serialize_extras(commit: &Commit) -> Vec<u8> {
     let mut proto = crate::protos::store::Commit::new();
     proto.is_open = commit.is_open;
@@ -143,9 +229,50 @@ fn deserialize_extras(commit: &mut Commit, bytes: &[u8]) {
     }
 }
 
+/// Reads the metadata that can't be stored in the Git commit (such as the change
+/// id) from the extra metadata store into `commit`, if there is any.
+pub(crate) fn read_extras(
+    extra_metadata_store: &TableStore,
+    id: &CommitId,
+    commit: &mut Commit,
+) -> BackendResult<()> {
+    let table = extra_metadata_store
+        .get_head()
+        .map_err(|err| BackendError::Other(format!("Failed to read non-git metadata: {err}")))?;
+    let maybe_extras = table.get_value(id.as_bytes());
+    if let Some(extras) = maybe_extras {
+        deserialize_extras(commit, extras);
+    }
+    Ok(())
+}
+
+/// Writes the metadata that can't be stored in the Git commit to the extra
+/// metadata store.
+pub(crate) fn write_extras(
+    extra_metadata_store: &TableStore,
+    id: &CommitId,
+    commit: &Commit,
+) -> BackendResult<()> {
+    let extras = serialize_extras(commit);
+    let mut mut_table = extra_metadata_store.get_head().unwrap().start_mutation();
+    if let Some(existing_extras) = mut_table.get_value(id.as_bytes()) {
+        if existing_extras != extras {
+            return Err(BackendError::Other(format!(
+                "Git commit '{}' already exists with different associated non-Git meta-data",
+                id.hex()
+            )));
+        }
+    }
+    mut_table.add_entry(id.to_bytes(), extras);
+    extra_metadata_store
+        .save_table(mut_table)
+        .map_err(|err| BackendError::Other(format!("Failed to write non-git metadata: {err}")))?;
+    Ok(())
+}
+
 /// Creates a random ref in refs/jj/. Used for preventing GC of commits we
 /// create.
-fn create_no_gc_ref() -> String {
+pub(crate) fn create_no_gc_ref() -> String {
     let mut no_gc_ref = NO_GC_REF_NAMESPACE.to_owned();
     let mut uuid_buffer = Uuid::encode_buffer();
     let uuid_str = Uuid::new_v4()
@@ -240,6 +367,14 @@ impl Backend for GitBackend {
             let name = entry.name().unwrap();
             let (name, value) = match entry.kind().unwrap() {
                 git2::ObjectType::Tree => {
+                    if let Some(conflict_name) = name.strip_suffix(CONFLICT_SIDES_SUFFIX) {
+                        if git_tree
+                            .get_name(&(conflict_name.to_string() + CONFLICT_SUFFIX))
+                            .is_some()
+                        {
+                            continue;
+                        }
+                    }
                     let id = TreeId::from_bytes(entry.id().as_bytes());
                     (entry.name().unwrap(), TreeValue::Tree(id))
                 }
@@ -305,11 +440,12 @@ impl Backend for GitBackend {
                 TreeValue::Symlink(id) => (name, id.as_bytes(), 0o120000),
                 TreeValue::Tree(id) => (name, id.as_bytes(), 0o040000),
                 TreeValue::GitSubmodule(id) => (name, id.as_bytes(), 0o160000),
-                TreeValue::Conflict(id) => (
-                    entry.name().string() + CONFLICT_SUFFIX,
-                    id.as_bytes(),
-                    0o100644,
-                ),
+                TreeValue::Conflict(id) => {
+                    let conflict = read_conflict_from_repo(&locked_repo, id)?;
+                    let sides_id = write_conflict_sides(&locked_repo, &conflict)?;
+                    builder.insert(name.clone() + CONFLICT_SIDES_SUFFIX, sides_id, 0o040000)?;
+                    (name + CONFLICT_SUFFIX, id.as_bytes(), 0o100644)
+                }
             };
             builder
                 .insert(name, Oid::from_bytes(id).unwrap(), filemode)
@@ -320,28 +456,14 @@ impl Backend for GitBackend {
     }
 
     fn read_conflict(&self, _path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
-        let mut file = self.read_file(
-            &RepoPath::from_internal_string("unused"),
-            &FileId::new(id.to_bytes()),
-        )?;
-        let mut data = String::new();
-        file.read_to_string(&mut data)?;
-        let json: serde_json::Value = serde_json::from_str(&data).unwrap();
-        Ok(Conflict {
-            removes: conflict_part_list_from_json(json.get("removes").unwrap()),
-            adds: conflict_part_list_from_json(json.get("adds").unwrap()),
-        })
+        let locked_repo = self.repo.lock().unwrap();
+        read_conflict_from_repo(&locked_repo, id)
     }
 
     fn write_conflict(&self, _path: &RepoPath, conflict: &Conflict) -> BackendResult<ConflictId> {
-        let json = serde_json::json!({
-            "removes": conflict_part_list_to_json(&conflict.removes),
-            "adds": conflict_part_list_to_json(&conflict.adds),
-        });
-        let json_string = json.to_string();
-        let bytes = json_string.as_bytes();
+        let json_string = conflict_to_json(conflict);
         let locked_repo = self.repo.lock().unwrap();
-        let oid = locked_repo.blob(bytes).unwrap();
+        let oid = locked_repo.blob(json_string.as_bytes()).unwrap();
         Ok(ConflictId::from_bytes(oid.as_bytes()))
     }
 
@@ -357,23 +479,15 @@ impl Backend for GitBackend {
         let locked_repo = self.repo.lock().unwrap();
         let git_commit_id = Oid::from_bytes(id.as_bytes())?;
         let commit = locked_repo.find_commit(git_commit_id)?;
-        // We reverse the bits of the commit id to create the change id. We don't want
-        // to use the first bytes unmodified because then it would be ambiguous
-        // if a given hash prefix refers to the commit id or the change id. It
-        // would have been enough to pick the last 16 bytes instead of the
-        // leading 16 bytes to address that. We also reverse the bits to make it less
-        // likely that users depend on any relationship between the two ids.
-        let change_id = ChangeId::new(
-            id.as_bytes()[4..HASH_LENGTH]
-                .iter()
-                .rev()
-                .map(|b| b.reverse_bits())
-                .collect(),
-        );
-        let mut parents = commit
-            .parent_ids()
-            .map(|oid| CommitId::from_bytes(oid.as_bytes()))
-            .collect_vec();
+        let change_id = change_id_from_commit_id(id);
+        let mut parents = if self.shallow_commits.contains(id) {
+            vec![]
+        } else {
+            commit
+                .parent_ids()
+                .map(|oid| CommitId::from_bytes(oid.as_bytes()))
+                .collect_vec()
+        };
         if parents.is_empty() {
             parents.push(self.root_commit_id.clone());
         };
@@ -381,6 +495,13 @@ impl Backend for GitBackend {
         let description = commit.message().unwrap_or("<no message>").to_owned();
         let author = signature_from_git(commit.author());
         let committer = signature_from_git(commit.committer());
+        let secure_sig = locked_repo
+            .extract_signature(&git_commit_id, None)
+            .ok()
+            .map(|(sig, data)| SecureSig {
+                data: data.to_vec(),
+                sig: sig.to_vec(),
+            });
 
         let mut commit = Commit {
             parents,
@@ -391,20 +512,18 @@ impl Backend for GitBackend {
             author,
             committer,
             is_open: false,
+            secure_sig,
         };
 
-        let table = self.extra_metadata_store.get_head().map_err(|err| {
-            BackendError::Other(format!("Failed to read non-git metadata: {err}"))
-        })?;
-        let maybe_extras = table.get_value(git_commit_id.as_bytes());
-        if let Some(extras) = maybe_extras {
-            deserialize_extras(&mut commit, extras);
-        }
-
+        read_extras(&self.extra_metadata_store, id, &mut commit)?;
         Ok(commit)
     }
 
-    fn write_commit(&self, contents: &Commit) -> BackendResult<CommitId> {
+    fn write_commit(
+        &self,
+        mut contents: Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<(CommitId, Commit)> {
         let locked_repo = self.repo.lock().unwrap();
         let git_tree = locked_repo.find_tree(Oid::from_bytes(contents.root_tree.as_bytes())?)?;
         let author = signature_to_git(&contents.author);
@@ -426,39 +545,130 @@ impl Backend for GitBackend {
             }
         }
         let parent_refs = parents.iter().collect_vec();
-        let git_id = locked_repo.commit(
-            Some(&create_no_gc_ref()),
-            &author,
-            &committer,
-            message,
-            &git_tree,
-            &parent_refs,
-        )?;
+        let mut secure_sig = None;
+        let git_id = if let Some(sign) = sign_with {
+            let data = locked_repo
+                .commit_create_buffer(&author, &committer, message, &git_tree, &parent_refs)?
+                .to_vec();
+            let sig = sign(&data)?;
+            let to_str = |bytes| {
+                std::str::from_utf8(bytes)
+                    .map_err(|err| BackendError::Other(format!("Invalid commit signature: {err}")))
+            };
+            let git_id = locked_repo.commit_signed(to_str(&data)?, to_str(&sig)?, None)?;
+            locked_repo.reference(&create_no_gc_ref(), git_id, false, "new signed commit")?;
+            secure_sig = Some(SecureSig { data, sig });
+            git_id
+        } else {
+            locked_repo.commit(
+                Some(&create_no_gc_ref()),
+                &author,
+                &committer,
+                message,
+                &git_tree,
+                &parent_refs,
+            )?
+        };
+        // The git signatures borrow from `contents`
+        drop((author, committer));
+        contents.secure_sig = secure_sig;
         let id = CommitId::from_bytes(git_id.as_bytes());
-        let extras = serialize_extras(contents);
-        let mut mut_table = self
-            .extra_metadata_store
-            .get_head()
-            .unwrap()
-            .start_mutation();
-        if let Some(existing_extras) = mut_table.get_value(git_id.as_bytes()) {
-            if existing_extras != extras {
-                return Err(BackendError::Other(format!(
-                    "Git commit '{}' already exists with different associated non-Git meta-data",
-                    id.hex()
-                )));
+        write_extras(&self.extra_metadata_store, &id, &contents)?;
+        Ok((id, contents))
+    }
+
+    fn verify(&self, object: &StoreObjectId) -> BackendResult<()> {
+        let expected_type = match object {
+            StoreObjectId::Commit(id) if *id == self.root_commit_id => return Ok(()),
+            StoreObjectId::Commit(_) => git2::ObjectType::Commit,
+            StoreObjectId::Tree(_) => git2::ObjectType::Tree,
+            // Symlinks and conflicts are stored as blobs too
+            StoreObjectId::File(_) | StoreObjectId::Symlink(_) | StoreObjectId::Conflict(_) => {
+                git2::ObjectType::Blob
+            }
+        };
+        let locked_repo = self.repo.lock().unwrap();
+        let oid = Oid::from_bytes(object.as_bytes())?;
+        let corrupt = || BackendError::Corrupt(object.clone());
+        let odb = locked_repo.odb()?;
+        let git_object = match odb.read(oid) {
+            Ok(git_object) => git_object,
+            Err(err) if err.code() == git2::ErrorCode::NotFound => {
+                return Err(BackendError::NotFound)
             }
+            // libgit2 fails to read objects that can't be inflated
+            Err(_) => return Err(corrupt()),
+        };
+        if git_object.kind() != expected_type
+            || Oid::hash_object(git_object.kind(), git_object.data())? != oid
+        {
+            return Err(corrupt());
         }
-        mut_table.add_entry(git_id.as_bytes().to_vec(), extras);
-        self.extra_metadata_store
-            .save_table(mut_table)
-            .map_err(|err| {
-                BackendError::Other(format!("Failed to write non-git metadata: {err}"))
-            })?;
-        Ok(id)
+        Ok(())
+    }
+}
+
+fn read_conflict_from_repo(repo: &git2::Repository, id: &ConflictId) -> BackendResult<Conflict> {
+    if id.as_bytes().len() != HASH_LENGTH {
+        return Err(BackendError::NotFound);
+    }
+    let blob = repo
+        .find_blob(Oid::from_bytes(id.as_bytes()).unwrap())
+        .map_err(|_| BackendError::NotFound)?;
+    Ok(conflict_from_json(blob.content()))
+}
+
+/// Parses a conflict as stored in a `.jjconflict` blob.
+pub(crate) fn conflict_from_json(bytes: &[u8]) -> Conflict {
+    let json: serde_json::Value = serde_json::from_slice(bytes).unwrap();
+    Conflict {
+        removes: conflict_part_list_from_json(json.get("removes").unwrap()),
+        adds: conflict_part_list_from_json(json.get("adds").unwrap()),
     }
 }
 
+/// Serializes a conflict to be stored in a `.jjconflict` blob.
+pub(crate) fn conflict_to_json(conflict: &Conflict) -> String {
+    let json = serde_json::json!({
+        "removes": conflict_part_list_to_json(&conflict.removes),
+        "adds": conflict_part_list_to_json(&conflict.adds),
+    });
+    json.to_string()
+}
+
+/// The names and values of the sides of a conflict in its `.jjconflict-sides`
+/// tree.
+pub(crate) fn conflict_sides(conflict: &Conflict) -> impl Iterator<Item = (String, &TreeValue)> {
+    let removes = conflict.removes.iter().enumerate();
+    let adds = conflict.adds.iter().enumerate();
+    removes
+        .map(|(i, part)| (format!("remove-{i}"), &part.value))
+        .chain(adds.map(|(i, part)| (format!("add-{i}"), &part.value)))
+}
+
+/// Writes a tree with the sides of the conflict as `remove-<n>` and `add-<n>`.
+fn write_conflict_sides(repo: &git2::Repository, conflict: &Conflict) -> BackendResult<Oid> {
+    let mut builder = repo.treebuilder(None)?;
+    for (name, value) in conflict_sides(conflict) {
+        let (id, filemode) = match value {
+            TreeValue::Normal {
+                id,
+                executable: false,
+            } => (id.as_bytes(), 0o100644),
+            TreeValue::Normal {
+                id,
+                executable: true,
+            } => (id.as_bytes(), 0o100755),
+            TreeValue::Symlink(id) => (id.as_bytes(), 0o120000),
+            TreeValue::Tree(id) => (id.as_bytes(), 0o040000),
+            TreeValue::GitSubmodule(id) => (id.as_bytes(), 0o160000),
+            TreeValue::Conflict(id) => (id.as_bytes(), 0o100644),
+        };
+        builder.insert(name, Oid::from_bytes(id).unwrap(), filemode)?;
+    }
+    Ok(builder.write()?)
+}
+
 fn conflict_part_list_to_json(parts: &[ConflictPart]) -> serde_json::Value {
     serde_json::Value::Array(parts.iter().map(conflict_part_to_json).collect())
 }
@@ -472,15 +682,24 @@ fn conflict_part_list_from_json(json: &serde_json::Value) -> Vec<ConflictPart> {
 }
 
 fn conflict_part_to_json(part: &ConflictPart) -> serde_json::Value {
-    serde_json::json!({
+    let mut json = serde_json::json!({
         "value": tree_value_to_json(&part.value),
-    })
+    });
+    // Leave out missing labels so unlabeled conflicts keep their ids
+    if let Some(label) = &part.label {
+        json["label"] = serde_json::json!(label);
+    }
+    json
 }
 
 fn conflict_part_from_json(json: &serde_json::Value) -> ConflictPart {
     let json_value = json.get("value").unwrap();
     ConflictPart {
         value: tree_value_from_json(json_value),
+        label: json
+            .get("label")
+            .and_then(|label| label.as_str())
+            .map(ToString::to_string),
     }
 }
 
@@ -669,8 +888,9 @@ mod tests {
             author: signature.clone(),
             committer: signature,
             is_open: false,
+            secure_sig: None,
         };
-        let commit_id = store.write_commit(&commit).unwrap();
+        let (commit_id, _) = store.write_commit(commit, None).unwrap();
         let git_refs = store
             .git_repo()
             .unwrap()
@@ -684,6 +904,66 @@ mod tests {
         );
     }
 
+    #[test]
+    fn conflict_sides_reachable_from_tree() {
+        let temp_dir = testutils::new_temp_dir();
+        let store = GitBackend::init_internal(temp_dir.path());
+        let path = RepoPath::from_internal_string("file");
+        let write_file = |contents: &[u8]| {
+            store
+                .write_file(&path, &mut Cursor::new(contents.to_vec()))
+                .unwrap()
+        };
+        let base_id = write_file(b"base");
+        let left_id = write_file(b"left");
+        let right_id = write_file(b"right");
+        let file_value = |id: &FileId| ConflictPart {
+            value: TreeValue::Normal {
+                id: id.clone(),
+                executable: false,
+            },
+            label: None,
+        };
+        let conflict = Conflict {
+            removes: vec![file_value(&base_id)],
+            adds: vec![file_value(&left_id), file_value(&right_id)],
+        };
+        let conflict_id = store.write_conflict(&path, &conflict).unwrap();
+        let mut tree = Tree::default();
+        tree.set(
+            RepoPathComponent::from("file"),
+            TreeValue::Conflict(conflict_id.clone()),
+        );
+        let tree_id = store.write_tree(&RepoPath::root(), &tree).unwrap();
+
+        // The sides are stored next to the conflict in the Git tree
+        let git_repo = store.git_repo().unwrap();
+        let git_tree = git_repo
+            .find_tree(Oid::from_bytes(tree_id.as_bytes()).unwrap())
+            .unwrap();
+        let sides_entry = git_tree.get_name("file.jjconflict-sides").unwrap();
+        assert_eq!(sides_entry.filemode(), 0o040000);
+        let sides_tree = git_repo.find_tree(sides_entry.id()).unwrap();
+        let sides = sides_tree
+            .iter()
+            .map(|entry| (entry.name().unwrap().to_string(), entry.id()))
+            .collect_vec();
+        let oid = |id: &FileId| Oid::from_bytes(id.as_bytes()).unwrap();
+        assert_eq!(
+            sides,
+            vec![
+                ("add-0".to_string(), oid(&left_id)),
+                ("add-1".to_string(), oid(&right_id)),
+                ("remove-0".to_string(), oid(&base_id)),
+            ]
+        );
+
+        // But they're not part of the tree read back
+        let read_tree = store.read_tree(&RepoPath::root(), &tree_id).unwrap();
+        assert_eq!(read_tree, tree);
+        assert_eq!(store.read_conflict(&path, &conflict_id).unwrap(), conflict);
+    }
+
     #[test]
     fn overlapping_git_commit_id() {
         let temp_dir = testutils::new_temp_dir();
@@ -705,12 +985,13 @@ mod tests {
             author: signature.clone(),
             committer: signature,
             is_open: false,
+            secure_sig: None,
         };
-        let commit_id1 = store.write_commit(&commit1).unwrap();
+        let (commit_id1, _) = store.write_commit(commit1.clone(), None).unwrap();
         let mut commit2 = commit1;
         commit2.predecessors.push(commit_id1.clone());
         let expected_error_message = format!("Git commit '{}' already exists", commit_id1.hex());
-        match store.write_commit(&commit2) {
+        match store.write_commit(commit2, None) {
             Ok(_) => {
                 panic!("expectedly successfully wrote two commits with the same git commit object")
             }
@@ -718,4 +999,42 @@ mod tests {
             Err(err) => panic!("unexpected error: {:?}", err),
         };
     }
+
+    #[test]
+    fn write_signed_commit() {
+        let temp_dir = testutils::new_temp_dir();
+        let store = GitBackend::init_internal(temp_dir.path());
+        let signature = Signature {
+            name: "Someone".to_string(),
+            email: "someone@example.com".to_string(),
+            timestamp: Timestamp {
+                timestamp: MillisSinceEpoch(0),
+                tz_offset: 0,
+            },
+        };
+        let commit = Commit {
+            parents: vec![],
+            predecessors: vec![],
+            root_tree: store.empty_tree_id().clone(),
+            change_id: ChangeId::new(vec![]),
+            description: "initial".to_string(),
+            author: signature.clone(),
+            committer: signature,
+            is_open: false,
+            secure_sig: None,
+        };
+        let mut signed_data = vec![];
+        let mut sign = |data: &[u8]| {
+            signed_data = data.to_vec();
+            Ok(b"test signature".to_vec())
+        };
+        let (commit_id, written) = store.write_commit(commit, Some(&mut sign)).unwrap();
+        let expected_sig = SecureSig {
+            data: signed_data,
+            sig: b"test signature".to_vec(),
+        };
+        assert_eq!(written.secure_sig.as_ref(), Some(&expected_sig));
+        let read = store.read_commit(&commit_id).unwrap();
+        assert_eq!(read.secure_sig, Some(expected_sig));
+    }
 }
@@ -0,0 +1,178 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Running the hooks of the backing Git repo (`pre-commit`, `commit-msg` and
+//! `pre-push`) at the corresponding points in jj.
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use thiserror::Error;
+
+use crate::backend::CommitId;
+
+#[derive(Debug, Error)]
+pub enum GitHookError {
+    #[error("Failed to run the {hook} hook: {err}")]
+    ExecuteError { hook: String, err: std::io::Error },
+    #[error("The {hook} hook failed ({status})")]
+    HookFailed { hook: String, status: ExitStatus },
+}
+
+/// A ref update passed to the `pre-push` hook. A missing id means that the ref
+/// doesn't exist on that side.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PushedRef {
+    pub local_ref: String,
+    pub local_id: Option<CommitId>,
+    pub remote_ref: String,
+    pub remote_id: Option<CommitId>,
+}
+
+/// The hooks of a Git repo. Like in Git, hooks are looked up in
+/// `core.hooksPath` or else in the `hooks` directory of the repo, and hooks
+/// that don't exist or aren't executable are skipped.
+#[derive(Debug, Clone)]
+pub struct GitHooks {
+    hooks_dir: PathBuf,
+    git_dir: PathBuf,
+    work_dir: PathBuf,
+}
+
+impl GitHooks {
+    /// Returns the hooks of `git_repo`, to be run in `work_dir`.
+    pub fn new(git_repo: &git2::Repository, work_dir: &Path) -> GitHooks {
+        let hooks_dir = git_repo
+            .config()
+            .and_then(|config| config.get_path("core.hooksPath"))
+            .map(|path| work_dir.join(path))
+            .unwrap_or_else(|_| git_repo.path().join("hooks"));
+        GitHooks {
+            hooks_dir,
+            git_dir: git_repo.path().to_owned(),
+            work_dir: work_dir.to_owned(),
+        }
+    }
+
+    /// Runs the `pre-commit` hook, which can reject the working-copy contents.
+    pub fn pre_commit(&self) -> Result<(), GitHookError> {
+        self.run("pre-commit", &[], None)
+    }
+
+    /// Runs the `commit-msg` hook, which can reject the description or edit it.
+    /// Returns the possibly edited description.
+    pub fn commit_msg(&self, description: &str) -> Result<String, GitHookError> {
+        if self.hook_path("commit-msg").is_none() {
+            return Ok(description.to_string());
+        }
+        let execute_error = |err| GitHookError::ExecuteError {
+            hook: "commit-msg".to_string(),
+            err,
+        };
+        // Git passes the message in this file too
+        let message_path = self.git_dir.join("COMMIT_EDITMSG");
+        fs::write(&message_path, description).map_err(execute_error)?;
+        self.run("commit-msg", &[message_path.as_os_str()], None)?;
+        fs::read_to_string(&message_path).map_err(execute_error)
+    }
+
+    /// Runs the `pre-push` hook, which can reject pushing the refs. The refs are
+    /// passed on stdin in Git's `<local ref> <local sha> <remote ref> <remote
+    /// sha>` format.
+    pub fn pre_push(
+        &self,
+        remote_name: &str,
+        remote_url: &str,
+        refs: &[PushedRef],
+    ) -> Result<(), GitHookError> {
+        let hex_or_zero =
+            |id: &Option<CommitId>| id.as_ref().map_or_else(|| "0".repeat(40), CommitId::hex);
+        let mut stdin = String::new();
+        for pushed_ref in refs {
+            let local_ref = match &pushed_ref.local_id {
+                Some(_) => pushed_ref.local_ref.as_str(),
+                None => "(delete)",
+            };
+            stdin.push_str(&format!(
+                "{} {} {} {}\n",
+                local_ref,
+                hex_or_zero(&pushed_ref.local_id),
+                pushed_ref.remote_ref,
+                hex_or_zero(&pushed_ref.remote_id),
+            ));
+        }
+        self.run(
+            "pre-push",
+            &[remote_name.as_ref(), remote_url.as_ref()],
+            Some(stdin.as_bytes()),
+        )
+    }
+
+    fn hook_path(&self, hook: &str) -> Option<PathBuf> {
+        let path = self.hooks_dir.join(hook);
+        let metadata = path.metadata().ok()?;
+        if !metadata.is_file() {
+            return None;
+        }
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            if metadata.permissions().mode() & 0o111 == 0 {
+                return None;
+            }
+        }
+        Some(path)
+    }
+
+    fn run(
+        &self,
+        hook: &str,
+        args: &[&std::ffi::OsStr],
+        stdin: Option<&[u8]>,
+    ) -> Result<(), GitHookError> {
+        let path = match self.hook_path(hook) {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+        let execute_error = |err| GitHookError::ExecuteError {
+            hook: hook.to_string(),
+            err,
+        };
+        let mut child = Command::new(path)
+            .args(args)
+            .current_dir(&self.work_dir)
+            .env("GIT_DIR", &self.git_dir)
+            .stdin(if stdin.is_some() {
+                Stdio::piped()
+            } else {
+                Stdio::null()
+            })
+            .spawn()
+            .map_err(execute_error)?;
+        if let Some(stdin) = stdin {
+            // The hook may exit without reading its input
+            let _ = child.stdin.take().unwrap().write_all(stdin);
+        }
+        let status = child.wait().map_err(execute_error)?;
+        if !status.success() {
+            return Err(GitHookError::HookFailed {
+                hook: hook.to_string(),
+                status,
+            });
+        }
+        Ok(())
+    }
+}
@@ -17,6 +17,7 @@ use std::io::Read;
 use std::path::PathBuf;
 use std::sync::Arc;
 
+use blake2::{Blake2b512, Digest};
 use itertools::Itertools;
 use regex::{escape as regex_escape, Regex};
 
@@ -159,6 +160,8 @@ impl GitIgnoreLine {
 pub struct GitIgnoreFile {
     parent: Option<Arc<GitIgnoreFile>>,
     lines: Vec<GitIgnoreLine>,
+    // Hash of the inputs of this file and its parents
+    fingerprint: Vec<u8>,
 }
 
 impl GitIgnoreFile {
@@ -166,6 +169,7 @@ impl GitIgnoreFile {
         Arc::new(GitIgnoreFile {
             parent: None,
             lines: vec![],
+            fingerprint: vec![],
         })
     }
 
@@ -180,12 +184,24 @@ impl GitIgnoreFile {
             }
         }
 
+        let mut hasher = Blake2b512::new();
+        hasher.update(&self.fingerprint);
+        hasher.update((prefix.len() as u64).to_le_bytes());
+        hasher.update(prefix);
+        hasher.update(input);
         Arc::new(GitIgnoreFile {
             parent: Some(self.clone()),
             lines,
+            fingerprint: hasher.finalize()[..32].to_vec(),
         })
     }
 
+    /// Identifies the patterns of this file and its parents, so callers can
+    /// tell whether the patterns changed since an earlier run.
+    pub fn fingerprint(&self) -> &[u8] {
+        &self.fingerprint
+    }
+
     pub fn chain_with_file(
         self: &Arc<GitIgnoreFile>,
         prefix: &str,
@@ -0,0 +1,631 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! A Git backend that reads and writes objects with gitoxide instead of
+//! libgit2. It uses the same store layout as `GitBackend`, and the Git-specific
+//! commands (fetching, pushing, etc.) still go through libgit2 via
+//! `Backend::git_repo()`.
+
+use std::collections::HashSet;
+use std::fmt::{Debug, Error, Formatter};
+use std::fs::File;
+use std::io::{Cursor, Read, Write};
+use std::path::Path;
+
+use gix::bstr::{BString, ByteSlice};
+use gix::objs::tree::EntryKind;
+use gix::objs::WriteTo;
+use gix::refs::transaction::PreviousValue;
+use gix::ObjectId;
+use itertools::Itertools;
+
+use crate::backend::{
+    make_root_commit, Backend, BackendError, BackendResult, Commit, CommitId, Conflict, ConflictId,
+    FileId, MillisSinceEpoch, SecureSig, Signature, SigningFn, StoreObjectId, SymlinkId, Timestamp,
+    Tree, TreeId, TreeValue,
+};
+use crate::git_backend::{
+    change_id_from_commit_id, conflict_from_json, conflict_sides, conflict_to_json,
+    create_no_gc_ref, read_extras, read_git_target, write_extras, ShallowCommits,
+    CONFLICT_SIDES_SUFFIX, CONFLICT_SUFFIX, HASH_LENGTH,
+};
+use crate::repo_path::{RepoPath, RepoPathComponent};
+use crate::stacked_table::TableStore;
+
+fn to_backend_error(err: impl std::error::Error) -> BackendError {
+    BackendError::Other(err.to_string())
+}
+
+fn object_id(bytes: &[u8]) -> BackendResult<ObjectId> {
+    if bytes.len() != HASH_LENGTH {
+        return Err(BackendError::NotFound);
+    }
+    Ok(ObjectId::from_bytes_or_panic(bytes))
+}
+
+pub struct GitoxideBackend {
+    repo: gix::ThreadSafeRepository,
+    root_commit_id: CommitId,
+    empty_tree_id: TreeId,
+    extra_metadata_store: TableStore,
+    /// Commits at the boundary of a shallow clone. Their parents are missing
+    /// from the repo, so they're treated as root commits.
+    shallow_commits: ShallowCommits,
+}
+
+impl GitoxideBackend {
+    fn new(repo: gix::ThreadSafeRepository, extra_metadata_store: TableStore) -> Self {
+        let root_commit_id = CommitId::from_bytes(&[0; HASH_LENGTH]);
+        let empty_tree_id = TreeId::from_hex("4b825dc642cb6eb9a060e54bf8d69288fbee4904");
+        let shallow_commits = ShallowCommits::load(repo.to_thread_local().git_dir());
+        GitoxideBackend {
+            repo,
+            root_commit_id,
+            empty_tree_id,
+            extra_metadata_store,
+            shallow_commits,
+        }
+    }
+
+    pub fn init_internal(store_path: &Path) -> Self {
+        let git_repo = gix::init_bare(store_path.join("git")).unwrap();
+        let extra_path = store_path.join("extra");
+        std::fs::create_dir(&extra_path).unwrap();
+        let mut git_target_file = File::create(store_path.join("git_target")).unwrap();
+        git_target_file.write_all(b"git").unwrap();
+        let extra_metadata_store = TableStore::init(extra_path, HASH_LENGTH);
+        GitoxideBackend::new(git_repo.into_sync(), extra_metadata_store)
+    }
+
+    pub fn init_external(store_path: &Path, git_repo_path: &Path) -> Self {
+        let extra_path = store_path.join("extra");
+        std::fs::create_dir(&extra_path).unwrap();
+        let mut git_target_file = File::create(store_path.join("git_target")).unwrap();
+        git_target_file
+            .write_all(git_repo_path.to_str().unwrap().as_bytes())
+            .unwrap();
+        let repo = gix::open(store_path.join(git_repo_path)).unwrap();
+        let extra_metadata_store = TableStore::init(extra_path, HASH_LENGTH);
+        GitoxideBackend::new(repo.into_sync(), extra_metadata_store)
+    }
+
+    pub fn load(store_path: &Path) -> Self {
+        let repo = gix::open(read_git_target(store_path)).unwrap();
+        let extra_metadata_store = TableStore::load(store_path.join("extra"), HASH_LENGTH);
+        GitoxideBackend::new(repo.into_sync(), extra_metadata_store)
+    }
+
+    /// Reads the object with the given id, which must be of the given kind.
+    fn read_object(&self, id: &[u8], kind: gix::object::Kind) -> BackendResult<Vec<u8>> {
+        let repo = self.repo.to_thread_local();
+        let object = repo
+            .try_find_object(object_id(id)?)
+            .map_err(to_backend_error)?
+            .ok_or(BackendError::NotFound)?;
+        if object.kind != kind {
+            return Err(BackendError::NotFound);
+        }
+        Ok(object.detach().data)
+    }
+
+    fn write_blob(&self, bytes: &[u8]) -> BackendResult<ObjectId> {
+        let repo = self.repo.to_thread_local();
+        let id = repo.write_blob(bytes).map_err(to_backend_error)?;
+        Ok(id.detach())
+    }
+
+    fn write_tree_entries(
+        &self,
+        mut entries: Vec<gix::objs::tree::Entry>,
+    ) -> BackendResult<ObjectId> {
+        // Git requires the entries to be sorted, with trees sorted as if their
+        // names had a trailing slash, which is what the `Ord` impl does
+        entries.sort();
+        let repo = self.repo.to_thread_local();
+        let id = repo
+            .write_object(&gix::objs::Tree { entries })
+            .map_err(to_backend_error)?;
+        Ok(id.detach())
+    }
+}
+
+fn signature_from_gix(signature: gix::actor::SignatureRef) -> Signature {
+    let name = signature.name.to_str().unwrap_or("<no name>").to_owned();
+    let email = signature.email.to_str().unwrap_or("<no email>").to_owned();
+    let timestamp = MillisSinceEpoch((signature.time.seconds * 1000) as u64);
+    let tz_offset = signature.time.offset / 60;
+    Signature {
+        name,
+        email,
+        timestamp: Timestamp {
+            timestamp,
+            tz_offset,
+        },
+    }
+}
+
+fn signature_to_gix(signature: &Signature) -> gix::actor::Signature {
+    gix::actor::Signature {
+        name: signature.name.as_str().into(),
+        email: signature.email.as_str().into(),
+        time: gix::date::Time::new(
+            (signature.timestamp.timestamp.0 / 1000) as i64,
+            signature.timestamp.tz_offset * 60,
+        ),
+    }
+}
+
+/// The file mode of a tree value in a Git tree.
+fn entry_kind(value: &TreeValue) -> EntryKind {
+    match value {
+        TreeValue::Normal {
+            executable: false, ..
+        } => EntryKind::Blob,
+        TreeValue::Normal {
+            executable: true, ..
+        } => EntryKind::BlobExecutable,
+        TreeValue::Symlink(_) => EntryKind::Link,
+        TreeValue::Tree(_) => EntryKind::Tree,
+        TreeValue::GitSubmodule(_) => EntryKind::Commit,
+        // Conflicts are stored as JSON blobs
+        TreeValue::Conflict(_) => EntryKind::Blob,
+    }
+}
+
+fn tree_value_id(value: &TreeValue) -> &[u8] {
+    match value {
+        TreeValue::Normal { id, .. } => id.as_bytes(),
+        TreeValue::Symlink(id) => id.as_bytes(),
+        TreeValue::Tree(id) => id.as_bytes(),
+        TreeValue::GitSubmodule(id) => id.as_bytes(),
+        TreeValue::Conflict(id) => id.as_bytes(),
+    }
+}
+
+fn tree_entry(name: String, value: &TreeValue) -> BackendResult<gix::objs::tree::Entry> {
+    Ok(gix::objs::tree::Entry {
+        mode: entry_kind(value).into(),
+        filename: name.into(),
+        oid: object_id(tree_value_id(value))?,
+    })
+}
+
+impl Debug for GitoxideBackend {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), Error> {
+        f.debug_struct("GitoxideStore")
+            .field("path", &self.repo.to_thread_local().git_dir())
+            .finish()
+    }
+}
+
+impl Backend for GitoxideBackend {
+    fn name(&self) -> &str {
+        "gitoxide"
+    }
+
+    fn hash_length(&self) -> usize {
+        HASH_LENGTH
+    }
+
+    fn git_repo(&self) -> Option<git2::Repository> {
+        let repo = self.repo.to_thread_local();
+        Some(git2::Repository::open(repo.git_dir()).unwrap())
+    }
+
+    fn read_file(&self, _path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
+        let content = self.read_object(id.as_bytes(), gix::object::Kind::Blob)?;
+        Ok(Box::new(Cursor::new(content)))
+    }
+
+    fn write_file(&self, _path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
+        let mut bytes = Vec::new();
+        contents.read_to_end(&mut bytes)?;
+        let id = self.write_blob(&bytes)?;
+        Ok(FileId::from_bytes(id.as_bytes()))
+    }
+
+    fn read_symlink(&self, _path: &RepoPath, id: &SymlinkId) -> BackendResult<String> {
+        let content = self.read_object(id.as_bytes(), gix::object::Kind::Blob)?;
+        Ok(String::from_utf8(content).unwrap())
+    }
+
+    fn write_symlink(&self, _path: &RepoPath, target: &str) -> BackendResult<SymlinkId> {
+        let id = self.write_blob(target.as_bytes())?;
+        Ok(SymlinkId::from_bytes(id.as_bytes()))
+    }
+
+    fn root_commit_id(&self) -> &CommitId {
+        &self.root_commit_id
+    }
+
+    fn empty_tree_id(&self) -> &TreeId {
+        &self.empty_tree_id
+    }
+
+    fn read_tree(&self, _path: &RepoPath, id: &TreeId) -> BackendResult<Tree> {
+        if id == &self.empty_tree_id {
+            return Ok(Tree::default());
+        }
+        let data = self.read_object(id.as_bytes(), gix::object::Kind::Tree)?;
+        let git_tree = gix::objs::TreeRef::from_bytes(&data).map_err(to_backend_error)?;
+        let names: HashSet<&[u8]> = git_tree
+            .entries
+            .iter()
+            .map(|entry| entry.filename.as_bytes())
+            .collect();
+        let mut tree = Tree::default();
+        for entry in &git_tree.entries {
+            let name = entry.filename.to_str().unwrap();
+            let entry_id = entry.oid.as_bytes();
+            let (name, value) = match entry.mode.kind() {
+                EntryKind::Tree => {
+                    if let Some(conflict_name) = name.strip_suffix(CONFLICT_SIDES_SUFFIX) {
+                        let conflict_file_name = conflict_name.to_string() + CONFLICT_SUFFIX;
+                        if names.contains(conflict_file_name.as_bytes()) {
+                            continue;
+                        }
+                    }
+                    (name, TreeValue::Tree(TreeId::from_bytes(entry_id)))
+                }
+                EntryKind::Blob => match name.strip_suffix(CONFLICT_SUFFIX) {
+                    Some(conflict_name) => (
+                        conflict_name,
+                        TreeValue::Conflict(ConflictId::from_bytes(entry_id)),
+                    ),
+                    None => (
+                        name,
+                        TreeValue::Normal {
+                            id: FileId::from_bytes(entry_id),
+                            executable: false,
+                        },
+                    ),
+                },
+                EntryKind::BlobExecutable => (
+                    name,
+                    TreeValue::Normal {
+                        id: FileId::from_bytes(entry_id),
+                        executable: true,
+                    },
+                ),
+                EntryKind::Link => (name, TreeValue::Symlink(SymlinkId::from_bytes(entry_id))),
+                EntryKind::Commit => (
+                    name,
+                    TreeValue::GitSubmodule(CommitId::from_bytes(entry_id)),
+                ),
+            };
+            tree.set(RepoPathComponent::from(name), value);
+        }
+        Ok(tree)
+    }
+
+    fn write_tree(&self, path: &RepoPath, contents: &Tree) -> BackendResult<TreeId> {
+        let mut entries = vec![];
+        for entry in contents.entries() {
+            let name = entry.name().string();
+            if let TreeValue::Conflict(id) = entry.value() {
+                // Store the sides of the conflict next to it, like `GitBackend` does
+                let conflict = self.read_conflict(path, id)?;
+                let sides_entries: Vec<_> = conflict_sides(&conflict)
+                    .map(|(side_name, value)| tree_entry(side_name, value))
+                    .try_collect()?;
+                let sides_id = self.write_tree_entries(sides_entries)?;
+                entries.push(gix::objs::tree::Entry {
+                    mode: EntryKind::Tree.into(),
+                    filename: (name.clone() + CONFLICT_SIDES_SUFFIX).into(),
+                    oid: sides_id,
+                });
+                entries.push(tree_entry(name + CONFLICT_SUFFIX, entry.value())?);
+            } else {
+                entries.push(tree_entry(name, entry.value())?);
+            }
+        }
+        let id = self.write_tree_entries(entries)?;
+        Ok(TreeId::from_bytes(id.as_bytes()))
+    }
+
+    fn read_conflict(&self, _path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
+        let data = self.read_object(id.as_bytes(), gix::object::Kind::Blob)?;
+        Ok(conflict_from_json(&data))
+    }
+
+    fn write_conflict(&self, _path: &RepoPath, conflict: &Conflict) -> BackendResult<ConflictId> {
+        let id = self.write_blob(conflict_to_json(conflict).as_bytes())?;
+        Ok(ConflictId::from_bytes(id.as_bytes()))
+    }
+
+    fn read_commit(&self, id: &CommitId) -> BackendResult<Commit> {
+        if id.as_bytes().len() != self.hash_length() {
+            return Err(BackendError::NotFound);
+        }
+
+        if *id == self.root_commit_id {
+            return Ok(make_root_commit(self.empty_tree_id.clone()));
+        }
+
+        let data = self.read_object(id.as_bytes(), gix::object::Kind::Commit)?;
+        let git_commit = gix::objs::CommitRef::from_bytes(&data).map_err(to_backend_error)?;
+        let mut parents = if self.shallow_commits.contains(id) {
+            vec![]
+        } else {
+            git_commit
+                .parents()
+                .map(|oid| CommitId::from_bytes(oid.as_bytes()))
+                .collect_vec()
+        };
+        if parents.is_empty() {
+            parents.push(self.root_commit_id.clone());
+        };
+        let secure_sig = match git_commit.extra_headers().pgp_signature() {
+            Some(sig) => {
+                // The signed data is the commit without the signature header
+                let mut unsigned_commit: gix::objs::Commit = git_commit.clone().into();
+                unsigned_commit
+                    .extra_headers
+                    .retain(|(name, _)| name != "gpgsig");
+                let mut data = vec![];
+                unsigned_commit.write_to(&mut data)?;
+                Some(SecureSig {
+                    data,
+                    sig: sig.to_vec(),
+                })
+            }
+            None => None,
+        };
+
+        let mut commit = Commit {
+            parents,
+            predecessors: vec![],
+            root_tree: TreeId::from_bytes(git_commit.tree().as_bytes()),
+            change_id: change_id_from_commit_id(id),
+            description: git_commit
+                .message
+                .to_str()
+                .unwrap_or("<no message>")
+                .to_owned(),
+            author: signature_from_gix(git_commit.author),
+            committer: signature_from_gix(git_commit.committer),
+            is_open: false,
+            secure_sig,
+        };
+        read_extras(&self.extra_metadata_store, id, &mut commit)?;
+        Ok(commit)
+    }
+
+    fn write_commit(
+        &self,
+        mut contents: Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<(CommitId, Commit)> {
+        let mut parents = vec![];
+        for parent_id in &contents.parents {
+            if *parent_id == self.root_commit_id {
+                // Git doesn't have a root commit, so if the parent is the root commit, we don't
+                // add it to the list of parents to write in the Git commit. We also check that
+                // there are no other parents since Git cannot represent a merge between a root
+                // commit and another commit.
+                assert_eq!(contents.parents.len(), 1);
+            } else {
+                parents.push(object_id(parent_id.as_bytes())?);
+            }
+        }
+        let mut git_commit = gix::objs::Commit {
+            tree: object_id(contents.root_tree.as_bytes())?,
+            parents: parents.into_iter().collect(),
+            author: signature_to_gix(&contents.author),
+            committer: signature_to_gix(&contents.committer),
+            encoding: None,
+            message: BString::from(contents.description.as_str()),
+            extra_headers: vec![],
+        };
+        let mut secure_sig = None;
+        if let Some(sign) = sign_with {
+            let mut data = vec![];
+            git_commit.write_to(&mut data)?;
+            let sig = sign(&data)?;
+            git_commit
+                .extra_headers
+                .push(("gpgsig".into(), BString::from(sig.clone())));
+            secure_sig = Some(SecureSig { data, sig });
+        }
+        let repo = self.repo.to_thread_local();
+        let git_id = repo
+            .write_object(&git_commit)
+            .map_err(to_backend_error)?
+            .detach();
+        repo.reference(
+            create_no_gc_ref(),
+            git_id,
+            PreviousValue::MustNotExist,
+            "new commit",
+        )
+        .map_err(to_backend_error)?;
+        contents.secure_sig = secure_sig;
+        let id = CommitId::from_bytes(git_id.as_bytes());
+        write_extras(&self.extra_metadata_store, &id, &contents)?;
+        Ok((id, contents))
+    }
+
+    fn verify(&self, object: &StoreObjectId) -> BackendResult<()> {
+        let expected_kind = match object {
+            StoreObjectId::Commit(id) if *id == self.root_commit_id => return Ok(()),
+            StoreObjectId::Commit(_) => gix::object::Kind::Commit,
+            StoreObjectId::Tree(_) => gix::object::Kind::Tree,
+            // Symlinks and conflicts are stored as blobs too
+            StoreObjectId::File(_) | StoreObjectId::Symlink(_) | StoreObjectId::Conflict(_) => {
+                gix::object::Kind::Blob
+            }
+        };
+        let oid = object_id(object.as_bytes())?;
+        let corrupt = || BackendError::Corrupt(object.clone());
+        let repo = self.repo.to_thread_local();
+        let git_object = match repo.try_find_object(oid) {
+            Ok(Some(git_object)) => git_object,
+            Ok(None) => return Err(BackendError::NotFound),
+            // Objects that can't be decompressed fail to be read
+            Err(_) => return Err(corrupt()),
+        };
+        if git_object.kind != expected_kind
+            || gix::objs::compute_hash(gix::hash::Kind::Sha1, git_object.kind, &git_object.data)
+                != oid
+        {
+            return Err(corrupt());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{ChangeId, ConflictPart};
+    use crate::git_backend::GitBackend;
+    use crate::testutils;
+
+    fn signature() -> Signature {
+        Signature {
+            name: "Someone".to_string(),
+            email: "someone@example.com".to_string(),
+            timestamp: Timestamp {
+                timestamp: MillisSinceEpoch(1_000_000),
+                tz_offset: -480,
+            },
+        }
+    }
+
+    #[test]
+    fn same_objects_as_libgit2() {
+        let temp_dir = testutils::new_temp_dir();
+        let store_path = temp_dir.path().join("gitoxide");
+        std::fs::create_dir(&store_path).unwrap();
+        let store = GitoxideBackend::init_internal(&store_path);
+        let git2_store_path = temp_dir.path().join("git2");
+        std::fs::create_dir(&git2_store_path).unwrap();
+        let git2_store = GitBackend::init_external(&git2_store_path, &store_path.join("git"));
+        let path = RepoPath::from_internal_string("file");
+
+        let file_id = store
+            .write_file(&path, &mut Cursor::new(b"contents".to_vec()))
+            .unwrap();
+        let mut contents = String::new();
+        git2_store
+            .read_file(&path, &file_id)
+            .unwrap()
+            .read_to_string(&mut contents)
+            .unwrap();
+        assert_eq!(contents, "contents");
+
+        let symlink_id = store.write_symlink(&path, "target").unwrap();
+        let conflict = Conflict {
+            removes: vec![ConflictPart {
+                value: TreeValue::Normal {
+                    id: file_id.clone(),
+                    executable: false,
+                },
+                label: None,
+            }],
+            adds: vec![ConflictPart {
+                value: TreeValue::Symlink(symlink_id.clone()),
+                label: None,
+            }],
+        };
+        let conflict_id = store.write_conflict(&path, &conflict).unwrap();
+        assert_eq!(
+            git2_store.read_conflict(&path, &conflict_id).unwrap(),
+            conflict
+        );
+        let mut tree = Tree::default();
+        tree.set(
+            RepoPathComponent::from("executable"),
+            TreeValue::Normal {
+                id: file_id.clone(),
+                executable: true,
+            },
+        );
+        tree.set(
+            RepoPathComponent::from("file"),
+            TreeValue::Normal {
+                id: file_id,
+                executable: false,
+            },
+        );
+        tree.set(
+            RepoPathComponent::from("symlink"),
+            TreeValue::Symlink(symlink_id),
+        );
+        tree.set(
+            RepoPathComponent::from("conflict"),
+            TreeValue::Conflict(conflict_id),
+        );
+        let tree_id = store.write_tree(&RepoPath::root(), &tree).unwrap();
+        assert_eq!(
+            git2_store.write_tree(&RepoPath::root(), &tree).unwrap(),
+            tree_id
+        );
+        assert_eq!(store.read_tree(&RepoPath::root(), &tree_id).unwrap(), tree);
+
+        let commit = Commit {
+            parents: vec![store.root_commit_id().clone()],
+            predecessors: vec![],
+            root_tree: tree_id.clone(),
+            change_id: ChangeId::new(vec![]),
+            description: "description\n".to_string(),
+            author: signature(),
+            committer: signature(),
+            is_open: false,
+            secure_sig: None,
+        };
+        let (commit_id, _) = store.write_commit(commit, None).unwrap();
+        for read in [
+            store.read_commit(&commit_id).unwrap(),
+            git2_store.read_commit(&commit_id).unwrap(),
+        ] {
+            assert_eq!(read.parents, vec![store.root_commit_id().clone()]);
+            assert_eq!(read.root_tree, tree_id);
+            assert_eq!(read.description, "description\n");
+            assert_eq!(read.author, signature());
+            assert_eq!(read.committer, signature());
+            assert_eq!(read.secure_sig, None);
+        }
+    }
+
+    #[test]
+    fn write_signed_commit() {
+        let temp_dir = testutils::new_temp_dir();
+        let store = GitoxideBackend::init_internal(temp_dir.path());
+        let commit = Commit {
+            parents: vec![store.root_commit_id().clone()],
+            predecessors: vec![],
+            root_tree: store.empty_tree_id().clone(),
+            change_id: ChangeId::new(vec![]),
+            description: "initial".to_string(),
+            author: signature(),
+            committer: signature(),
+            is_open: false,
+            secure_sig: None,
+        };
+        let mut signed_data = vec![];
+        let mut sign = |data: &[u8]| {
+            signed_data = data.to_vec();
+            Ok(b"test signature".to_vec())
+        };
+        let (commit_id, written) = store.write_commit(commit, Some(&mut sign)).unwrap();
+        let expected_sig = SecureSig {
+            data: signed_data,
+            sig: b"test signature".to_vec(),
+        };
+        assert_eq!(written.secure_sig.as_ref(), Some(&expected_sig));
+        let read = store.read_commit(&commit_id).unwrap();
+        assert_eq!(read.secure_sig, Some(expected_sig));
+    }
+}
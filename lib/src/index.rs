@@ -26,10 +26,12 @@ use std::sync::Arc;
 use blake2::{Blake2b512, Digest};
 use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
 use itertools::Itertools;
+use memmap2::Mmap;
 use tempfile::NamedTempFile;
 use thiserror::Error;
 
 use crate::backend::{ChangeId, CommitId};
+use crate::changed_path_filter::ChangedPathFilter;
 use crate::commit::Commit;
 use crate::file_util::persist_content_addressed_temp_file;
 #[cfg(not(feature = "map_first_last"))]
@@ -89,6 +91,27 @@ impl<'a> IndexRef<'a> {
         }
     }
 
+    pub fn resolve_change_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<CommitId>> {
+        match self {
+            IndexRef::Readonly(index) => index.resolve_change_id_prefix(prefix),
+            IndexRef::Mutable(index) => index.resolve_change_id_prefix(prefix),
+        }
+    }
+
+    pub fn shortest_unique_commit_id_prefix_len(&self, commit_id: &CommitId) -> usize {
+        match self {
+            IndexRef::Readonly(index) => index.shortest_unique_commit_id_prefix_len(commit_id),
+            IndexRef::Mutable(index) => index.shortest_unique_commit_id_prefix_len(commit_id),
+        }
+    }
+
+    pub fn shortest_unique_change_id_prefix_len(&self, change_id: &ChangeId) -> usize {
+        match self {
+            IndexRef::Readonly(index) => index.shortest_unique_change_id_prefix_len(change_id),
+            IndexRef::Mutable(index) => index.shortest_unique_change_id_prefix_len(change_id),
+        }
+    }
+
     pub fn entry_by_id(&self, commit_id: &CommitId) -> Option<IndexEntry<'a>> {
         match self {
             IndexRef::Readonly(index) => index.entry_by_id(commit_id),
@@ -208,6 +231,29 @@ impl CommitLookupEntry<'_> {
     }
 }
 
+struct ChangeLookupEntry<'a> {
+    data: &'a [u8],
+}
+
+impl ChangeLookupEntry<'_> {
+    const SIZE: usize = 16 + 4;
+
+    fn change_id(&self) -> ChangeId {
+        ChangeId::new(self.data[0..16].to_vec())
+    }
+
+    fn pos(&self) -> IndexPosition {
+        IndexPosition((&self.data[16..20]).read_u32::<LittleEndian>().unwrap())
+    }
+}
+
+const INDEX_FILE_MAGIC: &[u8; 4] = b"JJIX";
+const INDEX_FORMAT_VERSION: u32 = 2;
+
+/// Index files with fewer commits than this are squashed into the next file
+/// written on top of them.
+const MIN_SEGMENT_COMMITS: u32 = 16;
+
 #[derive(Error, Debug)]
 pub enum IndexLoadError {
     #[error("Index file '{0}' is corrupt.")]
@@ -217,8 +263,13 @@ pub enum IndexLoadError {
 }
 
 // File format:
+// 4 bytes: the magic bytes "JJIX"
+// u32: format version, currently 2
+// u32: length of the parent file's name, or 0 if there's no parent file
+// <length of the parent file's name>: the parent file's name
 // u32: number of entries
 // u32: number of parent overflow entries
+// u32: number of bytes of changed-path filters
 // for each entry, in some topological order with parents first:
 //   u32: generation number
 //   u32: number of parents
@@ -228,7 +279,15 @@ pub enum IndexLoadError {
 // for each entry, sorted by commit id:
 //   <hash length number of bytes>: commit id
 //    u32: position in the entry table above
-// TODO: add a version number
+// for each entry, sorted by change id and then by position:
+//   16 bytes: change id
+//   u32: position in the entry table above
+// for each parent overflow entry:
+//   u32: position in the entry table above
+// for each entry, in the same order as the first table:
+//   u32: end offset of its changed-path filter in the data below, which is
+//        empty if the commit doesn't have one
+// <number of bytes of changed-path filters>: the changed-path filters
 // TODO: replace the table by a trie so we don't have to repeat the full commit
 //       ids
 // TODO: add a fanout table like git's commit graph has?
@@ -241,9 +300,14 @@ pub struct ReadonlyIndex {
     commit_lookup_entry_size: usize,
     // Number of commits not counting the parent file
     num_local_commits: u32,
-    graph: Vec<u8>,
-    lookup: Vec<u8>,
-    overflow_parent: Vec<u8>,
+    // The whole file, which the offsets below refer to
+    data: Mmap,
+    graph_offset: usize,
+    lookup_offset: usize,
+    change_lookup_offset: usize,
+    overflow_parent_offset: usize,
+    changed_path_ends_offset: usize,
+    changed_path_data_offset: usize,
 }
 
 impl Debug for ReadonlyIndex {
@@ -318,6 +382,7 @@ struct MutableGraphEntry {
     change_id: ChangeId,
     generation_number: u32,
     parent_positions: Vec<IndexPosition>,
+    changed_path_filter: Option<ChangedPathFilter>,
 }
 
 pub struct MutableIndex {
@@ -326,6 +391,7 @@ pub struct MutableIndex {
     hash_length: usize,
     graph: Vec<MutableGraphEntry>,
     lookup: BTreeMap<CommitId, IndexPosition>,
+    change_lookup: BTreeMap<ChangeId, Vec<IndexPosition>>,
 }
 
 impl MutableIndex {
@@ -336,6 +402,7 @@ impl MutableIndex {
             hash_length,
             graph: vec![],
             lookup: BTreeMap::new(),
+            change_lookup: BTreeMap::new(),
         }
     }
 
@@ -348,6 +415,7 @@ impl MutableIndex {
             hash_length,
             graph: vec![],
             lookup: BTreeMap::new(),
+            change_lookup: BTreeMap::new(),
         }
     }
 
@@ -356,18 +424,33 @@ impl MutableIndex {
     }
 
     pub fn add_commit(&mut self, commit: &Commit) {
-        self.add_commit_data(
+        if self.has_id(commit.id()) {
+            return;
+        }
+        self.add_commit_with_filter(
             commit.id().clone(),
             commit.change_id().clone(),
             commit.parent_ids(),
+            ChangedPathFilter::for_commit(commit),
         );
     }
 
+    #[cfg(test)]
     fn add_commit_data(
         &mut self,
         commit_id: CommitId,
         change_id: ChangeId,
         parent_ids: &[CommitId],
+    ) {
+        self.add_commit_with_filter(commit_id, change_id, parent_ids, None);
+    }
+
+    fn add_commit_with_filter(
+        &mut self,
+        commit_id: CommitId,
+        change_id: ChangeId,
+        parent_ids: &[CommitId],
+        changed_path_filter: Option<ChangedPathFilter>,
     ) {
         if self.has_id(&commit_id) {
             return;
@@ -377,6 +460,7 @@ impl MutableIndex {
             change_id,
             generation_number: 0,
             parent_positions: vec![],
+            changed_path_filter,
         };
         for parent_id in parent_ids {
             let parent_entry = self
@@ -388,10 +472,12 @@ impl MutableIndex {
             );
             entry.parent_positions.push(parent_entry.pos);
         }
-        self.lookup.insert(
-            entry.commit_id.clone(),
-            IndexPosition(self.graph.len() as u32 + self.num_parent_commits),
-        );
+        let pos = IndexPosition(self.graph.len() as u32 + self.num_parent_commits);
+        self.lookup.insert(entry.commit_id.clone(), pos);
+        self.change_lookup
+            .entry(entry.change_id.clone())
+            .or_default()
+            .push(pos);
         self.graph.push(entry);
     }
 
@@ -404,7 +490,12 @@ impl MutableIndex {
                 .iter()
                 .map(|entry| entry.commit_id())
                 .collect_vec();
-            self.add_commit_data(entry.commit_id(), entry.change_id(), &parent_ids);
+            self.add_commit_with_filter(
+                entry.commit_id(),
+                entry.change_id(),
+                &parent_ids,
+                entry.changed_path_filter(),
+            );
         }
     }
 
@@ -445,6 +536,8 @@ impl MutableIndex {
         let num_commits = self.graph.len() as u32;
 
         let mut buf = vec![];
+        buf.write_all(INDEX_FILE_MAGIC).unwrap();
+        buf.write_u32::<LittleEndian>(INDEX_FORMAT_VERSION).unwrap();
 
         if let Some(parent_file) = &self.parent_file {
             buf.write_u32::<LittleEndian>(parent_file.name.len() as u32)
@@ -455,11 +548,15 @@ impl MutableIndex {
         }
 
         buf.write_u32::<LittleEndian>(num_commits).unwrap();
-        // We'll write the actual value later
+        // We'll write the actual values later
         let parent_overflow_offset = buf.len();
         buf.write_u32::<LittleEndian>(0_u32).unwrap();
+        let changed_path_data_offset = buf.len();
+        buf.write_u32::<LittleEndian>(0_u32).unwrap();
 
         let mut parent_overflow = vec![];
+        let mut changed_path_ends = vec![];
+        let mut changed_path_data = vec![];
         for entry in self.graph {
             let flags = 0;
             buf.write_u32::<LittleEndian>(flags).unwrap();
@@ -486,6 +583,11 @@ impl MutableIndex {
 
             assert_eq!(entry.commit_id.as_bytes().len(), self.hash_length);
             buf.write_all(entry.commit_id.as_bytes()).unwrap();
+
+            if let Some(filter) = &entry.changed_path_filter {
+                changed_path_data.extend_from_slice(filter.as_bytes());
+            }
+            changed_path_ends.push(changed_path_data.len() as u32);
         }
 
         for (commit_id, pos) in self.lookup {
@@ -493,14 +595,28 @@ impl MutableIndex {
             buf.write_u32::<LittleEndian>(pos.0).unwrap();
         }
 
-        buf[parent_overflow_offset..parent_overflow_offset + 4]
-            .as_mut()
+        for (change_id, positions) in self.change_lookup {
+            for pos in positions {
+                buf.write_all(change_id.as_bytes()).unwrap();
+                buf.write_u32::<LittleEndian>(pos.0).unwrap();
+            }
+        }
+
+        (&mut buf[parent_overflow_offset..parent_overflow_offset + 4])
             .write_u32::<LittleEndian>(parent_overflow.len() as u32)
             .unwrap();
         for parent_pos in parent_overflow {
             buf.write_u32::<LittleEndian>(parent_pos.0).unwrap();
         }
 
+        (&mut buf[changed_path_data_offset..changed_path_data_offset + 4])
+            .write_u32::<LittleEndian>(changed_path_data.len() as u32)
+            .unwrap();
+        for end in changed_path_ends {
+            buf.write_u32::<LittleEndian>(end).unwrap();
+        }
+        buf.write_all(&changed_path_data).unwrap();
+
         buf
     }
 
@@ -515,9 +631,11 @@ impl MutableIndex {
         loop {
             match maybe_parent_file {
                 Some(parent_file) => {
-                    // TODO: We should probably also squash if the parent file has less than N
-                    // commits, regardless of how many (few) are in `self`.
-                    if 2 * num_new_commits < parent_file.segment_num_commits() {
+                    // Small files are always squashed, so importing commits in many small
+                    // transactions doesn't leave behind a long chain of tiny files.
+                    if 2 * num_new_commits < parent_file.segment_num_commits()
+                        && parent_file.segment_num_commits() >= MIN_SEGMENT_COMMITS
+                    {
                         squashed = MutableIndex::incremental(parent_file);
                         break;
                     }
@@ -549,7 +667,6 @@ impl MutableIndex {
         }
 
         let hash_length = self.hash_length;
-
         let buf = self.maybe_squash_with_ancestors().serialize();
         let mut hasher = Blake2b512::new();
         hasher.update(&buf);
@@ -561,14 +678,11 @@ impl MutableIndex {
         file.write_all(&buf)?;
         persist_content_addressed_temp_file(temp_file, &index_file_path)?;
 
-        let mut cursor = Cursor::new(&buf);
-        ReadonlyIndex::load_from(&mut cursor, dir, index_file_id_hex, hash_length).map_err(|err| {
-            match err {
-                IndexLoadError::IndexCorrupt(err) => {
-                    panic!("Just-created index file is corrupt: {}", err)
-                }
-                IndexLoadError::IoError(err) => err,
+        ReadonlyIndex::load_from(dir, index_file_id_hex, hash_length).map_err(|err| match err {
+            IndexLoadError::IndexCorrupt(err) => {
+                panic!("Just-created index file is corrupt: {}", err)
             }
+            IndexLoadError::IoError(err) => err,
         })
     }
 
@@ -588,6 +702,18 @@ impl MutableIndex {
         CompositeIndex(self).resolve_prefix(prefix)
     }
 
+    pub fn resolve_change_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<CommitId>> {
+        CompositeIndex(self).resolve_change_id_prefix(prefix)
+    }
+
+    pub fn shortest_unique_commit_id_prefix_len(&self, commit_id: &CommitId) -> usize {
+        CompositeIndex(self).shortest_unique_commit_id_prefix_len(commit_id)
+    }
+
+    pub fn shortest_unique_change_id_prefix_len(&self, change_id: &ChangeId) -> usize {
+        CompositeIndex(self).shortest_unique_change_id_prefix_len(change_id)
+    }
+
     pub fn entry_by_id(&self, commit_id: &CommitId) -> Option<IndexEntry> {
         CompositeIndex(self).entry_by_id(commit_id)
     }
@@ -640,6 +766,21 @@ trait IndexSegment {
 
     fn segment_resolve_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<CommitId>;
 
+    /// Returns the change id matching the prefix and the positions of its
+    /// commits in this segment.
+    fn segment_resolve_change_id_prefix(
+        &self,
+        prefix: &HexPrefix,
+    ) -> PrefixResolution<(ChangeId, Vec<IndexPosition>)>;
+
+    /// Returns the length of the longest hex prefix the commit id shares with
+    /// another commit id in this segment.
+    fn segment_commit_id_common_prefix_len(&self, commit_id: &CommitId) -> usize;
+
+    /// Returns the length of the longest hex prefix the change id shares with
+    /// another change id in this segment.
+    fn segment_change_id_common_prefix_len(&self, change_id: &ChangeId) -> usize;
+
     fn segment_generation_number(&self, local_pos: u32) -> u32;
 
     fn segment_commit_id(&self, local_pos: u32) -> CommitId;
@@ -650,6 +791,8 @@ trait IndexSegment {
 
     fn segment_parent_positions(&self, local_pos: u32) -> Vec<IndexPosition>;
 
+    fn segment_changed_path_filter(&self, local_pos: u32) -> Option<ChangedPathFilter>;
+
     fn segment_entry_by_pos(&self, pos: IndexPosition, local_pos: u32) -> IndexEntry;
 }
 
@@ -742,6 +885,79 @@ impl<'a> CompositeIndex<'a> {
         local_match.plus(&parent_match)
     }
 
+    pub fn resolve_change_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<CommitId>> {
+        match self.resolve_change_id_prefix_to_positions(prefix) {
+            PrefixResolution::NoMatch => PrefixResolution::NoMatch,
+            PrefixResolution::SingleMatch((_change_id, positions)) => {
+                PrefixResolution::SingleMatch(
+                    positions
+                        .into_iter()
+                        .map(|pos| self.entry_by_pos(pos).commit_id())
+                        .collect(),
+                )
+            }
+            PrefixResolution::AmbiguousMatch => PrefixResolution::AmbiguousMatch,
+        }
+    }
+
+    fn resolve_change_id_prefix_to_positions(
+        &self,
+        prefix: &HexPrefix,
+    ) -> PrefixResolution<(ChangeId, Vec<IndexPosition>)> {
+        let local_match = self.0.segment_resolve_change_id_prefix(prefix);
+        if local_match == PrefixResolution::AmbiguousMatch {
+            // return early to avoid checking the parent file(s)
+            return local_match;
+        }
+        let parent_match = self
+            .0
+            .segment_parent_file()
+            .map_or(PrefixResolution::NoMatch, |file| {
+                CompositeIndex(file.as_ref()).resolve_change_id_prefix_to_positions(prefix)
+            });
+        match (local_match, parent_match) {
+            // The same change can have commits in several segments
+            (
+                PrefixResolution::SingleMatch((local_change_id, local_positions)),
+                PrefixResolution::SingleMatch((parent_change_id, mut positions)),
+            ) if local_change_id == parent_change_id => {
+                positions.extend(local_positions);
+                PrefixResolution::SingleMatch((local_change_id, positions))
+            }
+            (local_match, parent_match) => local_match.plus(&parent_match),
+        }
+    }
+
+    pub fn shortest_unique_commit_id_prefix_len(&self, commit_id: &CommitId) -> usize {
+        let mut common_prefix_len = 0;
+        let mut maybe_segment: Option<&dyn IndexSegment> = Some(self.0);
+        while let Some(segment) = maybe_segment {
+            common_prefix_len = max(
+                common_prefix_len,
+                segment.segment_commit_id_common_prefix_len(commit_id),
+            );
+            maybe_segment = segment
+                .segment_parent_file()
+                .map(|file| file.as_ref() as &dyn IndexSegment);
+        }
+        min(common_prefix_len + 1, commit_id.as_bytes().len() * 2)
+    }
+
+    pub fn shortest_unique_change_id_prefix_len(&self, change_id: &ChangeId) -> usize {
+        let mut common_prefix_len = 0;
+        let mut maybe_segment: Option<&dyn IndexSegment> = Some(self.0);
+        while let Some(segment) = maybe_segment {
+            common_prefix_len = max(
+                common_prefix_len,
+                segment.segment_change_id_common_prefix_len(change_id),
+            );
+            maybe_segment = segment
+                .segment_parent_file()
+                .map(|file| file.as_ref() as &dyn IndexSegment);
+        }
+        min(common_prefix_len + 1, change_id.as_bytes().len() * 2)
+    }
+
     pub fn entry_by_id(&self, commit_id: &CommitId) -> Option<IndexEntry<'a>> {
         self.commit_id_to_pos(commit_id)
             .map(&|pos| self.entry_by_pos(pos))
@@ -976,6 +1192,7 @@ pub struct RevWalk<'a> {
     items: BinaryHeap<RevWalkWorkItem<'a>>,
     wanted_boundary_set: HashSet<IndexPosition>,
     unwanted_boundary_set: HashSet<IndexPosition>,
+    min_generation: u32,
 }
 
 impl<'a> RevWalk<'a> {
@@ -985,9 +1202,18 @@ impl<'a> RevWalk<'a> {
             items: BinaryHeap::new(),
             wanted_boundary_set: HashSet::new(),
             unwanted_boundary_set: HashSet::new(),
+            min_generation: 0,
         }
     }
 
+    /// Skips commits with a generation number lower than `min_generation`.
+    /// Their ancestors have even lower generation numbers, so the walk doesn't
+    /// need to go past them.
+    pub fn min_generation(mut self, min_generation: u32) -> Self {
+        self.min_generation = min_generation;
+        self
+    }
+
     fn add_wanted(&mut self, pos: IndexPosition) {
         if !self.wanted_boundary_set.insert(pos) {
             return;
@@ -1013,10 +1239,15 @@ impl<'a> Iterator for RevWalk<'a> {
     type Item = IndexEntry<'a>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        while let Some(item) = self.items.pop() {
+        // Once there are no wanted commits left to visit, the remaining unwanted
+        // ones can't exclude anything
+        while !self.wanted_boundary_set.is_empty() {
+            let item = self.items.pop().unwrap();
             if item.wanted {
                 self.wanted_boundary_set.remove(&item.entry.0.pos);
-                if self.unwanted_boundary_set.contains(&item.entry.0.pos) {
+                if self.unwanted_boundary_set.contains(&item.entry.0.pos)
+                    || item.entry.0.generation_number() < self.min_generation
+                {
                     continue;
                 }
                 for parent_pos in item.entry.0.parent_positions() {
@@ -1106,6 +1337,81 @@ impl IndexSegment for ReadonlyIndex {
         }
     }
 
+    fn segment_resolve_change_id_prefix(
+        &self,
+        prefix: &HexPrefix,
+    ) -> PrefixResolution<(ChangeId, Vec<IndexPosition>)> {
+        let (bytes_prefix, min_bytes_prefix) = prefix.bytes_prefixes();
+        let mut found: Option<(ChangeId, Vec<IndexPosition>)> = None;
+        for i in self.change_id_lookup_pos(min_bytes_prefix.as_bytes())..self.num_local_commits {
+            let entry = self.change_lookup_entry(i);
+            let change_id = entry.change_id();
+            if !change_id.as_bytes().starts_with(bytes_prefix.as_bytes()) {
+                break;
+            }
+            if change_id.hex().starts_with(prefix.hex()) {
+                match &mut found {
+                    None => found = Some((change_id, vec![entry.pos()])),
+                    Some((found_change_id, positions)) => {
+                        if *found_change_id != change_id {
+                            return PrefixResolution::AmbiguousMatch;
+                        }
+                        positions.push(entry.pos());
+                    }
+                }
+            }
+        }
+        match found {
+            None => PrefixResolution::NoMatch,
+            Some(found) => PrefixResolution::SingleMatch(found),
+        }
+    }
+
+    fn segment_commit_id_common_prefix_len(&self, commit_id: &CommitId) -> usize {
+        let lookup_pos = self.commit_id_lookup_pos(commit_id.as_bytes());
+        let mut neighbors = vec![];
+        if lookup_pos > 0 {
+            neighbors.push(self.lookup_entry(lookup_pos - 1).commit_id());
+        }
+        let mut next_pos = lookup_pos;
+        if next_pos < self.num_local_commits
+            && self.lookup_entry(next_pos).commit_id() == *commit_id
+        {
+            next_pos += 1;
+        }
+        if next_pos < self.num_local_commits {
+            neighbors.push(self.lookup_entry(next_pos).commit_id());
+        }
+        neighbors
+            .iter()
+            .map(|neighbor| common_hex_prefix_len(neighbor.as_bytes(), commit_id.as_bytes()))
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn segment_change_id_common_prefix_len(&self, change_id: &ChangeId) -> usize {
+        let lookup_pos = self.change_id_lookup_pos(change_id.as_bytes());
+        let mut neighbors = vec![];
+        if lookup_pos > 0 {
+            neighbors.push(self.change_lookup_entry(lookup_pos - 1).change_id());
+        }
+        // Skip the commits that have the same change id
+        let mut next_pos = lookup_pos;
+        while next_pos < self.num_local_commits
+            && self.change_lookup_entry(next_pos).change_id() == *change_id
+        {
+            next_pos += 1;
+        }
+        if next_pos < self.num_local_commits {
+            neighbors.push(self.change_lookup_entry(next_pos).change_id());
+        }
+        neighbors
+            .iter()
+            .map(|neighbor| common_hex_prefix_len(neighbor.as_bytes(), change_id.as_bytes()))
+            .max()
+            .unwrap_or(0)
+    }
+
     fn segment_generation_number(&self, local_pos: u32) -> u32 {
         self.graph_entry(local_pos).generation_number()
     }
@@ -1138,6 +1444,23 @@ impl IndexSegment for ReadonlyIndex {
         parent_entries
     }
 
+    fn segment_changed_path_filter(&self, local_pos: u32) -> Option<ChangedPathFilter> {
+        let end_offset = self.changed_path_ends_offset + (local_pos as usize) * 4;
+        let read_end = |offset: usize| {
+            (&self.data[offset..offset + 4])
+                .read_u32::<LittleEndian>()
+                .unwrap() as usize
+        };
+        let start = if local_pos == 0 {
+            0
+        } else {
+            read_end(end_offset - 4)
+        };
+        let end = read_end(end_offset);
+        let data = &self.data[self.changed_path_data_offset..];
+        (start < end).then(|| ChangedPathFilter::from_bytes(&data[start..end]))
+    }
+
     fn segment_entry_by_pos(&self, pos: IndexPosition, local_pos: u32) -> IndexEntry {
         IndexEntry {
             source: self,
@@ -1198,6 +1521,65 @@ impl IndexSegment for MutableIndex {
         }
     }
 
+    fn segment_resolve_change_id_prefix(
+        &self,
+        prefix: &HexPrefix,
+    ) -> PrefixResolution<(ChangeId, Vec<IndexPosition>)> {
+        let (bytes_prefix, min_bytes_prefix) = prefix.bytes_prefixes();
+        let min_change_id = ChangeId::new(min_bytes_prefix.to_bytes());
+        let mut found = None;
+        for (change_id, positions) in self
+            .change_lookup
+            .range((Bound::Included(&min_change_id), Bound::Unbounded))
+        {
+            if !change_id.as_bytes().starts_with(bytes_prefix.as_bytes()) {
+                break;
+            }
+            if change_id.hex().starts_with(prefix.hex()) {
+                if found.is_some() {
+                    return PrefixResolution::AmbiguousMatch;
+                }
+                found = Some((change_id.clone(), positions.clone()));
+            }
+        }
+        match found {
+            None => PrefixResolution::NoMatch,
+            Some(found) => PrefixResolution::SingleMatch(found),
+        }
+    }
+
+    fn segment_commit_id_common_prefix_len(&self, commit_id: &CommitId) -> usize {
+        let before = self.lookup.range(..commit_id).next_back();
+        let after = self
+            .lookup
+            .range((Bound::Excluded(commit_id), Bound::Unbounded))
+            .next();
+        before
+            .into_iter()
+            .chain(after)
+            .map(|(neighbor, _pos)| {
+                common_hex_prefix_len(neighbor.as_bytes(), commit_id.as_bytes())
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    fn segment_change_id_common_prefix_len(&self, change_id: &ChangeId) -> usize {
+        let before = self.change_lookup.range(..change_id).next_back();
+        let after = self
+            .change_lookup
+            .range((Bound::Excluded(change_id), Bound::Unbounded))
+            .next();
+        before
+            .into_iter()
+            .chain(after)
+            .map(|(neighbor, _positions)| {
+                common_hex_prefix_len(neighbor.as_bytes(), change_id.as_bytes())
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
     fn segment_generation_number(&self, local_pos: u32) -> u32 {
         self.graph[local_pos as usize].generation_number
     }
@@ -1218,6 +1600,10 @@ impl IndexSegment for MutableIndex {
         self.graph[local_pos as usize].parent_positions.clone()
     }
 
+    fn segment_changed_path_filter(&self, local_pos: u32) -> Option<ChangedPathFilter> {
+        self.graph[local_pos as usize].changed_path_filter.clone()
+    }
+
     fn segment_entry_by_pos(&self, pos: IndexPosition, local_pos: u32) -> IndexEntry {
         IndexEntry {
             source: self,
@@ -1283,6 +1669,11 @@ impl<'a> IndexEntry<'a> {
         self.source.segment_parent_positions(self.local_pos)
     }
 
+    /// The filter of paths changed by this commit, if it has one.
+    pub fn changed_path_filter(&self) -> Option<ChangedPathFilter> {
+        self.source.segment_changed_path_filter(self.local_pos)
+    }
+
     pub fn parents(&self) -> Vec<IndexEntry<'a>> {
         let composite = CompositeIndex(self.source);
         self.parent_positions()
@@ -1293,45 +1684,66 @@ impl<'a> IndexEntry<'a> {
 }
 
 impl ReadonlyIndex {
+    /// Loads the index file with the given name and its parent files. The files
+    /// are memory-mapped rather than read, so only the parts that are used
+    /// get loaded from disk.
     pub(crate) fn load_from(
-        file: &mut dyn Read,
         dir: PathBuf,
         name: String,
         hash_length: usize,
     ) -> Result<Arc<ReadonlyIndex>, IndexLoadError> {
-        let parent_filename_len = file.read_u32::<LittleEndian>()?;
+        let file = File::open(dir.join(&name))?;
+        // Safety: index files are never modified after they're written, since
+        // they're named by the hash of their contents
+        let data = unsafe { Mmap::map(&file)? };
+        let mut header = Cursor::new(&data[..]);
+        // Files written by older versions, or truncated ones, are treated as
+        // corrupt so the index gets rebuilt
+        let corrupt = || IndexLoadError::IndexCorrupt(name.clone());
+        let mut magic = [0; 4];
+        header.read_exact(&mut magic).map_err(|_| corrupt())?;
+        if magic != *INDEX_FILE_MAGIC
+            || header.read_u32::<LittleEndian>().map_err(|_| corrupt())? != INDEX_FORMAT_VERSION
+        {
+            return Err(corrupt());
+        }
+        let parent_filename_len = header.read_u32::<LittleEndian>().map_err(|_| corrupt())?;
         let num_parent_commits;
         let maybe_parent_file;
         if parent_filename_len > 0 {
             let mut parent_filename_bytes = vec![0; parent_filename_len as usize];
-            file.read_exact(&mut parent_filename_bytes)?;
-            let parent_filename = String::from_utf8(parent_filename_bytes).unwrap();
-            let parent_file_path = dir.join(&parent_filename);
-            let mut index_file = File::open(&parent_file_path).unwrap();
-            let parent_file =
-                ReadonlyIndex::load_from(&mut index_file, dir, parent_filename, hash_length)?;
+            header
+                .read_exact(&mut parent_filename_bytes)
+                .map_err(|_| corrupt())?;
+            let parent_filename =
+                String::from_utf8(parent_filename_bytes).map_err(|_| corrupt())?;
+            let parent_file = ReadonlyIndex::load_from(dir, parent_filename, hash_length)?;
             num_parent_commits = parent_file.num_parent_commits + parent_file.num_local_commits;
             maybe_parent_file = Some(parent_file);
         } else {
             num_parent_commits = 0;
             maybe_parent_file = None;
         };
-        let num_commits = file.read_u32::<LittleEndian>()?;
-        let num_parent_overflow_entries = file.read_u32::<LittleEndian>()?;
-        let mut data = vec![];
-        file.read_to_end(&mut data)?;
+        let mut read_u32 = || header.read_u32::<LittleEndian>().map_err(|_| corrupt());
+        let num_commits = read_u32()?;
+        let num_parent_overflow_entries = read_u32()?;
+        let changed_path_data_size = read_u32()?;
+        let graph_offset = header.position() as usize;
         let commit_graph_entry_size = CommitGraphEntry::size(hash_length);
         let graph_size = (num_commits as usize) * commit_graph_entry_size;
         let commit_lookup_entry_size = CommitLookupEntry::size(hash_length);
         let lookup_size = (num_commits as usize) * commit_lookup_entry_size;
+        let change_lookup_size = (num_commits as usize) * ChangeLookupEntry::SIZE;
         let parent_overflow_size = (num_parent_overflow_entries as usize) * 4;
-        let expected_size = graph_size + lookup_size + parent_overflow_size;
-        if data.len() != expected_size {
-            return Err(IndexLoadError::IndexCorrupt(name));
+        let changed_path_ends_size = (num_commits as usize) * 4;
+        let lookup_offset = graph_offset + graph_size;
+        let change_lookup_offset = lookup_offset + lookup_size;
+        let overflow_parent_offset = change_lookup_offset + change_lookup_size;
+        let changed_path_ends_offset = overflow_parent_offset + parent_overflow_size;
+        let changed_path_data_offset = changed_path_ends_offset + changed_path_ends_size;
+        if data.len() != changed_path_data_offset + changed_path_data_size as usize {
+            return Err(corrupt());
         }
-        let overflow_parent = data.split_off(graph_size + lookup_size);
-        let lookup = data.split_off(graph_size);
-        let graph = data;
         Ok(Arc::new(ReadonlyIndex {
             parent_file: maybe_parent_file,
             num_parent_commits,
@@ -1340,9 +1752,13 @@ impl ReadonlyIndex {
             commit_graph_entry_size,
             commit_lookup_entry_size,
             num_local_commits: num_commits,
-            graph,
-            lookup,
-            overflow_parent,
+            data,
+            graph_offset,
+            lookup_offset,
+            change_lookup_offset,
+            overflow_parent_offset,
+            changed_path_ends_offset,
+            changed_path_data_offset,
         }))
     }
 
@@ -1370,6 +1786,18 @@ impl ReadonlyIndex {
         CompositeIndex(self).resolve_prefix(prefix)
     }
 
+    pub fn resolve_change_id_prefix(&self, prefix: &HexPrefix) -> PrefixResolution<Vec<CommitId>> {
+        CompositeIndex(self).resolve_change_id_prefix(prefix)
+    }
+
+    pub fn shortest_unique_commit_id_prefix_len(&self, commit_id: &CommitId) -> usize {
+        CompositeIndex(self).shortest_unique_commit_id_prefix_len(commit_id)
+    }
+
+    pub fn shortest_unique_change_id_prefix_len(&self, change_id: &ChangeId) -> usize {
+        CompositeIndex(self).shortest_unique_change_id_prefix_len(change_id)
+    }
+
     pub fn entry_by_id(&self, commit_id: &CommitId) -> Option<IndexEntry> {
         CompositeIndex(self).entry_by_id(commit_id)
     }
@@ -1410,24 +1838,65 @@ impl ReadonlyIndex {
 
     fn graph_entry(&self, local_pos: u32) -> CommitGraphEntry {
         let offset = (local_pos as usize) * self.commit_graph_entry_size;
+        let offset = self.graph_offset + offset;
         CommitGraphEntry {
-            data: &self.graph[offset..offset + self.commit_graph_entry_size],
+            data: &self.data[offset..offset + self.commit_graph_entry_size],
             hash_length: self.hash_length,
         }
     }
 
     fn lookup_entry(&self, lookup_pos: u32) -> CommitLookupEntry {
         let offset = (lookup_pos as usize) * self.commit_lookup_entry_size;
+        let offset = self.lookup_offset + offset;
         CommitLookupEntry {
-            data: &self.lookup[offset..offset + self.commit_lookup_entry_size],
+            data: &self.data[offset..offset + self.commit_lookup_entry_size],
             hash_length: self.hash_length,
         }
     }
 
+    fn change_lookup_entry(&self, lookup_pos: u32) -> ChangeLookupEntry {
+        let offset = self.change_lookup_offset + (lookup_pos as usize) * ChangeLookupEntry::SIZE;
+        ChangeLookupEntry {
+            data: &self.data[offset..offset + ChangeLookupEntry::SIZE],
+        }
+    }
+
+    /// Returns the position of the first entry in the commit lookup table
+    /// whose commit id is not less than `commit_id_bytes`.
+    fn commit_id_lookup_pos(&self, commit_id_bytes: &[u8]) -> u32 {
+        let mut low = 0;
+        let mut high = self.num_local_commits;
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.lookup_entry(mid).commit_id().as_bytes() < commit_id_bytes {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
+    /// Returns the position of the first entry in the change lookup table
+    /// whose change id is not less than `change_id_bytes`.
+    fn change_id_lookup_pos(&self, change_id_bytes: &[u8]) -> u32 {
+        let mut low = 0;
+        let mut high = self.num_local_commits;
+        while low < high {
+            let mid = (low + high) / 2;
+            if self.change_lookup_entry(mid).change_id().as_bytes() < change_id_bytes {
+                low = mid + 1;
+            } else {
+                high = mid;
+            }
+        }
+        low
+    }
+
     fn overflow_parent(&self, overflow_pos: u32) -> IndexPosition {
-        let offset = (overflow_pos as usize) * 4;
+        let offset = self.overflow_parent_offset + (overflow_pos as usize) * 4;
         IndexPosition(
-            (&self.overflow_parent[offset..offset + 4])
+            (&self.data[offset..offset + 4])
                 .read_u32::<LittleEndian>()
                 .unwrap(),
         )
@@ -1460,6 +1929,20 @@ impl ReadonlyIndex {
     }
 }
 
+/// Returns the number of leading hex digits that are the same in both ids.
+fn common_hex_prefix_len(id1: &[u8], id2: &[u8]) -> usize {
+    for (i, (byte1, byte2)) in id1.iter().zip(id2).enumerate() {
+        if byte1 != byte2 {
+            return if byte1 >> 4 == byte2 >> 4 {
+                i * 2 + 1
+            } else {
+                i * 2
+            };
+        }
+    }
+    min(id1.len(), id2.len()) * 2
+}
+
 #[cfg(test)]
 mod tests {
     use test_case::test_case;
@@ -1772,6 +2255,94 @@ mod tests {
             PrefixResolution::AmbiguousMatch
         );
     }
+
+    #[test]
+    fn resolve_change_id_prefix() {
+        let temp_dir = testutils::new_temp_dir();
+        let mut index = MutableIndex::full(3);
+
+        let change_id_a = ChangeId::from_hex("aaaa0000000000000000000000000000");
+        let change_id_b = ChangeId::from_hex("aab00000000000000000000000000000");
+        let change_id_c = ChangeId::from_hex("c0000000000000000000000000000000");
+        let id_0 = CommitId::from_hex("000000");
+        let id_1 = CommitId::from_hex("111111");
+        let id_2 = CommitId::from_hex("222222");
+        index.add_commit_data(id_0.clone(), change_id_a.clone(), &[]);
+        index.add_commit_data(id_1.clone(), change_id_b.clone(), &[]);
+        index.add_commit_data(id_2.clone(), change_id_c.clone(), &[]);
+
+        // Write the first three commits to one file and build the remainder on top.
+        let initial_file = index.save_in(temp_dir.path().to_owned()).unwrap();
+        index = MutableIndex::incremental(initial_file);
+
+        // A new commit for an existing change, and one for a new change
+        let id_3 = CommitId::from_hex("333333");
+        let id_4 = CommitId::from_hex("444444");
+        index.add_commit_data(id_3.clone(), change_id_a.clone(), &[]);
+        index.add_commit_data(id_4.clone(), change_id_c.clone(), &[]);
+
+        let resolve = |prefix: &str| {
+            index.resolve_change_id_prefix(&HexPrefix::new(prefix.to_string()).unwrap())
+        };
+        // Commits of the same change are found in all files
+        assert_eq!(
+            resolve(&change_id_a.hex()),
+            PrefixResolution::SingleMatch(vec![id_0, id_3])
+        );
+        assert_eq!(resolve("aab"), PrefixResolution::SingleMatch(vec![id_1]));
+        assert_eq!(
+            resolve("c"),
+            PrefixResolution::SingleMatch(vec![id_2, id_4])
+        );
+        assert_eq!(resolve("aa"), PrefixResolution::AmbiguousMatch);
+        assert_eq!(resolve("b"), PrefixResolution::NoMatch);
+        assert_eq!(resolve("aaab"), PrefixResolution::NoMatch);
+
+        assert_eq!(index.shortest_unique_change_id_prefix_len(&change_id_a), 3);
+        assert_eq!(index.shortest_unique_change_id_prefix_len(&change_id_b), 3);
+        assert_eq!(index.shortest_unique_change_id_prefix_len(&change_id_c), 1);
+    }
+
+    #[test_case(false; "in memory")]
+    #[test_case(true; "on disk")]
+    fn shortest_unique_commit_id_prefix_len(on_disk: bool) {
+        let temp_dir = testutils::new_temp_dir();
+        let mut index = MutableIndex::full(3);
+
+        let id_0 = CommitId::from_hex("000000");
+        let id_1 = CommitId::from_hex("009999");
+        let id_2 = CommitId::from_hex("055488");
+        let id_3 = CommitId::from_hex("055444");
+        let id_4 = CommitId::from_hex("ffffff");
+        index.add_commit_data(id_0.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_1.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_2.clone(), new_change_id(), &[]);
+
+        let initial_file = index.save_in(temp_dir.path().to_owned()).unwrap();
+        index = MutableIndex::incremental(initial_file);
+        index.add_commit_data(id_3.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_4.clone(), new_change_id(), &[]);
+        let mut _saved_index = None;
+        let index = if on_disk {
+            _saved_index = Some(index.save_in(temp_dir.path().to_owned()).unwrap());
+            IndexRef::Readonly(_saved_index.as_ref().unwrap())
+        } else {
+            IndexRef::Mutable(&index)
+        };
+
+        assert_eq!(index.shortest_unique_commit_id_prefix_len(&id_0), 3);
+        assert_eq!(index.shortest_unique_commit_id_prefix_len(&id_1), 3);
+        // Ids in different files are compared too
+        assert_eq!(index.shortest_unique_commit_id_prefix_len(&id_2), 5);
+        assert_eq!(index.shortest_unique_commit_id_prefix_len(&id_3), 5);
+        assert_eq!(index.shortest_unique_commit_id_prefix_len(&id_4), 1);
+        // Ids that aren't in the index are also handled
+        assert_eq!(
+            index.shortest_unique_commit_id_prefix_len(&CommitId::from_hex("055400")),
+            5
+        );
+    }
+
     #[test]
     fn test_is_ancestor() {
         let mut index = MutableIndex::full(3);
@@ -2017,6 +2588,63 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_walk_revs_min_generation() {
+        let mut index = MutableIndex::full(3);
+        // 4
+        // |\
+        // 3 |
+        // | |
+        // 2 |
+        // |/
+        // 1
+        // |
+        // 0
+        let id_0 = CommitId::from_hex("000000");
+        let id_1 = CommitId::from_hex("111111");
+        let id_2 = CommitId::from_hex("222222");
+        let id_3 = CommitId::from_hex("333333");
+        let id_4 = CommitId::from_hex("444444");
+        index.add_commit_data(id_0.clone(), new_change_id(), &[]);
+        index.add_commit_data(id_1.clone(), new_change_id(), &[id_0.clone()]);
+        index.add_commit_data(id_2.clone(), new_change_id(), &[id_1.clone()]);
+        index.add_commit_data(id_3.clone(), new_change_id(), &[id_2.clone()]);
+        index.add_commit_data(id_4.clone(), new_change_id(), &[id_3.clone(), id_1.clone()]);
+
+        let walk_commit_ids = |wanted: Vec<CommitId>, min_generation: u32| {
+            index
+                .walk_revs(&wanted, &[])
+                .min_generation(min_generation)
+                .map(|entry| entry.commit_id())
+                .collect_vec()
+        };
+
+        assert_eq!(
+            walk_commit_ids(vec![id_4.clone()], 0),
+            vec![
+                id_4.clone(),
+                id_3.clone(),
+                id_2.clone(),
+                id_1.clone(),
+                id_0.clone()
+            ]
+        );
+        // The walk stops at commit 1 even though it's also reached from commit 4
+        // directly
+        assert_eq!(
+            walk_commit_ids(vec![id_4.clone()], 1),
+            vec![id_4.clone(), id_3.clone(), id_2.clone(), id_1.clone()]
+        );
+        assert_eq!(
+            walk_commit_ids(vec![id_4.clone()], 3),
+            vec![id_4.clone(), id_3]
+        );
+        // Wanted commits below the limit are skipped too
+        assert_eq!(walk_commit_ids(vec![id_2.clone(), id_0], 2), vec![id_2]);
+        assert_eq!(walk_commit_ids(vec![id_4.clone()], 5), vec![]);
+        assert_eq!(walk_commit_ids(vec![id_4.clone()], 4), vec![id_4]);
+    }
+
     #[test]
     fn test_heads() {
         let mut index = MutableIndex::full(3);
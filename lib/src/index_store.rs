@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::collections::{HashMap, HashSet};
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::{Read, Write};
@@ -87,14 +88,7 @@ impl IndexStore {
             .read_to_end(&mut buf)
             .unwrap();
         let index_file_id_hex = String::from_utf8(buf).unwrap();
-        let index_file_path = self.dir.join(&index_file_id_hex);
-        let mut index_file = File::open(&index_file_path).unwrap();
-        ReadonlyIndex::load_from(
-            &mut index_file,
-            self.dir.clone(),
-            index_file_id_hex,
-            hash_length,
-        )
+        ReadonlyIndex::load_from(self.dir.clone(), index_file_id_hex, hash_length)
     }
 
     fn index_at_operation(
@@ -106,41 +100,49 @@ impl IndexStore {
         let operations_dir = self.dir.join("operations");
         let hash_length = store.hash_length();
         let mut new_heads = view.heads().clone();
-        let mut parent_op_id: Option<OperationId> = None;
+        let is_indexed = |op: &Operation| operations_dir.join(op.id().hex()).is_file();
+        // Don't walk past operations that are already indexed, so we only visit the
+        // operations added since the last time the index was updated.
+        let mut indexed_ops = vec![];
         for op in dag_walk::bfs(
             vec![operation.clone()],
             Box::new(|op: &Operation| op.id().clone()),
-            Box::new(|op: &Operation| op.parents()),
+            Box::new(
+                |op: &Operation| {
+                    if is_indexed(op) {
+                        vec![]
+                    } else {
+                        op.parents()
+                    }
+                },
+            ),
         ) {
-            if operations_dir.join(op.id().hex()).is_file() {
-                if parent_op_id.is_none() {
-                    parent_op_id = Some(op.id().clone())
-                }
+            if is_indexed(&op) {
+                indexed_ops.push(op);
             } else {
                 for head in op.view().heads() {
                     new_heads.insert(head.clone());
                 }
             }
         }
-        let mut data;
-        let maybe_parent_file;
-        match parent_op_id {
-            None => {
-                maybe_parent_file = None;
-                data = MutableIndex::full(hash_length);
-            }
-            Some(parent_op_id) => {
-                let parent_file = self
-                    .load_index_at_operation(hash_length, &parent_op_id)
-                    .unwrap();
-                maybe_parent_file = Some(parent_file.clone());
-                data = MutableIndex::incremental(parent_file)
-            }
+        let mut indexed_files = indexed_ops
+            .iter()
+            .map(|op| self.load_index_at_operation(hash_length, op.id()).unwrap())
+            .collect_vec();
+        // If we reached several indexed operations (e.g. after concurrent operations),
+        // build on the largest index and merge in the others.
+        indexed_files.sort_by_key(|index_file| index_file.num_commits());
+        let mut data = match indexed_files.pop() {
+            None => MutableIndex::full(hash_length),
+            Some(parent_file) => MutableIndex::incremental(parent_file),
+        };
+        for index_file in &indexed_files {
+            data.merge_in(index_file);
         }
 
         let mut heads = new_heads.into_iter().collect_vec();
         heads.sort();
-        let commits = topo_order_earlier_first(store, heads, maybe_parent_file);
+        let commits = topo_order_earlier_first(store, heads, &data);
 
         for commit in &commits {
             data.add_commit(commit);
@@ -168,6 +170,25 @@ impl IndexStore {
         )?;
         Ok(())
     }
+
+    /// Links `new_op_id` to the index version of `old_op_id`, if it has one.
+    /// Used when an operation is rewritten without changing what it indexes.
+    pub fn copy_operation_link(
+        &self,
+        old_op_id: &OperationId,
+        new_op_id: &OperationId,
+    ) -> io::Result<()> {
+        let operations_dir = self.dir.join("operations");
+        let index_file_id = match fs::read(operations_dir.join(old_op_id.hex())) {
+            Ok(index_file_id) => index_file_id,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => return Err(err),
+        };
+        let mut temp_file = NamedTempFile::new_in(&self.dir)?;
+        temp_file.as_file_mut().write_all(&index_file_id)?;
+        persist_content_addressed_temp_file(temp_file, operations_dir.join(new_op_id.hex()))?;
+        Ok(())
+    }
 }
 
 // Returns the ancestors of heads with parents and predecessors come before the
@@ -175,7 +196,7 @@ impl IndexStore {
 fn topo_order_earlier_first(
     store: &Arc<Store>,
     heads: Vec<CommitId>,
-    parent_file: Option<Arc<ReadonlyIndex>>,
+    index: &MutableIndex,
 ) -> Vec<Commit> {
     // First create a list of all commits in topological order with
     // children/successors first (reverse of what we want)
@@ -185,11 +206,10 @@ fn topo_order_earlier_first(
     }
     let mut commits = vec![];
     let mut visited = HashSet::new();
-    let mut in_parent_file = HashSet::new();
-    let parent_file_source = parent_file.as_ref().map(|file| file.as_ref());
+    let mut in_index = HashSet::new();
     while let Some(commit) = work.pop() {
-        if parent_file_source.map_or(false, |index| index.has_id(commit.id())) {
-            in_parent_file.insert(commit.id().clone());
+        if index.has_id(commit.id()) {
+            in_index.insert(commit.id().clone());
             continue;
         } else if !visited.insert(commit.id().clone()) {
             continue;
@@ -211,7 +231,7 @@ fn topo_order_earlier_first(
     let mut waiting = HashMap::new();
 
     let mut result = vec![];
-    let mut visited = in_parent_file;
+    let mut visited = in_index;
     while let Some(commit) = commits.pop() {
         let mut waiting_for_earlier_commit = false;
         for earlier in commit.parents().iter().chain(commit.predecessors().iter()) {
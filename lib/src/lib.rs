@@ -16,33 +16,49 @@
 #![cfg_attr(feature = "map_first_last", feature(map_first_last))]
 
 pub mod backend;
+pub mod changed_path_filter;
+pub mod chunking;
 pub mod commit;
 pub mod commit_builder;
 pub mod conflicts;
+pub mod copies;
 pub mod dag_walk;
 pub mod diff;
+pub mod eol;
 pub mod file_util;
 pub mod files;
+pub mod fsck;
+pub mod fsmonitor;
+pub mod gc;
 pub mod git;
 pub mod git_backend;
+pub mod git_hooks;
 pub mod gitignore;
+#[cfg(feature = "gitoxide")]
+pub mod gitoxide_backend;
 pub mod index;
 pub mod index_store;
 pub mod local_backend;
 pub mod lock;
 pub mod matchers;
+pub mod merge_drivers;
+pub mod migrate;
 pub mod nightly_shims;
+pub mod op_abandon;
 pub mod op_heads_store;
+pub mod op_hooks;
 pub mod op_store;
 pub mod operation;
 pub mod protos;
 pub mod refs;
 pub mod repo;
 pub mod repo_path;
+pub mod resolutions;
 pub mod revset;
 pub mod revset_graph_iterator;
 pub mod rewrite;
 pub mod settings;
+pub mod signing;
 pub mod simple_op_store;
 pub mod stacked_table;
 pub mod store;
@@ -51,5 +67,6 @@ pub mod transaction;
 pub mod tree;
 pub mod tree_builder;
 pub mod view;
+pub mod view_snapshot;
 pub mod working_copy;
 pub mod workspace;
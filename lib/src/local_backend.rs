@@ -12,11 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
 use std::fmt::Debug;
 use std::fs;
 use std::fs::File;
-use std::io::{ErrorKind, Read, Write};
+use std::io::{Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 use blake2::{Blake2b512, Digest};
 use protobuf::{Message, MessageField};
@@ -24,9 +26,10 @@ use tempfile::{NamedTempFile, PersistError};
 
 use crate::backend::{
     make_root_commit, Backend, BackendError, BackendResult, ChangeId, Commit, CommitId, Conflict,
-    ConflictId, ConflictPart, FileId, MillisSinceEpoch, Signature, SymlinkId, Timestamp, Tree,
-    TreeId, TreeValue,
+    ConflictId, ConflictPart, FileId, GcStats, MillisSinceEpoch, ReachableObjects, SecureSig,
+    Signature, SigningFn, StoreObjectId, SymlinkId, Timestamp, Tree, TreeId, TreeValue,
 };
+use crate::chunking;
 use crate::file_util::persist_content_addressed_temp_file;
 use crate::repo_path::{RepoPath, RepoPathComponent};
 
@@ -48,6 +51,125 @@ impl From<protobuf::Error> for BackendError {
     }
 }
 
+/// Prefix of compressed file and tree objects. It's followed by a format
+/// version byte and then the zstd-compressed object.
+const COMPRESSED_OBJECT_MAGIC: &[u8; 3] = b"JJZ";
+const COMPRESSED_OBJECT_VERSION: u8 = 1;
+
+/// How objects without the header above are stored. Older versions wrote
+/// files as bare zstd streams and trees uncompressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum LegacyFormat {
+    Zstd,
+    Uncompressed,
+}
+
+/// Writes the compressed object header and returns an encoder for the rest of
+/// the object.
+fn compressed_object_writer<W: Write>(mut writer: W) -> BackendResult<zstd::Encoder<'static, W>> {
+    writer.write_all(COMPRESSED_OBJECT_MAGIC)?;
+    writer.write_all(&[COMPRESSED_OBJECT_VERSION])?;
+    Ok(zstd::Encoder::new(writer, 0)?)
+}
+
+/// Returns a reader of the decompressed contents of the object in `file`,
+/// which may have been written in the current format or in `legacy_format`.
+fn compressed_object_reader(
+    mut file: File,
+    legacy_format: LegacyFormat,
+) -> BackendResult<Box<dyn Read>> {
+    let mut header = vec![];
+    (&mut file)
+        .take(COMPRESSED_OBJECT_MAGIC.len() as u64 + 1)
+        .read_to_end(&mut header)?;
+    if header.starts_with(COMPRESSED_OBJECT_MAGIC) && header.len() > COMPRESSED_OBJECT_MAGIC.len() {
+        let version = header[COMPRESSED_OBJECT_MAGIC.len()];
+        if version != COMPRESSED_OBJECT_VERSION {
+            return Err(BackendError::Other(format!(
+                "Unsupported object format version {}",
+                version
+            )));
+        }
+        return Ok(Box::new(zstd::Decoder::new(file)?));
+    }
+    let contents = Cursor::new(header).chain(file);
+    match legacy_format {
+        LegacyFormat::Zstd => Ok(Box::new(zstd::Decoder::new(contents)?)),
+        LegacyFormat::Uncompressed => Ok(Box::new(contents)),
+    }
+}
+
+/// Prefix of files that are stored as a list of content-defined chunks. It's
+/// followed by a format version byte and the hashes of the chunks, which are
+/// stored as compressed objects in the `chunks` directory.
+const CHUNKED_FILE_MAGIC: &[u8; 3] = b"JJC";
+const CHUNKED_FILE_VERSION: u8 = 1;
+const CHUNK_HASH_LENGTH: usize = 64;
+/// Files at least this large are split into chunks, so a small edit to them
+/// only adds the changed chunks to the store.
+const CHUNKING_THRESHOLD: usize = 4 * 1024 * 1024;
+
+/// Returns the chunk hashes if `file` is a chunked file. Otherwise, the
+/// position in `file` is unspecified.
+fn read_chunk_hashes(file: &mut File) -> BackendResult<Option<Vec<Vec<u8>>>> {
+    let mut header = vec![];
+    (&mut *file)
+        .take(CHUNKED_FILE_MAGIC.len() as u64 + 1)
+        .read_to_end(&mut header)?;
+    if !header.starts_with(CHUNKED_FILE_MAGIC) || header.len() == CHUNKED_FILE_MAGIC.len() {
+        return Ok(None);
+    }
+    let version = header[CHUNKED_FILE_MAGIC.len()];
+    if version != CHUNKED_FILE_VERSION {
+        return Err(BackendError::Other(format!(
+            "Unsupported chunked file format version {}",
+            version
+        )));
+    }
+    let mut hashes = vec![];
+    file.read_to_end(&mut hashes)?;
+    if hashes.len() % CHUNK_HASH_LENGTH != 0 {
+        return Err(BackendError::Other(
+            "Chunked file has a truncated chunk hash".to_string(),
+        ));
+    }
+    Ok(Some(
+        hashes
+            .chunks(CHUNK_HASH_LENGTH)
+            .map(|hash| hash.to_vec())
+            .collect(),
+    ))
+}
+
+/// Reads the contents of a chunked file by reading each of its chunks in turn.
+struct ChunkedFileReader {
+    chunks_dir: PathBuf,
+    chunk_hashes: std::vec::IntoIter<Vec<u8>>,
+    current: Option<Box<dyn Read>>,
+}
+
+impl Read for ChunkedFileReader {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        loop {
+            if let Some(reader) = &mut self.current {
+                let bytes_read = reader.read(buf)?;
+                if bytes_read > 0 || buf.is_empty() {
+                    return Ok(bytes_read);
+                }
+            }
+            match self.chunk_hashes.next() {
+                None => return Ok(0),
+                Some(hash) => {
+                    let file = File::open(self.chunks_dir.join(hex::encode(hash)))?;
+                    let reader = compressed_object_reader(file, LegacyFormat::Uncompressed)
+                        .map_err(|err| std::io::Error::new(ErrorKind::Other, err.to_string()))?;
+                    self.current = Some(reader);
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug)]
 pub struct LocalBackend {
     path: PathBuf,
@@ -62,6 +184,7 @@ impl LocalBackend {
         fs::create_dir(store_path.join("files")).unwrap();
         fs::create_dir(store_path.join("symlinks")).unwrap();
         fs::create_dir(store_path.join("conflicts")).unwrap();
+        fs::create_dir(store_path.join("chunks")).unwrap();
         let backend = Self::load(store_path);
         let empty_tree_id = backend
             .write_tree(&RepoPath::root(), &Tree::default())
@@ -96,9 +219,92 @@ impl LocalBackend {
         self.path.join("commits").join(id.hex())
     }
 
+    fn chunks_dir(&self) -> PathBuf {
+        self.path.join("chunks")
+    }
+
+    /// Writes a chunk of a large file and returns its hash.
+    fn write_chunk(&self, data: &[u8]) -> BackendResult<Vec<u8>> {
+        let hash = Blake2b512::digest(data).to_vec();
+        let path = self.chunks_dir().join(hex::encode(&hash));
+        // Unchanged chunks are usually already in the store
+        if !path.exists() {
+            let temp_file = NamedTempFile::new_in(&self.path)?;
+            let mut encoder = compressed_object_writer(temp_file.as_file())?;
+            encoder.write_all(data)?;
+            encoder.finish()?;
+            persist_content_addressed_temp_file(temp_file, path)?;
+        }
+        Ok(hash)
+    }
+
+    /// Removes the files in the `dir` subdirectory of the store whose names
+    /// aren't in `keep` and that are older than `keep_newer`.
+    fn gc_dir(
+        &self,
+        dir: &str,
+        keep: HashSet<String>,
+        keep_newer: SystemTime,
+        dry_run: bool,
+        stats: &mut GcStats,
+    ) -> BackendResult<()> {
+        let dir_path = self.path.join(dir);
+        if !dir_path.is_dir() {
+            return Ok(());
+        }
+        for entry in fs::read_dir(dir_path)? {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if !metadata.is_file() || metadata.modified()? >= keep_newer {
+                continue;
+            }
+            if let Some(name) = entry.file_name().to_str() {
+                if keep.contains(name) {
+                    continue;
+                }
+            }
+            if !dry_run {
+                fs::remove_file(entry.path())?;
+            }
+            stats.removed_objects += 1;
+            stats.removed_bytes += metadata.len();
+        }
+        Ok(())
+    }
+
     fn conflict_path(&self, id: &ConflictId) -> PathBuf {
         self.path.join("conflicts").join(id.hex())
     }
+
+    /// Hashes the object's contents the same way as when it was written.
+    fn hash_object(&self, object: &StoreObjectId) -> BackendResult<Vec<u8>> {
+        let read_raw = |path: PathBuf| fs::read(path).map_err(not_found_to_backend_error);
+        let mut hasher = Blake2b512::new();
+        match object {
+            StoreObjectId::Commit(id) => hasher.update(read_raw(self.commit_path(id))?),
+            StoreObjectId::Tree(id) => {
+                let file = File::open(self.tree_path(id)).map_err(not_found_to_backend_error)?;
+                let mut proto_bytes = vec![];
+                compressed_object_reader(file, LegacyFormat::Uncompressed)?
+                    .read_to_end(&mut proto_bytes)?;
+                hasher.update(proto_bytes);
+            }
+            StoreObjectId::File(id) => {
+                let mut reader = self.read_file(&RepoPath::root(), id)?;
+                let mut buff = vec![0; 1 << 14];
+                loop {
+                    let bytes_read = reader.read(&mut buff)?;
+                    if bytes_read == 0 {
+                        break;
+                    }
+                    hasher.update(&buff[..bytes_read]);
+                }
+            }
+            StoreObjectId::Symlink(id) => hasher.update(read_raw(self.symlink_path(id))?),
+            StoreObjectId::Conflict(id) => hasher.update(read_raw(self.conflict_path(id))?),
+        }
+        Ok(hasher.finalize().to_vec())
+    }
 }
 
 fn not_found_to_backend_error(err: std::io::Error) -> BackendError {
@@ -124,31 +330,64 @@ impl Backend for LocalBackend {
 
     fn read_file(&self, _path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
         let path = self.file_path(id);
-        let file = File::open(path).map_err(not_found_to_backend_error)?;
-        Ok(Box::new(zstd::Decoder::new(file)?))
+        let mut file = File::open(path).map_err(not_found_to_backend_error)?;
+        if let Some(chunk_hashes) = read_chunk_hashes(&mut file)? {
+            return Ok(Box::new(ChunkedFileReader {
+                chunks_dir: self.chunks_dir(),
+                chunk_hashes: chunk_hashes.into_iter(),
+                current: None,
+            }));
+        }
+        file.seek(SeekFrom::Start(0))?;
+        compressed_object_reader(file, LegacyFormat::Zstd)
     }
 
     fn write_file(&self, _path: &RepoPath, contents: &mut dyn Read) -> BackendResult<FileId> {
-        let temp_file = NamedTempFile::new_in(&self.path)?;
-        let mut encoder = zstd::Encoder::new(temp_file.as_file(), 0)?;
         let mut hasher = Blake2b512::new();
+        // Small files are written as a single object. Once a file reaches the
+        // chunking threshold, its chunks are written as soon as they're complete.
+        let mut pending: Vec<u8> = vec![];
+        let mut chunk_hashes: Option<Vec<u8>> = None;
+        let mut buff = vec![0; 1 << 14];
         loop {
-            let mut buff: Vec<u8> = Vec::with_capacity(1 << 14);
-            let bytes_read;
-            unsafe {
-                buff.set_len(1 << 14);
-                bytes_read = contents.read(&mut buff)?;
-                buff.set_len(bytes_read);
-            }
+            let bytes_read = contents.read(&mut buff)?;
             if bytes_read == 0 {
                 break;
             }
-            encoder.write_all(&buff)?;
-            hasher.update(&buff);
+            hasher.update(&buff[..bytes_read]);
+            pending.extend_from_slice(&buff[..bytes_read]);
+            if chunk_hashes.is_none() && pending.len() >= CHUNKING_THRESHOLD {
+                // Stores created by older versions don't have the directory
+                fs::create_dir_all(self.chunks_dir())?;
+                chunk_hashes = Some(vec![]);
+            }
+            if let Some(chunk_hashes) = &mut chunk_hashes {
+                while pending.len() >= chunking::MAX_CHUNK_SIZE {
+                    let chunk_len = chunking::first_chunk_len(&pending);
+                    chunk_hashes.extend(self.write_chunk(&pending[..chunk_len])?);
+                    pending.drain(..chunk_len);
+                }
+            }
         }
-        encoder.finish()?;
         let id = FileId::new(hasher.finalize().to_vec());
 
+        let temp_file = NamedTempFile::new_in(&self.path)?;
+        match chunk_hashes {
+            None => {
+                let mut encoder = compressed_object_writer(temp_file.as_file())?;
+                encoder.write_all(&pending)?;
+                encoder.finish()?;
+            }
+            Some(mut chunk_hashes) => {
+                for chunk in chunking::split_chunks(&pending) {
+                    chunk_hashes.extend(self.write_chunk(chunk)?);
+                }
+                let mut file = temp_file.as_file();
+                file.write_all(CHUNKED_FILE_MAGIC)?;
+                file.write_all(&[CHUNKED_FILE_VERSION])?;
+                file.write_all(&chunk_hashes)?;
+            }
+        }
         persist_content_addressed_temp_file(temp_file, self.file_path(&id))?;
         Ok(id)
     }
@@ -182,9 +421,10 @@ impl Backend for LocalBackend {
 
     fn read_tree(&self, _path: &RepoPath, id: &TreeId) -> BackendResult<Tree> {
         let path = self.tree_path(id);
-        let mut file = File::open(path).map_err(not_found_to_backend_error)?;
+        let file = File::open(path).map_err(not_found_to_backend_error)?;
+        let mut reader = compressed_object_reader(file, LegacyFormat::Uncompressed)?;
 
-        let proto: crate::protos::store::Tree = Message::parse_from_reader(&mut file)?;
+        let proto: crate::protos::store::Tree = Message::parse_from_reader(&mut reader)?;
         Ok(tree_from_proto(&proto))
     }
 
@@ -195,8 +435,12 @@ impl Backend for LocalBackend {
         let mut proto_bytes: Vec<u8> = Vec::new();
         proto.write_to_writer(&mut proto_bytes)?;
 
-        temp_file.as_file().write_all(&proto_bytes)?;
+        let mut encoder = compressed_object_writer(temp_file.as_file())?;
+        encoder.write_all(&proto_bytes)?;
+        encoder.finish()?;
 
+        // The id is the hash of the uncompressed tree so it doesn't depend on the
+        // compression
         let id = TreeId::new(Blake2b512::digest(&proto_bytes).to_vec());
 
         persist_content_addressed_temp_file(temp_file, self.tree_path(&id))?;
@@ -235,13 +479,34 @@ impl Backend for LocalBackend {
         let mut file = File::open(path).map_err(not_found_to_backend_error)?;
 
         let proto: crate::protos::store::Commit = Message::parse_from_reader(&mut file)?;
-        Ok(commit_from_proto(&proto))
+        let mut commit = commit_from_proto(&proto);
+        if !proto.secure_sig.is_empty() {
+            // The signature covers the commit as serialized without the signature
+            let mut unsigned_proto = proto.clone();
+            unsigned_proto.secure_sig.clear();
+            commit.secure_sig = Some(SecureSig {
+                data: unsigned_proto.write_to_bytes()?,
+                sig: proto.secure_sig.clone(),
+            });
+        }
+        Ok(commit)
     }
 
-    fn write_commit(&self, commit: &Commit) -> BackendResult<CommitId> {
+    fn write_commit(
+        &self,
+        mut commit: Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<(CommitId, Commit)> {
         let temp_file = NamedTempFile::new_in(&self.path)?;
 
-        let proto = commit_to_proto(commit);
+        let mut proto = commit_to_proto(&commit);
+        commit.secure_sig = None;
+        if let Some(sign) = sign_with {
+            let data = proto.write_to_bytes()?;
+            let sig = sign(&data)?;
+            proto.secure_sig = sig.clone();
+            commit.secure_sig = Some(SecureSig { data, sig });
+        }
         let mut proto_bytes: Vec<u8> = Vec::new();
         proto.write_to_writer(&mut proto_bytes)?;
 
@@ -250,7 +515,52 @@ impl Backend for LocalBackend {
         let id = CommitId::new(Blake2b512::digest(&proto_bytes).to_vec());
 
         persist_content_addressed_temp_file(temp_file, self.commit_path(&id))?;
-        Ok(id)
+        Ok((id, commit))
+    }
+
+    fn gc(
+        &self,
+        reachable: &ReachableObjects,
+        keep_newer: SystemTime,
+        dry_run: bool,
+    ) -> BackendResult<GcStats> {
+        let mut stats = GcStats::default();
+        let commits = reachable.commits.iter().map(CommitId::hex).collect();
+        self.gc_dir("commits", commits, keep_newer, dry_run, &mut stats)?;
+        let trees = reachable.trees.iter().map(TreeId::hex).collect();
+        self.gc_dir("trees", trees, keep_newer, dry_run, &mut stats)?;
+        let mut chunks = HashSet::new();
+        for id in &reachable.files {
+            match File::open(self.file_path(id)) {
+                Ok(mut file) => {
+                    if let Some(chunk_hashes) = read_chunk_hashes(&mut file)? {
+                        chunks.extend(chunk_hashes.iter().map(hex::encode));
+                    }
+                }
+                Err(err) if err.kind() == ErrorKind::NotFound => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        self.gc_dir("chunks", chunks, keep_newer, dry_run, &mut stats)?;
+        let files = reachable.files.iter().map(FileId::hex).collect();
+        self.gc_dir("files", files, keep_newer, dry_run, &mut stats)?;
+        let symlinks = reachable.symlinks.iter().map(SymlinkId::hex).collect();
+        self.gc_dir("symlinks", symlinks, keep_newer, dry_run, &mut stats)?;
+        let conflicts = reachable.conflicts.iter().map(ConflictId::hex).collect();
+        self.gc_dir("conflicts", conflicts, keep_newer, dry_run, &mut stats)?;
+        Ok(stats)
+    }
+
+    fn verify(&self, object: &StoreObjectId) -> BackendResult<()> {
+        if *object == StoreObjectId::Commit(self.root_commit_id.clone()) {
+            return Ok(());
+        }
+        match self.hash_object(object) {
+            Ok(hash) if hash == object.as_bytes() => Ok(()),
+            Err(BackendError::NotFound) => Err(BackendError::NotFound),
+            // Includes contents that couldn't be decompressed
+            _ => Err(BackendError::Corrupt(object.clone())),
+        }
     }
 }
 
@@ -271,7 +581,7 @@ pub fn commit_to_proto(commit: &Commit) -> crate::protos::store::Commit {
     proto
 }
 
-fn commit_from_proto(proto: &crate::protos::store::Commit) -> Commit {
+pub(crate) fn commit_from_proto(proto: &crate::protos::store::Commit) -> Commit {
     let commit_id_from_proto = |parent: &Vec<u8>| CommitId::new(parent.clone());
     let parents = proto.parents.iter().map(commit_id_from_proto).collect();
     let predecessors = proto
@@ -290,10 +600,11 @@ fn commit_from_proto(proto: &crate::protos::store::Commit) -> Commit {
         author: signature_from_proto(&proto.author),
         committer: signature_from_proto(&proto.committer),
         is_open: proto.is_open,
+        secure_sig: None,
     }
 }
 
-fn tree_to_proto(tree: &Tree) -> crate::protos::store::Tree {
+pub(crate) fn tree_to_proto(tree: &Tree) -> crate::protos::store::Tree {
     let mut proto = crate::protos::store::Tree::new();
     for entry in tree.entries() {
         let mut proto_entry = crate::protos::store::tree::Entry::new();
@@ -304,7 +615,7 @@ fn tree_to_proto(tree: &Tree) -> crate::protos::store::Tree {
     proto
 }
 
-fn tree_from_proto(proto: &crate::protos::store::Tree) -> Tree {
+pub(crate) fn tree_from_proto(proto: &crate::protos::store::Tree) -> Tree {
     let mut tree = Tree::default();
     for proto_entry in &proto.entries {
         let value = tree_value_from_proto(proto_entry.value.as_ref().unwrap());
@@ -406,11 +717,179 @@ fn conflict_from_proto(proto: &crate::protos::store::Conflict) -> Conflict {
 fn conflict_part_from_proto(proto: &crate::protos::store::conflict::Part) -> ConflictPart {
     ConflictPart {
         value: tree_value_from_proto(proto.content.as_ref().unwrap()),
+        label: (!proto.label.is_empty()).then(|| proto.label.clone()),
     }
 }
 
 fn conflict_part_to_proto(part: &ConflictPart) -> crate::protos::store::conflict::Part {
     let mut proto = crate::protos::store::conflict::Part::new();
     proto.content = MessageField::some(tree_value_to_proto(&part.value));
+    proto.label = part.label.clone().unwrap_or_default();
     proto
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::{FileId, TreeValue};
+    use crate::repo_path::RepoPathComponent;
+    use crate::testutils;
+
+    #[test]
+    fn write_and_read_compressed_objects() {
+        let temp_dir = testutils::new_temp_dir();
+        let backend = LocalBackend::init(temp_dir.path());
+        let contents = "some text\n".repeat(1000);
+        let file_id = backend
+            .write_file(&RepoPath::root(), &mut contents.as_bytes())
+            .unwrap();
+        let raw = fs::read(backend.file_path(&file_id)).unwrap();
+        assert!(raw.starts_with(b"JJZ\x01"));
+        assert!(raw.len() < contents.len() / 10);
+        let mut read_contents = String::new();
+        backend
+            .read_file(&RepoPath::root(), &file_id)
+            .unwrap()
+            .read_to_string(&mut read_contents)
+            .unwrap();
+        assert_eq!(read_contents, contents);
+
+        let mut tree = Tree::default();
+        tree.set(
+            RepoPathComponent::from("file"),
+            TreeValue::Normal {
+                id: file_id,
+                executable: false,
+            },
+        );
+        let tree_id = backend.write_tree(&RepoPath::root(), &tree).unwrap();
+        let raw = fs::read(backend.tree_path(&tree_id)).unwrap();
+        assert!(raw.starts_with(b"JJZ\x01"));
+        let read_tree = backend.read_tree(&RepoPath::root(), &tree_id).unwrap();
+        assert_eq!(tree_to_proto(&read_tree), tree_to_proto(&tree));
+    }
+
+    #[test]
+    fn read_legacy_objects() {
+        let temp_dir = testutils::new_temp_dir();
+        let backend = LocalBackend::init(temp_dir.path());
+
+        // Files used to be bare zstd streams
+        let file_id = FileId::new(vec![1, 2, 3]);
+        let compressed = zstd::encode_all(&b"legacy contents"[..], 0).unwrap();
+        fs::write(backend.file_path(&file_id), compressed).unwrap();
+        let mut contents = vec![];
+        backend
+            .read_file(&RepoPath::root(), &file_id)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        assert_eq!(contents, b"legacy contents");
+
+        // Trees used to be stored uncompressed
+        let mut tree = Tree::default();
+        tree.set(
+            RepoPathComponent::from("file"),
+            TreeValue::Normal {
+                id: file_id,
+                executable: true,
+            },
+        );
+        let tree_id = TreeId::from_hex("4567");
+        let proto_bytes = tree_to_proto(&tree).write_to_bytes().unwrap();
+        fs::write(backend.tree_path(&tree_id), proto_bytes).unwrap();
+        let read_tree = backend.read_tree(&RepoPath::root(), &tree_id).unwrap();
+        assert_eq!(tree_to_proto(&read_tree), tree_to_proto(&tree));
+        // Including the empty tree, whose file is empty
+        let empty_tree_id = TreeId::from_hex("89ab");
+        fs::write(backend.tree_path(&empty_tree_id), b"").unwrap();
+        let read_tree = backend
+            .read_tree(&RepoPath::root(), &empty_tree_id)
+            .unwrap();
+        assert!(read_tree.is_empty());
+    }
+
+    fn pseudo_random_bytes(len: usize, seed: u64) -> Vec<u8> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state
+                    .wrapping_mul(6364136223846793005)
+                    .wrapping_add(1442695040888963407);
+                (state >> 56) as u8
+            })
+            .collect()
+    }
+
+    fn read_file_contents(backend: &LocalBackend, id: &FileId) -> Vec<u8> {
+        let mut contents = vec![];
+        backend
+            .read_file(&RepoPath::root(), id)
+            .unwrap()
+            .read_to_end(&mut contents)
+            .unwrap();
+        contents
+    }
+
+    fn count_chunks(backend: &LocalBackend) -> usize {
+        fs::read_dir(backend.chunks_dir()).unwrap().count()
+    }
+
+    #[test]
+    fn write_and_read_chunked_file() {
+        let temp_dir = testutils::new_temp_dir();
+        let backend = LocalBackend::init(temp_dir.path());
+        let contents = pseudo_random_bytes(CHUNKING_THRESHOLD + 1000, 1);
+        let file_id = backend
+            .write_file(&RepoPath::root(), &mut contents.as_slice())
+            .unwrap();
+        let raw = fs::read(backend.file_path(&file_id)).unwrap();
+        assert!(raw.starts_with(b"JJC\x01"));
+        assert_eq!(file_id.as_bytes(), Blake2b512::digest(&contents).as_slice());
+        assert_eq!(read_file_contents(&backend, &file_id), contents);
+        let chunk_count = count_chunks(&backend);
+        assert!(chunk_count > 1);
+
+        // A small edit only adds the chunks around it
+        let mut edited = contents.clone();
+        edited.splice(2_000_000..2_000_000, b"an edit".iter().cloned());
+        let edited_id = backend
+            .write_file(&RepoPath::root(), &mut edited.as_slice())
+            .unwrap();
+        assert_eq!(read_file_contents(&backend, &edited_id), edited);
+        let added_chunks = count_chunks(&backend) - chunk_count;
+        assert!(added_chunks >= 1);
+        assert!(added_chunks <= 2);
+
+        // Chunks that are only used by unreachable files are removed
+        let mut reachable = ReachableObjects::default();
+        reachable.trees.insert(backend.empty_tree_id().clone());
+        reachable.files.insert(edited_id.clone());
+        let keep_newer = SystemTime::now() + std::time::Duration::from_secs(3600);
+        let mut file = File::open(backend.file_path(&edited_id)).unwrap();
+        let edited_chunks: HashSet<_> = read_chunk_hashes(&mut file)
+            .unwrap()
+            .unwrap()
+            .into_iter()
+            .collect();
+        let stats = backend.gc(&reachable, keep_newer, false).unwrap();
+        assert_eq!(
+            stats.removed_objects,
+            1 + chunk_count + added_chunks - edited_chunks.len()
+        );
+        assert_eq!(count_chunks(&backend), edited_chunks.len());
+        assert_eq!(read_file_contents(&backend, &edited_id), edited);
+    }
+
+    #[test]
+    fn read_unsupported_object_version() {
+        let temp_dir = testutils::new_temp_dir();
+        let backend = LocalBackend::init(temp_dir.path());
+        let file_id = FileId::new(vec![1, 2, 3]);
+        fs::write(backend.file_path(&file_id), b"JJZ\x02").unwrap();
+        assert!(matches!(
+            backend.read_file(&RepoPath::root(), &file_id),
+            Err(BackendError::Other(_))
+        ));
+    }
+}
@@ -13,10 +13,20 @@
 // limitations under the License.
 
 use std::fs::{File, OpenOptions};
+use std::io;
 use std::path::PathBuf;
 use std::time::Duration;
 
 use backoff::{retry, ExponentialBackoff};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum FileLockError {
+    #[error("Timed out waiting for lock file {} held by another process", path.display())]
+    Timeout { path: PathBuf },
+    #[error("Failed to create lock file {}: {err}", path.display())]
+    IoError { path: PathBuf, err: io::Error },
+}
 
 pub struct FileLock {
     path: PathBuf,
@@ -24,7 +34,26 @@ pub struct FileLock {
 }
 
 impl FileLock {
+    /// Takes the lock, waiting for up to 10 seconds for another process to
+    /// release it. Panics if the lock can't be taken.
     pub fn lock(path: PathBuf) -> FileLock {
+        match FileLock::lock_with_timeout(path.clone(), Some(Duration::from_secs(10))) {
+            Err(err) => panic!(
+                "failed to create lock file {}: {}",
+                path.to_string_lossy(),
+                err
+            ),
+            Ok(file_lock) => file_lock,
+        }
+    }
+
+    /// Takes the lock, waiting for up to `timeout` for another process to
+    /// release it, or forever if `timeout` is `None`. A zero timeout makes a
+    /// single attempt.
+    pub fn lock_with_timeout(
+        path: PathBuf,
+        timeout: Option<Duration>,
+    ) -> Result<FileLock, FileLockError> {
         let mut options = OpenOptions::new();
         options.create_new(true);
         options.write(true);
@@ -47,19 +76,24 @@ impl FileLock {
             }
             Err(err) => Err(backoff::Error::Permanent(err)),
         };
-        let backoff = ExponentialBackoff {
-            initial_interval: Duration::from_millis(1),
-            max_elapsed_time: Some(Duration::from_secs(10)),
-            ..Default::default()
+        let result = if timeout == Some(Duration::ZERO) {
+            try_write_lock_file()
+        } else {
+            let backoff = ExponentialBackoff {
+                initial_interval: Duration::from_millis(1),
+                max_interval: Duration::from_secs(1),
+                max_elapsed_time: timeout,
+                ..Default::default()
+            };
+            retry(backoff, try_write_lock_file)
         };
-        match retry(backoff, try_write_lock_file) {
-            Err(err) => panic!(
-                "failed to create lock file {}: {}",
-                path.to_string_lossy(),
-                err
-            ),
-            Ok(file_lock) => file_lock,
-        }
+        result.map_err(|err| match err {
+            backoff::Error::Transient { .. } => FileLockError::Timeout { path: path.clone() },
+            backoff::Error::Permanent(err) => FileLockError::IoError {
+                path: path.clone(),
+                err,
+            },
+        })
     }
 }
 
@@ -91,6 +125,17 @@ mod tests {
         assert!(!lock_path.exists());
     }
 
+    #[test]
+    fn lock_with_timeout() {
+        let temp_dir = testutils::new_temp_dir();
+        let lock_path = temp_dir.path().join("test.lock");
+        let _lock = FileLock::lock(lock_path.clone());
+        let result = FileLock::lock_with_timeout(lock_path.clone(), Some(Duration::ZERO));
+        assert!(matches!(result, Err(FileLockError::Timeout { .. })));
+        let result = FileLock::lock_with_timeout(lock_path, Some(Duration::from_millis(10)));
+        assert!(matches!(result, Err(FileLockError::Timeout { .. })));
+    }
+
     #[test]
     fn lock_concurrent() {
         let temp_dir = testutils::new_temp_dir();
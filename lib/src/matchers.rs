@@ -16,7 +16,7 @@
 
 use std::collections::{BTreeSet, HashMap, HashSet};
 
-use crate::repo_path::{RepoPath, RepoPathComponent};
+use crate::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum Visit {
@@ -265,6 +265,47 @@ impl Matcher for IntersectionMatcher<'_> {
     }
 }
 
+/// Returns paths such that every file the matcher matches is one of them or
+/// inside one of them, sorted. Returns `None` if there's no such list, such as
+/// when the matcher matches everything.
+pub fn match_roots(matcher: &dyn Matcher) -> Option<Vec<RepoPath>> {
+    let mut roots = vec![];
+    if !add_match_roots(matcher, &RepoPath::root(), &mut roots) {
+        return None;
+    }
+    roots.sort();
+    roots.dedup();
+    Some(roots)
+}
+
+fn add_match_roots(matcher: &dyn Matcher, dir: &RepoPath, roots: &mut Vec<RepoPath>) -> bool {
+    match matcher.visit(dir) {
+        Visit::AllRecursively => {
+            if dir.is_root() {
+                return false;
+            }
+            roots.push(dir.clone());
+        }
+        Visit::Specific {
+            dirs: VisitDirs::Set(dirs),
+            files: VisitFiles::Set(files),
+        } => {
+            for file in &files {
+                roots.push(dir.join(file));
+            }
+            // A path that's also listed as a file covers the directory too
+            for sub_dir in dirs.difference(&files) {
+                if !add_match_roots(matcher, &dir.join(sub_dir), roots) {
+                    return false;
+                }
+            }
+        }
+        Visit::Specific { .. } => return false,
+        Visit::Nothing => {}
+    }
+    true
+}
+
 /// Keeps track of which subdirectories and files of each directory need to be
 /// visited.
 #[derive(PartialEq, Eq, Debug)]
@@ -683,4 +724,36 @@ mod tests {
             Visit::AllRecursively
         );
     }
+
+    #[test]
+    fn test_match_roots() {
+        assert_eq!(match_roots(&NothingMatcher), Some(vec![]));
+        assert_eq!(match_roots(&EverythingMatcher), None);
+        assert_eq!(match_roots(&PrefixMatcher::new(&[RepoPath::root()])), None);
+
+        let m = FilesMatcher::new(hashset! {
+            RepoPath::from_internal_string("dir1/file"),
+            RepoPath::from_internal_string("dir2/subdir/file"),
+        });
+        assert_eq!(
+            match_roots(&m),
+            Some(vec![
+                RepoPath::from_internal_string("dir1/file"),
+                RepoPath::from_internal_string("dir2/subdir/file"),
+            ])
+        );
+
+        let m = PrefixMatcher::new(&[
+            RepoPath::from_internal_string("foo/bar"),
+            RepoPath::from_internal_string("foo/bar/baz"),
+            RepoPath::from_internal_string("qux"),
+        ]);
+        assert_eq!(
+            match_roots(&m),
+            Some(vec![
+                RepoPath::from_internal_string("foo/bar"),
+                RepoPath::from_internal_string("qux"),
+            ])
+        );
+    }
 }
@@ -0,0 +1,172 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Merge drivers resolve the conflicts in the contents of files of types that
+//! are known to be mergeable in some other way than line by line, e.g. by
+//! keeping the lines from both sides of changelogs.
+
+use std::fs;
+use std::process::Command;
+use std::sync::Arc;
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::files::MergeHunk;
+use crate::gitignore::GitIgnoreFile;
+use crate::repo_path::RepoPath;
+
+#[derive(Debug, Error)]
+pub enum MergeDriverError {
+    #[error("Failed to run merge driver {command}: {err}")]
+    ExecuteError {
+        command: String,
+        err: std::io::Error,
+    },
+    #[error("Failed to pass the files to merge to the merge driver: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// How to resolve the conflicts in a file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MergeDriver {
+    /// Keeps the lines from all sides in each conflicting region, in the order
+    /// of the sides, like Git's `union` driver.
+    Union,
+    /// Keeps the first side's version of the file. When rebasing, that's the
+    /// version from the destination.
+    Ours,
+    /// Keeps the last side's version of the file. When rebasing, that's the
+    /// version from the rebased commit.
+    Theirs,
+    /// Runs a program and its arguments. The arguments `$base`, `$left`,
+    /// `$right` and `$output` are replaced by the paths of temporary files with
+    /// the base and the two sides, and of the file to write the result to,
+    /// which initially has the left side's contents. The conflict is left
+    /// unresolved if the program fails. Only used for conflicts between two
+    /// sides.
+    Command(Vec<String>),
+}
+
+impl MergeDriver {
+    /// Resolves the conflict between `adds` and `removes`, where a merge found
+    /// the conflicting regions in `hunks`. Returns `None` if the driver can't
+    /// resolve it.
+    pub fn merge(
+        &self,
+        removes: &[&[u8]],
+        adds: &[&[u8]],
+        hunks: &[MergeHunk],
+    ) -> Result<Option<Vec<u8>>, MergeDriverError> {
+        match self {
+            MergeDriver::Union => {
+                let mut content = vec![];
+                for hunk in hunks {
+                    match hunk {
+                        MergeHunk::Resolved(resolved) => content.extend_from_slice(resolved),
+                        MergeHunk::Conflict { adds, .. } => {
+                            for add in adds {
+                                content.extend_from_slice(add);
+                            }
+                        }
+                    }
+                }
+                Ok(Some(content))
+            }
+            MergeDriver::Ours => Ok(adds.first().map(|add| add.to_vec())),
+            MergeDriver::Theirs => Ok(adds.last().map(|add| add.to_vec())),
+            MergeDriver::Command(command) => {
+                if removes.len() != 1 || adds.len() != 2 {
+                    return Ok(None);
+                }
+                run_merge_command(command, removes[0], adds[0], adds[1])
+            }
+        }
+    }
+}
+
+fn run_merge_command(
+    command: &[String],
+    base: &[u8],
+    left: &[u8],
+    right: &[u8],
+) -> Result<Option<Vec<u8>>, MergeDriverError> {
+    let temp_dir = tempfile::Builder::new()
+        .prefix("jj-merge-driver-")
+        .tempdir()?;
+    let base_path = temp_dir.path().join("base");
+    let left_path = temp_dir.path().join("left");
+    let right_path = temp_dir.path().join("right");
+    let output_path = temp_dir.path().join("output");
+    fs::write(&base_path, base)?;
+    fs::write(&left_path, left)?;
+    fs::write(&right_path, right)?;
+    fs::write(&output_path, left)?;
+    let args = command[1..]
+        .iter()
+        .map(|arg| match arg.as_str() {
+            "$base" => base_path.as_os_str().to_owned(),
+            "$left" => left_path.as_os_str().to_owned(),
+            "$right" => right_path.as_os_str().to_owned(),
+            "$output" => output_path.as_os_str().to_owned(),
+            _ => arg.into(),
+        })
+        .collect_vec();
+    let status = Command::new(&command[0])
+        .args(args)
+        .status()
+        .map_err(|err| MergeDriverError::ExecuteError {
+            command: command[0].clone(),
+            err,
+        })?;
+    if !status.success() {
+        return Ok(None);
+    }
+    Ok(Some(fs::read(&output_path)?))
+}
+
+/// Which merge driver, if any, resolves the conflicts in each file.
+#[derive(Debug, Clone, Default)]
+pub struct MergeDrivers {
+    // The last driver whose patterns match a file is used
+    drivers: Vec<(Arc<GitIgnoreFile>, MergeDriver)>,
+}
+
+impl MergeDrivers {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolves the conflicts in the files matching `patterns`, which use the
+    /// `.gitignore` syntax, with `driver`.
+    pub fn add_patterns(&mut self, patterns: &[String], driver: MergeDriver) {
+        if !patterns.is_empty() {
+            let patterns = GitIgnoreFile::empty().chain("", patterns.join("\n").as_bytes());
+            self.drivers.push((patterns, driver));
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.drivers.is_empty()
+    }
+
+    pub fn for_path(&self, path: &RepoPath) -> Option<&MergeDriver> {
+        let path = path.to_internal_file_string();
+        self.drivers
+            .iter()
+            .rev()
+            .find(|(patterns, _)| patterns.matches_file(&path))
+            .map(|(_, driver)| driver)
+    }
+}
@@ -0,0 +1,366 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Migration of a repo to a different commit backend.
+//!
+//! Commit, tree and other object ids depend on the backend, so all objects are
+//! copied to a new store and the operation log is rewritten to refer to the
+//! new ids. Change ids and the shape of the operation log are preserved.
+
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::backend::{
+    Backend, BackendError, CommitId, ConflictId, FileId, SymlinkId, Tree, TreeId, TreeValue,
+};
+use crate::op_store::{
+    BranchTarget, OpStore, OpStoreError, Operation, OperationId, RefTarget, View,
+};
+use crate::repo::ReadonlyRepo;
+use crate::repo_path::{RepoPath, RepoPathJoin};
+use crate::simple_op_store::SimpleOpStore;
+use crate::store::Store;
+
+#[derive(Debug, Error)]
+pub enum MigrateError {
+    #[error(transparent)]
+    BackendError(#[from] BackendError),
+    #[error(transparent)]
+    OpStoreError(#[from] OpStoreError),
+    #[error("Failed to update the repo directory: {0}")]
+    IoError(#[from] std::io::Error),
+}
+
+/// The mapping from old to new ids after a migration.
+#[derive(Debug, Default)]
+pub struct Migration {
+    pub commit_ids: HashMap<CommitId, CommitId>,
+    pub tree_ids: HashMap<TreeId, TreeId>,
+    pub conflict_ids: HashMap<ConflictId, ConflictId>,
+    pub operation_ids: HashMap<OperationId, OperationId>,
+}
+
+/// Converts the repo to the backend created by `backend_factory`, replacing
+/// its store and operation log. The index is rebuilt the next time the repo is
+/// loaded. Working copies record tree and operation ids, so the caller needs to
+/// update them using the returned mapping.
+pub fn migrate_repo(
+    repo: &ReadonlyRepo,
+    backend_factory: impl FnOnce(&Path) -> Box<dyn Backend>,
+) -> Result<Migration, MigrateError> {
+    // Keep other processes from adding operations while we rewrite the log
+    let _lock = repo.op_heads_store().lock();
+    let repo_path = repo.repo_path();
+    let new_store_path = repo_path.join("store.new");
+    let new_op_store_path = repo_path.join("op_store.new");
+    for path in [&new_store_path, &new_op_store_path] {
+        // Left over from an interrupted migration
+        if path.exists() {
+            fs::remove_dir_all(path)?;
+        }
+    }
+    fs::create_dir(&new_store_path)?;
+    fs::create_dir(&new_op_store_path)?;
+
+    let result = copy_repo(repo, &new_store_path, &new_op_store_path, backend_factory);
+    let (migration, new_op_heads) = match result {
+        Ok(result) => result,
+        Err(err) => {
+            fs::remove_dir_all(&new_store_path).ok();
+            fs::remove_dir_all(&new_op_store_path).ok();
+            return Err(err);
+        }
+    };
+
+    let old_store_path = repo_path.join("store.old");
+    let old_op_store_path = repo_path.join("op_store.old");
+    fs::rename(repo_path.join("store"), &old_store_path)?;
+    fs::rename(&new_store_path, repo_path.join("store"))?;
+    fs::rename(repo_path.join("op_store"), &old_op_store_path)?;
+    fs::rename(&new_op_store_path, repo_path.join("op_store"))?;
+    repo.op_heads_store().set_op_heads(&new_op_heads);
+    repo.index_store().reinit();
+    fs::remove_dir_all(old_store_path)?;
+    fs::remove_dir_all(old_op_store_path)?;
+    Ok(migration)
+}
+
+fn copy_repo(
+    repo: &ReadonlyRepo,
+    new_store_path: &Path,
+    new_op_store_path: &Path,
+    backend_factory: impl FnOnce(&Path) -> Box<dyn Backend>,
+) -> Result<(Migration, Vec<OperationId>), MigrateError> {
+    let backend = backend_factory(new_store_path);
+    fs::write(new_store_path.join("backend"), backend.name())?;
+    let mut migrator = Migrator {
+        old_store: repo.store().clone(),
+        new_store: Store::new(backend),
+        migration: Migration::default(),
+        file_ids: HashMap::new(),
+        symlink_ids: HashMap::new(),
+        new_commit_ids: HashSet::new(),
+    };
+    migrator.migration.commit_ids.insert(
+        migrator.old_store.root_commit_id().clone(),
+        migrator.new_store.root_commit_id().clone(),
+    );
+
+    let old_op_store = repo.op_store();
+    let new_op_store = SimpleOpStore::init(new_op_store_path.to_path_buf());
+    let old_op_heads = repo.op_heads_store().get_op_heads();
+    let mut new_op_heads = vec![];
+    for op_head in &old_op_heads {
+        new_op_heads.push(migrator.copy_operation(
+            old_op_store.as_ref(),
+            &new_op_store,
+            op_head,
+        )?);
+    }
+    Ok((migrator.migration, new_op_heads))
+}
+
+struct Migrator {
+    old_store: Arc<Store>,
+    new_store: Arc<Store>,
+    migration: Migration,
+    file_ids: HashMap<FileId, FileId>,
+    symlink_ids: HashMap<SymlinkId, SymlinkId>,
+    new_commit_ids: HashSet<CommitId>,
+}
+
+impl Migrator {
+    /// Copies the operation and its ancestors, parents first.
+    fn copy_operation(
+        &mut self,
+        old_op_store: &dyn OpStore,
+        new_op_store: &dyn OpStore,
+        op_id: &OperationId,
+    ) -> Result<OperationId, MigrateError> {
+        let mut stack = vec![(op_id.clone(), false)];
+        while let Some((op_id, parents_copied)) = stack.pop() {
+            if self.migration.operation_ids.contains_key(&op_id) {
+                continue;
+            }
+            let operation = old_op_store.read_operation(&op_id)?;
+            if !parents_copied {
+                stack.push((op_id, true));
+                for parent_id in &operation.parents {
+                    stack.push((parent_id.clone(), false));
+                }
+                continue;
+            }
+            let view = old_op_store.read_view(&operation.view_id)?;
+            let new_view = self.copy_view(&view)?;
+            let new_operation = Operation {
+                view_id: new_op_store.write_view(&new_view)?,
+                parents: operation
+                    .parents
+                    .iter()
+                    .map(|parent_id| self.migration.operation_ids[parent_id].clone())
+                    .collect(),
+                metadata: operation.metadata,
+            };
+            let new_op_id = new_op_store.write_operation(&new_operation)?;
+            self.migration.operation_ids.insert(op_id, new_op_id);
+        }
+        Ok(self.migration.operation_ids[op_id].clone())
+    }
+
+    fn copy_view(&mut self, view: &View) -> Result<View, MigrateError> {
+        let mut copy_ids = |ids: &HashSet<CommitId>| -> Result<HashSet<CommitId>, MigrateError> {
+            ids.iter().map(|id| self.copy_commit(id)).collect()
+        };
+        let head_ids = copy_ids(&view.head_ids)?;
+        let public_head_ids = copy_ids(&view.public_head_ids)?;
+        let mut branches = view.branches.clone();
+        for branch_target in branches.values_mut() {
+            let BranchTarget {
+                local_target,
+                remote_targets,
+                untracked_remotes: _,
+            } = branch_target;
+            if let Some(local_target) = local_target {
+                *local_target = self.copy_ref_target(local_target)?;
+            }
+            for remote_target in remote_targets.values_mut() {
+                *remote_target = self.copy_ref_target(remote_target)?;
+            }
+        }
+        let mut tags = view.tags.clone();
+        for target in tags.values_mut() {
+            *target = self.copy_ref_target(target)?;
+        }
+        let mut git_refs = view.git_refs.clone();
+        for target in git_refs.values_mut() {
+            *target = self.copy_ref_target(target)?;
+        }
+        let git_head = view
+            .git_head
+            .as_ref()
+            .map(|id| self.copy_commit(id))
+            .transpose()?;
+        let mut wc_commit_ids = view.wc_commit_ids.clone();
+        for id in wc_commit_ids.values_mut() {
+            *id = self.copy_commit(id)?;
+        }
+        Ok(View {
+            head_ids,
+            public_head_ids,
+            branches,
+            tags,
+            git_refs,
+            git_head,
+            wc_commit_ids,
+        })
+    }
+
+    fn copy_ref_target(&mut self, target: &RefTarget) -> Result<RefTarget, MigrateError> {
+        match target {
+            RefTarget::Normal(id) => Ok(RefTarget::Normal(self.copy_commit(id)?)),
+            RefTarget::Conflict { removes, adds } => Ok(RefTarget::Conflict {
+                removes: removes
+                    .iter()
+                    .map(|id| self.copy_commit(id))
+                    .collect::<Result<_, _>>()?,
+                adds: adds
+                    .iter()
+                    .map(|id| self.copy_commit(id))
+                    .collect::<Result<_, _>>()?,
+            }),
+        }
+    }
+
+    /// Copies the commit and its ancestors and predecessors, parents first.
+    fn copy_commit(&mut self, id: &CommitId) -> Result<CommitId, MigrateError> {
+        let mut stack = vec![(id.clone(), false)];
+        while let Some((commit_id, deps_copied)) = stack.pop() {
+            if self.migration.commit_ids.contains_key(&commit_id) {
+                continue;
+            }
+            let commit = self.old_store.get_commit(&commit_id)?;
+            if !deps_copied {
+                stack.push((commit_id, true));
+                for dep_id in commit.parent_ids().iter().chain(commit.predecessor_ids()) {
+                    stack.push((dep_id.clone(), false));
+                }
+                continue;
+            }
+            let mut new_commit = commit.store_commit().clone();
+            new_commit.parents = new_commit
+                .parents
+                .iter()
+                .map(|id| self.migration.commit_ids[id].clone())
+                .collect();
+            new_commit.predecessors = new_commit
+                .predecessors
+                .iter()
+                .map(|id| self.migration.commit_ids[id].clone())
+                .collect();
+            new_commit.root_tree = self.copy_tree(&RepoPath::root(), commit.tree_id())?;
+            // The signature covered the old serialization, so it's no longer valid
+            new_commit.secure_sig = None;
+            let new_commit_id = loop {
+                // The Git backend doesn't store the change id in the commit and only
+                // has timestamps with second precision, so different commits can end
+                // up with the same id. Make them distinct like they were before. The
+                // backend refuses to write such a commit if the change id differs.
+                match self.new_store.write_commit(new_commit.clone(), None) {
+                    Ok(written) if self.new_commit_ids.insert(written.id().clone()) => {
+                        break written.id().clone();
+                    }
+                    Ok(_) => {}
+                    Err(BackendError::Other(message))
+                        if message.contains("already exists with different") => {}
+                    Err(err) => return Err(err.into()),
+                }
+                new_commit.committer.timestamp.timestamp.0 += 1000;
+            };
+            self.migration.commit_ids.insert(commit_id, new_commit_id);
+        }
+        Ok(self.migration.commit_ids[id].clone())
+    }
+
+    fn copy_tree(&mut self, dir: &RepoPath, id: &TreeId) -> Result<TreeId, MigrateError> {
+        if let Some(new_id) = self.migration.tree_ids.get(id) {
+            return Ok(new_id.clone());
+        }
+        let tree = self.old_store.get_tree(dir, id)?;
+        let mut new_tree = Tree::default();
+        for entry in tree.data().entries() {
+            let path = dir.join(entry.name());
+            let new_value = self.copy_value(&path, entry.value())?;
+            new_tree.set(entry.name().clone(), new_value);
+        }
+        let new_id = self.new_store.write_tree(dir, &new_tree)?;
+        self.migration.tree_ids.insert(id.clone(), new_id.clone());
+        Ok(new_id)
+    }
+
+    fn copy_value(
+        &mut self,
+        path: &RepoPath,
+        value: &TreeValue,
+    ) -> Result<TreeValue, MigrateError> {
+        match value {
+            TreeValue::Normal { id, executable } => {
+                let new_id = match self.file_ids.get(id) {
+                    Some(new_id) => new_id.clone(),
+                    None => {
+                        let mut contents = self.old_store.read_file(path, id)?;
+                        let new_id = self.new_store.write_file(path, &mut contents)?;
+                        self.file_ids.insert(id.clone(), new_id.clone());
+                        new_id
+                    }
+                };
+                Ok(TreeValue::Normal {
+                    id: new_id,
+                    executable: *executable,
+                })
+            }
+            TreeValue::Symlink(id) => {
+                let new_id = match self.symlink_ids.get(id) {
+                    Some(new_id) => new_id.clone(),
+                    None => {
+                        let target = self.old_store.read_symlink(path, id)?;
+                        let new_id = self.new_store.write_symlink(path, &target)?;
+                        self.symlink_ids.insert(id.clone(), new_id.clone());
+                        new_id
+                    }
+                };
+                Ok(TreeValue::Symlink(new_id))
+            }
+            TreeValue::Tree(id) => Ok(TreeValue::Tree(self.copy_tree(path, id)?)),
+            TreeValue::GitSubmodule(id) => Ok(TreeValue::GitSubmodule(id.clone())),
+            TreeValue::Conflict(id) => {
+                if let Some(new_id) = self.migration.conflict_ids.get(id) {
+                    return Ok(TreeValue::Conflict(new_id.clone()));
+                }
+                let mut conflict = self.old_store.read_conflict(path, id)?;
+                for part in conflict.removes.iter_mut().chain(conflict.adds.iter_mut()) {
+                    part.value = self.copy_value(path, &part.value)?;
+                }
+                let new_id = self.new_store.write_conflict(path, &conflict)?;
+                self.migration
+                    .conflict_ids
+                    .insert(id.clone(), new_id.clone());
+                Ok(TreeValue::Conflict(new_id))
+            }
+        }
+    }
+}
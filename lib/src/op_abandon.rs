@@ -0,0 +1,182 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Abandoning operations to shorten the operation log.
+//!
+//! An operation id depends on the ids of its parents, so the descendants of
+//! abandoned operations are rewritten with new ids. The abandoned operations
+//! are left in the operation store, but they're no longer reachable from the
+//! operation heads.
+
+use std::collections::{HashMap, HashSet};
+
+use itertools::Itertools;
+use thiserror::Error;
+
+use crate::backend::MillisSinceEpoch;
+use crate::op_store::{OpStore, OpStoreError, OperationId};
+use crate::repo::ReadonlyRepo;
+
+#[derive(Debug, Error)]
+pub enum OpAbandonError {
+    #[error("Cannot abandon the root operation")]
+    RootOperation,
+    #[error("Cannot abandon the current operation {}", .0.hex())]
+    HeadOperation(OperationId),
+    #[error(transparent)]
+    OpStoreError(#[from] OpStoreError),
+}
+
+/// The result of abandoning operations.
+#[derive(Debug, Default)]
+pub struct OpAbandonment {
+    /// The number of operations that were abandoned.
+    pub abandoned_count: usize,
+    /// The new ids of the operations that were rewritten because they descend
+    /// from an abandoned operation.
+    pub rewritten_ids: HashMap<OperationId, OperationId>,
+}
+
+/// Abandons the given operations. The children of an abandoned operation
+/// become children of its closest ancestors that are kept. The root operation
+/// and the current operation heads can't be abandoned.
+///
+/// Working copies record the operation they were updated at, so the caller
+/// needs to update them using the returned mapping.
+pub fn abandon_operations(
+    repo: &ReadonlyRepo,
+    abandoned_ids: &HashSet<OperationId>,
+) -> Result<OpAbandonment, OpAbandonError> {
+    // Keep other processes from adding operations while we rewrite the log
+    let _lock = repo.op_heads_store().lock();
+    let op_store = repo.op_store().as_ref();
+    let op_heads = repo.op_heads_store().get_op_heads();
+    if let Some(head_id) = op_heads.iter().find(|id| abandoned_ids.contains(id)) {
+        return Err(OpAbandonError::HeadOperation(head_id.clone()));
+    }
+
+    let mut abandonment = OpAbandonment::default();
+    // What each operation was replaced by: its new id if it's kept, or the
+    // closest kept ancestors if it's abandoned
+    let mut replacements: HashMap<OperationId, Vec<OperationId>> = HashMap::new();
+    for op_head in &op_heads {
+        rewrite_operation(
+            op_store,
+            abandoned_ids,
+            op_head,
+            &mut replacements,
+            &mut abandonment,
+        )?;
+    }
+    let new_op_heads = op_heads
+        .iter()
+        .map(|op_head| replacements[op_head][0].clone())
+        .collect_vec();
+    // The rewritten operations have the same views, so they can share the
+    // index of the original operations. If that fails, the index is rebuilt
+    // when it's loaded.
+    for (old_op_id, new_op_id) in &abandonment.rewritten_ids {
+        repo.index_store()
+            .copy_operation_link(old_op_id, new_op_id)
+            .ok();
+    }
+    if new_op_heads != op_heads {
+        repo.op_heads_store().set_op_heads(&new_op_heads);
+    }
+    Ok(abandonment)
+}
+
+/// Rewrites the operation and its ancestors, parents first.
+fn rewrite_operation(
+    op_store: &dyn OpStore,
+    abandoned_ids: &HashSet<OperationId>,
+    op_id: &OperationId,
+    replacements: &mut HashMap<OperationId, Vec<OperationId>>,
+    abandonment: &mut OpAbandonment,
+) -> Result<(), OpAbandonError> {
+    let mut stack = vec![(op_id.clone(), false)];
+    while let Some((op_id, parents_rewritten)) = stack.pop() {
+        if replacements.contains_key(&op_id) {
+            continue;
+        }
+        let mut operation = op_store.read_operation(&op_id)?;
+        if !parents_rewritten {
+            stack.push((op_id, true));
+            for parent_id in &operation.parents {
+                stack.push((parent_id.clone(), false));
+            }
+            continue;
+        }
+        let new_parents = operation
+            .parents
+            .iter()
+            .flat_map(|parent_id| replacements[parent_id].iter().cloned())
+            .unique()
+            .collect_vec();
+        if abandoned_ids.contains(&op_id) {
+            if operation.parents.is_empty() {
+                return Err(OpAbandonError::RootOperation);
+            }
+            abandonment.abandoned_count += 1;
+            replacements.insert(op_id, new_parents);
+        } else if new_parents == operation.parents {
+            replacements.insert(op_id.clone(), vec![op_id]);
+        } else {
+            operation.parents = new_parents;
+            let new_op_id = op_store.write_operation(&operation)?;
+            abandonment
+                .rewritten_ids
+                .insert(op_id.clone(), new_op_id.clone());
+            replacements.insert(op_id, vec![new_op_id]);
+        }
+    }
+    Ok(())
+}
+
+/// Finds the operations to abandon to expire the ones that ended before
+/// `cutoff`: those operations and all their ancestors, except for the root
+/// operation and the current operation heads.
+pub fn find_expired_operations(
+    repo: &ReadonlyRepo,
+    cutoff: MillisSinceEpoch,
+) -> Result<HashSet<OperationId>, OpStoreError> {
+    let op_store = repo.op_store();
+    let op_heads = repo.op_heads_store().get_op_heads();
+    let mut visited = HashSet::new();
+    let mut expired = HashSet::new();
+    // The second element says whether a descendant already expired
+    let mut work = op_heads.iter().map(|id| (id.clone(), false)).collect_vec();
+    while let Some((op_id, descendant_expired)) = work.pop() {
+        // An operation can be reached first through a descendant that didn't
+        // expire and later through one that did
+        if !visited.insert((op_id.clone(), descendant_expired)) {
+            continue;
+        }
+        let operation = op_store.read_operation(&op_id)?;
+        if operation.parents.is_empty() {
+            continue;
+        }
+        let is_expired = descendant_expired || operation.metadata.end_time.timestamp.0 < cutoff.0;
+        if is_expired && !op_heads.contains(&op_id) {
+            expired.insert(op_id.clone());
+        }
+        work.extend(
+            operation
+                .parents
+                .into_iter()
+                .map(|parent_id| (parent_id, is_expired)),
+        );
+    }
+    Ok(expired)
+}
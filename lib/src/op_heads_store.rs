@@ -15,12 +15,13 @@
 use std::collections::HashSet;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
 use itertools::Itertools;
 use thiserror::Error;
 
 use crate::backend::Timestamp;
-use crate::lock::FileLock;
+use crate::lock::{FileLock, FileLockError};
 use crate::op_store::{OpStore, OperationId, OperationMetadata};
 use crate::operation::Operation;
 use crate::{dag_walk, op_store};
@@ -101,6 +102,17 @@ impl OpHeadsStore {
         std::fs::remove_file(self.dir.join(id.hex())).ok();
     }
 
+    /// Replaces all the current op heads. This is only meant for when the whole
+    /// operation log has been rewritten, such as by a backend migration.
+    pub fn set_op_heads(&self, op_ids: &[OperationId]) {
+        for old_id in self.get_op_heads() {
+            self.remove_op_head(&old_id);
+        }
+        for id in op_ids {
+            self.add_op_head(id);
+        }
+    }
+
     pub fn get_op_heads(&self) -> Vec<OperationId> {
         let mut op_heads = vec![];
         for op_head_entry in std::fs::read_dir(&self.dir).unwrap() {
@@ -121,6 +133,20 @@ impl OpHeadsStore {
         }
     }
 
+    /// Like `lock()`, but waits for up to `timeout` for another process to
+    /// release the lock (or forever if `timeout` is `None`) and returns an
+    /// error instead of panicking if it doesn't.
+    pub fn lock_with_timeout(
+        self: &Arc<Self>,
+        timeout: Option<Duration>,
+    ) -> Result<LockedOpHeads, FileLockError> {
+        let lock = FileLock::lock_with_timeout(self.dir.join("lock"), timeout)?;
+        Ok(LockedOpHeads {
+            store: self.clone(),
+            _lock: lock,
+        })
+    }
+
     pub fn get_heads(
         self: &Arc<Self>,
         op_store: &Arc<dyn OpStore>,
@@ -0,0 +1,238 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Summaries of what an operation changed in the view, and the hook command
+//! that gets them when an operation is committed, so tools like build systems
+//! and IDEs can react to changes to the repo.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, ExitStatus, Stdio};
+
+use itertools::Itertools;
+use serde_json::json;
+use thiserror::Error;
+
+use crate::backend::CommitId;
+use crate::op_store::{OperationId, RefTarget, WorkspaceId};
+use crate::operation::Operation;
+use crate::view::{RefName, View};
+
+#[derive(Debug, Error)]
+pub enum OpHookError {
+    #[error("Failed to run the post-operation hook: {0}")]
+    ExecuteError(#[from] std::io::Error),
+    #[error("The post-operation hook failed ({0})")]
+    HookFailed(ExitStatus),
+}
+
+/// A ref that an operation added, removed or moved. Refs mirroring the
+/// underlying Git repo aren't included.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefChange {
+    pub name: RefName,
+    pub old_target: Option<RefTarget>,
+    pub new_target: Option<RefTarget>,
+}
+
+/// What an operation changed in the view, compared to the view it started
+/// from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OperationSummary {
+    pub operation_id: OperationId,
+    pub parent_ids: Vec<OperationId>,
+    pub description: String,
+    pub added_heads: Vec<CommitId>,
+    pub removed_heads: Vec<CommitId>,
+    /// The new working-copy commit of each workspace whose working-copy
+    /// commit changed, or `None` if the workspace was removed.
+    pub wc_commit_ids: BTreeMap<WorkspaceId, Option<CommitId>>,
+    pub ref_changes: Vec<RefChange>,
+}
+
+impl OperationSummary {
+    /// Summarizes the changes from `old_view` to `new_view`, the views before
+    /// and after `operation`.
+    pub fn new(operation: &Operation, old_view: &View, new_view: &View) -> Self {
+        let added_heads = new_view
+            .heads()
+            .difference(old_view.heads())
+            .cloned()
+            .sorted()
+            .collect_vec();
+        let removed_heads = old_view
+            .heads()
+            .difference(new_view.heads())
+            .cloned()
+            .sorted()
+            .collect_vec();
+
+        let mut wc_commit_ids = BTreeMap::new();
+        for (workspace_id, commit_id) in new_view.wc_commit_ids() {
+            if old_view.get_wc_commit_id(workspace_id) != Some(commit_id) {
+                wc_commit_ids.insert(workspace_id.clone(), Some(commit_id.clone()));
+            }
+        }
+        for workspace_id in old_view.wc_commit_ids().keys() {
+            if new_view.get_wc_commit_id(workspace_id).is_none() {
+                wc_commit_ids.insert(workspace_id.clone(), None);
+            }
+        }
+
+        let mut ref_names = BTreeSet::new();
+        for view in [old_view, new_view] {
+            for (branch_name, branch_target) in view.branches() {
+                ref_names.insert(RefName::LocalBranch(branch_name.clone()));
+                for remote_name in branch_target.remote_targets.keys() {
+                    ref_names.insert(RefName::RemoteBranch {
+                        branch: branch_name.clone(),
+                        remote: remote_name.clone(),
+                    });
+                }
+            }
+            for tag_name in view.tags().keys() {
+                ref_names.insert(RefName::Tag(tag_name.clone()));
+            }
+        }
+        let ref_changes = ref_names
+            .into_iter()
+            .filter_map(|name| {
+                let old_target = old_view.get_ref(&name);
+                let new_target = new_view.get_ref(&name);
+                (old_target != new_target).then(|| RefChange {
+                    name,
+                    old_target,
+                    new_target,
+                })
+            })
+            .collect_vec();
+
+        OperationSummary {
+            operation_id: operation.id().clone(),
+            parent_ids: operation.parent_ids().clone(),
+            description: operation.store_operation().metadata.description.clone(),
+            added_heads,
+            removed_heads,
+            wc_commit_ids,
+            ref_changes,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added_heads.is_empty()
+            && self.removed_heads.is_empty()
+            && self.wc_commit_ids.is_empty()
+            && self.ref_changes.is_empty()
+    }
+
+    /// The summary as JSON, which is what the hook command gets on stdin.
+    pub fn to_json(&self) -> serde_json::Value {
+        let hexes = |ids: &[CommitId]| ids.iter().map(CommitId::hex).collect_vec();
+        let wc_commits: serde_json::Map<_, _> = self
+            .wc_commit_ids
+            .iter()
+            .map(|(workspace_id, commit_id)| {
+                (
+                    workspace_id.as_str().to_string(),
+                    json!(commit_id.as_ref().map(CommitId::hex)),
+                )
+            })
+            .collect();
+        let refs = self
+            .ref_changes
+            .iter()
+            .map(|change| {
+                let (kind, name, remote) = match &change.name {
+                    RefName::LocalBranch(branch) => ("branch", branch, None),
+                    RefName::RemoteBranch { branch, remote } => ("branch", branch, Some(remote)),
+                    RefName::Tag(tag) => ("tag", tag, None),
+                    RefName::GitRef(git_ref) => ("git_ref", git_ref, None),
+                };
+                json!({
+                    "kind": kind,
+                    "name": name,
+                    "remote": remote,
+                    "old": ref_target_to_json(change.old_target.as_ref()),
+                    "new": ref_target_to_json(change.new_target.as_ref()),
+                })
+            })
+            .collect_vec();
+        json!({
+            "operation": {
+                "id": self.operation_id.hex(),
+                "parents": self.parent_ids.iter().map(OperationId::hex).collect_vec(),
+                "description": self.description,
+            },
+            "added_heads": hexes(&self.added_heads),
+            "removed_heads": hexes(&self.removed_heads),
+            "working_copies": wc_commits,
+            "refs": refs,
+        })
+    }
+}
+
+/// A normal target is its commit id, a conflicted one lists the commits it
+/// adds and removes, and a missing one is null.
+fn ref_target_to_json(target: Option<&RefTarget>) -> serde_json::Value {
+    match target {
+        None => serde_json::Value::Null,
+        Some(RefTarget::Normal(id)) => json!(id.hex()),
+        Some(RefTarget::Conflict { removes, adds }) => json!({
+            "removes": removes.iter().map(CommitId::hex).collect_vec(),
+            "adds": adds.iter().map(CommitId::hex).collect_vec(),
+        }),
+    }
+}
+
+/// A command to run after each operation is committed. It gets the
+/// operation's summary as JSON on stdin. The operation has already been
+/// committed by then, so the hook can't reject it.
+#[derive(Debug, Clone)]
+pub struct PostOperationHook {
+    command: Vec<String>,
+    work_dir: PathBuf,
+}
+
+impl PostOperationHook {
+    /// Returns the hook running `command` (a program and its arguments) in
+    /// `work_dir`.
+    pub fn new(command: Vec<String>, work_dir: &Path) -> Self {
+        assert!(!command.is_empty());
+        PostOperationHook {
+            command,
+            work_dir: work_dir.to_owned(),
+        }
+    }
+
+    pub fn run(&self, summary: &OperationSummary) -> Result<(), OpHookError> {
+        let mut child = Command::new(&self.command[0])
+            .args(&self.command[1..])
+            .current_dir(&self.work_dir)
+            .env("JJ_OPERATION_ID", summary.operation_id.hex())
+            .stdin(Stdio::piped())
+            .spawn()?;
+        // The hook may exit without reading its input
+        let _ = child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(summary.to_json().to_string().as_bytes());
+        let status = child.wait()?;
+        if !status.success() {
+            return Err(OpHookError::HookFailed(status));
+        }
+        Ok(())
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{BTreeMap, HashMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Error, Formatter};
 
 use thiserror::Error;
@@ -154,6 +154,9 @@ pub struct BranchTarget {
     // whether the branch is known to have existed on the remote. We may not want to resurrect
     // the branch if the branch's state on the remote was just not known.
     pub remote_targets: BTreeMap<String, RefTarget>,
+    /// Remotes whose branch of this name is not tracked. Changes to an
+    /// untracked remote branch are not merged into the local branch on fetch.
+    pub untracked_remotes: BTreeSet<String>,
 }
 
 /// Represents the way the repo looks at a given time, just like how a Tree
@@ -170,6 +170,7 @@ mod tests {
             remote_targets: btreemap! {
                 "origin".to_string() => RefTarget::Normal(commit_id1)
             },
+            untracked_remotes: Default::default(),
         };
         assert_eq!(
             classify_branch_push_action(&branch, "origin"),
@@ -183,6 +184,7 @@ mod tests {
         let branch = BranchTarget {
             local_target: Some(RefTarget::Normal(commit_id1.clone())),
             remote_targets: btreemap! {},
+            untracked_remotes: Default::default(),
         };
         assert_eq!(
             classify_branch_push_action(&branch, "origin"),
@@ -201,6 +203,7 @@ mod tests {
             remote_targets: btreemap! {
                 "origin".to_string() => RefTarget::Normal(commit_id1.clone())
             },
+            untracked_remotes: Default::default(),
         };
         assert_eq!(
             classify_branch_push_action(&branch, "origin"),
@@ -220,6 +223,7 @@ mod tests {
             remote_targets: btreemap! {
                 "origin".to_string() => RefTarget::Normal(commit_id1.clone())
             },
+            untracked_remotes: Default::default(),
         };
         assert_eq!(
             classify_branch_push_action(&branch, "origin"),
@@ -242,6 +246,7 @@ mod tests {
             remote_targets: btreemap! {
                 "origin".to_string() => RefTarget::Normal(commit_id1)
             },
+            untracked_remotes: Default::default(),
         };
         assert_eq!(
             classify_branch_push_action(&branch, "origin"),
@@ -261,6 +266,7 @@ mod tests {
                 adds: vec![commit_id1, commit_id2]
             }
             },
+            untracked_remotes: Default::default(),
         };
         assert_eq!(
             classify_branch_push_action(&branch, "origin"),
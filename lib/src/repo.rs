@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::cell::RefCell;
-use std::collections::{HashMap, HashSet};
+use std::collections::{BTreeSet, HashMap, HashSet};
 use std::fmt::{Debug, Formatter};
 use std::fs;
 use std::io::ErrorKind;
@@ -24,11 +24,13 @@ use std::sync::{Arc, Mutex};
 use itertools::Itertools;
 use thiserror::Error;
 
-use crate::backend::{Backend, BackendError, ChangeId, CommitId};
+use crate::backend::{Backend, BackendError, BackendResult, ChangeId, CommitId, SigningFn};
 use crate::commit::Commit;
 use crate::commit_builder::CommitBuilder;
 use crate::dag_walk::topo_order_reverse;
 use crate::git_backend::GitBackend;
+#[cfg(feature = "gitoxide")]
+use crate::gitoxide_backend::GitoxideBackend;
 use crate::index::{IndexRef, MutableIndex, ReadonlyIndex};
 use crate::index_store::IndexStore;
 use crate::local_backend::LocalBackend;
@@ -56,6 +58,9 @@ impl From<BackendError> for RepoError {
         match err {
             BackendError::NotFound => RepoError::NotFound,
             BackendError::Other(description) => RepoError::Other(description),
+            err @ (BackendError::Corrupt(_) | BackendError::SigningFailed(_)) => {
+                RepoError::Other(err.to_string())
+            }
         }
     }
 }
@@ -116,7 +121,9 @@ pub struct ReadonlyRepo {
     settings: RepoSettings,
     index_store: Arc<IndexStore>,
     index: Mutex<Option<Arc<ReadonlyIndex>>>,
-    view: View,
+    // Loaded on first use, like the index, so commands that don't need the view
+    // don't pay for reading it
+    view: Mutex<Option<View>>,
 }
 
 impl Debug for ReadonlyRepo {
@@ -146,7 +153,10 @@ impl ReadonlyRepo {
         let store_path = repo_path.join("store");
         let backend = backend_factory(&store_path);
         fs::write(&store_path.join("backend"), backend.name()).unwrap();
-        let store = Store::new(backend);
+        let store = Store::with_options(
+            backend,
+            user_settings.store_options(&store_path.join("object_cache")),
+        );
         let repo_settings = user_settings.with_repo(&repo_path).unwrap();
         let op_store: Arc<dyn OpStore> = Arc::new(SimpleOpStore::init(repo_path.join("op_store")));
         let mut root_view = op_store::View::default();
@@ -168,7 +178,7 @@ impl ReadonlyRepo {
             settings: repo_settings,
             index_store,
             index: Mutex::new(None),
-            view,
+            view: Mutex::new(Some(view)),
         })
     }
 
@@ -178,6 +188,7 @@ impl ReadonlyRepo {
         backend_factories: &BackendFactories,
     ) -> Result<Arc<ReadonlyRepo>, BackendError> {
         RepoLoader::init(user_settings, repo_path, backend_factories)
+            .map_err(|err| BackendError::Other(err.to_string()))?
             .load_at_head()
             .resolve(user_settings)
     }
@@ -210,7 +221,15 @@ impl ReadonlyRepo {
     }
 
     pub fn view(&self) -> &View {
-        &self.view
+        let mut locked_view = self.view.lock().unwrap();
+        if locked_view.is_none() {
+            locked_view.replace(View::new(self.operation.view().take_store_view()));
+        }
+        let view: &View = locked_view.as_ref().unwrap();
+        // Extend lifetime from that of mutex lock to that of self. Safe since we never
+        // change value once it's been set.
+        let view: &View = unsafe { std::mem::transmute(view) };
+        view
     }
 
     pub fn index(&self) -> &Arc<ReadonlyIndex> {
@@ -259,7 +278,7 @@ impl ReadonlyRepo {
     }
 
     pub fn start_transaction(self: &Arc<ReadonlyRepo>, description: &str) -> Transaction {
-        let mut_repo = MutableRepo::new(self.clone(), self.index().clone(), &self.view);
+        let mut_repo = MutableRepo::new(self.clone(), self.index().clone(), self.view());
         Transaction::new(mut_repo, description)
     }
 
@@ -289,6 +308,30 @@ impl RepoAtHead {
     }
 }
 
+/// What couldn't be merged cleanly when merging concurrent changes to the
+/// view.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ViewMergeReport {
+    /// Workspaces whose working-copy commit was changed differently on both
+    /// sides. The working-copy commit from the self side was kept.
+    pub conflicting_wc_workspaces: Vec<WorkspaceId>,
+    /// Refs that were changed differently on both sides and are now
+    /// conflicted.
+    pub conflicted_refs: Vec<RefName>,
+}
+
+impl ViewMergeReport {
+    pub fn is_empty(&self) -> bool {
+        self.conflicting_wc_workspaces.is_empty() && self.conflicted_refs.is_empty()
+    }
+
+    pub fn extend(&mut self, other: ViewMergeReport) {
+        self.conflicting_wc_workspaces
+            .extend(other.conflicting_wc_workspaces);
+        self.conflicted_refs.extend(other.conflicted_refs);
+    }
+}
+
 pub struct UnresolvedHeadRepo {
     pub repo_loader: RepoLoader,
     pub locked_op_heads: LockedOpHeads,
@@ -309,8 +352,16 @@ impl UnresolvedHeadRepo {
     }
 }
 
+#[derive(Error, Debug, PartialEq, Eq)]
+pub enum StoreLoadError {
+    #[error("Unsupported commit backend type '{0}'")]
+    UnsupportedType(String),
+}
+
 type BackendFactory = Box<dyn Fn(&Path) -> Box<dyn Backend>>;
 
+/// The backends that can be used to load a repo, by name (see
+/// `Backend::name()`). Custom backends can be added with `add_backend()`.
 pub struct BackendFactories {
     factories: HashMap<String, BackendFactory>,
 }
@@ -332,6 +383,11 @@ impl BackendFactories {
             "git",
             Box::new(|store_path| Box::new(GitBackend::load(store_path))),
         );
+        #[cfg(feature = "gitoxide")]
+        factories.add_backend(
+            "gitoxide",
+            Box::new(|store_path| Box::new(GitoxideBackend::load(store_path))),
+        );
         factories
     }
 
@@ -355,7 +411,7 @@ impl RepoLoader {
         user_settings: &UserSettings,
         repo_path: &Path,
         backend_factories: &BackendFactories,
-    ) -> Self {
+    ) -> Result<Self, StoreLoadError> {
         let store_path = repo_path.join("store");
         let backend_type = match fs::read_to_string(store_path.join("backend")) {
             Ok(content) => content,
@@ -376,20 +432,23 @@ impl RepoLoader {
         let backend_factory = backend_factories
             .factories
             .get(&backend_type)
-            .expect("Unexpected backend type");
-        let store = Store::new(backend_factory(&store_path));
+            .ok_or(StoreLoadError::UnsupportedType(backend_type))?;
+        let store = Store::with_options(
+            backend_factory(&store_path),
+            user_settings.store_options(&store_path.join("object_cache")),
+        );
         let repo_settings = user_settings.with_repo(repo_path).unwrap();
         let op_store: Arc<dyn OpStore> = Arc::new(SimpleOpStore::load(repo_path.join("op_store")));
         let op_heads_store = Arc::new(OpHeadsStore::load(repo_path.join("op_heads")));
         let index_store = Arc::new(IndexStore::load(repo_path.join("index")));
-        Self {
+        Ok(Self {
             repo_path: repo_path.to_path_buf(),
             repo_settings,
             store,
             op_store,
             op_heads_store,
             index_store,
-        }
+        })
     }
 
     pub fn repo_path(&self) -> &PathBuf {
@@ -415,10 +474,7 @@ impl RepoLoader {
     pub fn load_at_head(&self) -> RepoAtHead {
         let op_heads = self.op_heads_store.get_heads(&self.op_store).unwrap();
         match op_heads {
-            OpHeads::Single(op) => {
-                let view = View::new(op.view().take_store_view());
-                RepoAtHead::Single(self._finish_load(op, view))
-            }
+            OpHeads::Single(op) => RepoAtHead::Single(self._finish_load(op)),
             OpHeads::Unresolved {
                 locked_op_heads,
                 op_heads,
@@ -430,9 +486,10 @@ impl RepoLoader {
         }
     }
 
+    /// Loads the repo at `op`. Its view and index are only read when they're
+    /// first needed.
     pub fn load_at(&self, op: &Operation) -> Arc<ReadonlyRepo> {
-        let view = View::new(op.view().take_store_view());
-        self._finish_load(op.clone(), view)
+        self._finish_load(op.clone())
     }
 
     pub fn create_from(
@@ -450,12 +507,12 @@ impl RepoLoader {
             settings: self.repo_settings.clone(),
             index_store: self.index_store.clone(),
             index: Mutex::new(Some(index)),
-            view,
+            view: Mutex::new(Some(view)),
         };
         Arc::new(repo)
     }
 
-    fn _finish_load(&self, operation: Operation, view: View) -> Arc<ReadonlyRepo> {
+    fn _finish_load(&self, operation: Operation) -> Arc<ReadonlyRepo> {
         let repo = ReadonlyRepo {
             repo_path: self.repo_path.clone(),
             store: self.store.clone(),
@@ -465,7 +522,7 @@ impl RepoLoader {
             settings: self.repo_settings.clone(),
             index_store: self.index_store.clone(),
             index: Mutex::new(None),
-            view,
+            view: Mutex::new(None),
         };
         Arc::new(repo)
     }
@@ -531,7 +588,7 @@ impl MutableRepo {
 
     pub fn has_changes(&self) -> bool {
         self.enforce_view_invariants();
-        self.view.borrow().deref() != &self.base_repo.view
+        self.view.borrow().deref() != self.base_repo.view()
     }
 
     pub fn consume(self) -> (MutableIndex, View) {
@@ -539,10 +596,14 @@ impl MutableRepo {
         (self.index, self.view.into_inner())
     }
 
-    pub fn write_commit(&mut self, commit: backend::Commit) -> Commit {
-        let commit = self.store().write_commit(commit);
+    pub fn write_commit(
+        &mut self,
+        commit: backend::Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<Commit> {
+        let commit = self.store().write_commit(commit, sign_with)?;
         self.add_head(&commit);
-        commit
+        Ok(commit)
     }
 
     /// Record a commit as having been rewritten in this transaction. This
@@ -617,13 +678,13 @@ impl MutableRepo {
         workspace_id: WorkspaceId,
         settings: &UserSettings,
         commit: &Commit,
-    ) -> Commit {
+    ) -> BackendResult<Commit> {
         self.leave_commit(&workspace_id);
         let open_commit =
             CommitBuilder::for_open_commit(settings, commit.id().clone(), commit.tree_id().clone())
-                .write_to_repo(self);
+                .try_write_to_repo(self)?;
         self.set_wc_commit(workspace_id, open_commit.id().clone());
-        open_commit
+        Ok(open_commit)
     }
 
     pub fn edit(&mut self, workspace_id: WorkspaceId, commit: &Commit) {
@@ -752,6 +813,17 @@ impl MutableRepo {
         self.view_mut().remove_remote_branch(name, remote_name);
     }
 
+    pub fn is_remote_branch_tracked(&self, name: &str, remote_name: &str) -> bool {
+        self.view
+            .borrow()
+            .is_remote_branch_tracked(name, remote_name)
+    }
+
+    pub fn set_remote_branch_tracked(&mut self, name: &str, remote_name: &str, tracked: bool) {
+        self.view_mut()
+            .set_remote_branch_tracked(name, remote_name, tracked);
+    }
+
     pub fn get_tag(&self, name: &str) -> Option<RefTarget> {
         self.view.borrow().get_tag(name)
     }
@@ -785,7 +857,13 @@ impl MutableRepo {
         self.view_dirty = true;
     }
 
-    pub fn merge(&mut self, base_repo: &ReadonlyRepo, other_repo: &ReadonlyRepo) {
+    /// Merges the changes from `base_repo` to `other_repo` into this repo.
+    /// Returns what couldn't be merged cleanly.
+    pub fn merge(
+        &mut self,
+        base_repo: &ReadonlyRepo,
+        other_repo: &ReadonlyRepo,
+    ) -> ViewMergeReport {
         // First, merge the index, so we can take advantage of a valid index when
         // merging the view. Merging in base_repo's index isn't typically
         // necessary, but it can be if base_repo is ahead of either self or other_repo
@@ -794,11 +872,13 @@ impl MutableRepo {
         self.index.merge_in(other_repo.index());
 
         self.enforce_view_invariants();
-        self.merge_view(&base_repo.view, &other_repo.view);
+        let report = self.merge_view(base_repo.view(), other_repo.view());
         self.view_dirty = true;
+        report
     }
 
-    fn merge_view(&mut self, base: &View, other: &View) {
+    fn merge_view(&mut self, base: &View, other: &View) -> ViewMergeReport {
+        let mut report = ViewMergeReport::default();
         // Merge checkouts. If there's a conflict, we keep the self side.
         for (workspace_id, base_checkout) in base.wc_commit_ids() {
             let self_checkout = self.view().get_wc_commit_id(workspace_id);
@@ -810,6 +890,8 @@ impl MutableRepo {
                 if self_checkout == Some(base_checkout) {
                     self.view_mut()
                         .set_wc_commit(workspace_id.clone(), other_checkout.clone());
+                } else if self_checkout.is_some() {
+                    report.conflicting_wc_workspaces.push(workspace_id.clone());
                 }
             } else {
                 // The other side removed the workspace. We want to remove it even if the self
@@ -818,14 +900,23 @@ impl MutableRepo {
             }
         }
         for (workspace_id, other_checkout) in other.wc_commit_ids() {
-            if self.view().get_wc_commit_id(workspace_id).is_none()
-                && base.get_wc_commit_id(workspace_id).is_none()
-            {
-                // The other side added the workspace.
-                self.view_mut()
-                    .set_wc_commit(workspace_id.clone(), other_checkout.clone());
+            if base.get_wc_commit_id(workspace_id).is_some() {
+                continue;
+            }
+            match self.view().get_wc_commit_id(workspace_id) {
+                None => {
+                    // The other side added the workspace.
+                    self.view_mut()
+                        .set_wc_commit(workspace_id.clone(), other_checkout.clone());
+                }
+                Some(self_checkout) if self_checkout != other_checkout => {
+                    // Both sides added the workspace with different checkouts.
+                    report.conflicting_wc_workspaces.push(workspace_id.clone());
+                }
+                Some(_) => {}
             }
         }
+        report.conflicting_wc_workspaces.sort();
 
         for removed_head in base.public_heads().difference(other.public_heads()) {
             self.view_mut().remove_public_head(removed_head);
@@ -846,6 +937,7 @@ impl MutableRepo {
         }
 
         let mut maybe_changed_ref_names = HashSet::new();
+        let mut changed_tracking = vec![];
 
         let base_branches: HashSet<_> = base.branches().keys().cloned().collect();
         let other_branches: HashSet<_> = other.branches().keys().cloned().collect();
@@ -858,6 +950,16 @@ impl MutableRepo {
             }
 
             maybe_changed_ref_names.insert(RefName::LocalBranch(branch_name.clone()));
+            let empty = BTreeSet::new();
+            let base_untracked = base_branch.map_or(&empty, |branch| &branch.untracked_remotes);
+            let other_untracked = other_branch.map_or(&empty, |branch| &branch.untracked_remotes);
+            for remote in base_untracked.symmetric_difference(other_untracked) {
+                changed_tracking.push((
+                    branch_name.clone(),
+                    remote.clone(),
+                    !other_untracked.contains(remote),
+                ));
+            }
             if let Some(branch) = base_branch {
                 for remote in branch.remote_targets.keys() {
                     maybe_changed_ref_names.insert(RefName::RemoteBranch {
@@ -893,13 +995,34 @@ impl MutableRepo {
         for ref_name in maybe_changed_ref_names {
             let base_target = base.get_ref(&ref_name);
             let other_target = other.get_ref(&ref_name);
+            let was_conflict = self
+                .view
+                .get_mut()
+                .get_ref(&ref_name)
+                .map_or(false, |target| target.is_conflict());
             self.view.get_mut().merge_single_ref(
                 self.index.as_index_ref(),
                 &ref_name,
                 base_target.as_ref(),
                 other_target.as_ref(),
             );
+            let is_conflict = self
+                .view
+                .get_mut()
+                .get_ref(&ref_name)
+                .map_or(false, |target| target.is_conflict());
+            if is_conflict && !was_conflict {
+                report.conflicted_refs.push(ref_name);
+            }
+        }
+        report.conflicted_refs.sort();
+
+        for (branch_name, remote, tracked) in changed_tracking {
+            self.view
+                .get_mut()
+                .set_remote_branch_tracked(&branch_name, &remote, tracked);
         }
+        report
     }
 
     /// Finds and records commits that were rewritten or abandoned between
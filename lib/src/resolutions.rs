@@ -0,0 +1,339 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Recorded resolutions of conflicts, so a conflict that was resolved once is
+//! resolved the same way when it appears again, e.g. when rebasing the same
+//! commits again. Like Git's `rerere`, resolutions are recorded per
+//! conflicting region (hunk) of a file, keyed by the contents of its sides, so
+//! they're reused even if the rest of the file changed.
+
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+use blake2::{Blake2b512, Digest};
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+use crate::backend::{BackendError, TreeValue};
+use crate::files;
+use crate::files::{MergeHunk, MergeResult};
+use crate::matchers::EverythingMatcher;
+use crate::store::Store;
+use crate::tree::{Diff, Tree};
+
+#[derive(Debug, Error)]
+pub enum ResolutionError {
+    #[error("Failed to access the recorded resolutions: {0}")]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    BackendError(#[from] BackendError),
+}
+
+/// A recorded resolution of a conflict hunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedResolution {
+    pub id: String,
+    pub num_sides: usize,
+    pub resolution: Vec<u8>,
+}
+
+/// The recorded resolutions, each kept in a directory named by the hash of the
+/// conflict hunk, with the hunk's bases and sides and its resolution.
+#[derive(Debug, Clone)]
+pub struct ResolutionCache {
+    dir: PathBuf,
+}
+
+impl ResolutionCache {
+    pub fn new(dir: PathBuf) -> Self {
+        ResolutionCache { dir }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// The recorded resolution of the conflict hunk, if any.
+    pub fn get(&self, removes: &[Vec<u8>], adds: &[Vec<u8>]) -> Option<Vec<u8>> {
+        let path = self.dir.join(hunk_id(removes, adds)).join("resolution");
+        fs::read(path).ok()
+    }
+
+    pub fn record(
+        &self,
+        removes: &[Vec<u8>],
+        adds: &[Vec<u8>],
+        resolution: &[u8],
+    ) -> std::io::Result<()> {
+        let dir = self.dir.join(hunk_id(removes, adds));
+        fs::create_dir_all(&dir)?;
+        for (i, remove) in removes.iter().enumerate() {
+            fs::write(dir.join(format!("base-{}", i + 1)), remove)?;
+        }
+        for (i, add) in adds.iter().enumerate() {
+            fs::write(dir.join(format!("side-{}", i + 1)), add)?;
+        }
+        // Written last, so a resolution is only found once it's complete
+        let mut temp_file = NamedTempFile::new_in(&dir)?;
+        temp_file.write_all(resolution)?;
+        temp_file
+            .persist(dir.join("resolution"))
+            .map_err(|err| err.error)?;
+        Ok(())
+    }
+
+    /// Resolves the conflicting hunks of a merged file with the recorded
+    /// resolutions. Returns `None` unless all of them have been resolved
+    /// before.
+    pub fn resolve_hunks(&self, hunks: &[MergeHunk]) -> Option<Vec<u8>> {
+        let mut content = vec![];
+        for hunk in hunks {
+            match hunk {
+                MergeHunk::Resolved(resolved) => content.extend_from_slice(resolved),
+                MergeHunk::Conflict { removes, adds } => {
+                    content.extend(self.get(removes, adds)?);
+                }
+            }
+        }
+        Some(content)
+    }
+
+    /// All recorded resolutions, sorted by id.
+    pub fn list(&self) -> std::io::Result<Vec<RecordedResolution>> {
+        let entries = match fs::read_dir(&self.dir) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+            Err(err) => return Err(err),
+        };
+        let mut resolutions = vec![];
+        for entry in entries {
+            let entry = entry?;
+            let mut resolution = vec![];
+            match fs::File::open(entry.path().join("resolution")) {
+                Ok(mut file) => file.read_to_end(&mut resolution)?,
+                // Not recorded completely
+                Err(_) => continue,
+            };
+            let mut num_sides = 0;
+            for file_entry in fs::read_dir(entry.path())? {
+                if file_entry?
+                    .file_name()
+                    .to_string_lossy()
+                    .starts_with("side-")
+                {
+                    num_sides += 1;
+                }
+            }
+            resolutions.push(RecordedResolution {
+                id: entry.file_name().to_string_lossy().to_string(),
+                num_sides,
+                resolution,
+            });
+        }
+        resolutions.sort_by(|a, b| a.id.cmp(&b.id));
+        Ok(resolutions)
+    }
+
+    pub fn remove(&self, id: &str) -> std::io::Result<()> {
+        fs::remove_dir_all(self.dir.join(id))
+    }
+}
+
+fn hunk_id(removes: &[Vec<u8>], adds: &[Vec<u8>]) -> String {
+    let mut hasher = Blake2b512::new();
+    for parts in [removes, adds] {
+        hasher.update((parts.len() as u64).to_le_bytes());
+        for part in parts {
+            hasher.update((part.len() as u64).to_le_bytes());
+            hasher.update(part);
+        }
+    }
+    hex::encode(&hasher.finalize()[..32])
+}
+
+/// Finds how each conflicting hunk was resolved in `content`, assuming that
+/// the resolved hunks around them weren't changed. Returns `None` if they were
+/// changed, or if two conflicting hunks are adjacent, since it's then unclear
+/// where one resolution ends and the next starts.
+fn find_hunk_resolutions<'a>(
+    hunks: &'a [MergeHunk],
+    content: &'a [u8],
+) -> Option<Vec<(&'a MergeHunk, &'a [u8])>> {
+    let mut resolutions = vec![];
+    let mut pos = 0;
+    let mut pending_conflict = None;
+    for (i, hunk) in hunks.iter().enumerate() {
+        match hunk {
+            MergeHunk::Resolved(resolved) if resolved.is_empty() => {}
+            MergeHunk::Resolved(resolved) => {
+                let rest = &content[pos..];
+                let start = match pending_conflict.take() {
+                    None if rest.starts_with(resolved) => 0,
+                    None => return None,
+                    // The last hunk must be at the end of the file
+                    Some(conflict) if i == hunks.len() - 1 => {
+                        if !rest.ends_with(resolved) {
+                            return None;
+                        }
+                        let start = rest.len() - resolved.len();
+                        resolutions.push((conflict, &rest[..start]));
+                        start
+                    }
+                    Some(conflict) => {
+                        let start = rest
+                            .windows(resolved.len())
+                            .position(|window| window == resolved.as_slice())?;
+                        resolutions.push((conflict, &rest[..start]));
+                        start
+                    }
+                };
+                pos += start + resolved.len();
+            }
+            MergeHunk::Conflict { .. } => {
+                if pending_conflict.is_some() {
+                    return None;
+                }
+                pending_conflict = Some(hunk);
+            }
+        }
+    }
+    match pending_conflict {
+        Some(conflict) => resolutions.push((conflict, &content[pos..])),
+        None if pos != content.len() => return None,
+        None => {}
+    }
+    Some(resolutions)
+}
+
+/// Records how the conflicts in `old_tree` that are regular files in
+/// `new_tree` were resolved. Returns the number of recorded hunk resolutions.
+pub fn record_resolutions(
+    store: &Store,
+    cache: &ResolutionCache,
+    old_tree: &Tree,
+    new_tree: &Tree,
+) -> Result<usize, ResolutionError> {
+    let mut num_recorded = 0;
+    for (path, diff) in old_tree.diff(new_tree, &EverythingMatcher) {
+        let (conflict_id, file_id) = match diff {
+            Diff::Modified(TreeValue::Conflict(conflict_id), TreeValue::Normal { id, .. }) => {
+                (conflict_id, id)
+            }
+            _ => continue,
+        };
+        let conflict = store.read_conflict(&path, &conflict_id)?;
+        let read_part = |value: &TreeValue| -> Result<Option<Vec<u8>>, ResolutionError> {
+            match value {
+                TreeValue::Normal { id, .. } => {
+                    let mut content = vec![];
+                    store.read_file(&path, id)?.read_to_end(&mut content)?;
+                    Ok(Some(content))
+                }
+                _ => Ok(None),
+            }
+        };
+        let mut removes = vec![];
+        for part in &conflict.removes {
+            removes.extend(read_part(&part.value)?);
+        }
+        let mut adds = vec![];
+        for part in &conflict.adds {
+            adds.extend(read_part(&part.value)?);
+        }
+        // Only conflicts between regular files are written with conflict
+        // markers
+        if removes.len() != conflict.removes.len() || adds.len() != conflict.adds.len() {
+            continue;
+        }
+        let removed_slices = removes.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let added_slices = adds.iter().map(Vec::as_slice).collect::<Vec<_>>();
+        let hunks = match files::merge_with_algorithm(
+            &removed_slices,
+            &added_slices,
+            store.merge_algorithm(&path),
+        ) {
+            MergeResult::Resolved(_) => continue,
+            MergeResult::Conflict(hunks) => hunks,
+        };
+        let resolved_content = read_part(&TreeValue::Normal {
+            id: file_id,
+            executable: false,
+        })?
+        .unwrap();
+        if let Some(resolutions) = find_hunk_resolutions(&hunks, &resolved_content) {
+            for (hunk, resolution) in resolutions {
+                if let MergeHunk::Conflict { removes, adds } = hunk {
+                    cache.record(removes, adds, resolution)?;
+                    num_recorded += 1;
+                }
+            }
+        }
+    }
+    Ok(num_recorded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn conflict(removes: &[&str], adds: &[&str]) -> MergeHunk {
+        MergeHunk::Conflict {
+            removes: removes.iter().map(|s| s.as_bytes().to_vec()).collect(),
+            adds: adds.iter().map(|s| s.as_bytes().to_vec()).collect(),
+        }
+    }
+
+    fn resolved(content: &str) -> MergeHunk {
+        MergeHunk::Resolved(content.as_bytes().to_vec())
+    }
+
+    #[test]
+    fn test_find_hunk_resolutions() {
+        let hunks = vec![
+            resolved("a\n"),
+            conflict(&["b\n"], &["c\n", "d\n"]),
+            resolved("e\n"),
+            conflict(&["f\n"], &["g\n", "h\n"]),
+        ];
+        assert_eq!(
+            find_hunk_resolutions(&hunks, b"a\ncd\ne\ngh\n"),
+            Some(vec![
+                (&hunks[1], b"cd\n".as_slice()),
+                (&hunks[3], b"gh\n".as_slice())
+            ])
+        );
+        // Resolved to nothing
+        assert_eq!(
+            find_hunk_resolutions(&hunks, b"a\ne\n"),
+            Some(vec![
+                (&hunks[1], b"".as_slice()),
+                (&hunks[3], b"".as_slice())
+            ])
+        );
+        // A line outside the conflicts was changed
+        assert_eq!(find_hunk_resolutions(&hunks, b"A\ncd\ne\ngh\n"), None);
+        assert_eq!(find_hunk_resolutions(&hunks, b"a\ncd\nE\ngh\n"), None);
+    }
+
+    #[test]
+    fn test_find_hunk_resolutions_trailing_context() {
+        let hunks = vec![conflict(&["a\n"], &["b\n", "c\n"]), resolved("x\n")];
+        assert_eq!(
+            find_hunk_resolutions(&hunks, b"x\nx\n"),
+            Some(vec![(&hunks[0], b"x\n".as_slice())])
+        );
+        assert_eq!(find_hunk_resolutions(&hunks, b"b\n"), None);
+    }
+}
@@ -13,7 +13,7 @@
 // limitations under the License.
 
 use std::cmp::{Ordering, Reverse};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::iter::Peekable;
 use std::ops::Range;
 use std::rc::Rc;
@@ -25,9 +25,10 @@ use pest::Parser;
 use pest_derive::Parser;
 use thiserror::Error;
 
-use crate::backend::{BackendError, BackendResult, CommitId};
+use crate::backend::{BackendError, BackendResult, ChangeId, CommitId};
 use crate::commit::Commit;
 use crate::index::{HexPrefix, IndexEntry, IndexPosition, PrefixResolution, RevWalk};
+use crate::matchers;
 use crate::matchers::Matcher;
 use crate::op_store::WorkspaceId;
 use crate::repo::RepoRef;
@@ -103,26 +104,45 @@ fn resolve_commit_id(repo: RepoRef, symbol: &str) -> Result<Vec<CommitId>, Revse
 
 fn resolve_change_id(repo: RepoRef, change_id_prefix: &str) -> Result<Vec<CommitId>, RevsetError> {
     if let Some(hex_prefix) = HexPrefix::new(change_id_prefix.to_owned()) {
-        let mut found_change_id = None;
-        let mut commit_ids = vec![];
-        // TODO: Create a persistent lookup from change id to (visible?) commit ids.
-        for index_entry in RevsetExpression::all().evaluate(repo, None).unwrap().iter() {
-            let change_id = index_entry.change_id();
-            if change_id.hex().starts_with(hex_prefix.hex()) {
-                if let Some(previous_change_id) = found_change_id.replace(change_id.clone()) {
-                    if previous_change_id != change_id {
-                        return Err(RevsetError::AmbiguousChangeIdPrefix(
-                            change_id_prefix.to_owned(),
-                        ));
-                    }
+        let index = repo.index();
+        let commit_ids = match index.resolve_change_id_prefix(&hex_prefix) {
+            PrefixResolution::NoMatch => {
+                return Err(RevsetError::NoSuchRevision(change_id_prefix.to_owned()))
+            }
+            PrefixResolution::AmbiguousMatch => {
+                return Err(RevsetError::AmbiguousChangeIdPrefix(
+                    change_id_prefix.to_owned(),
+                ))
+            }
+            PrefixResolution::SingleMatch(commit_ids) => commit_ids,
+        };
+        // Only the visible commits of the change are included. They're found by
+        // walking from the visible heads down to the oldest commit of the change.
+        let entries = commit_ids
+            .iter()
+            .map(|commit_id| index.entry_by_id(commit_id).unwrap())
+            .collect_vec();
+        let min_generation = entries
+            .iter()
+            .map(|entry| entry.generation_number())
+            .min()
+            .unwrap();
+        let mut remaining_positions: HashSet<_> =
+            entries.iter().map(|entry| entry.position()).collect();
+        let heads = repo.view().heads().iter().cloned().collect_vec();
+        let mut visible_commit_ids = vec![];
+        for entry in index.walk_revs(&heads, &[]).min_generation(min_generation) {
+            if remaining_positions.remove(&entry.position()) {
+                visible_commit_ids.push(entry.commit_id());
+                if remaining_positions.is_empty() {
+                    break;
                 }
-                commit_ids.push(index_entry.commit_id());
             }
         }
-        if found_change_id.is_none() {
+        if visible_commit_ids.is_empty() {
             return Err(RevsetError::NoSuchRevision(change_id_prefix.to_owned()));
         }
-        Ok(commit_ids)
+        Ok(visible_commit_ids)
     } else {
         Err(RevsetError::NoSuchRevision(change_id_prefix.to_owned()))
     }
@@ -225,6 +245,7 @@ pub enum RevsetExpression {
     Tags,
     GitRefs,
     GitHead,
+    Divergent,
     ParentCount {
         candidates: Rc<RevsetExpression>,
         parent_count_range: Range<u32>,
@@ -297,6 +318,11 @@ impl RevsetExpression {
         Rc::new(RevsetExpression::GitHead)
     }
 
+    /// Visible commits whose change has more than one visible commit.
+    pub fn divergent() -> Rc<RevsetExpression> {
+        Rc::new(RevsetExpression::Divergent)
+    }
+
     /// Commits in `self` that don't have descendants in `self`.
     pub fn heads(self: &Rc<RevsetExpression>) -> Rc<RevsetExpression> {
         Rc::new(RevsetExpression::Heads(self.clone()))
@@ -733,6 +759,16 @@ fn parse_function_expression(
                 })
             }
         }
+        "divergent" => {
+            if arg_count == 0 {
+                Ok(RevsetExpression::divergent())
+            } else {
+                Err(RevsetParseError::InvalidFunctionArguments {
+                    name,
+                    message: "Expected 0 arguments".to_string(),
+                })
+            }
+        }
         "merges" => {
             if arg_count > 1 {
                 return Err(RevsetParseError::InvalidFunctionArguments {
@@ -1187,10 +1223,28 @@ pub fn evaluate_expression<'repo>(
         #[allow(clippy::needless_collect)]
         RevsetExpression::DagRange { roots, heads } => {
             let root_set = roots.evaluate(repo, workspace_id)?;
-            let candidate_set = heads.ancestors().evaluate(repo, workspace_id)?;
-            let mut reachable: HashSet<_> = root_set.iter().map(|entry| entry.position()).collect();
+            let root_entries = root_set.iter().collect_vec();
+            // Descendants of the roots have higher generation numbers than them, so
+            // there's no need to walk further down the ancestors of the heads
+            let min_generation = match root_entries.iter().map(IndexEntry::generation_number).min()
+            {
+                Some(min_generation) => min_generation,
+                None => {
+                    return Ok(Box::new(EagerRevset {
+                        index_entries: vec![],
+                    }))
+                }
+            };
+            let mut reachable: HashSet<_> =
+                root_entries.iter().map(|entry| entry.position()).collect();
+            let head_set = heads.evaluate(repo, workspace_id)?;
+            let head_ids = head_set.iter().commit_ids().collect_vec();
+            let candidates = repo
+                .index()
+                .walk_revs(&head_ids, &[])
+                .min_generation(min_generation)
+                .collect_vec();
             let mut result = vec![];
-            let candidates = candidate_set.iter().collect_vec();
             for candidate in candidates.into_iter().rev() {
                 if reachable.contains(&candidate.position())
                     || candidate
@@ -1286,6 +1340,22 @@ pub fn evaluate_expression<'repo>(
             let commit_ids = repo.view().git_head().into_iter().collect_vec();
             Ok(revset_for_commit_ids(repo, &commit_ids))
         }
+        RevsetExpression::Divergent => {
+            // TODO: Create a persistent index from change id to commit ids.
+            let all_entries = RevsetExpression::all()
+                .evaluate(repo, workspace_id)?
+                .iter()
+                .collect_vec();
+            let mut commit_count_by_change: HashMap<ChangeId, usize> = HashMap::new();
+            for entry in &all_entries {
+                *commit_count_by_change.entry(entry.change_id()).or_default() += 1;
+            }
+            let index_entries = all_entries
+                .into_iter()
+                .filter(|entry| commit_count_by_change[&entry.change_id()] > 1)
+                .collect();
+            Ok(Box::new(EagerRevset { index_entries }))
+        }
         RevsetExpression::Description { needle, candidates } => {
             let candidates = candidates.evaluate(repo, workspace_id)?;
             let repo = repo;
@@ -1380,9 +1450,17 @@ pub fn filter_by_diff<'revset, 'repo: 'revset>(
     matcher: &'repo dyn Matcher,
     candidates: Box<dyn Revset<'repo> + 'revset>,
 ) -> Box<dyn Revset<'repo> + 'revset> {
+    let match_roots = matchers::match_roots(matcher);
     Box::new(FilterRevset {
         candidates,
         predicate: Box::new(move |entry| {
+            // Skip commits that the changed-path filter in the index says didn't
+            // touch any of the paths, without reading their trees
+            if let (Some(roots), Some(filter)) = (&match_roots, entry.changed_path_filter()) {
+                if !roots.iter().any(|root| filter.may_contain(root)) {
+                    return false;
+                }
+            }
             let commit = repo.store().get_commit(&entry.commit_id()).unwrap();
             let parents = commit.parents();
             let from_tree = rewrite::merge_commit_trees(repo, &parents);
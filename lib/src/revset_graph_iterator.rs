@@ -314,7 +314,9 @@ pub struct ReverseRevsetGraphIterator<'repo> {
 }
 
 impl<'repo> ReverseRevsetGraphIterator<'repo> {
-    fn new<'revset>(input: RevsetGraphIterator<'revset, 'repo>) -> Self {
+    /// Reverses the graph yielded by `input`, which is typically a (possibly
+    /// truncated) `RevsetGraphIterator`.
+    pub fn new(input: impl Iterator<Item = (IndexEntry<'repo>, Vec<RevsetGraphEdge>)>) -> Self {
         let mut entries = vec![];
         let mut reverse_edges: HashMap<IndexPosition, Vec<RevsetGraphEdge>> = HashMap::new();
         for (entry, edges) in input {
@@ -16,7 +16,7 @@ use std::collections::{HashMap, HashSet};
 
 use itertools::{process_results, Itertools};
 
-use crate::backend::{BackendError, CommitId};
+use crate::backend::{BackendError, BackendResult, CommitId, TreeValue};
 use crate::commit::Commit;
 use crate::commit_builder::CommitBuilder;
 use crate::dag_walk;
@@ -25,7 +25,7 @@ use crate::repo::{MutableRepo, RepoRef};
 use crate::repo_path::RepoPath;
 use crate::revset::RevsetExpression;
 use crate::settings::UserSettings;
-use crate::tree::{merge_trees, Tree};
+use crate::tree::{merge_trees_with_labels, MergeLabels, Tree};
 use crate::view::RefName;
 
 pub fn merge_commit_trees(repo: RepoRef, commits: &[Commit]) -> Tree {
@@ -48,19 +48,85 @@ pub fn merge_commit_trees(repo: RepoRef, commits: &[Commit]) -> Tree {
                 .map(|id| store.get_commit(id).unwrap())
                 .collect_vec();
             let ancestor_tree = merge_commit_trees(repo, &ancestors);
-            let new_tree_id = merge_trees(&new_tree, &ancestor_tree, &other_commit.tree()).unwrap();
+            let labels = MergeLabels {
+                side1: commits_conflict_label(&commits[0..i]),
+                base: commits_conflict_label(&ancestors),
+                side2: Some(commit_conflict_label(other_commit)),
+            };
+            let new_tree_id =
+                merge_trees_with_labels(&new_tree, &ancestor_tree, &other_commit.tree(), &labels)
+                    .unwrap();
             new_tree = store.get_tree(&RepoPath::root(), &new_tree_id).unwrap();
         }
         new_tree
     }
 }
 
+/// The paths that have conflicts in `new_tree` that they didn't have in
+/// `old_tree`, e.g. the conflicts a rebase introduced into a commit.
+pub fn new_conflict_paths(old_tree: &Tree, new_tree: &Tree) -> Vec<RepoPath> {
+    new_tree
+        .conflicts()
+        .into_iter()
+        .filter(|(path, conflict_id)| {
+            old_tree.path_value(path) != Some(TreeValue::Conflict(conflict_id.clone()))
+        })
+        .map(|(path, _)| path)
+        .collect()
+}
+
+/// Merges the trees of `parents` like creating a merge commit of them would,
+/// but without writing any commits, and returns the paths that would have
+/// conflicts. Conflicts that one of the parents already had aren't included.
+pub fn simulate_merge(repo: RepoRef, parents: &[Commit]) -> Vec<RepoPath> {
+    let merged_tree = merge_commit_trees(repo, parents);
+    let mut conflict_paths = merged_tree.conflicts();
+    for parent in parents {
+        let parent_tree = parent.tree();
+        conflict_paths.retain(|(path, conflict_id)| {
+            parent_tree.path_value(path) != Some(TreeValue::Conflict(conflict_id.clone()))
+        });
+    }
+    conflict_paths.into_iter().map(|(path, _)| path).collect()
+}
+
+/// Whether `parents` can be merged without new conflicts.
+pub fn can_merge_cleanly(repo: RepoRef, parents: &[Commit]) -> bool {
+    simulate_merge(repo, parents).is_empty()
+}
+
+/// Describes a commit in the labels of the conflicts a merge produces, by its
+/// short id and the first line of its description.
+pub fn commit_conflict_label(commit: &Commit) -> String {
+    let description = commit
+        .description()
+        .lines()
+        .next()
+        .unwrap_or("(no description set)");
+    format!("{} {}", &commit.id().hex()[..12], description)
+}
+
+/// Describes the commits whose trees were merged for one side of a merge.
+fn commits_conflict_label(commits: &[Commit]) -> Option<String> {
+    match commits {
+        [] => None,
+        [commit] => Some(commit_conflict_label(commit)),
+        _ => Some(format!(
+            "merge of {}",
+            commits
+                .iter()
+                .map(|commit| commit.id().hex()[..12].to_string())
+                .join(", ")
+        )),
+    }
+}
+
 pub fn rebase_commit(
     settings: &UserSettings,
     mut_repo: &mut MutableRepo,
     old_commit: &Commit,
     new_parents: &[Commit],
-) -> Commit {
+) -> BackendResult<Commit> {
     let old_parents = old_commit.parents();
     let old_parent_trees = old_parents
         .iter()
@@ -76,8 +142,13 @@ pub fn rebase_commit(
     } else {
         let old_base_tree = merge_commit_trees(mut_repo.as_repo_ref(), &old_parents);
         let new_base_tree = merge_commit_trees(mut_repo.as_repo_ref(), new_parents);
-        // TODO: pass in labels for the merge parts
-        merge_trees(&new_base_tree, &old_base_tree, &old_commit.tree()).unwrap()
+        let labels = MergeLabels {
+            side1: commits_conflict_label(new_parents),
+            base: commits_conflict_label(&old_parents),
+            side2: Some(commit_conflict_label(old_commit)),
+        };
+        merge_trees_with_labels(&new_base_tree, &old_base_tree, &old_commit.tree(), &labels)
+            .unwrap()
     };
     let new_parent_ids = new_parents
         .iter()
@@ -86,7 +157,7 @@ pub fn rebase_commit(
     CommitBuilder::for_rewrite_from(settings, old_commit)
         .set_parents(new_parent_ids)
         .set_tree(new_tree_id)
-        .write_to_repo(mut_repo)
+        .try_write_to_repo(mut_repo)
 }
 
 pub fn back_out_commit(
@@ -94,11 +165,18 @@ pub fn back_out_commit(
     mut_repo: &mut MutableRepo,
     old_commit: &Commit,
     new_parents: &[Commit],
-) -> Commit {
-    let old_base_tree = merge_commit_trees(mut_repo.as_repo_ref(), &old_commit.parents());
+) -> BackendResult<Commit> {
+    let old_parents = old_commit.parents();
+    let old_base_tree = merge_commit_trees(mut_repo.as_repo_ref(), &old_parents);
     let new_base_tree = merge_commit_trees(mut_repo.as_repo_ref(), new_parents);
-    // TODO: pass in labels for the merge parts
-    let new_tree_id = merge_trees(&new_base_tree, &old_commit.tree(), &old_base_tree).unwrap();
+    let labels = MergeLabels {
+        side1: commits_conflict_label(new_parents),
+        base: Some(commit_conflict_label(old_commit)),
+        side2: commits_conflict_label(&old_parents),
+    };
+    let new_tree_id =
+        merge_trees_with_labels(&new_base_tree, &old_commit.tree(), &old_base_tree, &labels)
+            .unwrap();
     let new_parent_ids = new_parents
         .iter()
         .map(|commit| commit.id().clone())
@@ -106,7 +184,7 @@ pub fn back_out_commit(
     // TODO: i18n the description based on repo language
     CommitBuilder::for_new_commit(settings, new_parent_ids, new_tree_id)
         .set_description(format!("backout of commit {}", &old_commit.id().hex()))
-        .write_to_repo(mut_repo)
+        .try_write_to_repo(mut_repo)
 }
 
 /// Rebases descendants of a commit onto a new commit (or several).
@@ -136,6 +214,11 @@ pub struct DescendantRebaser<'settings, 'repo> {
     // have been rebased.
     heads_to_add: HashSet<CommitId>,
     heads_to_remove: Vec<CommitId>,
+    // The commits that were rewritten before `self` was created.
+    rewritten: HashSet<CommitId>,
+    // Descendants of rewritten commits that were left in place, if
+    // `defer_descendants_of_rewritten()` was called.
+    deferred: Option<HashSet<CommitId>>,
 }
 
 impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
@@ -197,6 +280,7 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
         let to_visit = to_visit.iter().map(|entry| entry.commit_id()).collect_vec();
 
         let new_commits = rewritten.values().flatten().cloned().collect();
+        let rewritten_ids = rewritten.keys().cloned().collect();
 
         let mut new_parents = HashMap::new();
         let mut divergent = HashMap::new();
@@ -242,9 +326,24 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
             branches,
             heads_to_add,
             heads_to_remove: Default::default(),
+            rewritten: rewritten_ids,
+            deferred: None,
         }
     }
 
+    /// Leave the descendants of rewritten commits in place instead of rebasing
+    /// them onto the new commits. Branches and working-copy commits pointing to
+    /// the rewritten commits are still updated, and the descendants of
+    /// abandoned commits are still rebased.
+    pub fn defer_descendants_of_rewritten(&mut self) {
+        self.deferred = Some(HashSet::new());
+    }
+
+    /// Returns the descendants of rewritten commits that were left in place.
+    pub fn deferred(&self) -> HashSet<CommitId> {
+        self.deferred.clone().unwrap_or_default()
+    }
+
     /// Returns a map from `CommitId` of old commit to new commit. Includes the
     /// commits rebase so far. Does not include the inputs passed to
     /// `rebase_descendants`.
@@ -353,7 +452,7 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
                 new_commit.id().clone(),
                 new_commit.tree_id().clone(),
             )
-            .write_to_repo(self.mut_repo)
+            .try_write_to_repo(self.mut_repo)?
         };
         for workspace_id in workspaces_to_update.into_iter() {
             self.mut_repo.edit(workspace_id, &new_checkout_commit);
@@ -381,6 +480,15 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
             }
             let old_commit = self.mut_repo.store().get_commit(&old_commit_id)?;
             let old_parent_ids = old_commit.parent_ids();
+            if let Some(deferred) = &mut self.deferred {
+                if old_parent_ids
+                    .iter()
+                    .any(|id| self.rewritten.contains(id) || deferred.contains(id))
+                {
+                    deferred.insert(old_commit_id);
+                    continue;
+                }
+            }
             let new_parent_ids = self.new_parents(old_parent_ids);
             if self.abandoned.contains(&old_commit_id) {
                 // Update the `new_parents` map so descendants are rebased correctly.
@@ -408,7 +516,8 @@ impl<'settings, 'repo> DescendantRebaser<'settings, 'repo> {
                     .map(|new_parent_id| self.mut_repo.store().get_commit(new_parent_id)),
                 |iter| iter.collect_vec(),
             )?;
-            let new_commit = rebase_commit(self.settings, self.mut_repo, &old_commit, &new_parents);
+            let new_commit =
+                rebase_commit(self.settings, self.mut_repo, &old_commit, &new_parents)?;
             self.rebased
                 .insert(old_commit_id.clone(), new_commit.id().clone());
             self.update_references(old_commit_id, vec![new_commit.id().clone()], true)?;
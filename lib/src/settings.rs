@@ -12,11 +12,22 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::{BTreeMap, HashMap};
 use std::path::Path;
 
 use chrono::DateTime;
 
 use crate::backend::{Signature, Timestamp};
+use crate::conflicts::ConflictMarkerStyle;
+use crate::copies::CopyDetectionOptions;
+use crate::diff::DiffAlgorithm;
+use crate::eol::{EolConversion, EolConversionMode};
+use crate::files::MergeAlgorithms;
+use crate::git::HostKeyChecking;
+use crate::merge_drivers::{MergeDriver, MergeDrivers};
+use crate::store::StoreOptions;
+use crate::tree::ModifyDeletePolicy;
+use crate::working_copy::{AutoTrack, NestedRepos, WorkingCopySettings, DEFAULT_DIR_MTIME_CACHE};
 
 #[derive(Debug, Clone, Default)]
 pub struct UserSettings {
@@ -79,6 +90,106 @@ impl UserSettings {
             .unwrap_or_else(|_| "push-".to_string())
     }
 
+    /// The refspecs to fetch from the remote, from `git.remotes.<name>.fetch`.
+    /// If empty, the refspecs configured for the remote in Git are used.
+    pub fn git_fetch_refspecs(&self, remote_name: &str) -> Vec<String> {
+        self.config
+            .get(&format!("git.remotes.{}.fetch", remote_name))
+            .unwrap_or_default()
+    }
+
+    /// The revset of commits to tell the remote we already have when fetching,
+    /// from `git.fetch-negotiation-tips`. Defaults to the visible heads.
+    pub fn git_fetch_negotiation_tips(&self) -> String {
+        self.config
+            .get_string("git.fetch-negotiation-tips")
+            .unwrap_or_else(|_| "heads()".to_string())
+    }
+
+    /// The refspecs mapping local branches to refs on the remote when pushing,
+    /// from `git.remotes.<name>.push`.
+    pub fn git_push_refspecs(&self, remote_name: &str) -> Vec<String> {
+        self.config
+            .get(&format!("git.remotes.{}.push", remote_name))
+            .unwrap_or_default()
+    }
+
+    /// Whether to run the Git repo's `pre-commit`, `commit-msg` and `pre-push`
+    /// hooks, from `git.run-hooks`.
+    pub fn git_run_hooks(&self) -> bool {
+        self.config.get_bool("git.run-hooks").unwrap_or(false)
+    }
+
+    /// The private key file to authenticate to ssh remotes with, from
+    /// `git.ssh.key-path`.
+    pub fn git_ssh_key_path(&self) -> Option<String> {
+        self.config.get_string("git.ssh.key-path").ok()
+    }
+
+    /// Whether to authenticate to ssh remotes with ssh-agent, from
+    /// `git.ssh.use-agent`. Defaults to true.
+    pub fn git_ssh_use_agent(&self) -> bool {
+        self.config.get_bool("git.ssh.use-agent").unwrap_or(true)
+    }
+
+    /// How to verify the host keys of ssh remotes: against
+    /// `~/.ssh/known_hosts` by default, against the fingerprints in
+    /// `git.ssh.host-key-fingerprints` if set, or not at all if
+    /// `git.ssh.host-key-checking` is `"no"`.
+    pub fn git_ssh_host_key_checking(&self) -> HostKeyChecking {
+        if let Ok("no") = self
+            .config
+            .get_string("git.ssh.host-key-checking")
+            .as_deref()
+        {
+            return HostKeyChecking::No;
+        }
+        match self
+            .config
+            .get::<Vec<String>>("git.ssh.host-key-fingerprints")
+        {
+            Ok(fingerprints) if !fingerprints.is_empty() => {
+                HostKeyChecking::Fingerprints(fingerprints)
+            }
+            _ => HostKeyChecking::KnownHosts,
+        }
+    }
+
+    pub fn stash_branch_prefix(&self) -> String {
+        self.config
+            .get_string("stash.branch-prefix")
+            .unwrap_or_else(|_| "stash-".to_string())
+    }
+
+    pub fn signing_backend(&self) -> Option<String> {
+        self.config.get_string("signing.backend").ok()
+    }
+
+    pub fn signing_key(&self) -> Option<String> {
+        self.config.get_string("signing.key").ok()
+    }
+
+    pub fn signing_program(&self, backend: &str) -> String {
+        self.config
+            .get_string(&format!("signing.backends.{backend}.program"))
+            .unwrap_or_else(|_| match backend {
+                "ssh" => "ssh-keygen".to_string(),
+                _ => "gpg".to_string(),
+            })
+    }
+
+    /// The file listing the ssh keys that are trusted to sign commits, in
+    /// `ssh-keygen`'s allowed signers format.
+    pub fn signing_allowed_signers(&self) -> Option<String> {
+        self.config
+            .get_string("signing.backends.ssh.allowed-signers")
+            .ok()
+    }
+
+    pub fn sign_all(&self) -> bool {
+        self.config.get_bool("signing.sign-all").unwrap_or(false)
+    }
+
     pub fn signature(&self) -> Signature {
         let timestamp = self.timestamp.clone().unwrap_or_else(Timestamp::now);
         Signature {
@@ -94,7 +205,318 @@ impl UserSettings {
             .unwrap_or(false)
     }
 
+    pub fn auto_rebase(&self) -> bool {
+        self.config.get_bool("ui.auto-rebase").unwrap_or(true)
+    }
+
+    pub fn fsmonitor(&self) -> Option<String> {
+        self.config.get_string("core.fsmonitor").ok()
+    }
+
+    pub fn watchman_program(&self) -> String {
+        self.config
+            .get_string("core.watchman.program")
+            .unwrap_or_else(|_| "watchman".to_string())
+    }
+
+    /// Whether to respect the executable bit in the working copy, or `None` to
+    /// detect it from the file system.
+    pub fn file_mode(&self) -> Option<bool> {
+        self.config.get_bool("core.file-mode").ok()
+    }
+
+    /// Whether to check out symlinks as symlinks, or `None` to detect it from
+    /// the file system.
+    pub fn symlinks(&self) -> Option<bool> {
+        self.config.get_bool("core.symlinks").ok()
+    }
+
+    pub fn eol_conversion(&self) -> EolConversion {
+        let mode = match self.config.get_string("core.eol-conversion").as_deref() {
+            Ok("input") => EolConversionMode::Input,
+            Ok("input-output") => EolConversionMode::InputOutput,
+            _ => EolConversionMode::None,
+        };
+        let patterns: Vec<String> = self
+            .config
+            .get("core.eol-conversion-patterns")
+            .unwrap_or_default();
+        EolConversion::new(mode, &patterns)
+    }
+
+    /// Which new files to track when snapshotting, from `snapshot.auto-track`:
+    /// `"all"` (the default), `"none"`, or a list of `.gitignore`-style
+    /// patterns.
+    pub fn auto_track(&self) -> AutoTrack {
+        if let Ok(patterns) = self.config.get::<Vec<String>>("snapshot.auto-track") {
+            return AutoTrack::from_patterns(&patterns);
+        }
+        match self.config.get_string("snapshot.auto-track").as_deref() {
+            Ok("none") => AutoTrack::None,
+            _ => AutoTrack::All,
+        }
+    }
+
+    /// How nested repositories are treated when snapshotting, from
+    /// `snapshot.nested-repos`: `"ignore"` (the default), `"include"`, or
+    /// `"placeholder"`.
+    pub fn nested_repos(&self) -> NestedRepos {
+        match self.config.get_string("snapshot.nested-repos").as_deref() {
+            Ok("include") => NestedRepos::Include,
+            Ok("placeholder") => NestedRepos::Placeholder,
+            _ => NestedRepos::Ignore,
+        }
+    }
+
+    /// The size above which new files are refused when snapshotting, from
+    /// `snapshot.max-new-file-size`. It can be a number of bytes or a string
+    /// like "10MiB". Defaults to 1 MiB; 0 means no limit.
+    pub fn max_new_file_size(&self) -> Option<u64> {
+        let size = if let Ok(bytes) = self.config.get_int("snapshot.max-new-file-size") {
+            u64::try_from(bytes).ok()
+        } else if let Ok(string) = self.config.get_string("snapshot.max-new-file-size") {
+            parse_size(&string)
+        } else {
+            None
+        };
+        match size.unwrap_or(1024 * 1024) {
+            0 => None,
+            size => Some(size),
+        }
+    }
+
+    /// The number of days after which operations are abandoned from the
+    /// operation log, from `operation.expire-after-days`. Operations never
+    /// expire if unset.
+    pub fn operation_expire_after_days(&self) -> Option<u64> {
+        self.config
+            .get_int("operation.expire-after-days")
+            .ok()
+            .and_then(|days| u64::try_from(days).ok())
+    }
+
+    /// Tags to record on every operation, from the `operation.tags` table.
+    pub fn operation_tags(&self) -> HashMap<String, String> {
+        self.config
+            .get::<HashMap<String, String>>("operation.tags")
+            .unwrap_or_default()
+    }
+
+    /// The program and arguments to run after each operation is committed,
+    /// from `hooks.post-operation`.
+    pub fn post_operation_hook(&self) -> Option<Vec<String>> {
+        self.config
+            .get::<Vec<String>>("hooks.post-operation")
+            .ok()
+            .filter(|command| !command.is_empty())
+    }
+
+    /// How conflicts are written to files in the working copy, from
+    /// `ui.conflict-marker-style`: `"diff"` (the default), `"minimal"`,
+    /// `"diff3"` or `"zdiff3"`.
+    pub fn conflict_marker_style(&self) -> ConflictMarkerStyle {
+        match self
+            .config
+            .get_string("ui.conflict-marker-style")
+            .as_deref()
+        {
+            Ok("minimal") => ConflictMarkerStyle::Minimal,
+            Ok("diff3") => ConflictMarkerStyle::Diff3,
+            Ok("zdiff3") => ConflictMarkerStyle::Zdiff3,
+            _ => ConflictMarkerStyle::Diff,
+        }
+    }
+
+    /// Whether to check out files by cloning them from a cache on file systems
+    /// that support copy-on-write clones, from `core.reflink`.
+    pub fn reflink(&self) -> bool {
+        self.config.get_bool("core.reflink").unwrap_or(false)
+    }
+
+    /// Whether directories whose mtime hasn't changed since they were last
+    /// snapshotted are snapshotted without listing them again, from
+    /// `core.dir-mtime-cache`. Defaults to true on Unix.
+    pub fn dir_mtime_cache(&self) -> bool {
+        self.config
+            .get_bool("core.dir-mtime-cache")
+            .unwrap_or(DEFAULT_DIR_MTIME_CACHE)
+    }
+
+    /// How the working copy is checked out and snapshotted, from the settings
+    /// above.
+    pub fn working_copy_settings(&self) -> WorkingCopySettings {
+        WorkingCopySettings {
+            exec_bit_override: self.file_mode(),
+            symlink_override: self.symlinks(),
+            eol_conversion: self.eol_conversion(),
+            auto_track: self.auto_track(),
+            nested_repos: self.nested_repos(),
+            max_new_file_size: self.max_new_file_size(),
+            conflict_marker_style: self.conflict_marker_style(),
+            reflink: self.reflink(),
+            dir_mtime_cache: self.dir_mtime_cache(),
+            progress_callback: None,
+        }
+    }
+
+    /// Which diff algorithm to merge file contents with, from
+    /// `merge.algorithm` (`"lcs"`, the default, `"patience"` or
+    /// `"histogram"`) and `merge.algorithm-patterns`, a table from algorithm
+    /// names to `.gitignore`-style patterns of the files to use them for.
+    pub fn merge_algorithms(&self) -> MergeAlgorithms {
+        let parse_algorithm = |name: &str| match name {
+            "lcs" => Some(DiffAlgorithm::Lcs),
+            "patience" => Some(DiffAlgorithm::Patience),
+            "histogram" => Some(DiffAlgorithm::Histogram),
+            _ => None,
+        };
+        let default = self
+            .config
+            .get_string("merge.algorithm")
+            .ok()
+            .and_then(|name| parse_algorithm(&name))
+            .unwrap_or_default();
+        let mut algorithms = MergeAlgorithms::new(default);
+        // Sorted so files matching the patterns of several algorithms use the
+        // same one every time (the last one)
+        let patterns: BTreeMap<String, Vec<String>> = self
+            .config
+            .get("merge.algorithm-patterns")
+            .unwrap_or_default();
+        for (name, patterns) in &patterns {
+            if let Some(algorithm) = parse_algorithm(name) {
+                algorithms.add_patterns(patterns, algorithm);
+            }
+        }
+        algorithms
+    }
+
+    /// The merge drivers from the `merge-drivers` table. Each driver applies
+    /// to the files matching its `patterns`. It runs its `command` if it has
+    /// one, or else it's the built-in driver with its name: `"union"`, `"ours"`
+    /// or `"theirs"`.
+    pub fn merge_drivers(&self) -> MergeDrivers {
+        let mut drivers = MergeDrivers::new();
+        // Sorted so files matching the patterns of several drivers use the same
+        // one every time (the last one)
+        let names: BTreeMap<String, config::Value> = self
+            .config
+            .get_table("merge-drivers")
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        for name in names.keys() {
+            let patterns: Vec<String> = self
+                .config
+                .get(&format!("merge-drivers.{name}.patterns"))
+                .unwrap_or_default();
+            let command: Vec<String> = self
+                .config
+                .get(&format!("merge-drivers.{name}.command"))
+                .unwrap_or_default();
+            let driver = if !command.is_empty() {
+                MergeDriver::Command(command)
+            } else {
+                match name.as_str() {
+                    "union" => MergeDriver::Union,
+                    "ours" => MergeDriver::Ours,
+                    "theirs" => MergeDriver::Theirs,
+                    _ => continue,
+                }
+            };
+            drivers.add_patterns(&patterns, driver);
+        }
+        drivers
+    }
+
+    /// What merges do when one side modified a file and the other side deleted
+    /// it, from `merge.modify-delete`: `"ask"` (the default, leaving a
+    /// conflict), `"keep-modified"` or `"keep-deleted"`.
+    pub fn modify_delete_policy(&self) -> ModifyDeletePolicy {
+        match self.config.get_string("merge.modify-delete").as_deref() {
+            Ok("keep-modified") => ModifyDeletePolicy::KeepModified,
+            Ok("keep-deleted") => ModifyDeletePolicy::KeepDeleted,
+            _ => ModifyDeletePolicy::Ask,
+        }
+    }
+
+    /// How commits and trees read from the backend are cached, from
+    /// `core.object-cache-size`, `core.disk-object-cache` and
+    /// `core.merge-cache`, and how they're merged, from the `merge.*` and
+    /// `merge-drivers` settings. The disk caches are kept in `cache_dir` if
+    /// enabled.
+    pub fn store_options(&self, cache_dir: &Path) -> StoreOptions {
+        let mut options = StoreOptions::default();
+        if let Ok(size) = self.config.get_int("core.object-cache-size") {
+            options.cache_size = usize::try_from(size).unwrap_or(0);
+        }
+        if self
+            .config
+            .get_bool("core.disk-object-cache")
+            .unwrap_or(false)
+        {
+            options.disk_cache_dir = Some(cache_dir.to_path_buf());
+        }
+        options.verify_objects = self.config.get_bool("core.verify-objects").unwrap_or(false);
+        options.merge_algorithms = self.merge_algorithms();
+        options.modify_delete_policy = self.modify_delete_policy();
+        options.follow_renames = self.config.get_bool("merge.follow-renames").unwrap_or(true);
+        options.merge_drivers = self.merge_drivers();
+        if self
+            .config
+            .get_bool("merge.reuse-resolutions")
+            .unwrap_or(false)
+        {
+            options.resolutions_dir = Some(cache_dir.join("resolutions"));
+        }
+        if self.config.get_bool("core.merge-cache").unwrap_or(true) {
+            options.merge_cache_dir = Some(cache_dir.join("merges"));
+        }
+        options
+    }
+
+    /// How renamed and copied files are detected when showing diffs, from
+    /// `diff.renames`, `diff.copies`, `diff.rename-similarity` and
+    /// `diff.rename-limit`, or `None` if `diff.renames` is false.
+    pub fn copy_detection_options(&self) -> Option<CopyDetectionOptions> {
+        if !self.config.get_bool("diff.renames").unwrap_or(true) {
+            return None;
+        }
+        let mut options = CopyDetectionOptions {
+            find_copies: self.config.get_bool("diff.copies").unwrap_or(false),
+            ..Default::default()
+        };
+        if let Ok(similarity) = self.config.get_int("diff.rename-similarity") {
+            options.min_similarity = similarity.clamp(0, 100) as u32;
+        }
+        if let Ok(limit) = self.config.get_int("diff.rename-limit") {
+            options.rename_limit = usize::try_from(limit).unwrap_or(0);
+        }
+        Some(options)
+    }
+
     pub fn config(&self) -> &config::Config {
         &self.config
     }
 }
+
+/// Parses a size like "10MiB", "1.5GB" or "1024".
+fn parse_size(input: &str) -> Option<u64> {
+    let input = input.trim();
+    let number_end = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(number_end);
+    let multiplier: u64 = match unit.trim() {
+        "" | "B" => 1,
+        "KB" | "kB" => 1000,
+        "KiB" => 1024,
+        "MB" => 1000 * 1000,
+        "MiB" => 1024 * 1024,
+        "GB" => 1000 * 1000 * 1000,
+        "GiB" => 1024 * 1024 * 1024,
+        _ => return None,
+    };
+    let number: f64 = number.parse().ok()?;
+    Some((number * multiplier as f64) as u64)
+}
@@ -0,0 +1,349 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Signing of commits with an external program (gpg or ssh-keygen), and
+//! verification of their signatures.
+
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+use std::sync::Arc;
+
+use tempfile::NamedTempFile;
+use thiserror::Error;
+
+use crate::backend::{BackendError, SecureSig};
+use crate::settings::UserSettings;
+
+#[derive(Debug, Error)]
+pub enum SignError {
+    #[error("Unknown signing backend '{0}'")]
+    UnknownBackend(String),
+    #[error("The ssh signing backend requires `signing.key` to be set")]
+    MissingKey,
+    #[error("Failed to run {program}: {err}")]
+    ExecuteError {
+        program: String,
+        err: std::io::Error,
+    },
+    #[error("{program} failed: {stderr}")]
+    SigningFailed { program: String, stderr: String },
+}
+
+impl From<SignError> for BackendError {
+    fn from(err: SignError) -> Self {
+        BackendError::SigningFailed(err.to_string())
+    }
+}
+
+/// Signs commit data according to the `[signing]` config.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    backend: String,
+    program: String,
+    key: Option<String>,
+}
+
+impl Signer {
+    /// Returns the signer configured with `signing.backend`, or `None` if no
+    /// backend is configured.
+    pub fn from_settings(settings: &UserSettings) -> Option<Signer> {
+        let backend = settings.signing_backend()?;
+        let program = settings.signing_program(&backend);
+        Some(Signer {
+            backend,
+            program,
+            key: settings.signing_key(),
+        })
+    }
+
+    /// Returns a signer that signs with `key` instead of `signing.key`.
+    pub fn with_key(self, key: String) -> Signer {
+        Signer {
+            key: Some(key),
+            ..self
+        }
+    }
+
+    /// Signs `data`, returning an ASCII-armored detached signature.
+    pub fn sign(&self, data: &[u8]) -> Result<Vec<u8>, SignError> {
+        let mut command = Command::new(&self.program);
+        match self.backend.as_str() {
+            "gpg" => {
+                command.args(["--detach-sign", "--armor"]);
+                if let Some(key) = &self.key {
+                    command.args(["--local-user", key]);
+                }
+            }
+            "ssh" => {
+                let key = self.key.as_ref().ok_or(SignError::MissingKey)?;
+                command.args(["-Y", "sign", "-n", "git", "-f", key]);
+            }
+            backend => return Err(SignError::UnknownBackend(backend.to_string())),
+        }
+        let execute_error = |err| SignError::ExecuteError {
+            program: self.program.clone(),
+            err,
+        };
+        let mut child = command
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(execute_error)?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(data)
+            .map_err(execute_error)?;
+        let output = child.wait_with_output().map_err(execute_error)?;
+        if !output.status.success() {
+            return Err(SignError::SigningFailed {
+                program: self.program.clone(),
+                stderr: String::from_utf8_lossy(&output.stderr)
+                    .trim_end()
+                    .to_string(),
+            });
+        }
+        Ok(output.stdout)
+    }
+}
+
+/// The result of verifying a signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigStatus {
+    /// The signature is valid and made by a trusted key.
+    Good,
+    /// The signature is valid, but the key is not trusted.
+    Untrusted,
+    /// The signature doesn't match the commit.
+    Bad,
+    /// The signature couldn't be checked, e.g. because the key is missing.
+    Unknown,
+}
+
+impl SigStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigStatus::Good => "good",
+            SigStatus::Untrusted => "untrusted",
+            SigStatus::Bad => "bad",
+            SigStatus::Unknown => "unknown",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Verification {
+    pub status: SigStatus,
+    /// The key id (gpg) or fingerprint (ssh) of the key that made the
+    /// signature, if known.
+    pub key: Option<String>,
+    /// Who the key belongs to (gpg user id or ssh principal), if known.
+    pub display: Option<String>,
+}
+
+impl Verification {
+    fn new(status: SigStatus) -> Self {
+        Verification {
+            status,
+            key: None,
+            display: None,
+        }
+    }
+}
+
+/// Decides whether the key of a valid signature is trusted.
+pub type TrustFn = dyn Fn(&Verification) -> bool;
+
+/// Verifies commit signatures according to the `[signing]` config. The
+/// backend is chosen based on the kind of signature, so it doesn't need to
+/// match `signing.backend`.
+#[derive(Clone)]
+pub struct Verifier {
+    gpg_program: String,
+    ssh_program: String,
+    allowed_signers: Option<String>,
+    trust: Option<Arc<TrustFn>>,
+}
+
+impl Debug for Verifier {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Verifier")
+            .field("gpg_program", &self.gpg_program)
+            .field("ssh_program", &self.ssh_program)
+            .field("allowed_signers", &self.allowed_signers)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Verifier {
+    pub fn from_settings(settings: &UserSettings) -> Verifier {
+        Verifier {
+            gpg_program: settings.signing_program("gpg"),
+            ssh_program: settings.signing_program("ssh"),
+            allowed_signers: settings.signing_allowed_signers(),
+            trust: None,
+        }
+    }
+
+    /// Returns a verifier that asks `trust` whether to trust the keys of
+    /// signatures that are otherwise good. Those it rejects are reported as
+    /// untrusted.
+    pub fn with_trust(self, trust: impl Fn(&Verification) -> bool + 'static) -> Verifier {
+        Verifier {
+            trust: Some(Arc::new(trust)),
+            ..self
+        }
+    }
+
+    /// Verifies a commit's signature.
+    pub fn verify(&self, secure_sig: &SecureSig) -> Result<Verification, SignError> {
+        let mut verification = if secure_sig.sig.starts_with(b"-----BEGIN SSH SIGNATURE-----") {
+            self.verify_ssh(secure_sig)?
+        } else {
+            self.verify_gpg(secure_sig)?
+        };
+        if verification.status == SigStatus::Good {
+            if let Some(trust) = &self.trust {
+                if !trust(&verification) {
+                    verification.status = SigStatus::Untrusted;
+                }
+            }
+        }
+        Ok(verification)
+    }
+
+    fn verify_gpg(&self, secure_sig: &SecureSig) -> Result<Verification, SignError> {
+        let sig_file = write_sig_file(&self.gpg_program, &secure_sig.sig)?;
+        let mut command = Command::new(&self.gpg_program);
+        command
+            .args(["--keyid-format=long", "--status-fd=1", "--verify"])
+            .arg(sig_file.path())
+            .arg("-");
+        let output = run_with_input(&self.gpg_program, &mut command, &secure_sig.data)?;
+        let mut verification = Verification::new(SigStatus::Unknown);
+        let mut never_trusted = false;
+        for line in String::from_utf8_lossy(&output.stdout).lines() {
+            let mut words = line.splitn(4, ' ');
+            if words.next() != Some("[GNUPG:]") {
+                continue;
+            }
+            let status = match words.next() {
+                Some("GOODSIG") => SigStatus::Good,
+                Some("EXPSIG" | "EXPKEYSIG" | "REVKEYSIG") => SigStatus::Untrusted,
+                Some("BADSIG") => SigStatus::Bad,
+                Some("ERRSIG") => SigStatus::Unknown,
+                Some("TRUST_NEVER") => {
+                    never_trusted = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            verification = Verification {
+                status,
+                key: words.next().map(str::to_owned),
+                display: words.next().map(str::to_owned),
+            };
+        }
+        if never_trusted && verification.status == SigStatus::Good {
+            verification.status = SigStatus::Untrusted;
+        }
+        Ok(verification)
+    }
+
+    fn verify_ssh(&self, secure_sig: &SecureSig) -> Result<Verification, SignError> {
+        let sig_file = write_sig_file(&self.ssh_program, &secure_sig.sig)?;
+        if let Some(allowed_signers) = &self.allowed_signers {
+            let mut command = Command::new(&self.ssh_program);
+            command
+                .args(["-Y", "find-principals", "-f", allowed_signers, "-s"])
+                .arg(sig_file.path());
+            let output = run_with_input(&self.ssh_program, &mut command, &[])?;
+            let principals = String::from_utf8_lossy(&output.stdout);
+            if let (true, Some(principal)) = (output.status.success(), principals.lines().next()) {
+                let mut command = Command::new(&self.ssh_program);
+                command
+                    .args(["-Y", "verify", "-f", allowed_signers, "-I", principal])
+                    .args(["-n", "git", "-s"])
+                    .arg(sig_file.path());
+                let output = run_with_input(&self.ssh_program, &mut command, &secure_sig.data)?;
+                let status = if output.status.success() {
+                    SigStatus::Good
+                } else {
+                    SigStatus::Bad
+                };
+                return Ok(Verification {
+                    status,
+                    key: ssh_key_fingerprint(&output),
+                    display: Some(principal.to_string()),
+                });
+            }
+        }
+        // The key isn't an allowed signer, but the signature may still be valid
+        let mut command = Command::new(&self.ssh_program);
+        command
+            .args(["-Y", "check-novalidate", "-n", "git", "-s"])
+            .arg(sig_file.path());
+        let output = run_with_input(&self.ssh_program, &mut command, &secure_sig.data)?;
+        let status = if output.status.success() {
+            SigStatus::Untrusted
+        } else {
+            SigStatus::Bad
+        };
+        Ok(Verification {
+            status,
+            key: ssh_key_fingerprint(&output),
+            display: None,
+        })
+    }
+}
+
+fn write_sig_file(program: &str, sig: &[u8]) -> Result<NamedTempFile, SignError> {
+    let execute_error = |err| SignError::ExecuteError {
+        program: program.to_string(),
+        err,
+    };
+    let mut sig_file = NamedTempFile::new().map_err(execute_error)?;
+    sig_file.write_all(sig).map_err(execute_error)?;
+    Ok(sig_file)
+}
+
+fn run_with_input(program: &str, command: &mut Command, input: &[u8]) -> Result<Output, SignError> {
+    let execute_error = |err| SignError::ExecuteError {
+        program: program.to_string(),
+        err,
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(execute_error)?;
+    // The program may exit without reading its input
+    let _ = child.stdin.take().unwrap().write_all(input);
+    child.wait_with_output().map_err(execute_error)
+}
+
+/// Finds the key fingerprint in ssh-keygen's `Good "git" signature ... with
+/// ED25519 key SHA256:...` output.
+fn ssh_key_fingerprint(output: &Output) -> Option<String> {
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stdout.lines().chain(stderr.lines()).find_map(|line| {
+        line.split_once(" key ")
+            .map(|(_, key)| key.trim().to_string())
+    })
+}
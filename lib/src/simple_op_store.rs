@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::Debug;
 use std::fs;
 use std::fs::File;
@@ -198,7 +198,7 @@ fn operation_from_proto(proto: &crate::protos::op_store::Operation) -> Operation
     }
 }
 
-fn view_to_proto(view: &View) -> crate::protos::op_store::View {
+pub(crate) fn view_to_proto(view: &View) -> crate::protos::op_store::View {
     let mut proto = crate::protos::op_store::View::new();
     for (workspace_id, commit_id) in &view.wc_commit_ids {
         proto
@@ -224,6 +224,7 @@ fn view_to_proto(view: &View) -> crate::protos::op_store::View {
             remote_branch_proto.target = MessageField::some(ref_target_to_proto(target));
             branch_proto.remote_branches.push(remote_branch_proto);
         }
+        branch_proto.untracked_remotes = target.untracked_remotes.iter().cloned().collect();
         proto.branches.push(branch_proto);
     }
 
@@ -248,7 +249,7 @@ fn view_to_proto(view: &View) -> crate::protos::op_store::View {
     proto
 }
 
-fn view_from_proto(proto: &crate::protos::op_store::View) -> View {
+pub(crate) fn view_from_proto(proto: &crate::protos::op_store::View) -> View {
     let mut view = View::default();
     // For compatibility with old repos before we had support for multiple working
     // copies
@@ -285,12 +286,15 @@ fn view_from_proto(proto: &crate::protos::op_store::View) -> View {
                 ref_target_from_proto(&remote_branch.target),
             );
         }
+        let untracked_remotes: BTreeSet<String> =
+            branch_proto.untracked_remotes.iter().cloned().collect();
 
         view.branches.insert(
             branch_proto.name.clone(),
             BranchTarget {
                 local_target,
                 remote_targets,
+                untracked_remotes,
             },
         );
     }
@@ -365,7 +369,7 @@ fn ref_target_from_proto(proto: &crate::protos::op_store::RefTarget) -> RefTarge
 
 #[cfg(test)]
 mod tests {
-    use maplit::{btreemap, hashmap, hashset};
+    use maplit::{btreemap, btreeset, hashmap, hashset};
 
     use super::*;
     use crate::testutils;
@@ -380,6 +384,7 @@ mod tests {
         let public_head_id2 = CommitId::from_hex("bbb555");
         let branch_main_local_target = RefTarget::Normal(CommitId::from_hex("ccc111"));
         let branch_main_origin_target = RefTarget::Normal(CommitId::from_hex("ccc222"));
+        let branch_main_upstream_target = RefTarget::Normal(CommitId::from_hex("ccc444"));
         let branch_deleted_origin_target = RefTarget::Normal(CommitId::from_hex("ccc333"));
         let tag_v1_target = RefTarget::Normal(CommitId::from_hex("ddd111"));
         let git_refs_main_target = RefTarget::Normal(CommitId::from_hex("fff111"));
@@ -397,13 +402,16 @@ mod tests {
                     local_target: Some(branch_main_local_target),
                     remote_targets: btreemap! {
                         "origin".to_string() => branch_main_origin_target,
-                    }
+                        "upstream".to_string() => branch_main_upstream_target,
+                    },
+                    untracked_remotes: btreeset! {"upstream".to_string()},
                 },
                 "deleted".to_string() => BranchTarget {
                     local_target: None,
                     remote_targets: btreemap! {
                         "origin".to_string() => branch_deleted_origin_target,
-                    }
+                    },
+                    untracked_remotes: Default::default(),
                 },
             },
             tags: btreemap! {
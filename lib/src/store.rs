@@ -13,36 +13,144 @@
 // limitations under the License.
 
 use std::collections::HashMap;
-use std::io::Read;
+use std::fs;
+use std::hash::Hash;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Arc, RwLock};
+use std::time::SystemTime;
+
+use blake2::{Blake2b512, Digest};
+use protobuf::Message;
+use rayon::prelude::*;
+use tempfile::NamedTempFile;
 
 use crate::backend;
 use crate::backend::{
-    Backend, BackendResult, CommitId, Conflict, ConflictId, FileId, SymlinkId, TreeId,
+    Backend, BackendResult, CommitId, Conflict, ConflictId, FileId, GcStats, ReachableObjects,
+    SigningFn, StoreObjectId, SymlinkId, TreeId,
 };
 use crate::commit::Commit;
+use crate::diff::DiffAlgorithm;
+use crate::file_util::persist_content_addressed_temp_file;
+use crate::files::MergeAlgorithms;
+use crate::local_backend::{commit_from_proto, commit_to_proto, tree_from_proto, tree_to_proto};
+use crate::merge_drivers::{MergeDriver, MergeDrivers};
 use crate::repo_path::RepoPath;
-use crate::tree::Tree;
+use crate::resolutions::ResolutionCache;
+use crate::tree::{ModifyDeletePolicy, Tree};
 use crate::tree_builder::TreeBuilder;
 
+/// How the store caches and checks objects read from the backend.
+#[derive(Clone, Debug)]
+pub struct StoreOptions {
+    /// Maximum number of commits, and separately of trees, to keep in memory.
+    /// Zero disables the in-memory caches.
+    pub cache_size: usize,
+    /// Directory to cache commits and trees in, so they don't need to be read
+    /// from the backend again by later commands.
+    pub disk_cache_dir: Option<PathBuf>,
+    /// Check that each object read from the backend matches its ID, so
+    /// corruption is reported instead of returning bad data. Objects are read
+    /// twice, so this is slow.
+    pub verify_objects: bool,
+    /// Directory to cache the results of merging trees in, so rebasing the
+    /// same commits again doesn't need to merge their trees again. The cached
+    /// results are made with the default merge options, so the directory is
+    /// ignored if any of the options below are changed.
+    pub merge_cache_dir: Option<PathBuf>,
+    /// Which diff algorithm to merge the contents of each file with.
+    pub merge_algorithms: MergeAlgorithms,
+    /// What merges do when one side modified a file and the other side deleted
+    /// it.
+    pub modify_delete_policy: ModifyDeletePolicy,
+    /// Whether merges apply changes to a file to it after the other side
+    /// renamed it.
+    pub follow_renames: bool,
+    /// Which merge driver, if any, resolves the conflicts in each file.
+    pub merge_drivers: MergeDrivers,
+    /// Directory to record conflict resolutions in, so merges resolve the same
+    /// conflicts the same way again.
+    pub resolutions_dir: Option<PathBuf>,
+}
+
+impl StoreOptions {
+    /// Whether trees are merged the same way as with the default options, so
+    /// the results can be shared through `merge_cache_dir`.
+    fn merges_use_defaults(&self) -> bool {
+        self.merge_algorithms.is_default()
+            && self.modify_delete_policy == ModifyDeletePolicy::default()
+            && self.follow_renames
+            && self.merge_drivers.is_empty()
+            && self.resolutions_dir.is_none()
+    }
+}
+
+impl Default for StoreOptions {
+    fn default() -> Self {
+        StoreOptions {
+            cache_size: 50_000,
+            disk_cache_dir: None,
+            verify_objects: false,
+            merge_cache_dir: None,
+            merge_algorithms: MergeAlgorithms::default(),
+            modify_delete_policy: ModifyDeletePolicy::default(),
+            follow_renames: true,
+            merge_drivers: MergeDrivers::default(),
+            resolutions_dir: None,
+        }
+    }
+}
+
 /// Wraps the low-level backend and makes it return more convenient types. Also
 /// adds the root commit and adds caching.
 #[derive(Debug)]
 pub struct Store {
     backend: Box<dyn Backend>,
-    commit_cache: RwLock<HashMap<CommitId, Arc<backend::Commit>>>,
-    tree_cache: RwLock<HashMap<(RepoPath, TreeId), Arc<backend::Tree>>>,
+    commit_cache: LruCache<CommitId, Arc<backend::Commit>>,
+    tree_cache: LruCache<(RepoPath, TreeId), Arc<backend::Tree>>,
+    disk_cache: Option<DiskCache>,
+    verify_objects: bool,
+    merge_cache: LruCache<(TreeId, TreeId, TreeId), TreeId>,
+    merge_disk_cache: Option<DiskCache>,
+    merge_algorithms: MergeAlgorithms,
+    modify_delete_policy: ModifyDeletePolicy,
+    follow_renames: bool,
+    merge_drivers: MergeDrivers,
+    resolution_cache: Option<ResolutionCache>,
 }
 
 impl Store {
     pub fn new(backend: Box<dyn Backend>) -> Arc<Self> {
+        Store::with_options(backend, StoreOptions::default())
+    }
+
+    pub fn with_options(backend: Box<dyn Backend>, options: StoreOptions) -> Arc<Self> {
+        let merge_cache_dir = options
+            .merge_cache_dir
+            .clone()
+            .filter(|_| options.merges_use_defaults());
         Arc::new(Store {
             backend,
-            commit_cache: Default::default(),
-            tree_cache: Default::default(),
+            commit_cache: LruCache::new(options.cache_size),
+            tree_cache: LruCache::new(options.cache_size),
+            disk_cache: options.disk_cache_dir.map(|dir| DiskCache { dir }),
+            verify_objects: options.verify_objects,
+            merge_cache: LruCache::new(options.cache_size),
+            merge_disk_cache: merge_cache_dir.map(|dir| DiskCache { dir }),
+            merge_algorithms: options.merge_algorithms,
+            modify_delete_policy: options.modify_delete_policy,
+            follow_renames: options.follow_renames,
+            merge_drivers: options.merge_drivers,
+            resolution_cache: options.resolutions_dir.map(ResolutionCache::new),
         })
     }
 
+    pub fn backend_name(&self) -> &str {
+        self.backend.name()
+    }
+
     pub fn hash_length(&self) -> usize {
         self.backend.hash_length()
     }
@@ -51,6 +159,30 @@ impl Store {
         self.backend.git_repo()
     }
 
+    /// The diff algorithm to merge the contents of the file at `path` with.
+    pub fn merge_algorithm(&self, path: &RepoPath) -> DiffAlgorithm {
+        self.merge_algorithms.for_path(path)
+    }
+
+    pub fn modify_delete_policy(&self) -> ModifyDeletePolicy {
+        self.modify_delete_policy
+    }
+
+    pub fn follow_renames(&self) -> bool {
+        self.follow_renames
+    }
+
+    /// The merge driver to resolve conflicts in the file at `path` with, if
+    /// any.
+    pub fn merge_driver(&self, path: &RepoPath) -> Option<&MergeDriver> {
+        self.merge_drivers.for_path(path)
+    }
+
+    /// The recorded conflict resolutions, if recording them is enabled.
+    pub fn resolution_cache(&self) -> Option<&ResolutionCache> {
+        self.resolution_cache.as_ref()
+    }
+
     pub fn empty_tree_id(&self) -> &TreeId {
         self.backend.empty_tree_id()
     }
@@ -68,30 +200,58 @@ impl Store {
         Ok(Commit::new(self.clone(), id.clone(), data))
     }
 
-    fn get_backend_commit(&self, id: &CommitId) -> BackendResult<Arc<backend::Commit>> {
-        {
-            let read_locked_cached = self.commit_cache.read().unwrap();
-            if let Some(data) = read_locked_cached.get(id).cloned() {
-                return Ok(data);
+    /// Reads the commits in parallel, which is faster than reading them one by
+    /// one when they're not cached.
+    pub fn get_commits(self: &Arc<Self>, ids: &[CommitId]) -> Vec<BackendResult<Commit>> {
+        ids.par_iter().map(|id| self.get_commit(id)).collect()
+    }
+
+    /// Drops the commits from the caches, so they're read from the backend
+    /// again. Needed when the backend starts returning different data for
+    /// them, e.g. different parents after deepening a shallow Git clone.
+    pub fn forget_commits(&self, ids: &[CommitId]) {
+        for id in ids {
+            self.commit_cache.remove(id);
+            if let Some(disk_cache) = &self.disk_cache {
+                disk_cache.remove_commit(id);
             }
         }
-        let commit = self.backend.read_commit(id)?;
+    }
+
+    fn get_backend_commit(&self, id: &CommitId) -> BackendResult<Arc<backend::Commit>> {
+        if let Some(data) = self.commit_cache.get(id) {
+            return Ok(data);
+        }
+        let cached_commit = self
+            .disk_cache
+            .as_ref()
+            .and_then(|disk_cache| disk_cache.read_commit(id));
+        let commit = match cached_commit {
+            Some(commit) => commit,
+            None => {
+                self.verify_if_enabled(|| StoreObjectId::Commit(id.clone()))?;
+                let commit = self.backend.read_commit(id)?;
+                if let Some(disk_cache) = &self.disk_cache {
+                    disk_cache.write_commit(id, &commit);
+                }
+                commit
+            }
+        };
         let data = Arc::new(commit);
-        let mut write_locked_cache = self.commit_cache.write().unwrap();
-        write_locked_cache.insert(id.clone(), data.clone());
+        self.commit_cache.insert(id.clone(), data.clone());
         Ok(data)
     }
 
-    pub fn write_commit(self: &Arc<Self>, commit: backend::Commit) -> Commit {
+    pub fn write_commit(
+        self: &Arc<Self>,
+        commit: backend::Commit,
+        sign_with: Option<&mut SigningFn>,
+    ) -> BackendResult<Commit> {
         assert!(!commit.parents.is_empty());
-        let commit_id = self.backend.write_commit(&commit).unwrap();
+        let (commit_id, commit) = self.backend.write_commit(commit, sign_with)?;
         let data = Arc::new(commit);
-        {
-            let mut write_locked_cache = self.commit_cache.write().unwrap();
-            write_locked_cache.insert(commit_id.clone(), data.clone());
-        }
-
-        Commit::new(self.clone(), commit_id, data)
+        self.commit_cache.insert(commit_id.clone(), data.clone());
+        Ok(Commit::new(self.clone(), commit_id, data))
     }
 
     pub fn get_tree(self: &Arc<Self>, dir: &RepoPath, id: &TreeId) -> BackendResult<Tree> {
@@ -99,26 +259,44 @@ impl Store {
         Ok(Tree::new(self.clone(), dir.clone(), id.clone(), data))
     }
 
-    fn get_backend_tree(&self, dir: &RepoPath, id: &TreeId) -> BackendResult<Arc<backend::Tree>> {
+    pub(crate) fn get_backend_tree(
+        &self,
+        dir: &RepoPath,
+        id: &TreeId,
+    ) -> BackendResult<Arc<backend::Tree>> {
         let key = (dir.clone(), id.clone());
-        {
-            let read_locked_cache = self.tree_cache.read().unwrap();
-            if let Some(data) = read_locked_cache.get(&key).cloned() {
-                return Ok(data);
-            }
+        if let Some(data) = self.tree_cache.get(&key) {
+            return Ok(data);
         }
-        let data = Arc::new(self.backend.read_tree(dir, id)?);
-        let mut write_locked_cache = self.tree_cache.write().unwrap();
-        write_locked_cache.insert(key, data.clone());
+        let cached_tree = self
+            .disk_cache
+            .as_ref()
+            .and_then(|disk_cache| disk_cache.read_tree(id));
+        let tree = match cached_tree {
+            Some(tree) => tree,
+            None => {
+                self.verify_if_enabled(|| StoreObjectId::Tree(id.clone()))?;
+                let tree = self.backend.read_tree(dir, id)?;
+                if let Some(disk_cache) = &self.disk_cache {
+                    disk_cache.write_tree(id, &tree);
+                }
+                tree
+            }
+        };
+        let data = Arc::new(tree);
+        self.tree_cache.insert(key, data.clone());
         Ok(data)
     }
 
     pub fn write_tree(&self, path: &RepoPath, contents: &backend::Tree) -> BackendResult<TreeId> {
-        // TODO: This should also do caching like write_commit does.
-        self.backend.write_tree(path, contents)
+        let tree_id = self.backend.write_tree(path, contents)?;
+        self.tree_cache
+            .insert((path.clone(), tree_id.clone()), Arc::new(contents.clone()));
+        Ok(tree_id)
     }
 
     pub fn read_file(&self, path: &RepoPath, id: &FileId) -> BackendResult<Box<dyn Read>> {
+        self.verify_if_enabled(|| StoreObjectId::File(id.clone()))?;
         self.backend.read_file(path, id)
     }
 
@@ -127,6 +305,7 @@ impl Store {
     }
 
     pub fn read_symlink(&self, path: &RepoPath, id: &SymlinkId) -> BackendResult<String> {
+        self.verify_if_enabled(|| StoreObjectId::Symlink(id.clone()))?;
         self.backend.read_symlink(path, id)
     }
 
@@ -135,6 +314,7 @@ impl Store {
     }
 
     pub fn read_conflict(&self, path: &RepoPath, id: &ConflictId) -> BackendResult<Conflict> {
+        self.verify_if_enabled(|| StoreObjectId::Conflict(id.clone()))?;
         self.backend.read_conflict(path, id)
     }
 
@@ -146,7 +326,223 @@ impl Store {
         self.backend.write_conflict(path, contents)
     }
 
+    /// Checks that the object's stored contents match its ID.
+    pub fn verify(&self, object: &StoreObjectId) -> BackendResult<()> {
+        self.backend.verify(object)
+    }
+
+    fn verify_if_enabled(&self, object: impl FnOnce() -> StoreObjectId) -> BackendResult<()> {
+        if self.verify_objects {
+            self.backend.verify(&object())
+        } else {
+            Ok(())
+        }
+    }
+
+    pub fn gc(
+        &self,
+        reachable: &ReachableObjects,
+        keep_newer: SystemTime,
+        dry_run: bool,
+    ) -> BackendResult<GcStats> {
+        self.backend.gc(reachable, keep_newer, dry_run)
+    }
+
+    /// Returns the result of merging the trees if it was recorded by
+    /// `record_merged_tree_id()`, in this process or (if the merge cache is
+    /// enabled) in an earlier one. The tree may since have been garbage
+    /// collected, so callers should check that it can still be read.
+    pub fn cached_merged_tree_id(
+        &self,
+        side1_id: &TreeId,
+        base_id: &TreeId,
+        side2_id: &TreeId,
+    ) -> Option<TreeId> {
+        let key = (side1_id.clone(), base_id.clone(), side2_id.clone());
+        if let Some(merged_id) = self.merge_cache.get(&key) {
+            return Some(merged_id);
+        }
+        let merged_id = self.merge_disk_cache.as_ref()?.read_merged_tree_id(&key)?;
+        self.merge_cache.insert(key, merged_id.clone());
+        Some(merged_id)
+    }
+
+    pub fn record_merged_tree_id(
+        &self,
+        side1_id: &TreeId,
+        base_id: &TreeId,
+        side2_id: &TreeId,
+        merged_id: &TreeId,
+    ) {
+        let key = (side1_id.clone(), base_id.clone(), side2_id.clone());
+        if let Some(disk_cache) = &self.merge_disk_cache {
+            disk_cache.write_merged_tree_id(&key, merged_id);
+        }
+        self.merge_cache.insert(key, merged_id.clone());
+    }
+
     pub fn tree_builder(self: &Arc<Self>, base_tree_id: TreeId) -> TreeBuilder {
         TreeBuilder::new(self.clone(), base_tree_id)
     }
 }
+
+/// A map with a bounded size that evicts the least recently used entries when
+/// it's full.
+#[derive(Debug)]
+struct LruCache<K, V> {
+    capacity: usize,
+    clock: AtomicU64,
+    // Each entry records when it was last used, so lookups only need a read lock
+    entries: RwLock<HashMap<K, (V, AtomicU64)>>,
+}
+
+impl<K: Eq + Hash, V: Clone> LruCache<K, V> {
+    fn new(capacity: usize) -> Self {
+        LruCache {
+            capacity,
+            clock: AtomicU64::new(0),
+            entries: Default::default(),
+        }
+    }
+
+    fn tick(&self) -> u64 {
+        self.clock.fetch_add(1, Ordering::Relaxed)
+    }
+
+    fn get(&self, key: &K) -> Option<V> {
+        let entries = self.entries.read().unwrap();
+        let (value, last_used) = entries.get(key)?;
+        last_used.store(self.tick(), Ordering::Relaxed);
+        Some(value.clone())
+    }
+
+    fn insert(&self, key: K, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.write().unwrap();
+        entries.insert(key, (value, AtomicU64::new(self.tick())));
+        if entries.len() > self.capacity {
+            // Evict a quarter of the entries at once, so the cost of finding the least
+            // recently used ones is spread over many inserts.
+            let num_kept = self.capacity - self.capacity / 4;
+            let mut last_used: Vec<u64> = entries
+                .values()
+                .map(|(_, last_used)| last_used.load(Ordering::Relaxed))
+                .collect();
+            let num_evicted = last_used.len() - num_kept;
+            let (_, &mut oldest_kept, _) = last_used.select_nth_unstable(num_evicted);
+            entries.retain(|_, (_, last_used)| last_used.load(Ordering::Relaxed) >= oldest_kept);
+        }
+    }
+
+    fn remove(&self, key: &K) {
+        self.entries.write().unwrap().remove(key);
+    }
+
+    #[cfg(test)]
+    fn len(&self) -> usize {
+        self.entries.read().unwrap().len()
+    }
+}
+
+/// Caches commits and trees on disk in the local backend's format. Errors are
+/// ignored since the objects can always be read from the backend again.
+#[derive(Debug)]
+struct DiskCache {
+    dir: PathBuf,
+}
+
+impl DiskCache {
+    fn read_commit(&self, id: &CommitId) -> Option<backend::Commit> {
+        let bytes = fs::read(self.dir.join("commits").join(id.hex())).ok()?;
+        let proto = crate::protos::store::Commit::parse_from_bytes(&bytes).ok()?;
+        Some(commit_from_proto(&proto))
+    }
+
+    fn write_commit(&self, id: &CommitId, commit: &backend::Commit) {
+        // The signed data is specific to the backend, so signed commits are always read
+        // from the backend
+        if commit.secure_sig.is_none() {
+            if let Ok(bytes) = commit_to_proto(commit).write_to_bytes() {
+                self.write(&self.dir.join("commits"), &id.hex(), &bytes);
+            }
+        }
+    }
+
+    fn remove_commit(&self, id: &CommitId) {
+        fs::remove_file(self.dir.join("commits").join(id.hex())).ok();
+    }
+
+    fn read_tree(&self, id: &TreeId) -> Option<backend::Tree> {
+        let bytes = fs::read(self.dir.join("trees").join(id.hex())).ok()?;
+        let proto = crate::protos::store::Tree::parse_from_bytes(&bytes).ok()?;
+        Some(tree_from_proto(&proto))
+    }
+
+    fn write_tree(&self, id: &TreeId, tree: &backend::Tree) {
+        if let Ok(bytes) = tree_to_proto(tree).write_to_bytes() {
+            self.write(&self.dir.join("trees"), &id.hex(), &bytes);
+        }
+    }
+
+    fn read_merged_tree_id(&self, key: &(TreeId, TreeId, TreeId)) -> Option<TreeId> {
+        let bytes = fs::read(self.dir.join(merge_cache_file_name(key))).ok()?;
+        Some(TreeId::new(bytes))
+    }
+
+    fn write_merged_tree_id(&self, key: &(TreeId, TreeId, TreeId), merged_id: &TreeId) {
+        self.write(&self.dir, &merge_cache_file_name(key), merged_id.as_bytes());
+    }
+
+    fn write(&self, dir: &Path, name: &str, bytes: &[u8]) {
+        if fs::create_dir_all(dir).is_err() {
+            return;
+        }
+        if let Ok(mut temp_file) = NamedTempFile::new_in(dir) {
+            if temp_file.write_all(bytes).is_ok() {
+                persist_content_addressed_temp_file(temp_file, dir.join(name)).ok();
+            }
+        }
+    }
+}
+
+/// The tree ids can be too long to fit in a file name together, so the file is
+/// named by their hash instead.
+fn merge_cache_file_name((side1_id, base_id, side2_id): &(TreeId, TreeId, TreeId)) -> String {
+    let mut hasher = Blake2b512::new();
+    hasher.update(side1_id.as_bytes());
+    hasher.update(base_id.as_bytes());
+    hasher.update(side2_id.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_cache_evicts_least_recently_used() {
+        let cache = LruCache::new(4);
+        for i in 0..4 {
+            cache.insert(i, i);
+        }
+        // Use the oldest entries so the ones in the middle get evicted instead
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), Some(1));
+        cache.insert(4, 4);
+        assert_eq!(cache.len(), 3);
+        assert_eq!(cache.get(&0), Some(0));
+        assert_eq!(cache.get(&1), Some(1));
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&3), None);
+        assert_eq!(cache.get(&4), Some(4));
+    }
+
+    #[test]
+    fn test_lru_cache_disabled() {
+        let cache = LruCache::new(0);
+        cache.insert(1, 1);
+        assert_eq!(cache.get(&1), None);
+    }
+}
@@ -21,7 +21,7 @@ use crate::index::ReadonlyIndex;
 use crate::op_store;
 use crate::op_store::OperationMetadata;
 use crate::operation::Operation;
-use crate::repo::{MutableRepo, ReadonlyRepo, RepoLoader};
+use crate::repo::{MutableRepo, ReadonlyRepo, RepoLoader, ViewMergeReport};
 use crate::view::View;
 
 pub struct Transaction {
@@ -60,7 +60,9 @@ impl Transaction {
         self.repo.as_mut().unwrap()
     }
 
-    pub fn merge_operation(&mut self, other_op: Operation) {
+    /// Merges the changes from `other_op` into this transaction. Returns what
+    /// couldn't be merged cleanly.
+    pub fn merge_operation(&mut self, other_op: Operation) -> ViewMergeReport {
         let ancestor_op = closest_common_node(
             self.parent_ops.clone(),
             vec![other_op.clone()],
@@ -73,7 +75,7 @@ impl Transaction {
         let other_repo = repo_loader.load_at(&other_op);
         self.parent_ops.push(other_op);
         let merged_repo = self.mut_repo();
-        merged_repo.merge(&base_repo, &other_repo);
+        merged_repo.merge(&base_repo, &other_repo)
     }
 
     /// Writes the transaction to the operation store and publishes it.
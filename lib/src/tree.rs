@@ -13,6 +13,7 @@
 // limitations under the License.
 
 use std::cmp::Ordering;
+use std::collections::HashSet;
 use std::fmt::{Debug, Error, Formatter};
 use std::io::Read;
 use std::iter::Peekable;
@@ -26,11 +27,14 @@ use crate::backend::{
     BackendError, Conflict, ConflictId, ConflictPart, FileId, TreeEntriesNonRecursiveIterator,
     TreeEntry, TreeId, TreeValue,
 };
+use crate::copies::{CopyDetectionOptions, CopyRecord};
 use crate::files::MergeResult;
 use crate::matchers::{EverythingMatcher, Matcher};
+use crate::merge_drivers::MergeDriverError;
 use crate::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use crate::store::Store;
-use crate::{backend, files};
+use crate::tree_builder::TreeBuilder;
+use crate::{backend, copies, files};
 
 #[derive(Debug, Error)]
 pub enum TreeMergeError {
@@ -41,6 +45,8 @@ pub enum TreeMergeError {
     },
     #[error("Backend error: {0}")]
     BackendError(#[from] BackendError),
+    #[error(transparent)]
+    MergeDriverError(#[from] MergeDriverError),
 }
 
 #[derive(Clone)]
@@ -210,6 +216,18 @@ impl Tree {
         }
     }
 
+    /// Finds the files that were renamed or copied between this tree and
+    /// `other`.
+    pub fn find_copies(
+        &self,
+        other: &Tree,
+        matcher: &dyn Matcher,
+        options: &CopyDetectionOptions,
+    ) -> Result<Vec<CopyRecord>, BackendError> {
+        let diffs = self.diff(other, matcher).collect_vec();
+        copies::find_copies(&self.store, &diffs, options)
+    }
+
     pub fn has_conflict(&self) -> bool {
         !self.conflicts().is_empty()
     }
@@ -510,10 +528,176 @@ impl Iterator for TreeDiffIterator<'_> {
     }
 }
 
+/// Labels for the sides of a merge. They're recorded in the conflicts the
+/// merge produces, so they can be shown in the conflict markers.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MergeLabels {
+    pub side1: Option<String>,
+    pub base: Option<String>,
+    pub side2: Option<String>,
+}
+
+impl MergeLabels {
+    fn is_empty(&self) -> bool {
+        self.side1.is_none() && self.base.is_none() && self.side2.is_none()
+    }
+}
+
+/// What to do when one side of a merge modified a file and the other side
+/// deleted it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ModifyDeletePolicy {
+    /// Leave a conflict for the user to resolve.
+    Ask,
+    /// Keep the modified file.
+    KeepModified,
+    /// Delete the file.
+    KeepDeleted,
+}
+
+impl Default for ModifyDeletePolicy {
+    fn default() -> Self {
+        ModifyDeletePolicy::Ask
+    }
+}
+
 pub fn merge_trees(
     side1_tree: &Tree,
     base_tree: &Tree,
     side2_tree: &Tree,
+) -> Result<TreeId, TreeMergeError> {
+    merge_trees_with_labels(side1_tree, base_tree, side2_tree, &MergeLabels::default())
+}
+
+/// Like `merge_trees()`, but labels the sides of the conflicts it produces.
+pub fn merge_trees_with_labels(
+    side1_tree: &Tree,
+    base_tree: &Tree,
+    side2_tree: &Tree,
+    labels: &MergeLabels,
+) -> Result<TreeId, TreeMergeError> {
+    if base_tree.dir().is_root()
+        && base_tree.id() != side1_tree.id()
+        && base_tree.id() != side2_tree.id()
+        && side1_tree.id() != side2_tree.id()
+    {
+        let store = base_tree.store();
+        let cached_id =
+            store.cached_merged_tree_id(side1_tree.id(), base_tree.id(), side2_tree.id());
+        if let Some(merged_id) = cached_id {
+            // The merged tree may have been garbage collected. The cache doesn't
+            // know about labels, so a cached tree with conflicts may have been
+            // merged with different ones.
+            if let Ok(merged_tree) = store.get_tree(&RepoPath::root(), &merged_id) {
+                if labels.is_empty() || !merged_tree.has_conflict() {
+                    return Ok(merged_id);
+                }
+            }
+        }
+        let (renamed_side1_tree, renamed_base_tree, renamed_side2_tree) = if store.follow_renames()
+        {
+            follow_renames(side1_tree, base_tree, side2_tree)?
+        } else {
+            (side1_tree.clone(), base_tree.clone(), side2_tree.clone())
+        };
+        let merged_id = merge_tree_dirs(
+            &renamed_side1_tree,
+            &renamed_base_tree,
+            &renamed_side2_tree,
+            labels,
+        )?;
+        store.record_merged_tree_id(side1_tree.id(), base_tree.id(), side2_tree.id(), &merged_id);
+        return Ok(merged_id);
+    }
+    merge_tree_dirs(side1_tree, base_tree, side2_tree, labels)
+}
+
+/// If one side renamed a file that the other side modified, moves the file in
+/// the base and the other side to the new path, so the modifications get
+/// merged into the renamed file instead of conflicting with its removal.
+fn follow_renames(
+    side1_tree: &Tree,
+    base_tree: &Tree,
+    side2_tree: &Tree,
+) -> Result<(Tree, Tree, Tree), TreeMergeError> {
+    let store = base_tree.store();
+    let side1_diffs = base_tree.diff(side1_tree, &EverythingMatcher).collect_vec();
+    let side2_diffs = base_tree.diff(side2_tree, &EverythingMatcher).collect_vec();
+    let mut side1_renames = renames_of_modified_files(store, &side1_diffs, &side2_diffs)?;
+    let mut side2_renames = renames_of_modified_files(store, &side2_diffs, &side1_diffs)?;
+    if side1_renames.is_empty() && side2_renames.is_empty() {
+        return Ok((side1_tree.clone(), base_tree.clone(), side2_tree.clone()));
+    }
+    // Leave files that were renamed on both sides alone
+    let side1_sources: HashSet<_> = side1_renames.iter().map(|r| r.source.clone()).collect();
+    let side2_sources: HashSet<_> = side2_renames.iter().map(|r| r.source.clone()).collect();
+    side1_renames.retain(|rename| !side2_sources.contains(&rename.source));
+    side2_renames.retain(|rename| !side1_sources.contains(&rename.source));
+
+    let mut base_builder = store.tree_builder(base_tree.id().clone());
+    let mut side1_builder = store.tree_builder(side1_tree.id().clone());
+    let mut side2_builder = store.tree_builder(side2_tree.id().clone());
+    let move_file = |builder: &mut TreeBuilder, tree: &Tree, rename: &CopyRecord| {
+        let value = tree.path_value(&rename.source).unwrap();
+        builder.remove(rename.source.clone());
+        builder.set(rename.target.clone(), value);
+    };
+    for rename in &side1_renames {
+        move_file(&mut base_builder, base_tree, rename);
+        move_file(&mut side2_builder, side2_tree, rename);
+    }
+    for rename in &side2_renames {
+        move_file(&mut base_builder, base_tree, rename);
+        move_file(&mut side1_builder, side1_tree, rename);
+    }
+    let root = RepoPath::root();
+    Ok((
+        store.get_tree(&root, &side1_builder.write_tree())?,
+        store.get_tree(&root, &base_builder.write_tree())?,
+        store.get_tree(&root, &side2_builder.write_tree())?,
+    ))
+}
+
+/// Finds the files renamed in `diffs` that were modified in `other_diffs`,
+/// where the other side didn't also add a file at the new path.
+fn renames_of_modified_files(
+    store: &Arc<Store>,
+    diffs: &[(RepoPath, Diff<TreeValue>)],
+    other_diffs: &[(RepoPath, Diff<TreeValue>)],
+) -> Result<Vec<CopyRecord>, TreeMergeError> {
+    let other_changed: HashSet<&RepoPath> = other_diffs.iter().map(|(path, _)| path).collect();
+    let other_modified: HashSet<&RepoPath> = other_diffs
+        .iter()
+        .filter(|(_, diff)| matches!(diff, Diff::Modified(_, _)))
+        .map(|(path, _)| path)
+        .collect();
+    let candidate_diffs = diffs
+        .iter()
+        .filter(|(path, diff)| match diff {
+            Diff::Removed(_) => other_modified.contains(path),
+            Diff::Added(_) => !other_changed.contains(path),
+            Diff::Modified(_, _) => false,
+        })
+        .cloned()
+        .collect_vec();
+    if !candidate_diffs
+        .iter()
+        .any(|(_, diff)| matches!(diff, Diff::Removed(_)))
+    {
+        return Ok(vec![]);
+    }
+    Ok(copies::find_copies(
+        store,
+        &candidate_diffs,
+        &CopyDetectionOptions::default(),
+    )?)
+}
+
+fn merge_tree_dirs(
+    side1_tree: &Tree,
+    base_tree: &Tree,
+    side2_tree: &Tree,
+    labels: &MergeLabels,
 ) -> Result<TreeId, TreeMergeError> {
     let store = base_tree.store();
     let dir = base_tree.dir();
@@ -545,8 +729,15 @@ pub fn merge_trees(
             // value
         } else {
             // The two sides changed in different ways
-            let new_value =
-                merge_tree_value(store, dir, &basename, maybe_base, maybe_side1, maybe_side2)?;
+            let new_value = merge_tree_value(
+                store,
+                dir,
+                &basename,
+                maybe_base,
+                maybe_side1,
+                maybe_side2,
+                labels,
+            )?;
             match new_value {
                 None => new_tree.remove(&basename),
                 Some(value) => new_tree.set(basename, value),
@@ -576,6 +767,7 @@ fn merge_tree_value(
     maybe_base: Option<&TreeValue>,
     maybe_side1: Option<&TreeValue>,
     maybe_side2: Option<&TreeValue>,
+    labels: &MergeLabels,
 ) -> Result<Option<TreeValue>, TreeMergeError> {
     // Resolve non-trivial conflicts:
     //   * resolve tree conflicts by recursing
@@ -593,7 +785,7 @@ fn merge_tree_value(
             let base_tree = store.get_tree(&subdir, base_id)?;
             let side1_tree = store.get_tree(&subdir, side1_id)?;
             let side2_tree = store.get_tree(&subdir, side2_id)?;
-            let merged_tree_id = merge_trees(&side1_tree, &base_tree, &side2_tree)?;
+            let merged_tree_id = merge_tree_dirs(&side1_tree, &base_tree, &side2_tree, labels)?;
             if merged_tree_id == *empty_tree_id {
                 None
             } else {
@@ -601,22 +793,43 @@ fn merge_tree_value(
             }
         }
         _ => {
+            if let Some(base) = maybe_base {
+                let modified = match (maybe_side1, maybe_side2) {
+                    (Some(side1), None) => Some(side1),
+                    (None, Some(side2)) => Some(side2),
+                    _ => None,
+                };
+                if let Some(modified) = modified {
+                    // One side modified the file and the other side deleted it
+                    let is_tree = |value: &TreeValue| matches!(value, TreeValue::Tree(_));
+                    if !is_tree(base) && !is_tree(modified) {
+                        match store.modify_delete_policy() {
+                            ModifyDeletePolicy::Ask => {}
+                            ModifyDeletePolicy::KeepModified => return Ok(Some(modified.clone())),
+                            ModifyDeletePolicy::KeepDeleted => return Ok(None),
+                        }
+                    }
+                }
+            }
             // Start by creating a Conflict object. Conflicts can cleanly represent a single
             // resolved state, the absence of a state, or a conflicted state.
             let mut conflict = Conflict::default();
             if let Some(base) = maybe_base {
                 conflict.removes.push(ConflictPart {
                     value: base.clone(),
+                    label: labels.base.clone(),
                 });
             }
             if let Some(side1) = maybe_side1 {
                 conflict.adds.push(ConflictPart {
                     value: side1.clone(),
+                    label: labels.side1.clone(),
                 });
             }
             if let Some(side2) = maybe_side2 {
                 conflict.adds.push(ConflictPart {
                     value: side2.clone(),
+                    label: labels.side2.clone(),
                 });
             }
             let filename = dir.join(basename);
@@ -717,13 +930,33 @@ fn try_resolve_file_conflict(
             })?;
         added_contents.push(content);
     }
-    let merge_result = files::merge(
-        &removed_contents.iter().map(Vec::as_slice).collect_vec(),
-        &added_contents.iter().map(Vec::as_slice).collect_vec(),
+    let removed_slices = removed_contents.iter().map(Vec::as_slice).collect_vec();
+    let added_slices = added_contents.iter().map(Vec::as_slice).collect_vec();
+    let merge_result = files::merge_with_algorithm(
+        &removed_slices,
+        &added_slices,
+        store.merge_algorithm(filename),
     );
-    match merge_result {
-        MergeResult::Resolved(merged_content) => Ok(Some((merged_content, executable))),
-        MergeResult::Conflict(_) => Ok(None),
+    let hunks = match merge_result {
+        MergeResult::Resolved(merged_content) => return Ok(Some((merged_content, executable))),
+        MergeResult::Conflict(hunks) => hunks,
+    };
+    // Conflicts that were resolved before are resolved the same way again
+    if let Some(merged_content) = store
+        .resolution_cache()
+        .and_then(|cache| cache.resolve_hunks(&hunks))
+    {
+        return Ok(Some((merged_content, executable)));
+    }
+    // Merge drivers can't tell which side deleted the file
+    if added_slices.len() != removed_slices.len() + 1 {
+        return Ok(None);
+    }
+    match store.merge_driver(filename) {
+        Some(driver) => Ok(driver
+            .merge(&removed_slices, &added_slices, &hunks)?
+            .map(|merged_content| (merged_content, executable))),
+        None => Ok(None),
     }
 }
 
@@ -737,11 +970,9 @@ fn conflict_part_to_conflict(
             let conflict = store.read_conflict(path, id)?;
             Ok(conflict)
         }
-        other => Ok(Conflict {
+        _ => Ok(Conflict {
             removes: vec![],
-            adds: vec![ConflictPart {
-                value: other.clone(),
-            }],
+            adds: vec![part.clone()],
         }),
     }
 }
@@ -56,11 +56,19 @@ impl TreeBuilder {
         self.overrides.insert(path, Override::Tombstone);
     }
 
+    /// Writes the trees that changed and returns the id of the root tree.
+    /// Directories whose entries end up the same as in the base tree (e.g.
+    /// because a file was set to its current value) keep their existing id, so
+    /// only the trees that actually changed are written.
     pub fn write_tree(mut self) -> TreeId {
-        let mut trees_to_write = self.get_base_trees();
-        if trees_to_write.is_empty() {
+        let base_trees = self.get_base_trees();
+        if base_trees.is_empty() {
             return self.base_tree_id;
         }
+        let mut trees_to_write: BTreeMap<RepoPath, backend::Tree> = base_trees
+            .iter()
+            .map(|(dir, (_, tree))| (dir.clone(), tree.clone()))
+            .collect();
 
         // Update entries in parent trees for file overrides
         for (path, file_override) in self.overrides {
@@ -91,24 +99,33 @@ impl TreeBuilder {
 
             for dir in dirs_to_write {
                 let tree = trees_to_write.remove(&dir).unwrap();
+                let unchanged_tree_id = match &base_trees[&dir] {
+                    (Some(base_tree_id), base_tree) if *base_tree == tree => {
+                        Some(base_tree_id.clone())
+                    }
+                    _ => None,
+                };
+                let write_tree =
+                    || unchanged_tree_id.unwrap_or_else(|| store.write_tree(&dir, &tree).unwrap());
 
                 if let Some((parent, basename)) = dir.split() {
                     let parent_tree = trees_to_write.get_mut(&parent).unwrap();
                     if tree.is_empty() {
                         parent_tree.remove(basename);
                     } else {
-                        let tree_id = store.write_tree(&dir, &tree).unwrap();
-                        parent_tree.set(basename.clone(), TreeValue::Tree(tree_id));
+                        parent_tree.set(basename.clone(), TreeValue::Tree(write_tree()));
                     }
                 } else {
                     // We're writing the root tree. Write it even if empty. Return its id.
-                    return store.write_tree(&dir, &tree).unwrap();
+                    return write_tree();
                 }
             }
         }
     }
 
-    fn get_base_trees(&mut self) -> BTreeMap<RepoPath, backend::Tree> {
+    /// Returns the trees in the base tree that contain overridden paths, with
+    /// their ids if they exist there.
+    fn get_base_trees(&mut self) -> BTreeMap<RepoPath, (Option<TreeId>, backend::Tree)> {
         let mut tree_cache = BTreeMap::new();
         let mut base_trees = BTreeMap::new();
         let store = self.store.clone();
@@ -120,19 +137,23 @@ impl TreeBuilder {
                 let tree = store.get_tree(&current_dir, &self.base_tree_id).unwrap();
                 let store_tree = tree.data().clone();
                 tree_cache.insert(current_dir.clone(), tree);
-                base_trees.insert(current_dir.clone(), store_tree);
+                base_trees.insert(
+                    current_dir.clone(),
+                    (Some(self.base_tree_id.clone()), store_tree),
+                );
             }
 
             for component in dir.components() {
                 let next_dir = current_dir.join(component);
                 let current_tree = tree_cache.get(&current_dir).unwrap();
                 if !tree_cache.contains_key(&next_dir) {
-                    let tree = current_tree
-                        .sub_tree(component)
-                        .unwrap_or_else(|| Tree::null(self.store.clone(), next_dir.clone()));
+                    let (tree_id, tree) = match current_tree.sub_tree(component) {
+                        Some(tree) => (Some(tree.id().clone()), tree),
+                        None => (None, Tree::null(self.store.clone(), next_dir.clone())),
+                    };
                     let store_tree = tree.data().clone();
                     tree_cache.insert(next_dir.clone(), tree);
-                    base_trees.insert(next_dir.clone(), store_tree);
+                    base_trees.insert(next_dir.clone(), (tree_id, store_tree));
                 }
                 current_dir = next_dir;
             }
@@ -22,7 +22,7 @@ use crate::op_store;
 use crate::op_store::{BranchTarget, RefTarget, WorkspaceId};
 use crate::refs::merge_ref_targets;
 
-#[derive(PartialEq, Eq, Clone, Hash, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Clone, Hash, Debug)]
 pub enum RefName {
     LocalBranch(String),
     RemoteBranch { branch: String, remote: String },
@@ -206,12 +206,29 @@ impl View {
     pub fn remove_remote_branch(&mut self, name: &str, remote_name: &str) {
         if let Some(branch) = self.data.branches.get_mut(name) {
             branch.remote_targets.remove(remote_name);
+            branch.untracked_remotes.remove(remote_name);
             if branch.remote_targets.is_empty() && branch.local_target.is_none() {
                 self.remove_branch(name);
             }
         }
     }
 
+    pub fn is_remote_branch_tracked(&self, name: &str, remote_name: &str) -> bool {
+        self.data.branches.get(name).map_or(true, |branch_target| {
+            !branch_target.untracked_remotes.contains(remote_name)
+        })
+    }
+
+    pub fn set_remote_branch_tracked(&mut self, name: &str, remote_name: &str, tracked: bool) {
+        if let Some(branch) = self.data.branches.get_mut(name) {
+            if tracked {
+                branch.untracked_remotes.remove(remote_name);
+            } else {
+                branch.untracked_remotes.insert(remote_name.to_string());
+            }
+        }
+    }
+
     pub fn get_tag(&self, name: &str) -> Option<RefTarget> {
         self.data.tags.get(name).cloned()
     }
@@ -0,0 +1,116 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Portable snapshots of a view, for copying the state of a repo (its heads,
+//! branches, tags and working-copy commits) to another clone of the same
+//! store without merging their operation logs.
+//!
+//! A snapshot doesn't include the state of the backing Git repo (`git_refs`
+//! and `git_head`), since that's local to each clone.
+
+use std::collections::HashSet;
+
+use itertools::Itertools;
+use protobuf::Message;
+use thiserror::Error;
+
+use crate::backend::{BackendError, CommitId};
+use crate::op_store::View;
+use crate::repo::MutableRepo;
+use crate::simple_op_store::{view_from_proto, view_to_proto};
+
+/// Written at the start of a snapshot so other files aren't mistaken for one.
+const SNAPSHOT_HEADER: &[u8] = b"jj-view-snapshot-v1\n";
+
+#[derive(Debug, Error)]
+pub enum ViewSnapshotError {
+    #[error("Not a view snapshot")]
+    InvalidHeader,
+    #[error("The view snapshot is corrupt: {0}")]
+    Corrupt(#[from] protobuf::Error),
+    #[error("Commit {} from the view snapshot isn't in this repo", .0.hex())]
+    MissingCommit(CommitId),
+    #[error(transparent)]
+    BackendError(BackendError),
+}
+
+/// Serializes `view`, except for its Git state, as a snapshot.
+pub fn export_view(view: &View) -> Vec<u8> {
+    let mut view = view.clone();
+    view.git_refs.clear();
+    view.git_head = None;
+    let mut bytes = SNAPSHOT_HEADER.to_vec();
+    view_to_proto(&view)
+        .write_to_writer(&mut bytes)
+        .expect("writing to a Vec can't fail");
+    bytes
+}
+
+/// Parses a snapshot written by `export_view()`.
+pub fn parse_view_snapshot(bytes: &[u8]) -> Result<View, ViewSnapshotError> {
+    let proto_bytes = bytes
+        .strip_prefix(SNAPSHOT_HEADER)
+        .ok_or(ViewSnapshotError::InvalidHeader)?;
+    let proto: crate::protos::op_store::View = Message::parse_from_bytes(proto_bytes)?;
+    Ok(view_from_proto(&proto))
+}
+
+/// Replaces the heads, branches and tags in `mut_repo` by the ones from
+/// `snapshot`. The working-copy commit of each workspace is replaced by the one
+/// from the snapshot if it has that workspace. The Git state is kept as it is.
+///
+/// All commits the snapshot refers to must already be in the repo's store,
+/// e.g. by fetching them first.
+pub fn import_view(mut_repo: &mut MutableRepo, snapshot: &View) -> Result<(), ViewSnapshotError> {
+    let current_view = mut_repo.view().store_view().clone();
+    let mut new_view = snapshot.clone();
+    new_view.git_refs = current_view.git_refs;
+    new_view.git_head = current_view.git_head;
+    new_view.wc_commit_ids = current_view.wc_commit_ids;
+    for (workspace_id, wc_commit_id) in &mut new_view.wc_commit_ids {
+        if let Some(snapshot_commit_id) = snapshot.wc_commit_ids.get(workspace_id) {
+            *wc_commit_id = snapshot_commit_id.clone();
+        }
+    }
+
+    let mut referenced_ids: HashSet<CommitId> = HashSet::new();
+    referenced_ids.extend(new_view.head_ids.iter().cloned());
+    referenced_ids.extend(new_view.public_head_ids.iter().cloned());
+    referenced_ids.extend(new_view.wc_commit_ids.values().cloned());
+    for branch_target in new_view.branches.values() {
+        if let Some(local_target) = &branch_target.local_target {
+            referenced_ids.extend(local_target.adds());
+        }
+        for remote_target in branch_target.remote_targets.values() {
+            referenced_ids.extend(remote_target.adds());
+        }
+    }
+    for tag_target in new_view.tags.values() {
+        referenced_ids.extend(tag_target.adds());
+    }
+
+    // Index the commits (in case they were added to the store by another
+    // clone) and keep them all visible
+    let store = mut_repo.store().clone();
+    for commit_id in referenced_ids.iter().sorted() {
+        let commit = store.get_commit(commit_id).map_err(|err| match err {
+            BackendError::NotFound => ViewSnapshotError::MissingCommit(commit_id.clone()),
+            err => ViewSnapshotError::BackendError(err),
+        })?;
+        mut_repo.add_head(&commit);
+    }
+    new_view.head_ids.extend(referenced_ids);
+    mut_repo.set_view(new_view);
+    Ok(())
+}
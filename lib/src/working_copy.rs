@@ -13,31 +13,41 @@
 // limitations under the License.
 
 use std::cell::{RefCell, RefMut};
-use std::collections::{BTreeMap, HashSet};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::ffi::OsString;
 use std::fs;
 use std::fs::{DirEntry, File, Metadata, OpenOptions};
-use std::io::{Read, Write};
+use std::io::{self, Cursor, Read, Write};
 use std::ops::Bound;
 #[cfg(unix)]
 use std::os::unix::fs::symlink;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
-use std::path::{Path, PathBuf};
-use std::sync::Arc;
-use std::time::UNIX_EPOCH;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, UNIX_EPOCH};
 
 use protobuf::{EnumOrUnknown, Message, MessageField};
+use rayon::prelude::*;
 use tempfile::NamedTempFile;
 use thiserror::Error;
 
 use crate::backend::{
-    BackendError, ConflictId, FileId, MillisSinceEpoch, SymlinkId, TreeId, TreeValue,
+    BackendError, CommitId, ConflictId, FileId, MillisSinceEpoch, SymlinkId, TreeId, TreeValue,
 };
-use crate::conflicts::{materialize_conflict, update_conflict_from_content};
+use crate::conflicts::{materialize_conflict, update_conflict_from_content, ConflictMarkerStyle};
+use crate::eol::{EolConversion, EolConversionMode};
+use crate::file_util::{
+    check_case_sensitivity, check_executable_bit_support, check_reflink_support,
+    check_symlink_support, persist_content_addressed_temp_file, reflink,
+};
+use crate::fsmonitor::{FsMonitor, FsMonitorError};
 use crate::gitignore::GitIgnoreFile;
-use crate::lock::FileLock;
-use crate::matchers::{DifferenceMatcher, Matcher, PrefixMatcher};
+use crate::lock::{FileLock, FileLockError};
+use crate::matchers::{
+    DifferenceMatcher, EverythingMatcher, FilesMatcher, Matcher, NothingMatcher, PrefixMatcher,
+    Visit,
+};
 use crate::op_store::{OperationId, WorkspaceId};
 use crate::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use crate::store::Store;
@@ -89,7 +99,6 @@ impl FileState {
         }
     }
 
-    #[cfg_attr(unix, allow(dead_code))]
     fn is_executable(&self) -> bool {
         if let FileType::Normal { executable } = &self.file_type {
             *executable
@@ -105,6 +114,136 @@ impl FileState {
     }
 }
 
+/// The state of a directory whose entries were all tracked or ignored when it
+/// was last snapshotted. Until its mtime changes, no entries were added to or
+/// removed from it, so it doesn't have to be listed again.
+#[derive(Debug, PartialEq, Eq, Clone)]
+struct DirState {
+    mtime: MillisSinceEpoch,
+    // The fingerprint of the ignore patterns that applied to its entries, since
+    // ignored files may no longer be ignored
+    ignore_fingerprint: Vec<u8>,
+    // The subdirectories that were snapshotted
+    subdirs: Vec<String>,
+}
+
+/// Which new files are automatically tracked when the working copy is
+/// snapshotted.
+#[derive(Debug, Clone)]
+pub enum AutoTrack {
+    All,
+    None,
+    /// Files matching `.gitignore`-style patterns
+    Patterns(Arc<GitIgnoreFile>),
+}
+
+impl Default for AutoTrack {
+    fn default() -> Self {
+        AutoTrack::All
+    }
+}
+
+impl AutoTrack {
+    pub fn from_patterns(patterns: &[String]) -> Self {
+        AutoTrack::Patterns(GitIgnoreFile::empty().chain("", patterns.join("\n").as_bytes()))
+    }
+
+    fn matches(&self, path: &RepoPath) -> bool {
+        match self {
+            AutoTrack::All => true,
+            AutoTrack::None => false,
+            AutoTrack::Patterns(patterns) => patterns.matches_file(&path.to_internal_file_string()),
+        }
+    }
+}
+
+/// How directories containing another repository (a `.git` or `.jj`
+/// directory) are treated when the working copy is snapshotted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NestedRepos {
+    /// The nested repository is skipped, unless files in it are already
+    /// tracked.
+    Ignore,
+    /// The nested repository's files are snapshotted like any other files.
+    Include,
+    /// Like `Ignore`, but the commit checked out in a nested Git repository is
+    /// recorded as a Git submodule, like `git add` does. Only supported with
+    /// the Git backend.
+    Placeholder,
+}
+
+impl Default for NestedRepos {
+    fn default() -> Self {
+        NestedRepos::Ignore
+    }
+}
+
+fn is_nested_repo(disk_dir: &Path) -> bool {
+    disk_dir.join(".git").exists() || disk_dir.join(".jj").exists()
+}
+
+/// Returns the commit checked out in the Git repository at `disk_dir`, if any.
+fn nested_repo_head(disk_dir: &Path) -> Option<CommitId> {
+    let git_repo = git2::Repository::open(disk_dir).ok()?;
+    let head_commit = git_repo.head().ok()?.peel_to_commit().ok()?;
+    Some(CommitId::from_bytes(head_commit.id().as_bytes()))
+}
+
+/// Whether unchanged directories are snapshotted without listing them by
+/// default. Not all file systems on Windows update the mtime of a directory
+/// when its entries change.
+pub const DEFAULT_DIR_MTIME_CACHE: bool = cfg!(unix);
+
+/// How the working copy is checked out and snapshotted. The settings aren't
+/// saved with the working copy's state.
+#[derive(Clone)]
+pub struct WorkingCopySettings {
+    /// Overrides the executable-bit support detected from the file system.
+    pub exec_bit_override: Option<bool>,
+    /// Overrides the symlink support detected from the file system. Without
+    /// it, symlinks are written as regular files containing the target path.
+    pub symlink_override: Option<bool>,
+    /// How line endings are converted between the working copy and the store.
+    pub eol_conversion: EolConversion,
+    /// Which new files are tracked when snapshotting.
+    pub auto_track: AutoTrack,
+    /// How nested repositories are treated when snapshotting.
+    pub nested_repos: NestedRepos,
+    /// The size above which new files are refused when snapshotting, or
+    /// `None` for no limit.
+    pub max_new_file_size: Option<u64>,
+    /// How conflicts are written to files on checkout, and read back when
+    /// snapshotting.
+    pub conflict_marker_style: ConflictMarkerStyle,
+    /// Whether files are checked out by cloning them from a cache of file
+    /// contents, on file systems that support copy-on-write clones.
+    pub reflink: bool,
+    /// Whether directories whose mtime hasn't changed since they were last
+    /// snapshotted are snapshotted without listing them again. Only correct on
+    /// file systems that update the mtime of a directory when an entry is
+    /// added to or removed from it.
+    pub dir_mtime_cache: bool,
+    /// A callback to report the progress of checkouts and snapshots to.
+    pub progress_callback: Option<ProgressCallback>,
+}
+
+impl Default for WorkingCopySettings {
+    fn default() -> Self {
+        WorkingCopySettings {
+            exec_bit_override: None,
+            symlink_override: None,
+            eol_conversion: EolConversion::default(),
+            auto_track: AutoTrack::default(),
+            nested_repos: NestedRepos::default(),
+            max_new_file_size: None,
+            conflict_marker_style: ConflictMarkerStyle::default(),
+            reflink: false,
+            dir_mtime_cache: DEFAULT_DIR_MTIME_CACHE,
+            progress_callback: None,
+        }
+    }
+}
+
 pub struct TreeState {
     store: Arc<Store>,
     working_copy_path: PathBuf,
@@ -113,7 +252,25 @@ pub struct TreeState {
     file_states: BTreeMap<RepoPath, FileState>,
     // Currently only path prefixes
     sparse_patterns: Vec<RepoPath>,
-    own_mtime: MillisSinceEpoch,
+    // The file system's time when the state was last saved. Files modified at
+    // or after this time may have changed since their state was recorded even
+    // if their mtime and size still match.
+    clean_timestamp: MillisSinceEpoch,
+    dir_states: BTreeMap<RepoPath, DirState>,
+    fsmonitor_clock: Option<String>,
+    // Whether the file system supports symlinks, as detected when the state was
+    // created
+    detected_symlink_support: bool,
+    // If false, paths that differ only in case can't be checked out together.
+    // Detected when the state was created.
+    case_sensitive: bool,
+    // Whether the file system supports the executable bit, as detected when the
+    // state was created
+    detected_exec_bit_support: bool,
+    // `reflink` is only set if the file system supports copy-on-write clones
+    settings: WorkingCopySettings,
+    // The tree of a checkout that was started but whose state hasn't been saved
+    pending_checkout: Option<TreeId>,
 }
 
 fn file_state_from_proto(proto: &crate::protos::working_copy::FileState) -> FileState {
@@ -161,6 +318,22 @@ fn file_states_from_proto(
     file_states
 }
 
+fn dir_states_from_proto(
+    proto: &crate::protos::working_copy::TreeState,
+) -> BTreeMap<RepoPath, DirState> {
+    let mut dir_states = BTreeMap::new();
+    for (path_str, proto_dir_state) in &proto.dir_states {
+        let path = RepoPath::from_internal_string(path_str.as_str());
+        let dir_state = DirState {
+            mtime: MillisSinceEpoch(proto_dir_state.mtime_millis_since_epoch),
+            ignore_fingerprint: proto_dir_state.ignore_fingerprint.clone(),
+            subdirs: proto_dir_state.subdirs.clone(),
+        };
+        dir_states.insert(path, dir_state);
+    }
+    dir_states
+}
+
 fn sparse_patterns_from_proto(proto: &crate::protos::working_copy::TreeState) -> Vec<RepoPath> {
     let mut sparse_patterns = vec![];
     if let Some(proto_sparse_patterns) = proto.sparse_patterns.as_ref() {
@@ -256,6 +429,25 @@ fn file_state(metadata: &Metadata) -> Option<FileState> {
     })
 }
 
+/// Lists the entries of the directory at `disk_dir`.
+fn list_dir(disk_dir: &Path) -> Result<Vec<DirListingEntry>, SnapshotError> {
+    let mut entries = vec![];
+    for maybe_entry in disk_dir.read_dir().unwrap() {
+        let entry = maybe_entry.unwrap();
+        let file_type = entry.file_type().unwrap();
+        let name = entry
+            .file_name()
+            .into_string()
+            .map_err(|file_name| SnapshotError::InvalidUtf8Path { path: file_name })?;
+        entries.push(DirListingEntry {
+            name,
+            is_dir: file_type.is_dir(),
+            dir_entry: Some(entry),
+        });
+    }
+    Ok(entries)
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct CheckoutStats {
     pub updated_files: u32,
@@ -263,6 +455,95 @@ pub struct CheckoutStats {
     pub removed_files: u32,
 }
 
+/// What the working copy is doing when it reports progress.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum ProgressStage {
+    CheckingOut,
+    Snapshotting,
+}
+
+/// Progress of a checkout or snapshot, reported to the callback set with
+/// `WorkingCopySettings::progress_callback`.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub struct Progress {
+    pub stage: ProgressStage,
+    /// The number of files processed so far.
+    pub processed: usize,
+    /// The number of files to process. The stage is done when `processed`
+    /// reaches it.
+    pub total: usize,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(&Progress) + Send + Sync>;
+
+/// Counts the processed files of one stage and reports them. Files are
+/// processed in parallel, so reports are serialized to keep them in order.
+struct ProgressReporter<'a> {
+    callback: Option<&'a ProgressCallback>,
+    stage: ProgressStage,
+    total: usize,
+    processed: Mutex<usize>,
+}
+
+impl<'a> ProgressReporter<'a> {
+    fn start(callback: Option<&'a ProgressCallback>, stage: ProgressStage, total: usize) -> Self {
+        let reporter = ProgressReporter {
+            callback,
+            stage,
+            total,
+            processed: Mutex::new(0),
+        };
+        reporter.report(0);
+        reporter
+    }
+
+    fn file_processed(&self) {
+        let mut processed = self.processed.lock().unwrap();
+        *processed += 1;
+        self.report(*processed);
+    }
+
+    fn report(&self, processed: usize) {
+        if let Some(callback) = self.callback {
+            callback(&Progress {
+                stage: self.stage,
+                processed,
+                total: self.total,
+            });
+        }
+    }
+}
+
+/// The result of writing a single path during checkout, to be recorded in the
+/// tree state.
+enum FileUpdate {
+    Written(FileState),
+    ExecutableChanged(bool),
+    Skipped,
+}
+
+/// An entry of a directory being snapshotted, either listed from disk or from
+/// the recorded state of the directory.
+struct DirListingEntry {
+    name: String,
+    is_dir: bool,
+    dir_entry: Option<DirEntry>,
+}
+
+/// The result of snapshotting a single file, to be recorded in the tree state
+/// and the new tree.
+enum SnapshotUpdate {
+    /// The file is ignored, untracked, or unchanged.
+    Unchanged,
+    /// A tracked file was replaced by something we can't track, like a socket.
+    Removed,
+    /// The file's new state and value.
+    Changed {
+        file_state: FileState,
+        value: TreeValue,
+    },
+}
+
 #[derive(Debug, Error)]
 pub enum SnapshotError {
     #[error("{message}: {err}")]
@@ -277,6 +558,17 @@ pub enum SnapshotError {
     InvalidUtf8SymlinkTarget { path: PathBuf, target: PathBuf },
     #[error("Internal backend error: {0}")]
     InternalBackendError(#[from] BackendError),
+    #[error("Failed to query the filesystem monitor: {0}")]
+    FsMonitorError(#[from] FsMonitorError),
+    #[error(
+        "New file {} of size {size} bytes exceeds snapshot.max-new-file-size ({max_size} bytes)",
+        path.to_internal_file_string()
+    )]
+    NewFileTooLarge {
+        path: RepoPath,
+        size: u64,
+        max_size: u64,
+    },
 }
 
 #[derive(Debug, Error)]
@@ -297,6 +589,14 @@ pub enum CheckoutError {
     },
     #[error("Internal error: {0}")]
     InternalBackendError(#[from] BackendError),
+    #[error(
+        "Paths {} and {} differ only in case, which the file system doesn't distinguish",
+        path1.to_internal_file_string(),
+        path2.to_internal_file_string()
+    )]
+    PathCaseCollision { path1: RepoPath, path2: RepoPath },
+    #[error(transparent)]
+    Locked(#[from] FileLockError),
 }
 
 impl CheckoutError {
@@ -308,6 +608,59 @@ impl CheckoutError {
     }
 }
 
+/// Returns an error if one of `added_paths` (or one of their parent
+/// directories) differs only in case from another path in `tree` that's
+/// checked out according to `matcher`. Only the directories the added paths
+/// are in are searched, so this is cheap for small changes to a large tree.
+fn check_case_collisions(
+    tree: &Tree,
+    added_paths: impl IntoIterator<Item = RepoPath>,
+    matcher: &dyn Matcher,
+) -> Result<(), CheckoutError> {
+    let mut checked_paths = HashSet::new();
+    for path in added_paths {
+        let mut current = Some(path);
+        while let Some(path) = current {
+            let (dir, name) = match path.split() {
+                Some((dir, name)) => (dir, name.clone()),
+                None => break,
+            };
+            if !checked_paths.insert(path.clone()) {
+                // The parent directories have already been checked too
+                break;
+            }
+            let folded_name = name.as_str().to_lowercase();
+            let dir_tree = dir
+                .components()
+                .iter()
+                .try_fold(tree.clone(), |tree, name| tree.sub_tree(name));
+            for entry in dir_tree
+                .iter()
+                .flat_map(|tree| tree.entries_non_recursive())
+            {
+                if *entry.name() == name || entry.name().as_str().to_lowercase() != folded_name {
+                    continue;
+                }
+                let other = dir.join(entry.name());
+                let is_checked_out = match entry.value() {
+                    TreeValue::Tree(_) => !matcher.visit(&other).is_nothing(),
+                    _ => matcher.matches(&other),
+                };
+                if is_checked_out {
+                    let (path1, path2) = if other < path {
+                        (other, path)
+                    } else {
+                        (path, other)
+                    };
+                    return Err(CheckoutError::PathCaseCollision { path1, path2 });
+                }
+            }
+            current = Some(dir);
+        }
+    }
+    Ok(())
+}
+
 fn suppress_file_exists_error(orig_err: CheckoutError) -> Result<(), CheckoutError> {
     match orig_err {
         CheckoutError::IoError { err, .. } if err.kind() == std::io::ErrorKind::AlreadyExists => {
@@ -340,12 +693,46 @@ impl TreeState {
         &self.sparse_patterns
     }
 
+    /// Whether symlinks are checked out as symlinks. If not, they're written
+    /// as regular files containing the target path.
+    pub fn symlink_support(&self) -> bool {
+        self.settings
+            .symlink_override
+            .unwrap_or(self.detected_symlink_support)
+    }
+
+    /// Overrides whether file names are considered case-sensitive. This is
+    /// normally detected from the file system.
+    pub fn set_case_sensitive(&mut self, case_sensitive: bool) {
+        self.case_sensitive = case_sensitive;
+    }
+
+    /// Whether executable bits are read from and written to the working copy.
+    /// If not, the executable bits recorded in the tree are preserved.
+    pub fn exec_bit_support(&self) -> bool {
+        self.settings
+            .exec_bit_override
+            .unwrap_or(self.detected_exec_bit_support)
+    }
+
+    /// Sets how the working copy is checked out and snapshotted.
+    pub fn set_settings(&mut self, settings: WorkingCopySettings) {
+        let reflink = settings.reflink && check_reflink_support(&self.state_path).unwrap_or(false);
+        self.settings = WorkingCopySettings {
+            reflink,
+            ..settings
+        };
+    }
+
     fn sparse_matcher(&self) -> Box<dyn Matcher> {
         Box::new(PrefixMatcher::new(&self.sparse_patterns))
     }
 
     pub fn init(store: Arc<Store>, working_copy_path: PathBuf, state_path: PathBuf) -> TreeState {
         let mut wc = TreeState::empty(store, working_copy_path, state_path);
+        wc.detect_exec_bit_support();
+        wc.detect_symlink_support();
+        wc.detect_case_sensitivity();
         wc.save();
         wc
     }
@@ -361,7 +748,14 @@ impl TreeState {
             tree_id,
             file_states: BTreeMap::new(),
             sparse_patterns: vec![RepoPath::root()],
-            own_mtime: MillisSinceEpoch(0),
+            clean_timestamp: MillisSinceEpoch(0),
+            dir_states: BTreeMap::new(),
+            fsmonitor_clock: None,
+            detected_symlink_support: true,
+            case_sensitive: true,
+            detected_exec_bit_support: true,
+            settings: WorkingCopySettings::default(),
+            pending_checkout: None,
         }
     }
 
@@ -376,27 +770,127 @@ impl TreeState {
 
         let mut wc = TreeState::empty(store, working_copy_path, state_path);
         wc.read(file);
+        wc.read_pending_checkout();
         wc
     }
 
-    fn update_own_mtime(&mut self) {
-        if let Ok(metadata) = self.state_path.join("tree_state").symlink_metadata() {
-            self.own_mtime = mtime_from_metadata(&metadata);
-        } else {
-            self.own_mtime = MillisSinceEpoch(0);
-        }
+    fn detect_exec_bit_support(&mut self) {
+        self.detected_exec_bit_support =
+            check_executable_bit_support(&self.state_path).unwrap_or(true);
+    }
+
+    fn detect_symlink_support(&mut self) {
+        self.detected_symlink_support = check_symlink_support(&self.state_path).unwrap_or(true);
+    }
+
+    fn detect_case_sensitivity(&mut self) {
+        self.case_sensitive = check_case_sensitivity(&self.state_path).unwrap_or(true);
     }
 
     fn read(&mut self, mut file: File) {
-        self.update_own_mtime();
         let proto: crate::protos::working_copy::TreeState =
             Message::parse_from_reader(&mut file).unwrap();
+        self.clean_timestamp = if proto.clean_timestamp_millis_since_epoch != 0 {
+            MillisSinceEpoch(proto.clean_timestamp_millis_since_epoch)
+        } else {
+            // State written before the timestamp was recorded. The file's mtime is
+            // the time it was saved.
+            file.metadata()
+                .map(|metadata| mtime_from_metadata(&metadata))
+                .unwrap_or(MillisSinceEpoch(0))
+        };
         self.tree_id = TreeId::new(proto.tree_id.clone());
         self.file_states = file_states_from_proto(&proto);
+        self.dir_states = dir_states_from_proto(&proto);
         self.sparse_patterns = sparse_patterns_from_proto(&proto);
+        self.fsmonitor_clock =
+            Some(proto.fsmonitor_clock.clone()).filter(|clock| !clock.is_empty());
+        match proto.exec_bit_support.enum_value_or_default() {
+            // State written before this was detected
+            crate::protos::working_copy::ExecBitSupport::Unknown => {
+                self.detect_exec_bit_support();
+            }
+            crate::protos::working_copy::ExecBitSupport::Supported => {
+                self.detected_exec_bit_support = true;
+            }
+            crate::protos::working_copy::ExecBitSupport::Unsupported => {
+                self.detected_exec_bit_support = false;
+            }
+        }
+        match proto.symlink_support.enum_value_or_default() {
+            // State written before this was recorded
+            crate::protos::working_copy::SymlinkSupport::UnknownSymlinkSupport => {
+                self.detect_symlink_support();
+            }
+            crate::protos::working_copy::SymlinkSupport::SymlinksSupported => {
+                self.detected_symlink_support = true;
+            }
+            crate::protos::working_copy::SymlinkSupport::SymlinksUnsupported => {
+                self.detected_symlink_support = false;
+            }
+        }
+        match proto.case_sensitivity.enum_value_or_default() {
+            // State written before this was recorded
+            crate::protos::working_copy::CaseSensitivity::UnknownCaseSensitivity => {
+                self.detect_case_sensitivity();
+            }
+            crate::protos::working_copy::CaseSensitivity::CaseSensitive => {
+                self.case_sensitive = true;
+            }
+            crate::protos::working_copy::CaseSensitivity::CaseInsensitive => {
+                self.case_sensitive = false;
+            }
+        }
+    }
+
+    fn read_pending_checkout(&mut self) {
+        match File::open(self.state_path.join("pending_checkout")) {
+            Ok(mut file) => {
+                let proto: crate::protos::working_copy::PendingCheckout =
+                    Message::parse_from_reader(&mut file).unwrap();
+                self.pending_checkout = Some(TreeId::new(proto.new_tree_id));
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.pending_checkout = None;
+            }
+            Err(err) => panic!("failed to read pending checkout: {}", err),
+        }
+    }
+
+    /// Records that files are about to be updated to `new_tree_id`, so the
+    /// checkout can be resumed if it's interrupted.
+    fn write_pending_checkout(&mut self, new_tree_id: &TreeId) {
+        let mut proto = crate::protos::working_copy::PendingCheckout::new();
+        proto.new_tree_id = new_tree_id.to_bytes();
+        let mut temp_file = NamedTempFile::new_in(&self.state_path).unwrap();
+        proto.write_to_writer(temp_file.as_file_mut()).unwrap();
+        // Make sure the record survives a crash before we touch any files
+        temp_file.as_file().sync_all().unwrap();
+        temp_file
+            .persist(self.state_path.join("pending_checkout"))
+            .unwrap();
+        self.pending_checkout = Some(new_tree_id.clone());
     }
 
     fn save(&mut self) {
+        let mut temp_file = NamedTempFile::new_in(&self.state_path).unwrap();
+        // The new file's mtime is the file system's current time. Files with the same
+        // mtime may still be modified without changing their mtime, so we record them
+        // as racy (mtime 0) and read them again on the next snapshot, like Git does.
+        let clean_timestamp = mtime_from_metadata(&temp_file.as_file().metadata().unwrap());
+        for file_state in self.file_states.values_mut() {
+            if file_state.mtime >= clean_timestamp {
+                file_state.mtime = MillisSinceEpoch(0);
+            }
+        }
+        // Likewise for entries added to directories
+        for dir_state in self.dir_states.values_mut() {
+            if dir_state.mtime >= clean_timestamp {
+                dir_state.mtime = MillisSinceEpoch(0);
+            }
+        }
+        self.clean_timestamp = clean_timestamp;
+
         let mut proto = crate::protos::working_copy::TreeState::new();
         proto.tree_id = self.tree_id.to_bytes();
         for (file, file_state) in &self.file_states {
@@ -405,6 +899,15 @@ impl TreeState {
                 file_state_to_proto(file_state),
             );
         }
+        for (dir, dir_state) in &self.dir_states {
+            let mut proto_dir_state = crate::protos::working_copy::DirState::new();
+            proto_dir_state.mtime_millis_since_epoch = dir_state.mtime.0;
+            proto_dir_state.ignore_fingerprint = dir_state.ignore_fingerprint.clone();
+            proto_dir_state.subdirs = dir_state.subdirs.clone();
+            proto
+                .dir_states
+                .insert(dir.to_internal_file_string(), proto_dir_state);
+        }
         let mut sparse_patterns = crate::protos::working_copy::SparsePatterns::new();
         for path in &self.sparse_patterns {
             sparse_patterns
@@ -412,17 +915,38 @@ impl TreeState {
                 .push(path.to_internal_file_string());
         }
         proto.sparse_patterns = MessageField::some(sparse_patterns);
+        proto.fsmonitor_clock = self.fsmonitor_clock.clone().unwrap_or_default();
+        proto.exec_bit_support = EnumOrUnknown::new(if self.detected_exec_bit_support {
+            crate::protos::working_copy::ExecBitSupport::Supported
+        } else {
+            crate::protos::working_copy::ExecBitSupport::Unsupported
+        });
+        proto.symlink_support = EnumOrUnknown::new(if self.detected_symlink_support {
+            crate::protos::working_copy::SymlinkSupport::SymlinksSupported
+        } else {
+            crate::protos::working_copy::SymlinkSupport::SymlinksUnsupported
+        });
+        proto.case_sensitivity = EnumOrUnknown::new(if self.case_sensitive {
+            crate::protos::working_copy::CaseSensitivity::CaseSensitive
+        } else {
+            crate::protos::working_copy::CaseSensitivity::CaseInsensitive
+        });
+        proto.clean_timestamp_millis_since_epoch = self.clean_timestamp.0;
 
-        let mut temp_file = NamedTempFile::new_in(&self.state_path).unwrap();
         proto.write_to_writer(temp_file.as_file_mut()).unwrap();
-        // update own write time while we before we rename it, so we know
-        // there is no unknown data in it
-        self.update_own_mtime();
         // TODO: Retry if persisting fails (it will on Windows if the file happened to
         // be open for read).
         temp_file
             .persist(self.state_path.join("tree_state"))
             .unwrap();
+        // The saved state matches the files, so there's no checkout to resume
+        self.pending_checkout = None;
+        match fs::remove_file(self.state_path.join("pending_checkout")) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                panic!("failed to remove pending checkout: {}", err)
+            }
+            _ => {}
+        }
     }
 
     fn write_file_to_store(
@@ -434,7 +958,18 @@ impl TreeState {
             message: format!("Failed to open file {}", disk_path.display()),
             err,
         })?;
-        Ok(self.store.write_file(path, &mut Box::new(file))?)
+        if self.settings.eol_conversion.mode() == EolConversionMode::None {
+            return Ok(self.store.write_file(path, &mut Box::new(file))?);
+        }
+        let mut contents = vec![];
+        (&file)
+            .read_to_end(&mut contents)
+            .map_err(|err| SnapshotError::IoError {
+                message: format!("Failed to read file {}", disk_path.display()),
+                err,
+            })?;
+        let contents = self.settings.eol_conversion.convert_input(path, contents);
+        Ok(self.store.write_file(path, &mut Cursor::new(contents))?)
     }
 
     fn write_symlink_to_store(
@@ -442,6 +977,20 @@ impl TreeState {
         path: &RepoPath,
         disk_path: &Path,
     ) -> Result<SymlinkId, SnapshotError> {
+        if !self.symlink_support() {
+            // The symlink was written as a regular file containing the target
+            let target = fs::read(disk_path).map_err(|err| SnapshotError::IoError {
+                message: format!("Failed to read file {}", disk_path.display()),
+                err,
+            })?;
+            let str_target = String::from_utf8(target).map_err(|err| {
+                SnapshotError::InvalidUtf8SymlinkTarget {
+                    path: disk_path.to_path_buf(),
+                    target: PathBuf::from(String::from_utf8_lossy(err.as_bytes()).as_ref()),
+                }
+            })?;
+            return Ok(self.store.write_symlink(path, &str_target)?);
+        }
         let target = disk_path
             .read_link()
             .map_err(|err| SnapshotError::IoError {
@@ -461,6 +1010,83 @@ impl TreeState {
     /// Look for changes to the working copy. If there are any changes, create
     /// a new tree from it and return it, and also update the dirstate on disk.
     pub fn snapshot(&mut self, base_ignores: Arc<GitIgnoreFile>) -> Result<TreeId, SnapshotError> {
+        self.snapshot_internal(base_ignores, &EverythingMatcher, &NothingMatcher)
+    }
+
+    /// Like `snapshot()`, but only looks at the files matching `matcher`. The
+    /// recorded state of other files is kept, so changes to them are picked up
+    /// by the next full snapshot.
+    pub fn snapshot_matching(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        matcher: &dyn Matcher,
+    ) -> Result<TreeId, SnapshotError> {
+        self.snapshot_internal(base_ignores, matcher, &NothingMatcher)
+    }
+
+    /// Like `snapshot()`, but also starts tracking new files matching
+    /// `matcher` even if they wouldn't be tracked automatically.
+    pub fn track(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        matcher: &dyn Matcher,
+    ) -> Result<TreeId, SnapshotError> {
+        self.snapshot_internal(base_ignores, &EverythingMatcher, matcher)
+    }
+
+    /// Like `snapshot()`, but only looks at the files that `fsmonitor` reports
+    /// as changed since the last snapshot that used it.
+    pub fn snapshot_with_fsmonitor(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        fsmonitor: &mut dyn FsMonitor,
+    ) -> Result<TreeId, SnapshotError> {
+        let changes = fsmonitor.query_changed_files(self.fsmonitor_clock.as_deref())?;
+        self.fsmonitor_clock = Some(changes.clock);
+        let changed_paths = match changes.changed_files {
+            Some(changed_files) => changed_files,
+            None => return self.snapshot(base_ignores),
+        };
+        let mut changed_repo_paths = HashSet::new();
+        for path in changed_paths {
+            let mut components = vec![];
+            for component in path.components() {
+                match component.as_os_str().to_str() {
+                    Some(name) if matches!(component, Component::Normal(_)) => {
+                        components.push(name)
+                    }
+                    // Let the full snapshot deal with (or report) odd paths
+                    _ => return self.snapshot(base_ignores),
+                }
+            }
+            if components
+                .iter()
+                .any(|name| *name == ".jj" || *name == ".git")
+            {
+                continue;
+            }
+            if components.last() == Some(&".gitignore") {
+                // Any file may have become ignored or unignored
+                return self.snapshot(base_ignores);
+            }
+            changed_repo_paths.insert(RepoPath::from_internal_string(&components.join("/")));
+        }
+        self.snapshot_internal(
+            base_ignores,
+            &FilesMatcher::new(changed_repo_paths),
+            &NothingMatcher,
+        )
+    }
+
+    /// Snapshots the files matching `matcher`, keeping the recorded state of
+    /// other files. New files are tracked if they match `track_matcher` or the
+    /// auto-track setting.
+    fn snapshot_internal(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        matcher: &dyn Matcher,
+        track_matcher: &dyn Matcher,
+    ) -> Result<TreeId, SnapshotError> {
         let sparse_matcher = self.sparse_matcher();
         let mut work = vec![(
             RepoPath::root(),
@@ -468,27 +1094,46 @@ impl TreeState {
             base_ignores,
         )];
         let mut tree_builder = self.store.tree_builder(self.tree_id.clone());
-        let mut deleted_files: HashSet<_> = self.file_states.keys().cloned().collect();
+        let mut files = vec![];
+        let mut deleted_files: HashSet<_> = self
+            .file_states
+            .keys()
+            .filter(|path| matcher.matches(path))
+            .cloned()
+            .collect();
+        // Nested repositories that were skipped, and their checked-out commits
+        let mut nested_repos = HashMap::new();
+        let mut visited_dirs = HashSet::new();
         while let Some((dir, disk_dir, git_ignore)) = work.pop() {
-            if sparse_matcher.visit(&dir).is_nothing() {
+            if sparse_matcher.visit(&dir).is_nothing() || matcher.visit(&dir).is_nothing() {
                 continue;
             }
             let git_ignore = git_ignore
                 .chain_with_file(&dir.to_internal_dir_string(), disk_dir.join(".gitignore"));
-            for maybe_entry in disk_dir.read_dir().unwrap() {
-                let entry = maybe_entry.unwrap();
-                let file_type = entry.file_type().unwrap();
-                let file_name = entry.file_name();
-                let name = file_name
-                    .to_str()
-                    .ok_or_else(|| SnapshotError::InvalidUtf8Path {
-                        path: file_name.clone(),
-                    })?;
+            // Read the mtime before listing the directory, so entries added while
+            // it's listed change the mtime we compare with next time
+            let dir_mtime = if self.settings.dir_mtime_cache {
+                fs::metadata(&disk_dir)
+                    .ok()
+                    .map(|metadata| mtime_from_metadata(&metadata))
+            } else {
+                None
+            };
+            let entries = match self.cached_dir_entries(&dir, dir_mtime.as_ref(), &git_ignore) {
+                Some(entries) => entries,
+                None => list_dir(&disk_dir)?,
+            };
+            // Whether all entries are tracked or ignored
+            let mut clean = true;
+            let mut subdirs = vec![];
+            for entry in entries {
+                let name = entry.name.as_str();
                 if name == ".jj" || name == ".git" {
                     continue;
                 }
                 let sub_path = dir.join(&RepoPathComponent::from(name));
-                if file_type.is_dir() {
+                let disk_path = disk_dir.join(name);
+                if entry.is_dir {
                     // If the whole directory is ignored, skip it unless we're already tracking
                     // some file in it.
                     if git_ignore.matches_all_files_in(&sub_path.to_internal_dir_string())
@@ -496,29 +1141,210 @@ impl TreeState {
                     {
                         continue;
                     }
-                    work.push((sub_path, entry.path(), git_ignore.clone()));
+                    // Likewise for other repositories nested in this one
+                    if self.settings.nested_repos != NestedRepos::Include
+                        && is_nested_repo(&disk_path)
+                        && !self.has_files_under(&sub_path)
+                    {
+                        let head = if self.settings.nested_repos == NestedRepos::Placeholder {
+                            nested_repo_head(&disk_path)
+                        } else {
+                            None
+                        };
+                        nested_repos.insert(sub_path, head);
+                        // The directory has to be listed to find it again
+                        clean = false;
+                        continue;
+                    }
+                    subdirs.push(entry.name.clone());
+                    work.push((sub_path, disk_path, git_ignore.clone()));
                 } else {
                     deleted_files.remove(&sub_path);
-                    if sparse_matcher.matches(&sub_path) {
-                        self.update_file_state(
+                    if !self.file_states.contains_key(&sub_path)
+                        && !git_ignore.matches_file(&sub_path.to_internal_file_string())
+                    {
+                        clean = false;
+                    }
+                    if sparse_matcher.matches(&sub_path) && matcher.matches(&sub_path) {
+                        let track_new = self.settings.auto_track.matches(&sub_path)
+                            || track_matcher.matches(&sub_path);
+                        files.push((
                             sub_path,
-                            &entry,
-                            git_ignore.as_ref(),
-                            &mut tree_builder,
-                        )?;
+                            disk_path,
+                            entry.dir_entry,
+                            git_ignore.clone(),
+                            track_new,
+                        ));
                     }
                 }
             }
+            match dir_mtime {
+                Some(mtime) if clean => {
+                    let dir_state = DirState {
+                        mtime,
+                        ignore_fingerprint: git_ignore.fingerprint().to_vec(),
+                        subdirs,
+                    };
+                    self.dir_states.insert(dir.clone(), dir_state);
+                }
+                _ => {
+                    self.dir_states.remove(&dir);
+                }
+            }
+            visited_dirs.insert(dir);
+        }
+        if matcher.visit(&RepoPath::root()) == Visit::AllRecursively {
+            // Forget the directories that are gone
+            self.dir_states.retain(|dir, _| visited_dirs.contains(dir));
+        }
+
+        // Reading and hashing the files is the slow part, so do it in parallel and
+        // record the results in the order we found the files.
+        let this = &*self;
+        let progress = ProgressReporter::start(
+            self.settings.progress_callback.as_ref(),
+            ProgressStage::Snapshotting,
+            files.len(),
+        );
+        let updates: Vec<_> = files
+            .into_par_iter()
+            .map(|(path, disk_path, dir_entry, git_ignore, track_new)| {
+                let update = this.update_file_state(
+                    &path,
+                    &disk_path,
+                    dir_entry.as_ref(),
+                    &git_ignore,
+                    track_new,
+                );
+                progress.file_processed();
+                (path, update)
+            })
+            .collect();
+        for (path, update) in updates {
+            match update? {
+                SnapshotUpdate::Unchanged => {}
+                SnapshotUpdate::Removed => {
+                    self.file_states.remove(&path);
+                    tree_builder.remove(path);
+                }
+                SnapshotUpdate::Changed { file_state, value } => {
+                    self.file_states.insert(path.clone(), file_state);
+                    tree_builder.set(path, value);
+                }
+            }
         }
 
         for file in &deleted_files {
             self.file_states.remove(file);
             tree_builder.remove(file.clone());
         }
+        // Only the Git backend can store submodules
+        if self.settings.nested_repos == NestedRepos::Placeholder && self.store.git_repo().is_some()
+        {
+            self.update_nested_repo_placeholders(
+                &mut tree_builder,
+                &nested_repos,
+                sparse_matcher.as_ref(),
+                matcher,
+            )?;
+        }
         self.tree_id = tree_builder.write_tree();
         Ok(self.tree_id.clone())
     }
 
+    /// Records the commits checked out in nested Git repositories as
+    /// submodules. Placeholders are removed when their directory no longer
+    /// contains a repository, but not when it's missing, so submodules that
+    /// haven't been cloned are kept.
+    fn update_nested_repo_placeholders(
+        &self,
+        tree_builder: &mut TreeBuilder,
+        nested_repos: &HashMap<RepoPath, Option<CommitId>>,
+        sparse_matcher: &dyn Matcher,
+        matcher: &dyn Matcher,
+    ) -> Result<(), SnapshotError> {
+        let tree = self.store.get_tree(&RepoPath::root(), &self.tree_id)?;
+        for (path, value) in tree.entries_matching(matcher) {
+            if matches!(value, TreeValue::GitSubmodule(_))
+                && sparse_matcher.matches(&path)
+                && !nested_repos.contains_key(&path)
+                && path.to_fs_path(&self.working_copy_path).exists()
+            {
+                tree_builder.remove(path);
+            }
+        }
+        for (path, head) in nested_repos {
+            if let Some(commit_id) = head {
+                if sparse_matcher.matches(path) && matcher.matches(path) {
+                    tree_builder.set(path.clone(), TreeValue::GitSubmodule(commit_id.clone()));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The entries of `dir` from its recorded state, or `None` if it has to be
+    /// listed because it may have changed since it was recorded. Those are the
+    /// tracked files in it, and the subdirectories that were snapshotted or
+    /// have tracked files in them.
+    fn cached_dir_entries(
+        &self,
+        dir: &RepoPath,
+        dir_mtime: Option<&MillisSinceEpoch>,
+        git_ignore: &GitIgnoreFile,
+    ) -> Option<Vec<DirListingEntry>> {
+        let dir_state = self.dir_states.get(dir)?;
+        // Entries may have been added within the same mtime granularity if the
+        // state was recorded after it was last saved
+        if Some(&dir_state.mtime) != dir_mtime
+            || dir_state.mtime >= self.clean_timestamp
+            || dir_state.ignore_fingerprint != git_ignore.fingerprint()
+        {
+            return None;
+        }
+        let mut entries = vec![];
+        let mut subdirs: BTreeSet<String> = dir_state.subdirs.iter().cloned().collect();
+        let depth = dir.components().len();
+        let mut next_path = dir.join(&RepoPathComponent::from("\0"));
+        while let Some((path, _)) = self
+            .file_states
+            .range((Bound::Included(&next_path), Bound::Unbounded))
+            .next()
+        {
+            if !dir.contains(path) {
+                break;
+            }
+            let name = path.components()[depth].as_str();
+            if path.components().len() == depth + 1 {
+                entries.push(DirListingEntry {
+                    name: name.to_string(),
+                    is_dir: false,
+                    dir_entry: None,
+                });
+            } else {
+                subdirs.insert(name.to_string());
+            }
+            // Skip the rest of the paths under this entry
+            next_path = dir.join(&RepoPathComponent::from(format!("{}\0", name).as_str()));
+        }
+        entries.extend(subdirs.into_iter().map(|name| DirListingEntry {
+            name,
+            is_dir: true,
+            dir_entry: None,
+        }));
+        Some(entries)
+    }
+
+    /// Forgets the recorded state of the directories containing `path`, whose
+    /// entries may no longer be tracked or may not be on disk.
+    fn invalidate_dir_states(&mut self, path: &RepoPath) {
+        let mut maybe_dir = path.parent();
+        while let Some(dir) = maybe_dir {
+            self.dir_states.remove(&dir);
+            maybe_dir = dir.parent();
+        }
+    }
+
     fn has_files_under(&self, dir: &RepoPath) -> bool {
         // TODO: This is pretty ugly... Also, we should
         // optimize it to check exactly the already-tracked files (we know that
@@ -539,56 +1365,96 @@ impl TreeState {
     }
 
     fn update_file_state(
-        &mut self,
-        repo_path: RepoPath,
-        dir_entry: &DirEntry,
+        &self,
+        repo_path: &RepoPath,
+        disk_path: &Path,
+        dir_entry: Option<&DirEntry>,
         git_ignore: &GitIgnoreFile,
-        tree_builder: &mut TreeBuilder,
-    ) -> Result<(), SnapshotError> {
-        let maybe_current_file_state = self.file_states.get_mut(&repo_path);
+        track_new: bool,
+    ) -> Result<SnapshotUpdate, SnapshotError> {
+        let maybe_current_file_state = self.file_states.get(repo_path);
         if maybe_current_file_state.is_none()
             && git_ignore.matches_file(&repo_path.to_internal_file_string())
         {
             // If it wasn't already tracked and it matches the ignored paths, then
             // ignore it.
-            return Ok(());
+            return Ok(SnapshotUpdate::Unchanged);
         }
-        let disk_path = dir_entry.path();
-        let metadata = dir_entry.metadata().map_err(|err| SnapshotError::IoError {
-            message: format!("Failed to stat file {}", disk_path.display()),
-            err,
-        })?;
+        if maybe_current_file_state.is_none() && !track_new {
+            // Leave it untracked until it's explicitly tracked
+            return Ok(SnapshotUpdate::Unchanged);
+        }
+        let maybe_metadata = match dir_entry {
+            Some(dir_entry) => dir_entry.metadata(),
+            None => disk_path.symlink_metadata(),
+        };
+        let metadata = match maybe_metadata {
+            Ok(metadata) => metadata,
+            // Found in the recorded state of its directory, but removed since
+            Err(err) if dir_entry.is_none() && err.kind() == io::ErrorKind::NotFound => {
+                return Ok(SnapshotUpdate::Removed);
+            }
+            Err(err) => {
+                return Err(SnapshotError::IoError {
+                    message: format!("Failed to stat file {}", disk_path.display()),
+                    err,
+                });
+            }
+        };
         let maybe_new_file_state = file_state(&metadata);
         match (maybe_current_file_state, maybe_new_file_state) {
             (None, None) => {
                 // Untracked Unix socket or such
+                Ok(SnapshotUpdate::Unchanged)
             }
             (Some(_), None) => {
                 // Tracked file replaced by Unix socket or such
-                self.file_states.remove(&repo_path);
-                tree_builder.remove(repo_path);
+                Ok(SnapshotUpdate::Removed)
             }
-            (None, Some(new_file_state)) => {
+            (None, Some(mut new_file_state)) => {
                 // untracked
+                if let Some(max_size) = self.settings.max_new_file_size {
+                    if new_file_state.size > max_size {
+                        return Err(SnapshotError::NewFileTooLarge {
+                            path: repo_path.clone(),
+                            size: new_file_state.size,
+                            max_size,
+                        });
+                    }
+                }
+                if !self.exec_bit_support() {
+                    // The file system's executable bit is meaningless
+                    new_file_state.mark_executable(false);
+                }
                 let file_type = new_file_state.file_type.clone();
-                self.file_states.insert(repo_path.clone(), new_file_state);
-                let file_value = self.write_path_to_store(&repo_path, &disk_path, file_type)?;
-                tree_builder.set(repo_path, file_value);
+                let file_value = self.write_path_to_store(repo_path, disk_path, file_type)?;
+                Ok(SnapshotUpdate::Changed {
+                    file_state: new_file_state,
+                    value: file_value,
+                })
             }
             (Some(current_file_state), Some(mut new_file_state)) => {
-                #[cfg(windows)]
-                {
-                    // On Windows, we preserve the state we had recorded
-                    // when we wrote the file.
+                let mut current_file_state = current_file_state.clone();
+                if !self.exec_bit_support() {
+                    // Preserve the state we had recorded when we wrote the file
                     new_file_state.mark_executable(current_file_state.is_executable());
                 }
-                // If the file's mtime was set at the same time as this state file's own mtime,
-                // then we don't know if the file was modified before or after this state file.
-                // We set the file's mtime to 0 to simplify later code.
-                if current_file_state.mtime >= self.own_mtime {
+                if !self.symlink_support()
+                    && current_file_state.file_type == FileType::Symlink
+                    && matches!(new_file_state.file_type, FileType::Normal { .. })
+                {
+                    // The symlink was written as a regular file, so it's still a
+                    // symlink if it's still a regular file.
+                    new_file_state.file_type = FileType::Symlink;
+                }
+                // If the file's state was recorded after the state was last saved (by an
+                // earlier snapshot or checkout in this process), the file may have been
+                // modified again without changing its mtime. We set the recorded mtime to
+                // 0 so the file is read again.
+                if current_file_state.mtime >= self.clean_timestamp {
                     current_file_state.mtime = MillisSinceEpoch(0);
                 }
-                let mut clean = current_file_state == &new_file_state;
+                let mut clean = current_file_state == new_file_state;
                 // Because the file system doesn't have a built-in way of indicating a conflict,
                 // we look at the current state instead. If that indicates that the path has a
                 // conflict and the contents are now a file, then we take interpret that as if
@@ -611,36 +1477,41 @@ impl TreeState {
                         if let (FileType::Conflict { id }, FileType::Normal { executable: _ }) =
                             (&current_file_state.file_type, &new_file_state.file_type)
                         {
-                            let mut file = File::open(&disk_path).unwrap();
+                            let mut file = File::open(disk_path).unwrap();
                             let mut content = vec![];
                             file.read_to_end(&mut content).unwrap();
                             if let Some(new_conflict_id) = update_conflict_from_content(
                                 self.store.as_ref(),
-                                &repo_path,
+                                repo_path,
                                 id,
                                 &content,
+                                self.settings.conflict_marker_style,
                             )
                             .unwrap()
                             {
                                 new_file_state.file_type = FileType::Conflict {
                                     id: new_conflict_id.clone(),
                                 };
-                                *current_file_state = new_file_state;
-                                tree_builder.set(repo_path, TreeValue::Conflict(new_conflict_id));
-                                return Ok(());
+                                return Ok(SnapshotUpdate::Changed {
+                                    file_state: new_file_state,
+                                    value: TreeValue::Conflict(new_conflict_id),
+                                });
                             }
                         }
                     }
                 }
                 if !clean {
                     let file_type = new_file_state.file_type.clone();
-                    *current_file_state = new_file_state;
-                    let file_value = self.write_path_to_store(&repo_path, &disk_path, file_type)?;
-                    tree_builder.set(repo_path, file_value);
+                    let file_value = self.write_path_to_store(repo_path, disk_path, file_type)?;
+                    Ok(SnapshotUpdate::Changed {
+                        file_state: new_file_state,
+                        value: file_value,
+                    })
+                } else {
+                    Ok(SnapshotUpdate::Unchanged)
                 }
             }
-        };
-        Ok(())
+        }
     }
 
     fn write_path_to_store(
@@ -670,6 +1541,9 @@ impl TreeState {
         executable: bool,
     ) -> Result<FileState, CheckoutError> {
         create_parent_dirs(&self.working_copy_path, path)?;
+        if let Some(file_state) = self.try_reflink_file(disk_path, path, id, executable)? {
+            return Ok(file_state);
+        }
         let mut file = OpenOptions::new()
             .write(true)
             .create_new(true) // Don't overwrite un-ignored file. Don't follow symlink.
@@ -679,11 +1553,26 @@ impl TreeState {
                 err,
             })?;
         let mut contents = self.store.read_file(path, id)?;
-        let size =
-            std::io::copy(&mut contents, &mut file).map_err(|err| CheckoutError::IoError {
+        let size = if self.settings.eol_conversion.mode() == EolConversionMode::InputOutput {
+            let mut buf = vec![];
+            contents
+                .read_to_end(&mut buf)
+                .map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to read file {}", path.to_internal_file_string()),
+                    err,
+                })?;
+            let buf = self.settings.eol_conversion.convert_output(path, buf);
+            file.write_all(&buf).map_err(|err| CheckoutError::IoError {
                 message: format!("Failed to write file {}", disk_path.display()),
                 err,
             })?;
+            buf.len() as u64
+        } else {
+            std::io::copy(&mut contents, &mut file).map_err(|err| CheckoutError::IoError {
+                message: format!("Failed to write file {}", disk_path.display()),
+                err,
+            })?
+        };
         self.set_executable(disk_path, executable)?;
         // Read the file state from the file descriptor. That way, know that the file
         // exists and is of the expected type, and the stat information is most likely
@@ -695,7 +1584,61 @@ impl TreeState {
         Ok(FileState::for_file(executable, size, &metadata))
     }
 
-    #[cfg_attr(windows, allow(unused_variables))]
+    /// Checks out the file by cloning it from the file cache, adding it to the
+    /// cache first if needed. Returns `None` if the file should be written
+    /// normally instead.
+    fn try_reflink_file(
+        &self,
+        disk_path: &Path,
+        path: &RepoPath,
+        id: &FileId,
+        executable: bool,
+    ) -> Result<Option<FileState>, CheckoutError> {
+        // Converted files differ from the cached contents
+        if !self.settings.reflink
+            || self.settings.eol_conversion.mode() == EolConversionMode::InputOutput
+        {
+            return Ok(None);
+        }
+        let cache_dir = self.state_path.join("file_cache");
+        let cache_path = cache_dir.join(id.hex());
+        if !cache_path.exists() {
+            let write_to_cache = || -> io::Result<()> {
+                fs::create_dir_all(&cache_dir)?;
+                let mut temp_file = NamedTempFile::new_in(&cache_dir)?;
+                let mut contents = self
+                    .store
+                    .read_file(path, id)
+                    .map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+                std::io::copy(&mut contents, temp_file.as_file_mut())?;
+                persist_content_addressed_temp_file(temp_file, &cache_path)?;
+                Ok(())
+            };
+            if write_to_cache().is_err() {
+                return Ok(None);
+            }
+        }
+        match reflink(&cache_path, disk_path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists => {
+                return Err(CheckoutError::IoError {
+                    message: format!("Failed to open file {} for writing", disk_path.display()),
+                    err,
+                });
+            }
+            Err(_) => return Ok(None),
+        }
+        self.set_executable(disk_path, executable)?;
+        let metadata = disk_path
+            .symlink_metadata()
+            .map_err(|err| CheckoutError::for_stat_error(err, disk_path))?;
+        Ok(Some(FileState::for_file(
+            executable,
+            metadata.len(),
+            &metadata,
+        )))
+    }
+
     fn write_symlink(
         &self,
         disk_path: &Path,
@@ -704,22 +1647,43 @@ impl TreeState {
     ) -> Result<FileState, CheckoutError> {
         create_parent_dirs(&self.working_copy_path, path)?;
         let target = self.store.read_symlink(path, id)?;
-        #[cfg(windows)]
-        {
-            println!("ignoring symlink at {:?}", path);
+        if !self.symlink_support() {
+            // Like Git with `core.symlinks=false`, write the target to a regular file
+            let mut file = OpenOptions::new()
+                .write(true)
+                .create_new(true) // Don't overwrite un-ignored file. Don't follow symlink.
+                .open(disk_path)
+                .map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to open file {} for writing", disk_path.display()),
+                    err,
+                })?;
+            file.write_all(target.as_bytes())
+                .map_err(|err| CheckoutError::IoError {
+                    message: format!("Failed to write file {}", disk_path.display()),
+                    err,
+                })?;
+            let metadata = file
+                .metadata()
+                .map_err(|err| CheckoutError::for_stat_error(err, disk_path))?;
+            return Ok(FileState::for_symlink(&metadata));
         }
+        let target = PathBuf::from(&target);
         #[cfg(unix)]
-        {
-            let target = PathBuf::from(&target);
-            symlink(&target, disk_path).map_err(|err| CheckoutError::IoError {
-                message: format!(
-                    "Failed to create symlink from {} to {}",
-                    disk_path.display(),
-                    target.display()
-                ),
-                err,
-            })?;
-        }
+        let result = symlink(&target, disk_path);
+        #[cfg(windows)]
+        let result = if disk_path.parent().unwrap().join(&target).is_dir() {
+            std::os::windows::fs::symlink_dir(&target, disk_path)
+        } else {
+            std::os::windows::fs::symlink_file(&target, disk_path)
+        };
+        result.map_err(|err| CheckoutError::IoError {
+            message: format!(
+                "Failed to create symlink from {} to {}",
+                disk_path.display(),
+                target.display()
+            ),
+            err,
+        })?;
         let metadata = disk_path
             .symlink_metadata()
             .map_err(|err| CheckoutError::for_stat_error(err, disk_path))?;
@@ -743,8 +1707,14 @@ impl TreeState {
                 err,
             })?;
         let mut conflict_data = vec![];
-        materialize_conflict(self.store.as_ref(), path, &conflict, &mut conflict_data)
-            .expect("Failed to materialize conflict to in-memory buffer");
+        materialize_conflict(
+            self.store.as_ref(),
+            path,
+            &conflict,
+            self.settings.conflict_marker_style,
+            &mut conflict_data,
+        )
+        .expect("Failed to materialize conflict to in-memory buffer");
         file.write_all(&conflict_data)
             .map_err(|err| CheckoutError::IoError {
                 message: format!("Failed to write conflict to file {}", disk_path.display()),
@@ -761,6 +1731,9 @@ impl TreeState {
 
     #[cfg_attr(windows, allow(unused_variables))]
     fn set_executable(&self, disk_path: &Path, executable: bool) -> Result<(), CheckoutError> {
+        if !self.exec_bit_support() {
+            return Ok(());
+        }
         #[cfg(unix)]
         {
             let mode = if executable { 0o755 } else { 0o644 };
@@ -770,34 +1743,93 @@ impl TreeState {
         Ok(())
     }
 
-    pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
-        let old_tree = self
-            .store
+    /// The tree of a checkout that was interrupted before its state was saved,
+    /// leaving the files partially updated.
+    pub fn pending_checkout(&self) -> Option<&TreeId> {
+        self.pending_checkout.as_ref()
+    }
+
+    fn current_tree(&self) -> Result<Tree, CheckoutError> {
+        self.store
             .get_tree(&RepoPath::root(), &self.tree_id)
             .map_err(|err| match err {
                 BackendError::NotFound => CheckoutError::SourceNotFound,
                 other => CheckoutError::InternalBackendError(other),
-            })?;
-        let stats = self.update(&old_tree, new_tree, self.sparse_matcher().as_ref(), Err)?;
+            })
+    }
+
+    pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
+        self.resume_checkout()?;
+        let old_tree = self.current_tree()?;
+        let matcher = self.sparse_matcher();
+        if !self.case_sensitive {
+            // Check before touching any files, so we don't overwrite one file with another
+            let added_paths = old_tree
+                .diff(new_tree, matcher.as_ref())
+                .filter(|(_path, diff)| matches!(diff, Diff::Added(_)))
+                .map(|(path, _diff)| path);
+            check_case_collisions(new_tree, added_paths, matcher.as_ref())?;
+        }
+        self.write_pending_checkout(new_tree.id());
+        let stats = self.update(&old_tree, new_tree, matcher.as_ref(), Err)?;
         self.tree_id = new_tree.id().clone();
         Ok(stats)
     }
 
+    /// Finishes updating the files for a checkout that was interrupted. Returns
+    /// `None` if there was no such checkout.
+    ///
+    /// Files the interrupted checkout added are kept as they are, since they
+    /// may also be untracked files that were in the way. They're read again on
+    /// the next snapshot, so a partially written file shows up as modified.
+    pub fn resume_checkout(&mut self) -> Result<Option<CheckoutStats>, CheckoutError> {
+        let new_tree_id = match &self.pending_checkout {
+            Some(new_tree_id) => new_tree_id.clone(),
+            None => return Ok(None),
+        };
+        let old_tree = self.current_tree()?;
+        let new_tree = self.store.get_tree(&RepoPath::root(), &new_tree_id)?;
+        let matcher = self.sparse_matcher();
+        let stats = self.update(
+            &old_tree,
+            &new_tree,
+            matcher.as_ref(),
+            suppress_file_exists_error,
+        )?;
+        for (path, _diff) in old_tree.diff(&new_tree, matcher.as_ref()) {
+            if self.file_states.contains_key(&path) {
+                continue;
+            }
+            let disk_path = path.to_fs_path(&self.working_copy_path);
+            let metadata = match disk_path.symlink_metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if let Some(mut file_state) = file_state(&metadata) {
+                // Make sure the next snapshot reads the file
+                file_state.mtime = MillisSinceEpoch(0);
+                self.file_states.insert(path, file_state);
+            }
+        }
+        self.tree_id = new_tree_id;
+        Ok(Some(stats))
+    }
+
     pub fn set_sparse_patterns(
         &mut self,
         sparse_patterns: Vec<RepoPath>,
     ) -> Result<CheckoutStats, CheckoutError> {
-        let tree = self
-            .store
-            .get_tree(&RepoPath::root(), &self.tree_id)
-            .map_err(|err| match err {
-                BackendError::NotFound => CheckoutError::SourceNotFound,
-                other => CheckoutError::InternalBackendError(other),
-            })?;
+        let tree = self.current_tree()?;
         let old_matcher = PrefixMatcher::new(&self.sparse_patterns);
         let new_matcher = PrefixMatcher::new(&sparse_patterns);
         let added_matcher = DifferenceMatcher::new(&new_matcher, &old_matcher);
         let removed_matcher = DifferenceMatcher::new(&old_matcher, &new_matcher);
+        if !self.case_sensitive {
+            let added_paths = tree
+                .entries_matching(&added_matcher)
+                .map(|(path, _value)| path);
+            check_case_collisions(&tree, added_paths, &new_matcher)?;
+        }
         let empty_tree = Tree::null(self.store.clone(), RepoPath::root());
         let added_stats = self.update(
             &empty_tree,
@@ -830,90 +1862,118 @@ impl TreeState {
             added_files: 0,
             removed_files: 0,
         };
-        let mut apply_diff = |path: RepoPath, diff: Diff<TreeValue>| -> Result<(), CheckoutError> {
-            let disk_path = path.to_fs_path(&self.working_copy_path);
-
-            // TODO: Check that the file has not changed before overwriting/removing it.
-            match diff {
-                Diff::Removed(_before) => {
-                    fs::remove_file(&disk_path).ok();
-                    let mut parent_dir = disk_path.parent().unwrap();
-                    loop {
-                        if fs::remove_dir(&parent_dir).is_err() {
-                            break;
-                        }
-                        parent_dir = parent_dir.parent().unwrap();
+        // Remove files first, so files and directories that replace them can be
+        // written in any order.
+        let mut diffs = vec![];
+        for (path, diff) in old_tree.diff(new_tree, matcher) {
+            self.invalidate_dir_states(&path);
+            if let Diff::Removed(_before) = diff {
+                let disk_path = path.to_fs_path(&self.working_copy_path);
+                // TODO: Check that the file has not changed before removing it.
+                fs::remove_file(&disk_path).ok();
+                let mut parent_dir = disk_path.parent().unwrap();
+                loop {
+                    if fs::remove_dir(parent_dir).is_err() {
+                        break;
                     }
-                    self.file_states.remove(&path);
-                    stats.removed_files += 1;
+                    parent_dir = parent_dir.parent().unwrap();
                 }
-                Diff::Added(after) => {
-                    let file_state = match after {
-                        TreeValue::Normal { id, executable } => {
-                            self.write_file(&disk_path, &path, &id, executable)?
-                        }
-                        TreeValue::Symlink(id) => self.write_symlink(&disk_path, &path, &id)?,
-                        TreeValue::Conflict(id) => self.write_conflict(&disk_path, &path, &id)?,
-                        TreeValue::GitSubmodule(_id) => {
-                            println!("ignoring git submodule at {:?}", path);
-                            return Ok(());
-                        }
-                        TreeValue::Tree(_id) => {
-                            panic!("unexpected tree entry in diff at {:?}", path);
-                        }
-                    };
+                self.file_states.remove(&path);
+                stats.removed_files += 1;
+            } else {
+                diffs.push((path, diff));
+            }
+        }
+
+        // Writing files is mostly waiting for IO, so write them in parallel and
+        // record the results in path order.
+        let this = &*self;
+        let progress = ProgressReporter::start(
+            self.settings.progress_callback.as_ref(),
+            ProgressStage::CheckingOut,
+            diffs.len(),
+        );
+        let results: Vec<_> = diffs
+            .into_par_iter()
+            .map(|(path, diff)| {
+                let added = matches!(diff, Diff::Added(_));
+                let result = this.write_diff(&path, diff);
+                progress.file_processed();
+                (path, added, result)
+            })
+            .collect();
+        for (path, added, result) in results {
+            match result {
+                Ok(FileUpdate::Written(file_state)) => {
                     self.file_states.insert(path, file_state);
-                    stats.added_files += 1;
                 }
-                Diff::Modified(
-                    TreeValue::Normal {
-                        id: old_id,
-                        executable: old_executable,
-                    },
-                    TreeValue::Normal { id, executable },
-                ) if id == old_id => {
-                    // Optimization for when only the executable bit changed
-                    assert_ne!(executable, old_executable);
-                    self.set_executable(&disk_path, executable)?;
+                Ok(FileUpdate::ExecutableChanged(executable)) => {
                     let file_state = self.file_states.get_mut(&path).unwrap();
                     file_state.mark_executable(executable);
-                    stats.updated_files += 1;
                 }
-                Diff::Modified(before, after) => {
-                    fs::remove_file(&disk_path).ok();
-                    let file_state = match (before, after) {
-                        (_, TreeValue::Normal { id, executable }) => {
-                            self.write_file(&disk_path, &path, &id, executable)?
-                        }
-                        (_, TreeValue::Symlink(id)) => {
-                            self.write_symlink(&disk_path, &path, &id)?
-                        }
-                        (_, TreeValue::Conflict(id)) => {
-                            self.write_conflict(&disk_path, &path, &id)?
-                        }
-                        (_, TreeValue::GitSubmodule(_id)) => {
-                            println!("ignoring git submodule at {:?}", path);
-                            self.file_states.remove(&path);
-                            return Ok(());
-                        }
-                        (_, TreeValue::Tree(_id)) => {
-                            panic!("unexpected tree entry in diff at {:?}", path);
-                        }
-                    };
-
-                    self.file_states.insert(path, file_state);
-                    stats.updated_files += 1;
+                Ok(FileUpdate::Skipped) => {
+                    self.file_states.remove(&path);
+                    continue;
+                }
+                Err(err) => {
+                    handle_error(err)?;
+                    continue;
                 }
             }
-            Ok(())
-        };
-
-        for (path, diff) in old_tree.diff(new_tree, matcher) {
-            apply_diff(path, diff).or_else(&mut handle_error)?;
+            if added {
+                stats.added_files += 1;
+            } else {
+                stats.updated_files += 1;
+            }
         }
         Ok(stats)
     }
 
+    /// Writes the new value of an added or modified path to the working copy.
+    fn write_diff(
+        &self,
+        path: &RepoPath,
+        diff: Diff<TreeValue>,
+    ) -> Result<FileUpdate, CheckoutError> {
+        let disk_path = path.to_fs_path(&self.working_copy_path);
+        // TODO: Check that the file has not changed before overwriting it.
+        let after = match diff {
+            Diff::Removed(_before) => panic!("removed path {:?} should have been handled", path),
+            Diff::Added(after) => after,
+            Diff::Modified(
+                TreeValue::Normal {
+                    id: old_id,
+                    executable: old_executable,
+                },
+                TreeValue::Normal { id, executable },
+            ) if id == old_id => {
+                // Optimization for when only the executable bit changed
+                assert_ne!(executable, old_executable);
+                self.set_executable(&disk_path, executable)?;
+                return Ok(FileUpdate::ExecutableChanged(executable));
+            }
+            Diff::Modified(_before, after) => {
+                fs::remove_file(&disk_path).ok();
+                after
+            }
+        };
+        let file_state = match after {
+            TreeValue::Normal { id, executable } => {
+                self.write_file(&disk_path, path, &id, executable)?
+            }
+            TreeValue::Symlink(id) => self.write_symlink(&disk_path, path, &id)?,
+            TreeValue::Conflict(id) => self.write_conflict(&disk_path, path, &id)?,
+            TreeValue::GitSubmodule(_id) => {
+                println!("ignoring git submodule at {:?}", path);
+                return Ok(FileUpdate::Skipped);
+            }
+            TreeValue::Tree(_id) => {
+                panic!("unexpected tree entry in diff at {:?}", path);
+            }
+        };
+        Ok(FileUpdate::Written(file_state))
+    }
+
     pub fn reset(&mut self, new_tree: &Tree) -> Result<(), ResetError> {
         let old_tree = self
             .store
@@ -923,7 +1983,10 @@ impl TreeState {
                 other => ResetError::InternalBackendError(other),
             })?;
 
+        // The files are assumed to match the new tree already
+        self.pending_checkout = None;
         for (path, diff) in old_tree.diff(new_tree, self.sparse_matcher().as_ref()) {
+            self.invalidate_dir_states(&path);
             match diff {
                 Diff::Removed(_before) => {
                     self.file_states.remove(&path);
@@ -953,6 +2016,17 @@ impl TreeState {
         self.tree_id = new_tree.id().clone();
         Ok(())
     }
+
+    fn remap_ids(&mut self, tree_id: TreeId, conflict_ids: &HashMap<ConflictId, ConflictId>) {
+        self.tree_id = tree_id;
+        for file_state in self.file_states.values_mut() {
+            if let FileType::Conflict { id } = &mut file_state.file_type {
+                if let Some(new_id) = conflict_ids.get(id) {
+                    *id = new_id.clone();
+                }
+            }
+        }
+    }
 }
 
 pub struct WorkingCopy {
@@ -962,8 +2036,14 @@ pub struct WorkingCopy {
     operation_id: RefCell<Option<OperationId>>,
     workspace_id: RefCell<Option<WorkspaceId>>,
     tree_state: RefCell<Option<TreeState>>,
+    settings: WorkingCopySettings,
+    lock_timeout: Option<Duration>,
 }
 
+/// How long to wait for another process to release the working copy's lock by
+/// default.
+const DEFAULT_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
 impl WorkingCopy {
     /// Initializes a new working copy at `working_copy_path`. The working
     /// copy's state will be stored in the `state_path` directory. The working
@@ -991,6 +2071,8 @@ impl WorkingCopy {
             operation_id: RefCell::new(Some(operation_id)),
             workspace_id: RefCell::new(Some(workspace_id)),
             tree_state: RefCell::new(None),
+            settings: WorkingCopySettings::default(),
+            lock_timeout: Some(DEFAULT_LOCK_TIMEOUT),
         }
     }
 
@@ -1002,6 +2084,8 @@ impl WorkingCopy {
             operation_id: RefCell::new(None),
             workspace_id: RefCell::new(None),
             tree_state: RefCell::new(None),
+            settings: WorkingCopySettings::default(),
+            lock_timeout: Some(DEFAULT_LOCK_TIMEOUT),
         }
     }
 
@@ -1013,6 +2097,24 @@ impl WorkingCopy {
         &self.state_path
     }
 
+    pub fn settings(&self) -> &WorkingCopySettings {
+        &self.settings
+    }
+
+    /// Sets how the working copy is checked out and snapshotted.
+    pub fn set_settings(&mut self, settings: WorkingCopySettings) {
+        if let Some(tree_state) = self.tree_state.get_mut() {
+            tree_state.set_settings(settings.clone());
+        }
+        self.settings = settings;
+    }
+
+    /// Sets how long to wait for another process to release the working
+    /// copy's lock in `try_start_mutation()`, or `None` to wait forever.
+    pub fn set_lock_timeout(&mut self, lock_timeout: Option<Duration>) {
+        self.lock_timeout = lock_timeout;
+    }
+
     fn write_proto(&self, proto: crate::protos::working_copy::Checkout) {
         let mut temp_file = NamedTempFile::new_in(&self.state_path).unwrap();
         proto.write_to_writer(temp_file.as_file_mut()).unwrap();
@@ -1055,11 +2157,13 @@ impl WorkingCopy {
 
     fn tree_state(&self) -> RefMut<Option<TreeState>> {
         if self.tree_state.borrow().is_none() {
-            self.tree_state.replace(Some(TreeState::load(
+            let mut tree_state = TreeState::load(
                 self.store.clone(),
                 self.working_copy_path.clone(),
                 self.state_path.clone(),
-            )));
+            );
+            tree_state.set_settings(self.settings.clone());
+            self.tree_state.replace(Some(tree_state));
         }
         self.tree_state.borrow_mut()
     }
@@ -1076,6 +2180,16 @@ impl WorkingCopy {
         self.tree_state().as_ref().unwrap().file_states().clone()
     }
 
+    /// Whether a checkout was interrupted before it finished updating the
+    /// files. It can be resumed with `LockedWorkingCopy::resume_checkout()`.
+    pub fn has_pending_checkout(&self) -> bool {
+        self.tree_state()
+            .as_ref()
+            .unwrap()
+            .pending_checkout()
+            .is_some()
+    }
+
     pub fn sparse_patterns(&self) -> Vec<RepoPath> {
         self.tree_state()
             .as_ref()
@@ -1091,9 +2205,20 @@ impl WorkingCopy {
         self.write_proto(proto);
     }
 
+    /// Locks the working copy. Panics if the lock can't be taken, e.g. because
+    /// another process holds it for longer than the lock timeout.
     pub fn start_mutation(&mut self) -> LockedWorkingCopy {
+        match self.try_start_mutation() {
+            Ok(locked_wc) => locked_wc,
+            Err(err) => panic!("failed to lock the working copy: {}", err),
+        }
+    }
+
+    /// Locks the working copy, waiting for another process to release the lock
+    /// for up to the lock timeout.
+    pub fn try_start_mutation(&mut self) -> Result<LockedWorkingCopy<'_>, FileLockError> {
         let lock_path = self.state_path.join("working_copy.lock");
-        let lock = FileLock::lock(lock_path);
+        let lock = FileLock::lock_with_timeout(lock_path, self.lock_timeout)?;
 
         // Re-read from disk after taking the lock
         self.load_proto();
@@ -1103,13 +2228,13 @@ impl WorkingCopy {
         let old_operation_id = self.operation_id();
         let old_tree_id = self.current_tree_id();
 
-        LockedWorkingCopy {
+        Ok(LockedWorkingCopy {
             wc: self,
             lock,
             old_operation_id,
             old_tree_id,
             closed: false,
-        }
+        })
     }
 
     pub fn check_out(
@@ -1118,7 +2243,7 @@ impl WorkingCopy {
         old_tree_id: Option<&TreeId>,
         new_tree: &Tree,
     ) -> Result<CheckoutStats, CheckoutError> {
-        let mut locked_wc = self.start_mutation();
+        let mut locked_wc = self.try_start_mutation()?;
         // Check if the current checkout has changed on disk compared to what the caller
         // expected. It's safe to check out another commit regardless, but it's
         // probably not what  the caller wanted, so we let them know.
@@ -1128,7 +2253,13 @@ impl WorkingCopy {
                 return Err(CheckoutError::ConcurrentCheckout);
             }
         }
-        let stats = locked_wc.check_out(new_tree)?;
+        let stats = match locked_wc.check_out(new_tree) {
+            Ok(stats) => stats,
+            Err(err) => {
+                locked_wc.discard();
+                return Err(err);
+            }
+        };
         locked_wc.finish(operation_id);
         Ok(stats)
     }
@@ -1167,17 +2298,69 @@ impl LockedWorkingCopy<'_> {
             .snapshot(base_ignores)
     }
 
+    /// Snapshots only the files matching `matcher`.
+    pub fn snapshot_matching(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        matcher: &dyn Matcher,
+    ) -> Result<TreeId, SnapshotError> {
+        self.wc
+            .tree_state()
+            .as_mut()
+            .unwrap()
+            .snapshot_matching(base_ignores, matcher)
+    }
+
+    pub fn track(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        matcher: &dyn Matcher,
+    ) -> Result<TreeId, SnapshotError> {
+        self.wc
+            .tree_state()
+            .as_mut()
+            .unwrap()
+            .track(base_ignores, matcher)
+    }
+
+    pub fn snapshot_with_fsmonitor(
+        &mut self,
+        base_ignores: Arc<GitIgnoreFile>,
+        fsmonitor: &mut dyn FsMonitor,
+    ) -> Result<TreeId, SnapshotError> {
+        self.wc
+            .tree_state()
+            .as_mut()
+            .unwrap()
+            .snapshot_with_fsmonitor(base_ignores, fsmonitor)
+    }
+
     pub fn check_out(&mut self, new_tree: &Tree) -> Result<CheckoutStats, CheckoutError> {
-        // TODO: Write a "pending_checkout" file with the new TreeId so we can
-        // continue an interrupted update if we find such a file.
         let stats = self.wc.tree_state().as_mut().unwrap().check_out(new_tree)?;
         Ok(stats)
     }
 
+    /// Finishes updating the files for a checkout that was interrupted, e.g.
+    /// by a crash. Returns `None` if there was no such checkout.
+    pub fn resume_checkout(&mut self) -> Result<Option<CheckoutStats>, CheckoutError> {
+        self.wc.tree_state().as_mut().unwrap().resume_checkout()
+    }
+
     pub fn reset(&mut self, new_tree: &Tree) -> Result<(), ResetError> {
         self.wc.tree_state().as_mut().unwrap().reset(new_tree)
     }
 
+    /// Updates the recorded tree and conflict ids after the repo's objects
+    /// were rewritten with new ids, e.g. by a backend migration. The files on
+    /// disk are assumed to be unchanged.
+    pub fn remap_ids(&mut self, tree_id: TreeId, conflict_ids: &HashMap<ConflictId, ConflictId>) {
+        self.wc
+            .tree_state()
+            .as_mut()
+            .unwrap()
+            .remap_ids(tree_id, conflict_ids)
+    }
+
     pub fn sparse_patterns(&self) -> Vec<RepoPath> {
         self.wc.sparse_patterns()
     }
@@ -1199,7 +2382,6 @@ impl LockedWorkingCopy<'_> {
         self.wc.tree_state().as_mut().unwrap().save();
         self.wc.operation_id.replace(Some(operation_id));
         self.wc.save();
-        // TODO: Clear the "pending_checkout" file here.
         self.closed = true;
     }
 
@@ -19,11 +19,11 @@ use std::sync::Arc;
 
 use thiserror::Error;
 
-use crate::backend::Backend;
+use crate::backend::{Backend, BackendError};
 use crate::git_backend::GitBackend;
 use crate::local_backend::LocalBackend;
 use crate::op_store::WorkspaceId;
-use crate::repo::{BackendFactories, ReadonlyRepo, RepoLoader};
+use crate::repo::{BackendFactories, ReadonlyRepo, RepoLoader, StoreLoadError};
 use crate::settings::UserSettings;
 use crate::working_copy::WorkingCopy;
 
@@ -31,6 +31,8 @@ use crate::working_copy::WorkingCopy;
 pub enum WorkspaceInitError {
     #[error("The destination repo ({0}) already exists")]
     DestinationExists(PathBuf),
+    #[error(transparent)]
+    Backend(#[from] BackendError),
 }
 
 #[derive(Error, Debug, PartialEq, Eq)]
@@ -39,16 +41,22 @@ pub enum WorkspaceLoadError {
     RepoDoesNotExist(PathBuf),
     #[error("There is no Jujutsu repo in {0}")]
     NoWorkspaceHere(PathBuf),
+    #[error(transparent)]
+    StoreLoadError(#[from] StoreLoadError),
 }
 
 /// Represents a workspace, i.e. what's typically the .jj/ directory and its
 /// parent.
+///
+/// A bare workspace has a repo but no working copy (there's no
+/// .jj/working_copy/ directory). It's meant for servers and automation that
+/// only need to read and exchange commits.
 pub struct Workspace {
     // Path to the workspace root (typically the parent of a .jj/ directory), which is where
     // working copy files live.
     workspace_root: PathBuf,
     repo_loader: RepoLoader,
-    working_copy: WorkingCopy,
+    working_copy: Option<WorkingCopy>,
 }
 
 fn create_jj_dir(workspace_root: &Path) -> Result<PathBuf, WorkspaceInitError> {
@@ -67,7 +75,7 @@ fn init_working_copy(
     workspace_root: &Path,
     jj_dir: &Path,
     workspace_id: WorkspaceId,
-) -> (WorkingCopy, Arc<ReadonlyRepo>) {
+) -> Result<(WorkingCopy, Arc<ReadonlyRepo>), BackendError> {
     let working_copy_state_path = jj_dir.join("working_copy");
     std::fs::create_dir(&working_copy_state_path).unwrap();
 
@@ -76,21 +84,26 @@ fn init_working_copy(
         workspace_id.clone(),
         user_settings,
         &repo.store().root_commit(),
-    );
+    )?;
     let repo = tx.commit();
 
-    let working_copy = WorkingCopy::init(
+    let mut working_copy = WorkingCopy::init(
         repo.store().clone(),
         workspace_root.to_path_buf(),
         working_copy_state_path,
         repo.op_id().clone(),
         workspace_id,
     );
-    (working_copy, repo)
+    working_copy.set_settings(user_settings.working_copy_settings());
+    Ok((working_copy, repo))
 }
 
 impl Workspace {
-    fn new(workspace_root: &Path, working_copy: WorkingCopy, repo_loader: RepoLoader) -> Workspace {
+    fn new(
+        workspace_root: &Path,
+        working_copy: Option<WorkingCopy>,
+        repo_loader: RepoLoader,
+    ) -> Workspace {
         let workspace_root = workspace_root.canonicalize().unwrap();
         Workspace {
             workspace_root,
@@ -146,9 +159,25 @@ impl Workspace {
             workspace_root,
             &jj_dir,
             WorkspaceId::default(),
-        );
+        )?;
         let repo_loader = repo.loader();
-        let workspace = Workspace::new(workspace_root, working_copy, repo_loader);
+        let workspace = Workspace::new(workspace_root, Some(working_copy), repo_loader);
+        Ok((workspace, repo))
+    }
+
+    /// Initializes a bare workspace, i.e. a repo without a working copy. Nothing
+    /// is checked out in it, so only commands that don't need a working copy
+    /// can be used with it.
+    pub fn init_bare(
+        user_settings: &UserSettings,
+        workspace_root: &Path,
+        backend_factory: impl FnOnce(&Path) -> Box<dyn Backend>,
+    ) -> Result<(Self, Arc<ReadonlyRepo>), WorkspaceInitError> {
+        let jj_dir = create_jj_dir(workspace_root)?;
+        let repo_dir = jj_dir.join("repo");
+        std::fs::create_dir(&repo_dir).unwrap();
+        let repo = ReadonlyRepo::init(user_settings, &repo_dir, backend_factory);
+        let workspace = Workspace::new(workspace_root, None, repo.loader());
         Ok((workspace, repo))
     }
 
@@ -167,11 +196,19 @@ impl Workspace {
             .unwrap();
 
         let (working_copy, repo) =
-            init_working_copy(user_settings, repo, workspace_root, &jj_dir, workspace_id);
-        let workspace = Workspace::new(workspace_root, working_copy, repo.loader());
+            init_working_copy(user_settings, repo, workspace_root, &jj_dir, workspace_id)?;
+        let workspace = Workspace::new(workspace_root, Some(working_copy), repo.loader());
         Ok((workspace, repo))
     }
 
+    /// Finds the root of the workspace that `workspace_path` is in, without
+    /// loading the repo.
+    pub fn find_root(workspace_path: &Path) -> Result<PathBuf, WorkspaceLoadError> {
+        let jj_dir = find_jj_dir(workspace_path)
+            .ok_or_else(|| WorkspaceLoadError::NoWorkspaceHere(workspace_path.to_owned()))?;
+        Ok(jj_dir.parent().unwrap().canonicalize().unwrap())
+    }
+
     pub fn load(
         user_settings: &UserSettings,
         workspace_path: &Path,
@@ -193,22 +230,40 @@ impl Workspace {
                 return Err(WorkspaceLoadError::RepoDoesNotExist(repo_dir));
             }
         }
-        let repo_loader = RepoLoader::init(user_settings, &repo_dir, backend_factories);
+        let repo_loader = RepoLoader::init(user_settings, &repo_dir, backend_factories)?;
         let working_copy_state_path = jj_dir.join("working_copy");
-        let working_copy = WorkingCopy::load(
+        if !working_copy_state_path.is_dir() {
+            return Ok(Workspace::new(&workspace_root, None, repo_loader));
+        }
+        let mut working_copy = WorkingCopy::load(
             repo_loader.store().clone(),
             workspace_root.clone(),
             working_copy_state_path,
         );
-        Ok(Workspace::new(&workspace_root, working_copy, repo_loader))
+        working_copy.set_settings(user_settings.working_copy_settings());
+        Ok(Workspace::new(
+            &workspace_root,
+            Some(working_copy),
+            repo_loader,
+        ))
     }
 
     pub fn workspace_root(&self) -> &PathBuf {
         &self.workspace_root
     }
 
+    /// Returns the ID of the workspace. Bare workspaces use the default ID,
+    /// but nothing is checked out for it.
     pub fn workspace_id(&self) -> WorkspaceId {
-        self.working_copy.workspace_id()
+        match &self.working_copy {
+            Some(working_copy) => working_copy.workspace_id(),
+            None => WorkspaceId::default(),
+        }
+    }
+
+    /// Whether this is a bare workspace, i.e. one without a working copy.
+    pub fn is_bare(&self) -> bool {
+        self.working_copy.is_none()
     }
 
     pub fn repo_path(&self) -> &PathBuf {
@@ -219,12 +274,18 @@ impl Workspace {
         &self.repo_loader
     }
 
+    /// Panics if the workspace is bare.
     pub fn working_copy(&self) -> &WorkingCopy {
-        &self.working_copy
+        self.working_copy
+            .as_ref()
+            .expect("bare workspace has no working copy")
     }
 
+    /// Panics if the workspace is bare.
     pub fn working_copy_mut(&mut self) -> &mut WorkingCopy {
-        &mut self.working_copy
+        self.working_copy
+            .as_mut()
+            .expect("bare workspace has no working copy")
     }
 }
 
@@ -13,7 +13,10 @@
 // limitations under the License.
 
 use jujutsu_lib::backend::{Conflict, ConflictPart, TreeValue};
-use jujutsu_lib::conflicts::{materialize_conflict, parse_conflict, update_conflict_from_content};
+use jujutsu_lib::conflicts::{
+    conflict_kind, materialize_conflict, parse_conflict, update_conflict_from_content,
+    ConflictKind, ConflictMarkerStyle,
+};
 use jujutsu_lib::files::MergeHunk;
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::store::Store;
@@ -65,6 +68,7 @@ line 5
                 id: base_id,
                 executable: false,
             },
+            label: None,
         }],
         adds: vec![
             ConflictPart {
@@ -72,12 +76,14 @@ line 5
                     id: left_id,
                     executable: false,
                 },
+                label: None,
             },
             ConflictPart {
                 value: TreeValue::Normal {
                     id: right_id,
                     executable: false,
                 },
+                label: None,
             },
         ],
     };
@@ -121,6 +127,218 @@ line 5
     );
 }
 
+#[test]
+fn test_materialize_conflict_labeled() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let base_id = testutils::write_file(store, &path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, &path, "line 1\nleft 2.1\nleft 2.2\nline 3\n");
+    let right_id = testutils::write_file(store, &path, "line 1\nright 2\nline 3\n");
+
+    // Parts without a label are described by their position
+    let conflict = Conflict {
+        removes: vec![ConflictPart {
+            value: TreeValue::Normal {
+                id: base_id,
+                executable: false,
+            },
+            label: Some("aaaaaaaaaaaa base".to_string()),
+        }],
+        adds: vec![
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: left_id,
+                    executable: false,
+                },
+                label: Some("bbbbbbbbbbbb left".to_string()),
+            },
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: right_id,
+                    executable: false,
+                },
+                label: None,
+            },
+        ],
+    };
+    let materialized = materialize_conflict_string(store, &path, &conflict);
+    insta::assert_snapshot!(
+        &materialized,
+        @r###"
+    line 1
+    <<<<<<<
+    %%%%%%% Changes from aaaaaaaaaaaa base to side #2
+    -line 2
+    +right 2
+    +++++++ Contents of bbbbbbbbbbbb left
+    left 2.1
+    left 2.2
+    >>>>>>>
+    line 3
+    "###
+    );
+
+    // The labels don't get in the way of parsing the markers
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 1, 2),
+        Some(vec![
+            MergeHunk::Resolved(b"line 1\n".to_vec()),
+            MergeHunk::Conflict {
+                removes: vec![b"line 2\n".to_vec()],
+                adds: vec![b"right 2\n".to_vec(), b"left 2.1\nleft 2.2\n".to_vec()]
+            },
+            MergeHunk::Resolved(b"line 3\n".to_vec())
+        ])
+    );
+}
+
+#[test]
+fn test_materialize_conflict_git_styles() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let base_id = testutils::write_file(store, &path, "line 1\nline 2\nline 3\n");
+    let left_id = testutils::write_file(store, &path, "line 1\nnew\nshared\nleft\nline 3\n");
+    let right_id = testutils::write_file(store, &path, "line 1\nnew\nshared\nright\nline 3\n");
+
+    let conflict = Conflict {
+        removes: vec![ConflictPart {
+            value: TreeValue::Normal {
+                id: base_id,
+                executable: false,
+            },
+            label: Some("aaaaaaaaaaaa base".to_string()),
+        }],
+        adds: vec![
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: left_id,
+                    executable: false,
+                },
+                label: Some("bbbbbbbbbbbb left".to_string()),
+            },
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: right_id,
+                    executable: false,
+                },
+                label: Some("cccccccccccc right".to_string()),
+            },
+        ],
+    };
+
+    // The minimal style leaves out the base
+    let materialized = materialize_conflict_string_with_style(
+        store,
+        &path,
+        &conflict,
+        ConflictMarkerStyle::Minimal,
+    );
+    insta::assert_snapshot!(
+        &materialized,
+        @r###"
+    line 1
+    <<<<<<< bbbbbbbbbbbb left
+    new
+    shared
+    left
+    =======
+    new
+    shared
+    right
+    >>>>>>> cccccccccccc right
+    line 3
+    "###
+    );
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 0, 2),
+        Some(vec![
+            MergeHunk::Resolved(b"line 1\n".to_vec()),
+            MergeHunk::Conflict {
+                removes: vec![],
+                adds: vec![
+                    b"new\nshared\nleft\n".to_vec(),
+                    b"new\nshared\nright\n".to_vec()
+                ]
+            },
+            MergeHunk::Resolved(b"line 3\n".to_vec())
+        ])
+    );
+
+    let materialized =
+        materialize_conflict_string_with_style(store, &path, &conflict, ConflictMarkerStyle::Diff3);
+    insta::assert_snapshot!(
+        &materialized,
+        @r###"
+    line 1
+    <<<<<<< bbbbbbbbbbbb left
+    new
+    shared
+    left
+    ||||||| aaaaaaaaaaaa base
+    line 2
+    =======
+    new
+    shared
+    right
+    >>>>>>> cccccccccccc right
+    line 3
+    "###
+    );
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 1, 2),
+        Some(vec![
+            MergeHunk::Resolved(b"line 1\n".to_vec()),
+            MergeHunk::Conflict {
+                removes: vec![b"line 2\n".to_vec()],
+                adds: vec![
+                    b"new\nshared\nleft\n".to_vec(),
+                    b"new\nshared\nright\n".to_vec()
+                ]
+            },
+            MergeHunk::Resolved(b"line 3\n".to_vec())
+        ])
+    );
+
+    // The lines both sides start with are moved out of the conflict
+    let materialized = materialize_conflict_string_with_style(
+        store,
+        &path,
+        &conflict,
+        ConflictMarkerStyle::Zdiff3,
+    );
+    insta::assert_snapshot!(
+        &materialized,
+        @r###"
+    line 1
+    new
+    shared
+    <<<<<<< bbbbbbbbbbbb left
+    left
+    ||||||| aaaaaaaaaaaa base
+    line 2
+    =======
+    right
+    >>>>>>> cccccccccccc right
+    line 3
+    "###
+    );
+    assert_eq!(
+        parse_conflict(materialized.as_bytes(), 1, 2),
+        Some(vec![
+            MergeHunk::Resolved(b"line 1\nnew\nshared\n".to_vec()),
+            MergeHunk::Conflict {
+                removes: vec![b"line 2\n".to_vec()],
+                adds: vec![b"left\n".to_vec(), b"right\n".to_vec()]
+            },
+            MergeHunk::Resolved(b"line 3\n".to_vec())
+        ])
+    );
+}
+
 #[test]
 fn test_materialize_conflict_modify_delete() {
     let test_repo = TestRepo::init(false);
@@ -163,6 +381,7 @@ line 5
                 id: base_id,
                 executable: false,
             },
+            label: None,
         }],
         adds: vec![
             ConflictPart {
@@ -170,12 +389,14 @@ line 5
                     id: left_id,
                     executable: false,
                 },
+                label: None,
             },
             ConflictPart {
                 value: TreeValue::Normal {
                     id: right_id,
                     executable: false,
                 },
+                label: None,
             },
         ],
     };
@@ -236,6 +457,7 @@ line 5
                 id: base_id,
                 executable: false,
             },
+            label: None,
         }],
         adds: vec![
             ConflictPart {
@@ -243,12 +465,14 @@ line 5
                     id: left_id,
                     executable: false,
                 },
+                label: None,
             },
             ConflictPart {
                 value: TreeValue::Normal {
                     id: right_id,
                     executable: false,
                 },
+                label: None,
             },
         ],
     };
@@ -270,6 +494,63 @@ line 5
     );
 }
 
+#[test]
+fn test_materialize_conflict_file_directory() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    // One side edited the file and the other replaced it by a directory
+    let path = RepoPath::from_internal_string("file");
+    let base_id = testutils::write_file(store, &path, "line 1\n");
+    let left_id = testutils::write_file(store, &path, "left 1\n");
+    let right_tree = testutils::create_tree(
+        &test_repo.repo,
+        &[
+            (&RepoPath::from_internal_string("file/a"), "a\n"),
+            (&RepoPath::from_internal_string("file/sub/b"), "b\n"),
+        ],
+    );
+    let right_value = right_tree.path_value(&path).unwrap();
+
+    let conflict = Conflict {
+        removes: vec![ConflictPart {
+            value: TreeValue::Normal {
+                id: base_id,
+                executable: false,
+            },
+            label: None,
+        }],
+        adds: vec![
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: left_id,
+                    executable: false,
+                },
+                label: Some("left".to_string()),
+            },
+            ConflictPart {
+                value: right_value,
+                label: Some("right".to_string()),
+            },
+        ],
+    };
+    assert_eq!(
+        conflict_kind(&conflict),
+        ConflictKind::Type(vec!["file", "directory"])
+    );
+    insta::assert_snapshot!(&materialize_conflict_string(store, &path, &conflict), @r###"
+    Conflict (file/directory):
+      Removing file:
+        line 1
+      Adding file (from left):
+        left 1
+      Adding directory (from right):
+        file/a
+        file/sub/b
+    "###
+    );
+}
+
 #[test]
 fn test_parse_conflict_resolved() {
     assert_eq!(
@@ -444,6 +725,7 @@ fn test_update_conflict_from_content() {
                 id: base_file_id,
                 executable: false,
             },
+            label: None,
         }],
         adds: vec![
             ConflictPart {
@@ -451,12 +733,14 @@ fn test_update_conflict_from_content() {
                     id: left_file_id,
                     executable: false,
                 },
+                label: None,
             },
             ConflictPart {
                 value: TreeValue::Normal {
                     id: right_file_id,
                     executable: false,
                 },
+                label: None,
             },
         ],
     };
@@ -465,8 +749,22 @@ fn test_update_conflict_from_content() {
     // If the content is unchanged compared to the materialized value, we get the
     // old conflict id back.
     let mut materialized = vec![];
-    materialize_conflict(store, &path, &conflict, &mut materialized).unwrap();
-    let result = update_conflict_from_content(store, &path, &conflict_id, &materialized).unwrap();
+    materialize_conflict(
+        store,
+        &path,
+        &conflict,
+        ConflictMarkerStyle::Diff,
+        &mut materialized,
+    )
+    .unwrap();
+    let result = update_conflict_from_content(
+        store,
+        &path,
+        &conflict_id,
+        &materialized,
+        ConflictMarkerStyle::Diff,
+    )
+    .unwrap();
     assert_eq!(result, Some(conflict_id.clone()));
 
     // If the conflict is resolved, we None back to indicate that.
@@ -475,6 +773,7 @@ fn test_update_conflict_from_content() {
         &path,
         &conflict_id,
         b"resolved 1\nline 2\nresolved 3\n",
+        ConflictMarkerStyle::Diff,
     )
     .unwrap();
     assert_eq!(result, None);
@@ -485,6 +784,7 @@ fn test_update_conflict_from_content() {
         &path,
         &conflict_id,
         b"resolved 1\nline 2\n<<<<<<<\n%%%%%%%\n-line 3\n+left 3\n+++++++\nright 3\n>>>>>>>\n",
+        ConflictMarkerStyle::Diff,
     )
     .unwrap();
     assert_ne!(result, None);
@@ -501,28 +801,235 @@ fn test_update_conflict_from_content() {
                 value: TreeValue::Normal {
                     id: new_base_file_id,
                     executable: false
-                }
+                },
+                label: None,
             }],
             adds: vec![
                 ConflictPart {
                     value: TreeValue::Normal {
                         id: new_left_file_id,
                         executable: false
-                    }
+                    },
+                    label: None,
                 },
                 ConflictPart {
                     value: TreeValue::Normal {
                         id: new_right_file_id,
                         executable: false
-                    }
+                    },
+                    label: None,
                 }
             ]
         }
     )
 }
 
+#[test]
+fn test_update_conflict_from_content_minimal_style() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("dir/file");
+    let base_file_id = testutils::write_file(store, &path, "line 1\nline 2\nline 3\n");
+    let left_file_id = testutils::write_file(store, &path, "left 1\nline 2\nleft 3\n");
+    let right_file_id = testutils::write_file(store, &path, "right 1\nline 2\nright 3\n");
+    let conflict = Conflict {
+        removes: vec![ConflictPart {
+            value: TreeValue::Normal {
+                id: base_file_id,
+                executable: false,
+            },
+            label: None,
+        }],
+        adds: vec![
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: left_file_id,
+                    executable: false,
+                },
+                label: None,
+            },
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: right_file_id,
+                    executable: false,
+                },
+                label: None,
+            },
+        ],
+    };
+    let conflict_id = store.write_conflict(&path, &conflict).unwrap();
+
+    let materialized = materialize_conflict_string_with_style(
+        store,
+        &path,
+        &conflict,
+        ConflictMarkerStyle::Minimal,
+    );
+    insta::assert_snapshot!(
+        &materialized,
+        @r###"
+    <<<<<<<
+    left 1
+    =======
+    right 1
+    >>>>>>>
+    line 2
+    <<<<<<<
+    left 3
+    =======
+    right 3
+    >>>>>>>
+    "###
+    );
+    let result = update_conflict_from_content(
+        store,
+        &path,
+        &conflict_id,
+        materialized.as_bytes(),
+        ConflictMarkerStyle::Minimal,
+    )
+    .unwrap();
+    assert_eq!(result, Some(conflict_id.clone()));
+
+    // The base of the remaining conflict is taken from the old conflict
+    let result = update_conflict_from_content(
+        store,
+        &path,
+        &conflict_id,
+        b"resolved 1\nline 2\n<<<<<<<\nleft 3\n=======\nright 3\n>>>>>>>\n",
+        ConflictMarkerStyle::Minimal,
+    )
+    .unwrap();
+    let new_conflict = store.read_conflict(&path, &result.unwrap()).unwrap();
+    let new_base_file_id = testutils::write_file(store, &path, "resolved 1\nline 2\nline 3\n");
+    let new_left_file_id = testutils::write_file(store, &path, "resolved 1\nline 2\nleft 3\n");
+    let new_right_file_id = testutils::write_file(store, &path, "resolved 1\nline 2\nright 3\n");
+    assert_eq!(
+        new_conflict,
+        Conflict {
+            removes: vec![ConflictPart {
+                value: TreeValue::Normal {
+                    id: new_base_file_id,
+                    executable: false
+                },
+                label: None,
+            }],
+            adds: vec![
+                ConflictPart {
+                    value: TreeValue::Normal {
+                        id: new_left_file_id,
+                        executable: false
+                    },
+                    label: None,
+                },
+                ConflictPart {
+                    value: TreeValue::Normal {
+                        id: new_right_file_id.clone(),
+                        executable: false
+                    },
+                    label: None,
+                }
+            ]
+        }
+    );
+
+    // Markers in another style than the configured one can be read too
+    let result = update_conflict_from_content(
+        store,
+        &path,
+        &conflict_id,
+        b"resolved 1\nline 2\n<<<<<<<\nleft 3\n|||||||\nline 3\n=======\nright 3\n>>>>>>>\n",
+        ConflictMarkerStyle::Minimal,
+    )
+    .unwrap();
+    let new_conflict = store.read_conflict(&path, &result.unwrap()).unwrap();
+    assert_eq!(
+        new_conflict.adds[1].value,
+        TreeValue::Normal {
+            id: new_right_file_id,
+            executable: false
+        }
+    );
+}
+
+#[test]
+fn test_update_conflict_from_content_non_file() {
+    let test_repo = TestRepo::init(false);
+    let store = test_repo.repo.store();
+
+    let path = RepoPath::from_internal_string("dir/file");
+    let base_file_id = testutils::write_file(store, &path, "line 1\n");
+    let left_file_id = testutils::write_file(store, &path, "left 1\n");
+    let right_symlink_id = store.write_symlink(&path, "target").unwrap();
+    let conflict = Conflict {
+        removes: vec![ConflictPart {
+            value: TreeValue::Normal {
+                id: base_file_id,
+                executable: false,
+            },
+            label: None,
+        }],
+        adds: vec![
+            ConflictPart {
+                value: TreeValue::Normal {
+                    id: left_file_id,
+                    executable: false,
+                },
+                label: None,
+            },
+            ConflictPart {
+                value: TreeValue::Symlink(right_symlink_id),
+                label: None,
+            },
+        ],
+    };
+    let conflict_id = store.write_conflict(&path, &conflict).unwrap();
+
+    // The unchanged description keeps the conflict
+    let mut materialized = vec![];
+    materialize_conflict(
+        store,
+        &path,
+        &conflict,
+        ConflictMarkerStyle::Diff,
+        &mut materialized,
+    )
+    .unwrap();
+    let result = update_conflict_from_content(
+        store,
+        &path,
+        &conflict_id,
+        &materialized,
+        ConflictMarkerStyle::Diff,
+    )
+    .unwrap();
+    assert_eq!(result, Some(conflict_id.clone()));
+
+    // Content that looks like conflict markers can't be parsed into a conflict
+    // involving a symlink, so it resolves the conflict
+    let result = update_conflict_from_content(
+        store,
+        &path,
+        &conflict_id,
+        b"<<<<<<<\n%%%%%%%\n-line 1\n+left 1\n+++++++\nright 1\n>>>>>>>\n",
+        ConflictMarkerStyle::Diff,
+    )
+    .unwrap();
+    assert_eq!(result, None);
+}
+
 fn materialize_conflict_string(store: &Store, path: &RepoPath, conflict: &Conflict) -> String {
+    materialize_conflict_string_with_style(store, path, conflict, ConflictMarkerStyle::Diff)
+}
+
+fn materialize_conflict_string_with_style(
+    store: &Store,
+    path: &RepoPath,
+    conflict: &Conflict,
+    style: ConflictMarkerStyle,
+) -> String {
     let mut result: Vec<u8> = vec![];
-    materialize_conflict(store, path, conflict, &mut result).unwrap();
+    materialize_conflict(store, path, conflict, style, &mut result).unwrap();
     String::from_utf8(result).unwrap()
 }
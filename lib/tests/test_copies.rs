@@ -0,0 +1,187 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::copies::{CopyDetectionOptions, CopyOperation, CopyRecord};
+use jujutsu_lib::matchers::EverythingMatcher;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use test_case::test_case;
+
+fn record(
+    source: &RepoPath,
+    target: &RepoPath,
+    operation: CopyOperation,
+    similarity: u32,
+) -> CopyRecord {
+    CopyRecord {
+        source: source.clone(),
+        target: target.clone(),
+        operation,
+        similarity,
+    }
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_exact_rename(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let old_path = RepoPath::from_internal_string("old");
+    let new_path = RepoPath::from_internal_string("dir/new");
+    let removed_path = RepoPath::from_internal_string("removed");
+    let added_path = RepoPath::from_internal_string("added");
+    let empty_path = RepoPath::from_internal_string("empty");
+    let empty_copy_path = RepoPath::from_internal_string("empty_copy");
+
+    let tree1 = testutils::create_tree(
+        repo,
+        &[
+            (&old_path, "renamed\n"),
+            (&removed_path, "removed\n"),
+            (&empty_path, ""),
+        ],
+    );
+    let tree2 = testutils::create_tree(
+        repo,
+        &[
+            (&new_path, "renamed\n"),
+            (&added_path, "added\n"),
+            (&empty_copy_path, ""),
+        ],
+    );
+
+    let options = CopyDetectionOptions::default();
+    assert_eq!(
+        tree1
+            .find_copies(&tree2, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![record(&old_path, &new_path, CopyOperation::Rename, 100)]
+    );
+    // The other direction is also a rename
+    assert_eq!(
+        tree2
+            .find_copies(&tree1, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![record(&new_path, &old_path, CopyOperation::Rename, 100)]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_similar_rename(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let old_path = RepoPath::from_internal_string("old");
+    let new_path = RepoPath::from_internal_string("new");
+    let other_path = RepoPath::from_internal_string("other");
+
+    let tree1 = testutils::create_tree(repo, &[(&old_path, "a\nb\nc\nd\n")]);
+    let tree2 = testutils::create_tree(
+        repo,
+        &[(&new_path, "a\nb\nc\nx\n"), (&other_path, "a\nx\ny\nz\n")],
+    );
+
+    // The most similar file is picked
+    let options = CopyDetectionOptions::default();
+    assert_eq!(
+        tree1
+            .find_copies(&tree2, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![record(&old_path, &new_path, CopyOperation::Rename, 75)]
+    );
+
+    // Files below the similarity threshold are not paired up
+    let options = CopyDetectionOptions {
+        min_similarity: 80,
+        ..Default::default()
+    };
+    assert_eq!(
+        tree1
+            .find_copies(&tree2, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![]
+    );
+
+    // Nor are they if there are too many files to compare
+    let options = CopyDetectionOptions {
+        rename_limit: 1,
+        ..Default::default()
+    };
+    assert_eq!(
+        tree1
+            .find_copies(&tree2, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![]
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_copies(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let modified_path = RepoPath::from_internal_string("modified");
+    let removed_path = RepoPath::from_internal_string("removed");
+    let copy1_path = RepoPath::from_internal_string("copy1");
+    let copy2_path = RepoPath::from_internal_string("copy2");
+    let renamed_path = RepoPath::from_internal_string("renamed");
+
+    let tree1 = testutils::create_tree(
+        repo,
+        &[(&modified_path, "modified\n"), (&removed_path, "removed\n")],
+    );
+    let tree2 = testutils::create_tree(
+        repo,
+        &[
+            (&modified_path, "modified after\n"),
+            (&copy1_path, "modified\n"),
+            (&copy2_path, "removed\n"),
+            (&renamed_path, "removed\n"),
+        ],
+    );
+
+    // Without copy detection, only one of the targets of a removed file is
+    // considered a rename
+    let options = CopyDetectionOptions::default();
+    assert_eq!(
+        tree1
+            .find_copies(&tree2, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![record(
+            &removed_path,
+            &copy2_path,
+            CopyOperation::Rename,
+            100
+        )]
+    );
+
+    let options = CopyDetectionOptions {
+        find_copies: true,
+        ..Default::default()
+    };
+    assert_eq!(
+        tree1
+            .find_copies(&tree2, &EverythingMatcher, &options)
+            .unwrap(),
+        vec![
+            record(&modified_path, &copy1_path, CopyOperation::Copy, 100),
+            record(&removed_path, &copy2_path, CopyOperation::Rename, 100),
+            record(&removed_path, &renamed_path, CopyOperation::Copy, 100),
+        ]
+    );
+}
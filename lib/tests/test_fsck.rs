@@ -0,0 +1,156 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::PathBuf;
+
+use jujutsu_lib::backend::{BackendError, FileId, StoreObjectId};
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::fsck::{check_repo, CorruptObject};
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::repo::ReadonlyRepo;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::store::{Store, StoreOptions};
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use test_case::test_case;
+
+fn file_object_path(repo: &ReadonlyRepo, use_git: bool, id: &FileId) -> PathBuf {
+    if use_git {
+        let hex = id.hex();
+        let git_repo = repo.store().git_repo().unwrap();
+        git_repo
+            .path()
+            .join("objects")
+            .join(&hex[..2])
+            .join(&hex[2..])
+    } else {
+        repo.repo_path().join("store").join("files").join(id.hex())
+    }
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_fsck(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let path_a = RepoPath::from_internal_string("a");
+    let path_b = RepoPath::from_internal_string("dir/b");
+    let file_a = testutils::write_file(store, &path_a, "a\n");
+    let file_b = testutils::write_file(store, &path_b, "b\n");
+    let file_c = testutils::write_file(store, &path_b, "c\n");
+    let tree1 = testutils::create_tree(repo, &[(&path_a, "a\n")]);
+    let tree2 = testutils::create_tree(repo, &[(&path_a, "a\n"), (&path_b, "b\n")]);
+    let mut tx = repo.start_transaction("add commits");
+    let commit1 = CommitBuilder::for_new_commit(
+        &settings,
+        vec![store.root_commit_id().clone()],
+        tree1.id().clone(),
+    )
+    .write_to_repo(tx.mut_repo());
+    let commit2 =
+        CommitBuilder::for_new_commit(&settings, vec![commit1.id().clone()], tree2.id().clone())
+            .write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+
+    let report = check_repo(&repo).unwrap();
+    assert!(report.checked_objects > 0);
+    assert_eq!(report.corrupt_objects, vec![]);
+
+    // Replace one file with the contents of another, and remove one
+    let path_a_object = file_object_path(&repo, use_git, &file_a);
+    std::fs::remove_file(&path_a_object).unwrap();
+    std::fs::copy(file_object_path(&repo, use_git, &file_c), &path_a_object).unwrap();
+    std::fs::remove_file(file_object_path(&repo, use_git, &file_b)).unwrap();
+    let report = check_repo(&repo).unwrap();
+    let mut commits_with_a = vec![commit1.id().clone(), commit2.id().clone()];
+    commits_with_a.sort();
+    let mut expected = vec![
+        CorruptObject {
+            object: StoreObjectId::File(file_a),
+            missing: false,
+            commits: commits_with_a,
+        },
+        CorruptObject {
+            object: StoreObjectId::File(file_b),
+            missing: true,
+            commits: vec![commit2.id().clone()],
+        },
+    ];
+    expected.sort_by(|a, b| a.object.cmp(&b.object));
+    assert_eq!(report.corrupt_objects, expected);
+}
+
+#[test]
+fn test_fsck_missing_commit() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("add commit");
+    let commit = testutils::create_random_commit(&settings, repo).write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+
+    std::fs::remove_file(
+        repo.repo_path()
+            .join("store")
+            .join("commits")
+            .join(commit.id().hex()),
+    )
+    .unwrap();
+    let report = check_repo(&repo).unwrap();
+    assert_eq!(
+        report.corrupt_objects,
+        vec![CorruptObject {
+            object: StoreObjectId::Commit(commit.id().clone()),
+            missing: true,
+            commits: vec![commit.id().clone()],
+        }]
+    );
+}
+
+#[test]
+fn test_verify_objects_on_read() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string("file");
+    let file_id = testutils::write_file(repo.store(), &path, "contents\n");
+    let other_file_id = testutils::write_file(repo.store(), &path, "other contents\n");
+    let store_path = repo.repo_path().join("store");
+    std::fs::copy(
+        store_path.join("files").join(other_file_id.hex()),
+        store_path.join("files").join(file_id.hex()),
+    )
+    .unwrap();
+
+    // Without verification, the wrong contents are returned
+    assert_eq!(
+        testutils::read_file(repo.store(), &path, &file_id),
+        b"other contents\n"
+    );
+    let options = StoreOptions {
+        verify_objects: true,
+        ..StoreOptions::default()
+    };
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+    match store.read_file(&path, &file_id) {
+        Err(BackendError::Corrupt(object)) => assert_eq!(object, StoreObjectId::File(file_id)),
+        result => panic!("unexpected result {:?}", result.map(|_| ())),
+    }
+    let other_contents = testutils::read_file(&store, &path, &other_file_id);
+    assert_eq!(other_contents, b"other contents\n");
+}
@@ -0,0 +1,83 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::time::{Duration, SystemTime};
+
+use jujutsu_lib::backend::{GcStats, TreeValue};
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::gc::collect_garbage;
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+
+#[test]
+fn test_gc_local_backend() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let path = RepoPath::from_internal_string("file");
+    let tree = testutils::create_tree(repo, &[(&path, "reachable")]);
+    let mut tx = repo.start_transaction("add commit");
+    let commit = CommitBuilder::for_new_commit(
+        &settings,
+        vec![store.root_commit_id().clone()],
+        tree.id().clone(),
+    )
+    .write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+    // An abandoned commit is still reachable from the operation that added it
+    let mut tx = repo.start_transaction("abandon commit");
+    tx.mut_repo().record_abandoned_commit(commit.id().clone());
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    let repo = tx.commit();
+
+    // A file that isn't in any tree is unreachable
+    let orphan_id = testutils::write_file(store, &path, "unreachable");
+
+    // Objects written after the cutoff are kept
+    let cutoff = SystemTime::now() - Duration::from_secs(3600);
+    let stats = collect_garbage(&repo, cutoff, false).unwrap();
+    assert_eq!(stats, GcStats::default());
+
+    let cutoff = SystemTime::now() + Duration::from_secs(3600);
+    let stats = collect_garbage(&repo, cutoff, true).unwrap();
+    assert_eq!(stats.removed_objects, 1);
+    // The size is the compressed size on disk
+    assert!(stats.removed_bytes > 0);
+    assert!(store.read_file(&path, &orphan_id).is_ok());
+
+    let stats = collect_garbage(&repo, cutoff, false).unwrap();
+    assert_eq!(stats.removed_objects, 1);
+    assert!(store.read_file(&path, &orphan_id).is_err());
+    let commit = store.get_commit(commit.id()).unwrap();
+    match commit.tree().path_value(&path) {
+        Some(TreeValue::Normal { id, .. }) => {
+            assert_eq!(testutils::read_file(store, &path, &id), b"reachable");
+        }
+        value => panic!("unexpected value {:?}", value),
+    }
+
+    // Nothing more to remove
+    let stats = collect_garbage(&repo, cutoff, false).unwrap();
+    assert_eq!(stats, GcStats::default());
+}
+
+#[test]
+fn test_gc_git_backend_unsupported() {
+    let test_repo = TestRepo::init(true);
+    let repo = &test_repo.repo;
+    assert!(collect_garbage(repo, SystemTime::now(), true).is_err());
+}
@@ -18,7 +18,8 @@ use std::sync::Arc;
 use git2::Oid;
 use jujutsu_lib::backend::CommitId;
 use jujutsu_lib::commit::Commit;
-use jujutsu_lib::git::{GitFetchError, GitPushError, GitRefUpdate};
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::git::{GitFetchError, GitPushError, GitRefUpdate, RefSpec, RefSpecParseError};
 use jujutsu_lib::git_backend::GitBackend;
 use jujutsu_lib::op_store::{BranchTarget, RefTarget};
 use jujutsu_lib::repo::ReadonlyRepo;
@@ -91,6 +92,7 @@ fn test_import_refs() {
         remote_targets: btreemap! {
           "origin".to_string() => RefTarget::Normal(commit_id(&commit1)),
         },
+        untracked_remotes: Default::default(),
     };
     assert_eq!(
         view.branches().get("main"),
@@ -99,6 +101,7 @@ fn test_import_refs() {
     let expected_feature1_branch = BranchTarget {
         local_target: Some(RefTarget::Normal(commit_id(&commit3))),
         remote_targets: btreemap! {},
+        untracked_remotes: Default::default(),
     };
     assert_eq!(
         view.branches().get("feature1"),
@@ -107,6 +110,7 @@ fn test_import_refs() {
     let expected_feature2_branch = BranchTarget {
         local_target: Some(RefTarget::Normal(commit_id(&commit4))),
         remote_targets: btreemap! {},
+        untracked_remotes: Default::default(),
     };
     assert_eq!(
         view.branches().get("feature2"),
@@ -142,6 +146,47 @@ fn test_import_refs() {
     assert_eq!(view.git_head(), Some(commit_id(&commit2)));
 }
 
+#[test]
+fn test_import_refs_shallow() {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let git_repo_dir = temp_dir.path().join("git");
+    let jj_repo_dir = temp_dir.path().join("jj");
+    let git_repo = git2::Repository::init_bare(&git_repo_dir).unwrap();
+    let commit1 = empty_git_commit(&git_repo, "refs/heads/main", &[]);
+    let commit2 = empty_git_commit(&git_repo, "refs/heads/main", &[&commit1]);
+    let commit3 = empty_git_commit(&git_repo, "refs/heads/main", &[&commit2]);
+    // Make it look like the repo was cloned with `--depth 2` by removing the
+    // first commit and marking the second one as a shallow boundary
+    let commit1_hex = commit1.id().to_string();
+    std::fs::remove_file(
+        git_repo_dir
+            .join("objects")
+            .join(&commit1_hex[..2])
+            .join(&commit1_hex[2..]),
+    )
+    .unwrap();
+    std::fs::write(git_repo_dir.join("shallow"), format!("{}\n", commit2.id())).unwrap();
+
+    std::fs::create_dir(&jj_repo_dir).unwrap();
+    let repo = ReadonlyRepo::init(&settings, &jj_repo_dir, |store_path| {
+        Box::new(GitBackend::init_external(store_path, &git_repo_dir))
+    });
+    let mut tx = repo.start_transaction("test");
+    git::import_refs(tx.mut_repo(), &git_repo).unwrap();
+    let repo = tx.commit();
+
+    // The shallow commit is a child of the root commit
+    let commit2 = repo.store().get_commit(&commit_id(&commit2)).unwrap();
+    assert_eq!(
+        commit2.parent_ids(),
+        vec![repo.store().root_commit_id().clone()]
+    );
+    assert_eq!(*repo.view().heads(), hashset! {commit_id(&commit3)});
+    assert!(repo.index().has_id(&commit_id(&commit3)));
+    assert!(!repo.index().has_id(&commit_id(&commit1)));
+}
+
 #[test]
 fn test_import_refs_reimport() {
     let settings = testutils::user_settings();
@@ -200,6 +245,7 @@ fn test_import_refs_reimport() {
         remote_targets: btreemap! {
           "origin".to_string() => commit1_target.clone(),
         },
+        untracked_remotes: Default::default(),
     };
     assert_eq!(
         view.branches().get("main"),
@@ -211,6 +257,7 @@ fn test_import_refs_reimport() {
             adds: vec![commit6.id().clone(), commit_id(&commit5)],
         }),
         remote_targets: btreemap! {},
+        untracked_remotes: Default::default(),
     };
     assert_eq!(
         view.branches().get("feature2"),
@@ -438,6 +485,55 @@ fn test_export_refs_branch_changed() {
     assert_eq!(git_repo.head().unwrap().name(), Some("refs/heads/feature"));
 }
 
+#[test]
+fn test_export_changes_and_record() {
+    // Exporting from a transaction records the exported refs, so importing them
+    // afterwards doesn't change anything
+    let test_data = GitRepoData::create();
+    let git_repo = test_data.git_repo;
+    let commit = empty_git_commit(&git_repo, "refs/heads/main", &[]);
+    let mut tx = test_data.repo.start_transaction("test");
+    git::import_refs(tx.mut_repo(), &git_repo).unwrap();
+    let repo = tx.commit();
+
+    let mut tx = repo.start_transaction("test");
+    let new_commit = testutils::create_random_commit(&test_data.settings, &repo)
+        .set_parents(vec![CommitId::from_bytes(commit.id().as_bytes())])
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo().set_local_branch(
+        "main".to_string(),
+        RefTarget::Normal(new_commit.id().clone()),
+    );
+    tx.mut_repo().set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(new_commit.id().clone()),
+    );
+    assert_eq!(
+        git::export_changes_and_record(tx.mut_repo(), &git_repo),
+        Ok(())
+    );
+    assert_eq!(
+        git_repo
+            .find_reference("refs/heads/feature")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap()
+            .id(),
+        Oid::from_bytes(new_commit.id().as_bytes()).unwrap()
+    );
+    assert_eq!(
+        tx.mut_repo().view().git_refs().get("refs/heads/main"),
+        Some(&RefTarget::Normal(new_commit.id().clone()))
+    );
+    assert_eq!(
+        tx.mut_repo().view().git_refs().get("refs/heads/feature"),
+        Some(&RefTarget::Normal(new_commit.id().clone()))
+    );
+    let view_before_import = tx.mut_repo().view().store_view().clone();
+    git::import_refs(tx.mut_repo(), &git_repo).unwrap();
+    assert_eq!(tx.mut_repo().view().store_view(), &view_before_import);
+}
+
 #[test]
 fn test_export_refs_current_branch_changed() {
     // If we update a branch that is checked out in the git repo, HEAD gets detached
@@ -513,6 +609,71 @@ fn test_export_refs_unborn_git_branch() {
     assert!(!git_repo.head_detached().unwrap());
 }
 
+#[test]
+fn test_notes_copied_on_rewrite() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(true);
+    let repo = &test_repo.repo;
+    let git_repo = repo.store().git_repo().unwrap();
+    let signature = git2::Signature::now("Someone", "someone@example.com").unwrap();
+
+    let mut tx = repo.start_transaction("test");
+    let commit = create_random_commit(&settings, repo).write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+    let oid = Oid::from_bytes(commit.id().as_bytes()).unwrap();
+    git_repo
+        .note(&signature, &signature, None, oid, "reviewed", false)
+        .unwrap();
+    git_repo
+        .note(
+            &signature,
+            &signature,
+            Some("refs/notes/ci"),
+            oid,
+            "passed",
+            false,
+        )
+        .unwrap();
+    assert_eq!(
+        git::get_note(&git_repo, None, commit.id()),
+        Some("reviewed".to_string())
+    );
+    assert_eq!(
+        git::get_note(&git_repo, Some("refs/notes/ci"), commit.id()),
+        Some("passed".to_string())
+    );
+
+    // Without `notes.rewriteRef`, the notes aren't copied
+    let mut tx = repo.start_transaction("test");
+    let rewritten1 = CommitBuilder::for_rewrite_from(&settings, &commit)
+        .set_description("rewritten 1".to_string())
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    tx.commit();
+    assert_eq!(git::get_note(&git_repo, None, rewritten1.id()), None);
+
+    // Only the notes in the refs matching `notes.rewriteRef` are copied
+    git_repo
+        .config()
+        .unwrap()
+        .set_str("notes.rewriteRef", "refs/notes/commits")
+        .unwrap();
+    let mut tx = repo.start_transaction("test");
+    let rewritten2 = CommitBuilder::for_rewrite_from(&settings, &commit)
+        .set_description("rewritten 2".to_string())
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    tx.commit();
+    assert_eq!(
+        git::get_note(&git_repo, None, rewritten2.id()),
+        Some("reviewed".to_string())
+    );
+    assert_eq!(
+        git::get_note(&git_repo, Some("refs/notes/ci"), rewritten2.id()),
+        None
+    );
+}
+
 #[test]
 fn test_init() {
     let settings = testutils::user_settings();
@@ -536,7 +697,14 @@ fn test_fetch_empty_repo() {
     let test_data = GitRepoData::create();
 
     let mut tx = test_data.repo.start_transaction("test");
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // No default branch and no refs
     assert_eq!(default_branch, None);
     assert_eq!(*tx.mut_repo().view().git_refs(), btreemap! {});
@@ -549,7 +717,14 @@ fn test_fetch_initial_commit() {
     let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // No default branch because the origin repo's HEAD wasn't set
     assert_eq!(default_branch, None);
     let repo = tx.commit();
@@ -568,19 +743,70 @@ fn test_fetch_initial_commit() {
         btreemap! {
             "main".to_string() => BranchTarget {
                 local_target: Some(initial_commit_target.clone()),
-                remote_targets: btreemap! {"origin".to_string() => initial_commit_target}
+                remote_targets: btreemap! {"origin".to_string() => initial_commit_target},
+                untracked_remotes: Default::default(),
             },
         }
     );
 }
 
+#[test]
+fn test_fetch_with_negotiation_tips() {
+    let test_data = GitRepoData::create();
+    let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+    // A commit we have locally but no Git ref points to
+    let local_git_commit = empty_git_commit(&test_data.git_repo, "refs/heads/local", &[]);
+    test_data
+        .git_repo
+        .find_reference("refs/heads/local")
+        .unwrap()
+        .delete()
+        .unwrap();
+
+    let mut tx = test_data.repo.start_transaction("test");
+    let negotiation_tips = vec![
+        test_data.repo.store().root_commit_id().clone(),
+        commit_id(&local_git_commit),
+    ];
+    git::fetch_with_negotiation_tips(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        &negotiation_tips,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
+    assert!(tx
+        .mut_repo()
+        .view()
+        .heads()
+        .contains(&commit_id(&initial_git_commit)));
+    // The temporary refs for the negotiation tips are removed after the fetch
+    assert_eq!(
+        test_data
+            .git_repo
+            .references_glob("refs/jj/negotiation/*")
+            .unwrap()
+            .count(),
+        0
+    );
+}
+
 #[test]
 fn test_fetch_success() {
     let mut test_data = GitRepoData::create();
     let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     test_data.repo = tx.commit();
 
     test_data.origin_repo.set_head("refs/heads/main").unwrap();
@@ -591,7 +817,14 @@ fn test_fetch_success() {
     );
 
     let mut tx = test_data.repo.start_transaction("test");
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // The default branch is "main"
     assert_eq!(default_branch, Some("main".to_string()));
     let repo = tx.commit();
@@ -610,7 +843,8 @@ fn test_fetch_success() {
         btreemap! {
             "main".to_string() => BranchTarget {
                 local_target: Some(new_commit_target.clone()),
-                remote_targets: btreemap! {"origin".to_string() => new_commit_target}
+                remote_targets: btreemap! {"origin".to_string() => new_commit_target},
+                untracked_remotes: Default::default(),
             },
         }
     );
@@ -622,7 +856,14 @@ fn test_fetch_prune_deleted_ref() {
     empty_git_commit(&test_data.git_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // Test the setup
     assert!(tx.mut_repo().get_branch("main").is_some());
 
@@ -633,7 +874,14 @@ fn test_fetch_prune_deleted_ref() {
         .delete()
         .unwrap();
     // After re-fetching, the branch should be deleted
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     assert!(tx.mut_repo().get_branch("main").is_none());
 }
 
@@ -643,7 +891,14 @@ fn test_fetch_no_default_branch() {
     let initial_git_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
 
     let mut tx = test_data.repo.start_transaction("test");
-    git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
 
     empty_git_commit(
         &test_data.origin_repo,
@@ -658,17 +913,122 @@ fn test_fetch_no_default_branch() {
         .set_head_detached(initial_git_commit.id())
         .unwrap();
 
-    let default_branch = git::fetch(tx.mut_repo(), &test_data.git_repo, "origin").unwrap();
+    let default_branch = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &[],
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
     // There is no default branch
     assert_eq!(default_branch, None);
 }
 
+#[test]
+fn test_fetch_refspecs() {
+    let test_data = GitRepoData::create();
+    let main_commit = empty_git_commit(&test_data.origin_repo, "refs/heads/main", &[]);
+    let feature_commit = empty_git_commit(
+        &test_data.origin_repo,
+        "refs/heads/feature/a",
+        &[&main_commit],
+    );
+    empty_git_commit(&test_data.origin_repo, "refs/heads/other", &[&main_commit]);
+
+    let mut tx = test_data.repo.start_transaction("test");
+    let refspecs = vec![
+        RefSpec::parse_fetch("origin", "main:upstream").unwrap(),
+        RefSpec::parse_fetch("origin", "feature/*").unwrap(),
+    ];
+    git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "origin",
+        &refspecs,
+        git::RemoteCallbacks::default(),
+    )
+    .unwrap();
+    // Only the requested branches were fetched, to the requested names. Like in
+    // Git, the remote-tracking branch of a fetched branch is also updated.
+    assert_eq!(
+        *tx.mut_repo().view().git_refs(),
+        btreemap! {
+            "refs/remotes/origin/feature/a".to_string() =>
+                RefTarget::Normal(commit_id(&feature_commit)),
+            "refs/remotes/origin/main".to_string() =>
+                RefTarget::Normal(commit_id(&main_commit)),
+            "refs/remotes/origin/upstream".to_string() =>
+                RefTarget::Normal(commit_id(&main_commit)),
+        }
+    );
+}
+
+#[test]
+fn test_parse_refspecs() {
+    let refspec = |source: &str, destination: &str| RefSpec {
+        source: source.to_string(),
+        destination: destination.to_string(),
+    };
+    assert_eq!(
+        RefSpec::parse_fetch("origin", "main"),
+        Ok(refspec("refs/heads/main", "refs/remotes/origin/main"))
+    );
+    assert_eq!(
+        RefSpec::parse_fetch("origin", "+feature/*:mine/*"),
+        Ok(refspec(
+            "refs/heads/feature/*",
+            "refs/remotes/origin/mine/*"
+        ))
+    );
+    assert_eq!(
+        RefSpec::parse_fetch("origin", "refs/tags/*"),
+        Ok(refspec("refs/tags/*", "refs/tags/*"))
+    );
+    assert_eq!(
+        RefSpec::parse_push("main:refs/for/main"),
+        Ok(refspec("main", "refs/for/main"))
+    );
+    assert_eq!(
+        RefSpec::parse_push("feature/*"),
+        Ok(refspec("feature/*", "refs/heads/feature/*"))
+    );
+    for spec in [
+        "",
+        ":main",
+        "main:",
+        "a*b*",
+        "main:feature/*",
+        "+main",
+        "refs/heads/main",
+    ] {
+        assert_eq!(
+            RefSpec::parse_push(spec),
+            Err(RefSpecParseError(spec.to_string()))
+        );
+    }
+
+    let refspec = RefSpec::parse_push("feature/*-wip:wip/*").unwrap();
+    assert_eq!(
+        refspec.map("feature/a-wip"),
+        Some("refs/heads/wip/a".to_string())
+    );
+    assert_eq!(refspec.map("feature/a"), None);
+    assert_eq!(refspec.map("main"), None);
+}
+
 #[test]
 fn test_fetch_no_such_remote() {
     let test_data = GitRepoData::create();
 
     let mut tx = test_data.repo.start_transaction("test");
-    let result = git::fetch(tx.mut_repo(), &test_data.git_repo, "invalid-remote");
+    let result = git::fetch(
+        tx.mut_repo(),
+        &test_data.git_repo,
+        "invalid-remote",
+        &[],
+        git::RemoteCallbacks::default(),
+    );
     assert!(matches!(result, Err(GitFetchError::NoSuchRemote(_))));
 }
 
@@ -714,8 +1074,10 @@ fn test_push_updates_success() {
         &[GitRefUpdate {
             qualified_name: "refs/heads/main".to_string(),
             force: false,
+            expected_current_target: None,
             new_target: Some(setup.new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -755,8 +1117,10 @@ fn test_push_updates_deletion() {
         &[GitRefUpdate {
             qualified_name: "refs/heads/main".to_string(),
             force: false,
+            expected_current_target: None,
             new_target: None,
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -784,14 +1148,17 @@ fn test_push_updates_mixed_deletion_and_addition() {
             GitRefUpdate {
                 qualified_name: "refs/heads/main".to_string(),
                 force: false,
+                expected_current_target: None,
                 new_target: None,
             },
             GitRefUpdate {
                 qualified_name: "refs/heads/topic".to_string(),
                 force: false,
+                expected_current_target: None,
                 new_target: Some(setup.new_commit.id().clone()),
             },
         ],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -823,8 +1190,10 @@ fn test_push_updates_not_fast_forward() {
         &[GitRefUpdate {
             qualified_name: "refs/heads/main".to_string(),
             force: false,
+            expected_current_target: None,
             new_target: Some(new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Err(GitPushError::NotFastForward));
 }
@@ -844,8 +1213,10 @@ fn test_push_updates_not_fast_forward_with_force() {
         &[GitRefUpdate {
             qualified_name: "refs/heads/main".to_string(),
             force: true,
+            expected_current_target: None,
             new_target: Some(new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert_eq!(result, Ok(()));
 
@@ -859,6 +1230,82 @@ fn test_push_updates_not_fast_forward_with_force() {
     assert_eq!(new_target, Some(new_oid));
 }
 
+#[test]
+fn test_push_updates_expected_current_target() {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let mut setup = set_up_push_repos(&settings, &temp_dir);
+    let mut tx = setup.jj_repo.start_transaction("test");
+    let new_commit =
+        testutils::create_random_commit(&settings, &setup.jj_repo).write_to_repo(tx.mut_repo());
+    setup.jj_repo = tx.commit();
+    let clone_repo = setup.jj_repo.store().git_repo().unwrap();
+    let source_repo = git2::Repository::open(&setup.source_repo_dir).unwrap();
+    let initial_oid = source_repo
+        .find_reference("refs/heads/main")
+        .unwrap()
+        .target()
+        .unwrap();
+
+    // The ref isn't where we expect it on the remote
+    let result = git::push_updates(
+        &clone_repo,
+        "origin",
+        &[
+            GitRefUpdate {
+                qualified_name: "refs/heads/main".to_string(),
+                force: true,
+                expected_current_target: Some(Some(setup.new_commit.id().clone())),
+                new_target: Some(new_commit.id().clone()),
+            },
+            GitRefUpdate {
+                qualified_name: "refs/heads/topic".to_string(),
+                force: false,
+                expected_current_target: Some(None),
+                new_target: Some(new_commit.id().clone()),
+            },
+        ],
+        git::RemoteCallbacks::default(),
+    );
+    assert_eq!(
+        result,
+        Err(GitPushError::UnexpectedRemoteRefs(vec![
+            "refs/heads/main".to_string()
+        ]))
+    );
+    // Nothing was pushed
+    assert_eq!(
+        source_repo
+            .find_reference("refs/heads/main")
+            .unwrap()
+            .target(),
+        Some(initial_oid)
+    );
+    assert!(source_repo.find_reference("refs/heads/topic").is_err());
+
+    // The ref is where we expect it, so the update is forced
+    let result = git::push_updates(
+        &clone_repo,
+        "origin",
+        &[GitRefUpdate {
+            qualified_name: "refs/heads/main".to_string(),
+            force: true,
+            expected_current_target: Some(Some(CommitId::from_bytes(initial_oid.as_bytes()))),
+            new_target: Some(new_commit.id().clone()),
+        }],
+        git::RemoteCallbacks::default(),
+    );
+    assert_eq!(result, Ok(()));
+    let new_oid = Oid::from_bytes(new_commit.id().as_bytes()).unwrap();
+    assert_eq!(
+        source_repo
+            .find_reference("refs/heads/main")
+            .unwrap()
+            .target(),
+        Some(new_oid)
+    );
+}
+
 #[test]
 fn test_push_updates_no_such_remote() {
     let settings = testutils::user_settings();
@@ -870,8 +1317,10 @@ fn test_push_updates_no_such_remote() {
         &[GitRefUpdate {
             qualified_name: "refs/heads/main".to_string(),
             force: false,
+            expected_current_target: None,
             new_target: Some(setup.new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert!(matches!(result, Err(GitPushError::NoSuchRemote(_))));
 }
@@ -887,8 +1336,10 @@ fn test_push_updates_invalid_remote() {
         &[GitRefUpdate {
             qualified_name: "refs/heads/main".to_string(),
             force: false,
+            expected_current_target: None,
             new_target: Some(setup.new_commit.id().clone()),
         }],
+        git::RemoteCallbacks::default(),
     );
     assert!(matches!(result, Err(GitPushError::NoSuchRemote(_))));
 }
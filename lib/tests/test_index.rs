@@ -19,6 +19,7 @@ use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
 use jujutsu_lib::index::IndexRef;
 use jujutsu_lib::repo::{BackendFactories, ReadonlyRepo};
+use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::{create_random_commit, CommitGraphBuilder, TestRepo};
@@ -463,7 +464,7 @@ fn test_index_commits_incremental_squashed(use_git: bool) {
     let repo = create_n_commits(&settings, &repo, 7);
     let repo = create_n_commits(&settings, &repo, 3);
     let repo = create_n_commits(&settings, &repo, 1);
-    assert_eq!(commits_by_level(&repo), vec![31, 15, 7, 3, 1]);
+    assert_eq!(commits_by_level(&repo), vec![53, 4]);
 
     let test_repo = TestRepo::init(use_git);
     let repo = &test_repo.repo;
@@ -477,4 +478,135 @@ fn test_index_commits_incremental_squashed(use_git: bool) {
     let repo = create_n_commits(&settings, &repo, 10);
     let repo = create_n_commits(&settings, &repo, 10);
     assert_eq!(commits_by_level(&repo), vec![71, 20]);
+
+    // Small files are squashed even if the new file is smaller
+    let test_repo = TestRepo::init(use_git);
+    let mut repo = test_repo.repo.clone();
+    for _ in 0..40 {
+        repo = create_n_commits(&settings, &repo, 1);
+    }
+    assert_eq!(commits_by_level(&repo), vec![36, 5]);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_commits_concurrent_operations(use_git: bool) {
+    // Test that the index for an operation that merges concurrent operations can
+    // be built from the indexes of the merged operations.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = create_n_commits(&settings, &test_repo.repo, 20);
+
+    let mut tx1 = repo.start_transaction("test");
+    let commit_a = create_random_commit(&settings, &repo).write_to_repo(tx1.mut_repo());
+    tx1.commit();
+
+    // Commit B is only visible in an earlier operation on this side
+    let mut tx2 = repo.start_transaction("test");
+    let commit_b = create_random_commit(&settings, &repo).write_to_repo(tx2.mut_repo());
+    let repo2 = tx2.commit();
+    let mut tx2 = repo2.start_transaction("test");
+    tx2.mut_repo().remove_head(commit_b.id());
+    let commit_c = create_random_commit(&settings, &repo2).write_to_repo(tx2.mut_repo());
+    tx2.commit();
+
+    // Delete the index for the merge operation
+    let repo = repo.reload_at_head(&settings).unwrap();
+    let index_operations_dir = repo.repo_path().join("index").join("operations");
+    std::fs::remove_file(index_operations_dir.join(repo.op_id().hex())).unwrap();
+
+    let repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let index = repo.index();
+    assert_eq!(index.num_commits(), 1 + 20 + 3);
+    assert!(index.has_id(commit_a.id()));
+    assert!(index.has_id(commit_b.id()));
+    assert!(index.has_id(commit_c.id()));
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_index_changed_path_filters(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let path_a = RepoPath::from_internal_string("dir/a");
+    let path_b = RepoPath::from_internal_string("b");
+    let tree_a = testutils::create_tree(repo, &[(&path_a, "a")]);
+    let tree_ab = testutils::create_tree(repo, &[(&path_a, "a"), (&path_b, "b")]);
+    let root_commit = repo.store().root_commit();
+    let mut tx = repo.start_transaction("test");
+    let commit_a = CommitBuilder::for_new_commit(
+        &settings,
+        vec![root_commit.id().clone()],
+        tree_a.id().clone(),
+    )
+    .write_to_repo(tx.mut_repo());
+    let commit_b =
+        CommitBuilder::for_new_commit(&settings, vec![commit_a.id().clone()], tree_ab.id().clone())
+            .write_to_repo(tx.mut_repo());
+    let commit_c = child_commit(&settings, repo, &root_commit).write_to_repo(tx.mut_repo());
+    let merge = CommitBuilder::for_new_commit(
+        &settings,
+        vec![commit_b.id().clone(), commit_c.id().clone()],
+        tree_ab.id().clone(),
+    )
+    .write_to_repo(tx.mut_repo());
+    tx.commit();
+
+    // The filters are read back from the index file
+    let repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let index = repo.index();
+    let filter = |commit: &Commit| {
+        index
+            .entry_by_id(commit.id())
+            .unwrap()
+            .changed_path_filter()
+    };
+    let dir = RepoPath::from_internal_string("dir");
+    let filter_root = filter(&root_commit).unwrap();
+    assert!(!filter_root.may_contain(&path_a));
+    let filter_a = filter(&commit_a).unwrap();
+    assert!(filter_a.may_contain(&path_a));
+    assert!(filter_a.may_contain(&dir));
+    assert!(!filter_a.may_contain(&path_b));
+    let filter_b = filter(&commit_b).unwrap();
+    assert!(!filter_b.may_contain(&path_a));
+    assert!(!filter_b.may_contain(&dir));
+    assert!(filter_b.may_contain(&path_b));
+    // Merge commits don't have a filter
+    assert_eq!(filter(&merge), None);
+}
+
+#[test]
+fn test_index_unversioned_file_is_rebuilt() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let commit = create_random_commit(&settings, repo).write_to_repo(tx.mut_repo());
+    let repo = tx.commit();
+
+    // Remove the header, like in files written before the format was versioned
+    let index_dir = repo.repo_path().join("index");
+    for entry in std::fs::read_dir(&index_dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_file() {
+            let contents = std::fs::read(&path).unwrap();
+            std::fs::write(&path, &contents[8..]).unwrap();
+        }
+    }
+
+    let repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let index = repo.index();
+    // The root commit and the new one
+    assert_eq!(index.num_commits(), 2);
+    assert!(index.has_id(commit.id()));
 }
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use jujutsu_lib::local_backend::LocalBackend;
 use jujutsu_lib::repo::{BackendFactories, RepoLoader};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::store::{Store, StoreOptions};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::TestRepo;
 use test_case::test_case;
@@ -34,13 +37,56 @@ fn test_load_at_operation(use_git: bool) {
 
     // If we load the repo at head, we should not see the commit since it was
     // removed
-    let loader = RepoLoader::init(&settings, repo.repo_path(), &BackendFactories::default());
+    let loader =
+        RepoLoader::init(&settings, repo.repo_path(), &BackendFactories::default()).unwrap();
     let head_repo = loader.load_at_head().resolve(&settings).unwrap();
     assert!(!head_repo.view().heads().contains(commit.id()));
 
     // If we load the repo at the previous operation, we should see the commit since
     // it has not been removed yet
-    let loader = RepoLoader::init(&settings, repo.repo_path(), &BackendFactories::default());
+    let loader =
+        RepoLoader::init(&settings, repo.repo_path(), &BackendFactories::default()).unwrap();
     let old_repo = loader.load_at(repo.operation());
     assert!(old_repo.view().heads().contains(commit.id()));
 }
+
+#[test]
+fn test_disk_object_cache() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("add commit");
+    let commit = testutils::create_random_commit(&settings, repo).write_to_repo(tx.mut_repo());
+    tx.commit();
+
+    let store_path = repo.repo_path().join("store");
+    let temp_dir = testutils::new_temp_dir();
+    let cache_dir = temp_dir.path().join("cache");
+    let options = StoreOptions {
+        cache_size: 0,
+        disk_cache_dir: Some(cache_dir.clone()),
+        ..StoreOptions::default()
+    };
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options.clone());
+    store.get_commit(commit.id()).unwrap();
+    store.get_tree(&RepoPath::root(), commit.tree_id()).unwrap();
+    assert!(cache_dir.join("commits").join(commit.id().hex()).is_file());
+    assert!(cache_dir
+        .join("trees")
+        .join(commit.tree_id().hex())
+        .is_file());
+
+    // The objects can be read from the cache even if the backend no longer has them
+    std::fs::remove_file(store_path.join("commits").join(commit.id().hex())).unwrap();
+    std::fs::remove_file(store_path.join("trees").join(commit.tree_id().hex())).unwrap();
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+    assert_eq!(store.get_commit(commit.id()).unwrap(), commit);
+    assert_eq!(
+        store
+            .get_tree(&RepoPath::root(), commit.tree_id())
+            .unwrap()
+            .id(),
+        commit.tree_id()
+    );
+}
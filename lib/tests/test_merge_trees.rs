@@ -14,12 +14,20 @@
 
 use assert_matches::assert_matches;
 use itertools::Itertools;
-use jujutsu_lib::backend::{ConflictPart, TreeValue};
+use std::sync::Arc;
+
+use jujutsu_lib::backend::{ConflictPart, TreeId, TreeValue};
+use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::diff::DiffAlgorithm;
+use jujutsu_lib::files::MergeAlgorithms;
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::merge_drivers::{MergeDriver, MergeDrivers};
 use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent};
-use jujutsu_lib::rewrite::rebase_commit;
+use jujutsu_lib::rewrite::{commit_conflict_label, rebase_commit};
+use jujutsu_lib::store::{Store, StoreOptions};
 use jujutsu_lib::testutils::TestRepo;
-use jujutsu_lib::tree::Tree;
+use jujutsu_lib::tree::{ModifyDeletePolicy, Tree};
 use jujutsu_lib::{testutils, tree};
 use test_case::test_case;
 
@@ -34,7 +42,8 @@ fn test_same_type(use_git: bool) {
     let store = repo.store();
 
     // The file name encodes the state in the base and in each side ("_" means
-    // missing)
+    // missing). The path is included in the contents so the files aren't
+    // considered renames of each other.
     let files = vec![
         "__a", // side 2 added
         "_a_", // side 1 added
@@ -60,7 +69,7 @@ fn test_same_type(use_git: bool) {
                 testutils::write_normal_file(
                     &mut tree_builder,
                     &RepoPath::from_internal_string(path),
-                    contents,
+                    &format!("contents {} of {:?}", contents, path),
                 );
             }
         }
@@ -127,10 +136,12 @@ fn test_same_type(use_git: bool) {
                 conflict.adds,
                 vec![
                     ConflictPart {
-                        value: side1_tree.value(&component).cloned().unwrap()
+                        value: side1_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                     ConflictPart {
-                        value: side2_tree.value(&component).cloned().unwrap()
+                        value: side2_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     }
                 ]
             );
@@ -147,13 +158,15 @@ fn test_same_type(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![ConflictPart {
-                    value: side2_tree.value(&component).cloned().unwrap()
+                    value: side2_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
         }
@@ -168,13 +181,15 @@ fn test_same_type(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![ConflictPart {
-                    value: side1_tree.value(&component).cloned().unwrap()
+                    value: side1_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
         }
@@ -189,17 +204,20 @@ fn test_same_type(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![
                     ConflictPart {
-                        value: side1_tree.value(&component).cloned().unwrap()
+                        value: side1_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                     ConflictPart {
-                        value: side2_tree.value(&component).cloned().unwrap()
+                        value: side2_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     }
                 ]
             );
@@ -407,17 +425,20 @@ fn test_types(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![
                     ConflictPart {
-                        value: side1_tree.value(&component).cloned().unwrap()
+                        value: side1_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                     ConflictPart {
-                        value: side2_tree.value(&component).cloned().unwrap()
+                        value: side2_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                 ]
             );
@@ -433,17 +454,20 @@ fn test_types(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![
                     ConflictPart {
-                        value: side1_tree.value(&component).cloned().unwrap()
+                        value: side1_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                     ConflictPart {
-                        value: side2_tree.value(&component).cloned().unwrap()
+                        value: side2_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                 ]
             );
@@ -452,6 +476,432 @@ fn test_types(use_git: bool) {
     };
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_follow_renames(use_git: bool) {
+    // Tests that changes to a file get merged into the file when the other side
+    // renamed it
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let old_path = RepoPath::from_internal_string("old");
+    let new_path = RepoPath::from_internal_string("dir/new");
+    let base_tree = testutils::create_tree(repo, &[(&old_path, "a\nb\nc\nd\n")]);
+    let side1_tree = testutils::create_tree(repo, &[(&new_path, "A\nb\nc\nd\n")]);
+    let side2_tree = testutils::create_tree(repo, &[(&old_path, "a\nb\nc\nD\n")]);
+
+    for (side1_tree, side2_tree) in [(&side1_tree, &side2_tree), (&side2_tree, &side1_tree)] {
+        let merged_tree_id = tree::merge_trees(side1_tree, &base_tree, side2_tree).unwrap();
+        let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+        assert_eq!(merged_tree.path_value(&old_path), None);
+        match merged_tree.path_value(&new_path) {
+            Some(TreeValue::Normal {
+                id,
+                executable: false,
+            }) => {
+                assert_eq!(testutils::read_file(store, &new_path, &id), b"A\nb\nc\nD\n");
+            }
+            other => {
+                panic!("unexpected value: {:#?}", other);
+            }
+        }
+    }
+
+    // If the file was renamed on both sides, it's not followed
+    let other_path = RepoPath::from_internal_string("other");
+    let side2_tree = testutils::create_tree(repo, &[(&other_path, "a\nb\nc\nD\n")]);
+    let merged_tree_id = tree::merge_trees(&side1_tree, &base_tree, &side2_tree).unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+    assert_eq!(
+        merged_tree
+            .entries()
+            .map(|(path, _)| path.to_internal_file_string())
+            .collect_vec(),
+        vec!["dir/new", "other"]
+    );
+}
+
+#[test]
+fn test_modify_delete_policy() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string("file");
+    let other_path = RepoPath::from_internal_string("other");
+    let base_tree = testutils::create_tree(repo, &[(&path, "a\n"), (&other_path, "a\n")]);
+    let modified_tree = testutils::create_tree(repo, &[(&path, "b\n"), (&other_path, "a\n")]);
+    let deleted_tree = testutils::create_tree(repo, &[(&other_path, "a\n")]);
+
+    let store_path = repo.repo_path().join("store");
+    let merge_with_policy = |policy: ModifyDeletePolicy, side1_tree: &Tree, side2_tree: &Tree| {
+        let options = StoreOptions {
+            modify_delete_policy: policy,
+            ..StoreOptions::default()
+        };
+        let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+        let get_tree = |tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+        let merged_tree_id = tree::merge_trees(
+            &get_tree(side1_tree),
+            &get_tree(&base_tree),
+            &get_tree(side2_tree),
+        )
+        .unwrap();
+        let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+        merged_tree.path_value(&path)
+    };
+
+    for (side1_tree, side2_tree) in [
+        (&modified_tree, &deleted_tree),
+        (&deleted_tree, &modified_tree),
+    ] {
+        assert_matches!(
+            merge_with_policy(ModifyDeletePolicy::Ask, side1_tree, side2_tree),
+            Some(TreeValue::Conflict(_))
+        );
+        assert_eq!(
+            merge_with_policy(ModifyDeletePolicy::KeepModified, side1_tree, side2_tree),
+            modified_tree.path_value(&path)
+        );
+        assert_eq!(
+            merge_with_policy(ModifyDeletePolicy::KeepDeleted, side1_tree, side2_tree),
+            None
+        );
+    }
+}
+
+#[test]
+fn test_follow_renames_disabled() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let old_path = RepoPath::from_internal_string("old");
+    let new_path = RepoPath::from_internal_string("new");
+    let base_tree = testutils::create_tree(repo, &[(&old_path, "a\nb\nc\nd\n")]);
+    let side1_tree = testutils::create_tree(repo, &[(&new_path, "a\nb\nc\nd\n")]);
+    let side2_tree = testutils::create_tree(repo, &[(&old_path, "a\nb\nc\nD\n")]);
+
+    // The rename is treated like the removal of the old file
+    let store_path = repo.repo_path().join("store");
+    let options = StoreOptions {
+        follow_renames: false,
+        ..StoreOptions::default()
+    };
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+    let get_tree = |tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&side1_tree),
+        &get_tree(&base_tree),
+        &get_tree(&side2_tree),
+    )
+    .unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+    assert_matches!(
+        merged_tree.path_value(&old_path),
+        Some(TreeValue::Conflict(_))
+    );
+    assert_eq!(
+        merged_tree.path_value(&new_path),
+        side1_tree.path_value(&new_path)
+    );
+}
+
+#[test]
+fn test_merge_cache() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string("file");
+    let base_tree = testutils::create_tree(repo, &[(&path, "a\nb\nc\n")]);
+    let side1_tree = testutils::create_tree(repo, &[(&path, "A\nb\nc\n")]);
+    let side2_tree = testutils::create_tree(repo, &[(&path, "a\nb\nC\n")]);
+    let expected_tree = testutils::create_tree(repo, &[(&path, "A\nb\nC\n")]);
+
+    let store_path = repo.repo_path().join("store");
+    let temp_dir = testutils::new_temp_dir();
+    let options = StoreOptions {
+        merge_cache_dir: Some(temp_dir.path().join("merges")),
+        ..StoreOptions::default()
+    };
+    let new_store =
+        || Store::with_options(Box::new(LocalBackend::load(&store_path)), options.clone());
+    let get_tree =
+        |store: &Arc<Store>, tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+
+    let store = new_store();
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&store, &side1_tree),
+        &get_tree(&store, &base_tree),
+        &get_tree(&store, &side2_tree),
+    )
+    .unwrap();
+    assert_eq!(merged_tree_id, *expected_tree.id());
+
+    // The result is available to later processes
+    let store = new_store();
+    assert_eq!(
+        store.cached_merged_tree_id(side1_tree.id(), base_tree.id(), side2_tree.id()),
+        Some(merged_tree_id.clone())
+    );
+    // The order of the sides matters, since it determines the order in conflicts
+    assert_eq!(
+        store.cached_merged_tree_id(side2_tree.id(), base_tree.id(), side1_tree.id()),
+        None
+    );
+
+    // Cached results are used as is
+    store.record_merged_tree_id(
+        side2_tree.id(),
+        base_tree.id(),
+        side1_tree.id(),
+        side1_tree.id(),
+    );
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&store, &side2_tree),
+        &get_tree(&store, &base_tree),
+        &get_tree(&store, &side1_tree),
+    )
+    .unwrap();
+    assert_eq!(merged_tree_id, *side1_tree.id());
+
+    // Unless the merged tree no longer exists
+    let missing_tree_id = TreeId::new(vec![0; store.hash_length()]);
+    store.record_merged_tree_id(
+        side2_tree.id(),
+        base_tree.id(),
+        side1_tree.id(),
+        &missing_tree_id,
+    );
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&store, &side2_tree),
+        &get_tree(&store, &base_tree),
+        &get_tree(&store, &side1_tree),
+    )
+    .unwrap();
+    assert_eq!(merged_tree_id, *expected_tree.id());
+}
+
+#[test]
+fn test_merge_cache_with_other_options() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string("file");
+    let base_tree = testutils::create_tree(repo, &[(&path, "a\n")]);
+    let modified_tree = testutils::create_tree(repo, &[(&path, "b\n")]);
+    let deleted_tree = testutils::create_tree(repo, &[]);
+
+    let store_path = repo.repo_path().join("store");
+    let temp_dir = testutils::new_temp_dir();
+    let merge_with_policy = |policy: ModifyDeletePolicy| {
+        let options = StoreOptions {
+            merge_cache_dir: Some(temp_dir.path().join("merges")),
+            modify_delete_policy: policy,
+            ..StoreOptions::default()
+        };
+        let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+        let get_tree = |tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+        let merged_tree_id = tree::merge_trees(
+            &get_tree(&modified_tree),
+            &get_tree(&base_tree),
+            &get_tree(&deleted_tree),
+        )
+        .unwrap();
+        let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+        merged_tree.path_value(&path)
+    };
+
+    // The conflict from the merge with the default options is cached, but not
+    // used when the options change
+    assert_matches!(
+        merge_with_policy(ModifyDeletePolicy::Ask),
+        Some(TreeValue::Conflict(_))
+    );
+    assert_eq!(
+        merge_with_policy(ModifyDeletePolicy::KeepModified),
+        modified_tree.path_value(&path)
+    );
+    assert_matches!(
+        merge_with_policy(ModifyDeletePolicy::Ask),
+        Some(TreeValue::Conflict(_))
+    );
+}
+
+#[test]
+fn test_merge_algorithm_patterns() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    // One side moves the last line to the top, and the other side changes a
+    // line between repeated lines
+    let text_path = RepoPath::from_internal_string("file.txt");
+    let code_path = RepoPath::from_internal_string("file.rs");
+    let tree_with_contents = |contents: &str| {
+        testutils::create_tree(repo, &[(&text_path, contents), (&code_path, contents)])
+    };
+    let base_tree = tree_with_contents("a\nb\na\nc\n");
+    let side1_tree = tree_with_contents("c\na\nb\na\n");
+    let side2_tree = tree_with_contents("a\nB\na\nc\n");
+
+    let store_path = repo.repo_path().join("store");
+    let mut merge_algorithms = MergeAlgorithms::new(DiffAlgorithm::Lcs);
+    merge_algorithms.add_patterns(&["*.rs".to_string()], DiffAlgorithm::Histogram);
+    let options = StoreOptions {
+        merge_algorithms,
+        ..StoreOptions::default()
+    };
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+    let get_tree = |tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&side1_tree),
+        &get_tree(&base_tree),
+        &get_tree(&side2_tree),
+    )
+    .unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+
+    // The histogram algorithm keeps the moved line apart from the changed one
+    let expected_id = testutils::write_file(&store, &code_path, "c\na\nB\na\n");
+    assert_eq!(
+        merged_tree.path_value(&code_path),
+        Some(TreeValue::Normal {
+            id: expected_id,
+            executable: false
+        })
+    );
+    assert_matches!(
+        merged_tree.path_value(&text_path),
+        Some(TreeValue::Conflict(_))
+    );
+}
+
+#[test]
+fn test_merge_drivers() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let union_path = RepoPath::from_internal_string("CHANGELOG.md");
+    let ours_path = RepoPath::from_internal_string("image.png");
+    let theirs_path = RepoPath::from_internal_string("data/generated.txt");
+    let plain_path = RepoPath::from_internal_string("file.txt");
+    let tree_with_contents = |contents: &str| {
+        testutils::create_tree(
+            repo,
+            &[
+                (&union_path, contents),
+                (&ours_path, contents),
+                (&theirs_path, contents),
+                (&plain_path, contents),
+            ],
+        )
+    };
+    let base_tree = tree_with_contents("a\nb\n");
+    let side1_tree = tree_with_contents("a\nb\nleft\n");
+    let side2_tree = tree_with_contents("a\nb\nright\n");
+
+    let store_path = repo.repo_path().join("store");
+    let mut merge_drivers = MergeDrivers::new();
+    merge_drivers.add_patterns(&["CHANGELOG.md".to_string()], MergeDriver::Union);
+    merge_drivers.add_patterns(&["*.png".to_string()], MergeDriver::Ours);
+    merge_drivers.add_patterns(&["data/*".to_string()], MergeDriver::Theirs);
+    let options = StoreOptions {
+        merge_drivers,
+        ..StoreOptions::default()
+    };
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+    let get_tree = |tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&side1_tree),
+        &get_tree(&base_tree),
+        &get_tree(&side2_tree),
+    )
+    .unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+
+    let file_value = |path: &RepoPath, contents: &str| {
+        Some(TreeValue::Normal {
+            id: testutils::write_file(&store, path, contents),
+            executable: false,
+        })
+    };
+    assert_eq!(
+        merged_tree.path_value(&union_path),
+        file_value(&union_path, "a\nb\nleft\nright\n")
+    );
+    assert_eq!(
+        merged_tree.path_value(&ours_path),
+        file_value(&ours_path, "a\nb\nleft\n")
+    );
+    assert_eq!(
+        merged_tree.path_value(&theirs_path),
+        file_value(&theirs_path, "a\nb\nright\n")
+    );
+    assert_matches!(
+        merged_tree.path_value(&plain_path),
+        Some(TreeValue::Conflict(_))
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn test_merge_driver_command() {
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let resolved_path = RepoPath::from_internal_string("file.json");
+    let failed_path = RepoPath::from_internal_string("file.xml");
+    let tree_with_contents = |contents: &str| {
+        testutils::create_tree(
+            repo,
+            &[(&resolved_path, contents), (&failed_path, contents)],
+        )
+    };
+    let base_tree = tree_with_contents("base\n");
+    let side1_tree = tree_with_contents("left\n");
+    let side2_tree = tree_with_contents("right\n");
+
+    let store_path = repo.repo_path().join("store");
+    let mut merge_drivers = MergeDrivers::new();
+    let command = ["sh", "-c", r#"cat "$1" "$2" "$3" > "$4""#, "sh"]
+        .iter()
+        .chain(&["$right", "$base", "$left", "$output"])
+        .map(|arg| arg.to_string())
+        .collect_vec();
+    merge_drivers.add_patterns(&["*.json".to_string()], MergeDriver::Command(command));
+    merge_drivers.add_patterns(
+        &["*.xml".to_string()],
+        MergeDriver::Command(vec!["false".to_string()]),
+    );
+    let options = StoreOptions {
+        merge_drivers,
+        ..StoreOptions::default()
+    };
+    let store = Store::with_options(Box::new(LocalBackend::load(&store_path)), options);
+    let get_tree = |tree: &Tree| store.get_tree(&RepoPath::root(), tree.id()).unwrap();
+    let merged_tree_id = tree::merge_trees(
+        &get_tree(&side1_tree),
+        &get_tree(&base_tree),
+        &get_tree(&side2_tree),
+    )
+    .unwrap();
+    let merged_tree = store.get_tree(&RepoPath::root(), &merged_tree_id).unwrap();
+
+    // The command's output is the merged file
+    let expected_id = testutils::write_file(&store, &resolved_path, "right\nbase\nleft\n");
+    assert_eq!(
+        merged_tree.path_value(&resolved_path),
+        Some(TreeValue::Normal {
+            id: expected_id,
+            executable: false
+        })
+    );
+    // A failing command leaves the conflict
+    assert_matches!(
+        merged_tree.path_value(&failed_path),
+        Some(TreeValue::Conflict(_))
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_simplify_conflict(use_git: bool) {
@@ -508,17 +958,20 @@ fn test_simplify_conflict(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![
                     ConflictPart {
-                        value: branch_tree.value(&component).cloned().unwrap()
+                        value: branch_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                     ConflictPart {
-                        value: upstream2_tree.value(&component).cloned().unwrap()
+                        value: upstream2_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                 ]
             );
@@ -532,17 +985,20 @@ fn test_simplify_conflict(use_git: bool) {
             assert_eq!(
                 conflict.removes,
                 vec![ConflictPart {
-                    value: base_tree.value(&component).cloned().unwrap()
+                    value: base_tree.value(&component).cloned().unwrap(),
+                    label: None,
                 }]
             );
             assert_eq!(
                 conflict.adds,
                 vec![
                     ConflictPart {
-                        value: upstream2_tree.value(&component).cloned().unwrap()
+                        value: upstream2_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                     ConflictPart {
-                        value: branch_tree.value(&component).cloned().unwrap()
+                        value: branch_tree.value(&component).cloned().unwrap(),
+                        label: None,
                     },
                 ]
             );
@@ -592,8 +1048,9 @@ fn test_simplify_conflict_after_resolving_parent(use_git: bool) {
         CommitBuilder::for_new_commit(&settings, vec![commit_a.id().clone()], tree_d.id().clone())
             .write_to_repo(tx.mut_repo());
 
-    let commit_b2 = rebase_commit(&settings, tx.mut_repo(), &commit_b, &[commit_d]);
-    let commit_c2 = rebase_commit(&settings, tx.mut_repo(), &commit_c, &[commit_b2.clone()]);
+    let commit_b2 = rebase_commit(&settings, tx.mut_repo(), &commit_b, &[commit_d]).unwrap();
+    let commit_c2 =
+        rebase_commit(&settings, tx.mut_repo(), &commit_c, &[commit_b2.clone()]).unwrap();
 
     // Test the setup: Both B and C should have conflicts.
     assert_matches!(
@@ -610,7 +1067,7 @@ fn test_simplify_conflict_after_resolving_parent(use_git: bool) {
     let commit_b3 = CommitBuilder::for_rewrite_from(&settings, &commit_b2)
         .set_tree(tree_b3.id().clone())
         .write_to_repo(tx.mut_repo());
-    let commit_c3 = rebase_commit(&settings, tx.mut_repo(), &commit_c2, &[commit_b3]);
+    let commit_c3 = rebase_commit(&settings, tx.mut_repo(), &commit_c2, &[commit_b3]).unwrap();
     tx.mut_repo().rebase_descendants(&settings).unwrap();
     let repo = tx.commit();
 
@@ -632,5 +1089,65 @@ fn test_simplify_conflict_after_resolving_parent(use_git: bool) {
     }
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_rebase_conflict_labels(use_git: bool) {
+    // Tests that the sides of a conflict from a rebase are labeled with the
+    // commits they came from, and that they keep their labels when the conflict
+    // is rebased again.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let path = RepoPath::from_internal_string("file");
+    let mut tx = repo.start_transaction("test");
+    let mut write_commit = |parent: &Commit, contents: &str, description: &str| {
+        let tree = testutils::create_tree(repo, &[(&path, contents)]);
+        CommitBuilder::for_new_commit(&settings, vec![parent.id().clone()], tree.id().clone())
+            .set_description(description.to_string())
+            .write_to_repo(tx.mut_repo())
+    };
+    let root_commit = repo.store().root_commit();
+    let commit_a = write_commit(&root_commit, "a\n", "A");
+    let commit_b = write_commit(&commit_a, "b\n", "B");
+    let commit_c = write_commit(&commit_a, "c\n", "C\n\nmore description");
+    let commit_d = write_commit(&commit_c, "d\n", "");
+
+    let label = |commit: &Commit| Some(commit_conflict_label(commit));
+    let read_conflict = |commit: &Commit| match commit.tree().path_value(&path) {
+        Some(TreeValue::Conflict(id)) => repo.store().read_conflict(&path, &id).unwrap(),
+        other => panic!("unexpected value: {:#?}", other),
+    };
+    let labels = |parts: &[ConflictPart]| parts.iter().map(|part| part.label.clone()).collect_vec();
+
+    let commit_b2 =
+        rebase_commit(&settings, tx.mut_repo(), &commit_b, &[commit_c.clone()]).unwrap();
+    let conflict = read_conflict(&commit_b2);
+    assert_eq!(labels(&conflict.removes), vec![label(&commit_a)]);
+    assert_eq!(
+        labels(&conflict.adds),
+        vec![label(&commit_c), label(&commit_b)]
+    );
+    assert_eq!(
+        commit_conflict_label(&commit_c),
+        format!("{} C", &commit_c.id().hex()[..12])
+    );
+    assert_eq!(
+        commit_conflict_label(&commit_d),
+        format!("{} (no description set)", &commit_d.id().hex()[..12])
+    );
+
+    // Rebasing the conflict onto D replaces the side from C, and keeps the labels
+    // of the other sides
+    let commit_b3 =
+        rebase_commit(&settings, tx.mut_repo(), &commit_b2, &[commit_d.clone()]).unwrap();
+    let conflict = read_conflict(&commit_b3);
+    assert_eq!(labels(&conflict.removes), vec![label(&commit_a)]);
+    assert_eq!(
+        labels(&conflict.adds),
+        vec![label(&commit_d), label(&commit_b)]
+    );
+}
+
 // TODO: Add tests for simplification of multi-way conflicts. Both the content
 // and the executable bit need testing.
@@ -0,0 +1,166 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::backend::{Backend, TreeValue};
+use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::git_backend::GitBackend;
+use jujutsu_lib::local_backend::LocalBackend;
+use jujutsu_lib::migrate::migrate_repo;
+use jujutsu_lib::op_store::RefTarget;
+use jujutsu_lib::repo::{BackendFactories, ReadonlyRepo};
+use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use test_case::test_case;
+
+#[test_case(false ; "local to git")]
+#[test_case(true ; "git to local")]
+fn test_migrate_repo(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let path = RepoPath::from_internal_string("dir/file");
+    let tree1 = testutils::create_tree(repo, &[(&path, "contents 1\n")]);
+    let tree2 = testutils::create_tree(repo, &[(&path, "contents 2\n")]);
+    let mut tx = repo.start_transaction("add commits");
+    let commit1 = CommitBuilder::for_new_commit(
+        &settings,
+        vec![store.root_commit_id().clone()],
+        tree1.id().clone(),
+    )
+    .set_description("commit 1".to_string())
+    .write_to_repo(tx.mut_repo());
+    let commit2 =
+        CommitBuilder::for_new_commit(&settings, vec![commit1.id().clone()], tree2.id().clone())
+            .set_description("commit 2".to_string())
+            .write_to_repo(tx.mut_repo());
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit2.id().clone()));
+    let repo = tx.commit();
+    let mut tx = repo.start_transaction("rewrite commit");
+    let commit3 = CommitBuilder::for_rewrite_from(&settings, &commit2)
+        .set_description("rewritten".to_string())
+        .write_to_repo(tx.mut_repo());
+    tx.mut_repo()
+        .record_rewritten_commit(commit2.id().clone(), commit3.id().clone());
+    tx.mut_repo().rebase_descendants(&settings).unwrap();
+    let repo = tx.commit();
+
+    let migration = migrate_repo(&repo, |store_path| -> Box<dyn Backend> {
+        if use_git {
+            Box::new(LocalBackend::init(store_path))
+        } else {
+            Box::new(GitBackend::init_internal(store_path))
+        }
+    })
+    .unwrap();
+    let new_repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let new_store = new_repo.store();
+    assert_eq!(
+        new_store.backend_name(),
+        if use_git { "local" } else { "git" }
+    );
+
+    // The commits are copied with their change ids, predecessors and contents
+    let new_commit3 = new_store
+        .get_commit(&migration.commit_ids[commit3.id()])
+        .unwrap();
+    assert_ne!(new_commit3.id(), commit3.id());
+    assert_eq!(new_commit3.change_id(), commit3.change_id());
+    assert_eq!(new_commit3.description(), "rewritten");
+    assert_eq!(
+        new_commit3.parent_ids(),
+        vec![migration.commit_ids[commit1.id()].clone()]
+    );
+    assert_eq!(
+        new_commit3.predecessor_ids(),
+        vec![migration.commit_ids[commit2.id()].clone()]
+    );
+    match new_commit3.tree().path_value(&path) {
+        Some(TreeValue::Normal { id, .. }) => {
+            assert_eq!(testutils::read_file(new_store, &path, &id), b"contents 2\n");
+        }
+        value => panic!("unexpected value {:?}", value),
+    }
+
+    // The view and the operation log refer to the new ids
+    assert_eq!(
+        new_repo.view().get_local_branch("main"),
+        Some(RefTarget::Normal(new_commit3.id().clone()))
+    );
+    assert_eq!(
+        *new_repo.view().heads(),
+        repo.view()
+            .heads()
+            .iter()
+            .map(|id| migration.commit_ids[id].clone())
+            .collect()
+    );
+    assert_eq!(new_repo.op_id(), &migration.operation_ids[repo.op_id()]);
+    assert_eq!(
+        new_repo.operation().store_operation().metadata,
+        repo.operation().store_operation().metadata
+    );
+    // The initial operation, adding the commits, and rewriting one
+    assert_eq!(migration.operation_ids.len(), 3);
+    assert_eq!(new_repo.operation().parents().len(), 1);
+
+    // The index is rebuilt from the new operation log
+    assert!(new_repo.index().has_id(new_commit3.id()));
+}
+
+#[test]
+fn test_migrate_commits_differing_only_in_change_id() {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    // These would be the same commit in the Git backend
+    let mut tx = repo.start_transaction("add commits");
+    let new_commit = || {
+        CommitBuilder::for_new_commit(
+            &settings,
+            vec![store.root_commit_id().clone()],
+            store.empty_tree_id().clone(),
+        )
+    };
+    let commit1 = new_commit().write_to_repo(tx.mut_repo());
+    let commit2 = new_commit().write_to_repo(tx.mut_repo());
+    assert_ne!(commit1.change_id(), commit2.change_id());
+    let repo = tx.commit();
+
+    let migration = migrate_repo(&repo, |store_path| -> Box<dyn Backend> {
+        Box::new(GitBackend::init_internal(store_path))
+    })
+    .unwrap();
+    let new_repo =
+        ReadonlyRepo::load_at_head(&settings, repo.repo_path(), &BackendFactories::default())
+            .unwrap();
+    let new_commit1 = new_repo
+        .store()
+        .get_commit(&migration.commit_ids[commit1.id()])
+        .unwrap();
+    let new_commit2 = new_repo
+        .store()
+        .get_commit(&migration.commit_ids[commit2.id()])
+        .unwrap();
+    assert_ne!(new_commit1.id(), new_commit2.id());
+    assert_eq!(new_commit1.change_id(), commit1.change_id());
+    assert_eq!(new_commit2.change_id(), commit2.change_id());
+}
@@ -62,7 +62,8 @@ fn test_checkout_closed(use_git: bool) {
     let ws_id = WorkspaceId::default();
     let actual_checkout = tx
         .mut_repo()
-        .check_out(ws_id.clone(), &settings, &requested_checkout);
+        .check_out(ws_id.clone(), &settings, &requested_checkout)
+        .unwrap();
     assert_eq!(actual_checkout.tree_id(), requested_checkout.tree_id());
     assert_eq!(actual_checkout.parents().len(), 1);
     assert_eq!(actual_checkout.parents()[0].id(), requested_checkout.id());
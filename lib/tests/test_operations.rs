@@ -12,13 +12,20 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::collections::HashSet;
 use std::path::Path;
 
 use jujutsu_lib::backend::CommitId;
 use jujutsu_lib::commit_builder::CommitBuilder;
-use jujutsu_lib::repo::RepoRef;
+use jujutsu_lib::op_abandon;
+use jujutsu_lib::op_abandon::OpAbandonError;
+use jujutsu_lib::op_hooks::{OperationSummary, RefChange};
+use jujutsu_lib::op_store::{OperationId, RefTarget, WorkspaceId};
+use jujutsu_lib::repo::{ReadonlyRepo, RepoRef};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::TestRepo;
+use jujutsu_lib::view::RefName;
+use maplit::{btreemap, hashset};
 use test_case::test_case;
 
 fn list_dir(dir: &Path) -> Vec<String> {
@@ -179,3 +186,128 @@ fn test_isolation(use_git: bool) {
     let repo = repo.reload_at_head(&settings).unwrap();
     assert_heads(repo.as_repo_ref(), vec![rewrite1.id(), rewrite2.id()]);
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_abandon_operations(use_git: bool) {
+    // Test that abandoning operations reparents their descendants and keeps the
+    // views as they were
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let mut repo = test_repo.repo.clone();
+    let mut op_ids = vec![repo.op_id().clone()];
+    for i in 1..=3 {
+        let mut tx = repo.start_transaction(&format!("transaction {i}"));
+        testutils::create_random_commit(&settings, &repo).write_to_repo(tx.mut_repo());
+        repo = tx.commit();
+        op_ids.push(repo.op_id().clone());
+    }
+    let head_view_id = repo.operation().store_operation().view_id.clone();
+
+    let abandonment =
+        op_abandon::abandon_operations(&repo, &hashset! {op_ids[1].clone(), op_ids[2].clone()})
+            .unwrap();
+    assert_eq!(abandonment.abandoned_count, 2);
+    assert_eq!(abandonment.rewritten_ids.len(), 1);
+    let new_head_id = abandonment.rewritten_ids[&op_ids[3]].clone();
+    let repo = repo.reload_at_head(&settings).unwrap();
+    assert_eq!(repo.op_id(), &new_head_id);
+    let new_head = repo.operation().store_operation();
+    assert_eq!(new_head.parents, vec![op_ids[0].clone()]);
+    assert_eq!(new_head.view_id, head_view_id);
+
+    // The current operation and the root operation can't be abandoned
+    assert!(matches!(
+        op_abandon::abandon_operations(&repo, &hashset! {new_head_id.clone()}),
+        Err(OpAbandonError::HeadOperation(id)) if id == new_head_id
+    ));
+    let root_op_id = root_operation_id(&repo);
+    assert!(matches!(
+        op_abandon::abandon_operations(&repo, &hashset! {root_op_id}),
+        Err(OpAbandonError::RootOperation)
+    ));
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_find_expired_operations(use_git: bool) {
+    // Test that the ancestors of expired operations expire too, but not the
+    // root operation or the current operation
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let mut repo = test_repo.repo.clone();
+    let mut op_ids = vec![repo.op_id().clone()];
+    for i in 1..=3 {
+        let mut tx = repo.start_transaction(&format!("transaction {i}"));
+        testutils::create_random_commit(&settings, &repo).write_to_repo(tx.mut_repo());
+        repo = tx.commit();
+        op_ids.push(repo.op_id().clone());
+    }
+    let root_op_id = root_operation_id(&repo);
+
+    let op2 = repo.op_store().read_operation(&op_ids[2]).unwrap();
+    let mut cutoff = op2.metadata.end_time.timestamp;
+    cutoff.0 += 1;
+    let expired = op_abandon::find_expired_operations(&repo, cutoff).unwrap();
+    let expected: HashSet<OperationId> = op_ids[..3]
+        .iter()
+        .filter(|id| **id != root_op_id)
+        .cloned()
+        .collect();
+    assert_eq!(expired, expected);
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_operation_summary(use_git: bool) {
+    // Test that the summary lists the heads, working-copy commits and refs the
+    // operation changed
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let root_commit_id = repo.store().root_commit_id().clone();
+    let ws_id = WorkspaceId::new("ws".to_string());
+
+    let mut tx = repo.start_transaction("test");
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(tx.mut_repo());
+    tx.mut_repo()
+        .set_wc_commit(ws_id.clone(), commit1.id().clone());
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    let new_repo = tx.commit();
+
+    let summary = OperationSummary::new(new_repo.operation(), repo.view(), new_repo.view());
+    assert_eq!(summary.operation_id, *new_repo.op_id());
+    assert_eq!(summary.parent_ids, vec![repo.op_id().clone()]);
+    assert_eq!(summary.description, "test");
+    assert_eq!(summary.added_heads, vec![commit1.id().clone()]);
+    assert_eq!(summary.removed_heads, vec![root_commit_id]);
+    assert_eq!(
+        summary.wc_commit_ids,
+        btreemap! { ws_id => Some(commit1.id().clone()) }
+    );
+    assert_eq!(
+        summary.ref_changes,
+        vec![RefChange {
+            name: RefName::LocalBranch("main".to_string()),
+            old_target: None,
+            new_target: Some(RefTarget::Normal(commit1.id().clone())),
+        }]
+    );
+    assert_eq!(
+        summary.to_json()["refs"][0]["new"],
+        serde_json::json!(commit1.id().hex())
+    );
+
+    // Nothing changed relative to the view itself
+    let summary = OperationSummary::new(new_repo.operation(), new_repo.view(), new_repo.view());
+    assert!(summary.is_empty());
+}
+
+fn root_operation_id(repo: &ReadonlyRepo) -> OperationId {
+    let mut op = repo.operation().clone();
+    while let Some(parent) = op.parents().pop() {
+        op = parent;
+    }
+    op.id().clone()
+}
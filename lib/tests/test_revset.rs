@@ -235,6 +235,44 @@ fn test_resolve_symbol_change_id() {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_resolve_symbol_change_id_hidden(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    let change_id_hex = commit2.change_id().hex();
+
+    // Divergent commits are all included, newest first
+    let commit3 = CommitBuilder::for_rewrite_from(&settings, &commit2)
+        .set_description("rewritten".to_string())
+        .write_to_repo(mut_repo);
+    assert_eq!(
+        resolve_symbol(mut_repo.as_repo_ref(), &change_id_hex, None),
+        Ok(vec![commit3.id().clone(), commit2.id().clone()])
+    );
+
+    // Hidden commits are not included
+    mut_repo.record_abandoned_commit(commit2.id().clone());
+    mut_repo.rebase_descendants(&settings).unwrap();
+    assert_eq!(
+        resolve_symbol(mut_repo.as_repo_ref(), &change_id_hex, None),
+        Ok(vec![commit3.id().clone()])
+    );
+    mut_repo.record_abandoned_commit(commit3.id().clone());
+    mut_repo.rebase_descendants(&settings).unwrap();
+    assert_eq!(
+        resolve_symbol(mut_repo.as_repo_ref(), &change_id_hex, None),
+        Err(RevsetError::NoSuchRevision(change_id_hex.clone()))
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_resolve_symbol_checkout(use_git: bool) {
@@ -1365,6 +1403,41 @@ fn test_evaluate_expression_merges(use_git: bool) {
     );
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_evaluate_expression_divergent(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let mut_repo = tx.mut_repo();
+    let mut graph_builder = CommitGraphBuilder::new(&settings, mut_repo);
+    let commit1 = graph_builder.initial_commit();
+    let commit2 = graph_builder.commit_with_parents(&[&commit1]);
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "divergent()"),
+        vec![]
+    );
+
+    // Rewrite commit2 without hiding the original, making its change divergent
+    let commit3 = CommitBuilder::for_rewrite_from(&settings, &commit2)
+        .set_description("rewritten".to_string())
+        .write_to_repo(mut_repo);
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "divergent()"),
+        vec![commit3.id().clone(), commit2.id().clone()]
+    );
+
+    // Once one of the commits is abandoned, the change is no longer divergent
+    mut_repo.record_abandoned_commit(commit2.id().clone());
+    mut_repo.rebase_descendants(&settings).unwrap();
+    assert_eq!(
+        resolve_commit_ids(mut_repo.as_repo_ref(), "divergent()"),
+        vec![]
+    );
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_evaluate_expression_description(use_git: bool) {
@@ -12,12 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
 use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
 use jujutsu_lib::repo_path::RepoPath;
-use jujutsu_lib::rewrite::DescendantRebaser;
+use jujutsu_lib::rewrite::{
+    can_merge_cleanly, merge_commit_trees, new_conflict_paths, simulate_merge, DescendantRebaser,
+};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::{assert_rebased, create_random_commit, CommitGraphBuilder, TestRepo};
+use jujutsu_lib::transaction::Transaction;
 use maplit::{hashmap, hashset};
 use test_case::test_case;
 
@@ -1394,3 +1398,54 @@ fn test_rebase_descendants_update_checkout_abandoned_merge(use_git: bool) {
     assert!(checkout.is_open());
     assert_eq!(checkout.parent_ids(), vec![commit_b.id().clone()]);
 }
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_simulate_merge(use_git: bool) {
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+
+    // B and C change file1 differently, D changes file2
+    //
+    // B C D
+    // |/_/
+    // A
+    let mut tx = repo.start_transaction("test");
+    let path1 = RepoPath::from_internal_string("file1");
+    let path2 = RepoPath::from_internal_string("file2");
+    let new_commit = |tx: &mut Transaction, parent: &Commit, contents: &[(&RepoPath, &str)]| {
+        let tree = testutils::create_tree(repo, contents);
+        CommitBuilder::for_new_commit(&settings, vec![parent.id().clone()], tree.id().clone())
+            .write_to_repo(tx.mut_repo())
+    };
+    let root_commit = repo.store().root_commit();
+    let commit_a = new_commit(&mut tx, &root_commit, &[(&path1, "a"), (&path2, "a")]);
+    let commit_b = new_commit(&mut tx, &commit_a, &[(&path1, "b"), (&path2, "a")]);
+    let commit_c = new_commit(&mut tx, &commit_a, &[(&path1, "c"), (&path2, "a")]);
+    let commit_d = new_commit(&mut tx, &commit_a, &[(&path1, "a"), (&path2, "d")]);
+    let mut_repo = tx.mut_repo();
+    let heads = mut_repo.view().heads().clone();
+
+    let repo_ref = mut_repo.as_repo_ref();
+    assert_eq!(
+        simulate_merge(repo_ref, &[commit_b.clone(), commit_c.clone()]),
+        vec![path1.clone()]
+    );
+    assert!(!can_merge_cleanly(
+        repo_ref,
+        &[commit_b.clone(), commit_c.clone()]
+    ));
+    assert!(simulate_merge(repo_ref, &[commit_b.clone(), commit_d.clone()]).is_empty());
+    assert!(can_merge_cleanly(repo_ref, &[commit_b.clone(), commit_d]));
+    // No commits were added to the repo
+    assert_eq!(mut_repo.view().heads(), &heads);
+
+    // Only the conflicts that the old tree didn't have are new
+    let merged_tree = merge_commit_trees(repo_ref, &[commit_b, commit_c]);
+    assert_eq!(
+        new_conflict_paths(&commit_a.tree(), &merged_tree),
+        vec![path1]
+    );
+    assert!(new_conflict_paths(&merged_tree, &merged_tree).is_empty());
+}
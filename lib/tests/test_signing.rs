@@ -0,0 +1,95 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use jujutsu_lib::backend::SecureSig;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::signing::{SigStatus, Verification, Verifier};
+use jujutsu_lib::testutils;
+
+/// Returns settings with a fake gpg that reports signatures containing "bad" as
+/// bad, and others as good signatures by the key in the signature.
+fn fake_gpg_settings(dir: &Path) -> UserSettings {
+    let program = dir.join("fake-gpg");
+    std::fs::write(
+        &program,
+        r#"#!/bin/sh
+cat >/dev/null
+sig="$(cat "$4")"
+case "$sig" in
+  *bad*) echo "[GNUPG:] BADSIG $sig Some One" ;;
+  *) echo "[GNUPG:] GOODSIG $sig Some One <some.one@example.com>" ;;
+esac
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let config = config::Config::builder()
+        .set_override("signing.backends.gpg.program", program.to_str().unwrap())
+        .unwrap()
+        .build()
+        .unwrap();
+    UserSettings::from_config(config)
+}
+
+fn secure_sig(sig: &str) -> SecureSig {
+    SecureSig {
+        data: b"commit data".to_vec(),
+        sig: sig.as_bytes().to_vec(),
+    }
+}
+
+#[test]
+fn test_verify_gpg() {
+    let temp_dir = testutils::new_temp_dir();
+    let verifier = Verifier::from_settings(&fake_gpg_settings(temp_dir.path()));
+
+    assert_eq!(
+        verifier.verify(&secure_sig("KEY1")).unwrap(),
+        Verification {
+            status: SigStatus::Good,
+            key: Some("KEY1".to_string()),
+            display: Some("Some One <some.one@example.com>".to_string()),
+        }
+    );
+    assert_eq!(
+        verifier.verify(&secure_sig("bad")).unwrap().status,
+        SigStatus::Bad
+    );
+}
+
+#[test]
+fn test_verify_trust_callback() {
+    let temp_dir = testutils::new_temp_dir();
+    let verifier = Verifier::from_settings(&fake_gpg_settings(temp_dir.path()))
+        .with_trust(|verification| verification.key.as_deref() == Some("TRUSTED"));
+
+    assert_eq!(
+        verifier.verify(&secure_sig("TRUSTED")).unwrap().status,
+        SigStatus::Good
+    );
+    assert_eq!(
+        verifier.verify(&secure_sig("OTHER")).unwrap().status,
+        SigStatus::Untrusted
+    );
+    // The callback doesn't make bad signatures good
+    assert_eq!(
+        verifier.verify(&secure_sig("bad")).unwrap().status,
+        SigStatus::Bad
+    );
+}
@@ -0,0 +1,66 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent};
+use jujutsu_lib::testutils;
+use jujutsu_lib::testutils::TestRepo;
+use test_case::test_case;
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_unchanged_trees_are_reused(use_git: bool) {
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store();
+
+    let file_path = RepoPath::from_internal_string("dir/sub/file");
+    let other_path = RepoPath::from_internal_string("other/file");
+    let tree = testutils::create_tree(repo, &[(&file_path, "contents"), (&other_path, "other")]);
+
+    // Setting a file to its current value, or removing a file that doesn't
+    // exist, results in the same tree
+    let mut tree_builder = store.tree_builder(tree.id().clone());
+    tree_builder.set(file_path.clone(), tree.path_value(&file_path).unwrap());
+    tree_builder.remove(RepoPath::from_internal_string("dir/missing/file"));
+    assert_eq!(tree_builder.write_tree(), *tree.id());
+
+    // Changing a file and changing it back also does
+    let mut tree_builder = store.tree_builder(tree.id().clone());
+    let changed_tree = testutils::create_tree(repo, &[(&file_path, "changed")]);
+    tree_builder.set(
+        file_path.clone(),
+        changed_tree.path_value(&file_path).unwrap(),
+    );
+    tree_builder.set(file_path.clone(), tree.path_value(&file_path).unwrap());
+    assert_eq!(tree_builder.write_tree(), *tree.id());
+
+    // Only the directories containing the changed file get new ids
+    let mut tree_builder = store.tree_builder(tree.id().clone());
+    tree_builder.set(
+        file_path.clone(),
+        changed_tree.path_value(&file_path).unwrap(),
+    );
+    let new_tree = store
+        .get_tree(&RepoPath::root(), &tree_builder.write_tree())
+        .unwrap();
+    assert_ne!(new_tree.id(), tree.id());
+    let dir = RepoPathComponent::from("dir");
+    let other = RepoPathComponent::from("other");
+    assert_ne!(new_tree.value(&dir), tree.value(&dir));
+    assert_eq!(new_tree.value(&other), tree.value(&other));
+    assert_eq!(
+        new_tree.path_value(&file_path),
+        changed_tree.path_value(&file_path)
+    );
+}
@@ -21,6 +21,9 @@ use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::{CommitGraphBuilder, TestRepo};
 use jujutsu_lib::transaction::Transaction;
+use jujutsu_lib::view::RefName;
+use jujutsu_lib::view_snapshot;
+use jujutsu_lib::view_snapshot::ViewSnapshotError;
 use maplit::{btreemap, hashset};
 use test_case::test_case;
 
@@ -233,6 +236,73 @@ fn test_merge_views_checkout() {
     assert_eq!(repo.view().get_wc_commit_id(&ws7_id), Some(commit3.id()));
 }
 
+#[test]
+fn test_merge_views_report() {
+    // Tests that merging concurrent operations reports the working-copy commits
+    // and refs that couldn't be merged cleanly.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut initial_tx = repo.start_transaction("test");
+    let commit1 =
+        testutils::create_random_commit(&settings, repo).write_to_repo(initial_tx.mut_repo());
+    let commit2 =
+        testutils::create_random_commit(&settings, repo).write_to_repo(initial_tx.mut_repo());
+    let commit3 =
+        testutils::create_random_commit(&settings, repo).write_to_repo(initial_tx.mut_repo());
+    let ws1_id = WorkspaceId::new("ws1".to_string());
+    let ws2_id = WorkspaceId::new("ws2".to_string());
+    initial_tx
+        .mut_repo()
+        .set_wc_commit(ws1_id.clone(), commit1.id().clone());
+    for name in ["main", "feature"] {
+        initial_tx
+            .mut_repo()
+            .set_local_branch(name.to_string(), RefTarget::Normal(commit1.id().clone()));
+    }
+    let repo = initial_tx.commit();
+
+    let mut tx1 = repo.start_transaction("test");
+    tx1.mut_repo()
+        .set_wc_commit(ws1_id.clone(), commit2.id().clone());
+    tx1.mut_repo()
+        .set_wc_commit(ws2_id.clone(), commit2.id().clone());
+    tx1.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit2.id().clone()));
+    tx1.mut_repo().set_local_branch(
+        "feature".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    let repo1 = tx1.commit();
+
+    let mut tx2 = repo.start_transaction("test");
+    tx2.mut_repo()
+        .set_wc_commit(ws1_id.clone(), commit3.id().clone());
+    tx2.mut_repo()
+        .set_wc_commit(ws2_id.clone(), commit3.id().clone());
+    tx2.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit3.id().clone()));
+    let repo2 = tx2.commit();
+
+    let mut tx = repo1.start_transaction("merge");
+    let report = tx.merge_operation(repo2.operation().clone());
+    assert_eq!(report.conflicting_wc_workspaces, vec![ws1_id, ws2_id]);
+    assert_eq!(
+        report.conflicted_refs,
+        vec![RefName::LocalBranch("main".to_string())]
+    );
+    assert!(tx
+        .mut_repo()
+        .get_local_branch("main")
+        .unwrap()
+        .is_conflict());
+    assert_eq!(
+        tx.mut_repo().get_local_branch("feature"),
+        Some(RefTarget::Normal(commit2.id().clone()))
+    );
+}
+
 #[test]
 fn test_merge_views_branches() {
     // Tests merging of branches (by performing concurrent operations). See
@@ -320,10 +390,12 @@ fn test_merge_views_branches() {
             "origin".to_string() => RefTarget::Normal(main_branch_origin_tx1.id().clone()),
             "alternate".to_string() => RefTarget::Normal(main_branch_alternate_tx0.id().clone()),
         },
+        untracked_remotes: Default::default(),
     };
     let expected_feature_branch = BranchTarget {
         local_target: Some(RefTarget::Normal(feature_branch_tx1.id().clone())),
         remote_targets: btreemap! {},
+        untracked_remotes: Default::default(),
     };
     assert_eq!(
         repo.view().branches(),
@@ -594,3 +666,53 @@ fn test_merge_views_child_on_abandoned(child_first: bool) {
     assert_eq!(commit_c2.change_id(), commit_c.change_id());
     assert_eq!(commit_c2.parent_ids(), vec![commit_a.id().clone()]);
 }
+
+#[test]
+fn test_view_snapshot() {
+    // Tests that importing a view snapshot restores the heads and refs, but
+    // keeps the Git refs of the repo it's imported into.
+    let settings = testutils::user_settings();
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+
+    let mut tx = repo.start_transaction("test");
+    let commit1 = testutils::create_random_commit(&settings, repo).write_to_repo(tx.mut_repo());
+    tx.mut_repo()
+        .set_local_branch("main".to_string(), RefTarget::Normal(commit1.id().clone()));
+    tx.mut_repo().set_git_ref(
+        "refs/heads/main".to_string(),
+        RefTarget::Normal(commit1.id().clone()),
+    );
+    let repo = tx.commit();
+    let snapshot_bytes = view_snapshot::export_view(repo.view().store_view());
+
+    let mut tx = repo.start_transaction("test");
+    let commit2 = testutils::create_random_commit(&settings, &repo).write_to_repo(tx.mut_repo());
+    tx.mut_repo().remove_head(commit1.id());
+    tx.mut_repo().remove_local_branch("main");
+    tx.mut_repo().set_git_ref(
+        "refs/heads/main".to_string(),
+        RefTarget::Normal(commit2.id().clone()),
+    );
+    let repo = tx.commit();
+
+    let snapshot = view_snapshot::parse_view_snapshot(&snapshot_bytes).unwrap();
+    assert!(snapshot.git_refs.is_empty());
+    let mut tx = repo.start_transaction("test");
+    view_snapshot::import_view(tx.mut_repo(), &snapshot).unwrap();
+    let repo = tx.commit();
+    assert_eq!(*repo.view().heads(), hashset! {commit1.id().clone()});
+    assert_eq!(
+        repo.view().get_local_branch("main"),
+        Some(RefTarget::Normal(commit1.id().clone()))
+    );
+    assert_eq!(
+        *repo.view().git_refs(),
+        btreemap! {"refs/heads/main".to_string() => RefTarget::Normal(commit2.id().clone())}
+    );
+
+    assert!(matches!(
+        view_snapshot::parse_view_snapshot(b"foo"),
+        Err(ViewSnapshotError::InvalidHeader)
+    ));
+}
@@ -18,11 +18,14 @@ use std::io::Write;
 use std::os::unix::fs::PermissionsExt;
 #[cfg(unix)]
 use std::os::unix::net::UnixListener;
-use std::sync::Arc;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
 
 use itertools::Itertools;
-use jujutsu_lib::backend::{Conflict, ConflictPart, TreeValue};
+use jujutsu_lib::backend::{CommitId, Conflict, ConflictPart, TreeValue};
+use jujutsu_lib::fsmonitor::{FsMonitor, FsMonitorChanges, FsMonitorError};
 use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::matchers::{EverythingMatcher, PrefixMatcher};
 #[cfg(unix)]
 use jujutsu_lib::op_store::OperationId;
 use jujutsu_lib::op_store::WorkspaceId;
@@ -30,9 +33,12 @@ use jujutsu_lib::repo::ReadonlyRepo;
 use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::testutils;
-use jujutsu_lib::testutils::TestWorkspace;
+use jujutsu_lib::testutils::{TestRepo, TestWorkspace};
 use jujutsu_lib::tree_builder::TreeBuilder;
-use jujutsu_lib::working_copy::WorkingCopy;
+use jujutsu_lib::working_copy::{
+    CheckoutError, NestedRepos, Progress, ProgressStage, TreeState, WorkingCopy,
+    WorkingCopySettings,
+};
 use test_case::test_case;
 
 #[test_case(false ; "local backend")]
@@ -127,6 +133,7 @@ fn test_checkout_file_transitions(use_git: bool) {
                             id: base_file_id,
                             executable: false,
                         },
+                        label: None,
                     }],
                     adds: vec![
                         ConflictPart {
@@ -134,12 +141,14 @@ fn test_checkout_file_transitions(use_git: bool) {
                                 id: left_file_id,
                                 executable: false,
                             },
+                            label: None,
                         },
                         ConflictPart {
                             value: TreeValue::Normal {
                                 id: right_file_id,
                                 executable: false,
                             },
+                            label: None,
                         },
                     ],
                 };
@@ -391,6 +400,607 @@ fn test_checkout_discard() {
     assert!(!reloaded_wc.file_states().contains_key(&file2_path));
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_checkout_symlink_without_symlink_support(use_git: bool) {
+    // Tests that symlinks are written as regular files containing the target
+    // when the file system doesn't support symlinks, and that they remain
+    // symlinks when snapshotted.
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store().clone();
+
+    let temp_dir = testutils::new_temp_dir();
+    let wc_dir = temp_dir.path().join("wc");
+    let state_dir = temp_dir.path().join("state");
+    std::fs::create_dir(&wc_dir).unwrap();
+    std::fs::create_dir(&state_dir).unwrap();
+    let mut tree_state = TreeState::init(store.clone(), wc_dir.clone(), state_dir);
+    tree_state.set_settings(WorkingCopySettings {
+        symlink_override: Some(false),
+        ..Default::default()
+    });
+
+    let link_path = RepoPath::from_internal_string("link");
+    let file_path = RepoPath::from_internal_string("file");
+    let mut tree_builder = store.tree_builder(store.empty_tree_id().clone());
+    testutils::write_symlink(&mut tree_builder, &link_path, "target");
+    testutils::write_normal_file(&mut tree_builder, &file_path, "contents");
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    tree_state.check_out(&tree).unwrap();
+
+    let link_disk_path = link_path.to_fs_path(&wc_dir);
+    assert!(link_disk_path.symlink_metadata().unwrap().is_file());
+    assert_eq!(std::fs::read_to_string(&link_disk_path).unwrap(), "target");
+
+    // An unchanged working copy snapshots to the same tree
+    assert_eq!(
+        tree_state.snapshot(GitIgnoreFile::empty()).unwrap(),
+        tree_id
+    );
+
+    // Editing the file changes the symlink's target
+    std::fs::write(&link_disk_path, "new-target").unwrap();
+    let new_tree_id = tree_state.snapshot(GitIgnoreFile::empty()).unwrap();
+    let new_tree = store.get_tree(&RepoPath::root(), &new_tree_id).unwrap();
+    let mut tree_builder = store.tree_builder(store.empty_tree_id().clone());
+    testutils::write_symlink(&mut tree_builder, &link_path, "new-target");
+    testutils::write_normal_file(&mut tree_builder, &file_path, "contents");
+    let expected_tree_id = tree_builder.write_tree();
+    assert_eq!(new_tree.id(), &expected_tree_id);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_exec_bit_without_exec_bit_support() {
+    // Tests that the executable bits recorded in the tree are preserved when the
+    // file system's executable bits are not respected.
+    let test_repo = TestRepo::init(false);
+    let repo = &test_repo.repo;
+    let store = repo.store().clone();
+
+    let temp_dir = testutils::new_temp_dir();
+    let wc_dir = temp_dir.path().join("wc");
+    let state_dir = temp_dir.path().join("state");
+    std::fs::create_dir(&wc_dir).unwrap();
+    std::fs::create_dir(&state_dir).unwrap();
+    let mut tree_state = TreeState::init(store.clone(), wc_dir.clone(), state_dir);
+    tree_state.set_settings(WorkingCopySettings {
+        exec_bit_override: Some(false),
+        ..Default::default()
+    });
+
+    let executable_path = RepoPath::from_internal_string("executable");
+    let normal_path = RepoPath::from_internal_string("normal");
+    let mut tree_builder = store.tree_builder(store.empty_tree_id().clone());
+    let id = testutils::write_file(&store, &executable_path, "contents");
+    tree_builder.set(
+        executable_path.clone(),
+        TreeValue::Normal {
+            id,
+            executable: true,
+        },
+    );
+    testutils::write_normal_file(&mut tree_builder, &normal_path, "contents");
+    let tree_id = tree_builder.write_tree();
+    let tree = store.get_tree(&RepoPath::root(), &tree_id).unwrap();
+    tree_state.check_out(&tree).unwrap();
+
+    // The file system's executable bits are not set...
+    let executable_disk_path = executable_path.to_fs_path(&wc_dir);
+    let normal_disk_path = normal_path.to_fs_path(&wc_dir);
+    let mode = |path: &PathBuf| path.metadata().unwrap().permissions().mode() & 0o111;
+    assert_eq!(mode(&executable_disk_path), 0);
+
+    // ...and changing them doesn't change the tree
+    std::fs::set_permissions(&normal_disk_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    let new_file_path = RepoPath::from_internal_string("new");
+    testutils::write_working_copy_file(&wc_dir, &new_file_path, "contents");
+    std::fs::set_permissions(
+        new_file_path.to_fs_path(&wc_dir),
+        std::fs::Permissions::from_mode(0o755),
+    )
+    .unwrap();
+    let new_tree_id = tree_state.snapshot(GitIgnoreFile::empty()).unwrap();
+    let new_tree = store.get_tree(&RepoPath::root(), &new_tree_id).unwrap();
+    let is_executable = |path: &RepoPath| match new_tree.path_value(path) {
+        Some(TreeValue::Normal { executable, .. }) => executable,
+        value => panic!("unexpected value at {:?}: {:?}", path, value),
+    };
+    assert!(is_executable(&executable_path));
+    assert!(!is_executable(&normal_path));
+    assert!(!is_executable(&new_file_path));
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_checkout_case_collision(use_git: bool) {
+    // Tests that paths that differ only in case are refused on a
+    // case-insensitive file system, without writing any files.
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store().clone();
+
+    let temp_dir = testutils::new_temp_dir();
+    let wc_dir = temp_dir.path().join("wc");
+    let state_dir = temp_dir.path().join("state");
+    std::fs::create_dir(&wc_dir).unwrap();
+    std::fs::create_dir(&state_dir).unwrap();
+    let mut tree_state = TreeState::init(store.clone(), wc_dir.clone(), state_dir);
+    tree_state.set_case_sensitive(false);
+
+    let file_tree = testutils::create_tree(
+        repo,
+        &[
+            (&RepoPath::from_internal_string("a"), "a"),
+            (&RepoPath::from_internal_string("file"), "lower"),
+            (&RepoPath::from_internal_string("FILE"), "upper"),
+        ],
+    );
+    match tree_state.check_out(&file_tree) {
+        Err(CheckoutError::PathCaseCollision { path1, path2 }) => {
+            assert_eq!(
+                [
+                    path1.to_internal_file_string(),
+                    path2.to_internal_file_string()
+                ],
+                ["FILE".to_string(), "file".to_string()]
+            );
+        }
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+    assert!(!wc_dir.join("a").exists());
+
+    // Directories are checked too
+    let dir_tree = testutils::create_tree(
+        repo,
+        &[
+            (&RepoPath::from_internal_string("dir/a"), "a"),
+            (&RepoPath::from_internal_string("DIR/b"), "b"),
+        ],
+    );
+    assert!(matches!(
+        tree_state.check_out(&dir_tree),
+        Err(CheckoutError::PathCaseCollision { .. })
+    ));
+
+    // Paths that only share a prefix are fine
+    let ok_tree = testutils::create_tree(
+        repo,
+        &[
+            (&RepoPath::from_internal_string("dir/a"), "a"),
+            (&RepoPath::from_internal_string("dir/b"), "b"),
+        ],
+    );
+    tree_state.check_out(&ok_tree).unwrap();
+    assert!(wc_dir.join("dir").join("b").exists());
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_checkout_case_collision_only_added_paths(use_git: bool) {
+    // Tests that only the paths a checkout adds are checked for case
+    // collisions, so paths that are already checked out aren't checked again.
+    let test_repo = TestRepo::init(use_git);
+    let repo = &test_repo.repo;
+    let store = repo.store().clone();
+
+    let temp_dir = testutils::new_temp_dir();
+    let wc_dir = temp_dir.path().join("wc");
+    let state_dir = temp_dir.path().join("state");
+    std::fs::create_dir(&wc_dir).unwrap();
+    std::fs::create_dir(&state_dir).unwrap();
+    let mut tree_state = TreeState::init(store.clone(), wc_dir.clone(), state_dir);
+    let dir_a_path = RepoPath::from_internal_string("dir/a");
+    let upper_dir_b_path = RepoPath::from_internal_string("DIR/b");
+    let tree1 = testutils::create_tree(repo, &[(&dir_a_path, "a"), (&upper_dir_b_path, "b")]);
+    tree_state.set_case_sensitive(true);
+    tree_state.check_out(&tree1).unwrap();
+
+    tree_state.set_case_sensitive(false);
+    let tree2 = testutils::create_tree(
+        repo,
+        &[
+            (&dir_a_path, "a"),
+            (&upper_dir_b_path, "b"),
+            (&RepoPath::from_internal_string("file"), "file"),
+        ],
+    );
+    tree_state.check_out(&tree2).unwrap();
+    assert!(wc_dir.join("file").exists());
+
+    let tree3 = testutils::create_tree(
+        repo,
+        &[
+            (&dir_a_path, "a"),
+            (&upper_dir_b_path, "b"),
+            (&RepoPath::from_internal_string("file"), "file"),
+            (&RepoPath::from_internal_string("File"), "File"),
+        ],
+    );
+    match tree_state.check_out(&tree3) {
+        Err(CheckoutError::PathCaseCollision { path1, path2 }) => {
+            assert_eq!(
+                [
+                    path1.to_internal_file_string(),
+                    path2.to_internal_file_string()
+                ],
+                ["File".to_string(), "file".to_string()]
+            );
+        }
+        result => panic!("unexpected result: {:?}", result.map(|_| ())),
+    }
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_checkout_many_files(use_git: bool) {
+    // Tests that checking out and snapshotting many files, which happens in
+    // parallel, records the state of all of them.
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let paths = (0..100)
+        .map(|i| RepoPath::from_internal_string(&format!("dir{}/file{}", i % 7, i)))
+        .collect_vec();
+    let path_contents = paths
+        .iter()
+        .map(|path| (path, path.to_internal_file_string()))
+        .collect_vec();
+    let tree = testutils::create_tree(
+        repo,
+        &path_contents
+            .iter()
+            .map(|(path, contents)| (*path, contents.as_str()))
+            .collect_vec(),
+    );
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    assert_eq!(stats.added_files, 100);
+    for path in &paths {
+        assert_eq!(
+            std::fs::read_to_string(path.to_fs_path(&workspace_root)).unwrap(),
+            path.to_internal_file_string()
+        );
+    }
+    assert_eq!(wc.file_states().len(), 100);
+
+    // Modify some of the files and check that the snapshot picks up exactly those
+    for path in paths.iter().step_by(3) {
+        std::fs::write(path.to_fs_path(&workspace_root), "modified").unwrap();
+    }
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let modified_paths = tree
+        .diff(&new_tree, &EverythingMatcher)
+        .map(|(path, _diff)| path)
+        .collect_vec();
+    assert_eq!(
+        modified_paths,
+        paths.iter().step_by(3).cloned().sorted().collect_vec()
+    );
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_checkout_reflink(use_git: bool) {
+    // Tests that checking out with reflinks enabled writes the right contents,
+    // whether or not the file system supports cloning
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let path1 = RepoPath::from_internal_string("file1");
+    let path2 = RepoPath::from_internal_string("dir/file2");
+    let tree = testutils::create_tree(repo, &[(&path1, "same"), (&path2, "same")]);
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_settings(WorkingCopySettings {
+        reflink: true,
+        ..Default::default()
+    });
+    let stats = wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    assert_eq!(stats.added_files, 2);
+    assert_eq!(
+        std::fs::read_to_string(path1.to_fs_path(&workspace_root)).unwrap(),
+        "same"
+    );
+    assert_eq!(
+        std::fs::read_to_string(path2.to_fs_path(&workspace_root)).unwrap(),
+        "same"
+    );
+
+    // Modifying one of the files doesn't affect the other one
+    std::fs::write(path1.to_fs_path(&workspace_root), "modified").unwrap();
+    assert_eq!(
+        std::fs::read_to_string(path2.to_fs_path(&workspace_root)).unwrap(),
+        "same"
+    );
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let modified_paths = tree
+        .diff(&new_tree, &EverythingMatcher)
+        .map(|(path, _diff)| path)
+        .collect_vec();
+    assert_eq!(modified_paths, vec![path1]);
+}
+
+#[test]
+fn test_resume_interrupted_checkout() {
+    // Tests that a checkout that failed after writing some of the files can be
+    // resumed
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let added_path = RepoPath::from_internal_string("added");
+    let blocked_path = RepoPath::from_internal_string("blocked");
+    let tree = testutils::create_tree(repo, &[(&added_path, "added"), (&blocked_path, "new")]);
+
+    // An untracked file is in the way of one of the files
+    std::fs::write(blocked_path.to_fs_path(&workspace_root), "untracked").unwrap();
+    let wc = test_workspace.workspace.working_copy_mut();
+    assert!(!wc.has_pending_checkout());
+    assert!(wc.check_out(repo.op_id().clone(), None, &tree).is_err());
+    assert!(wc.has_pending_checkout());
+    assert_eq!(wc.current_tree_id(), *repo.store().empty_tree_id());
+
+    let mut locked_wc = wc.start_mutation();
+    let stats = locked_wc.resume_checkout().unwrap();
+    assert!(stats.is_some());
+    locked_wc.finish(repo.op_id().clone());
+    assert!(!wc.has_pending_checkout());
+    assert_eq!(wc.current_tree_id(), *tree.id());
+    assert_eq!(
+        std::fs::read_to_string(added_path.to_fs_path(&workspace_root)).unwrap(),
+        "added"
+    );
+
+    // The file that was in the way is kept and considered modified
+    assert_eq!(
+        std::fs::read_to_string(blocked_path.to_fs_path(&workspace_root)).unwrap(),
+        "untracked"
+    );
+    let mut locked_wc = wc.start_mutation();
+    assert_eq!(locked_wc.resume_checkout().unwrap(), None);
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let modified_paths = tree
+        .diff(&new_tree, &EverythingMatcher)
+        .map(|(path, _diff)| path)
+        .collect_vec();
+    assert_eq!(modified_paths, vec![blocked_path]);
+}
+
+#[test]
+fn test_snapshot_matching() {
+    // Tests that a partial snapshot only records changes to the matching paths
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let inside_modified_path = RepoPath::from_internal_string("dir/modified");
+    let inside_deleted_path = RepoPath::from_internal_string("dir/deleted");
+    let inside_added_path = RepoPath::from_internal_string("dir/added");
+    let outside_modified_path = RepoPath::from_internal_string("other/modified");
+    let outside_deleted_path = RepoPath::from_internal_string("other/deleted");
+    let outside_added_path = RepoPath::from_internal_string("other/added");
+    let tree = testutils::create_tree(
+        repo,
+        &[
+            (&inside_modified_path, "contents"),
+            (&inside_deleted_path, "contents"),
+            (&outside_modified_path, "contents"),
+            (&outside_deleted_path, "contents"),
+        ],
+    );
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+
+    for path in [&inside_modified_path, &outside_modified_path] {
+        std::fs::write(path.to_fs_path(&workspace_root), "modified").unwrap();
+    }
+    for path in [&inside_deleted_path, &outside_deleted_path] {
+        std::fs::remove_file(path.to_fs_path(&workspace_root)).unwrap();
+    }
+    for path in [&inside_added_path, &outside_added_path] {
+        std::fs::write(path.to_fs_path(&workspace_root), "added").unwrap();
+    }
+
+    let mut locked_wc = wc.start_mutation();
+    let matcher = PrefixMatcher::new(&[RepoPath::from_internal_string("dir")]);
+    let new_tree_id = locked_wc
+        .snapshot_matching(GitIgnoreFile::empty(), &matcher)
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let changed_paths = tree
+        .diff(&new_tree, &EverythingMatcher)
+        .map(|(path, _diff)| path)
+        .collect_vec();
+    assert_eq!(
+        changed_paths,
+        vec![
+            inside_added_path.clone(),
+            inside_deleted_path.clone(),
+            inside_modified_path.clone()
+        ]
+    );
+
+    // A full snapshot picks up the other changes
+    let mut locked_wc = wc.start_mutation();
+    let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+    let new_tree = repo
+        .store()
+        .get_tree(&RepoPath::root(), &new_tree_id)
+        .unwrap();
+    let changed_paths = tree
+        .diff(&new_tree, &EverythingMatcher)
+        .map(|(path, _diff)| path)
+        .collect_vec();
+    assert_eq!(
+        changed_paths,
+        vec![
+            inside_added_path,
+            inside_deleted_path,
+            inside_modified_path,
+            outside_added_path,
+            outside_deleted_path,
+            outside_modified_path
+        ]
+    );
+}
+
+#[test_case(NestedRepos::Ignore ; "ignore")]
+#[test_case(NestedRepos::Include ; "include")]
+#[test_case(NestedRepos::Placeholder ; "placeholder")]
+fn test_snapshot_nested_repos(nested_repos: NestedRepos) {
+    // Tests that directories containing other repos are skipped or included
+    // depending on the setting
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, true);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = RepoPath::from_internal_string("file");
+    let git_file_path = RepoPath::from_internal_string("git/file");
+    let jj_file_path = RepoPath::from_internal_string("jj/file");
+    std::fs::write(file_path.to_fs_path(&workspace_root), "contents").unwrap();
+    // A nested Git repo with a commit checked out
+    let git_repo = git2::Repository::init(workspace_root.join("git")).unwrap();
+    std::fs::write(git_file_path.to_fs_path(&workspace_root), "contents").unwrap();
+    let signature = git2::Signature::now("Someone", "someone@example.com").unwrap();
+    let mut index = git_repo.index().unwrap();
+    index.add_path(Path::new("file")).unwrap();
+    let git_tree = git_repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let git_commit_id = git_repo
+        .commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "message",
+            &git_tree,
+            &[],
+        )
+        .unwrap();
+    // A nested jj repo
+    std::fs::create_dir_all(workspace_root.join("jj").join(".jj")).unwrap();
+    std::fs::write(jj_file_path.to_fs_path(&workspace_root), "contents").unwrap();
+
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_settings(WorkingCopySettings {
+        nested_repos,
+        ..Default::default()
+    });
+    let mut locked_wc = wc.start_mutation();
+    let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+    let entries = tree.entries().collect_vec();
+    let expected_paths = match nested_repos {
+        NestedRepos::Ignore => vec![file_path.clone()],
+        NestedRepos::Include => vec![file_path.clone(), git_file_path, jj_file_path],
+        NestedRepos::Placeholder => {
+            let git_path = RepoPath::from_internal_string("git");
+            assert_eq!(
+                tree.path_value(&git_path),
+                Some(TreeValue::GitSubmodule(CommitId::from_bytes(
+                    git_commit_id.as_bytes()
+                )))
+            );
+            vec![file_path.clone(), git_path]
+        }
+    };
+    assert_eq!(
+        entries
+            .iter()
+            .map(|(path, _value)| path.clone())
+            .collect_vec(),
+        expected_paths
+    );
+
+    // Placeholders are removed when the directory no longer contains a repo
+    if nested_repos == NestedRepos::Placeholder {
+        std::fs::remove_dir_all(workspace_root.join("git").join(".git")).unwrap();
+        let mut locked_wc = wc.start_mutation();
+        let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+        locked_wc.discard();
+        let tree = repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap();
+        assert_eq!(
+            tree.entries().map(|(path, _value)| path).collect_vec(),
+            vec![file_path, RepoPath::from_internal_string("git/file")]
+        );
+    }
+}
+
+#[test]
+fn test_progress_callback() {
+    // Tests that checkouts and snapshots report their progress in order
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, false);
+    let repo = &test_workspace.repo;
+
+    let paths = (0..10)
+        .map(|i| RepoPath::from_internal_string(&format!("file{}", i)))
+        .collect_vec();
+    let tree = testutils::create_tree(
+        repo,
+        &paths.iter().map(|path| (path, "contents")).collect_vec(),
+    );
+
+    let reports = Arc::new(Mutex::new(vec![]));
+    let reports_clone = reports.clone();
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_settings(WorkingCopySettings {
+        progress_callback: Some(Arc::new(move |progress: &Progress| {
+            reports_clone.lock().unwrap().push(*progress);
+        })),
+        ..Default::default()
+    });
+    wc.check_out(repo.op_id().clone(), None, &tree).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+    locked_wc.discard();
+
+    let reports = reports.lock().unwrap();
+    let expected = [ProgressStage::CheckingOut, ProgressStage::Snapshotting]
+        .into_iter()
+        .flat_map(|stage| {
+            (0..=10).map(move |processed| Progress {
+                stage,
+                processed,
+                total: 10,
+            })
+        })
+        .collect_vec();
+    assert_eq!(*reports, expected);
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_snapshot_racy_timestamps(use_git: bool) {
@@ -423,6 +1033,115 @@ fn test_snapshot_racy_timestamps(use_git: bool) {
     }
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_snapshot_racy_timestamps_after_save(use_git: bool) {
+    // Tests that file modifications that don't change the size are detected even
+    // if they happen the same millisecond as the previous snapshot was saved.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file_path = workspace_root.join("file");
+    let mut previous_tree_id = repo.store().empty_tree_id().clone();
+    let wc = test_workspace.workspace.working_copy_mut();
+    for i in 0..100 {
+        std::fs::write(&file_path, format!("contents {:03}", i)).unwrap();
+        let mut locked_wc = wc.start_mutation();
+        let new_tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+        locked_wc.finish(repo.op_id().clone());
+        assert_ne!(new_tree_id, previous_tree_id);
+        previous_tree_id = new_tree_id;
+    }
+}
+
+#[cfg(unix)]
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_snapshot_dir_mtime_cache(use_git: bool) {
+    // Tests that changes are found in directories that aren't listed again because
+    // their mtime didn't change.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = &test_workspace.repo;
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let gitignore_path = RepoPath::from_internal_string(".gitignore");
+    let file_path = RepoPath::from_internal_string("dir/file");
+    let ignored_path = RepoPath::from_internal_string("dir/ignored");
+    let subdir_file_path = RepoPath::from_internal_string("dir/subdir/file");
+    let subdir_added_path = RepoPath::from_internal_string("dir/subdir/added");
+    std::fs::create_dir_all(workspace_root.join("dir").join("subdir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &gitignore_path, "/dir/ignored\n");
+    testutils::write_working_copy_file(&workspace_root, &file_path, "1");
+    testutils::write_working_copy_file(&workspace_root, &ignored_path, "1");
+    testutils::write_working_copy_file(&workspace_root, &subdir_file_path, "1");
+
+    let snapshot = |wc: &mut WorkingCopy| {
+        // Directories modified within the mtime granularity of saving the state
+        // are always listed again
+        std::thread::sleep(std::time::Duration::from_millis(10));
+        let mut locked_wc = wc.start_mutation();
+        let tree_id = locked_wc.snapshot(GitIgnoreFile::empty()).unwrap();
+        locked_wc.finish(repo.op_id().clone());
+        repo.store().get_tree(&RepoPath::root(), &tree_id).unwrap()
+    };
+    let wc = test_workspace.workspace.working_copy_mut();
+    wc.set_settings(WorkingCopySettings {
+        dir_mtime_cache: true,
+        ..Default::default()
+    });
+    let tree1 = snapshot(wc);
+    assert_eq!(
+        tree1.entries().map(|(path, _value)| path).collect_vec(),
+        vec![
+            gitignore_path.clone(),
+            file_path.clone(),
+            subdir_file_path.clone()
+        ]
+    );
+    // Records the state of the directories
+    assert_eq!(snapshot(wc).id(), tree1.id());
+
+    // Files that are no longer ignored are found even though no directory changed
+    testutils::write_working_copy_file(&workspace_root, &gitignore_path, "");
+    testutils::write_working_copy_file(&workspace_root, &subdir_file_path, "modified");
+    let tree2 = snapshot(wc);
+    assert_eq!(
+        tree2.entries().map(|(path, _value)| path).collect_vec(),
+        vec![
+            gitignore_path.clone(),
+            file_path.clone(),
+            ignored_path.clone(),
+            subdir_file_path.clone()
+        ]
+    );
+    assert_ne!(
+        tree2.path_value(&subdir_file_path),
+        tree1.path_value(&subdir_file_path)
+    );
+
+    // Added files are found, and so are modified files in directories that aren't
+    // listed again
+    testutils::write_working_copy_file(&workspace_root, &subdir_added_path, "1");
+    testutils::write_working_copy_file(&workspace_root, &file_path, "modified");
+    let tree3 = snapshot(wc);
+    assert_eq!(
+        tree3.entries().map(|(path, _value)| path).collect_vec(),
+        vec![
+            gitignore_path,
+            file_path.clone(),
+            ignored_path,
+            subdir_added_path,
+            subdir_file_path
+        ]
+    );
+    assert_ne!(tree3.path_value(&file_path), tree2.path_value(&file_path));
+}
+
 #[cfg(unix)]
 #[test]
 fn test_snapshot_special_file() {
@@ -707,3 +1426,88 @@ fn test_existing_directory_symlink(use_git: bool) {
     // Therefore, "../escaped" shouldn't be created.
     assert!(!workspace_root.parent().unwrap().join("escaped").exists());
 }
+
+/// Reports the given changes, recording the clocks it was queried with.
+struct FakeFsMonitor {
+    responses: Vec<FsMonitorChanges>,
+    queried_clocks: Vec<Option<String>>,
+}
+
+impl FsMonitor for FakeFsMonitor {
+    fn query_changed_files(
+        &mut self,
+        since: Option<&str>,
+    ) -> Result<FsMonitorChanges, FsMonitorError> {
+        self.queried_clocks.push(since.map(ToOwned::to_owned));
+        Ok(self.responses.remove(0))
+    }
+}
+
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_snapshot_with_fsmonitor(use_git: bool) {
+    // Tests that only the files reported by the filesystem monitor are
+    // snapshotted once it has a clock.
+    let _home_dir = testutils::new_user_home();
+    let settings = testutils::user_settings();
+    let mut test_workspace = TestWorkspace::init(&settings, use_git);
+    let repo = test_workspace.repo.clone();
+    let workspace_root = test_workspace.workspace.workspace_root().clone();
+
+    let file1_path = RepoPath::from_internal_string("file1");
+    let file2_path = RepoPath::from_internal_string("dir/file2");
+    let file3_path = RepoPath::from_internal_string("dir/file3");
+    std::fs::create_dir(workspace_root.join("dir")).unwrap();
+    testutils::write_working_copy_file(&workspace_root, &file1_path, "1");
+    testutils::write_working_copy_file(&workspace_root, &file2_path, "2");
+    testutils::write_working_copy_file(&workspace_root, &file3_path, "3");
+
+    let mut fsmonitor = FakeFsMonitor {
+        responses: vec![
+            FsMonitorChanges {
+                clock: "c:1".to_string(),
+                changed_files: None,
+            },
+            FsMonitorChanges {
+                clock: "c:2".to_string(),
+                changed_files: Some(vec![
+                    PathBuf::from("dir").join("file2"),
+                    PathBuf::from("dir").join("file3"),
+                    PathBuf::from(".jj").join("working_copy"),
+                ]),
+            },
+        ],
+        queried_clocks: vec![],
+    };
+    let wc = test_workspace.workspace.working_copy_mut();
+    // Without a clock, all files are snapshotted
+    let mut locked_wc = wc.start_mutation();
+    let tree_id1 = locked_wc
+        .snapshot_with_fsmonitor(GitIgnoreFile::empty(), &mut fsmonitor)
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let tree1 = repo.store().get_tree(&RepoPath::root(), &tree_id1).unwrap();
+    assert!(tree1.path_value(&file1_path).is_some());
+    assert!(tree1.path_value(&file2_path).is_some());
+    assert!(tree1.path_value(&file3_path).is_some());
+
+    // Changes to files that weren't reported are not noticed
+    testutils::write_working_copy_file(&workspace_root, &file1_path, "1 changed");
+    testutils::write_working_copy_file(&workspace_root, &file2_path, "2 changed");
+    std::fs::remove_file(file3_path.to_fs_path(&workspace_root)).unwrap();
+    let mut locked_wc = wc.start_mutation();
+    let tree_id2 = locked_wc
+        .snapshot_with_fsmonitor(GitIgnoreFile::empty(), &mut fsmonitor)
+        .unwrap();
+    locked_wc.finish(repo.op_id().clone());
+    let tree2 = repo.store().get_tree(&RepoPath::root(), &tree_id2).unwrap();
+    assert_eq!(tree2.path_value(&file1_path), tree1.path_value(&file1_path));
+    assert_ne!(tree2.path_value(&file2_path), tree1.path_value(&file2_path));
+    assert_eq!(tree2.path_value(&file3_path), None);
+
+    // The clock was saved with the working copy state
+    assert_eq!(
+        fsmonitor.queried_clocks,
+        vec![None, Some("c:1".to_string())]
+    );
+}
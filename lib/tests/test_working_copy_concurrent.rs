@@ -14,9 +14,11 @@
 
 use std::cmp::max;
 use std::thread;
+use std::time::Duration;
 
 use assert_matches::assert_matches;
 use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::lock::FileLockError;
 use jujutsu_lib::repo::BackendFactories;
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::testutils;
@@ -77,6 +79,42 @@ fn test_concurrent_checkout(use_git: bool) {
     assert_eq!(workspace3.working_copy().current_tree_id(), tree_id2);
 }
 
+#[test]
+fn test_concurrent_lock() {
+    // Test that taking the working copy's lock fails instead of waiting when
+    // another process holds it and the lock timeout is zero.
+    let settings = testutils::user_settings();
+    let mut test_workspace1 = TestWorkspace::init(&settings, false);
+    let repo1 = test_workspace1.repo.clone();
+    let workspace1_root = test_workspace1.workspace.workspace_root().clone();
+    let tree = repo1
+        .store()
+        .get_tree(&RepoPath::root(), &testutils::create_random_tree(&repo1))
+        .unwrap();
+
+    let locked_wc1 = test_workspace1
+        .workspace
+        .working_copy_mut()
+        .start_mutation();
+
+    let mut workspace2 =
+        Workspace::load(&settings, &workspace1_root, &BackendFactories::default()).unwrap();
+    let wc2 = workspace2.working_copy_mut();
+    wc2.set_lock_timeout(Some(Duration::ZERO));
+    assert!(matches!(
+        wc2.try_start_mutation(),
+        Err(FileLockError::Timeout { .. })
+    ));
+    assert_matches!(
+        wc2.check_out(repo1.op_id().clone(), None, &tree),
+        Err(CheckoutError::Locked(FileLockError::Timeout { .. }))
+    );
+
+    // The lock can be taken once it's released
+    locked_wc1.discard();
+    wc2.try_start_mutation().unwrap().discard();
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_checkout_parallel(use_git: bool) {
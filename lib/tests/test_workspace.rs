@@ -12,8 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use jujutsu_lib::git_backend::GitBackend;
+use jujutsu_lib::local_backend::LocalBackend;
 use jujutsu_lib::op_store::WorkspaceId;
-use jujutsu_lib::repo::BackendFactories;
+use jujutsu_lib::repo::{BackendFactories, StoreLoadError};
 use jujutsu_lib::testutils;
 use jujutsu_lib::testutils::TestWorkspace;
 use jujutsu_lib::workspace::{Workspace, WorkspaceLoadError};
@@ -32,6 +34,34 @@ fn test_load_bad_path() {
     );
 }
 
+#[test]
+fn test_load_unsupported_backend() {
+    let settings = testutils::user_settings();
+    let test_workspace = TestWorkspace::init(&settings, false);
+    let workspace_root = test_workspace.workspace.workspace_root();
+    let backend_path = test_workspace
+        .repo
+        .repo_path()
+        .join("store")
+        .join("backend");
+    std::fs::write(&backend_path, "custom").unwrap();
+    let result = Workspace::load(&settings, workspace_root, &BackendFactories::default());
+    assert_eq!(
+        result.err(),
+        Some(WorkspaceLoadError::StoreLoadError(
+            StoreLoadError::UnsupportedType("custom".to_string())
+        ))
+    );
+
+    // The backend can be loaded once a factory for it has been registered
+    let mut backend_factories = BackendFactories::default();
+    backend_factories.add_backend(
+        "custom",
+        Box::new(|store_path| Box::new(LocalBackend::load(store_path))),
+    );
+    assert!(Workspace::load(&settings, workspace_root, &backend_factories).is_ok());
+}
+
 #[test_case(false ; "local backend")]
 #[test_case(true ; "git backend")]
 fn test_load_from_subdir(use_git: bool) {
@@ -48,6 +78,38 @@ fn test_load_from_subdir(use_git: bool) {
     assert_eq!(same_workspace.workspace_root(), workspace.workspace_root());
 }
 
+#[test_case(false ; "local backend")]
+#[test_case(true ; "git backend")]
+fn test_init_bare(use_git: bool) {
+    let settings = testutils::user_settings();
+    let temp_dir = testutils::new_temp_dir();
+    let workspace_root = temp_dir.path().join("repo");
+    std::fs::create_dir(&workspace_root).unwrap();
+    let (workspace, repo) = Workspace::init_bare(&settings, &workspace_root, |store_path| {
+        if use_git {
+            Box::new(GitBackend::init_internal(store_path))
+        } else {
+            Box::new(LocalBackend::init(store_path))
+        }
+    })
+    .unwrap();
+    assert!(workspace.is_bare());
+    assert!(repo.view().wc_commit_ids().is_empty());
+    assert!(!workspace_root.join(".jj").join("working_copy").exists());
+
+    // The workspace is still bare when loaded again
+    let workspace =
+        Workspace::load(&settings, &workspace_root, &BackendFactories::default()).unwrap();
+    assert!(workspace.is_bare());
+    assert_eq!(workspace.workspace_id(), WorkspaceId::default());
+    let repo = workspace
+        .repo_loader()
+        .load_at_head()
+        .resolve(&settings)
+        .unwrap();
+    assert!(repo.view().wc_commit_ids().is_empty());
+}
+
 #[test_case(false ; "local backend")]
 // #[test_case(true ; "git backend")]
 fn test_init_additional_workspace(use_git: bool) {
@@ -17,33 +17,47 @@ use std::env::ArgsOs;
 use std::fmt::Debug;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::time::Duration;
 
+use atty::Stream;
+use chrono::{DateTime, Local, NaiveDate, NaiveTime, TimeZone};
 use clap::{ArgMatches, FromArgMatches};
 use git2::{Oid, Repository};
 use itertools::Itertools;
-use jujutsu_lib::backend::{BackendError, CommitId, TreeId};
+use jujutsu_lib::backend::{BackendError, ChangeId, CommitId, MillisSinceEpoch, Timestamp, TreeId};
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
+use jujutsu_lib::fsmonitor::{FsMonitor, Watchman};
 use jujutsu_lib::git::{GitExportError, GitImportError};
+use jujutsu_lib::git_hooks::{GitHookError, GitHooks};
 use jujutsu_lib::gitignore::GitIgnoreFile;
+use jujutsu_lib::lock::FileLockError;
 use jujutsu_lib::matchers::{EverythingMatcher, Matcher, PrefixMatcher, Visit};
+use jujutsu_lib::op_abandon::{OpAbandonError, OpAbandonment};
 use jujutsu_lib::op_heads_store::{OpHeadResolutionError, OpHeads, OpHeadsStore};
+use jujutsu_lib::op_hooks::{OperationSummary, PostOperationHook};
 use jujutsu_lib::op_store::{OpStore, OpStoreError, OperationId, WorkspaceId};
 use jujutsu_lib::operation::Operation;
-use jujutsu_lib::repo::{BackendFactories, MutableRepo, ReadonlyRepo};
+use jujutsu_lib::repo::{
+    BackendFactories, MutableRepo, ReadonlyRepo, StoreLoadError, ViewMergeReport,
+};
 use jujutsu_lib::repo_path::RepoPath;
+use jujutsu_lib::resolutions::record_resolutions;
 use jujutsu_lib::revset::{RevsetError, RevsetParseError};
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::transaction::Transaction;
-use jujutsu_lib::tree::{Tree, TreeMergeError};
+use jujutsu_lib::tree::{merge_trees, Tree, TreeMergeError};
+use jujutsu_lib::view::{RefName, View};
 use jujutsu_lib::working_copy::{
-    CheckoutStats, LockedWorkingCopy, ResetError, SnapshotError, WorkingCopy,
+    CheckoutError, CheckoutStats, LockedWorkingCopy, ResetError, SnapshotError, WorkingCopy,
 };
 use jujutsu_lib::workspace::{Workspace, WorkspaceInitError, WorkspaceLoadError};
-use jujutsu_lib::{dag_walk, git, revset};
+use jujutsu_lib::{dag_walk, git, op_abandon, revset};
 
 use crate::config::read_config;
 use crate::diff_edit::DiffEditError;
+use crate::patch::PatchError;
+use crate::progress::terminal_progress_callback;
 use crate::ui;
 use crate::ui::{ColorChoice, FilePathParseError, Ui};
 
@@ -60,6 +74,17 @@ impl From<std::io::Error> for CommandError {
     fn from(err: std::io::Error) -> Self {
         if err.kind() == std::io::ErrorKind::BrokenPipe {
             CommandError::BrokenPipe
+        } else if err
+            .get_ref()
+            .map_or(false, |inner| inner.is::<BackendError>())
+        {
+            // Templates report errors reading the commits they format this way
+            let err = err
+                .into_inner()
+                .unwrap()
+                .downcast::<BackendError>()
+                .unwrap();
+            CommandError::from(*err)
         } else {
             // TODO: Record the error as a chained cause
             CommandError::InternalError(format!("I/O error: {err}"))
@@ -75,13 +100,21 @@ impl From<config::ConfigError> for CommandError {
 
 impl From<BackendError> for CommandError {
     fn from(err: BackendError) -> Self {
-        CommandError::UserError(format!("Unexpected error from store: {err}"))
+        match err {
+            BackendError::SigningFailed(_) => CommandError::UserError(err.to_string()),
+            err => CommandError::UserError(format!("Unexpected error from store: {err}")),
+        }
     }
 }
 
 impl From<WorkspaceInitError> for CommandError {
-    fn from(_: WorkspaceInitError) -> Self {
-        CommandError::UserError("The target repo already exists".to_string())
+    fn from(err: WorkspaceInitError) -> Self {
+        match err {
+            WorkspaceInitError::DestinationExists(_) => {
+                CommandError::UserError("The target repo already exists".to_string())
+            }
+            WorkspaceInitError::Backend(err) => err.into(),
+        }
     }
 }
 
@@ -97,7 +130,27 @@ impl From<OpHeadResolutionError> for CommandError {
 
 impl From<SnapshotError> for CommandError {
     fn from(err: SnapshotError) -> Self {
-        CommandError::InternalError(format!("Failed to snapshot the working copy: {err}"))
+        match err {
+            SnapshotError::NewFileTooLarge { .. } => CommandError::UserError(format!(
+                "Failed to snapshot the working copy: {err}\nIgnore the file, raise the limit, \
+                 or run the command with --allow-large-files to snapshot it anyway."
+            )),
+            _ => CommandError::InternalError(format!("Failed to snapshot the working copy: {err}")),
+        }
+    }
+}
+
+impl From<FileLockError> for CommandError {
+    fn from(err: FileLockError) -> Self {
+        match err {
+            FileLockError::Timeout { path } => CommandError::UserError(format!(
+                "Another jj command is using the working copy. Wait for it to finish, or run the \
+                 command with --wait.\nIf no other command is running, a command may have \
+                 crashed. Delete the lock file {} to recover.",
+                path.display()
+            )),
+            FileLockError::IoError { .. } => CommandError::InternalError(err.to_string()),
+        }
     }
 }
 
@@ -119,6 +172,12 @@ impl From<DiffEditError> for CommandError {
     }
 }
 
+impl From<PatchError> for CommandError {
+    fn from(err: PatchError) -> Self {
+        CommandError::UserError(err.to_string())
+    }
+}
+
 impl From<git2::Error> for CommandError {
     fn from(err: git2::Error) -> Self {
         CommandError::UserError(format!("Git operation failed: {err}"))
@@ -146,6 +205,23 @@ impl From<GitExportError> for CommandError {
     }
 }
 
+impl From<OpAbandonError> for CommandError {
+    fn from(err: OpAbandonError) -> Self {
+        match err {
+            OpAbandonError::OpStoreError(err) => {
+                CommandError::InternalError(format!("Failed to rewrite the operation log: {err}"))
+            }
+            err => CommandError::UserError(err.to_string()),
+        }
+    }
+}
+
+impl From<GitHookError> for CommandError {
+    fn from(err: GitHookError) -> Self {
+        CommandError::UserError(err.to_string())
+    }
+}
+
 impl From<RevsetParseError> for CommandError {
     fn from(err: RevsetParseError) -> Self {
         CommandError::UserError(format!("Failed to parse revset: {err}"))
@@ -206,21 +282,51 @@ impl<'help> CommandHelper<'help> {
     }
 
     pub fn workspace_helper(&self, ui: &mut Ui) -> Result<WorkspaceCommandHelper, CommandError> {
+        self.workspace_helper_internal(ui, true)
+    }
+
+    /// Like `workspace_helper()`, but doesn't snapshot the working copy, e.g.
+    /// because it may be stale.
+    pub fn workspace_helper_no_snapshot(
+        &self,
+        ui: &mut Ui,
+    ) -> Result<WorkspaceCommandHelper, CommandError> {
+        self.workspace_helper_internal(ui, false)
+    }
+
+    /// Like `workspace_helper()`, but only snapshots the working-copy files
+    /// matching `paths`, which is faster in large repos. For commands that only
+    /// look at those paths. Snapshots all files if `paths` is empty.
+    pub fn workspace_helper_for_paths(
+        &self,
+        ui: &mut Ui,
+        paths: &[String],
+    ) -> Result<WorkspaceCommandHelper, CommandError> {
+        let mut workspace_command = self.workspace_helper_internal(ui, false)?;
+        let matcher = if paths.is_empty() {
+            None
+        } else {
+            Some(matcher_from_values(
+                ui,
+                workspace_command.workspace_root(),
+                paths,
+            )?)
+        };
+        workspace_command.snapshot(ui, matcher.as_deref())?;
+        Ok(workspace_command)
+    }
+
+    fn workspace_helper_internal(
+        &self,
+        ui: &mut Ui,
+        snapshot: bool,
+    ) -> Result<WorkspaceCommandHelper, CommandError> {
         let wc_path_str = self.global_args.repository.as_deref().unwrap_or(".");
         let wc_path = ui.cwd().join(wc_path_str);
         let workspace = match Workspace::load(ui.settings(), &wc_path, &self.backend_factories) {
             Ok(workspace) => workspace,
             Err(WorkspaceLoadError::NoWorkspaceHere(wc_path)) => {
-                let mut message = format!("There is no jj repo in \"{}\"", wc_path_str);
-                let git_dir = wc_path.join(".git");
-                if git_dir.is_dir() {
-                    // TODO: Make this hint separate from the error, so the caller can format
-                    // it differently.
-                    message += "
-It looks like this is a git repo. You can create a jj repo backed by it by running this:
-jj init --git-repo=.";
-                }
-                return Err(CommandError::UserError(message));
+                return Err(no_workspace_error(wc_path_str, &wc_path));
             }
             Err(WorkspaceLoadError::RepoDoesNotExist(repo_dir)) => {
                 return Err(CommandError::UserError(format!(
@@ -228,6 +334,15 @@ jj init --git-repo=.";
                     repo_dir.to_str().unwrap()
                 )));
             }
+            Err(WorkspaceLoadError::StoreLoadError(StoreLoadError::UnsupportedType(
+                backend_type,
+            ))) => {
+                return Err(CommandError::UserError(format!(
+                    "This repo uses the '{}' commit backend, which isn't supported by this \
+                     version of jj",
+                    backend_type
+                )));
+            }
         };
         let repo_loader = workspace.repo_loader();
         let op_heads = resolve_op_for_load(
@@ -245,12 +360,22 @@ jj init --git-repo=.";
                     ui,
                     "Concurrent modification detected, resolving automatically.",
                 )?;
+                writeln!(ui, "Merging these operations:")?;
+                for op_head in &op_heads {
+                    writeln!(
+                        ui,
+                        "  {} {}",
+                        short_operation_hash(op_head.id()),
+                        op_head.store_operation().metadata.description
+                    )?;
+                }
                 let base_repo = repo_loader.load_at(&op_heads[0]);
-                // TODO: It may be helpful to print each operation we're merging here
-                let mut workspace_command = self.for_loaded_repo(ui, workspace, base_repo)?;
+                let mut workspace_command =
+                    self.for_loaded_repo_internal(ui, workspace, base_repo, snapshot)?;
                 let mut tx = workspace_command.start_transaction("resolve concurrent operations");
+                let mut merge_report = ViewMergeReport::default();
                 for other_op_head in op_heads.into_iter().skip(1) {
-                    tx.merge_operation(other_op_head);
+                    merge_report.extend(tx.merge_operation(other_op_head));
                     let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
                     if num_rebased > 0 {
                         writeln!(
@@ -261,13 +386,27 @@ jj init --git-repo=.";
                         )?;
                     }
                 }
+                for workspace_id in &merge_report.conflicting_wc_workspaces {
+                    ui.write_warn(format!(
+                        "warning: Concurrent operations changed the working-copy commit of \
+                         workspace '{}' differently. Keeping the one from the operation that \
+                         finished first.\n",
+                        workspace_id.as_str()
+                    ))?;
+                }
+                write_conflicted_refs_warning(
+                    ui,
+                    "Concurrent operations changed some refs differently. These refs are now \
+                     conflicted:",
+                    &merge_report.conflicted_refs,
+                )?;
                 let merged_repo = tx.write().leave_unpublished();
                 locked_op_heads.finish(merged_repo.operation());
                 workspace_command.repo = merged_repo;
                 return Ok(workspace_command);
             }
         };
-        self.for_loaded_repo(ui, workspace, repo)
+        self.for_loaded_repo_internal(ui, workspace, repo, snapshot)
     }
 
     pub fn for_loaded_repo(
@@ -276,16 +415,52 @@ jj init --git-repo=.";
         workspace: Workspace,
         repo: Arc<ReadonlyRepo>,
     ) -> Result<WorkspaceCommandHelper, CommandError> {
-        WorkspaceCommandHelper::for_loaded_repo(
+        self.for_loaded_repo_internal(ui, workspace, repo, true)
+    }
+
+    /// Finds the root of the workspace without loading the repo, for commands
+    /// that only need to know where the workspace is. That keeps them fast
+    /// enough to run from e.g. a shell prompt.
+    pub fn workspace_root(&self, ui: &Ui) -> Result<PathBuf, CommandError> {
+        let wc_path_str = self.global_args.repository.as_deref().unwrap_or(".");
+        let wc_path = ui.cwd().join(wc_path_str);
+        Workspace::find_root(&wc_path).map_err(|_| no_workspace_error(wc_path_str, &wc_path))
+    }
+
+    fn for_loaded_repo_internal(
+        &self,
+        ui: &mut Ui,
+        workspace: Workspace,
+        repo: Arc<ReadonlyRepo>,
+        snapshot: bool,
+    ) -> Result<WorkspaceCommandHelper, CommandError> {
+        let mut workspace_command = WorkspaceCommandHelper::new(
             ui,
             workspace,
             self.string_args.clone(),
             &self.global_args,
             repo,
-        )
+        )?;
+        if snapshot {
+            workspace_command.snapshot(ui, None)?;
+        }
+        Ok(workspace_command)
     }
 }
 
+fn no_workspace_error(wc_path_str: &str, wc_path: &Path) -> CommandError {
+    let mut message = format!("There is no jj repo in \"{}\"", wc_path_str);
+    let git_dir = wc_path.join(".git");
+    if git_dir.is_dir() {
+        // TODO: Make this hint separate from the error, so the caller can format
+        // it differently.
+        message += "
+It looks like this is a git repo. You can create a jj repo backed by it by running this:
+jj init --git-repo=.";
+    }
+    CommandError::UserError(message)
+}
+
 // Provides utilities for writing a command that works on a workspace (like most
 // commands do).
 pub struct WorkspaceCommandHelper {
@@ -307,18 +482,52 @@ impl WorkspaceCommandHelper {
         global_args: &GlobalArgs,
         repo: Arc<ReadonlyRepo>,
     ) -> Result<Self, CommandError> {
+        let mut helper = Self::new(ui, workspace, string_args, global_args, repo)?;
+        helper.snapshot(ui, None)?;
+        Ok(helper)
+    }
+
+    /// Like `for_loaded_repo()`, but doesn't import Git refs or snapshot the
+    /// working copy.
+    pub fn new(
+        ui: &mut Ui,
+        mut workspace: Workspace,
+        string_args: Vec<String>,
+        global_args: &GlobalArgs,
+        repo: Arc<ReadonlyRepo>,
+    ) -> Result<Self, CommandError> {
+        if !workspace.is_bare() {
+            let mut wc_settings = workspace.working_copy().settings().clone();
+            if global_args.allow_large_files {
+                wc_settings.max_new_file_size = None;
+            }
+            if atty::is(Stream::Stderr) {
+                wc_settings.progress_callback = Some(terminal_progress_callback());
+            }
+            workspace.working_copy_mut().set_settings(wc_settings);
+            // Fail right away instead of making the user wait for another command they
+            // may not know about
+            let lock_timeout = match global_args.wait {
+                None => Some(Duration::ZERO),
+                Some(None) => None,
+                Some(Some(seconds)) => Some(Duration::from_secs(seconds)),
+            };
+            workspace.working_copy_mut().set_lock_timeout(lock_timeout);
+        }
         let loaded_at_head = &global_args.at_operation == "@";
-        let may_update_working_copy = loaded_at_head && !global_args.no_commit_working_copy;
+        let may_update_working_copy =
+            loaded_at_head && !global_args.no_commit_working_copy && !workspace.is_bare();
         let mut working_copy_shared_with_git = false;
-        let maybe_git_repo = repo.store().git_repo();
-        if let Some(git_workdir) = maybe_git_repo
+        if let Some(git_workdir) = repo
+            .store()
+            .git_repo()
             .as_ref()
             .and_then(|git_repo| git_repo.workdir())
             .and_then(|workdir| workdir.canonicalize().ok())
         {
             working_copy_shared_with_git = git_workdir == workspace.workspace_root().as_path();
         }
-        let mut helper = Self {
+        Ok(Self {
             cwd: ui.cwd().to_owned(),
             string_args,
             global_args: global_args.clone(),
@@ -327,19 +536,28 @@ impl WorkspaceCommandHelper {
             repo,
             may_update_working_copy,
             working_copy_shared_with_git,
-        };
-        if may_update_working_copy {
-            if working_copy_shared_with_git {
-                helper.import_git_refs_and_head(ui, maybe_git_repo.as_ref().unwrap())?;
+        })
+    }
+
+    /// Imports Git refs if the working copy is shared with Git, and snapshots
+    /// the working copy (only the files matching `matcher` if given), unless
+    /// the command isn't allowed to update it.
+    fn snapshot(&mut self, ui: &mut Ui, matcher: Option<&dyn Matcher>) -> Result<(), CommandError> {
+        if self.may_update_working_copy {
+            if self.working_copy_shared_with_git {
+                let git_repo = self.repo.store().git_repo().unwrap();
+                self.import_git_refs_and_head(ui, &git_repo)?;
             }
-            helper.commit_working_copy(ui)?;
+            self.commit_working_copy_matching(ui, matcher)?;
         }
-        Ok(helper)
+        Ok(())
     }
 
     fn check_working_copy_writable(&self) -> Result<(), CommandError> {
         if self.may_update_working_copy {
             Ok(())
+        } else if self.workspace.is_bare() {
+            Err(bare_workspace_error())
         } else if self.global_args.no_commit_working_copy {
             Err(CommandError::UserError(
                 "This command must be able to update the working copy (don't use \
@@ -368,7 +586,8 @@ impl WorkspaceCommandHelper {
             // Git HEAD.
             if new_git_head != old_git_head && new_git_head.is_some() {
                 let workspace_id = self.workspace.workspace_id();
-                let mut locked_working_copy = self.workspace.working_copy_mut().start_mutation();
+                let mut locked_working_copy =
+                    self.workspace.working_copy_mut().try_start_mutation()?;
                 if let Some(old_wc_commit_id) = self.repo.view().get_wc_commit_id(&workspace_id) {
                     tx.mut_repo()
                         .record_abandoned_commit(old_wc_commit_id.clone());
@@ -378,14 +597,16 @@ impl WorkspaceCommandHelper {
                     .store()
                     .get_commit(new_git_head.as_ref().unwrap())?;
                 tx.mut_repo()
-                    .check_out(workspace_id, &self.settings, &new_checkout);
+                    .check_out(workspace_id, &self.settings, &new_checkout)?;
                 // The working copy was presumably updated by the git command that updated HEAD,
                 // so we just need to reset our working copy state to it without updating
                 // working copy files.
                 locked_working_copy.reset(&new_checkout.tree())?;
                 tx.mut_repo().rebase_descendants(&self.settings)?;
+                let old_view = tx.base_repo().view().clone();
                 self.repo = tx.commit();
                 locked_working_copy.finish(self.repo.op_id().clone());
+                self.run_post_operation_hook(ui, &old_view)?;
             } else {
                 let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
                 if num_rebased > 0 {
@@ -433,12 +654,20 @@ impl WorkspaceCommandHelper {
         &self.repo
     }
 
+    pub fn settings(&self) -> &UserSettings {
+        &self.settings
+    }
+
     pub fn repo_mut(&mut self) -> &mut Arc<ReadonlyRepo> {
         &mut self.repo
     }
 
-    pub fn working_copy(&self) -> &WorkingCopy {
-        self.workspace.working_copy()
+    pub fn working_copy(&self) -> Result<&WorkingCopy, CommandError> {
+        if self.workspace.is_bare() {
+            Err(bare_workspace_error())
+        } else {
+            Ok(self.workspace.working_copy())
+        }
     }
 
     pub fn start_working_copy_mutation(
@@ -454,7 +683,7 @@ impl WorkspaceCommandHelper {
             ));
         };
 
-        let locked_working_copy = self.workspace.working_copy_mut().start_mutation();
+        let locked_working_copy = self.workspace.working_copy_mut().try_start_mutation()?;
         if wc_commit.tree_id() != locked_working_copy.old_tree_id() {
             return Err(CommandError::UserError(
                 "Concurrent working copy operation. Try again.".to_string(),
@@ -483,6 +712,15 @@ impl WorkspaceCommandHelper {
             .to_owned()
     }
 
+    /// Returns the hooks of the backing Git repo if `git.run-hooks` is enabled.
+    pub fn git_hooks(&self) -> Option<GitHooks> {
+        if !self.settings.git_run_hooks() {
+            return None;
+        }
+        let git_repo = self.repo.store().git_repo()?;
+        Some(GitHooks::new(&git_repo, self.workspace_root()))
+    }
+
     pub fn git_config(&self) -> Result<git2::Config, git2::Error> {
         if let Some(git_repo) = self.repo.store().git_repo() {
             git_repo.config()
@@ -499,6 +737,8 @@ impl WorkspaceCommandHelper {
         {
             let excludes_file_path = expand_git_path(excludes_file_str);
             git_ignores = git_ignores.chain_with_file("", excludes_file_path);
+        } else if let Some(excludes_file_path) = default_git_excludes_file() {
+            git_ignores = git_ignores.chain_with_file("", excludes_file_path);
         }
         if let Some(git_repo) = self.repo.store().git_repo() {
             git_ignores =
@@ -507,6 +747,20 @@ impl WorkspaceCommandHelper {
         git_ignores
     }
 
+    /// Returns the filesystem monitor configured with `core.fsmonitor`, if any.
+    pub fn fsmonitor(&self) -> Result<Option<Box<dyn FsMonitor>>, CommandError> {
+        match self.settings.fsmonitor().as_deref() {
+            None | Some("none") => Ok(None),
+            Some("watchman") => Ok(Some(Box::new(Watchman::new(
+                self.settings.watchman_program(),
+                self.workspace_root(),
+            )))),
+            Some(other) => Err(CommandError::ConfigError(format!(
+                "Unknown filesystem monitor in core.fsmonitor: {other}"
+            ))),
+        }
+    }
+
     pub fn resolve_single_op(&self, op_str: &str) -> Result<Operation, CommandError> {
         // When resolving the "@" operation in a `ReadonlyRepo`, we resolve it to the
         // operation the repo was loaded at.
@@ -529,13 +783,25 @@ impl WorkspaceCommandHelper {
                 revision_str
             ))),
             Some(commit) => {
-                if iter.next().is_some() {
-                    Err(CommandError::UserError(format!(
-                        "Revset \"{}\" resolved to more than one revision",
-                        revision_str
-                    )))
-                } else {
-                    Ok(commit?)
+                let commit = commit?;
+                match iter.next() {
+                    None => Ok(commit),
+                    Some(other_commit) => {
+                        if other_commit?.change_id() == commit.change_id() {
+                            Err(CommandError::UserError(format!(
+                                "Revset \"{}\" resolved to more than one revision because \
+                                 change {} is divergent. Use a commit id to select one of its \
+                                 commits.",
+                                revision_str,
+                                short_change_hash(commit.change_id())
+                            )))
+                        } else {
+                            Err(CommandError::UserError(format!(
+                                "Revset \"{}\" resolved to more than one revision",
+                                revision_str
+                            )))
+                        }
+                    }
                 }
             }
         }
@@ -568,7 +834,44 @@ impl WorkspaceCommandHelper {
         Ok(())
     }
 
+    /// Finishes updating the working copy if a previous command was interrupted
+    /// while doing so, so we don't snapshot a half-updated working copy.
+    fn resume_interrupted_checkout(&mut self, ui: &mut Ui) -> Result<(), CommandError> {
+        if !self.workspace.working_copy().has_pending_checkout() {
+            return Ok(());
+        }
+        let mut locked_wc = self.workspace.working_copy_mut().try_start_mutation()?;
+        let stats = match locked_wc.resume_checkout() {
+            Ok(Some(stats)) => stats,
+            // Another process resumed it while we were waiting for the lock
+            Ok(None) => {
+                locked_wc.discard();
+                return Ok(());
+            }
+            Err(err) => {
+                locked_wc.discard();
+                return Err(CommandError::InternalError(format!(
+                    "Failed to resume interrupted checkout: {}",
+                    err
+                )));
+            }
+        };
+        let operation_id = locked_wc.old_operation_id().clone();
+        locked_wc.finish(operation_id);
+        writeln!(ui, "Resumed interrupted update of the working copy")?;
+        print_checkout_stats(ui, stats)?;
+        Ok(())
+    }
+
     pub fn commit_working_copy(&mut self, ui: &mut Ui) -> Result<(), CommandError> {
+        self.commit_working_copy_matching(ui, None)
+    }
+
+    fn commit_working_copy_matching(
+        &mut self,
+        ui: &mut Ui,
+        matcher: Option<&dyn Matcher>,
+    ) -> Result<(), CommandError> {
         let repo = self.repo.clone();
         let workspace_id = self.workspace_id();
         let checkout_id = match repo.view().get_wc_commit_id(&self.workspace_id()) {
@@ -579,87 +882,87 @@ impl WorkspaceCommandHelper {
                 return Ok(());
             }
         };
+        self.resume_interrupted_checkout(ui)?;
         let base_ignores = self.base_ignores();
-        let mut locked_wc = self.workspace.working_copy_mut().start_mutation();
-        // Check if the working copy commit matches the repo's view. It's fine if it
-        // doesn't, but we'll need to reload the repo so the new commit is
-        // in the index and view, and so we don't cause unnecessary
-        // divergence.
+        // Looking at only a few paths is cheap enough without the filesystem monitor
+        let mut fsmonitor = if matcher.is_none() {
+            self.fsmonitor()?
+        } else {
+            None
+        };
+        let auto_rebase = self.auto_rebase();
+        let mut locked_wc = self.workspace.working_copy_mut().try_start_mutation()?;
         let checkout_commit = repo.store().get_commit(&checkout_id)?;
-        let wc_tree_id = locked_wc.old_tree_id().clone();
-        let mut wc_was_stale = false;
-        if *checkout_commit.tree_id() != wc_tree_id {
-            let wc_operation_data = self
-                .repo
-                .op_store()
-                .read_operation(locked_wc.old_operation_id())
-                .unwrap();
-            let wc_operation = Operation::new(
-                repo.op_store().clone(),
-                locked_wc.old_operation_id().clone(),
-                wc_operation_data,
-            );
-            let repo_operation = repo.operation();
-            let maybe_ancestor_op = dag_walk::closest_common_node(
-                [wc_operation.clone()],
-                [repo_operation.clone()],
-                &|op: &Operation| op.parents(),
-                &|op: &Operation| op.id().clone(),
-            );
-            if let Some(ancestor_op) = maybe_ancestor_op {
-                if ancestor_op.id() == repo_operation.id() {
-                    // The working copy was updated since we loaded the repo. We reload the repo
-                    // at the working copy's operation.
-                    self.repo = repo.reload_at(&wc_operation);
-                } else if ancestor_op.id() == wc_operation.id() {
-                    // The working copy was not updated when some repo operation committed,
-                    // meaning that it's stale compared to the repo view. We update the working
-                    // copy to what the view says.
-                    writeln!(
-                        ui,
-                        "The working copy is stale (not updated since operation {}), now updating \
-                         to operation {}",
-                        short_operation_hash(wc_operation.id()),
-                        short_operation_hash(repo_operation.id()),
-                    )?;
-                    locked_wc
-                        .check_out(&checkout_commit.tree())
-                        .map_err(|err| {
-                            CommandError::InternalError(format!(
-                                "Failed to check out commit {}: {}",
-                                checkout_commit.id().hex(),
-                                err
-                            ))
-                        })?;
-                    wc_was_stale = true;
-                } else {
-                    return Err(CommandError::InternalError(format!(
-                        "The repo was loaded at operation {}, which seems to be a sibling of the \
-                         working copy's operation {}",
-                        short_operation_hash(repo_operation.id()),
-                        short_operation_hash(wc_operation.id())
-                    )));
-                }
-            } else {
-                return Err(CommandError::InternalError(format!(
-                    "The repo was loaded at operation {}, which seems unrelated to the working \
-                     copy's operation {}",
-                    short_operation_hash(repo_operation.id()),
+        match check_stale_working_copy(&locked_wc, &checkout_commit, &repo) {
+            Ok(WorkingCopyFreshness::Fresh) => {}
+            Ok(WorkingCopyFreshness::Updated(wc_operation)) => {
+                // The working copy was updated since we loaded the repo. We reload the repo
+                // at the working copy's operation so the new commit is in the index and view,
+                // and so we don't cause unnecessary divergence.
+                self.repo = repo.reload_at(&wc_operation);
+            }
+            Ok(WorkingCopyFreshness::Stale(wc_operation)) => {
+                // Updating the files now could overwrite changes made since the working copy
+                // was last updated, so leave that to `jj workspace update-stale`.
+                locked_wc.discard();
+                return Err(CommandError::UserError(format!(
+                    "The working copy is stale (not updated since operation {}).\nRun `jj \
+                     workspace update-stale` to update it.",
                     short_operation_hash(wc_operation.id())
                 )));
             }
+            Err(err) => {
+                locked_wc.discard();
+                return Err(err);
+            }
         }
-        let new_tree_id = locked_wc.snapshot(base_ignores)?;
+        let snapshot_result = match (&mut fsmonitor, matcher) {
+            (Some(fsmonitor), _) => {
+                locked_wc.snapshot_with_fsmonitor(base_ignores, fsmonitor.as_mut())
+            }
+            (None, Some(matcher)) => locked_wc.snapshot_matching(base_ignores, matcher),
+            (None, None) => locked_wc.snapshot(base_ignores),
+        };
+        let new_tree_id = match snapshot_result {
+            Ok(tree_id) => tree_id,
+            Err(err) => {
+                // Don't save the partially updated state
+                locked_wc.discard();
+                return Err(err.into());
+            }
+        };
         if new_tree_id != *checkout_commit.tree_id() {
+            if let Some(cache) = repo.store().resolution_cache() {
+                let new_tree = repo.store().get_tree(&RepoPath::root(), &new_tree_id)?;
+                if let Err(err) =
+                    record_resolutions(repo.store(), cache, &checkout_commit.tree(), &new_tree)
+                {
+                    ui.write_warn(format!("warning: {err}\n"))?;
+                }
+            }
             let mut tx = self.repo.start_transaction("commit working copy");
             let mut_repo = tx.mut_repo();
-            let commit = CommitBuilder::for_rewrite_from(&self.settings, &checkout_commit)
+            // Signing the commit can fail, so don't leave the working copy locked then
+            let commit = match CommitBuilder::for_rewrite_from(&self.settings, &checkout_commit)
                 .set_tree(new_tree_id)
-                .write_to_repo(mut_repo);
+                .try_write_to_repo(mut_repo)
+            {
+                Ok(commit) => commit,
+                Err(err) => {
+                    locked_wc.discard();
+                    return Err(err.into());
+                }
+            };
             mut_repo.set_wc_commit(workspace_id, commit.id().clone());
 
             // Rebase descendants
-            let num_rebased = mut_repo.rebase_descendants(&self.settings)?;
+            let num_rebased = match rebase_descendants(ui, &self.settings, auto_rebase, mut_repo) {
+                Ok(num_rebased) => num_rebased,
+                Err(err) => {
+                    locked_wc.discard();
+                    return Err(err);
+                }
+            };
             if num_rebased > 0 {
                 writeln!(
                     ui,
@@ -668,9 +971,12 @@ impl WorkspaceCommandHelper {
                 )?;
             }
 
+            let old_view = tx.base_repo().view().clone();
             self.repo = tx.commit();
             locked_wc.finish(self.repo.op_id().clone());
-        } else if wc_was_stale {
+            self.run_post_operation_hook(ui, &old_view)?;
+        } else if fsmonitor.is_some() {
+            // Also save the working copy if the filesystem monitor's clock was updated
             locked_wc.finish(self.repo.op_id().clone());
         } else {
             locked_wc.discard();
@@ -678,6 +984,107 @@ impl WorkspaceCommandHelper {
         Ok(())
     }
 
+    /// Updates a stale working copy to the working-copy commit in the repo
+    /// view. Changes made on disk since the working copy was last updated are
+    /// merged into a rewritten working-copy commit.
+    pub fn update_stale_working_copy(&mut self, ui: &mut Ui) -> Result<(), CommandError> {
+        self.check_working_copy_writable()?;
+        let repo = self.repo.clone();
+        let workspace_id = self.workspace_id();
+        let wc_commit = match repo.view().get_wc_commit_id(&workspace_id) {
+            Some(wc_commit_id) => repo.store().get_commit(wc_commit_id)?,
+            None => {
+                return Err(CommandError::UserError(
+                    "Nothing checked out in this workspace".to_string(),
+                ));
+            }
+        };
+        let base_ignores = self.base_ignores();
+        let auto_rebase = self.auto_rebase();
+        let mut locked_wc = self.workspace.working_copy_mut().try_start_mutation()?;
+        let wc_operation = match check_stale_working_copy(&locked_wc, &wc_commit, &repo) {
+            Ok(WorkingCopyFreshness::Stale(wc_operation)) => wc_operation,
+            Ok(_) => {
+                locked_wc.discard();
+                writeln!(ui, "Nothing to do (the working copy is not stale).")?;
+                return Ok(());
+            }
+            Err(err) => {
+                locked_wc.discard();
+                return Err(err);
+            }
+        };
+        let old_tree_id = locked_wc.old_tree_id().clone();
+        let snapshot_tree_id = match locked_wc.snapshot(base_ignores) {
+            Ok(tree_id) => tree_id,
+            Err(err) => {
+                locked_wc.discard();
+                return Err(err.into());
+            }
+        };
+        let mut new_wc_commit = wc_commit.clone();
+        if snapshot_tree_id != old_tree_id {
+            // Apply the changes made on disk on top of the working-copy commit
+            let store = repo.store();
+            let old_tree = store.get_tree(&RepoPath::root(), &old_tree_id)?;
+            let snapshot_tree = store.get_tree(&RepoPath::root(), &snapshot_tree_id)?;
+            let merged_tree_id = merge_trees(&wc_commit.tree(), &old_tree, &snapshot_tree)?;
+            let mut tx = repo.start_transaction("update stale working copy");
+            let mut_repo = tx.mut_repo();
+            new_wc_commit = match CommitBuilder::for_rewrite_from(&self.settings, &wc_commit)
+                .set_tree(merged_tree_id)
+                .try_write_to_repo(mut_repo)
+            {
+                Ok(commit) => commit,
+                Err(err) => {
+                    locked_wc.discard();
+                    return Err(err.into());
+                }
+            };
+            mut_repo.set_wc_commit(workspace_id.clone(), new_wc_commit.id().clone());
+            let num_rebased = match rebase_descendants(ui, &self.settings, auto_rebase, mut_repo) {
+                Ok(num_rebased) => num_rebased,
+                Err(err) => {
+                    locked_wc.discard();
+                    return Err(err);
+                }
+            };
+            if num_rebased > 0 {
+                writeln!(
+                    ui,
+                    "Rebased {} descendant commits onto updated working copy",
+                    num_rebased
+                )?;
+            }
+            self.repo = tx.commit();
+            writeln!(
+                ui,
+                "Merged changes made since operation {} into the working-copy commit",
+                short_operation_hash(wc_operation.id())
+            )?;
+        }
+        let stats = match locked_wc.check_out(&new_wc_commit.tree()) {
+            Ok(stats) => stats,
+            Err(err) => {
+                locked_wc.discard();
+                return Err(CommandError::InternalError(format!(
+                    "Failed to check out commit {}: {}",
+                    new_wc_commit.id().hex(),
+                    err
+                )));
+            }
+        };
+        locked_wc.finish(self.repo.op_id().clone());
+        ui.write("Working copy now at: ")?;
+        ui.write_commit_summary(self.repo.as_repo_ref(), &workspace_id, &new_wc_commit)?;
+        ui.write("\n")?;
+        print_checkout_stats(ui, stats)?;
+        if !Arc::ptr_eq(&self.repo, &repo) {
+            self.run_post_operation_hook(ui, repo.view())?;
+        }
+        Ok(())
+    }
+
     pub fn edit_diff(
         &self,
         ui: &mut Ui,
@@ -704,18 +1111,9 @@ impl WorkspaceCommandHelper {
         interactive: bool,
         matcher: &dyn Matcher,
     ) -> Result<TreeId, CommandError> {
-        if interactive {
-            Ok(crate::diff_edit::edit_diff(
-                ui,
-                &self.settings,
-                left_tree,
-                right_tree,
-                instructions,
-                self.base_ignores(),
-            )?)
-        } else if matcher.visit(&RepoPath::root()) == Visit::AllRecursively {
+        let selected_tree_id = if matcher.visit(&RepoPath::root()) == Visit::AllRecursively {
             // Optimization for a common case
-            Ok(right_tree.id().clone())
+            right_tree.id().clone()
         } else {
             let mut tree_builder = self.repo().store().tree_builder(left_tree.id().clone());
             for (repo_path, diff) in left_tree.diff(right_tree, matcher) {
@@ -728,7 +1126,24 @@ impl WorkspaceCommandHelper {
                     }
                 }
             }
-            Ok(tree_builder.write_tree())
+            tree_builder.write_tree()
+        };
+        if interactive {
+            // Only the changes to the matched paths are shown in the diff editor
+            let selected_tree = self
+                .repo()
+                .store()
+                .get_tree(&RepoPath::root(), &selected_tree_id)?;
+            Ok(crate::diff_edit::edit_diff(
+                ui,
+                &self.settings,
+                left_tree,
+                &selected_tree,
+                instructions,
+                self.base_ignores(),
+            )?)
+        } else {
+            Ok(selected_tree_id)
         }
     }
 
@@ -756,11 +1171,18 @@ impl WorkspaceCommandHelper {
                 format!("'{}'", arg.replace('\'', "\\'"))
             }
         };
+        for (key, value) in self.settings.operation_tags() {
+            tx.set_tag(key, value);
+        }
         let quoted_strings = self.string_args.iter().map(shell_escape).collect_vec();
         tx.set_tag("args".to_string(), quoted_strings.join(" "));
         tx
     }
 
+    fn auto_rebase(&self) -> bool {
+        self.settings.auto_rebase() && !self.global_args.no_auto_rebase
+    }
+
     pub fn finish_transaction(
         &mut self,
         ui: &mut Ui,
@@ -772,16 +1194,27 @@ impl WorkspaceCommandHelper {
             writeln!(ui, "Nothing changed.")?;
             return Ok(());
         }
-        let num_rebased = mut_repo.rebase_descendants(ui.settings())?;
+        let num_rebased = rebase_descendants(ui, &self.settings, self.auto_rebase(), mut_repo)?;
         if num_rebased > 0 {
             writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
         }
+        if self.workspace.is_bare()
+            && mut_repo
+                .view()
+                .get_wc_commit_id(&self.workspace_id())
+                .is_some()
+        {
+            return Err(bare_workspace_error());
+        }
         if self.working_copy_shared_with_git {
             self.export_head_to_git(mut_repo)?;
+            // Export the branches now and record the resulting Git refs in this
+            // operation, so the next command doesn't need to import them
+            let git_repo = store.git_repo().unwrap();
+            git::export_changes_and_record(mut_repo, &git_repo)?;
         }
-        let maybe_old_commit = tx
-            .base_repo()
-            .view()
+        let old_view = tx.base_repo().view().clone();
+        let maybe_old_commit = old_view
             .get_wc_commit_id(&self.workspace_id())
             .map(|commit_id| store.get_commit(commit_id))
             .transpose()?;
@@ -802,8 +1235,95 @@ impl WorkspaceCommandHelper {
             let git_repo = self.repo.store().git_repo().unwrap();
             git::export_refs(&self.repo, &git_repo)?;
         }
+        self.run_post_operation_hook(ui, &old_view)?;
         Ok(())
     }
+
+    /// Runs the `hooks.post-operation` command with a summary of what the
+    /// current operation changed since `old_view`. The operation is already
+    /// committed, so a failing hook only causes a warning.
+    fn run_post_operation_hook(&self, ui: &mut Ui, old_view: &View) -> Result<(), CommandError> {
+        let command = match self.settings.post_operation_hook() {
+            Some(command) => command,
+            None => return Ok(()),
+        };
+        let summary = OperationSummary::new(self.repo.operation(), old_view, self.repo.view());
+        let hook = PostOperationHook::new(command, self.workspace_root());
+        if let Err(err) = hook.run(&summary) {
+            ui.write_warn(format!("warning: {err}\n"))?;
+        }
+        Ok(())
+    }
+
+    /// Abandons the operations and records the working copy's new operation id
+    /// if the operation it was last updated at was rewritten. That operation
+    /// itself can't be abandoned. The repo is reloaded at the new head.
+    pub fn abandon_operations(
+        &mut self,
+        abandoned_ids: &HashSet<OperationId>,
+    ) -> Result<OpAbandonment, CommandError> {
+        // The working copy records the operation it was updated at, so keep it
+        // locked until that's been updated
+        let locked_wc = if self.workspace.is_bare() {
+            None
+        } else {
+            let locked_wc = self.workspace.working_copy_mut().try_start_mutation()?;
+            if abandoned_ids.contains(locked_wc.old_operation_id()) {
+                let err = OpAbandonError::HeadOperation(locked_wc.old_operation_id().clone());
+                locked_wc.discard();
+                return Err(err.into());
+            }
+            Some(locked_wc)
+        };
+        let abandonment = match op_abandon::abandon_operations(&self.repo, abandoned_ids) {
+            Ok(abandonment) => abandonment,
+            Err(err) => {
+                if let Some(locked_wc) = locked_wc {
+                    locked_wc.discard();
+                }
+                return Err(err.into());
+            }
+        };
+        if let Some(locked_wc) = locked_wc {
+            let new_op_id = abandonment
+                .rewritten_ids
+                .get(locked_wc.old_operation_id())
+                .cloned();
+            match new_op_id {
+                Some(new_op_id) => locked_wc.finish(new_op_id),
+                None => locked_wc.discard(),
+            }
+        }
+        self.repo = self.repo.reload_at_head(&self.settings)?;
+        Ok(abandonment)
+    }
+
+    /// The operations older than `operation.expire-after-days`, except for the
+    /// one the working copy was last updated at.
+    pub fn expired_operations(&self) -> Result<HashSet<OperationId>, CommandError> {
+        let days = self.settings.operation_expire_after_days().ok_or_else(|| {
+            CommandError::UserError(
+                "Operations don't expire. Set `operation.expire-after-days` to expire them."
+                    .to_string(),
+            )
+        })?;
+        const DAY_MILLIS: u64 = 24 * 60 * 60 * 1000;
+        let now = Timestamp::now().timestamp.0;
+        let cutoff = now.saturating_sub(days.saturating_mul(DAY_MILLIS));
+        let mut expired_ids =
+            op_abandon::find_expired_operations(&self.repo, MillisSinceEpoch(cutoff))
+                .map_err(OpAbandonError::OpStoreError)?;
+        if !self.workspace.is_bare() {
+            expired_ids.remove(&self.workspace.working_copy().operation_id());
+        }
+        Ok(expired_ids)
+    }
+}
+
+fn bare_workspace_error() -> CommandError {
+    CommandError::UserError(
+        "This command needs a working copy, but this is a bare repo.".to_string(),
+    )
 }
 
 pub fn print_checkout_stats(ui: &mut Ui, stats: CheckoutStats) -> Result<(), std::io::Error> {
@@ -817,8 +1337,47 @@ pub fn print_checkout_stats(ui: &mut Ui, stats: CheckoutStats) -> Result<(), std
     Ok(())
 }
 
+/// Rebases the descendants of rewritten commits, or leaves them in place if
+/// `auto_rebase` is false. Returns the number of rebased commits.
+fn rebase_descendants(
+    ui: &mut Ui,
+    settings: &UserSettings,
+    auto_rebase: bool,
+    mut_repo: &mut MutableRepo,
+) -> Result<usize, CommandError> {
+    if auto_rebase {
+        return Ok(mut_repo.rebase_descendants(settings)?);
+    }
+    if !mut_repo.has_rewrites() {
+        return Ok(0);
+    }
+    let mut rebaser = mut_repo.create_descendant_rebaser(settings);
+    rebaser.defer_descendants_of_rewritten();
+    rebaser.rebase_all()?;
+    let num_deferred = rebaser.deferred().len();
+    if num_deferred > 0 {
+        writeln!(
+            ui,
+            "Left {} descendant commits in place (use `jj evolve` to rebase them)",
+            num_deferred
+        )?;
+    }
+    Ok(rebaser.rebased().len())
+}
+
+/// The file Git uses for global ignores when core.excludesFile is not set.
+fn default_git_excludes_file() -> Option<PathBuf> {
+    match std::env::var("XDG_CONFIG_HOME") {
+        Ok(config_dir) if !config_dir.is_empty() => Some(PathBuf::from(config_dir)),
+        _ => std::env::var("HOME")
+            .ok()
+            .map(|home_dir| PathBuf::from(home_dir).join(".config")),
+    }
+    .map(|config_dir| config_dir.join("git").join("ignore"))
+}
+
 /// Expands "~/" to "$HOME/" as Git seems to do for e.g. core.excludesFile.
-fn expand_git_path(path_str: String) -> PathBuf {
+pub fn expand_git_path(path_str: String) -> PathBuf {
     if let Some(remainder) = path_str.strip_prefix("~/") {
         if let Ok(home_dir_str) = std::env::var("HOME") {
             return PathBuf::from(home_dir_str).join(remainder);
@@ -895,6 +1454,9 @@ fn resolve_single_op_from_store(
     op_str: &str,
 ) -> Result<Operation, CommandError> {
     if op_str.is_empty() || !op_str.as_bytes().iter().all(|b| b.is_ascii_hexdigit()) {
+        if let Some(time) = parse_op_time_spec(op_str, Local::now()) {
+            return resolve_op_at_time(op_store, op_heads_store, op_str, time);
+        }
         return Err(CommandError::UserError(format!(
             "Operation ID \"{}\" is not a valid hexadecimal prefix",
             op_str
@@ -937,6 +1499,97 @@ fn resolve_single_op_from_store(
     }
 }
 
+/// Resolves to the latest operation that ended at or before `time`.
+fn resolve_op_at_time(
+    op_store: &Arc<dyn OpStore>,
+    op_heads_store: &Arc<OpHeadsStore>,
+    op_str: &str,
+    time: DateTime<Local>,
+) -> Result<Operation, CommandError> {
+    let millis = time.timestamp_millis();
+    find_all_operations(op_store, op_heads_store)
+        .into_iter()
+        .filter(|op| op.store_operation().metadata.end_time.timestamp.0 as i64 <= millis)
+        .max_by_key(|op| op.store_operation().metadata.end_time.timestamp.0)
+        .ok_or_else(|| {
+            CommandError::UserError(format!(
+                "No operation ended at or before \"{}\" ({})",
+                op_str,
+                time.format("%Y-%m-%d %H:%M:%S %:z")
+            ))
+        })
+}
+
+/// Parses a time to select an operation by, relative to `now`. The time can be
+/// wrapped in `@{...}` and is one of:
+/// * `now`, or `<n> <unit>s ago` where the unit is `second`, `minute`,
+///   `hour`, `day`, or `week`, with the words separated by spaces or dots (as
+///   in `2.days.ago`)
+/// * `today` or `yesterday`, optionally followed by a time of day (`17:00`);
+///   without one, it's the current time of day
+/// * a date (`2022-10-01`), optionally followed by a time of day; without one,
+///   it's the start of the day
+/// * an RFC 3339 timestamp
+fn parse_op_time_spec(spec: &str, now: DateTime<Local>) -> Option<DateTime<Local>> {
+    let spec = spec
+        .strip_prefix("@{")
+        .and_then(|spec| spec.strip_suffix('}'))
+        .unwrap_or(spec)
+        .trim();
+    if let Ok(time) = DateTime::parse_from_rfc3339(spec) {
+        return Some(time.with_timezone(&Local));
+    }
+    let spec = spec.to_lowercase();
+    if spec == "now" {
+        return Some(now);
+    }
+    let words = spec
+        .split([' ', '.'])
+        .filter(|word| !word.is_empty())
+        .collect_vec();
+    match words.as_slice() {
+        [count, unit, "ago"] => {
+            let count: i64 = count.parse().ok()?;
+            let duration = match unit.strip_suffix('s').unwrap_or(*unit) {
+                "second" => chrono::Duration::seconds(count),
+                "minute" => chrono::Duration::minutes(count),
+                "hour" => chrono::Duration::hours(count),
+                "day" => chrono::Duration::days(count),
+                "week" => chrono::Duration::weeks(count),
+                _ => return None,
+            };
+            Some(now - duration)
+        }
+        [day] | [day, _] if *day == "today" || *day == "yesterday" => {
+            let date = if *day == "today" {
+                now.naive_local().date()
+            } else {
+                now.naive_local().date().pred_opt()?
+            };
+            let time = match words.get(1) {
+                Some(time) => parse_time_of_day(time)?,
+                None => now.time(),
+            };
+            Local.from_local_datetime(&date.and_time(time)).earliest()
+        }
+        [date] | [date, _] => {
+            let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").ok()?;
+            let time = match words.get(1) {
+                Some(time) => parse_time_of_day(time)?,
+                None => NaiveTime::from_hms_opt(0, 0, 0)?,
+            };
+            Local.from_local_datetime(&date.and_time(time)).earliest()
+        }
+        _ => None,
+    }
+}
+
+fn parse_time_of_day(time: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(time, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(time, "%H:%M"))
+        .ok()
+}
+
 pub fn resolve_base_revs(
     workspace_command: &WorkspaceCommandHelper,
     revisions: &[String],
@@ -996,6 +1649,65 @@ pub fn matcher_from_values(
     }
 }
 
+/// How the working copy's state relates to the repo view.
+enum WorkingCopyFreshness {
+    /// The working copy matches the working-copy commit in the view.
+    Fresh,
+    /// The working copy was updated by an operation after the one the repo
+    /// was loaded at.
+    Updated(Operation),
+    /// The working copy was last updated by an operation before the one the
+    /// repo was loaded at, so it doesn't reflect later changes to the
+    /// working-copy commit.
+    Stale(Operation),
+}
+
+fn check_stale_working_copy(
+    locked_wc: &LockedWorkingCopy,
+    wc_commit: &Commit,
+    repo: &ReadonlyRepo,
+) -> Result<WorkingCopyFreshness, CommandError> {
+    if wc_commit.tree_id() == locked_wc.old_tree_id() {
+        return Ok(WorkingCopyFreshness::Fresh);
+    }
+    let wc_operation_data = repo
+        .op_store()
+        .read_operation(locked_wc.old_operation_id())
+        .unwrap();
+    let wc_operation = Operation::new(
+        repo.op_store().clone(),
+        locked_wc.old_operation_id().clone(),
+        wc_operation_data,
+    );
+    let repo_operation = repo.operation();
+    let maybe_ancestor_op = dag_walk::closest_common_node(
+        [wc_operation.clone()],
+        [repo_operation.clone()],
+        &|op: &Operation| op.parents(),
+        &|op: &Operation| op.id().clone(),
+    );
+    match maybe_ancestor_op {
+        Some(ancestor_op) if ancestor_op.id() == repo_operation.id() => {
+            Ok(WorkingCopyFreshness::Updated(wc_operation))
+        }
+        Some(ancestor_op) if ancestor_op.id() == wc_operation.id() => {
+            Ok(WorkingCopyFreshness::Stale(wc_operation))
+        }
+        Some(_) => Err(CommandError::InternalError(format!(
+            "The repo was loaded at operation {}, which seems to be a sibling of the working \
+             copy's operation {}",
+            short_operation_hash(repo_operation.id()),
+            short_operation_hash(wc_operation.id())
+        ))),
+        None => Err(CommandError::InternalError(format!(
+            "The repo was loaded at operation {}, which seems unrelated to the working copy's \
+             operation {}",
+            short_operation_hash(repo_operation.id()),
+            short_operation_hash(wc_operation.id())
+        ))),
+    }
+}
+
 fn update_working_copy(
     ui: &mut Ui,
     repo: &Arc<ReadonlyRepo>,
@@ -1021,18 +1733,19 @@ fn update_working_copy(
                 old_tree_id.as_ref(),
                 &new_commit.tree(),
             )
-            .map_err(|err| {
-                CommandError::InternalError(format!(
+            .map_err(|err| match err {
+                CheckoutError::Locked(err) => err.into(),
+                err => CommandError::InternalError(format!(
                     "Failed to check out commit {}: {}",
                     new_commit.id().hex(),
                     err
-                ))
+                )),
             })?;
         Some(stats)
     } else {
         // Record new operation id which represents the latest working-copy state
         // TODO: no need to rewrite the tree_state file
-        let locked_wc = wc.start_mutation();
+        let locked_wc = wc.try_start_mutation()?;
         locked_wc.finish(repo.op_id().clone());
         None
     };
@@ -1053,6 +1766,37 @@ pub fn short_commit_hash(commit_id: &CommitId) -> String {
     commit_id.hex()[0..12].to_string()
 }
 
+pub fn short_change_hash(change_id: &ChangeId) -> String {
+    change_id.hex()[0..12].to_string()
+}
+
+/// Warns about refs that were left conflicted by merging concurrent changes,
+/// one per line below the message. Conflicts in the refs mirroring the
+/// underlying Git repo aren't listed since the next import resolves them.
+pub fn write_conflicted_refs_warning(
+    ui: &mut Ui,
+    message: &str,
+    conflicted_refs: &[RefName],
+) -> Result<(), std::io::Error> {
+    let names = conflicted_refs
+        .iter()
+        .filter_map(|ref_name| match ref_name {
+            RefName::LocalBranch(branch) => Some(format!("branch {branch}")),
+            RefName::RemoteBranch { branch, remote } => Some(format!("branch {branch}@{remote}")),
+            RefName::Tag(tag) => Some(format!("tag {tag}")),
+            RefName::GitRef(_) => None,
+        })
+        .collect_vec();
+    if names.is_empty() {
+        return Ok(());
+    }
+    ui.write_warn(format!("warning: {message}\n"))?;
+    for name in names {
+        ui.write_warn(format!("  {name}\n"))?;
+    }
+    Ok(())
+}
+
 pub fn short_operation_hash(operation_id: &OperationId) -> String {
     operation_id.hex()[0..12].to_string()
 }
@@ -1100,6 +1844,34 @@ pub struct GlobalArgs {
     /// another process that commits the working copy.
     #[clap(long, global = true, help_heading = "GLOBAL OPTIONS")]
     pub no_commit_working_copy: bool,
+    /// Don't rebase descendants of rewritten commits
+    ///
+    /// By default, when a command rewrites a commit, its descendants are
+    /// rebased onto the new commit. With this flag, they're left on top of the
+    /// old commit until you run `jj evolve`. This can also be made the default
+    /// with the `ui.auto-rebase` config setting.
+    #[clap(long, global = true, help_heading = "GLOBAL OPTIONS")]
+    pub no_auto_rebase: bool,
+    /// Snapshot new files even if they're too large
+    ///
+    /// By default, new files larger than `snapshot.max-new-file-size` make
+    /// snapshotting the working copy fail, so large files don't get committed
+    /// by accident.
+    #[clap(long, global = true, help_heading = "GLOBAL OPTIONS")]
+    pub allow_large_files: bool,
+    /// Wait for other commands using the working copy to finish
+    ///
+    /// By default, a command fails right away if another command is using the
+    /// same workspace's working copy. With `--wait`, it waits for as long as it
+    /// takes. With `--wait=<SECONDS>`, it gives up after that many seconds.
+    #[clap(
+        long,
+        global = true,
+        help_heading = "GLOBAL OPTIONS",
+        require_equals = true,
+        value_name = "SECONDS"
+    )]
+    pub wait: Option<Option<u64>>,
     /// Operation to load the repo at
     ///
     /// Operation to load the repo at. By default, Jujutsu loads the repo at the
@@ -1111,6 +1883,11 @@ pub struct GlobalArgs {
     /// Use `jj op log` to find the operation ID you want. Any unambiguous
     /// prefix of the operation ID is enough.
     ///
+    /// The operation can also be given as a time, which selects the latest
+    /// operation that ended at or before it: `2.days.ago`, `yesterday 17:00`,
+    /// `2022-10-01 09:30`, or an RFC 3339 timestamp, optionally wrapped in
+    /// `@{...}` (e.g. `@{2.days.ago}`).
+    ///
     /// When loading the repo at an earlier operation, the working copy will not
     /// be automatically committed.
     ///
@@ -1134,6 +1911,13 @@ pub struct GlobalArgs {
         help_heading = "GLOBAL OPTIONS"
     )]
     pub color: Option<ColorChoice>,
+    /// Don't send long output to a pager
+    ///
+    /// By default, the output of commands like `jj log` and `jj diff` is sent
+    /// to a pager if stdout is a terminal. Paging can also be turned off with
+    /// `ui.paginate = "never"`.
+    #[clap(long, global = true, help_heading = "GLOBAL OPTIONS")]
+    pub no_pager: bool,
 }
 
 pub fn create_ui() -> (Ui<'static>, Result<(), CommandError>) {
@@ -1257,6 +2041,9 @@ pub fn parse_args<'help>(
         // Here we assume ui was created for_terminal().
         ui.reset_color_for_terminal(choice);
     }
+    if args.global_args.no_pager {
+        ui.disable_pager();
+    }
     let command_helper = CommandHelper::new(app, string_args, args.global_args);
     Ok((command_helper, matches))
 }
@@ -1264,6 +2051,9 @@ pub fn parse_args<'help>(
 // TODO: Return std::process::ExitCode instead, once our MSRV is >= 1.61
 #[must_use]
 pub fn handle_command_result(ui: &mut Ui, result: Result<(), CommandError>) -> i32 {
+    // Wait for the pager first, so errors are printed after it exits instead of
+    // being hidden behind it
+    let paged = ui.finalize_pager();
     match result {
         Ok(()) => 0,
         Err(CommandError::UserError(message)) => {
@@ -1279,6 +2069,8 @@ pub fn handle_command_result(ui: &mut Ui, result: Result<(), CommandError>) -> i
             ui.write_error(&format!("Error: {}\n", message)).unwrap();
             2
         }
+        // The pager was quit before it got all the output
+        Err(CommandError::BrokenPipe) if paged => 0,
         Err(CommandError::BrokenPipe) => std::process::exit(3),
         Err(CommandError::InternalError(message)) => {
             ui.write_error(&format!("Internal error: {}\n", message))
@@ -1287,3 +2079,59 @@ pub fn handle_command_result(ui: &mut Ui, result: Result<(), CommandError>) -> i
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn local_time(date: &str, time: &str) -> DateTime<Local> {
+        let date = NaiveDate::parse_from_str(date, "%Y-%m-%d").unwrap();
+        let time = NaiveTime::parse_from_str(time, "%H:%M:%S").unwrap();
+        Local
+            .from_local_datetime(&date.and_time(time))
+            .earliest()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_parse_op_time_spec() {
+        let now = local_time("2022-10-05", "12:34:56");
+        assert_eq!(parse_op_time_spec("now", now), Some(now));
+        assert_eq!(
+            parse_op_time_spec("2.days.ago", now),
+            Some(local_time("2022-10-03", "12:34:56"))
+        );
+        assert_eq!(
+            parse_op_time_spec("@{1 hour ago}", now),
+            Some(local_time("2022-10-05", "11:34:56"))
+        );
+        assert_eq!(
+            parse_op_time_spec("yesterday 17:00", now),
+            Some(local_time("2022-10-04", "17:00:00"))
+        );
+        assert_eq!(
+            parse_op_time_spec("yesterday", now),
+            Some(local_time("2022-10-04", "12:34:56"))
+        );
+        assert_eq!(
+            parse_op_time_spec("today 08:15:30", now),
+            Some(local_time("2022-10-05", "08:15:30"))
+        );
+        assert_eq!(
+            parse_op_time_spec("2022-10-01", now),
+            Some(local_time("2022-10-01", "00:00:00"))
+        );
+        assert_eq!(
+            parse_op_time_spec("@{2022-10-01 09:30}", now),
+            Some(local_time("2022-10-01", "09:30:00"))
+        );
+        assert_eq!(
+            parse_op_time_spec("2001-02-03T04:05:06+07:00", now).map(|time| time.timestamp()),
+            Some(981147906)
+        );
+
+        assert_eq!(parse_op_time_spec("foo", now), None);
+        assert_eq!(parse_op_time_spec("2.fortnights.ago", now), None);
+        assert_eq!(parse_op_time_spec("yesterday 25:00", now), None);
+    }
+}
@@ -12,56 +12,82 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::collections::{HashSet, VecDeque};
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
 use std::fmt::Debug;
 use std::fs::OpenOptions;
 use std::io::{Read, Seek, SeekFrom, Write};
 use std::ops::Range;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime};
 use std::{fs, io};
 
+use atty::Stream;
 use chrono::{FixedOffset, TimeZone, Utc};
 use clap::{ArgGroup, ArgMatches, CommandFactory, FromArgMatches, Subcommand};
 use criterion::Criterion;
 use itertools::Itertools;
-use jujutsu_lib::backend::{BackendError, CommitId, Timestamp, TreeValue};
+use jujutsu_lib::backend::{
+    Backend, BackendError, ChangeId, CommitId, ConflictId, FileId, Timestamp, TreeId, TreeValue,
+};
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::commit_builder::CommitBuilder;
-use jujutsu_lib::dag_walk::topo_order_reverse;
+use jujutsu_lib::conflicts::ConflictMarkerStyle;
+use jujutsu_lib::copies::{CopyOperation, CopyRecord};
+use jujutsu_lib::dag_walk::{bfs, topo_order_reverse};
 use jujutsu_lib::diff::{Diff, DiffHunk};
 use jujutsu_lib::files::DiffLine;
-use jujutsu_lib::git::{GitFetchError, GitRefUpdate};
-use jujutsu_lib::index::{HexPrefix, IndexEntry};
+use jujutsu_lib::fsck::FsckError;
+use jujutsu_lib::gc::GcError;
+use jujutsu_lib::git::{GitFetchError, GitPushError, GitRefUpdate, RefSpec};
+use jujutsu_lib::git_backend::GitBackend;
+use jujutsu_lib::git_hooks::PushedRef;
+#[cfg(feature = "gitoxide")]
+use jujutsu_lib::gitoxide_backend::GitoxideBackend;
+use jujutsu_lib::index::{HexPrefix, IndexEntry, IndexPosition};
+use jujutsu_lib::local_backend::LocalBackend;
 use jujutsu_lib::matchers::{EverythingMatcher, Matcher};
-use jujutsu_lib::op_store::{RefTarget, WorkspaceId};
+use jujutsu_lib::op_store::{BranchTarget, OperationId, RefTarget, WorkspaceId};
 use jujutsu_lib::operation::Operation;
 use jujutsu_lib::refs::{classify_branch_push_action, BranchPushAction, BranchPushUpdate};
-use jujutsu_lib::repo::{ReadonlyRepo, RepoRef};
+use jujutsu_lib::repo::{MutableRepo, ReadonlyRepo, RepoRef};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::revset::RevsetExpression;
-use jujutsu_lib::revset_graph_iterator::{RevsetGraphEdge, RevsetGraphEdgeType};
-use jujutsu_lib::rewrite::{back_out_commit, merge_commit_trees, rebase_commit, DescendantRebaser};
+use jujutsu_lib::revset_graph_iterator::{
+    ReverseRevsetGraphIterator, RevsetGraphEdge, RevsetGraphEdgeType,
+};
+use jujutsu_lib::rewrite::{
+    back_out_commit, merge_commit_trees, new_conflict_paths, rebase_commit, simulate_merge,
+    DescendantRebaser,
+};
 use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::signing::Signer;
 use jujutsu_lib::store::Store;
+use jujutsu_lib::transaction::Transaction;
 use jujutsu_lib::tree::{merge_trees, Tree, TreeDiffIterator};
 use jujutsu_lib::view::View;
+use jujutsu_lib::view_snapshot::ViewSnapshotError;
+use jujutsu_lib::working_copy::TreeState;
 use jujutsu_lib::workspace::Workspace;
-use jujutsu_lib::{conflicts, diff, files, git, revset, tree};
+use jujutsu_lib::{
+    conflicts, copies, diff, files, fsck, gc, git, migrate, revset, tree, view_snapshot,
+};
 use maplit::{hashmap, hashset};
 use pest::Parser;
 
 use crate::cli_util::{
-    matcher_from_values, print_checkout_stats, repo_paths_from_values, resolve_base_revs,
-    short_commit_description, short_commit_hash, Args, CommandError, CommandHelper,
-    WorkspaceCommandHelper,
+    expand_git_path, matcher_from_values, print_checkout_stats, repo_paths_from_values,
+    resolve_base_revs, short_change_hash, short_commit_description, short_commit_hash,
+    write_conflicted_refs_warning, Args, CommandError, CommandHelper, WorkspaceCommandHelper,
 };
 use crate::commands::CommandError::UserError;
-use crate::formatter::Formatter;
+use crate::diff_edit;
+use crate::formatter::{Formatter, PlainTextFormatter};
 use crate::graphlog::{AsciiGraphDrawer, Edge};
+use crate::patch;
+use crate::progress::GitProgressRenderer;
 use crate::template_parser::TemplateParser;
-use crate::templater::Template;
+use crate::templater::{format_timestamp, LazyCommit, Template};
 use crate::ui;
 use crate::ui::Ui;
 
@@ -70,9 +96,15 @@ enum Commands {
     Version(VersionArgs),
     Init(InitArgs),
     Checkout(CheckoutArgs),
+    Track(TrackArgs),
     Untrack(UntrackArgs),
     Files(FilesArgs),
     Print(PrintArgs),
+    Blame(BlameArgs),
+    Grep(GrepArgs),
+    Archive(ArchiveArgs),
+    FormatPatch(FormatPatchArgs),
+    Apply(ApplyArgs),
     Diff(DiffArgs),
     Show(ShowArgs),
     Status(StatusArgs),
@@ -86,9 +118,16 @@ enum Commands {
     Abandon(AbandonArgs),
     Edit(EditArgs),
     New(NewArgs),
+    Next(NextArgs),
+    Prev(PrevArgs),
     Move(MoveArgs),
     Squash(SquashArgs),
     Unsquash(UnsquashArgs),
+    Absorb(AbsorbArgs),
+    Fix(FixArgs),
+    Run(RunArgs),
+    #[clap(subcommand)]
+    Bisect(BisectCommands),
     Restore(RestoreArgs),
     Touchup(TouchupArgs),
     Split(SplitArgs),
@@ -103,7 +142,10 @@ enum Commands {
     /// arguments.
     Merge(NewArgs),
     Rebase(RebaseArgs),
+    Evolve(EvolveArgs),
     Backout(BackoutArgs),
+    Parallelize(ParallelizeArgs),
+    Sign(SignArgs),
     #[clap(subcommand)]
     Branch(BranchSubcommand),
     /// Undo an operation (shortcut for `jj op undo`)
@@ -113,7 +155,13 @@ enum Commands {
     Operation(OperationCommands),
     #[clap(subcommand)]
     Workspace(WorkspaceCommands),
+    #[clap(subcommand)]
+    Stash(StashCommands),
+    #[clap(subcommand)]
+    Resolutions(ResolutionsCommands),
     Sparse(SparseArgs),
+    Gc(GcArgs),
+    Fsck(FsckArgs),
     #[clap(subcommand)]
     Git(GitCommands),
     #[clap(subcommand)]
@@ -142,6 +190,18 @@ struct InitArgs {
     /// Path to a git repo the jj repo will be backed by
     #[clap(long, value_hint = clap::ValueHint::DirPath)]
     git_repo: Option<String>,
+    /// Read and write Git objects with gitoxide instead of libgit2
+    ///
+    /// Only available if jj was built with the `gitoxide` feature.
+    #[clap(long, requires = "backend")]
+    gitoxide: bool,
+    /// Create a repo without a working copy
+    ///
+    /// Commands that only read or exchange commits (such as `jj log`, `jj
+    /// diff --from A --to B`, and `jj git fetch/push`) work in a bare repo.
+    /// Commands that need a working copy fail.
+    #[clap(long)]
+    bare: bool,
 }
 
 /// Create a new, empty change and edit it in the working copy
@@ -161,6 +221,18 @@ struct CheckoutArgs {
     message: String,
 }
 
+/// Start tracking specified paths in the working copy
+///
+/// New files are tracked automatically unless `snapshot.auto-track` says
+/// otherwise. Use this command to track files that aren't tracked
+/// automatically. Ignored files are not tracked.
+#[derive(clap::Args, Clone, Debug)]
+struct TrackArgs {
+    /// Paths to track
+    #[clap(required = true, value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+}
+
 /// Stop tracking specified paths in the working copy
 #[derive(clap::Args, Clone, Debug)]
 struct UntrackArgs {
@@ -191,12 +263,113 @@ struct PrintArgs {
     path: String,
 }
 
+/// Show which revision last changed each line of a file
+///
+/// Each line is prefixed by the change id and commit id of the revision that
+/// introduced it. When a file was renamed, its history is followed across the
+/// rename if the revision that added it also removed a similar file.
+#[derive(clap::Args, Clone, Debug)]
+#[clap(visible_alias = "annotate")]
+struct BlameArgs {
+    /// The revision to show the file at
+    #[clap(long, short, default_value = "@")]
+    revision: String,
+    /// The file to show
+    #[clap(value_hint = clap::ValueHint::FilePath)]
+    path: String,
+}
+
+/// Search for a pattern in the files in a revision
+///
+/// Each matching line is printed as `path:line:content`. When searching more
+/// than one revision, each line is also prefixed by the commit id.
+#[derive(clap::Args, Clone, Debug)]
+struct GrepArgs {
+    /// The revisions to search in
+    #[clap(long, short, default_value = "@")]
+    revisions: String,
+    /// The regular expression to search for
+    pattern: String,
+    /// Only search in these paths
+    #[clap(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+}
+
+/// Write the files in a revision to an archive
+///
+/// The archive format is determined by the file name, which must end in
+/// `.tar`, `.tar.gz`, `.tgz`, or `.zip`. Use `--output-dir` to write the files
+/// to a plain directory instead.
+#[derive(clap::Args, Clone, Debug)]
+#[clap(group(ArgGroup::new("output_to").args(&["output", "output-dir"]).required(true)))]
+struct ArchiveArgs {
+    /// The revision to archive
+    #[clap(long, short, default_value = "@")]
+    revision: String,
+    /// The archive file to write
+    #[clap(long, short, value_hint = clap::ValueHint::FilePath)]
+    output: Option<PathBuf>,
+    /// Write the files to this directory, which must not exist or be empty
+    #[clap(long, value_hint = clap::ValueHint::DirPath)]
+    output_dir: Option<PathBuf>,
+    /// Only archive these paths
+    #[clap(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+}
+
+/// Export revisions as patches for sending by email
+///
+/// Each revision is written to a separate file in the Git patch format, with
+/// mail headers, so it can be sent with e.g. `git send-email` and applied with
+/// `git am`. The files are numbered in order, oldest revision first.
+#[derive(clap::Args, Clone, Debug)]
+struct FormatPatchArgs {
+    /// The revisions to export
+    #[clap(long, short, default_value = "@")]
+    revisions: String,
+    /// The directory to write the patch files to
+    #[clap(
+        long,
+        short,
+        default_value = ".",
+        conflicts_with = "stdout",
+        value_hint = clap::ValueHint::DirPath
+    )]
+    output_dir: PathBuf,
+    /// Print the patches instead of writing them to files
+    #[clap(long)]
+    stdout: bool,
+}
+
+/// Apply patches to the working copy
+///
+/// The patches can be plain unified diffs or emails with patches like the ones
+/// created by `jj format-patch` or `git format-patch`. Several emails can be
+/// concatenated in the same file (in mbox format).
+#[derive(clap::Args, Clone, Debug)]
+struct ApplyArgs {
+    /// The patch files to apply (standard input if none or `-`)
+    #[clap(value_hint = clap::ValueHint::FilePath)]
+    patches: Vec<PathBuf>,
+    /// Create a new revision for each patch, instead of modifying the
+    /// working-copy commit
+    ///
+    /// The author, date, and description of the revision are taken from the
+    /// email, if any. The new revisions are inserted between the working-copy
+    /// commit and its parents.
+    #[clap(long)]
+    commit: bool,
+}
+
 #[derive(clap::Args, Clone, Debug)]
-#[clap(group(ArgGroup::new("format").args(&["summary", "git", "color-words"])))]
+#[clap(group(ArgGroup::new("format").args(&["summary", "stat", "git", "color-words"])))]
 struct DiffFormatArgs {
     /// For each path, show only whether it was modified, added, or removed
     #[clap(long, short)]
     summary: bool,
+    /// Show a histogram of the number of added and removed lines in each path
+    #[clap(long)]
+    stat: bool,
     /// Show a Git-format diff
     #[clap(long)]
     git: bool,
@@ -216,6 +389,10 @@ struct DiffFormatArgs {
 /// given revisions. If either is left out, it defaults to the current checkout.
 /// For example, `jj diff --from main` shows the changes from "main" (perhaps a
 /// branch name) to the current checkout.
+///
+/// With the `--tool` option, or if `ui.diff-tool` is set and no other format is
+/// requested, the two sides of the diff are written to temporary directories
+/// and passed to the given external diff tool.
 #[derive(clap::Args, Clone, Debug)]
 struct DiffArgs {
     /// Show changes in this revision, compared to its parent(s)
@@ -232,6 +409,9 @@ struct DiffArgs {
     paths: Vec<String>,
     #[clap(flatten)]
     format: DiffFormatArgs,
+    /// Show the diff in this external diff tool
+    #[clap(long, conflicts_with = "format")]
+    tool: Option<String>,
 }
 
 /// Show commit description and changes in a revision
@@ -272,7 +452,15 @@ struct LogArgs {
     /// Show commits modifying the given paths
     #[clap(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
+    /// Limit number of revisions to show
+    ///
+    /// With `--reversed`, the newest revisions are selected before the order
+    /// is reversed.
+    #[clap(long, short = 'n')]
+    limit: Option<usize>,
     /// Show revisions in the opposite order (older revisions first)
+    ///
+    /// This can be made the default by setting `ui.log-reversed = true`.
     #[clap(long)]
     reversed: bool,
     /// Don't show the graph, show a flat list of revisions
@@ -452,6 +640,42 @@ struct NewArgs {
     /// The change description to use
     #[clap(long, short, default_value = "")]
     message: String,
+    /// Only report which paths would have conflicts, without creating the
+    /// change
+    #[clap(long)]
+    dry_run: bool,
+}
+
+/// Move the working copy to a child revision
+///
+/// By default, a new change is created on top of the child of the working
+/// copy's parent. That way, you can walk up a stack of changes while leaving
+/// the changes themselves untouched. With `--edit`, the child of the working
+/// copy commit is instead edited directly.
+#[derive(clap::Args, Clone, Debug)]
+struct NextArgs {
+    /// How many revisions to move forward
+    #[clap(default_value = "1")]
+    amount: usize,
+    /// Edit the child revision instead of creating a new change on top of it
+    #[clap(long)]
+    edit: bool,
+}
+
+/// Move the working copy to a parent revision
+///
+/// By default, a new change is created on top of the grandparent of the
+/// working copy. That way, you can walk down a stack of changes while leaving
+/// the changes themselves untouched. With `--edit`, the parent of the working
+/// copy commit is instead edited directly.
+#[derive(clap::Args, Clone, Debug)]
+struct PrevArgs {
+    /// How many revisions to move backward
+    #[clap(default_value = "1")]
+    amount: usize,
+    /// Edit the parent revision instead of creating a new change on top of it
+    #[clap(long)]
+    edit: bool,
 }
 
 /// Move changes from one revision into another
@@ -479,7 +703,10 @@ struct MoveArgs {
     #[clap(long, short)]
     interactive: bool,
     /// Move only changes to these paths (instead of all paths)
-    #[clap(conflicts_with = "interactive", value_hint = clap::ValueHint::AnyPath)]
+    ///
+    /// With `--interactive`, only changes to these paths are shown in the diff
+    /// editor.
+    #[clap(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
 }
 
@@ -502,7 +729,10 @@ struct SquashArgs {
     #[clap(long, short)]
     interactive: bool,
     /// Move only changes to these paths (instead of all paths)
-    #[clap(conflicts_with = "interactive", value_hint = clap::ValueHint::AnyPath)]
+    ///
+    /// With `--interactive`, only changes to these paths are shown in the diff
+    /// editor.
+    #[clap(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
 }
 
@@ -528,6 +758,125 @@ struct UnsquashArgs {
     interactive: bool,
 }
 
+/// Move changes into the ancestors that last modified the same lines
+///
+/// Each hunk of changes in the source revision is moved into the closest
+/// ancestor that modified the lines the hunk touches. Hunks that can't be
+/// attributed to a single ancestor (e.g. because they touch lines modified by
+/// several ancestors, or because they modify lines that no ancestor in the
+/// destination set modified) are left in the source revision. Descendants of
+/// the rewritten revisions are rebased.
+#[derive(clap::Args, Clone, Debug)]
+struct AbsorbArgs {
+    /// Source revision to absorb changes from
+    #[clap(long, default_value = "@")]
+    from: String,
+    /// Ancestors that changes may be moved into
+    ///
+    /// Only the linear chain of ancestors of the source revision's parent that
+    /// are in this set are considered.
+    #[clap(long, default_value = "remote_branches()..")]
+    into: String,
+    /// Absorb only changes to these paths (instead of all paths)
+    #[clap(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+}
+
+/// Update files with formatting fixes or other changes
+///
+/// Runs the tools configured in the `fix.tools` config table on the files
+/// modified in each of the given revisions, and rewrites the revisions with
+/// the result. Descendants of the rewritten revisions are rebased.
+///
+/// Each tool is configured with a command and the file name extensions it
+/// applies to. The command gets the file content on stdin and should write the
+/// fixed content to stdout. For example, `fix.tools.rustfmt = { command =
+/// ["rustfmt", "--emit", "stdout"], extensions = ["rs"] }`.
+#[derive(clap::Args, Clone, Debug)]
+struct FixArgs {
+    /// The revisions to fix
+    #[clap(long, short, default_value = "@")]
+    revisions: String,
+    /// Fix only these paths (instead of all paths)
+    #[clap(value_hint = clap::ValueHint::AnyPath)]
+    paths: Vec<String>,
+}
+
+/// Run a command on each of the given revisions
+///
+/// Each revision is checked out in a temporary directory, oldest revision
+/// first, and the command is run in that directory. The working copy is not
+/// affected, and any changes the command makes to the files are discarded. The
+/// commit id of the revision is available to the command in the
+/// `JJ_COMMIT_ID` environment variable.
+///
+/// For example, `jj run -r 'main..@' -- cargo test` runs the tests on every
+/// revision in the current stack.
+#[derive(clap::Args, Clone, Debug)]
+struct RunArgs {
+    /// The revisions to run the command on
+    #[clap(long, short, default_value = "@")]
+    revisions: String,
+    /// The command to run, followed by its arguments
+    #[clap(required = true, last = true)]
+    command: Vec<String>,
+}
+
+/// Find the first bad revision using binary search
+///
+/// Start by marking a bad revision with `jj bisect bad` and one or more good
+/// revisions with `jj bisect good`. The working copy is then moved to a
+/// revision between them. Keep marking the revision being tested as good or
+/// bad until the first bad revision has been found. Alternatively, use `jj
+/// bisect run` to test the revisions automatically.
+///
+/// The bisection state is stored in the repo until it's cleared with `jj bisect
+/// reset`.
+#[derive(Subcommand, Clone, Debug)]
+enum BisectCommands {
+    Good(BisectGoodArgs),
+    Bad(BisectBadArgs),
+    Run(BisectRunArgs),
+    Reset(BisectResetArgs),
+}
+
+/// Mark revisions as good
+#[derive(clap::Args, Clone, Debug)]
+struct BisectGoodArgs {
+    /// The revisions to mark (the revision being tested by default, or the
+    /// working-copy commit if no revision is being tested)
+    revisions: Vec<String>,
+}
+
+/// Mark a revision as bad
+#[derive(clap::Args, Clone, Debug)]
+struct BisectBadArgs {
+    /// The revision to mark (the revision being tested by default, or the
+    /// working-copy commit if no revision is being tested)
+    revision: Option<String>,
+}
+
+/// Find the first bad revision by running a command on each tested revision
+///
+/// The revisions are checked out in a temporary directory, like with `jj run`.
+/// The revision is good if the command exits successfully and bad otherwise.
+/// The working copy is not affected.
+#[derive(clap::Args, Clone, Debug)]
+struct BisectRunArgs {
+    /// The command to run, followed by its arguments
+    #[clap(
+        long,
+        required = true,
+        multiple_values = true,
+        allow_hyphen_values = true
+    )]
+    command: Vec<String>,
+}
+
+/// Clear the bisection state
+#[derive(clap::Args, Clone, Debug)]
+struct BisectResetArgs {}
+
 /// Restore paths from another revision
 ///
 /// That means that the paths get the same content in the destination (`--to`)
@@ -575,11 +924,19 @@ struct TouchupArgs {
 /// first revision. Once you close the editor, your edited content will replace
 /// the previous revision. The remaining changes will be put in a new revision
 /// on top. You will be asked to enter a change description for each.
+///
+/// With `--into <N>`, the diff editor is started again on the remaining
+/// changes until the revision has been split into N revisions. If you don't
+/// make any changes in one of the later rounds, all the remaining changes go
+/// into that revision and no further rounds are started.
 #[derive(clap::Args, Clone, Debug)]
 struct SplitArgs {
     /// The revision to split
     #[clap(long, short, default_value = "@")]
     revision: String,
+    /// The number of revisions to split the revision into
+    #[clap(long, default_value = "2", conflicts_with = "paths")]
+    into: usize,
     /// Put these paths in the first commit and don't run the diff editor
     #[clap(value_hint = clap::ValueHint::AnyPath)]
     paths: Vec<String>,
@@ -645,6 +1002,13 @@ struct SplitArgs {
 /// | B        | B
 /// |/         |/
 /// A          A
+///
+/// With `-i`, it opens an editor with the list of revisions that `-b` would
+/// rebase (the specified revision and its ancestors that are not ancestors of
+/// the destination), oldest first. Reorder the lines to reorder the revisions,
+/// and change `pick` to `squash` or `fixup` to fold a revision into the one
+/// above it, or to `drop` to remove it. The revisions must form a linear
+/// history. Descendants of the revisions are rebased as usual.
 #[derive(clap::Args, Clone, Debug)]
 #[clap(verbatim_doc_comment)]
 #[clap(group(ArgGroup::new("to_rebase").args(&["branch", "source", "revision"])))]
@@ -662,8 +1026,24 @@ struct RebaseArgs {
     /// The revision(s) to rebase onto
     #[clap(long, short, required = true)]
     destination: Vec<String>,
+    /// Edit the list of revisions to rebase in an editor
+    #[clap(long, short, conflicts_with_all = &["source", "revision"])]
+    interactive: bool,
+    /// Only report which rebased revisions would have new conflicts, without
+    /// rebasing them
+    #[clap(long, conflicts_with = "interactive")]
+    dry_run: bool,
 }
 
+/// Rebase revisions that were left on top of rewritten revisions
+///
+/// When automatic rebasing is disabled (with `--no-auto-rebase` or the
+/// `ui.auto-rebase` config setting), rewriting a revision leaves its
+/// descendants on top of the old version of the revision. This command rebases
+/// such descendants onto the latest versions of their parents.
+#[derive(clap::Args, Clone, Debug)]
+struct EvolveArgs {}
+
 /// Apply the reverse of a revision on top of another revision
 #[derive(clap::Args, Clone, Debug)]
 struct BackoutArgs {
@@ -677,6 +1057,34 @@ struct BackoutArgs {
     destination: Vec<String>,
 }
 
+/// Make a linear range of revisions siblings of each other
+///
+/// Each revision in the range is rebased onto the parent(s) of the first
+/// revision in the range, keeping only its own changes. Children of the last
+/// revision in the range become merge commits with all the parallelized
+/// revisions as parents. This fails if the changes in a revision overlap with
+/// the changes in the revisions before it in the range.
+#[derive(clap::Args, Clone, Debug)]
+struct ParallelizeArgs {
+    /// The revisions to parallelize (must form a linear range)
+    revisions: String,
+}
+
+/// Cryptographically sign revisions
+///
+/// Rewrites the revisions with a signature made by the backend configured in
+/// `signing.backend` (`gpg` or `ssh`). Set `signing.sign-all` to sign every
+/// commit as it is written instead.
+#[derive(clap::Args, Clone, Debug)]
+struct SignArgs {
+    /// The revision(s) to sign
+    #[clap(long, short, default_value = "@")]
+    revisions: Vec<String>,
+    /// The key to sign with, instead of `signing.key`
+    #[clap(long)]
+    key: Option<String>,
+}
+
 /// Manage branches.
 ///
 /// For information about branches, see
@@ -716,7 +1124,7 @@ enum BranchSubcommand {
     /// List branches and their targets
     ///
     /// A remote branch will be included only if its target is different from
-    /// the local target. For a conflicted branch (both local and remote), old
+    /// the local target or if it's untracked. For a conflicted branch (both local and remote), old
     /// target revisions are preceded by a "-" and new target revisions are
     /// preceded by a "+". For information about branches, see
     /// https://github.com/martinvonz/jj/blob/main/docs/branches.md.
@@ -738,6 +1146,28 @@ enum BranchSubcommand {
         #[clap(required = true)]
         names: Vec<String>,
     },
+
+    /// Start tracking remote branches
+    ///
+    /// Changes to a tracked remote branch are merged into the local branch of
+    /// the same name when fetching. If the local branch doesn't exist, it is
+    /// created at the remote branch's target. Remote branches are tracked by
+    /// default.
+    Track {
+        /// The remote branches to track, as `<branch>@<remote>`.
+        #[clap(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Stop tracking remote branches
+    ///
+    /// Changes to an untracked remote branch are still fetched but are no
+    /// longer merged into the local branch of the same name.
+    Untrack {
+        /// The remote branches to untrack, as `<branch>@<remote>`.
+        #[clap(required = true)]
+        names: Vec<String>,
+    },
 }
 
 /// Commands for working with the operation log
@@ -746,14 +1176,70 @@ enum BranchSubcommand {
 /// operation log, see https://github.com/martinvonz/jj/blob/main/docs/operation-log.md.
 #[derive(Subcommand, Clone, Debug)]
 enum OperationCommands {
+    Abandon(OperationAbandonArgs),
     Log(OperationLogArgs),
+    Show(OperationShowArgs),
     Undo(OperationUndoArgs),
     Restore(OperationRestoreArgs),
+    ExportView(OperationExportViewArgs),
+    ImportView(OperationImportViewArgs),
+}
+
+/// Abandon operations to shorten the operation log
+///
+/// The operations are removed from the operation log, so they can no longer
+/// be undone or restored to. The children of an abandoned operation become
+/// children of its parents.
+#[derive(clap::Args, Clone, Debug)]
+struct OperationAbandonArgs {
+    /// The operations to abandon
+    ///
+    /// Either a single operation, `..<operation>` for the operation and all its
+    /// ancestors, or `<from>..<to>` for the ancestors of `<to>` (including
+    /// itself) that aren't ancestors of `<from>`. The current operation and
+    /// the root operation can't be abandoned.
+    #[clap(required_unless_present = "expired")]
+    operations: Option<String>,
+    /// Abandon the operations older than the `operation.expire-after-days`
+    /// config
+    #[clap(long, conflicts_with = "operations")]
+    expired: bool,
 }
 
 /// Show the operation log
+///
+/// When filtering by user, host, or tag, operations that don't match are left
+/// out of the graph.
+#[derive(clap::Args, Clone, Debug)]
+struct OperationLogArgs {
+    /// Only show operations run by this user
+    #[clap(long)]
+    username: Option<String>,
+    /// Only show operations run on this host
+    #[clap(long)]
+    hostname: Option<String>,
+    /// Only show operations with this tag, given as `KEY=VALUE`, or as `KEY`
+    /// to match any value (can be repeated)
+    #[clap(long = "tag")]
+    tags: Vec<String>,
+    /// Render each operation using the given template (the syntax is not yet
+    /// documented and is likely to change)
+    #[clap(long, short = 'T')]
+    template: Option<String>,
+}
+
+/// Show what an operation changed
+///
+/// Compares the repo view at the end of the operation with the view at the
+/// end of its parent operation (the first parent for a merge of concurrent
+/// operations): the working-copy commits, the heads, and the branches, tags,
+/// and Git refs.
 #[derive(clap::Args, Clone, Debug)]
-struct OperationLogArgs {}
+struct OperationShowArgs {
+    /// The operation to show
+    #[clap(default_value = "@")]
+    operation: String,
+}
 
 /// Restore to the state at an operation
 #[derive(clap::Args, Clone, Debug)]
@@ -762,7 +1248,39 @@ struct OperationRestoreArgs {
     operation: String,
 }
 
+/// Export the current view to a file
+///
+/// The file has the heads, branches, tags, and working-copy commits of the
+/// repo, but not the state of the backing Git repo. It can be imported into
+/// another clone of the same store with `jj op import-view`.
+#[derive(clap::Args, Clone, Debug)]
+struct OperationExportViewArgs {
+    /// The file to write the view to
+    #[clap(value_hint = clap::ValueHint::FilePath)]
+    path: PathBuf,
+}
+
+/// Import a view exported with `jj op export-view`
+///
+/// The heads, branches, and tags of the repo are replaced by the ones from the
+/// file, as are the working-copy commits of workspaces that are in the file.
+/// Unlike merging operation logs, this doesn't keep any changes made in this
+/// repo since the view was exported. The import can be undone with `jj undo`.
+///
+/// The commits the view refers to must already be in the repo, e.g. by
+/// fetching them first.
+#[derive(clap::Args, Clone, Debug)]
+struct OperationImportViewArgs {
+    /// The file to read the view from
+    #[clap(value_hint = clap::ValueHint::FilePath)]
+    path: PathBuf,
+}
+
 /// Undo an operation
+///
+/// The operation doesn't need to be the latest one. Its changes to the repo
+/// view are reverted while the changes made by later operations are kept.
+/// Branches and tags that later operations changed again become conflicted.
 #[derive(clap::Args, Clone, Debug)]
 struct OperationUndoArgs {
     /// The operation to undo
@@ -776,6 +1294,8 @@ enum WorkspaceCommands {
     Add(WorkspaceAddArgs),
     Forget(WorkspaceForgetArgs),
     List(WorkspaceListArgs),
+    Root(WorkspaceRootArgs),
+    UpdateStale(WorkspaceUpdateStaleArgs),
 }
 
 /// Add a workspace
@@ -805,6 +1325,89 @@ struct WorkspaceForgetArgs {
 #[derive(clap::Args, Clone, Debug)]
 struct WorkspaceListArgs {}
 
+/// Show the root directory of the current workspace
+///
+/// The repo isn't loaded and the working copy isn't snapshotted, so this is
+/// fast enough to run from e.g. a shell prompt.
+#[derive(clap::Args, Clone, Debug)]
+struct WorkspaceRootArgs {}
+
+/// Update a workspace that has become stale
+///
+/// A workspace becomes stale when its working-copy commit is rewritten from
+/// another workspace (e.g. by `jj squash`). Commands refuse to snapshot a stale
+/// working copy, since that could lose changes made in it. This command updates
+/// the files on disk to the rewritten commit. Changes made on disk since the
+/// workspace was last updated are merged into the working-copy commit, possibly
+/// resulting in conflicts.
+#[derive(clap::Args, Clone, Debug)]
+struct WorkspaceUpdateStaleArgs {}
+
+/// Put working-copy changes aside and bring them back later
+///
+/// A stash is an ordinary change with a branch pointing to it. The branch name
+/// starts with `stash-` by default, which can be overridden by the
+/// `stash.branch-prefix` config setting.
+#[derive(Subcommand, Clone, Debug)]
+enum StashCommands {
+    Push(StashPushArgs),
+    Pop(StashPopArgs),
+    List(StashListArgs),
+}
+
+/// Move the working-copy changes into a stash
+///
+/// The current working-copy commit is kept as the stash, and a new, empty
+/// working-copy commit is created on top of its parent(s).
+#[derive(clap::Args, Clone, Debug)]
+struct StashPushArgs {
+    /// A name for the stash
+    ///
+    /// To override the default, which is the lowest unused number.
+    #[clap(long)]
+    name: Option<String>,
+    /// The change description to use for the stash
+    #[clap(long, short)]
+    message: Option<String>,
+}
+
+/// Move the changes from a stash into the working copy and drop the stash
+#[derive(clap::Args, Clone, Debug)]
+struct StashPopArgs {
+    /// The stash to pop (the most recent one by default)
+    name: Option<String>,
+}
+
+/// List stashes
+#[derive(clap::Args, Clone, Debug)]
+struct StashListArgs {}
+
+/// Manage recorded conflict resolutions
+///
+/// When `merge.reuse-resolutions` is enabled, resolving a conflict in the
+/// working copy records how each conflicting region was resolved. Merges (e.g.
+/// when rebasing) resolve the same conflicts the same way again.
+#[derive(Subcommand, Clone, Debug)]
+enum ResolutionsCommands {
+    List(ResolutionsListArgs),
+    Forget(ResolutionsForgetArgs),
+}
+
+/// List recorded conflict resolutions
+#[derive(clap::Args, Clone, Debug)]
+struct ResolutionsListArgs {}
+
+/// Forget recorded conflict resolutions
+#[derive(clap::Args, Clone, Debug)]
+struct ResolutionsForgetArgs {
+    /// The ids of the resolutions to forget (or unique prefixes of them)
+    #[clap(required_unless_present = "all")]
+    ids: Vec<String>,
+    /// Forget all recorded resolutions
+    #[clap(long, conflicts_with = "ids")]
+    all: bool,
+}
+
 /// Manage which paths from the current checkout are present in the working copy
 #[derive(clap::Args, Clone, Debug)]
 struct SparseArgs {
@@ -825,6 +1428,30 @@ struct SparseArgs {
     list: bool,
 }
 
+/// Remove objects that aren't reachable from any operation from the store
+///
+/// Commits, trees, and files that are reachable from any operation, including
+/// old operations that can be restored with `jj op restore`, are kept. Only
+/// the local backend supports this; use `git gc` for the Git backend.
+#[derive(clap::Args, Clone, Debug)]
+struct GcArgs {
+    /// Only report how much would be removed
+    #[clap(long)]
+    dry_run: bool,
+    /// Keep objects written less than this many seconds ago, since they may
+    /// belong to commands that are still running
+    #[clap(long, value_name = "SECONDS", default_value = "3600")]
+    min_age: u64,
+}
+
+/// Check that the objects in the store are intact
+///
+/// Verifies that each commit, tree, and file that is reachable from any
+/// operation is present and matches its ID, and lists the commits that contain
+/// any that aren't.
+#[derive(clap::Args, Clone, Debug)]
+struct FsckArgs {}
+
 /// Commands for working with the underlying Git repo
 ///
 /// For a comparison with Git, including a table of commands, see
@@ -876,6 +1503,21 @@ struct GitFetchArgs {
     /// The remote to fetch from (only named remotes are supported)
     #[clap(long, default_value = "origin")]
     remote: String,
+    /// Fetch only the branches matching this refspec (can be repeated)
+    ///
+    /// A refspec is a branch name, which may contain a `*`, optionally
+    /// followed by `:` and the name to give the remote-tracking branch (e.g.
+    /// `main:upstream`). Names starting with `refs/` are full ref names.
+    /// Overrides the refspecs configured in `git.remotes.<name>.fetch`.
+    #[clap(long)]
+    refspec: Vec<String>,
+    /// Fetch only this many commits from the tip of each branch
+    ///
+    /// The commits at the boundary are treated as root commits. Fetching again
+    /// with a larger depth fetches more of the history. Requires `git` to be
+    /// installed.
+    #[clap(long)]
+    depth: Option<u32>,
 }
 
 /// Create a new repo backed by a clone of a Git repo
@@ -889,6 +1531,13 @@ struct GitCloneArgs {
     /// The directory to write the Jujutsu repo to
     #[clap(value_hint = clap::ValueHint::DirPath)]
     destination: Option<String>,
+    /// Clone only this many commits from the tip of each branch
+    ///
+    /// The commits at the boundary are treated as root commits. Use `jj git
+    /// fetch --depth` to fetch more of the history later. Requires `git` to be
+    /// installed.
+    #[clap(long)]
+    depth: Option<u32>,
 }
 
 /// Push to a Git remote
@@ -897,20 +1546,51 @@ struct GitCloneArgs {
 /// specific branch. Use `--all` to push all branches. Use `--change` to
 /// generate a branch name based on a specific commit's change ID.
 #[derive(clap::Args, Clone, Debug)]
-#[clap(group(ArgGroup::new("what").args(&["branch", "all", "change"])))]
+#[clap(group(ArgGroup::new("what").args(&["branch", "all", "change", "refspec"])))]
 struct GitPushArgs {
     /// The remote to push to (only named remotes are supported)
-    #[clap(long, default_value = "origin")]
-    remote: String,
+    ///
+    /// Defaults to the remote tracked by the branch given with `--branch` if
+    /// it tracks exactly one remote, and to "origin" otherwise.
+    #[clap(long)]
+    remote: Option<String>,
     /// Push only this branch
     #[clap(long)]
     branch: Option<String>,
     /// Push all branches
     #[clap(long)]
     all: bool,
-    /// Push this commit by creating a branch based on its change ID
+    /// Push these commits by creating branches based on their change IDs (can
+    /// be repeated)
+    ///
+    /// The value is a revset, so e.g. `--change 'main..@'` pushes a whole
+    /// stack of changes. The branches are named after the change ID prefix,
+    /// with the prefix configured in `push.branch-prefix` (`push-` by
+    /// default).
+    #[clap(long)]
+    change: Vec<String>,
+    /// Push the branches matching this refspec (can be repeated)
+    ///
+    /// A refspec is a branch name, which may contain a `*`, optionally
+    /// followed by `:` and the name of the branch to update on the remote
+    /// (e.g. `main:trunk`), or a full ref name starting with `refs/`.
+    /// Overrides the refspecs configured in `git.remotes.<name>.push`.
+    #[clap(long)]
+    refspec: Vec<String>,
+    /// Push even if the branches have moved on the remote since they were
+    /// last fetched
+    ///
+    /// By default, the push is refused if a branch on the remote doesn't point
+    /// where its remote-tracking branch does, so changes pushed by others
+    /// aren't overwritten.
+    #[clap(long)]
+    force: bool,
+    /// Push commits even if they have conflicts
+    ///
+    /// The conflicts are preserved, so they can be resolved by someone who
+    /// fetches the commits with jj.
     #[clap(long)]
-    change: Option<String>,
+    allow_conflicts: bool,
     /// Only display what will change on the remote
     #[clap(long)]
     dry_run: bool,
@@ -980,6 +1660,8 @@ enum DebugCommands {
     #[clap(name = "reindex")]
     ReIndex(DebugReIndexArgs),
     Operation(DebugOperationArgs),
+    #[clap(name = "migrate-store")]
+    MigrateStore(DebugMigrateStoreArgs),
 }
 
 /// Print a command-line-completion script
@@ -1047,6 +1729,20 @@ struct DebugOperationArgs {
     operation: String,
 }
 
+/// Convert the repo to a different commit backend
+///
+/// All commits are copied to a new store and the operation log is rewritten to
+/// refer to the new commit ids. Change ids and the operation history are
+/// preserved, but commit ids change, and commit signatures are dropped since
+/// they're no longer valid. Converting to the Git backend creates an internal
+/// Git repo, like `jj init --git` does.
+#[derive(clap::Args, Clone, Debug)]
+struct DebugMigrateStoreArgs {
+    /// The backend to convert the repo to
+    #[clap(long, value_parser = ["local", "git"])]
+    backend: String,
+}
+
 fn add_to_git_exclude(ui: &mut Ui, git_repo: &git2::Repository) -> Result<(), CommandError> {
     let exclude_file_path = git_repo.path().join("info").join("exclude");
     if exclude_file_path.exists() {
@@ -1099,6 +1795,11 @@ fn cmd_init(ui: &mut Ui, command: &CommandHelper, args: &InitArgs) -> Result<(),
             "'--repository' cannot be used with 'init'".to_string(),
         ));
     }
+    if args.gitoxide && !cfg!(feature = "gitoxide") {
+        return Err(CommandError::UserError(
+            "jj was built without gitoxide support (the `gitoxide` feature)".to_string(),
+        ));
+    }
     let wc_path = ui.cwd().join(&args.destination);
     if wc_path.exists() {
         assert!(wc_path.is_dir());
@@ -1107,10 +1808,31 @@ fn cmd_init(ui: &mut Ui, command: &CommandHelper, args: &InitArgs) -> Result<(),
     }
     let wc_path = wc_path.canonicalize().unwrap();
 
-    if let Some(git_store_str) = &args.git_repo {
-        let mut git_store_path = ui.cwd().join(git_store_str);
-        if !git_store_path.ends_with(".git") {
-            git_store_path = git_store_path.join(".git");
+    // With `--git` in an existing Git checkout, share the working copy with it
+    let git_repo_arg = match &args.git_repo {
+        Some(git_store_str) => Some(ui.cwd().join(git_store_str)),
+        None if args.git && !args.bare && wc_path.join(".git").is_dir() => Some(wc_path.clone()),
+        None => None,
+    };
+    if let Some(mut git_store_path) = git_repo_arg {
+        // A linked worktree's Git dir only has the worktree's HEAD and index; the
+        // objects and refs are in the common dir of the main repo.
+        let mut worktree_name = None;
+        let linked_worktree_repo = git2::Repository::open(&git_store_path)
+            .ok()
+            .filter(|git_repo| git_repo.is_worktree());
+        if let Some(git_repo) = linked_worktree_repo {
+            worktree_name = git2::Worktree::open_from_repository(&git_repo)
+                .ok()
+                .and_then(|worktree| worktree.name().map(str::to_owned));
+            // The `commondir` file has the path of the common dir relative to the
+            // worktree's Git dir
+            git_store_path = match fs::read_to_string(git_repo.path().join("commondir")) {
+                Ok(common_dir) => git_repo.path().join(common_dir.trim_end()),
+                Err(_) => git_repo.path().to_path_buf(),
+            };
+        } else if !git_store_path.ends_with(".git") {
+            git_store_path = git_store_path.join(".git");
         }
         git_store_path = git_store_path.canonicalize().unwrap();
         // If the git repo is inside the workspace, use a relative path to it so the
@@ -1121,38 +1843,104 @@ fn cmd_init(ui: &mut Ui, command: &CommandHelper, args: &InitArgs) -> Result<(),
                 .join("..")
                 .join(relative_path);
         }
-        let (workspace, repo) =
-            Workspace::init_external_git(ui.settings(), &wc_path, &git_store_path)?;
-        let git_repo = repo.store().git_repo().unwrap();
-        let mut workspace_command = command.for_loaded_repo(ui, workspace, repo)?;
-        if workspace_command.working_copy_shared_with_git() {
-            add_to_git_exclude(ui, &git_repo)?;
-        } else {
+        if args.bare {
+            let (workspace, repo) = Workspace::init_bare(ui.settings(), &wc_path, |store_path| {
+                init_git_backend(args.gitoxide, store_path, Some(git_store_path.as_path()))
+            })?;
+            let git_repo = repo.store().git_repo().unwrap();
+            let mut workspace_command = command.for_loaded_repo(ui, workspace, repo)?;
             let mut tx = workspace_command.start_transaction("import git refs");
             git::import_refs(tx.mut_repo(), &git_repo)?;
-            if let Some(git_head_id) = tx.mut_repo().view().git_head() {
-                let git_head_commit = tx.mut_repo().store().get_commit(&git_head_id)?;
-                tx.mut_repo().check_out(
-                    workspace_command.workspace_id(),
-                    ui.settings(),
-                    &git_head_commit,
-                );
-            }
+            git::import_worktree_heads(tx.mut_repo(), &git_repo)?;
             if tx.mut_repo().has_changes() {
                 workspace_command.finish_transaction(ui, tx)?;
             }
+        } else {
+            let (workspace, repo) =
+                Workspace::init_with_backend(ui.settings(), &wc_path, |store_path| {
+                    init_git_backend(args.gitoxide, store_path, Some(git_store_path.as_path()))
+                })?;
+            let git_repo = repo.store().git_repo().unwrap();
+            let mut workspace_command = command.for_loaded_repo(ui, workspace, repo)?;
+            if workspace_command.working_copy_shared_with_git() {
+                add_to_git_exclude(ui, &git_repo)?;
+            } else {
+                let mut tx = workspace_command.start_transaction("import git refs");
+                git::import_refs(tx.mut_repo(), &git_repo)?;
+                let worktree_heads = git::import_worktree_heads(tx.mut_repo(), &git_repo)?;
+                // Start from the HEAD of the worktree the user pointed us at
+                let checkout_id = match &worktree_name {
+                    Some(name) => worktree_heads.get(name).cloned(),
+                    None => tx.mut_repo().view().git_head(),
+                };
+                if let Some(git_head_id) = checkout_id {
+                    let git_head_commit = tx.mut_repo().store().get_commit(&git_head_id)?;
+                    tx.mut_repo().check_out(
+                        workspace_command.workspace_id(),
+                        ui.settings(),
+                        &git_head_commit,
+                    )?;
+                }
+                if tx.mut_repo().has_changes() {
+                    workspace_command.finish_transaction(ui, tx)?;
+                }
+            }
+        }
+    } else if args.bare {
+        if args.git {
+            Workspace::init_bare(ui.settings(), &wc_path, |store_path| {
+                init_git_backend(args.gitoxide, store_path, None)
+            })?;
+        } else {
+            Workspace::init_bare(ui.settings(), &wc_path, |store_path| {
+                Box::new(LocalBackend::init(store_path))
+            })?;
         }
     } else if args.git {
-        Workspace::init_internal_git(ui.settings(), &wc_path)?;
+        Workspace::init_with_backend(ui.settings(), &wc_path, |store_path| {
+            init_git_backend(args.gitoxide, store_path, None)
+        })?;
     } else {
         Workspace::init_local(ui.settings(), &wc_path)?;
     };
     let cwd = ui.cwd().canonicalize().unwrap();
     let relative_wc_path = ui::relative_path(&cwd, &wc_path);
-    writeln!(ui, "Initialized repo in \"{}\"", relative_wc_path.display())?;
+    if args.bare {
+        writeln!(
+            ui,
+            "Initialized bare repo in \"{}\"",
+            relative_wc_path.display()
+        )?;
+    } else {
+        writeln!(ui, "Initialized repo in \"{}\"", relative_wc_path.display())?;
+    }
     Ok(())
 }
 
+/// Creates the backend for a new Git-backed repo, using the Git repo at
+/// `git_repo_path` if given, or else a new one inside the store.
+fn init_git_backend(
+    use_gitoxide: bool,
+    store_path: &Path,
+    git_repo_path: Option<&Path>,
+) -> Box<dyn Backend> {
+    if use_gitoxide {
+        #[cfg(feature = "gitoxide")]
+        return match git_repo_path {
+            Some(git_repo_path) => {
+                Box::new(GitoxideBackend::init_external(store_path, git_repo_path))
+            }
+            None => Box::new(GitoxideBackend::init_internal(store_path)),
+        };
+        #[cfg(not(feature = "gitoxide"))]
+        panic!("jj was built without gitoxide support");
+    }
+    match git_repo_path {
+        Some(git_repo_path) => Box::new(GitBackend::init_external(store_path, git_repo_path)),
+        None => Box::new(GitBackend::init_internal(store_path)),
+    }
+}
+
 fn cmd_checkout(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -1181,7 +1969,7 @@ fn cmd_checkout(
                     target.tree_id().clone(),
                 )
                 .set_description(args.message.clone());
-                let new_commit = commit_builder.write_to_repo(tx.mut_repo());
+                let new_commit = commit_builder.try_write_to_repo(tx.mut_repo())?;
                 tx.mut_repo().edit(workspace_id, &new_commit);
             }
             workspace_command.finish_transaction(ui, tx)?;
@@ -1195,13 +1983,38 @@ fn cmd_checkout(
             target.tree_id().clone(),
         )
         .set_description(args.message.clone());
-        let new_commit = commit_builder.write_to_repo(tx.mut_repo());
+        let new_commit = commit_builder.try_write_to_repo(tx.mut_repo())?;
         tx.mut_repo().edit(workspace_id, &new_commit);
         workspace_command.finish_transaction(ui, tx)?;
     }
     Ok(())
 }
 
+fn cmd_track(ui: &mut Ui, command: &CommandHelper, args: &TrackArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+
+    let mut tx = workspace_command.start_transaction("track paths");
+    let base_ignores = workspace_command.base_ignores();
+    let (mut locked_working_copy, wc_commit) = workspace_command.start_working_copy_mutation()?;
+    let new_tree_id = locked_working_copy.track(base_ignores, matcher.as_ref())?;
+    if &new_tree_id == wc_commit.tree_id() {
+        locked_working_copy.discard();
+        ui.write("Nothing changed.\n")?;
+        return Ok(());
+    }
+    CommitBuilder::for_rewrite_from(ui.settings(), &wc_commit)
+        .set_tree(new_tree_id)
+        .try_write_to_repo(tx.mut_repo())?;
+    let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
+    if num_rebased > 0 {
+        writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
+    }
+    let repo = tx.commit();
+    locked_working_copy.finish(repo.op_id().clone());
+    Ok(())
+}
+
 fn cmd_untrack(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -1256,7 +2069,7 @@ fn cmd_untrack(
     }
     CommitBuilder::for_rewrite_from(ui.settings(), &wc_commit)
         .set_tree(new_tree_id)
-        .write_to_repo(tx.mut_repo());
+        .try_write_to_repo(tx.mut_repo())?;
     let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())?;
     if num_rebased > 0 {
         writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
@@ -1267,7 +2080,7 @@ fn cmd_untrack(
 }
 
 fn cmd_files(ui: &mut Ui, command: &CommandHelper, args: &FilesArgs) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
+    let workspace_command = command.workspace_helper_for_paths(ui, &args.paths)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
     let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
     for (name, _value) in commit.tree().entries_matching(matcher.as_ref()) {
@@ -1292,7 +2105,14 @@ fn cmd_print(ui: &mut Ui, command: &CommandHelper, args: &PrintArgs) -> Result<(
         Some(TreeValue::Conflict(id)) => {
             let conflict = repo.store().read_conflict(&path, &id)?;
             let mut contents = vec![];
-            conflicts::materialize_conflict(repo.store(), &path, &conflict, &mut contents).unwrap();
+            conflicts::materialize_conflict(
+                repo.store(),
+                &path,
+                &conflict,
+                ui.settings().conflict_marker_style(),
+                &mut contents,
+            )
+            .unwrap();
             ui.stdout_formatter().write_all(&contents)?;
         }
         _ => {
@@ -1304,138 +2124,849 @@ fn cmd_print(ui: &mut Ui, command: &CommandHelper, args: &PrintArgs) -> Result<(
     Ok(())
 }
 
-fn show_color_words_diff_hunks(
-    left: &[u8],
-    right: &[u8],
-    formatter: &mut dyn Formatter,
-) -> io::Result<()> {
-    let num_context_lines = 3;
-    let mut context = VecDeque::new();
-    // Have we printed "..." for any skipped context?
-    let mut skipped_context = false;
-    // Are the lines in `context` to be printed before the next modified line?
-    let mut context_before = true;
-    for diff_line in files::diff(left, right) {
-        if diff_line.is_unmodified() {
-            context.push_back(diff_line.clone());
-            if context.len() > num_context_lines {
-                if context_before {
-                    context.pop_front();
-                } else {
-                    context.pop_back();
+fn cmd_blame(ui: &mut Ui, command: &CommandHelper, args: &BlameArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let path = ui.parse_file_path(workspace_command.workspace_root(), &args.path)?;
+    let repo = workspace_command.repo();
+    let file_id = match commit.tree().path_value(&path) {
+        None => {
+            return Err(CommandError::UserError("No such path".to_string()));
+        }
+        Some(TreeValue::Normal { id, .. }) => id,
+        Some(TreeValue::Conflict(_)) => {
+            return Err(CommandError::UserError(
+                "Can't show line origins of a conflicted file".to_string(),
+            ));
+        }
+        _ => {
+            return Err(CommandError::UserError(
+                "Path exists but is not a file".to_string(),
+            ));
+        }
+    };
+    let content = read_file_content(repo.store(), &path, &file_id)?;
+    let origins = blame_lines(repo.as_repo_ref(), commit, path, file_id, content.clone())?;
+    let line_number_width = origins.len().to_string().len();
+    for (i, (line, origin)) in content
+        .split_inclusive(|b| *b == b'\n')
+        .zip_eq(&origins)
+        .enumerate()
+    {
+        write!(
+            ui,
+            "{} {} {:>width$}: ",
+            &origin.change_id().hex()[0..12],
+            short_commit_hash(origin.id()),
+            i + 1,
+            width = line_number_width
+        )?;
+        ui.stdout_formatter().write_all(line)?;
+        if !line.ends_with(b"\n") {
+            writeln!(ui)?;
+        }
+    }
+    Ok(())
+}
+
+fn read_file_content(
+    store: &Arc<Store>,
+    path: &RepoPath,
+    id: &FileId,
+) -> Result<Vec<u8>, CommandError> {
+    let mut content = vec![];
+    store.read_file(path, id)?.read_to_end(&mut content)?;
+    Ok(content)
+}
+
+/// A version of a file whose lines are not all attributed to a commit yet.
+struct BlameCandidate {
+    commit: Commit,
+    file_id: FileId,
+    content: Vec<u8>,
+    /// The line number in the blamed file of each line of `content`, or `None`
+    /// if the line isn't in the blamed file or has already been attributed
+    lines: Vec<Option<usize>>,
+}
+
+/// Finds the commit that introduced each line of a file. The ancestors of
+/// `commit` are visited in index order, newest first, so each commit is only
+/// visited once, after all its descendants, and only while it still has
+/// unattributed lines.
+fn blame_lines(
+    repo: RepoRef,
+    commit: Commit,
+    path: RepoPath,
+    file_id: FileId,
+    content: Vec<u8>,
+) -> Result<Vec<Commit>, CommandError> {
+    let num_lines = count_lines(&content);
+    let mut origins: Vec<Option<Commit>> = vec![None; num_lines];
+    let mut candidates = BTreeMap::new();
+    candidates.insert(
+        (repo.index().commit_id_to_pos(commit.id()).unwrap(), path),
+        BlameCandidate {
+            commit,
+            file_id,
+            content,
+            lines: (0..num_lines).map(Some).collect(),
+        },
+    );
+    while let Some(key) = candidates.keys().next_back().cloned() {
+        let mut candidate = candidates.remove(&key).unwrap();
+        let (_, path) = key;
+        for parent in candidate.commit.parents() {
+            if candidate.lines.iter().all(Option::is_none) {
+                break;
+            }
+            let (parent_path, parent_file_id) =
+                match find_blame_parent_file(repo, &candidate, &parent, &path)? {
+                    Some(parent_file) => parent_file,
+                    None => continue,
+                };
+            let parent_content;
+            let mut parent_lines;
+            if parent_file_id == candidate.file_id {
+                parent_content = candidate.content.clone();
+                parent_lines = std::mem::take(&mut candidate.lines);
+                candidate.lines = vec![None; parent_lines.len()];
+            } else {
+                parent_content = read_file_content(repo.store(), &parent_path, &parent_file_id)?;
+                parent_lines = vec![None; count_lines(&parent_content)];
+                let diff = Diff::for_tokenizer(
+                    &[&parent_content, &candidate.content],
+                    &diff::find_line_ranges,
+                );
+                let mut parent_line = 0;
+                let mut line = 0;
+                for hunk in diff.hunks() {
+                    match hunk {
+                        DiffHunk::Matching(content) => {
+                            for _ in 0..count_lines(content) {
+                                parent_lines[parent_line] = candidate.lines[line].take();
+                                parent_line += 1;
+                                line += 1;
+                            }
+                        }
+                        DiffHunk::Different(contents) => {
+                            parent_line += count_lines(contents[0]);
+                            line += count_lines(contents[1]);
+                        }
+                    }
                 }
-                if !context_before {
-                    for line in &context {
-                        show_color_words_diff_line(formatter, line)?;
+            }
+            if parent_lines.iter().all(Option::is_none) {
+                continue;
+            }
+            let parent_key = (
+                repo.index().commit_id_to_pos(parent.id()).unwrap(),
+                parent_path,
+            );
+            match candidates.get_mut(&parent_key) {
+                Some(existing) => {
+                    // Another child already passed lines to this version of the file
+                    for (existing_line, line) in existing.lines.iter_mut().zip(parent_lines) {
+                        if existing_line.is_none() {
+                            *existing_line = line;
+                        }
                     }
-                    context.clear();
-                    context_before = true;
                 }
-                if !skipped_context {
-                    formatter.write_bytes(b"    ...\n")?;
-                    skipped_context = true;
+                None => {
+                    candidates.insert(
+                        parent_key,
+                        BlameCandidate {
+                            commit: parent,
+                            file_id: parent_file_id,
+                            content: parent_content,
+                            lines: parent_lines,
+                        },
+                    );
                 }
             }
-        } else {
-            for line in &context {
-                show_color_words_diff_line(formatter, line)?;
-            }
-            context.clear();
-            show_color_words_diff_line(formatter, &diff_line)?;
-            context_before = false;
-            skipped_context = false;
         }
-    }
-    if !context_before {
-        for line in &context {
-            show_color_words_diff_line(formatter, line)?;
+        // The remaining lines were introduced by this commit
+        for line in candidate.lines.iter().flatten() {
+            origins[*line] = Some(candidate.commit.clone());
         }
     }
+    Ok(origins.into_iter().map(Option::unwrap).collect())
+}
 
-    // If the last diff line doesn't end with newline, add it.
-    let no_hunk = left.is_empty() && right.is_empty();
-    let any_last_newline = left.ends_with(b"\n") || right.ends_with(b"\n");
-    if !skipped_context && !no_hunk && !any_last_newline {
-        formatter.write_bytes(b"\n")?;
+/// Finds the version of the candidate's file in the parent commit. If the file
+/// doesn't exist in the parent, it's considered renamed from the file removed
+/// in the candidate's commit that has the most lines in common with it.
+fn find_blame_parent_file(
+    repo: RepoRef,
+    candidate: &BlameCandidate,
+    parent: &Commit,
+    path: &RepoPath,
+) -> Result<Option<(RepoPath, FileId)>, CommandError> {
+    let parent_tree = parent.tree();
+    match parent_tree.path_value(path) {
+        Some(TreeValue::Normal { id, .. }) => return Ok(Some((path.clone(), id))),
+        Some(_) => return Ok(None),
+        None => {}
+    }
+    let mut best_match = None;
+    let mut best_num_common_lines = 0;
+    for (removed_path, value_diff) in parent_tree.diff(&candidate.commit.tree(), &EverythingMatcher)
+    {
+        if let tree::Diff::Removed(TreeValue::Normal { id, .. }) = value_diff {
+            let removed_content = read_file_content(repo.store(), &removed_path, &id)?;
+            let diff = Diff::for_tokenizer(
+                &[&removed_content, &candidate.content],
+                &diff::find_line_ranges,
+            );
+            let num_common_lines: usize = diff
+                .hunks()
+                .map(|hunk| match hunk {
+                    DiffHunk::Matching(content) => count_lines(content),
+                    DiffHunk::Different(_) => 0,
+                })
+                .sum();
+            if num_common_lines > best_num_common_lines {
+                best_num_common_lines = num_common_lines;
+                best_match = Some((removed_path, id));
+            }
+        }
     }
+    Ok(best_match)
+}
 
+fn cmd_grep(ui: &mut Ui, command: &CommandHelper, args: &GrepArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper_for_paths(ui, &args.paths)?;
+    let commits = workspace_command.resolve_revset(&args.revisions)?;
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    let regex = regex::bytes::Regex::new(&args.pattern)
+        .map_err(|err| UserError(format!("Invalid regular expression: {}", err)))?;
+    let repo = workspace_command.repo();
+    for commit in &commits {
+        for (path, value) in commit.tree().entries_matching(matcher.as_ref()) {
+            let id = match value {
+                TreeValue::Normal { id, .. } => id,
+                _ => continue,
+            };
+            let content = read_file_content(repo.store(), &path, &id)?;
+            if content.is_empty() {
+                continue;
+            }
+            let lines = content
+                .strip_suffix(b"\n")
+                .unwrap_or(&content)
+                .split(|b| *b == b'\n');
+            for (i, line) in lines.enumerate() {
+                if !regex.is_match(line) {
+                    continue;
+                }
+                if commits.len() > 1 {
+                    write!(ui, "{}:", short_commit_hash(commit.id()))?;
+                }
+                write!(
+                    ui,
+                    "{}:{}:",
+                    workspace_command.format_file_path(&path),
+                    i + 1
+                )?;
+                ui.stdout_formatter().write_all(line)?;
+                writeln!(ui)?;
+            }
+        }
+    }
     Ok(())
 }
 
-fn show_color_words_diff_line(
-    formatter: &mut dyn Formatter,
-    diff_line: &DiffLine,
-) -> io::Result<()> {
-    if diff_line.has_left_content {
-        formatter.add_label(String::from("removed"))?;
-        formatter.write_bytes(format!("{:>4}", diff_line.left_line_number).as_bytes())?;
-        formatter.remove_label()?;
-        formatter.write_bytes(b" ")?;
-    } else {
-        formatter.write_bytes(b"     ")?;
+/// Where `jj archive` writes the files.
+enum ArchiveWriter {
+    Tar(tar::Builder<fs::File>),
+    TarGz(tar::Builder<flate2::write::GzEncoder<fs::File>>),
+    Zip(zip::ZipWriter<fs::File>),
+    Dir(PathBuf),
+}
+
+enum ArchiveEntry {
+    File { content: Vec<u8>, executable: bool },
+    Symlink(String),
+}
+
+impl ArchiveWriter {
+    fn create(path: &Path) -> Result<Self, CommandError> {
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let create_file = || {
+            fs::File::create(path)
+                .map_err(|err| UserError(format!("Failed to create {}: {}", path.display(), err)))
+        };
+        if file_name.ends_with(".tar") {
+            Ok(ArchiveWriter::Tar(tar::Builder::new(create_file()?)))
+        } else if file_name.ends_with(".tar.gz") || file_name.ends_with(".tgz") {
+            let encoder = flate2::write::GzEncoder::new(create_file()?, Default::default());
+            Ok(ArchiveWriter::TarGz(tar::Builder::new(encoder)))
+        } else if file_name.ends_with(".zip") {
+            Ok(ArchiveWriter::Zip(zip::ZipWriter::new(create_file()?)))
+        } else {
+            Err(UserError(format!(
+                "Can't tell the archive format of {}; use a name ending in .tar, .tar.gz, \
+                 .tgz, or .zip",
+                path.display()
+            )))
+        }
     }
-    if diff_line.has_right_content {
-        formatter.add_label(String::from("added"))?;
-        formatter.write_bytes(format!("{:>4}", diff_line.right_line_number).as_bytes())?;
-        formatter.remove_label()?;
-        formatter.write_bytes(b": ")?;
-    } else {
-        formatter.write_bytes(b"    : ")?;
+
+    fn create_dir(path: &Path) -> Result<Self, CommandError> {
+        if path.exists() && fs::read_dir(path)?.next().is_some() {
+            return Err(UserError(format!(
+                "Output directory {} is not empty",
+                path.display()
+            )));
+        }
+        fs::create_dir_all(path)?;
+        Ok(ArchiveWriter::Dir(path.to_path_buf()))
     }
-    for hunk in &diff_line.hunks {
-        match hunk {
-            DiffHunk::Matching(data) => {
-                formatter.write_bytes(data)?;
+
+    fn add(
+        &mut self,
+        path: &RepoPath,
+        entry: &ArchiveEntry,
+        mtime: u64,
+    ) -> Result<(), CommandError> {
+        let name = path.to_internal_file_string();
+        match self {
+            ArchiveWriter::Tar(builder) => append_tar_entry(builder, &name, entry, mtime)?,
+            ArchiveWriter::TarGz(builder) => append_tar_entry(builder, &name, entry, mtime)?,
+            ArchiveWriter::Zip(writer) => {
+                let options = zip::write::FileOptions::default();
+                let result = match entry {
+                    ArchiveEntry::File {
+                        content,
+                        executable,
+                    } => {
+                        let mode = if *executable { 0o755 } else { 0o644 };
+                        writer
+                            .start_file(name, options.unix_permissions(mode))
+                            .and_then(|()| Ok(writer.write_all(content)?))
+                    }
+                    ArchiveEntry::Symlink(target) => writer.add_symlink(name, target, options),
+                };
+                result.map_err(|err| UserError(format!("Failed to write archive: {}", err)))?;
             }
-            DiffHunk::Different(data) => {
-                let before = data[0];
-                let after = data[1];
-                if !before.is_empty() {
-                    formatter.add_label(String::from("removed"))?;
-                    formatter.write_bytes(before)?;
-                    formatter.remove_label()?;
-                }
-                if !after.is_empty() {
-                    formatter.add_label(String::from("added"))?;
-                    formatter.write_bytes(after)?;
-                    formatter.remove_label()?;
+            ArchiveWriter::Dir(dir) => {
+                let disk_path = path.to_fs_path(dir);
+                fs::create_dir_all(disk_path.parent().unwrap())?;
+                match entry {
+                    ArchiveEntry::File {
+                        content,
+                        executable,
+                    } => {
+                        fs::write(&disk_path, content)?;
+                        #[cfg(unix)]
+                        if *executable {
+                            use std::os::unix::fs::PermissionsExt;
+                            fs::set_permissions(&disk_path, fs::Permissions::from_mode(0o755))?;
+                        }
+                        #[cfg(not(unix))]
+                        let _ = executable;
+                    }
+                    #[cfg(unix)]
+                    ArchiveEntry::Symlink(target) => {
+                        std::os::unix::fs::symlink(target, &disk_path)?;
+                    }
+                    #[cfg(not(unix))]
+                    ArchiveEntry::Symlink(target) => {
+                        fs::write(&disk_path, target)?;
+                    }
                 }
             }
         }
+        Ok(())
     }
 
-    Ok(())
-}
-
-fn cmd_diff(ui: &mut Ui, command: &CommandHelper, args: &DiffArgs) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
-    let from_tree;
-    let to_tree;
-    if args.from.is_some() || args.to.is_some() {
-        let from = workspace_command.resolve_single_rev(args.from.as_deref().unwrap_or("@"))?;
-        from_tree = from.tree();
-        let to = workspace_command.resolve_single_rev(args.to.as_deref().unwrap_or("@"))?;
-        to_tree = to.tree();
-    } else {
-        let commit =
-            workspace_command.resolve_single_rev(args.revision.as_deref().unwrap_or("@"))?;
-        let parents = commit.parents();
-        from_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &parents);
-        to_tree = commit.tree()
+    fn finish(self) -> Result<(), CommandError> {
+        match self {
+            ArchiveWriter::Tar(builder) => {
+                builder.into_inner()?;
+            }
+            ArchiveWriter::TarGz(builder) => {
+                builder.into_inner()?.finish()?;
+            }
+            ArchiveWriter::Zip(mut writer) => {
+                writer
+                    .finish()
+                    .map_err(|err| UserError(format!("Failed to write archive: {}", err)))?;
+            }
+            ArchiveWriter::Dir(_) => {}
+        }
+        Ok(())
     }
-    let workspace_root = workspace_command.workspace_root();
-    let matcher = matcher_from_values(ui, workspace_root, &args.paths)?;
-    let diff_iterator = from_tree.diff(&to_tree, matcher.as_ref());
-    show_diff(
-        ui.stdout_formatter().as_mut(),
-        &workspace_command,
-        diff_iterator,
-        diff_format_for(ui, &args.format),
-    )?;
-    Ok(())
 }
 
-fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(), CommandError> {
+fn append_tar_entry<W: Write>(
+    builder: &mut tar::Builder<W>,
+    name: &str,
+    entry: &ArchiveEntry,
+    mtime: u64,
+) -> io::Result<()> {
+    let mut header = tar::Header::new_gnu();
+    header.set_mtime(mtime);
+    match entry {
+        ArchiveEntry::File {
+            content,
+            executable,
+        } => {
+            header.set_entry_type(tar::EntryType::Regular);
+            header.set_mode(if *executable { 0o755 } else { 0o644 });
+            header.set_size(content.len() as u64);
+            builder.append_data(&mut header, name, content.as_slice())
+        }
+        ArchiveEntry::Symlink(target) => {
+            header.set_entry_type(tar::EntryType::Symlink);
+            header.set_mode(0o777);
+            header.set_size(0);
+            builder.append_link(&mut header, name, target)
+        }
+    }
+}
+
+fn cmd_archive(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &ArchiveArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    let repo = workspace_command.repo();
+    let mut writer = match (&args.output, &args.output_dir) {
+        (Some(output), _) => ArchiveWriter::create(&ui.cwd().join(output))?,
+        (None, Some(output_dir)) => ArchiveWriter::create_dir(&ui.cwd().join(output_dir))?,
+        (None, None) => unreachable!(),
+    };
+    let mtime = commit.committer().timestamp.timestamp.0 / 1000;
+    let mut num_files = 0;
+    for (path, value) in commit.tree().entries_matching(matcher.as_ref()) {
+        let entry = match value {
+            TreeValue::Normal { id, executable } => ArchiveEntry::File {
+                content: read_file_content(repo.store(), &path, &id)?,
+                executable,
+            },
+            TreeValue::Symlink(id) => ArchiveEntry::Symlink(repo.store().read_symlink(&path, &id)?),
+            TreeValue::Conflict(id) => {
+                let conflict = repo.store().read_conflict(&path, &id)?;
+                let mut content = vec![];
+                conflicts::materialize_conflict(
+                    repo.store(),
+                    &path,
+                    &conflict,
+                    ui.settings().conflict_marker_style(),
+                    &mut content,
+                )
+                .unwrap();
+                ArchiveEntry::File {
+                    content,
+                    executable: false,
+                }
+            }
+            TreeValue::GitSubmodule(_) | TreeValue::Tree(_) => continue,
+        };
+        writer.add(&path, &entry, mtime)?;
+        num_files += 1;
+    }
+    writer.finish()?;
+    writeln!(ui, "Archived {} files", num_files)?;
+    Ok(())
+}
+
+/// Returns a file name for a patch based on the subject, like `git
+/// format-patch` does.
+fn patch_file_name(number: usize, subject: &str) -> String {
+    let mut slug = String::new();
+    for c in subject.chars() {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            slug.push(c);
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+        if slug.len() >= 52 {
+            break;
+        }
+    }
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        format!("{:04}.patch", number)
+    } else {
+        format!("{:04}-{}.patch", number, slug)
+    }
+}
+
+fn format_patch(
+    workspace_command: &WorkspaceCommandHelper,
+    commit: &Commit,
+    number: usize,
+    total: usize,
+) -> Result<Vec<u8>, CommandError> {
+    let parents = commit.parents();
+    if parents.len() > 1 {
+        return Err(UserError(format!(
+            "Can't create a patch for merge commit {}",
+            short_commit_hash(commit.id())
+        )));
+    }
+    let mut patch = vec![];
+    let author = commit.author();
+    let date = Utc
+        .timestamp(author.timestamp.timestamp.0 as i64 / 1000, 0)
+        .with_timezone(&FixedOffset::east(author.timestamp.tz_offset * 60));
+    let (subject, body) = match commit.description().split_once('\n') {
+        Some((subject, body)) => (subject, body.trim_start_matches('\n')),
+        None => (commit.description(), ""),
+    };
+    let subject_prefix = if total > 1 {
+        format!("[PATCH {}/{}]", number, total)
+    } else {
+        "[PATCH]".to_string()
+    };
+    writeln!(patch, "From {} Mon Sep 17 00:00:00 2001", commit.id().hex())?;
+    writeln!(patch, "From: {} <{}>", author.name, author.email)?;
+    // Like `git format-patch`, the day of the month isn't zero-padded
+    writeln!(patch, "Date: {}", date.format("%a, %-d %b %Y %H:%M:%S %z"))?;
+    writeln!(patch, "Subject: {} {}", subject_prefix, subject)?;
+    writeln!(patch)?;
+    if !body.is_empty() {
+        write!(patch, "{}", body)?;
+        if !body.ends_with('\n') {
+            writeln!(patch)?;
+        }
+    }
+    writeln!(patch, "---")?;
+    let parent_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &parents);
+    let mut formatter = PlainTextFormatter::new(Box::new(&mut patch));
+    show_git_diff(
+        &mut formatter,
+        workspace_command,
+        parent_tree.diff(&commit.tree(), &EverythingMatcher),
+    )?;
+    drop(formatter);
+    writeln!(patch)?;
+    Ok(patch)
+}
+
+fn cmd_format_patch(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &FormatPatchArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    // Oldest revision first
+    let commits = workspace_command
+        .resolve_revset(&args.revisions)?
+        .into_iter()
+        .rev()
+        .collect_vec();
+    let output_dir = ui.cwd().join(&args.output_dir);
+    if !args.stdout {
+        fs::create_dir_all(&output_dir)?;
+    }
+    for (i, commit) in commits.iter().enumerate() {
+        let patch = format_patch(&workspace_command, commit, i + 1, commits.len())?;
+        if args.stdout {
+            ui.stdout_formatter().write_all(&patch)?;
+        } else {
+            let subject = commit.description().lines().next().unwrap_or_default();
+            let path = output_dir.join(patch_file_name(i + 1, subject));
+            fs::write(&path, patch)?;
+            writeln!(ui, "{}", ui::relative_path(ui.cwd(), &path).display())?;
+        }
+    }
+    Ok(())
+}
+
+/// Applies the changes to the files to the given tree.
+fn apply_file_patches(
+    repo: RepoRef,
+    tree: &Tree,
+    files: &[patch::FilePatch],
+) -> Result<TreeId, CommandError> {
+    let store = repo.store();
+    let mut tree_builder = store.tree_builder(tree.id().clone());
+    // Read the files from the tree being built, so a patch can change a file
+    // more than once
+    let mut current_tree = tree.clone();
+    for file in files {
+        let (old_content, old_executable) = match &file.old_path {
+            None => (vec![], false),
+            Some(path) => {
+                let repo_path = RepoPath::from_internal_string(path);
+                match current_tree.path_value(&repo_path) {
+                    Some(TreeValue::Normal { id, executable }) => {
+                        (read_file_content(store, &repo_path, &id)?, executable)
+                    }
+                    Some(_) => {
+                        return Err(UserError(format!("{} is not a regular file", path)));
+                    }
+                    None => {
+                        return Err(UserError(format!("{} does not exist", path)));
+                    }
+                }
+            }
+        };
+        if let (None, Some(path)) = (&file.old_path, &file.new_path) {
+            if current_tree
+                .path_value(&RepoPath::from_internal_string(path))
+                .is_some()
+            {
+                return Err(UserError(format!("{} already exists", path)));
+            }
+        }
+        let new_content = patch::apply_hunks(&old_content, file)?;
+        if let Some(path) = &file.old_path {
+            tree_builder.remove(RepoPath::from_internal_string(path));
+        }
+        if let Some(path) = &file.new_path {
+            let repo_path = RepoPath::from_internal_string(path);
+            let id = store.write_file(&repo_path, &mut new_content.as_slice())?;
+            tree_builder.set(
+                repo_path,
+                TreeValue::Normal {
+                    id,
+                    executable: file.executable.unwrap_or(old_executable),
+                },
+            );
+        }
+        let tree_id = tree_builder.write_tree();
+        current_tree = store.get_tree(&RepoPath::root(), &tree_id)?;
+        tree_builder = store.tree_builder(tree_id);
+    }
+    Ok(current_tree.id().clone())
+}
+
+fn cmd_apply(ui: &mut Ui, command: &CommandHelper, args: &ApplyArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut messages = vec![];
+    let stdin_path = [PathBuf::from("-")];
+    let patch_paths = if args.patches.is_empty() {
+        &stdin_path[..]
+    } else {
+        &args.patches
+    };
+    for path in patch_paths {
+        let mut input = vec![];
+        if path == Path::new("-") {
+            io::stdin().read_to_end(&mut input)?;
+        } else {
+            input = fs::read(ui.cwd().join(path))
+                .map_err(|err| UserError(format!("Failed to read {}: {}", path.display(), err)))?;
+        }
+        messages.extend(patch::parse_patches(&input)?);
+    }
+    messages.retain(|message| !message.files.is_empty());
+    if messages.is_empty() {
+        return Err(UserError("No patches found".to_string()));
+    }
+
+    let wc_commit = workspace_command.resolve_single_rev("@")?;
+    workspace_command.check_rewriteable(&wc_commit)?;
+    let mut tx = workspace_command.start_transaction("apply patches");
+    let mut_repo = tx.mut_repo();
+    if args.commit {
+        let parents = wc_commit.parents();
+        let mut tree = merge_commit_trees(mut_repo.as_repo_ref(), &parents);
+        let mut parent_ids = parents
+            .iter()
+            .map(|commit| commit.id().clone())
+            .collect_vec();
+        for message in &messages {
+            let tree_id = apply_file_patches(mut_repo.as_repo_ref(), &tree, &message.files)?;
+            tree = mut_repo.store().get_tree(&RepoPath::root(), &tree_id)?;
+            let mut commit_builder =
+                CommitBuilder::for_new_commit(ui.settings(), parent_ids, tree_id)
+                    .set_description(message.description.clone().unwrap_or_default());
+            if let Some((name, email)) = &message.author {
+                let mut author = ui.settings().signature();
+                author.name = name.clone();
+                author.email = email.clone();
+                if let Some(date) = &message.date {
+                    let datetime = chrono::DateTime::parse_from_rfc2822(date).map_err(|err| {
+                        UserError(format!("Invalid date in patch '{}': {}", date, err))
+                    })?;
+                    author.timestamp = Timestamp::from_datetime(datetime);
+                }
+                commit_builder = commit_builder.set_author(author);
+            }
+            let commit = commit_builder.try_write_to_repo(mut_repo)?;
+            write!(ui, "Created ")?;
+            ui.write_commit_summary(
+                mut_repo.as_repo_ref(),
+                &workspace_command.workspace_id(),
+                &commit,
+            )?;
+            writeln!(ui)?;
+            parent_ids = vec![commit.id().clone()];
+        }
+        let new_parent = mut_repo.store().get_commit(&parent_ids[0])?;
+        rebase_commit(ui.settings(), mut_repo, &wc_commit, &[new_parent])?;
+    } else {
+        let mut tree = wc_commit.tree();
+        for message in &messages {
+            let tree_id = apply_file_patches(mut_repo.as_repo_ref(), &tree, &message.files)?;
+            tree = mut_repo.store().get_tree(&RepoPath::root(), &tree_id)?;
+        }
+        CommitBuilder::for_rewrite_from(ui.settings(), &wc_commit)
+            .set_tree(tree.id().clone())
+            .try_write_to_repo(mut_repo)?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn show_color_words_diff_hunks(
+    left: &[u8],
+    right: &[u8],
+    formatter: &mut dyn Formatter,
+) -> io::Result<()> {
+    let num_context_lines = 3;
+    let mut context = VecDeque::new();
+    // Have we printed "..." for any skipped context?
+    let mut skipped_context = false;
+    // Are the lines in `context` to be printed before the next modified line?
+    let mut context_before = true;
+    for diff_line in files::diff(left, right) {
+        if diff_line.is_unmodified() {
+            context.push_back(diff_line.clone());
+            if context.len() > num_context_lines {
+                if context_before {
+                    context.pop_front();
+                } else {
+                    context.pop_back();
+                }
+                if !context_before {
+                    for line in &context {
+                        show_color_words_diff_line(formatter, line)?;
+                    }
+                    context.clear();
+                    context_before = true;
+                }
+                if !skipped_context {
+                    formatter.write_bytes(b"    ...\n")?;
+                    skipped_context = true;
+                }
+            }
+        } else {
+            for line in &context {
+                show_color_words_diff_line(formatter, line)?;
+            }
+            context.clear();
+            show_color_words_diff_line(formatter, &diff_line)?;
+            context_before = false;
+            skipped_context = false;
+        }
+    }
+    if !context_before {
+        for line in &context {
+            show_color_words_diff_line(formatter, line)?;
+        }
+    }
+
+    // If the last diff line doesn't end with newline, add it.
+    let no_hunk = left.is_empty() && right.is_empty();
+    let any_last_newline = left.ends_with(b"\n") || right.ends_with(b"\n");
+    if !skipped_context && !no_hunk && !any_last_newline {
+        formatter.write_bytes(b"\n")?;
+    }
+
+    Ok(())
+}
+
+fn show_color_words_diff_line(
+    formatter: &mut dyn Formatter,
+    diff_line: &DiffLine,
+) -> io::Result<()> {
+    if diff_line.has_left_content {
+        formatter.add_label(String::from("removed"))?;
+        formatter.write_bytes(format!("{:>4}", diff_line.left_line_number).as_bytes())?;
+        formatter.remove_label()?;
+        formatter.write_bytes(b" ")?;
+    } else {
+        formatter.write_bytes(b"     ")?;
+    }
+    if diff_line.has_right_content {
+        formatter.add_label(String::from("added"))?;
+        formatter.write_bytes(format!("{:>4}", diff_line.right_line_number).as_bytes())?;
+        formatter.remove_label()?;
+        formatter.write_bytes(b": ")?;
+    } else {
+        formatter.write_bytes(b"    : ")?;
+    }
+    for hunk in &diff_line.hunks {
+        match hunk {
+            DiffHunk::Matching(data) => {
+                formatter.write_bytes(data)?;
+            }
+            DiffHunk::Different(data) => {
+                let before = data[0];
+                let after = data[1];
+                if !before.is_empty() {
+                    formatter.add_label(String::from("removed"))?;
+                    formatter.write_bytes(before)?;
+                    formatter.remove_label()?;
+                }
+                if !after.is_empty() {
+                    formatter.add_label(String::from("added"))?;
+                    formatter.write_bytes(after)?;
+                    formatter.remove_label()?;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn cmd_diff(ui: &mut Ui, command: &CommandHelper, args: &DiffArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper_for_paths(ui, &args.paths)?;
+    let from_tree;
+    let to_tree;
+    if args.from.is_some() || args.to.is_some() {
+        let from = workspace_command.resolve_single_rev(args.from.as_deref().unwrap_or("@"))?;
+        from_tree = from.tree();
+        let to = workspace_command.resolve_single_rev(args.to.as_deref().unwrap_or("@"))?;
+        to_tree = to.tree();
+    } else {
+        let commit =
+            workspace_command.resolve_single_rev(args.revision.as_deref().unwrap_or("@"))?;
+        let parents = commit.parents();
+        from_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &parents);
+        to_tree = commit.tree()
+    }
+    let workspace_root = workspace_command.workspace_root();
+    let matcher = matcher_from_values(ui, workspace_root, &args.paths)?;
+    let format_requested =
+        args.format.summary || args.format.stat || args.format.git || args.format.color_words;
+    let diff_tool = match &args.tool {
+        Some(tool) => Some(tool.clone()),
+        None if !format_requested => ui.settings().config().get_string("ui.diff-tool").ok(),
+        None => None,
+    };
+    if let Some(diff_tool) = diff_tool {
+        diff_edit::view_diff(
+            ui.settings(),
+            &diff_tool,
+            &from_tree,
+            &to_tree,
+            matcher.as_ref(),
+        )
+        .map_err(|err| UserError(format!("Failed to show diff: {err}")))?;
+        return Ok(());
+    }
+    ui.request_pager();
+    let diff_iterator = from_tree.diff(&to_tree, matcher.as_ref());
+    show_diff(
+        ui.stdout_formatter().as_mut(),
+        &workspace_command,
+        diff_iterator,
+        diff_format_for(ui, &args.format),
+    )?;
+    Ok(())
+}
+
+fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(), CommandError> {
     let workspace_command = command.workspace_helper(ui)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
     let parents = commit.parents();
@@ -1449,6 +2980,7 @@ fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(),
             "Change ID: " change_id "\n"
             "Author: " author " <" author.email() "> (" author.timestamp() ")\n"
             "Committer: " committer " <" committer.email() "> (" committer.timestamp() ")\n"
+            if(signed, "Signature: " signature_status "\n")
             "\n"
             description
             "\n""#;
@@ -1463,11 +2995,12 @@ fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(),
     let template = crate::template_parser::parse_commit_template(
         workspace_command.repo().as_repo_ref(),
         &workspace_command.workspace_id(),
+        ui.settings(),
         &template_string,
     );
     let mut formatter = ui.stdout_formatter();
     let formatter = formatter.as_mut();
-    template.format(&commit, formatter)?;
+    template.format(&LazyCommit::from_commit(commit.clone()), formatter)?;
     show_diff(
         formatter,
         &workspace_command,
@@ -1480,6 +3013,7 @@ fn cmd_show(ui: &mut Ui, command: &CommandHelper, args: &ShowArgs) -> Result<(),
 #[derive(Clone, Copy, Debug, Eq, PartialEq)]
 enum DiffFormat {
     Summary,
+    Stat,
     Git,
     ColorWords,
 }
@@ -1487,6 +3021,8 @@ enum DiffFormat {
 fn diff_format_for(ui: &Ui, args: &DiffFormatArgs) -> DiffFormat {
     if args.summary {
         DiffFormat::Summary
+    } else if args.stat {
+        DiffFormat::Stat
     } else if args.git {
         DiffFormat::Git
     } else if args.color_words {
@@ -1494,6 +3030,7 @@ fn diff_format_for(ui: &Ui, args: &DiffFormatArgs) -> DiffFormat {
     } else {
         match ui.settings().config().get_string("diff.format") {
             Ok(value) if &value == "summary" => DiffFormat::Summary,
+            Ok(value) if &value == "stat" => DiffFormat::Stat,
             Ok(value) if &value == "git" => DiffFormat::Git,
             Ok(value) if &value == "color-words" => DiffFormat::ColorWords,
             _ => DiffFormat::ColorWords,
@@ -1511,6 +3048,9 @@ fn show_diff(
         DiffFormat::Summary => {
             show_diff_summary(formatter, workspace_command, tree_diff)?;
         }
+        DiffFormat::Stat => {
+            show_diff_stat(formatter, workspace_command, tree_diff)?;
+        }
         DiffFormat::Git => {
             show_git_diff(formatter, workspace_command, tree_diff)?;
         }
@@ -1549,33 +3089,128 @@ fn diff_content(
         TreeValue::Conflict(id) => {
             let conflict = repo.store().read_conflict(path, id).unwrap();
             let mut content = vec![];
-            conflicts::materialize_conflict(repo.store(), path, &conflict, &mut content).unwrap();
+            conflicts::materialize_conflict(
+                repo.store(),
+                path,
+                &conflict,
+                ConflictMarkerStyle::Diff,
+                &mut content,
+            )
+            .unwrap();
             Ok(content)
         }
     }
 }
 
-fn basic_diff_file_type(value: &TreeValue) -> String {
-    match value {
-        TreeValue::Normal { executable, .. } => {
-            if *executable {
-                "executable file".to_string()
-            } else {
-                "regular file".to_string()
-            }
-        }
-        TreeValue::Symlink(_) => "symlink".to_string(),
-        TreeValue::Tree(_) => "tree".to_string(),
-        TreeValue::GitSubmodule(_) => "Git submodule".to_string(),
-        TreeValue::Conflict(_) => "conflict".to_string(),
-    }
-}
+type ConflictSideContents = Vec<(String, Vec<u8>)>;
 
-fn show_color_words_diff(
-    formatter: &mut dyn Formatter,
-    workspace_command: &WorkspaceCommandHelper,
-    tree_diff: TreeDiffIterator,
-) -> Result<(), CommandError> {
+type ConflictSideDiffs = Vec<(String, Vec<u8>, Vec<u8>)>;
+
+/// The contents of the sides of a conflict between regular files, each with a
+/// description of the side, or `None` if the conflict involves other types.
+fn conflict_side_contents(
+    repo: &Arc<ReadonlyRepo>,
+    path: &RepoPath,
+    conflict_id: &ConflictId,
+) -> Result<Option<ConflictSideContents>, CommandError> {
+    let conflict = repo.store().read_conflict(path, conflict_id)?;
+    if conflict
+        .removes
+        .iter()
+        .chain(&conflict.adds)
+        .any(|part| !matches!(part.value, TreeValue::Normal { .. }))
+    {
+        return Ok(None);
+    }
+    let mut sides = vec![];
+    for (i, part) in conflict.adds.iter().enumerate() {
+        let description = match &part.label {
+            Some(label) => format!("Side #{} ({})", i + 1, label),
+            None => format!("Side #{}", i + 1),
+        };
+        sides.push((description, diff_content(repo, path, &part.value)?));
+    }
+    Ok(Some(sides))
+}
+
+/// When a regular file became a conflict or the other way around, the changes
+/// from the file to each side of the conflict (or from each side to the file)
+/// are easier to follow than the changes to the conflict markers. Returns the
+/// description of each side with the contents to diff, or `None` if the change
+/// should be shown as a diff of the materialized contents instead.
+fn conflict_side_diffs(
+    repo: &Arc<ReadonlyRepo>,
+    path: &RepoPath,
+    left_value: &TreeValue,
+    right_value: &TreeValue,
+) -> Result<Option<ConflictSideDiffs>, CommandError> {
+    let side_diffs = match (left_value, right_value) {
+        (TreeValue::Conflict(left_id), TreeValue::Conflict(right_id)) => {
+            let left_sides = conflict_side_contents(repo, path, left_id)?;
+            let right_sides = conflict_side_contents(repo, path, right_id)?;
+            match (left_sides, right_sides) {
+                (Some(left_sides), Some(right_sides)) if left_sides.len() == right_sides.len() => {
+                    left_sides
+                        .into_iter()
+                        .zip(right_sides)
+                        .map(|((_, left_content), (description, right_content))| {
+                            (description, left_content, right_content)
+                        })
+                        .collect_vec()
+                }
+                _ => return Ok(None),
+            }
+        }
+        (TreeValue::Conflict(left_id), TreeValue::Normal { .. }) => {
+            let right_content = diff_content(repo, path, right_value)?;
+            match conflict_side_contents(repo, path, left_id)? {
+                Some(left_sides) => left_sides
+                    .into_iter()
+                    .map(|(description, left_content)| {
+                        (description, left_content, right_content.clone())
+                    })
+                    .collect_vec(),
+                None => return Ok(None),
+            }
+        }
+        (TreeValue::Normal { .. }, TreeValue::Conflict(right_id)) => {
+            let left_content = diff_content(repo, path, left_value)?;
+            match conflict_side_contents(repo, path, right_id)? {
+                Some(right_sides) => right_sides
+                    .into_iter()
+                    .map(|(description, right_content)| {
+                        (description, left_content.clone(), right_content)
+                    })
+                    .collect_vec(),
+                None => return Ok(None),
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(side_diffs))
+}
+
+fn basic_diff_file_type(value: &TreeValue) -> String {
+    match value {
+        TreeValue::Normal { executable, .. } => {
+            if *executable {
+                "executable file".to_string()
+            } else {
+                "regular file".to_string()
+            }
+        }
+        TreeValue::Symlink(_) => "symlink".to_string(),
+        TreeValue::Tree(_) => "tree".to_string(),
+        TreeValue::GitSubmodule(_) => "Git submodule".to_string(),
+        TreeValue::Conflict(_) => "conflict".to_string(),
+    }
+}
+
+fn show_color_words_diff(
+    formatter: &mut dyn Formatter,
+    workspace_command: &WorkspaceCommandHelper,
+    tree_diff: TreeDiffIterator,
+) -> Result<(), CommandError> {
     let repo = workspace_command.repo();
     formatter.add_label(String::from("diff"))?;
     for (path, diff) in tree_diff {
@@ -1590,9 +3225,8 @@ fn show_color_words_diff(
                 show_color_words_diff_hunks(&[], &right_content, formatter)?;
             }
             tree::Diff::Modified(left_value, right_value) => {
-                let left_content = diff_content(repo, &path, &left_value)?;
-                let right_content = diff_content(repo, &path, &right_value)?;
-                let description = match (left_value, right_value) {
+                let side_diffs = conflict_side_diffs(repo, &path, &left_value, &right_value)?;
+                let description = match (&left_value, &right_value) {
                     (
                         TreeValue::Normal {
                             executable: left_executable,
@@ -1603,11 +3237,11 @@ fn show_color_words_diff(
                             ..
                         },
                     ) => {
-                        if left_executable && right_executable {
+                        if *left_executable && *right_executable {
                             "Modified executable file".to_string()
-                        } else if left_executable {
+                        } else if *left_executable {
                             "Executable file became non-executable at".to_string()
-                        } else if right_executable {
+                        } else if *right_executable {
                             "Non-executable file became executable at".to_string()
                         } else {
                             "Modified regular file".to_string()
@@ -1622,8 +3256,8 @@ fn show_color_words_diff(
                         "Symlink target changed at".to_string()
                     }
                     (left_value, right_value) => {
-                        let left_type = basic_diff_file_type(&left_value);
-                        let right_type = basic_diff_file_type(&right_value);
+                        let left_type = basic_diff_file_type(left_value);
+                        let right_type = basic_diff_file_type(right_value);
                         let (first, rest) = left_type.split_at(1);
                         format!(
                             "{}{} became {} at",
@@ -1636,7 +3270,18 @@ fn show_color_words_diff(
                 formatter.add_label(String::from("header"))?;
                 formatter.write_str(&format!("{} {}:\n", description, ui_path))?;
                 formatter.remove_label()?;
-                show_color_words_diff_hunks(&left_content, &right_content, formatter)?;
+                if let Some(side_diffs) = side_diffs {
+                    for (side_description, left_content, right_content) in side_diffs {
+                        formatter.add_label(String::from("header"))?;
+                        formatter.write_str(&format!("{}:\n", side_description))?;
+                        formatter.remove_label()?;
+                        show_color_words_diff_hunks(&left_content, &right_content, formatter)?;
+                    }
+                } else {
+                    let left_content = diff_content(repo, &path, &left_value)?;
+                    let right_content = diff_content(repo, &path, &right_value)?;
+                    show_color_words_diff_hunks(&left_content, &right_content, formatter)?;
+                }
             }
             tree::Diff::Removed(left_value) => {
                 let left_content = diff_content(repo, &path, &left_value)?;
@@ -1691,14 +3336,21 @@ fn git_diff_part(
         }
         TreeValue::GitSubmodule(id) => {
             // TODO: What should we actually do here?
-            mode = "040000".to_string();
+            mode = "160000".to_string();
             hash = id.hex();
         }
         TreeValue::Conflict(id) => {
             mode = "100644".to_string();
             hash = id.hex();
             let conflict = repo.store().read_conflict(path, id).unwrap();
-            conflicts::materialize_conflict(repo.store(), path, &conflict, &mut content).unwrap();
+            conflicts::materialize_conflict(
+                repo.store(),
+                path,
+                &conflict,
+                ConflictMarkerStyle::Diff,
+                &mut content,
+            )
+            .unwrap();
         }
     }
     let hash = hash[0..10].to_string();
@@ -1847,16 +3499,86 @@ fn show_unified_diff_hunks(
     Ok(())
 }
 
+/// Finds the renamed and copied files among the diffs, unless disabled in the
+/// config.
+fn find_copies(
+    workspace_command: &WorkspaceCommandHelper,
+    diffs: &[(RepoPath, tree::Diff<TreeValue>)],
+) -> Result<Vec<CopyRecord>, CommandError> {
+    match workspace_command.settings().copy_detection_options() {
+        None => Ok(vec![]),
+        Some(options) => Ok(copies::find_copies(
+            workspace_command.repo().store(),
+            diffs,
+            &options,
+        )?),
+    }
+}
+
 fn show_git_diff(
     formatter: &mut dyn Formatter,
     workspace_command: &WorkspaceCommandHelper,
     tree_diff: TreeDiffIterator,
 ) -> Result<(), CommandError> {
     let repo = workspace_command.repo();
+    let diffs = tree_diff.collect_vec();
+    let copies = find_copies(workspace_command, &diffs)?;
+    let copies_by_target: HashMap<&RepoPath, &CopyRecord> =
+        copies.iter().map(|copy| (&copy.target, copy)).collect();
+    let renamed_paths: HashSet<&RepoPath> = copies
+        .iter()
+        .filter(|copy| copy.operation == CopyOperation::Rename)
+        .map(|copy| &copy.source)
+        .collect();
+    let old_values: HashMap<&RepoPath, &TreeValue> = diffs
+        .iter()
+        .filter_map(|(path, diff)| diff.as_options().0.map(|value| (path, value)))
+        .collect();
     formatter.add_label(String::from("diff"))?;
-    for (path, diff) in tree_diff {
+    for (path, diff) in diffs.iter().cloned() {
+        if renamed_paths.contains(&path) {
+            continue;
+        }
         let path_string = path.to_internal_file_string();
         formatter.add_label(String::from("file_header"))?;
+        if let (Some(copy), tree::Diff::Added(new_value)) = (copies_by_target.get(&path), &diff) {
+            let old_path = &copy.source;
+            let old_path_string = old_path.to_internal_file_string();
+            writeln!(
+                formatter,
+                "diff --git a/{} b/{}",
+                old_path_string, path_string
+            )?;
+            let left_part = git_diff_part(repo, old_path, old_values[old_path])?;
+            let right_part = git_diff_part(repo, &path, new_value)?;
+            if left_part.mode != right_part.mode {
+                writeln!(formatter, "old mode {}", &left_part.mode)?;
+                writeln!(formatter, "new mode {}", &right_part.mode)?;
+            }
+            writeln!(formatter, "similarity index {}%", copy.similarity)?;
+            let operation = match copy.operation {
+                CopyOperation::Rename => "rename",
+                CopyOperation::Copy => "copy",
+            };
+            writeln!(formatter, "{} from {}", operation, old_path_string)?;
+            writeln!(formatter, "{} to {}", operation, path_string)?;
+            if left_part.content != right_part.content {
+                if left_part.mode == right_part.mode {
+                    writeln!(
+                        formatter,
+                        "index {}...{} {}",
+                        &left_part.hash, right_part.hash, left_part.mode
+                    )?;
+                } else {
+                    writeln!(formatter, "index {}...{}", &left_part.hash, right_part.hash)?;
+                }
+                writeln!(formatter, "--- a/{}", old_path_string)?;
+                writeln!(formatter, "+++ b/{}", path_string)?;
+            }
+            formatter.remove_label()?;
+            show_unified_diff_hunks(formatter, &left_part.content, &right_part.content)?;
+            continue;
+        }
         writeln!(formatter, "diff --git a/{} b/{}", path_string, path_string)?;
         match diff {
             tree::Diff::Added(right_value) => {
@@ -1910,16 +3632,44 @@ fn show_diff_summary(
     formatter: &mut dyn Formatter,
     workspace_command: &WorkspaceCommandHelper,
     tree_diff: TreeDiffIterator,
-) -> io::Result<()> {
+) -> Result<(), CommandError> {
+    let diffs = tree_diff.collect_vec();
+    let copies = find_copies(workspace_command, &diffs)?;
+    let copies_by_target: HashMap<&RepoPath, &CopyRecord> =
+        copies.iter().map(|copy| (&copy.target, copy)).collect();
+    let renamed_paths: HashSet<&RepoPath> = copies
+        .iter()
+        .filter(|copy| copy.operation == CopyOperation::Rename)
+        .map(|copy| &copy.source)
+        .collect();
     formatter.add_label(String::from("diff"))?;
-    for (repo_path, diff) in tree_diff {
+    for (repo_path, diff) in &diffs {
+        if let Some(copy) = copies_by_target.get(repo_path) {
+            let (label, letter) = match copy.operation {
+                CopyOperation::Rename => ("renamed", "R"),
+                CopyOperation::Copy => ("copied", "C"),
+            };
+            formatter.add_label(String::from(label))?;
+            writeln!(
+                formatter,
+                "{} {} -> {}",
+                letter,
+                workspace_command.format_file_path(&copy.source),
+                workspace_command.format_file_path(repo_path)
+            )?;
+            formatter.remove_label()?;
+            continue;
+        }
+        if renamed_paths.contains(repo_path) {
+            continue;
+        }
         match diff {
             tree::Diff::Modified(_, _) => {
                 formatter.add_label(String::from("modified"))?;
                 writeln!(
                     formatter,
                     "M {}",
-                    workspace_command.format_file_path(&repo_path)
+                    workspace_command.format_file_path(repo_path)
                 )?;
                 formatter.remove_label()?;
             }
@@ -1928,7 +3678,7 @@ fn show_diff_summary(
                 writeln!(
                     formatter,
                     "A {}",
-                    workspace_command.format_file_path(&repo_path)
+                    workspace_command.format_file_path(repo_path)
                 )?;
                 formatter.remove_label()?;
             }
@@ -1937,7 +3687,7 @@ fn show_diff_summary(
                 writeln!(
                     formatter,
                     "R {}",
-                    workspace_command.format_file_path(&repo_path)
+                    workspace_command.format_file_path(repo_path)
                 )?;
                 formatter.remove_label()?;
             }
@@ -1947,6 +3697,91 @@ fn show_diff_summary(
     Ok(())
 }
 
+fn show_diff_stat(
+    formatter: &mut dyn Formatter,
+    workspace_command: &WorkspaceCommandHelper,
+    tree_diff: TreeDiffIterator,
+) -> Result<(), CommandError> {
+    let repo = workspace_command.repo();
+    let mut stats = vec![];
+    for (repo_path, diff) in tree_diff {
+        let (left_content, right_content) = match &diff {
+            tree::Diff::Modified(left_value, right_value) => (
+                diff_content(repo, &repo_path, left_value)?,
+                diff_content(repo, &repo_path, right_value)?,
+            ),
+            tree::Diff::Added(right_value) => {
+                (vec![], diff_content(repo, &repo_path, right_value)?)
+            }
+            tree::Diff::Removed(left_value) => {
+                (diff_content(repo, &repo_path, left_value)?, vec![])
+            }
+        };
+        let mut num_removed = 0;
+        let mut num_added = 0;
+        for hunk in line_hunks(&left_content, &right_content) {
+            num_removed += hunk.left.len();
+            num_added += hunk.right.len();
+        }
+        let ui_path = workspace_command.format_file_path(&repo_path);
+        stats.push((ui_path, num_added, num_removed));
+    }
+
+    // Scale the histogram down so the lines fit in 80 columns, like Git does.
+    let path_width = stats
+        .iter()
+        .map(|(path, _, _)| path.chars().count())
+        .max()
+        .unwrap_or(0);
+    let max_changes = stats
+        .iter()
+        .map(|(_, added, removed)| added + removed)
+        .max()
+        .unwrap_or(0);
+    let number_width = max_changes.to_string().len();
+    let bar_width = 80usize
+        .saturating_sub(path_width + number_width + 5)
+        .max(10);
+    let scale = |count: usize| {
+        if max_changes <= bar_width || count == 0 {
+            count
+        } else {
+            (count * bar_width / max_changes).max(1)
+        }
+    };
+    formatter.add_label(String::from("diff"))?;
+    let mut total_added = 0;
+    let mut total_removed = 0;
+    for (path, num_added, num_removed) in &stats {
+        total_added += num_added;
+        total_removed += num_removed;
+        write!(
+            formatter,
+            " {path:path_width$} | {:>number_width$}",
+            num_added + num_removed
+        )?;
+        if num_added + num_removed > 0 {
+            formatter.write_str(" ")?;
+        }
+        formatter.add_label(String::from("added"))?;
+        formatter.write_str(&"+".repeat(scale(*num_added)))?;
+        formatter.remove_label()?;
+        formatter.add_label(String::from("removed"))?;
+        formatter.write_str(&"-".repeat(scale(*num_removed)))?;
+        formatter.remove_label()?;
+        writeln!(formatter)?;
+    }
+    writeln!(
+        formatter,
+        " {} files changed, {} insertions(+), {} deletions(-)",
+        stats.len(),
+        total_added,
+        total_removed
+    )?;
+    formatter.remove_label()?;
+    Ok(())
+}
+
 fn cmd_status(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -2024,6 +3859,34 @@ fn cmd_status(
         )?;
     }
 
+    let mut divergent_commit_counts: BTreeMap<ChangeId, usize> = BTreeMap::new();
+    for entry in RevsetExpression::divergent()
+        .evaluate(repo.as_repo_ref(), None)?
+        .iter()
+    {
+        *divergent_commit_counts
+            .entry(entry.change_id())
+            .or_default() += 1;
+    }
+    if !divergent_commit_counts.is_empty() {
+        ui.stdout_formatter().add_label("divergent".to_string())?;
+        writeln!(ui, "These changes are divergent:")?;
+        ui.stdout_formatter().remove_label()?;
+        for (change_id, num_commits) in divergent_commit_counts {
+            writeln!(
+                ui,
+                "  {} ({} commits)",
+                short_change_hash(&change_id),
+                num_commits
+            )?;
+        }
+        writeln!(
+            ui,
+            "  Use `jj log -r 'divergent()'` to see details. Use `jj abandon` on the commits you \
+             don't want to keep."
+        )?;
+    }
+
     if let Some(checkout_commit) = &maybe_checkout {
         let parent_tree = checkout_commit.parents()[0].tree();
         let tree = checkout_commit.tree();
@@ -2043,9 +3906,25 @@ fn cmd_status(
             ui.stdout_formatter().add_label("conflict".to_string())?;
             writeln!(ui, "There are unresolved conflicts at these paths:")?;
             ui.stdout_formatter().remove_label()?;
-            for (path, _) in conflicts {
-                writeln!(ui, "{}", &workspace_command.format_file_path(&path))?;
+            let ui_paths = conflicts
+                .iter()
+                .map(|(path, _)| workspace_command.format_file_path(path))
+                .collect_vec();
+            let path_width = ui_paths.iter().map(|path| path.chars().count()).max();
+            let path_width = path_width.unwrap_or(0);
+            for ((path, conflict_id), ui_path) in conflicts.iter().zip(ui_paths) {
+                let conflict = repo.store().read_conflict(path, conflict_id)?;
+                let num_sides = conflict.removes.len() + 1;
+                let kind = conflicts::conflict_kind(&conflict);
+                writeln!(
+                    ui,
+                    "{ui_path:path_width$}    {num_sides}-sided {kind} conflict"
+                )?;
             }
+            writeln!(
+                ui,
+                "  Edit the conflict markers in the files to resolve the conflicts."
+            )?;
         }
     }
 
@@ -2083,8 +3962,12 @@ fn log_template(settings: &UserSettings) -> String {
         .unwrap_or(default_template)
 }
 
+/// How many commits `jj log` reads from the store at once, if the template
+/// needs them.
+const LOG_BATCH_SIZE: usize = 100;
+
 fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), CommandError> {
-    let workspace_command = command.workspace_helper(ui)?;
+    let workspace_command = command.workspace_helper_for_paths(ui, &args.paths)?;
 
     let revset_expression = revset::parse(&args.revisions)?;
     let repo = workspace_command.repo();
@@ -2099,6 +3982,13 @@ fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), C
     let store = repo.store();
     let diff_format = (args.patch || args.diff_format.git || args.diff_format.summary)
         .then(|| diff_format_for(ui, &args.diff_format));
+    let reversed = args.reversed
+        || ui
+            .settings()
+            .config()
+            .get_bool("ui.log-reversed")
+            .unwrap_or(false);
+    let limit = args.limit.unwrap_or(usize::MAX);
 
     let template_string = match &args.template {
         Some(value) => value.to_string(),
@@ -2107,98 +3997,107 @@ fn cmd_log(ui: &mut Ui, command: &CommandHelper, args: &LogArgs) -> Result<(), C
     let template = crate::template_parser::parse_commit_template(
         repo.as_repo_ref(),
         &workspace_id,
+        ui.settings(),
         &template_string,
     );
 
+    ui.request_pager();
     let mut formatter = ui.stdout_formatter();
     let mut formatter = formatter.as_mut();
     formatter.add_label(String::from("log"))?;
 
     if !args.no_graph {
         let mut graph = AsciiGraphDrawer::new(&mut formatter);
-        let iter: Box<dyn Iterator<Item = (IndexEntry, Vec<RevsetGraphEdge>)>> = if args.reversed {
-            Box::new(revset.iter().graph().reversed())
+        let iter = revset.iter().graph().take(limit);
+        let iter: Box<dyn Iterator<Item = (IndexEntry, Vec<RevsetGraphEdge>)>> = if reversed {
+            Box::new(ReverseRevsetGraphIterator::new(iter))
         } else {
-            Box::new(revset.iter().graph())
+            Box::new(iter)
         };
-        for (index_entry, edges) in iter {
-            let mut graphlog_edges = vec![];
-            // TODO: Should we update RevsetGraphIterator to yield this flag instead of all
-            // the missing edges since we don't care about where they point here
-            // anyway?
-            let mut has_missing = false;
-            for edge in edges {
-                match edge.edge_type {
-                    RevsetGraphEdgeType::Missing => {
-                        has_missing = true;
+        for chunk in &iter.chunks(LOG_BATCH_SIZE) {
+            let (index_entries, edges): (Vec<_>, Vec<_>) = chunk.unzip();
+            let commits = LazyCommit::batch_from_index_entries(store, &index_entries);
+            for ((index_entry, edges), commit) in index_entries.into_iter().zip(edges).zip(commits)
+            {
+                let mut graphlog_edges = vec![];
+                // TODO: Should we update RevsetGraphIterator to yield this flag instead of all
+                // the missing edges since we don't care about where they point here
+                // anyway?
+                let mut has_missing = false;
+                for edge in edges {
+                    match edge.edge_type {
+                        RevsetGraphEdgeType::Missing => {
+                            has_missing = true;
+                        }
+                        RevsetGraphEdgeType::Direct => graphlog_edges.push(Edge::Present {
+                            direct: true,
+                            target: edge.target,
+                        }),
+                        RevsetGraphEdgeType::Indirect => graphlog_edges.push(Edge::Present {
+                            direct: false,
+                            target: edge.target,
+                        }),
                     }
-                    RevsetGraphEdgeType::Direct => graphlog_edges.push(Edge::Present {
-                        direct: true,
-                        target: edge.target,
-                    }),
-                    RevsetGraphEdgeType::Indirect => graphlog_edges.push(Edge::Present {
-                        direct: false,
-                        target: edge.target,
-                    }),
                 }
-            }
-            if has_missing {
-                graphlog_edges.push(Edge::Missing);
-            }
-            let mut buffer = vec![];
-            let commit_id = index_entry.commit_id();
-            let commit = store.get_commit(&commit_id)?;
-            let is_checkout = Some(&commit_id) == checkout_id;
-            {
-                let writer = Box::new(&mut buffer);
-                let mut formatter = ui.new_formatter(writer);
-                if is_checkout {
-                    formatter.add_label("working_copy".to_string())?;
+                if has_missing {
+                    graphlog_edges.push(Edge::Missing);
                 }
-                template.format(&commit, formatter.as_mut())?;
-                if is_checkout {
-                    formatter.remove_label()?;
+                let mut buffer = vec![];
+                let is_checkout = Some(commit.id()) == checkout_id;
+                {
+                    let writer = Box::new(&mut buffer);
+                    let mut formatter = ui.new_formatter(writer);
+                    if is_checkout {
+                        formatter.add_label("working_copy".to_string())?;
+                    }
+                    template.format(&commit, formatter.as_mut())?;
+                    if is_checkout {
+                        formatter.remove_label()?;
+                    }
                 }
-            }
-            if !buffer.ends_with(b"\n") {
-                buffer.push(b'\n');
-            }
-            if let Some(diff_format) = diff_format {
-                let writer = Box::new(&mut buffer);
-                let mut formatter = ui.new_formatter(writer);
-                show_patch(
-                    formatter.as_mut(),
-                    &workspace_command,
-                    &commit,
-                    matcher.as_ref(),
-                    diff_format,
+                if !buffer.ends_with(b"\n") {
+                    buffer.push(b'\n');
+                }
+                if let Some(diff_format) = diff_format {
+                    let writer = Box::new(&mut buffer);
+                    let mut formatter = ui.new_formatter(writer);
+                    show_patch(
+                        formatter.as_mut(),
+                        &workspace_command,
+                        &commit.commit()?,
+                        matcher.as_ref(),
+                        diff_format,
+                    )?;
+                }
+                let node_symbol = if is_checkout { b"@" } else { b"o" };
+                graph.add_node(
+                    &index_entry.position(),
+                    &graphlog_edges,
+                    node_symbol,
+                    &buffer,
                 )?;
             }
-            let node_symbol = if is_checkout { b"@" } else { b"o" };
-            graph.add_node(
-                &index_entry.position(),
-                &graphlog_edges,
-                node_symbol,
-                &buffer,
-            )?;
         }
     } else {
-        let iter: Box<dyn Iterator<Item = IndexEntry>> = if args.reversed {
-            Box::new(revset.iter().reversed())
+        let iter = revset.iter().take(limit);
+        let iter: Box<dyn Iterator<Item = IndexEntry>> = if reversed {
+            Box::new(iter.collect_vec().into_iter().rev())
         } else {
-            Box::new(revset.iter())
+            Box::new(iter)
         };
-        for index_entry in iter {
-            let commit = store.get_commit(&index_entry.commit_id())?;
-            template.format(&commit, formatter)?;
-            if let Some(diff_format) = diff_format {
-                show_patch(
-                    formatter,
-                    &workspace_command,
-                    &commit,
-                    matcher.as_ref(),
-                    diff_format,
-                )?;
+        for chunk in &iter.chunks(LOG_BATCH_SIZE) {
+            let index_entries = chunk.collect_vec();
+            for commit in LazyCommit::batch_from_index_entries(store, &index_entries) {
+                template.format(&commit, formatter)?;
+                if let Some(diff_format) = diff_format {
+                    show_patch(
+                        formatter,
+                        &workspace_command,
+                        &commit.commit()?,
+                        matcher.as_ref(),
+                        diff_format,
+                    )?;
+                }
             }
         }
     }
@@ -2240,9 +4139,11 @@ fn cmd_obslog(ui: &mut Ui, command: &CommandHelper, args: &ObslogArgs) -> Result
     let template = crate::template_parser::parse_commit_template(
         workspace_command.repo().as_repo_ref(),
         &workspace_id,
+        ui.settings(),
         &template_string,
     );
 
+    ui.request_pager();
     let mut formatter = ui.stdout_formatter();
     let mut formatter = formatter.as_mut();
     formatter.add_label(String::from("log"))?;
@@ -2263,7 +4164,7 @@ fn cmd_obslog(ui: &mut Ui, command: &CommandHelper, args: &ObslogArgs) -> Result
             {
                 let writer = Box::new(&mut buffer);
                 let mut formatter = ui.new_formatter(writer);
-                template.format(&commit, formatter.as_mut())?;
+                template.format(&LazyCommit::from_commit(commit.clone()), formatter.as_mut())?;
             }
             if !buffer.ends_with(b"\n") {
                 buffer.push(b'\n');
@@ -2287,7 +4188,7 @@ fn cmd_obslog(ui: &mut Ui, command: &CommandHelper, args: &ObslogArgs) -> Result
         }
     } else {
         for commit in commits {
-            template.format(&commit, formatter)?;
+            template.format(&LazyCommit::from_commit(commit.clone()), formatter)?;
             if let Some(diff_format) = diff_format {
                 show_predecessor_patch(formatter, &workspace_command, &commit, diff_format)?;
             }
@@ -2361,24 +4262,24 @@ fn rebase_to_dest_parent(
     }
 }
 
-fn edit_description(
+/// Opens the user's editor on a temporary file in the repo directory with the
+/// given content, and returns the edited content.
+fn edit_temp_file(
     ui: &Ui,
     repo: &ReadonlyRepo,
-    description: &str,
+    name: &str,
+    content: &str,
 ) -> Result<String, CommandError> {
     let random: u32 = rand::random();
-    let description_file_path = repo.repo_path().join(format!("description-{}.txt", random));
+    let file_path = repo.repo_path().join(format!("{}-{}.txt", name, random));
     {
-        let mut description_file = OpenOptions::new()
+        let mut file = OpenOptions::new()
             .write(true)
             .create_new(true)
             .truncate(true)
-            .open(&description_file_path)
-            .unwrap_or_else(|_| panic!("failed to open {:?} for write", &description_file_path));
-        description_file.write_all(description.as_bytes()).unwrap();
-        description_file
-            .write_all(b"\nJJ: Lines starting with \"JJ: \" (like this one) will be removed.\n")
-            .unwrap();
+            .open(&file_path)
+            .unwrap_or_else(|_| panic!("failed to open {:?} for write", &file_path));
+        file.write_all(content.as_bytes()).unwrap();
     }
 
     let editor = ui
@@ -2391,7 +4292,7 @@ fn edit_description(
     let editor_args = if args.len() > 1 { &args[1..] } else { &[] };
     let exit_status = std::process::Command::new(args[0])
         .args(editor_args)
-        .arg(&description_file_path)
+        .arg(&file_path)
         .status()
         .map_err(|_| CommandError::UserError(format!("Failed to run editor '{editor}'")))?;
     if !exit_status.success() {
@@ -2400,16 +4301,29 @@ fn edit_description(
         )));
     }
 
-    let mut description_file = OpenOptions::new()
+    let mut file = OpenOptions::new()
         .read(true)
-        .open(&description_file_path)
-        .unwrap_or_else(|_| panic!("failed to open {:?} for read", &description_file_path));
+        .open(&file_path)
+        .unwrap_or_else(|_| panic!("failed to open {:?} for read", &file_path));
     let mut buf = vec![];
-    description_file.read_to_end(&mut buf).unwrap();
-    let description = String::from_utf8(buf).unwrap();
+    file.read_to_end(&mut buf).unwrap();
+    let content = String::from_utf8(buf).unwrap();
     // Delete the file only if everything went well.
     // TODO: Tell the user the name of the file we left behind.
-    std::fs::remove_file(description_file_path).ok();
+    std::fs::remove_file(file_path).ok();
+    Ok(content)
+}
+
+fn edit_description(
+    ui: &Ui,
+    repo: &ReadonlyRepo,
+    description: &str,
+) -> Result<String, CommandError> {
+    let content = format!(
+        "{}\nJJ: Lines starting with \"JJ: \" (like this one) will be removed.\n",
+        description
+    );
+    let description = edit_temp_file(ui, repo, "description", &content)?;
     let mut lines = description
         .split_inclusive('\n')
         .filter(|line| !line.starts_with("JJ: "))
@@ -2429,7 +4343,7 @@ fn cmd_describe(
     let mut workspace_command = command.workspace_helper(ui)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
     workspace_command.check_rewriteable(&commit)?;
-    let description;
+    let mut description;
     if args.stdin {
         let mut buffer = String::new();
         io::stdin().read_to_string(&mut buffer).unwrap();
@@ -2439,6 +4353,9 @@ fn cmd_describe(
     } else {
         description = edit_description(ui, workspace_command.repo(), commit.description())?;
     }
+    if let Some(hooks) = workspace_command.git_hooks() {
+        description = hooks.commit_msg(&description)?;
+    }
     if description == *commit.description() {
         ui.write("Nothing changed.\n")?;
     } else {
@@ -2446,7 +4363,7 @@ fn cmd_describe(
             workspace_command.start_transaction(&format!("describe commit {}", commit.id().hex()));
         CommitBuilder::for_rewrite_from(ui.settings(), &commit)
             .set_description(description)
-            .write_to_repo(tx.mut_repo());
+            .try_write_to_repo(tx.mut_repo())?;
         workspace_command.finish_transaction(ui, tx)?;
     }
     Ok(())
@@ -2459,7 +4376,7 @@ fn cmd_open(ui: &mut Ui, command: &CommandHelper, args: &OpenArgs) -> Result<(),
     let mut tx = workspace_command.start_transaction(&format!("open commit {}", commit.id().hex()));
     CommitBuilder::for_rewrite_from(ui.settings(), &commit)
         .set_open(true)
-        .write_to_repo(tx.mut_repo());
+        .try_write_to_repo(tx.mut_repo())?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
@@ -2468,9 +4385,20 @@ fn cmd_close(ui: &mut Ui, command: &CommandHelper, args: &CloseArgs) -> Result<(
     let mut workspace_command = command.workspace_helper(ui)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
     workspace_command.check_rewriteable(&commit)?;
+    if let Some(hooks) = workspace_command.git_hooks() {
+        // The working copy only has the commit's contents if it's checked out here
+        if workspace_command
+            .repo()
+            .view()
+            .get_wc_commit_id(&workspace_command.workspace_id())
+            == Some(commit.id())
+        {
+            hooks.pre_commit()?;
+        }
+    }
     let mut commit_builder =
         CommitBuilder::for_rewrite_from(ui.settings(), &commit).set_open(false);
-    let description = if let Some(message) = &args.message {
+    let mut description = if let Some(message) = &args.message {
         message.to_string()
     } else if commit.description().is_empty() {
         edit_description(
@@ -2483,10 +4411,13 @@ fn cmd_close(ui: &mut Ui, command: &CommandHelper, args: &CloseArgs) -> Result<(
     } else {
         commit.description().to_string()
     };
+    if let Some(hooks) = workspace_command.git_hooks() {
+        description = hooks.commit_msg(&description)?;
+    }
     commit_builder = commit_builder.set_description(description);
     let mut tx =
         workspace_command.start_transaction(&format!("close commit {}", commit.id().hex()));
-    let new_commit = commit_builder.write_to_repo(tx.mut_repo());
+    let new_commit = commit_builder.try_write_to_repo(tx.mut_repo())?;
     let workspace_ids = tx
         .mut_repo()
         .view()
@@ -2497,7 +4428,7 @@ fn cmd_close(ui: &mut Ui, command: &CommandHelper, args: &CloseArgs) -> Result<(
             new_commit.id().clone(),
             new_commit.tree_id().clone(),
         )
-        .write_to_repo(tx.mut_repo());
+        .try_write_to_repo(tx.mut_repo())?;
         for workspace_id in workspace_ids {
             tx.mut_repo().edit(workspace_id, &new_checkout);
         }
@@ -2518,7 +4449,7 @@ fn cmd_duplicate(
     let mut_repo = tx.mut_repo();
     let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), &predecessor)
         .generate_new_change_id()
-        .write_to_repo(mut_repo);
+        .try_write_to_repo(mut_repo)?;
     ui.write("Created: ")?;
     ui.write_commit_summary(
         mut_repo.as_repo_ref(),
@@ -2600,6 +4531,18 @@ fn cmd_new(ui: &mut Ui, command: &CommandHelper, args: &NewArgs) -> Result<(), C
         "expected a non-empty list from clap"
     );
     let commits = resolve_base_revs(&workspace_command, &args.revisions)?;
+    if args.dry_run {
+        let conflict_paths = simulate_merge(workspace_command.repo().as_repo_ref(), &commits);
+        if conflict_paths.is_empty() {
+            writeln!(ui, "The merge would not have conflicts")?;
+        } else {
+            writeln!(ui, "The merge would have conflicts at these paths:")?;
+            for path in &conflict_paths {
+                writeln!(ui, "  {}", workspace_command.format_file_path(path))?;
+            }
+        }
+        return Ok(());
+    }
     let parent_ids = commits.iter().map(|c| c.id().clone()).collect();
     let mut tx = workspace_command.start_transaction("new empty commit");
     let merged_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &commits);
@@ -2607,13 +4550,99 @@ fn cmd_new(ui: &mut Ui, command: &CommandHelper, args: &NewArgs) -> Result<(), C
         CommitBuilder::for_new_commit(ui.settings(), parent_ids, merged_tree.id().clone())
             .set_description(args.message.clone())
             .set_open(true)
-            .write_to_repo(tx.mut_repo());
+            .try_write_to_repo(tx.mut_repo())?;
     let workspace_id = workspace_command.workspace_id();
     tx.mut_repo().edit(workspace_id, &new_commit);
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
 
+fn single_relative(candidates: Vec<Commit>, relation: &str) -> Result<Commit, CommandError> {
+    match candidates.as_slice() {
+        [] => Err(CommandError::UserError(format!(
+            "No {} revision found",
+            relation
+        ))),
+        [commit] => Ok(commit.clone()),
+        _ => Err(CommandError::UserError(format!(
+            "Ambiguous {} revision; choose one of:\n{}",
+            relation,
+            candidates
+                .iter()
+                .map(|commit| format!("  {}", short_commit_description(commit)))
+                .join("\n")
+        ))),
+    }
+}
+
+fn move_working_copy_to(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    target: &Commit,
+    edit: bool,
+) -> Result<(), CommandError> {
+    let workspace_id = workspace_command.workspace_id();
+    if edit {
+        workspace_command.check_rewriteable(target)?;
+        let mut tx =
+            workspace_command.start_transaction(&format!("edit commit {}", target.id().hex()));
+        tx.mut_repo().edit(workspace_id, target);
+        workspace_command.finish_transaction(ui, tx)?;
+    } else {
+        let mut tx = workspace_command
+            .start_transaction(&format!("new empty commit on {}", target.id().hex()));
+        let new_commit = CommitBuilder::for_new_commit(
+            ui.settings(),
+            vec![target.id().clone()],
+            target.tree_id().clone(),
+        )
+        .set_open(true)
+        .try_write_to_repo(tx.mut_repo())?;
+        tx.mut_repo().edit(workspace_id, &new_commit);
+        workspace_command.finish_transaction(ui, tx)?;
+    }
+    Ok(())
+}
+
+fn cmd_next(ui: &mut Ui, command: &CommandHelper, args: &NextArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let wc_commit = workspace_command.resolve_single_rev("@")?;
+    let mut current = if args.edit {
+        wc_commit.clone()
+    } else {
+        single_relative(wc_commit.parents(), "parent")?
+    };
+    let repo = workspace_command.repo();
+    for _ in 0..args.amount {
+        // Don't count the working copy commit itself as a child of its parent
+        let children = RevsetExpression::commit(current.id().clone())
+            .children()
+            .minus(&RevsetExpression::commit(wc_commit.id().clone()))
+            .evaluate(repo.as_repo_ref(), Some(&workspace_command.workspace_id()))?
+            .iter()
+            .commits(repo.store())
+            .collect::<Result<Vec<_>, _>>()?;
+        current = single_relative(children, "child")?;
+    }
+    move_working_copy_to(ui, &mut workspace_command, &current, args.edit)
+}
+
+fn cmd_prev(ui: &mut Ui, command: &CommandHelper, args: &PrevArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut current = workspace_command.resolve_single_rev("@")?;
+    // Without --edit, we create a new change on top of the target, so we need to
+    // go one step further back
+    let steps = if args.edit {
+        args.amount
+    } else {
+        args.amount + 1
+    };
+    for _ in 0..steps {
+        current = single_relative(current.parents(), "parent")?;
+    }
+    move_working_copy_to(ui, &mut workspace_command, &current, args.edit)
+}
+
 fn combine_messages(
     ui: &Ui,
     repo: &ReadonlyRepo,
@@ -2700,7 +4729,7 @@ from the source will be moved into the destination.
     } else {
         CommitBuilder::for_rewrite_from(ui.settings(), &source)
             .set_tree(new_source_tree_id)
-            .write_to_repo(mut_repo);
+            .try_write_to_repo(mut_repo)?;
     }
     if repo.index().is_ancestor(source.id(), destination.id()) {
         // If we're moving changes to a descendant, first rebase descendants onto the
@@ -2724,7 +4753,7 @@ from the source will be moved into the destination.
     CommitBuilder::for_rewrite_from(ui.settings(), &destination)
         .set_tree(new_destination_tree_id)
         .set_description(description)
-        .write_to_repo(mut_repo);
+        .try_write_to_repo(mut_repo)?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
@@ -2781,85 +4810,892 @@ from the source will be moved into the parent.
         .set_tree(new_parent_tree_id)
         .set_predecessors(vec![parent.id().clone(), commit.id().clone()])
         .set_description(description)
-        .write_to_repo(mut_repo);
+        .try_write_to_repo(mut_repo)?;
     if abandon_child {
         mut_repo.record_abandoned_commit(commit.id().clone());
     } else {
         // Commit the remainder on top of the new parent commit.
         CommitBuilder::for_rewrite_from(ui.settings(), &commit)
             .set_parents(vec![new_parent.id().clone()])
-            .write_to_repo(mut_repo);
+            .try_write_to_repo(mut_repo)?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn cmd_unsquash(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &UnsquashArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let commit = workspace_command.resolve_single_rev(&args.revision)?;
+    workspace_command.check_rewriteable(&commit)?;
+    let parents = commit.parents();
+    if parents.len() != 1 {
+        return Err(CommandError::UserError(String::from(
+            "Cannot unsquash merge commits",
+        )));
+    }
+    let parent = &parents[0];
+    workspace_command.check_rewriteable(parent)?;
+    let mut tx =
+        workspace_command.start_transaction(&format!("unsquash commit {}", commit.id().hex()));
+    let parent_base_tree =
+        merge_commit_trees(workspace_command.repo().as_repo_ref(), &parent.parents());
+    let new_parent_tree_id;
+    if args.interactive {
+        let instructions = format!(
+            "\
+You are moving changes from: {}
+into its child: {}
+
+The diff initially shows the parent commit's changes.
+
+Adjust the right side until it shows the contents you want to keep in
+the parent commit. The changes you edited out will be moved into the
+child commit. If you don't make any changes, then the operation will be
+aborted.
+",
+            short_commit_description(parent),
+            short_commit_description(&commit)
+        );
+        new_parent_tree_id =
+            workspace_command.edit_diff(ui, &parent_base_tree, &parent.tree(), &instructions)?;
+        if &new_parent_tree_id == parent_base_tree.id() {
+            return Err(CommandError::UserError(String::from("No changes selected")));
+        }
+    } else {
+        new_parent_tree_id = parent_base_tree.id().clone();
+    }
+    // Abandon the parent if it is now empty (always the case in the non-interactive
+    // case).
+    if &new_parent_tree_id == parent_base_tree.id() {
+        tx.mut_repo().record_abandoned_commit(parent.id().clone());
+        let description = combine_messages(ui, workspace_command.repo(), parent, &commit, true)?;
+        // Commit the new child on top of the parent's parents.
+        CommitBuilder::for_rewrite_from(ui.settings(), &commit)
+            .set_parents(parent.parent_ids().to_vec())
+            .set_description(description)
+            .try_write_to_repo(tx.mut_repo())?;
+    } else {
+        let new_parent = CommitBuilder::for_rewrite_from(ui.settings(), parent)
+            .set_tree(new_parent_tree_id)
+            .set_predecessors(vec![parent.id().clone(), commit.id().clone()])
+            .try_write_to_repo(tx.mut_repo())?;
+        // Commit the new child on top of the new parent.
+        CommitBuilder::for_rewrite_from(ui.settings(), &commit)
+            .set_parents(vec![new_parent.id().clone()])
+            .try_write_to_repo(tx.mut_repo())?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+/// Rewrites each commit with the given tree, oldest commit first. If a commit was
+/// already rebased by an earlier rewrite, the change to its tree is applied on
+/// top of the rebased commit. Descendants are rebased after each rewrite.
+/// Returns the rewritten commits.
+fn rewrite_commit_trees(
+    settings: &UserSettings,
+    mut_repo: &mut MutableRepo,
+    new_trees: Vec<(Commit, TreeId)>,
+) -> Result<Vec<Commit>, CommandError> {
+    let store = mut_repo.store().clone();
+    let mut rebased: HashMap<CommitId, CommitId> = HashMap::new();
+    let mut rewritten_commits = vec![];
+    for (commit, new_tree_id) in new_trees {
+        let current_commit = match rebased.get(commit.id()) {
+            Some(id) => store.get_commit(id)?,
+            None => commit.clone(),
+        };
+        let new_tree_id = if current_commit.id() == commit.id() {
+            new_tree_id
+        } else {
+            let new_tree = store.get_tree(&RepoPath::root(), &new_tree_id)?;
+            merge_trees(&current_commit.tree(), &commit.tree(), &new_tree)?
+        };
+        let new_commit = CommitBuilder::for_rewrite_from(settings, &current_commit)
+            .set_tree(new_tree_id)
+            .try_write_to_repo(mut_repo)?;
+        rewritten_commits.push(new_commit);
+        let mut rebaser = mut_repo.create_descendant_rebaser(settings);
+        rebaser.rebase_all()?;
+        let newly_rebased = rebaser.rebased().clone();
+        for new_id in rebased.values_mut() {
+            if let Some(newer_id) = newly_rebased.get(new_id) {
+                *new_id = newer_id.clone();
+            }
+        }
+        for (old_id, new_id) in newly_rebased {
+            rebased.entry(old_id).or_insert(new_id);
+        }
+    }
+    Ok(rewritten_commits)
+}
+
+/// A range of lines that differs between two versions of a file.
+struct LineHunk<'input> {
+    left: Range<usize>,
+    right: Range<usize>,
+    right_content: &'input [u8],
+}
+
+fn count_lines(content: &[u8]) -> usize {
+    content.split_inclusive(|b| *b == b'\n').count()
+}
+
+fn line_hunks<'input>(left: &'input [u8], right: &'input [u8]) -> Vec<LineHunk<'input>> {
+    let diff = Diff::for_tokenizer(&[left, right], &diff::find_line_ranges);
+    let mut hunks = vec![];
+    let mut left_line = 0;
+    let mut right_line = 0;
+    for hunk in diff.hunks() {
+        match hunk {
+            DiffHunk::Matching(content) => {
+                let num_lines = count_lines(content);
+                left_line += num_lines;
+                right_line += num_lines;
+            }
+            DiffHunk::Different(contents) => {
+                let num_left_lines = count_lines(contents[0]);
+                let num_right_lines = count_lines(contents[1]);
+                hunks.push(LineHunk {
+                    left: left_line..left_line + num_left_lines,
+                    right: right_line..right_line + num_right_lines,
+                    right_content: contents[1],
+                });
+                left_line += num_left_lines;
+                right_line += num_right_lines;
+            }
+        }
+    }
+    hunks
+}
+
+/// Finds the commit that last modified the given range of lines. `versions`
+/// has the content of the file before and after each commit, newest commit
+/// first, and `range` is relative to the newest version. An empty range means
+/// an insertion before that line. Returns the index of the commit in `versions`
+/// and the range relative to that commit's version of the file, or `None` if
+/// there's no single such commit.
+fn find_absorb_target(
+    versions: &[(Vec<u8>, Vec<u8>)],
+    mut range: Range<usize>,
+) -> Option<(usize, Range<usize>)> {
+    for (i, (parent_content, content)) in versions.iter().enumerate() {
+        let mut offset = 0isize;
+        for hunk in line_hunks(parent_content, content) {
+            if range.is_empty() {
+                if hunk.right.start < range.start && range.start < hunk.right.end {
+                    return Some((i, range));
+                } else if hunk.right.start <= range.start && range.start <= hunk.right.end {
+                    // Inserting next to lines modified by this commit is ambiguous
+                    return None;
+                }
+            } else if hunk.right.start <= range.start && range.end <= hunk.right.end {
+                return Some((i, range));
+            } else if hunk.right.start < range.end && range.start < hunk.right.end {
+                // Partially overlaps the lines modified by this commit
+                return None;
+            } else if hunk.right.is_empty()
+                && range.start < hunk.right.start
+                && hunk.right.start < range.end
+            {
+                // This commit deleted lines in the middle of the range
+                return None;
+            }
+            if hunk.right.end <= range.start {
+                offset += hunk.left.len() as isize - hunk.right.len() as isize;
+            }
+        }
+        range = (range.start as isize + offset) as usize..(range.end as isize + offset) as usize;
+    }
+    None
+}
+
+fn replace_lines(content: &[u8], mut replacements: Vec<(Range<usize>, &[u8])>) -> Vec<u8> {
+    replacements.sort_by_key(|(range, _)| (range.start, range.end));
+    let lines = content.split_inclusive(|b| *b == b'\n').collect_vec();
+    let mut result = vec![];
+    let mut next_line = 0;
+    for (range, new_content) in replacements {
+        for line in &lines[next_line..range.start] {
+            result.extend_from_slice(line);
+        }
+        result.extend_from_slice(new_content);
+        next_line = range.end;
+    }
+    for line in &lines[next_line..] {
+        result.extend_from_slice(line);
+    }
+    result
+}
+
+fn cmd_absorb(ui: &mut Ui, command: &CommandHelper, args: &AbsorbArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let source = workspace_command.resolve_single_rev(&args.from)?;
+    let repo = workspace_command.repo().clone();
+    let store = repo.store();
+    let candidates: HashSet<CommitId> = workspace_command
+        .resolve_revset(&args.into)?
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect();
+    // The chain of commits that changes can be moved into, newest first
+    let mut stack = vec![];
+    let mut parents = source.parents();
+    while parents.len() == 1 {
+        let commit = parents.pop().unwrap();
+        if commit.id() == store.root_commit_id() || !candidates.contains(commit.id()) {
+            break;
+        }
+        parents = commit.parents();
+        stack.push(commit);
+    }
+    if stack.is_empty() {
+        return Err(CommandError::UserError(
+            "No revisions to absorb changes into".to_string(),
+        ));
+    }
+
+    let read_file = |tree: &Tree, path: &RepoPath| -> Result<Option<Vec<u8>>, CommandError> {
+        match tree.path_value(path) {
+            None => Ok(Some(vec![])),
+            Some(TreeValue::Normal { id, .. }) => {
+                let mut content = vec![];
+                store.read_file(path, &id)?.read_to_end(&mut content)?;
+                Ok(Some(content))
+            }
+            Some(_) => Ok(None),
+        }
+    };
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    let source_parent_tree = stack[0].tree();
+    // For each commit in the stack, the new contents of the files it absorbs changes
+    // into
+    let mut absorbed_files: Vec<Vec<(RepoPath, TreeValue)>> = vec![vec![]; stack.len()];
+    for (path, diff) in source_parent_tree.diff(&source.tree(), matcher.as_ref()) {
+        let (before_executable, after_id) = match diff {
+            tree::Diff::Modified(
+                TreeValue::Normal {
+                    executable: before_executable,
+                    ..
+                },
+                TreeValue::Normal { id, .. },
+            ) => (before_executable, id),
+            _ => continue,
+        };
+        let mut source_content = vec![];
+        store
+            .read_file(&path, &after_id)?
+            .read_to_end(&mut source_content)?;
+        let mut versions = vec![];
+        for commit in &stack {
+            let parent_tree = merge_commit_trees(repo.as_repo_ref(), &commit.parents());
+            match (
+                read_file(&parent_tree, &path)?,
+                read_file(&commit.tree(), &path)?,
+            ) {
+                (Some(parent_content), Some(content)) => versions.push((parent_content, content)),
+                _ => break,
+            }
+        }
+        if versions.is_empty() {
+            continue;
+        }
+        let mut replacements = vec![vec![]; versions.len()];
+        for hunk in line_hunks(&versions[0].1, &source_content) {
+            if let Some((index, range)) = find_absorb_target(&versions, hunk.left) {
+                replacements[index].push((range, hunk.right_content));
+            }
+        }
+        for (index, replacements) in replacements.into_iter().enumerate() {
+            if replacements.is_empty() {
+                continue;
+            }
+            let new_content = replace_lines(&versions[index].1, replacements);
+            let executable = match stack[index].tree().path_value(&path) {
+                Some(TreeValue::Normal { executable, .. }) => executable,
+                _ => before_executable,
+            };
+            let id = store.write_file(&path, &mut new_content.as_slice())?;
+            absorbed_files[index].push((path.clone(), TreeValue::Normal { id, executable }));
+        }
+    }
+    if absorbed_files.iter().all(|files| files.is_empty()) {
+        ui.write("Nothing changed.\n")?;
+        return Ok(());
+    }
+
+    // Oldest commit first
+    let mut new_trees = vec![];
+    for (commit, files) in stack.iter().zip(absorbed_files).rev() {
+        if files.is_empty() {
+            continue;
+        }
+        let mut tree_builder = store.tree_builder(commit.tree_id().clone());
+        for (path, value) in files {
+            tree_builder.set(path, value);
+        }
+        new_trees.push((commit.clone(), tree_builder.write_tree()));
+    }
+    let mut tx =
+        workspace_command.start_transaction(&format!("absorb changes from {}", source.id().hex()));
+    let absorbed_commits = rewrite_commit_trees(ui.settings(), tx.mut_repo(), new_trees)?;
+    writeln!(
+        ui,
+        "Absorbed changes into {} revisions:",
+        absorbed_commits.len()
+    )?;
+    for commit in absorbed_commits.iter().rev() {
+        ui.write("  ")?;
+        ui.write_commit_summary(
+            tx.repo().as_repo_ref(),
+            &workspace_command.workspace_id(),
+            commit,
+        )?;
+        writeln!(ui)?;
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+/// Code formatter or other fix tool loaded from `[fix.tools.<name>]`.
+#[derive(Clone, Debug, serde::Deserialize)]
+struct FixTool {
+    /// Program and arguments to run. The file content is passed on stdin and
+    /// the fixed content is read from stdout.
+    command: Vec<String>,
+    /// File name extensions (without the leading `.`) to run the tool on.
+    #[serde(default)]
+    extensions: Vec<String>,
+}
+
+fn get_fix_tools(settings: &UserSettings) -> Result<Vec<(String, FixTool)>, CommandError> {
+    const TABLE_KEY: &str = "fix.tools";
+    let tools_table = match settings.config().get_table(TABLE_KEY) {
+        Ok(table) => table,
+        Err(config::ConfigError::NotFound(_)) => Default::default(),
+        Err(err) => return Err(err.into()),
+    };
+    let mut tools = vec![];
+    for (name, value) in tools_table.into_iter().sorted_by(|(a, _), (b, _)| a.cmp(b)) {
+        let tool: FixTool = value.try_deserialize().map_err(|e| {
+            // add config key, deserialize error is otherwise unclear
+            config::ConfigError::Message(format!("{TABLE_KEY}.{name}: {e}"))
+        })?;
+        if tool.command.is_empty() {
+            return Err(CommandError::ConfigError(format!(
+                "{TABLE_KEY}.{name}: command must not be empty"
+            )));
+        }
+        tools.push((name, tool));
+    }
+    Ok(tools)
+}
+
+/// Runs the tool on the content of a file. Returns `None` if the tool failed.
+fn run_fix_tool(
+    ui: &mut Ui,
+    name: &str,
+    tool: &FixTool,
+    cwd: &Path,
+    ui_path: &str,
+    content: Vec<u8>,
+) -> Result<Option<Vec<u8>>, CommandError> {
+    let mut child = std::process::Command::new(&tool.command[0])
+        .args(&tool.command[1..])
+        .current_dir(cwd)
+        .stdin(std::process::Stdio::piped())
+        .stdout(std::process::Stdio::piped())
+        .spawn()
+        .map_err(|err| {
+            CommandError::UserError(format!("Failed to run fix tool '{}': {}", name, err))
+        })?;
+    let mut stdin = child.stdin.take().unwrap();
+    // Write the input from another thread so the tool can't block on a full stdout
+    // pipe while we're still writing.
+    let writer = std::thread::spawn(move || stdin.write_all(&content));
+    let output = child.wait_with_output()?;
+    // The tool may exit without reading all of its input, so ignore write errors.
+    writer.join().unwrap().ok();
+    if !output.status.success() {
+        ui.write_warn(format!(
+            "Fix tool '{}' failed on {}; leaving it unchanged\n",
+            name, ui_path
+        ))?;
+        return Ok(None);
+    }
+    Ok(Some(output.stdout))
+}
+
+fn cmd_fix(ui: &mut Ui, command: &CommandHelper, args: &FixArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let tools = get_fix_tools(ui.settings())?;
+    if tools.is_empty() {
+        return Err(CommandError::UserError(
+            "No fix tools are configured (see the `fix.tools` config table)".to_string(),
+        ));
+    }
+    // Oldest revision first
+    let commits = workspace_command
+        .resolve_revset(&args.revisions)?
+        .into_iter()
+        .rev()
+        .collect_vec();
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+    }
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    let repo = workspace_command.repo().clone();
+    let store = repo.store();
+    let workspace_root = workspace_command.workspace_root().clone();
+    // The same file content is often present in many revisions, so only run each
+    // tool once on it.
+    let mut fixed_file_ids: HashMap<(usize, FileId), FileId> = HashMap::new();
+    let mut new_trees = vec![];
+    for commit in &commits {
+        let parent_tree = merge_commit_trees(repo.as_repo_ref(), &commit.parents());
+        let mut tree_builder = store.tree_builder(commit.tree_id().clone());
+        let mut changed = false;
+        for (path, diff) in parent_tree.diff(&commit.tree(), matcher.as_ref()) {
+            let (id, executable) = match diff.into_options().1 {
+                Some(TreeValue::Normal { id, executable }) => (id, executable),
+                _ => continue,
+            };
+            let file_path = path.to_internal_file_string();
+            let extension = Path::new(&file_path)
+                .extension()
+                .and_then(|extension| extension.to_str());
+            let tool_index = match tools.iter().position(|(_, tool)| {
+                tool.extensions
+                    .iter()
+                    .any(|tool_extension| Some(tool_extension.as_str()) == extension)
+            }) {
+                Some(tool_index) => tool_index,
+                None => continue,
+            };
+            let new_id = match fixed_file_ids.get(&(tool_index, id.clone())) {
+                Some(new_id) => new_id.clone(),
+                None => {
+                    let mut content = vec![];
+                    store.read_file(&path, &id)?.read_to_end(&mut content)?;
+                    let (name, tool) = &tools[tool_index];
+                    let new_id = match run_fix_tool(
+                        ui,
+                        name,
+                        tool,
+                        &workspace_root,
+                        &workspace_command.format_file_path(&path),
+                        content.clone(),
+                    )? {
+                        Some(new_content) if new_content != content => {
+                            store.write_file(&path, &mut new_content.as_slice())?
+                        }
+                        _ => id.clone(),
+                    };
+                    fixed_file_ids.insert((tool_index, id.clone()), new_id.clone());
+                    new_id
+                }
+            };
+            if new_id != id {
+                tree_builder.set(
+                    path,
+                    TreeValue::Normal {
+                        id: new_id,
+                        executable,
+                    },
+                );
+                changed = true;
+            }
+        }
+        if changed {
+            new_trees.push((commit.clone(), tree_builder.write_tree()));
+        }
+    }
+    if new_trees.is_empty() {
+        ui.write("Nothing changed.\n")?;
+        return Ok(());
+    }
+
+    let mut tx = workspace_command.start_transaction(&format!(
+        "fix {} commits in revset {}",
+        new_trees.len(),
+        args.revisions
+    ));
+    let fixed_commits = rewrite_commit_trees(ui.settings(), tx.mut_repo(), new_trees)?;
+    writeln!(ui, "Fixed {} commits", fixed_commits.len())?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+/// Checks out the commit in a new temporary directory and runs the command
+/// there.
+fn run_command_on_commit(
+    store: &Arc<Store>,
+    commit: &Commit,
+    command: &[String],
+) -> Result<std::process::ExitStatus, CommandError> {
+    // Use a fresh directory for each commit so files left behind by the command
+    // for one commit can't affect the next.
+    let temp_dir = tempfile::Builder::new().prefix("jj-run-").tempdir()?;
+    let wc_dir = temp_dir.path().join("wc");
+    let state_dir = temp_dir.path().join("state");
+    fs::create_dir(&wc_dir)?;
+    fs::create_dir(&state_dir)?;
+    let mut tree_state = TreeState::init(store.clone(), wc_dir.clone(), state_dir);
+    tree_state.check_out(&commit.tree()).map_err(|err| {
+        CommandError::InternalError(format!(
+            "Failed to check out commit {}: {}",
+            commit.id().hex(),
+            err
+        ))
+    })?;
+    std::process::Command::new(&command[0])
+        .args(&command[1..])
+        .current_dir(&wc_dir)
+        .env("JJ_COMMIT_ID", commit.id().hex())
+        .status()
+        .map_err(|err| UserError(format!("Failed to run '{}': {}", command[0], err)))
+}
+
+fn cmd_run(ui: &mut Ui, command: &CommandHelper, args: &RunArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    // Oldest revision first
+    let commits = workspace_command
+        .resolve_revset(&args.revisions)?
+        .into_iter()
+        .rev()
+        .collect_vec();
+    let repo = workspace_command.repo();
+    let mut num_failed = 0;
+    for commit in &commits {
+        let status = run_command_on_commit(repo.store(), commit, &args.command)?;
+        ui.write_commit_summary(
+            repo.as_repo_ref(),
+            &workspace_command.workspace_id(),
+            commit,
+        )?;
+        if status.success() {
+            ui.write(": passed\n")?;
+        } else {
+            writeln!(ui, ": failed ({})", status)?;
+            num_failed += 1;
+        }
+    }
+    if num_failed > 0 {
+        return Err(CommandError::UserError(format!(
+            "The command failed on {} of {} revisions",
+            num_failed,
+            commits.len()
+        )));
+    }
+    Ok(())
+}
+
+/// The bisection state, stored in the `bisect` file in the repo directory.
+#[derive(Default)]
+struct BisectState {
+    bad: Option<CommitId>,
+    good: Vec<CommitId>,
+    /// The revision currently being tested, if any
+    current: Option<CommitId>,
+}
+
+impl BisectState {
+    fn path(workspace_command: &WorkspaceCommandHelper) -> PathBuf {
+        workspace_command.repo().repo_path().join("bisect")
+    }
+
+    fn load(workspace_command: &WorkspaceCommandHelper) -> Result<Self, CommandError> {
+        let content = match fs::read_to_string(Self::path(workspace_command)) {
+            Ok(content) => content,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Self::default()),
+            Err(err) => return Err(err.into()),
+        };
+        let mut state = Self::default();
+        for line in content.lines() {
+            let invalid = || CommandError::InternalError(format!("Invalid bisect state: {line}"));
+            let (kind, hex) = line.split_once(' ').ok_or_else(invalid)?;
+            let id = CommitId::new(hex::decode(hex).map_err(|_| invalid())?);
+            match kind {
+                "bad" => state.bad = Some(id),
+                "good" => state.good.push(id),
+                "current" => state.current = Some(id),
+                _ => return Err(invalid()),
+            }
+        }
+        Ok(state)
+    }
+
+    fn save(&self, workspace_command: &WorkspaceCommandHelper) -> Result<(), CommandError> {
+        let mut content = String::new();
+        if let Some(id) = &self.bad {
+            content.push_str(&format!("bad {}\n", id.hex()));
+        }
+        for id in &self.good {
+            content.push_str(&format!("good {}\n", id.hex()));
+        }
+        if let Some(id) = &self.current {
+            content.push_str(&format!("current {}\n", id.hex()));
+        }
+        fs::write(Self::path(workspace_command), content)?;
+        Ok(())
+    }
+
+    /// Returns the revisions that may be the first bad revision, with the bad
+    /// revision first, or `None` if there's not enough information to start
+    /// bisecting.
+    fn candidates(
+        &self,
+        workspace_command: &WorkspaceCommandHelper,
+    ) -> Result<Option<Vec<CommitId>>, CommandError> {
+        let bad = match &self.bad {
+            Some(bad) if !self.good.is_empty() => bad,
+            _ => return Ok(None),
+        };
+        let repo = workspace_command.repo();
+        let revset = RevsetExpression::commit(bad.clone())
+            .ancestors()
+            .minus(&RevsetExpression::commits(self.good.clone()).ancestors())
+            .evaluate(repo.as_repo_ref(), Some(&workspace_command.workspace_id()))?;
+        let candidates = revset.iter().commit_ids().collect_vec();
+        if candidates.is_empty() {
+            return Err(UserError(
+                "The bad revision is an ancestor of a good revision".to_string(),
+            ));
+        }
+        Ok(Some(candidates))
+    }
+}
+
+/// Picks the candidate that splits the candidates most evenly into the ones
+/// that are its ancestors and the ones that aren't.
+fn bisect_midpoint(repo: RepoRef, candidates: &[CommitId]) -> CommitId {
+    let index = repo.index();
+    let num_candidates = candidates.len();
+    let entries = candidates
+        .iter()
+        .map(|id| index.entry_by_id(id).unwrap())
+        .collect_vec();
+    let candidate_by_pos: HashMap<IndexPosition, usize> = entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| (entry.position(), i))
+        .collect();
+    // Bit j of ancestors[i] is set if candidates[j] is an ancestor of
+    // candidates[i]. The candidates are the ancestors of the bad revision that
+    // aren't ancestors of a good one, so the paths between them only go through
+    // other candidates, and each candidate's ancestors can be found from its
+    // parents' if the parents are visited first.
+    let num_words = (num_candidates + 63) / 64;
+    let mut ancestors = vec![vec![0u64; num_words]; num_candidates];
+    for i in (0..num_candidates).sorted_by_key(|&i| entries[i].position()) {
+        let mut bits = vec![0u64; num_words];
+        bits[i / 64] |= 1 << (i % 64);
+        for parent_pos in entries[i].parent_positions() {
+            if let Some(&parent) = candidate_by_pos.get(&parent_pos) {
+                for (word, parent_word) in bits.iter_mut().zip(&ancestors[parent]) {
+                    *word |= parent_word;
+                }
+            }
+        }
+        ancestors[i] = bits;
+    }
+    let midpoint = (1..num_candidates)
+        .max_by_key(|&i| {
+            let num_ancestors: usize = ancestors[i]
+                .iter()
+                .map(|word| word.count_ones() as usize)
+                .sum();
+            // Prefer the newest candidate if there's a tie
+            (
+                num_ancestors.min(num_candidates - num_ancestors),
+                std::cmp::Reverse(i),
+            )
+        })
+        .unwrap();
+    candidates[midpoint].clone()
+}
+
+fn cmd_bisect(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    subcommand: &BisectCommands,
+) -> Result<(), CommandError> {
+    match subcommand {
+        BisectCommands::Good(command_matches) => cmd_bisect_good(ui, command, command_matches),
+        BisectCommands::Bad(command_matches) => cmd_bisect_bad(ui, command, command_matches),
+        BisectCommands::Run(command_matches) => cmd_bisect_run(ui, command, command_matches),
+        BisectCommands::Reset(command_matches) => cmd_bisect_reset(ui, command, command_matches),
+    }
+}
+
+fn resolve_bisect_targets(
+    workspace_command: &WorkspaceCommandHelper,
+    state: &BisectState,
+    revisions: &[String],
+) -> Result<Vec<Commit>, CommandError> {
+    if revisions.is_empty() {
+        return match &state.current {
+            Some(id) => Ok(vec![workspace_command.repo().store().get_commit(id)?]),
+            None => Ok(vec![workspace_command.resolve_single_rev("@")?]),
+        };
+    }
+    let mut commits = vec![];
+    for revision in revisions {
+        commits.push(workspace_command.resolve_single_rev(revision)?);
+    }
+    Ok(commits)
+}
+
+fn cmd_bisect_good(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &BisectGoodArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut state = BisectState::load(&workspace_command)?;
+    let commits = resolve_bisect_targets(&workspace_command, &state, &args.revisions)?;
+    state
+        .good
+        .extend(commits.iter().map(|commit| commit.id().clone()));
+    bisect_next_step(ui, &mut workspace_command, state, &commits, "good")
+}
+
+fn cmd_bisect_bad(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &BisectBadArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut state = BisectState::load(&workspace_command)?;
+    let revisions = args.revision.iter().cloned().collect_vec();
+    let commit = resolve_bisect_targets(&workspace_command, &state, &revisions)?
+        .pop()
+        .unwrap();
+    state.bad = Some(commit.id().clone());
+    bisect_next_step(ui, &mut workspace_command, state, &[commit], "bad")
+}
+
+/// Saves the state with the newly marked revisions and either reports the first
+/// bad revision or checks out the next revision to test.
+fn bisect_next_step(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    mut state: BisectState,
+    marked: &[Commit],
+    kind: &str,
+) -> Result<(), CommandError> {
+    let candidates = state.candidates(workspace_command)?;
+    for commit in marked {
+        write!(ui, "Marked ")?;
+        ui.write_commit_summary(
+            workspace_command.repo().as_repo_ref(),
+            &workspace_command.workspace_id(),
+            commit,
+        )?;
+        writeln!(ui, " as {kind}")?;
+    }
+    state.current = None;
+    match candidates {
+        None => {
+            state.save(workspace_command)?;
+        }
+        Some(candidates) if candidates.len() == 1 => {
+            state.save(workspace_command)?;
+            write_first_bad_revision(ui, workspace_command, &candidates[0])?;
+        }
+        Some(candidates) => {
+            let repo = workspace_command.repo();
+            let next = repo
+                .store()
+                .get_commit(&bisect_midpoint(repo.as_repo_ref(), &candidates))?;
+            state.current = Some(next.id().clone());
+            state.save(workspace_command)?;
+            writeln!(
+                ui,
+                "Bisecting: {} revisions left to test",
+                candidates.len() - 1
+            )?;
+            move_working_copy_to(ui, workspace_command, &next, false)?;
+        }
     }
-    workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
 
-fn cmd_unsquash(
+fn write_first_bad_revision(
+    ui: &mut Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    id: &CommitId,
+) -> Result<(), CommandError> {
+    let repo = workspace_command.repo();
+    let commit = repo.store().get_commit(id)?;
+    write!(ui, "The first bad revision is: ")?;
+    ui.write_commit_summary(
+        repo.as_repo_ref(),
+        &workspace_command.workspace_id(),
+        &commit,
+    )?;
+    writeln!(ui)?;
+    Ok(())
+}
+
+fn cmd_bisect_run(
     ui: &mut Ui,
     command: &CommandHelper,
-    args: &UnsquashArgs,
+    args: &BisectRunArgs,
 ) -> Result<(), CommandError> {
-    let mut workspace_command = command.workspace_helper(ui)?;
-    let commit = workspace_command.resolve_single_rev(&args.revision)?;
-    workspace_command.check_rewriteable(&commit)?;
-    let parents = commit.parents();
-    if parents.len() != 1 {
-        return Err(CommandError::UserError(String::from(
-            "Cannot unsquash merge commits",
-        )));
+    let workspace_command = command.workspace_helper(ui)?;
+    let mut state = BisectState::load(&workspace_command)?;
+    let repo = workspace_command.repo();
+    loop {
+        let candidates = state.candidates(&workspace_command)?.ok_or_else(|| {
+            UserError(
+                "Mark a bad and a good revision with `jj bisect bad` and `jj bisect good` first"
+                    .to_string(),
+            )
+        })?;
+        if candidates.len() == 1 {
+            state.current = None;
+            state.save(&workspace_command)?;
+            return write_first_bad_revision(ui, &workspace_command, &candidates[0]);
+        }
+        let commit = repo
+            .store()
+            .get_commit(&bisect_midpoint(repo.as_repo_ref(), &candidates))?;
+        let status = run_command_on_commit(repo.store(), &commit, &args.command)?;
+        ui.write_commit_summary(
+            repo.as_repo_ref(),
+            &workspace_command.workspace_id(),
+            &commit,
+        )?;
+        if status.success() {
+            writeln!(ui, ": good")?;
+            state.good.push(commit.id().clone());
+        } else {
+            writeln!(ui, ": bad")?;
+            state.bad = Some(commit.id().clone());
+        }
+        state.save(&workspace_command)?;
     }
-    let parent = &parents[0];
-    workspace_command.check_rewriteable(parent)?;
-    let mut tx =
-        workspace_command.start_transaction(&format!("unsquash commit {}", commit.id().hex()));
-    let parent_base_tree =
-        merge_commit_trees(workspace_command.repo().as_repo_ref(), &parent.parents());
-    let new_parent_tree_id;
-    if args.interactive {
-        let instructions = format!(
-            "\
-You are moving changes from: {}
-into its child: {}
-
-The diff initially shows the parent commit's changes.
+}
 
-Adjust the right side until it shows the contents you want to keep in
-the parent commit. The changes you edited out will be moved into the
-child commit. If you don't make any changes, then the operation will be
-aborted.
-",
-            short_commit_description(parent),
-            short_commit_description(&commit)
-        );
-        new_parent_tree_id =
-            workspace_command.edit_diff(ui, &parent_base_tree, &parent.tree(), &instructions)?;
-        if &new_parent_tree_id == parent_base_tree.id() {
-            return Err(CommandError::UserError(String::from("No changes selected")));
+fn cmd_bisect_reset(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &BisectResetArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    match fs::remove_file(BisectState::path(&workspace_command)) {
+        Ok(()) => {}
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {
+            return Err(UserError("Not bisecting".to_string()));
         }
-    } else {
-        new_parent_tree_id = parent_base_tree.id().clone();
-    }
-    // Abandon the parent if it is now empty (always the case in the non-interactive
-    // case).
-    if &new_parent_tree_id == parent_base_tree.id() {
-        tx.mut_repo().record_abandoned_commit(parent.id().clone());
-        let description = combine_messages(ui, workspace_command.repo(), parent, &commit, true)?;
-        // Commit the new child on top of the parent's parents.
-        CommitBuilder::for_rewrite_from(ui.settings(), &commit)
-            .set_parents(parent.parent_ids().to_vec())
-            .set_description(description)
-            .write_to_repo(tx.mut_repo());
-    } else {
-        let new_parent = CommitBuilder::for_rewrite_from(ui.settings(), parent)
-            .set_tree(new_parent_tree_id)
-            .set_predecessors(vec![parent.id().clone(), commit.id().clone()])
-            .write_to_repo(tx.mut_repo());
-        // Commit the new child on top of the new parent.
-        CommitBuilder::for_rewrite_from(ui.settings(), &commit)
-            .set_parents(vec![new_parent.id().clone()])
-            .write_to_repo(tx.mut_repo());
+        Err(err) => return Err(err.into()),
     }
-    workspace_command.finish_transaction(ui, tx)?;
+    writeln!(ui, "Cleared the bisection state")?;
     Ok(())
 }
 
@@ -2929,7 +5765,7 @@ side. If you don't make any changes, then the operation will be aborted.
         let mut_repo = tx.mut_repo();
         let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), &to_commit)
             .set_tree(tree_id)
-            .write_to_repo(mut_repo);
+            .try_write_to_repo(mut_repo)?;
         ui.write("Created ")?;
         ui.write_commit_summary(
             mut_repo.as_repo_ref(),
@@ -2970,7 +5806,7 @@ don't make any changes, then the operation will be aborted.",
         let mut_repo = tx.mut_repo();
         let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), &commit)
             .set_tree(tree_id)
-            .write_to_repo(mut_repo);
+            .try_write_to_repo(mut_repo)?;
         ui.write("Created ")?;
         ui.write_commit_summary(
             mut_repo.as_repo_ref(),
@@ -2983,63 +5819,113 @@ don't make any changes, then the operation will be aborted.",
     Ok(())
 }
 
+fn split_part_name(index: usize, num_parts: usize) -> String {
+    if num_parts == 2 {
+        ["First part", "Second part"][index].to_string()
+    } else {
+        format!("Part {}", index + 1)
+    }
+}
+
 fn cmd_split(ui: &mut Ui, command: &CommandHelper, args: &SplitArgs) -> Result<(), CommandError> {
+    if args.into < 2 {
+        return Err(CommandError::UserError(
+            "Can't split a revision into fewer than two parts".to_string(),
+        ));
+    }
     let mut workspace_command = command.workspace_helper(ui)?;
     let commit = workspace_command.resolve_single_rev(&args.revision)?;
     workspace_command.check_rewriteable(&commit)?;
-    let base_tree = merge_commit_trees(workspace_command.repo().as_repo_ref(), &commit.parents());
-    let instructions = format!(
-        "\
-You are splitting a commit in two: {}
+    let store = workspace_command.repo().store().clone();
+    let mut base_tree =
+        merge_commit_trees(workspace_command.repo().as_repo_ref(), &commit.parents());
+    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
+    // The trees of all parts except the last one, which has the commit's tree.
+    let mut part_tree_ids = vec![];
+    while part_tree_ids.len() < args.into - 1 {
+        let instructions = if part_tree_ids.is_empty() {
+            format!(
+                "\
+You are splitting a commit in {}: {}
 
 The diff initially shows the changes in the commit you're splitting.
 
 Adjust the right side until it shows the contents you want for the first
-commit. The remainder will be in the second commit. If you don't make
+commit. The remainder will be in the {} commit. If you don't make
 any changes, then the operation will be aborted.
 ",
-        short_commit_description(&commit)
-    );
-    let matcher = matcher_from_values(ui, workspace_command.workspace_root(), &args.paths)?;
-    let tree_id = workspace_command.select_diff(
-        ui,
-        &base_tree,
-        &commit.tree(),
-        &instructions,
-        args.paths.is_empty(),
-        matcher.as_ref(),
-    )?;
-    if &tree_id == commit.tree_id() {
+                if args.into == 2 {
+                    "two".to_string()
+                } else {
+                    format!("{} parts", args.into)
+                },
+                short_commit_description(&commit),
+                if args.into == 2 { "second" } else { "next" }
+            )
+        } else {
+            format!(
+                "\
+You are splitting a commit in {} parts: {}
+
+The diff shows the changes that remain after the previous parts.
+
+Adjust the right side until it shows the contents you want for part {}.
+The remainder will be in the next commit. If you don't make any changes,
+then all the remaining changes will be put in part {} and no more parts
+will be created.
+",
+                args.into,
+                short_commit_description(&commit),
+                part_tree_ids.len() + 1,
+                part_tree_ids.len() + 1
+            )
+        };
+        let tree_id = workspace_command.select_diff(
+            ui,
+            &base_tree,
+            &commit.tree(),
+            &instructions,
+            args.paths.is_empty(),
+            matcher.as_ref(),
+        )?;
+        if &tree_id == commit.tree_id() {
+            break;
+        }
+        base_tree = store.get_tree(&RepoPath::root(), &tree_id)?;
+        part_tree_ids.push(tree_id);
+    }
+    if part_tree_ids.is_empty() {
         ui.write("Nothing changed.\n")?;
     } else {
         let mut tx =
             workspace_command.start_transaction(&format!("split commit {}", commit.id().hex()));
-        let first_description = edit_description(
-            ui,
-            tx.base_repo(),
-            &("JJ: Enter commit description for the first part.\n".to_string()
-                + commit.description()),
-        )?;
-        let first_commit = CommitBuilder::for_rewrite_from(ui.settings(), &commit)
-            .set_tree(tree_id)
-            .set_description(first_description)
-            .write_to_repo(tx.mut_repo());
-        let second_description = edit_description(
-            ui,
-            tx.base_repo(),
-            &("JJ: Enter commit description for the second part.\n".to_string()
-                + commit.description()),
-        )?;
-        let second_commit = CommitBuilder::for_rewrite_from(ui.settings(), &commit)
-            .set_parents(vec![first_commit.id().clone()])
-            .set_tree(commit.tree_id().clone())
-            .generate_new_change_id()
-            .set_description(second_description)
-            .write_to_repo(tx.mut_repo());
+        part_tree_ids.push(commit.tree_id().clone());
+        let num_parts = part_tree_ids.len();
+        let mut parts: Vec<Commit> = vec![];
+        for (i, tree_id) in part_tree_ids.into_iter().enumerate() {
+            let part_name = split_part_name(i, num_parts);
+            let description = edit_description(
+                ui,
+                tx.base_repo(),
+                &(format!(
+                    "JJ: Enter commit description for the {}.\n",
+                    part_name.to_lowercase()
+                ) + commit.description()),
+            )?;
+            let mut commit_builder = CommitBuilder::for_rewrite_from(ui.settings(), &commit)
+                .set_tree(tree_id)
+                .set_description(description);
+            if let Some(previous_part) = parts.last() {
+                commit_builder = commit_builder
+                    .set_parents(vec![previous_part.id().clone()])
+                    .generate_new_change_id();
+            }
+            parts.push(commit_builder.try_write_to_repo(tx.mut_repo())?);
+        }
         let mut rebaser = DescendantRebaser::new(
             ui.settings(),
             tx.mut_repo(),
-            hashmap! { commit.id().clone() => hashset!{second_commit.id().clone()} },
+            hashmap! { commit.id().clone() => hashset!{parts.last().unwrap().id().clone()} },
             hashset! {},
         );
         rebaser.rebase_all()?;
@@ -3047,19 +5933,15 @@ any changes, then the operation will be aborted.
         if num_rebased > 0 {
             writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
         }
-        ui.write("First part: ")?;
-        ui.write_commit_summary(
-            tx.repo().as_repo_ref(),
-            &workspace_command.workspace_id(),
-            &first_commit,
-        )?;
-        ui.write("\nSecond part: ")?;
-        ui.write_commit_summary(
-            tx.repo().as_repo_ref(),
-            &workspace_command.workspace_id(),
-            &second_commit,
-        )?;
-        ui.write("\n")?;
+        for (i, part) in parts.iter().enumerate() {
+            write!(ui, "{}: ", split_part_name(i, num_parts))?;
+            ui.write_commit_summary(
+                tx.repo().as_repo_ref(),
+                &workspace_command.workspace_id(),
+                part,
+            )?;
+            writeln!(ui)?;
+        }
         workspace_command.finish_transaction(ui, tx)?;
     }
     Ok(())
@@ -3078,12 +5960,33 @@ fn cmd_rebase(ui: &mut Ui, command: &CommandHelper, args: &RebaseArgs) -> Result
     let mut workspace_command = command.workspace_helper(ui)?;
     let new_parents = resolve_base_revs(&workspace_command, &args.destination)?;
     if let Some(rev_str) = &args.revision {
-        rebase_revision(ui, &mut workspace_command, &new_parents, rev_str)?;
+        rebase_revision(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            rev_str,
+            args.dry_run,
+        )?;
     } else if let Some(source_str) = &args.source {
-        rebase_descendants(ui, &mut workspace_command, &new_parents, source_str)?;
+        rebase_descendants(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            source_str,
+            args.dry_run,
+        )?;
+    } else if args.interactive {
+        let branch_str = args.branch.as_deref().unwrap_or("@");
+        rebase_interactive(ui, &mut workspace_command, &new_parents, branch_str)?;
     } else {
         let branch_str = args.branch.as_deref().unwrap_or("@");
-        rebase_branch(ui, &mut workspace_command, &new_parents, branch_str)?;
+        rebase_branch(
+            ui,
+            &mut workspace_command,
+            &new_parents,
+            branch_str,
+            args.dry_run,
+        )?;
     }
     Ok(())
 }
@@ -3093,6 +5996,7 @@ fn rebase_branch(
     workspace_command: &mut WorkspaceCommandHelper,
     new_parents: &[Commit],
     branch_str: &str,
+    dry_run: bool,
 ) -> Result<(), CommandError> {
     let branch_commit = workspace_command.resolve_single_rev(branch_str)?;
     let mut tx = workspace_command
@@ -3119,10 +6023,14 @@ fn rebase_branch(
     {
         let root_commit = root_result?;
         workspace_command.check_rewriteable(&root_commit)?;
-        rebase_commit(ui.settings(), tx.mut_repo(), &root_commit, new_parents);
+        rebase_commit(ui.settings(), tx.mut_repo(), &root_commit, new_parents)?;
         num_rebased += 1;
     }
     num_rebased += tx.mut_repo().rebase_descendants(ui.settings())?;
+    if dry_run {
+        writeln!(ui, "Would rebase {} commits", num_rebased)?;
+        return report_rebase_conflicts(ui, workspace_command, &tx);
+    }
     writeln!(ui, "Rebased {} commits", num_rebased)?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
@@ -3133,6 +6041,7 @@ fn rebase_descendants(
     workspace_command: &mut WorkspaceCommandHelper,
     new_parents: &[Commit],
     source_str: &str,
+    dry_run: bool,
 ) -> Result<(), CommandError> {
     let old_commit = workspace_command.resolve_single_rev(source_str)?;
     workspace_command.check_rewriteable(&old_commit)?;
@@ -3141,8 +6050,12 @@ fn rebase_descendants(
         "rebase commit {} and descendants",
         old_commit.id().hex()
     ));
-    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents);
+    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents)?;
     let num_rebased = tx.mut_repo().rebase_descendants(ui.settings())? + 1;
+    if dry_run {
+        writeln!(ui, "Would rebase {} commits", num_rebased)?;
+        return report_rebase_conflicts(ui, workspace_command, &tx);
+    }
     writeln!(ui, "Rebased {} commits", num_rebased)?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
@@ -3153,13 +6066,14 @@ fn rebase_revision(
     workspace_command: &mut WorkspaceCommandHelper,
     new_parents: &[Commit],
     rev_str: &str,
+    dry_run: bool,
 ) -> Result<(), CommandError> {
     let old_commit = workspace_command.resolve_single_rev(rev_str)?;
     workspace_command.check_rewriteable(&old_commit)?;
     check_rebase_destinations(workspace_command, new_parents, &old_commit)?;
     let mut tx =
         workspace_command.start_transaction(&format!("rebase commit {}", old_commit.id().hex()));
-    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents);
+    rebase_commit(ui.settings(), tx.mut_repo(), &old_commit, new_parents)?;
     // Manually rebase children because we don't want to rebase them onto the
     // rewritten commit. (But we still want to record the commit as rewritten so
     // branches and the working copy get updated to the rewritten commit.)
@@ -3215,17 +6129,280 @@ fn rebase_revision(
             tx.mut_repo(),
             &child_commit,
             &new_child_parents?,
-        );
+        )?;
         num_rebased_descendants += 1;
     }
     num_rebased_descendants += tx.mut_repo().rebase_descendants(ui.settings())?;
     if num_rebased_descendants > 0 {
         writeln!(
             ui,
-            "Also rebased {} descendant commits onto parent of rebased commit",
+            "{} {} descendant commits onto parent of rebased commit",
+            if dry_run {
+                "Would also rebase"
+            } else {
+                "Also rebased"
+            },
             num_rebased_descendants
         )?;
     }
+    if dry_run {
+        return report_rebase_conflicts(ui, workspace_command, &tx);
+    }
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+/// Reports the new conflicts in the commits that `tx` rebased, which is then
+/// discarded.
+fn report_rebase_conflicts(
+    ui: &mut Ui,
+    workspace_command: &WorkspaceCommandHelper,
+    tx: &Transaction,
+) -> Result<(), CommandError> {
+    let mut_repo = tx.repo();
+    let old_heads = workspace_command.repo().view().heads().iter().cloned();
+    let new_heads = mut_repo.view().heads().iter().cloned();
+    let new_commits = RevsetExpression::commits(new_heads.collect())
+        .ancestors()
+        .minus(&RevsetExpression::commits(old_heads.collect()).ancestors());
+    let mut num_conflicted = 0;
+    for commit in new_commits
+        .evaluate(mut_repo.as_repo_ref(), None)?
+        .iter()
+        .commits(mut_repo.store())
+    {
+        let commit = commit?;
+        let old_commit = match commit.predecessors().as_slice() {
+            [predecessor] => predecessor.clone(),
+            _ => continue,
+        };
+        let conflict_paths = new_conflict_paths(&old_commit.tree(), &commit.tree());
+        if conflict_paths.is_empty() {
+            continue;
+        }
+        writeln!(
+            ui,
+            "Rebasing {} would cause conflicts at these paths:",
+            short_commit_description(&old_commit)
+        )?;
+        for path in &conflict_paths {
+            writeln!(ui, "  {}", workspace_command.format_file_path(path))?;
+        }
+        num_conflicted += 1;
+    }
+    if num_conflicted == 0 {
+        writeln!(ui, "The rebase would not cause conflicts")?;
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum RebaseTodoAction {
+    Pick,
+    Squash,
+    Fixup,
+    Drop,
+}
+
+fn parse_rebase_todo(
+    todo: &str,
+    commits: &[Commit],
+) -> Result<Vec<(RebaseTodoAction, Commit)>, CommandError> {
+    let mut steps = vec![];
+    let mut seen = HashSet::new();
+    for line in todo.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("JJ:") {
+            continue;
+        }
+        let mut words = line.split_whitespace();
+        let action = match words.next().unwrap() {
+            "pick" | "p" => RebaseTodoAction::Pick,
+            "squash" | "s" => RebaseTodoAction::Squash,
+            "fixup" | "f" => RebaseTodoAction::Fixup,
+            "drop" | "d" => RebaseTodoAction::Drop,
+            action => {
+                return Err(UserError(format!("Unknown rebase action: {action}")));
+            }
+        };
+        let hash = words
+            .next()
+            .ok_or_else(|| UserError(format!("Missing revision in line: {line}")))?;
+        let matches = commits
+            .iter()
+            .filter(|commit| commit.id().hex().starts_with(hash))
+            .collect_vec();
+        let commit = match matches.as_slice() {
+            [commit] => (*commit).clone(),
+            [] => {
+                return Err(UserError(format!(
+                    "Revision {hash} is not in the list of revisions to rebase"
+                )));
+            }
+            _ => return Err(UserError(format!("Revision {hash} is ambiguous"))),
+        };
+        if !seen.insert(commit.id().clone()) {
+            return Err(UserError(format!(
+                "Revision {hash} is listed more than once"
+            )));
+        }
+        steps.push((action, commit));
+    }
+    Ok(steps)
+}
+
+fn rebase_interactive(
+    ui: &mut Ui,
+    workspace_command: &mut WorkspaceCommandHelper,
+    new_parents: &[Commit],
+    branch_str: &str,
+) -> Result<(), CommandError> {
+    let branch_commit = workspace_command.resolve_single_rev(branch_str)?;
+    check_rebase_destinations(workspace_command, new_parents, &branch_commit)?;
+    let parent_ids = new_parents
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect_vec();
+    let range_expression = RevsetExpression::commits(parent_ids)
+        .range(&RevsetExpression::commit(branch_commit.id().clone()));
+    let store = workspace_command.repo().store().clone();
+    let mut commits: Vec<Commit> = range_expression
+        .evaluate(
+            workspace_command.repo().as_repo_ref(),
+            Some(&workspace_command.workspace_id()),
+        )?
+        .iter()
+        .commits(&store)
+        .try_collect()?;
+    if commits.is_empty() {
+        writeln!(ui, "Nothing to rebase")?;
+        return Ok(());
+    }
+    commits.reverse();
+    for (i, commit) in commits.iter().enumerate() {
+        workspace_command.check_rewriteable(commit)?;
+        if i > 0 && commit.parent_ids() != [commits[i - 1].id().clone()] {
+            return Err(UserError(format!(
+                "Cannot rebase non-linear history interactively (revision {} is a merge or \
+                 has a parent outside the range)",
+                short_commit_hash(commit.id())
+            )));
+        }
+    }
+
+    let mut todo = String::new();
+    for commit in &commits {
+        let first_line = commit.description().lines().next().unwrap_or_default();
+        todo.push_str(&format!(
+            "pick {} {}\n",
+            short_commit_hash(commit.id()),
+            first_line
+        ));
+    }
+    todo.push_str(&format!(
+        r#"
+JJ: Rebase {} revisions onto {}
+JJ:
+JJ: Commands:
+JJ: p, pick <revision> = use the revision
+JJ: s, squash <revision> = fold the revision into the previous one
+JJ: f, fixup <revision> = like "squash", but discard the revision's description
+JJ: d, drop <revision> = remove the revision
+JJ:
+JJ: The lines can be reordered. Removing a line also drops the revision.
+JJ: Lines starting with "JJ:" are ignored.
+"#,
+        commits.len(),
+        new_parents
+            .iter()
+            .map(|commit| short_commit_hash(commit.id()))
+            .join(", ")
+    ));
+    let todo = edit_temp_file(ui, workspace_command.repo(), "rebase-todo", &todo)?;
+    let steps = parse_rebase_todo(&todo, &commits)?;
+    if steps.is_empty() {
+        writeln!(ui, "Nothing to do")?;
+        return Ok(());
+    }
+
+    // Group each picked revision with the revisions squashed into it
+    let mut groups: Vec<Vec<(RebaseTodoAction, Commit)>> = vec![];
+    let mut dropped = commits
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect::<HashSet<_>>();
+    for (action, commit) in steps {
+        match action {
+            RebaseTodoAction::Drop => continue,
+            RebaseTodoAction::Pick => groups.push(vec![]),
+            RebaseTodoAction::Squash | RebaseTodoAction::Fixup => {
+                if groups.is_empty() {
+                    return Err(UserError(format!(
+                        "Cannot {} revision {} without a previous revision",
+                        if action == RebaseTodoAction::Squash {
+                            "squash"
+                        } else {
+                            "fixup"
+                        },
+                        short_commit_hash(commit.id())
+                    )));
+                }
+            }
+        }
+        dropped.remove(commit.id());
+        groups.last_mut().unwrap().push((action, commit));
+    }
+
+    let mut tx = workspace_command.start_transaction(&format!(
+        "rebase branch at {} interactively",
+        branch_commit.id().hex()
+    ));
+    let mut_repo = tx.mut_repo();
+    let mut num_rebased = 0;
+    let mut parents = new_parents.to_vec();
+    for group in groups {
+        let (_, first_commit) = &group[0];
+        let new_parent_ids = parents
+            .iter()
+            .map(|commit| commit.id().clone())
+            .collect_vec();
+        if group.len() == 1 && *first_commit.parent_ids() == new_parent_ids {
+            // The revision is already in place.
+            parents = vec![first_commit.clone()];
+            continue;
+        }
+        let mut tree = merge_commit_trees(mut_repo.as_repo_ref(), &parents);
+        let mut description = String::new();
+        for (action, commit) in &group {
+            let old_base_tree = merge_commit_trees(mut_repo.as_repo_ref(), &commit.parents());
+            let new_tree_id = merge_trees(&tree, &old_base_tree, &commit.tree())?;
+            tree = store.get_tree(&RepoPath::root(), &new_tree_id)?;
+            if *action != RebaseTodoAction::Fixup && !commit.description().is_empty() {
+                if !description.is_empty() {
+                    if !description.ends_with('\n') {
+                        description.push('\n');
+                    }
+                    description.push('\n');
+                }
+                description.push_str(commit.description());
+            }
+        }
+        let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), first_commit)
+            .set_parents(new_parent_ids)
+            .set_tree(tree.id().clone())
+            .set_description(description)
+            .try_write_to_repo(mut_repo)?;
+        for (_, commit) in &group[1..] {
+            mut_repo.record_rewritten_commit(commit.id().clone(), new_commit.id().clone());
+        }
+        num_rebased += group.len();
+        parents = vec![new_commit];
+    }
+    for commit_id in dropped {
+        mut_repo.record_abandoned_commit(commit_id);
+    }
+    num_rebased += mut_repo.rebase_descendants(ui.settings())?;
+    writeln!(ui, "Rebased {} commits", num_rebased)?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
@@ -3251,6 +6428,69 @@ fn check_rebase_destinations(
     Ok(())
 }
 
+fn cmd_evolve(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &EvolveArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().clone();
+    let visible_entries = RevsetExpression::all()
+        .evaluate(repo.as_repo_ref(), Some(&workspace_command.workspace_id()))?
+        .iter()
+        .collect_vec();
+    let visible_ids: HashSet<CommitId> = visible_entries
+        .iter()
+        .map(|entry| entry.commit_id())
+        .collect();
+    // Only changes with several visible commits can have descendants left on top
+    // of an obsolete commit.
+    let mut commits_by_change: HashMap<_, Vec<CommitId>> = HashMap::new();
+    for entry in &visible_entries {
+        commits_by_change
+            .entry(entry.change_id())
+            .or_default()
+            .push(entry.commit_id());
+    }
+    // Find the visible predecessors of the newest commits in each change. A
+    // visible predecessor that is not a head is only kept visible by the
+    // descendants that were left on top of it.
+    let mut rewrites = vec![];
+    for commit_ids in commits_by_change.values().filter(|ids| ids.len() > 1) {
+        for commit_id in commit_ids {
+            let commit = repo.store().get_commit(commit_id)?;
+            let mut to_visit = commit.predecessor_ids().to_vec();
+            let mut visited = HashSet::new();
+            while let Some(predecessor_id) = to_visit.pop() {
+                if !visited.insert(predecessor_id.clone()) {
+                    continue;
+                }
+                if visible_ids.contains(&predecessor_id) {
+                    if !repo.view().heads().contains(&predecessor_id) {
+                        rewrites.push((predecessor_id, commit_id.clone()));
+                    }
+                } else {
+                    let predecessor = repo.store().get_commit(&predecessor_id)?;
+                    to_visit.extend(predecessor.predecessor_ids().iter().cloned());
+                }
+            }
+        }
+    }
+    if rewrites.is_empty() {
+        writeln!(ui, "No revisions need to be rebased")?;
+        return Ok(());
+    }
+    let mut tx = workspace_command.start_transaction("evolve");
+    let mut_repo = tx.mut_repo();
+    for (old_id, new_id) in rewrites {
+        mut_repo.record_rewritten_commit(old_id, new_id);
+    }
+    let num_rebased = mut_repo.rebase_descendants(ui.settings())?;
+    writeln!(ui, "Rebased {} descendant commits", num_rebased)?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
 fn cmd_backout(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -3267,12 +6507,81 @@ fn cmd_backout(
         "back out commit {}",
         commit_to_back_out.id().hex()
     ));
-    back_out_commit(ui.settings(), tx.mut_repo(), &commit_to_back_out, &parents);
+    back_out_commit(ui.settings(), tx.mut_repo(), &commit_to_back_out, &parents)?;
     workspace_command.finish_transaction(ui, tx)?;
 
     Ok(())
 }
 
+fn cmd_parallelize(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &ParallelizeArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    // Oldest revision first
+    let commits = workspace_command
+        .resolve_revset(&args.revisions)?
+        .into_iter()
+        .rev()
+        .collect_vec();
+    if commits.len() < 2 {
+        ui.write("Nothing changed.\n")?;
+        return Ok(());
+    }
+    for commit in &commits {
+        workspace_command.check_rewriteable(commit)?;
+    }
+    for (parent, child) in commits.iter().tuple_windows() {
+        if child.parent_ids() != [parent.id().clone()] {
+            return Err(CommandError::UserError(format!(
+                "Revisions must form a linear range, but {} is not the only parent of {}",
+                short_commit_hash(parent.id()),
+                short_commit_hash(child.id())
+            )));
+        }
+    }
+    let first = &commits[0];
+    let last = commits.last().unwrap();
+    let repo = workspace_command.repo().clone();
+    let base_tree = merge_commit_trees(repo.as_repo_ref(), &first.parents());
+    let children_of_last = RevsetExpression::commit(last.id().clone())
+        .children()
+        .evaluate(repo.as_repo_ref(), Some(&workspace_command.workspace_id()))?
+        .iter()
+        .commits(repo.store())
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut tx = workspace_command.start_transaction(&format!(
+        "parallelize {} commits starting at {}",
+        commits.len(),
+        first.id().hex()
+    ));
+    let mut new_commits = vec![first.clone()];
+    for (parent, commit) in commits.iter().tuple_windows() {
+        let new_tree_id = merge_trees(&base_tree, &parent.tree(), &commit.tree())?;
+        let new_tree = repo.store().get_tree(&RepoPath::root(), &new_tree_id)?;
+        if new_tree.has_conflict() && !commit.tree().has_conflict() {
+            return Err(CommandError::UserError(format!(
+                "The changes in {} overlap with the changes in the revisions before it",
+                short_commit_description(commit)
+            )));
+        }
+        let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+            .set_parents(first.parent_ids().to_vec())
+            .set_tree(new_tree_id)
+            .try_write_to_repo(tx.mut_repo())?;
+        new_commits.push(new_commit);
+    }
+    // The children of the last revision need all the changes, so they become merge
+    // commits. Other descendants are rebased when the transaction is finished.
+    for child in &children_of_last {
+        rebase_commit(ui.settings(), tx.mut_repo(), child, &new_commits)?;
+    }
+    writeln!(ui, "Parallelized {} commits", commits.len())?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
 fn is_fast_forward(repo: RepoRef, branch_name: &str, new_target_id: &CommitId) -> bool {
     if let Some(current_target) = repo.view().get_local_branch(branch_name) {
         current_target
@@ -3284,6 +6593,71 @@ fn is_fast_forward(repo: RepoRef, branch_name: &str, new_target_id: &CommitId) -
     }
 }
 
+fn cmd_sign(ui: &mut Ui, command: &CommandHelper, args: &SignArgs) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let mut signer = Signer::from_settings(ui.settings()).ok_or_else(|| {
+        CommandError::ConfigError(
+            "No signing backend configured (set `signing.backend` to \"gpg\" or \"ssh\")"
+                .to_string(),
+        )
+    })?;
+    if let Some(key) = &args.key {
+        signer = signer.with_key(key.clone());
+    }
+    let mut to_sign = vec![];
+    for revset in &args.revisions {
+        let revisions = workspace_command.resolve_revset(revset)?;
+        workspace_command.check_non_empty(&revisions)?;
+        for commit in revisions {
+            workspace_command.check_rewriteable(&commit)?;
+            if !to_sign.contains(&commit) {
+                to_sign.push(commit);
+            }
+        }
+    }
+    // Sign parents before their children so the children can be rewritten onto
+    // the signed parents.
+    let index = workspace_command.repo().index();
+    to_sign.sort_by_key(|commit| index.entry_by_id(commit.id()).unwrap().position());
+    let transaction_description = if to_sign.len() == 1 {
+        format!("sign commit {}", to_sign[0].id().hex())
+    } else {
+        format!(
+            "sign commit {} and {} more",
+            to_sign[0].id().hex(),
+            to_sign.len() - 1
+        )
+    };
+    let mut tx = workspace_command.start_transaction(&transaction_description);
+    let mut signed_ids = HashMap::new();
+    for commit in &to_sign {
+        let new_parents = commit
+            .parent_ids()
+            .iter()
+            .map(|id| signed_ids.get(id).unwrap_or(id).clone())
+            .collect_vec();
+        let new_commit = CommitBuilder::for_rewrite_from(ui.settings(), commit)
+            .set_parents(new_parents)
+            .set_signer(Some(signer.clone()))
+            .try_write_to_repo(tx.mut_repo())
+            .map_err(|err| {
+                let message = match err {
+                    BackendError::SigningFailed(message) => message,
+                    err => err.to_string(),
+                };
+                CommandError::UserError(format!(
+                    "Failed to sign commit {}: {}",
+                    short_commit_hash(commit.id()),
+                    message
+                ))
+            })?;
+        signed_ids.insert(commit.id().clone(), new_commit.id().clone());
+    }
+    writeln!(ui, "Signed {} commits", to_sign.len())?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
 fn cmd_branch(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -3306,6 +6680,30 @@ fn cmd_branch(
         Ok(())
     }
 
+    fn parse_remote_branch_names<'a>(
+        view: &'a View,
+        names: &'a [String],
+    ) -> Result<Vec<(&'a str, &'a str)>, CommandError> {
+        names
+            .iter()
+            .map(|name| {
+                let (branch_name, remote_name) = name.rsplit_once('@').ok_or_else(|| {
+                    CommandError::UserError(format!(
+                        "Remote branch must be specified as <branch>@<remote>: {}",
+                        name
+                    ))
+                })?;
+                if view.get_remote_branch(branch_name, remote_name).is_none() {
+                    return Err(CommandError::UserError(format!(
+                        "No such remote branch: {}",
+                        name
+                    )));
+                }
+                Ok((branch_name, remote_name))
+            })
+            .try_collect()
+    }
+
     fn make_branch_term(branch_names: &[impl AsRef<str>]) -> String {
         match branch_names {
             [branch_name] => format!("branch {}", branch_name.as_ref()),
@@ -3416,6 +6814,32 @@ fn cmd_branch(
             workspace_command.finish_transaction(ui, tx)?;
         }
 
+        BranchSubcommand::Track { names } => {
+            let remote_branches = parse_remote_branch_names(view, names)?;
+            let mut tx =
+                workspace_command.start_transaction(&format!("track {}", make_branch_term(names)));
+            for (branch_name, remote_name) in remote_branches {
+                let mut_repo = tx.mut_repo();
+                mut_repo.set_remote_branch_tracked(branch_name, remote_name, true);
+                if mut_repo.get_local_branch(branch_name).is_none() {
+                    let remote_target = mut_repo.get_remote_branch(branch_name, remote_name);
+                    mut_repo.set_local_branch(branch_name.to_string(), remote_target.unwrap());
+                }
+            }
+            workspace_command.finish_transaction(ui, tx)?;
+        }
+
+        BranchSubcommand::Untrack { names } => {
+            let remote_branches = parse_remote_branch_names(view, names)?;
+            let mut tx = workspace_command
+                .start_transaction(&format!("untrack {}", make_branch_term(names)));
+            for (branch_name, remote_name) in remote_branches {
+                tx.mut_repo()
+                    .set_remote_branch_tracked(branch_name, remote_name, false);
+            }
+            workspace_command.finish_transaction(ui, tx)?;
+        }
+
         BranchSubcommand::List => {
             list_branches(ui, &workspace_command)?;
         }
@@ -3478,13 +6902,17 @@ fn list_branches(
             .iter()
             .sorted_by_key(|(name, _target)| name.to_owned())
         {
-            if Some(remote_target) == branch_target.local_target.as_ref() {
+            let untracked = branch_target.untracked_remotes.contains(remote);
+            if !untracked && Some(remote_target) == branch_target.local_target.as_ref() {
                 continue;
             }
             write!(ui, "  ")?;
             ui.stdout_formatter().add_label("branch".to_string())?;
             write!(ui, "@{}", remote)?;
             ui.stdout_formatter().remove_label()?;
+            if untracked {
+                write!(ui, " (untracked)")?;
+            }
             if let Some(local_target) = branch_target.local_target.as_ref() {
                 let remote_ahead_count = index
                     .walk_revs(&remote_target.adds(), &local_target.adds())
@@ -3543,7 +6971,7 @@ fn cmd_debug(
         }
         DebugCommands::WorkingCopy(_wc_matches) => {
             let workspace_command = command.workspace_helper(ui)?;
-            let wc = workspace_command.working_copy();
+            let wc = workspace_command.working_copy()?;
             writeln!(ui, "Current operation: {:?}", wc.operation_id())?;
             writeln!(ui, "Current tree: {:?}", wc.current_tree_id())?;
             for (file, state) in wc.file_states() {
@@ -3588,7 +7016,76 @@ fn cmd_debug(
             writeln!(ui, "{:#?}", op.store_operation())?;
             writeln!(ui, "{:#?}", op.view().store_view())?;
         }
+        DebugCommands::MigrateStore(migrate_args) => {
+            cmd_debug_migrate_store(ui, command, migrate_args)?;
+        }
+    }
+    Ok(())
+}
+
+fn cmd_debug_migrate_store(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &DebugMigrateStoreArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().clone();
+    if repo.store().backend_name() == args.backend {
+        return Err(UserError(format!(
+            "The repo already uses the '{}' backend",
+            args.backend
+        )));
+    }
+    if workspace_command.working_copy_shared_with_git() {
+        return Err(UserError(
+            "Can't migrate a repo whose working copy is shared with Git".to_string(),
+        ));
+    }
+    if repo.view().wc_commit_ids().len() > 1 {
+        return Err(UserError(
+            "Can't migrate a repo with multiple workspaces".to_string(),
+        ));
+    }
+    // The working copy records tree and operation ids, so keep it locked until
+    // they've been updated
+    let locked_wc = if workspace_command.working_copy().is_ok() {
+        Some(workspace_command.start_working_copy_mutation()?.0)
+    } else {
+        None
+    };
+    let backend_factory = |store_path: &Path| -> Box<dyn Backend> {
+        if args.backend == "git" {
+            Box::new(GitBackend::init_internal(store_path))
+        } else {
+            Box::new(LocalBackend::init(store_path))
+        }
+    };
+    let migration = match migrate::migrate_repo(&repo, backend_factory) {
+        Ok(migration) => migration,
+        Err(err) => {
+            if let Some(locked_wc) = locked_wc {
+                locked_wc.discard();
+            }
+            return Err(CommandError::InternalError(format!(
+                "Failed to migrate the repo: {}",
+                err
+            )));
+        }
+    };
+    if let Some(mut locked_wc) = locked_wc {
+        let new_tree_id = migration.tree_ids[locked_wc.old_tree_id()].clone();
+        locked_wc.remap_ids(new_tree_id, &migration.conflict_ids);
+        let new_op_id = migration.operation_ids[locked_wc.old_operation_id()].clone();
+        locked_wc.finish(new_op_id);
     }
+    writeln!(
+        ui,
+        "Migrated {} commits and {} operations to the '{}' backend",
+        // The root commit isn't stored
+        migration.commit_ids.len() - 1,
+        migration.operation_ids.len(),
+        args.backend
+    )?;
     Ok(())
 }
 
@@ -3686,60 +7183,83 @@ fn cmd_bench(
     Ok(())
 }
 
-fn format_timestamp(timestamp: &Timestamp) -> String {
-    let utc = Utc
-        .timestamp(
-            timestamp.timestamp.0 as i64 / 1000,
-            (timestamp.timestamp.0 % 1000) as u32 * 1000000,
-        )
-        .with_timezone(&FixedOffset::east(timestamp.tz_offset * 60));
-    utc.format("%Y-%m-%d %H:%M:%S.%3f %:z").to_string()
+struct OpTemplate;
+impl Template<Operation> for OpTemplate {
+    fn format(&self, op: &Operation, formatter: &mut dyn Formatter) -> io::Result<()> {
+        // TODO: Make this templated
+        formatter.add_label("id".to_string())?;
+        formatter.write_str(&op.id().hex()[0..12])?;
+        formatter.remove_label()?;
+        formatter.write_str(" ")?;
+        let metadata = &op.store_operation().metadata;
+        formatter.add_label("user".to_string())?;
+        formatter.write_str(&format!("{}@{}", metadata.username, metadata.hostname))?;
+        formatter.remove_label()?;
+        formatter.write_str(" ")?;
+        formatter.add_label("time".to_string())?;
+        formatter.write_str(&format!(
+            "{} - {}",
+            format_timestamp(&metadata.start_time),
+            format_timestamp(&metadata.end_time)
+        ))?;
+        formatter.remove_label()?;
+        formatter.write_str("\n")?;
+        formatter.add_label("description".to_string())?;
+        formatter.write_str(&metadata.description)?;
+        formatter.remove_label()?;
+        for (key, value) in &metadata.tags {
+            formatter.add_label("tags".to_string())?;
+            formatter.write_str(&format!("\n{}: {}", key, value))?;
+            formatter.remove_label()?;
+        }
+        Ok(())
+    }
 }
 
 fn cmd_op_log(
     ui: &mut Ui,
     command: &CommandHelper,
-    _args: &OperationLogArgs,
+    args: &OperationLogArgs,
 ) -> Result<(), CommandError> {
     let workspace_command = command.workspace_helper(ui)?;
     let repo = workspace_command.repo();
     let head_op = repo.operation().clone();
     let head_op_id = head_op.id().clone();
+    let tag_filters: Vec<(&str, Option<&str>)> = args
+        .tags
+        .iter()
+        .map(|tag| match tag.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (tag.as_str(), None),
+        })
+        .collect();
+    let is_filtered = args.username.is_some() || args.hostname.is_some() || !args.tags.is_empty();
+    let matches = |op: &Operation| {
+        let metadata = &op.store_operation().metadata;
+        args.username
+            .as_ref()
+            .map_or(true, |username| metadata.username == *username)
+            && args
+                .hostname
+                .as_ref()
+                .map_or(true, |hostname| metadata.hostname == *hostname)
+            && tag_filters
+                .iter()
+                .all(|(key, value)| match (metadata.tags.get(*key), value) {
+                    (Some(actual), Some(value)) => actual == value,
+                    (Some(_), None) => true,
+                    (None, _) => false,
+                })
+    };
+    ui.request_pager();
     let mut formatter = ui.stdout_formatter();
     let mut formatter = formatter.as_mut();
-    struct OpTemplate;
-    impl Template<Operation> for OpTemplate {
-        fn format(&self, op: &Operation, formatter: &mut dyn Formatter) -> io::Result<()> {
-            // TODO: Make this templated
-            formatter.add_label("id".to_string())?;
-            formatter.write_str(&op.id().hex()[0..12])?;
-            formatter.remove_label()?;
-            formatter.write_str(" ")?;
-            let metadata = &op.store_operation().metadata;
-            formatter.add_label("user".to_string())?;
-            formatter.write_str(&format!("{}@{}", metadata.username, metadata.hostname))?;
-            formatter.remove_label()?;
-            formatter.write_str(" ")?;
-            formatter.add_label("time".to_string())?;
-            formatter.write_str(&format!(
-                "{} - {}",
-                format_timestamp(&metadata.start_time),
-                format_timestamp(&metadata.end_time)
-            ))?;
-            formatter.remove_label()?;
-            formatter.write_str("\n")?;
-            formatter.add_label("description".to_string())?;
-            formatter.write_str(&metadata.description)?;
-            formatter.remove_label()?;
-            for (key, value) in &metadata.tags {
-                formatter.add_label("tags".to_string())?;
-                formatter.write_str(&format!("\n{}: {}", key, value))?;
-                formatter.remove_label()?;
-            }
-            Ok(())
+    let template: Box<dyn Template<Operation>> = match &args.template {
+        Some(template_text) => {
+            crate::template_parser::parse_operation_template(&head_op_id, template_text)
         }
-    }
-    let template = OpTemplate;
+        None => Box::new(OpTemplate),
+    };
 
     let mut graph = AsciiGraphDrawer::new(&mut formatter);
     for op in topo_order_reverse(
@@ -3747,10 +7267,17 @@ fn cmd_op_log(
         Box::new(|op: &Operation| op.id().clone()),
         Box::new(|op: &Operation| op.parents()),
     ) {
-        let mut edges = vec![];
-        for parent in op.parents() {
-            edges.push(Edge::direct(parent.id().clone()));
-        }
+        let edges = if is_filtered {
+            if !matches(&op) {
+                continue;
+            }
+            filtered_op_log_edges(&op, &matches)
+        } else {
+            op.parents()
+                .iter()
+                .map(|parent| Edge::direct(parent.id().clone()))
+                .collect()
+        };
         let is_head_op = op.id() == &head_op_id;
         let mut buffer = vec![];
         {
@@ -3776,6 +7303,269 @@ fn cmd_op_log(
     Ok(())
 }
 
+/// Edges from `op` to its closest ancestors that match the op log filter.
+/// Edges that skip over operations that don't match are indirect.
+fn filtered_op_log_edges(
+    op: &Operation,
+    matches: &impl Fn(&Operation) -> bool,
+) -> Vec<Edge<OperationId>> {
+    let mut edges = vec![];
+    let mut visited = HashSet::new();
+    let mut work = op
+        .parents()
+        .into_iter()
+        .map(|parent| (parent, true))
+        .collect_vec();
+    while let Some((ancestor, direct)) = work.pop() {
+        if !visited.insert(ancestor.id().clone()) {
+            continue;
+        }
+        if matches(&ancestor) {
+            if direct {
+                edges.push(Edge::direct(ancestor.id().clone()));
+            } else {
+                edges.push(Edge::indirect(ancestor.id().clone()));
+            }
+        } else {
+            work.extend(ancestor.parents().into_iter().map(|parent| (parent, false)));
+        }
+    }
+    edges
+}
+
+fn cmd_op_show(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &OperationShowArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let repo = workspace_command.repo().as_repo_ref();
+    let workspace_id = workspace_command.workspace_id();
+    let op = workspace_command.resolve_single_op(&args.operation)?;
+    let old_view = match op.parents().first() {
+        Some(parent_op) => View::new(parent_op.view().take_store_view()),
+        None => View::new(jujutsu_lib::op_store::View::default()),
+    };
+    let new_view = View::new(op.view().take_store_view());
+
+    {
+        let mut formatter = ui.stdout_formatter();
+        let formatter = formatter.as_mut();
+        formatter.add_label("op-log".to_string())?;
+        OpTemplate.format(&op, formatter)?;
+        formatter.remove_label()?;
+    }
+    writeln!(ui)?;
+    writeln!(ui)?;
+
+    let write_section = |ui: &mut Ui, title: &str, old_ids, new_ids| {
+        write_op_diff_section(ui, repo, &workspace_id, title, old_ids, new_ids)
+    };
+    let mut changed = false;
+    let old_wc_commit_ids = old_view.wc_commit_ids();
+    let new_wc_commit_ids = new_view.wc_commit_ids();
+    for workspace in old_wc_commit_ids
+        .keys()
+        .chain(new_wc_commit_ids.keys())
+        .sorted_by_key(|workspace| workspace.as_str())
+        .dedup()
+    {
+        changed |= write_section(
+            ui,
+            &format!("Working copy of {}", workspace.as_str()),
+            old_wc_commit_ids
+                .get(workspace)
+                .cloned()
+                .into_iter()
+                .collect(),
+            new_wc_commit_ids
+                .get(workspace)
+                .cloned()
+                .into_iter()
+                .collect(),
+        )?;
+    }
+    changed |= write_section(
+        ui,
+        "Heads",
+        old_view.heads().iter().cloned().sorted().collect(),
+        new_view.heads().iter().cloned().sorted().collect(),
+    )?;
+    let no_branch = BranchTarget::default();
+    for name in old_view
+        .branches()
+        .keys()
+        .chain(new_view.branches().keys())
+        .sorted()
+        .dedup()
+    {
+        let old_branch = old_view.branches().get(name).unwrap_or(&no_branch);
+        let new_branch = new_view.branches().get(name).unwrap_or(&no_branch);
+        changed |= write_section(
+            ui,
+            &format!("Branch {name}"),
+            ref_target_ids(old_branch.local_target.as_ref()),
+            ref_target_ids(new_branch.local_target.as_ref()),
+        )?;
+        for remote in old_branch
+            .remote_targets
+            .keys()
+            .chain(new_branch.remote_targets.keys())
+            .sorted()
+            .dedup()
+        {
+            changed |= write_section(
+                ui,
+                &format!("Branch {name}@{remote}"),
+                ref_target_ids(old_branch.remote_targets.get(remote)),
+                ref_target_ids(new_branch.remote_targets.get(remote)),
+            )?;
+        }
+    }
+    for name in old_view
+        .tags()
+        .keys()
+        .chain(new_view.tags().keys())
+        .sorted()
+        .dedup()
+    {
+        changed |= write_section(
+            ui,
+            &format!("Tag {name}"),
+            ref_target_ids(old_view.tags().get(name)),
+            ref_target_ids(new_view.tags().get(name)),
+        )?;
+    }
+    for name in old_view
+        .git_refs()
+        .keys()
+        .chain(new_view.git_refs().keys())
+        .sorted()
+        .dedup()
+    {
+        changed |= write_section(
+            ui,
+            &format!("Git ref {name}"),
+            ref_target_ids(old_view.git_refs().get(name)),
+            ref_target_ids(new_view.git_refs().get(name)),
+        )?;
+    }
+    changed |= write_section(
+        ui,
+        "Git HEAD",
+        old_view.git_head().into_iter().collect(),
+        new_view.git_head().into_iter().collect(),
+    )?;
+    if !changed {
+        writeln!(ui, "The operation didn't change the repo view.")?;
+    }
+
+    Ok(())
+}
+
+/// Writes the commits that were removed from and added to a part of the view
+/// (e.g. a branch) for `jj op show`. Returns false, without writing anything,
+/// if the part didn't change.
+fn write_op_diff_section(
+    ui: &mut Ui,
+    repo: RepoRef,
+    workspace_id: &WorkspaceId,
+    title: &str,
+    old_ids: Vec<CommitId>,
+    new_ids: Vec<CommitId>,
+) -> Result<bool, CommandError> {
+    let removed = old_ids
+        .iter()
+        .filter(|id| !new_ids.contains(id))
+        .collect_vec();
+    let added = new_ids
+        .iter()
+        .filter(|id| !old_ids.contains(id))
+        .collect_vec();
+    if removed.is_empty() && added.is_empty() {
+        return Ok(false);
+    }
+    writeln!(ui, "{title}:")?;
+    for (sign, id) in removed
+        .into_iter()
+        .map(|id| ("-", id))
+        .chain(added.into_iter().map(|id| ("+", id)))
+    {
+        write!(ui, "  {sign} ")?;
+        match repo.store().get_commit(id) {
+            Ok(commit) => ui.write_commit_summary(repo, workspace_id, &commit)?,
+            // The commit may have been garbage-collected since
+            Err(_) => write!(ui, "{}", short_commit_hash(id))?,
+        }
+        writeln!(ui)?;
+    }
+    Ok(true)
+}
+
+/// The commits a possibly absent (deleted) ref points to, for `jj op show`.
+fn ref_target_ids(target: Option<&RefTarget>) -> Vec<CommitId> {
+    target.map(RefTarget::adds).unwrap_or_default()
+}
+
+fn cmd_op_abandon(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &OperationAbandonArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper_no_snapshot(ui)?;
+    if workspace_command.repo().view().wc_commit_ids().len() > 1 {
+        return Err(UserError(
+            "Can't abandon operations in a repo with multiple workspaces".to_string(),
+        ));
+    }
+    let abandoned_ids: HashSet<OperationId> = match &args.operations {
+        None => workspace_command.expired_operations()?,
+        Some(operations) => match operations.split_once("..") {
+            Some((from_str, to_str)) => {
+                let to_op = workspace_command.resolve_single_op(to_str)?;
+                let excluded_ids = if from_str.is_empty() {
+                    HashSet::new()
+                } else {
+                    let from_op = workspace_command.resolve_single_op(from_str)?;
+                    operation_ancestor_ids(&from_op)
+                };
+                operation_ancestor_ids(&to_op)
+                    .into_iter()
+                    .filter(|id| !excluded_ids.contains(id))
+                    .collect()
+            }
+            None => {
+                let op = workspace_command.resolve_single_op(operations)?;
+                hashset! {op.id().clone()}
+            }
+        },
+    };
+    let abandonment = workspace_command.abandon_operations(&abandoned_ids)?;
+    if abandonment.abandoned_count == 0 {
+        writeln!(ui, "Nothing changed.")?;
+    } else {
+        writeln!(
+            ui,
+            "Abandoned {} operations and rewrote {} operations on top of them.",
+            abandonment.abandoned_count,
+            abandonment.rewritten_ids.len()
+        )?;
+    }
+    Ok(())
+}
+
+/// The ids of the operation and its ancestors, except for the root operation.
+fn operation_ancestor_ids(op: &Operation) -> HashSet<OperationId> {
+    bfs(
+        vec![op.clone()],
+        Box::new(|op: &Operation| op.id().clone()),
+        Box::new(|op: &Operation| op.parents()),
+    )
+    .filter(|op| !op.parents().is_empty())
+    .map(|op| op.id().clone())
+    .collect()
+}
+
 fn cmd_op_undo(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -3800,8 +7590,15 @@ fn cmd_op_undo(
     let repo_loader = workspace_command.repo().loader();
     let bad_repo = repo_loader.load_at(&bad_op);
     let parent_repo = repo_loader.load_at(&parent_ops[0]);
-    tx.mut_repo().merge(&bad_repo, &parent_repo);
+    let merge_report = tx.mut_repo().merge(&bad_repo, &parent_repo);
     workspace_command.finish_transaction(ui, tx)?;
+    // Refs that later operations changed again can't be restored cleanly
+    write_conflicted_refs_warning(
+        ui,
+        "Later operations changed some of the refs the undone operation changed. These refs \
+         are now conflicted:",
+        &merge_report.conflicted_refs,
+    )?;
 
     Ok(())
 }
@@ -3817,7 +7614,44 @@ fn cmd_op_restore(
         .start_transaction(&format!("restore to operation {}", target_op.id().hex()));
     tx.mut_repo().set_view(target_op.view().take_store_view());
     workspace_command.finish_transaction(ui, tx)?;
-
+
+    Ok(())
+}
+
+fn cmd_op_export_view(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &OperationExportViewArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let bytes = view_snapshot::export_view(workspace_command.repo().view().store_view());
+    let path = ui.cwd().join(&args.path);
+    fs::write(&path, bytes)
+        .map_err(|err| UserError(format!("Failed to write {}: {}", path.display(), err)))?;
+    Ok(())
+}
+
+fn cmd_op_import_view(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &OperationImportViewArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let path = ui.cwd().join(&args.path);
+    let bytes = fs::read(&path)
+        .map_err(|err| UserError(format!("Failed to read {}: {}", path.display(), err)))?;
+    let snapshot = view_snapshot::parse_view_snapshot(&bytes)
+        .map_err(|err| UserError(format!("Failed to read {}: {}", path.display(), err)))?;
+    let mut tx =
+        workspace_command.start_transaction(&format!("import view from {}", args.path.display()));
+    view_snapshot::import_view(tx.mut_repo(), &snapshot).map_err(|err| match err {
+        err @ ViewSnapshotError::MissingCommit(_) => UserError(format!(
+            "{}\nFetch the commits from the repo the view was exported from first.",
+            err
+        )),
+        err => CommandError::InternalError(err.to_string()),
+    })?;
+    workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
 
@@ -3827,9 +7661,17 @@ fn cmd_operation(
     subcommand: &OperationCommands,
 ) -> Result<(), CommandError> {
     match subcommand {
+        OperationCommands::Abandon(command_matches) => cmd_op_abandon(ui, command, command_matches),
         OperationCommands::Log(command_matches) => cmd_op_log(ui, command, command_matches),
+        OperationCommands::Show(command_matches) => cmd_op_show(ui, command, command_matches),
         OperationCommands::Restore(command_matches) => cmd_op_restore(ui, command, command_matches),
         OperationCommands::Undo(command_matches) => cmd_op_undo(ui, command, command_matches),
+        OperationCommands::ExportView(command_matches) => {
+            cmd_op_export_view(ui, command, command_matches)
+        }
+        OperationCommands::ImportView(command_matches) => {
+            cmd_op_import_view(ui, command, command_matches)
+        }
     }
 }
 
@@ -3846,6 +7688,12 @@ fn cmd_workspace(
         WorkspaceCommands::List(command_matches) => {
             cmd_workspace_list(ui, command, command_matches)
         }
+        WorkspaceCommands::Root(command_matches) => {
+            cmd_workspace_root(ui, command, command_matches)
+        }
+        WorkspaceCommands::UpdateStale(command_matches) => {
+            cmd_workspace_update_stale(ui, command, command_matches)
+        }
     }
 }
 
@@ -3922,7 +7770,7 @@ fn cmd_workspace_add(
         new_workspace_command.workspace_id(),
         ui.settings(),
         &new_checkout_commit,
-    );
+    )?;
     new_workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
@@ -3971,10 +7819,270 @@ fn cmd_workspace_list(
     Ok(())
 }
 
+fn cmd_workspace_root(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &WorkspaceRootArgs,
+) -> Result<(), CommandError> {
+    let workspace_root = command.workspace_root(ui)?;
+    writeln!(ui, "{}", workspace_root.display())?;
+    Ok(())
+}
+
+fn cmd_workspace_update_stale(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &WorkspaceUpdateStaleArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper_no_snapshot(ui)?;
+    workspace_command.update_stale_working_copy(ui)
+}
+
+fn cmd_stash(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    subcommand: &StashCommands,
+) -> Result<(), CommandError> {
+    match subcommand {
+        StashCommands::Push(command_matches) => cmd_stash_push(ui, command, command_matches),
+        StashCommands::Pop(command_matches) => cmd_stash_pop(ui, command, command_matches),
+        StashCommands::List(command_matches) => cmd_stash_list(ui, command, command_matches),
+    }
+}
+
+/// Returns the stashes as (name, commit) pairs, in branch name order.
+fn list_stashes(
+    ui: &Ui,
+    workspace_command: &WorkspaceCommandHelper,
+) -> Result<Vec<(String, Commit)>, CommandError> {
+    let repo = workspace_command.repo();
+    let prefix = ui.settings().stash_branch_prefix();
+    let mut stashes = vec![];
+    for (branch_name, branch_target) in repo.view().branches() {
+        if let Some(name) = branch_name.strip_prefix(&prefix) {
+            match &branch_target.local_target {
+                Some(RefTarget::Normal(id)) => {
+                    stashes.push((name.to_string(), repo.store().get_commit(id)?));
+                }
+                Some(RefTarget::Conflict { .. }) => {
+                    return Err(CommandError::UserError(format!(
+                        "Stash {} is conflicted (use `jj branch set {}` to resolve)",
+                        name, branch_name
+                    )));
+                }
+                None => {}
+            }
+        }
+    }
+    Ok(stashes)
+}
+
+fn cmd_stash_push(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &StashPushArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let workspace_id = workspace_command.workspace_id();
+    let repo = workspace_command.repo();
+    let wc_commit = match repo.view().get_wc_commit_id(&workspace_id) {
+        Some(wc_commit_id) => repo.store().get_commit(wc_commit_id)?,
+        None => {
+            return Err(UserError(
+                "Nothing checked out in this workspace".to_string(),
+            ));
+        }
+    };
+    workspace_command.check_rewriteable(&wc_commit)?;
+    let parents = wc_commit.parents();
+    let parent_tree = merge_commit_trees(repo.as_repo_ref(), &parents);
+    if wc_commit.tree_id() == parent_tree.id() {
+        return Err(UserError("No changes to stash".to_string()));
+    }
+    let prefix = ui.settings().stash_branch_prefix();
+    let name = match &args.name {
+        Some(name) => name.clone(),
+        None => (0..)
+            .map(|i: u32| i.to_string())
+            .find(|name| {
+                repo.view()
+                    .get_local_branch(&format!("{prefix}{name}"))
+                    .is_none()
+            })
+            .unwrap(),
+    };
+    let branch_name = format!("{prefix}{name}");
+    if repo.view().get_local_branch(&branch_name).is_some() {
+        return Err(UserError(format!("Stash {name} already exists")));
+    }
+
+    let mut tx = workspace_command.start_transaction(&format!("stash working copy as {name}"));
+    let stash_commit = match &args.message {
+        Some(message) => CommitBuilder::for_rewrite_from(ui.settings(), &wc_commit)
+            .set_description(message.clone())
+            .try_write_to_repo(tx.mut_repo())?,
+        None => wc_commit.clone(),
+    };
+    tx.mut_repo()
+        .set_local_branch(branch_name, RefTarget::Normal(stash_commit.id().clone()));
+    let parent_ids = parents.iter().map(|c| c.id().clone()).collect();
+    let new_wc_commit =
+        CommitBuilder::for_new_commit(ui.settings(), parent_ids, parent_tree.id().clone())
+            .set_open(true)
+            .try_write_to_repo(tx.mut_repo())?;
+    tx.mut_repo().edit(workspace_id.clone(), &new_wc_commit);
+    write!(ui, "Saved working copy changes as stash {name}: ")?;
+    ui.write_commit_summary(tx.repo().as_repo_ref(), &workspace_id, &stash_commit)?;
+    writeln!(ui)?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn cmd_stash_pop(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    args: &StashPopArgs,
+) -> Result<(), CommandError> {
+    let mut workspace_command = command.workspace_helper(ui)?;
+    let stashes = list_stashes(ui, &workspace_command)?;
+    let (name, stash_commit) = match &args.name {
+        Some(name) => stashes
+            .into_iter()
+            .find(|(stash_name, _)| stash_name == name)
+            .ok_or_else(|| UserError(format!("No such stash: {name}")))?,
+        None => stashes
+            .into_iter()
+            .max_by_key(|(_, commit)| commit.committer().timestamp.timestamp.0)
+            .ok_or_else(|| UserError("There are no stashes".to_string()))?,
+    };
+    let workspace_id = workspace_command.workspace_id();
+    let repo = workspace_command.repo();
+    let wc_commit = match repo.view().get_wc_commit_id(&workspace_id) {
+        Some(wc_commit_id) => repo.store().get_commit(wc_commit_id)?,
+        None => {
+            return Err(UserError(
+                "Nothing checked out in this workspace".to_string(),
+            ));
+        }
+    };
+    if wc_commit.id() == stash_commit.id() {
+        return Err(UserError(format!(
+            "Stash {name} is already checked out in this workspace"
+        )));
+    }
+    workspace_command.check_rewriteable(&wc_commit)?;
+    let stash_parent_tree = merge_commit_trees(repo.as_repo_ref(), &stash_commit.parents());
+    // Apply the stashed changes onto the working-copy commit
+    let new_tree_id = merge_trees(&wc_commit.tree(), &stash_parent_tree, &stash_commit.tree())?;
+    let description = if wc_commit.description().is_empty() {
+        stash_commit.description().to_string()
+    } else {
+        wc_commit.description().to_string()
+    };
+
+    let mut tx = workspace_command.start_transaction(&format!("pop stash {name}"));
+    let mut_repo = tx.mut_repo();
+    CommitBuilder::for_rewrite_from(ui.settings(), &wc_commit)
+        .set_tree(new_tree_id)
+        .set_description(description)
+        .try_write_to_repo(mut_repo)?;
+    mut_repo.remove_local_branch(&format!("{}{name}", ui.settings().stash_branch_prefix()));
+    mut_repo.record_abandoned_commit(stash_commit.id().clone());
+    writeln!(ui, "Restored stash {name}")?;
+    workspace_command.finish_transaction(ui, tx)?;
+    Ok(())
+}
+
+fn cmd_stash_list(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    _args: &StashListArgs,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let workspace_id = workspace_command.workspace_id();
+    for (name, commit) in list_stashes(ui, &workspace_command)? {
+        write!(ui, "{}: ", name)?;
+        ui.write_commit_summary(
+            workspace_command.repo().as_repo_ref(),
+            &workspace_id,
+            &commit,
+        )?;
+        writeln!(ui)?;
+    }
+    Ok(())
+}
+
+fn cmd_resolutions(
+    ui: &mut Ui,
+    command: &CommandHelper,
+    subcommand: &ResolutionsCommands,
+) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let cache = workspace_command
+        .repo()
+        .store()
+        .resolution_cache()
+        .ok_or_else(|| {
+            CommandError::UserError(
+                "Conflict resolutions are not recorded. Set `merge.reuse-resolutions = true` \
+                 to record them."
+                    .to_string(),
+            )
+        })?;
+    let resolutions = cache.list()?;
+    match subcommand {
+        ResolutionsCommands::List(_) => {
+            for resolution in &resolutions {
+                let num_lines = resolution
+                    .resolution
+                    .split_inclusive(|b| *b == b'\n')
+                    .count();
+                writeln!(
+                    ui,
+                    "{}: {}-sided conflict resolved to {} lines",
+                    &resolution.id[..12],
+                    resolution.num_sides,
+                    num_lines
+                )?;
+            }
+        }
+        ResolutionsCommands::Forget(args) => {
+            let mut forgotten_ids = vec![];
+            if args.all {
+                forgotten_ids.extend(resolutions.iter().map(|resolution| &resolution.id));
+            }
+            for prefix in &args.ids {
+                let matches = resolutions
+                    .iter()
+                    .filter(|resolution| resolution.id.starts_with(prefix.as_str()))
+                    .collect_vec();
+                match matches.as_slice() {
+                    [resolution] => forgotten_ids.push(&resolution.id),
+                    [] => {
+                        return Err(CommandError::UserError(format!(
+                            "No recorded resolution with id {prefix}"
+                        )));
+                    }
+                    _ => {
+                        return Err(CommandError::UserError(format!(
+                            "Resolution id prefix {prefix} is ambiguous"
+                        )));
+                    }
+                }
+            }
+            for id in &forgotten_ids {
+                cache.remove(id)?;
+            }
+            writeln!(ui, "Forgot {} resolutions", forgotten_ids.len())?;
+        }
+    }
+    Ok(())
+}
+
 fn cmd_sparse(ui: &mut Ui, command: &CommandHelper, args: &SparseArgs) -> Result<(), CommandError> {
     if args.list {
         let workspace_command = command.workspace_helper(ui)?;
-        for path in workspace_command.working_copy().sparse_patterns() {
+        for path in workspace_command.working_copy()?.sparse_patterns() {
             let ui_path = workspace_command.format_file_path(&path);
             writeln!(ui, "{}", ui_path)?;
         }
@@ -4009,6 +8117,70 @@ fn cmd_sparse(ui: &mut Ui, command: &CommandHelper, args: &SparseArgs) -> Result
     Ok(())
 }
 
+fn cmd_gc(ui: &mut Ui, command: &CommandHelper, args: &GcArgs) -> Result<(), CommandError> {
+    let workspace_command = command.workspace_helper(ui)?;
+    let keep_newer = SystemTime::now() - Duration::from_secs(args.min_age);
+    let stats =
+        gc::collect_garbage(workspace_command.repo(), keep_newer, args.dry_run).map_err(|err| {
+            match err {
+                GcError::BackendError(BackendError::Other(message)) => {
+                    CommandError::UserError(message)
+                }
+                err => CommandError::InternalError(err.to_string()),
+            }
+        })?;
+    if args.dry_run {
+        writeln!(
+            ui,
+            "Would remove {} objects ({} bytes)",
+            stats.removed_objects, stats.removed_bytes
+        )?;
+    } else {
+        writeln!(
+            ui,
+            "Removed {} objects ({} bytes)",
+            stats.removed_objects, stats.removed_bytes
+        )?;
+    }
+    Ok(())
+}
+
+fn cmd_fsck(ui: &mut Ui, command: &CommandHelper, _args: &FsckArgs) -> Result<(), CommandError> {
+    // Don't snapshot, since that would write to a store that may be corrupt
+    let workspace_command = command.workspace_helper_no_snapshot(ui)?;
+    let report = fsck::check_repo(workspace_command.repo()).map_err(|err| match err {
+        FsckError::BackendError(BackendError::Other(message)) => CommandError::UserError(message),
+        err => CommandError::InternalError(err.to_string()),
+    })?;
+    writeln!(ui, "Checked {} objects", report.checked_objects)?;
+    if report.corrupt_objects.is_empty() {
+        return Ok(());
+    }
+    for corrupt_object in &report.corrupt_objects {
+        let object = &corrupt_object.object;
+        let problem = if corrupt_object.missing {
+            "Missing"
+        } else {
+            "Corrupt"
+        };
+        writeln!(ui, "{} {} {}", problem, object.type_name(), object.hex())?;
+        let commits = corrupt_object
+            .commits
+            .iter()
+            .map(short_commit_hash)
+            .join(" ");
+        writeln!(ui, "  Used by commits: {}", commits)?;
+    }
+    ui.write_hint(
+        "Hint: Restore the objects from a backup or another clone of the repo, or abandon the \
+         affected commits with `jj abandon`.\n",
+    )?;
+    Err(CommandError::UserError(format!(
+        "Found {} corrupt or missing objects",
+        report.corrupt_objects.len()
+    )))
+}
+
 fn get_git_repo(store: &Store) -> Result<git2::Repository, CommandError> {
     match store.git_repo() {
         None => Err(CommandError::UserError(
@@ -4018,6 +8190,68 @@ fn get_git_repo(store: &Store) -> Result<git2::Repository, CommandError> {
     }
 }
 
+/// Runs `f` with callbacks that ask for credentials with the askpass program
+/// configured like in Git (`GIT_ASKPASS`, `core.askPass` or `SSH_ASKPASS`),
+/// show the transfer progress on the terminal, and use the ssh options from
+/// the `git.ssh.*` settings.
+fn with_remote_callbacks<T>(
+    settings: &UserSettings,
+    git_repo: &git2::Repository,
+    f: impl FnOnce(git::RemoteCallbacks<'_>) -> T,
+) -> T {
+    let askpass_program = std::env::var("GIT_ASKPASS")
+        .ok()
+        .or_else(|| git_repo.config().ok()?.get_string("core.askPass").ok())
+        .or_else(|| std::env::var("SSH_ASKPASS").ok())
+        .filter(|program| !program.is_empty());
+    let mut get_username_password = |url: &str, username: Option<&str>| {
+        let askpass_program = askpass_program.as_ref()?;
+        let username = match username {
+            Some(username) => username.to_string(),
+            None => run_askpass(askpass_program, &format!("Username for '{url}': "))?,
+        };
+        let password = run_askpass(askpass_program, &format!("Password for '{url}': "))?;
+        Some((username, password))
+    };
+    let key_path = match settings.git_ssh_key_path() {
+        Some(key_path) => Some(expand_git_path(key_path)),
+        None => std::env::var("HOME")
+            .ok()
+            .map(|home_dir| PathBuf::from(home_dir).join(".ssh").join("id_rsa")),
+    };
+    let mut progress_renderer = GitProgressRenderer::new();
+    let mut update_progress = |progress: &git::Progress| progress_renderer.update(progress);
+    let progress: Option<&mut dyn FnMut(&git::Progress)> = if atty::is(Stream::Stderr) {
+        Some(&mut update_progress)
+    } else {
+        None
+    };
+    f(git::RemoteCallbacks {
+        get_username_password: Some(&mut get_username_password),
+        progress,
+        ssh_options: git::SshOptions {
+            use_agent: settings.git_ssh_use_agent(),
+            key_path,
+            host_key_checking: settings.git_ssh_host_key_checking(),
+        },
+    })
+}
+
+/// Asks the user for something with an askpass program, which prints the
+/// answer.
+fn run_askpass(program: &str, prompt: &str) -> Option<String> {
+    let output = std::process::Command::new(program)
+        .arg(prompt)
+        .stderr(std::process::Stdio::inherit())
+        .output()
+        .ok()?;
+    if !output.status.success() {
+        return None;
+    }
+    let answer = String::from_utf8(output.stdout).ok()?;
+    Some(answer.trim_end_matches(['\n', '\r']).to_string())
+}
+
 fn cmd_git_remote_add(
     ui: &mut Ui,
     command: &CommandHelper,
@@ -4089,11 +8323,56 @@ fn cmd_git_fetch(
     let mut workspace_command = command.workspace_helper(ui)?;
     let repo = workspace_command.repo();
     let git_repo = get_git_repo(repo.store())?;
+    let refspecs = if args.refspec.is_empty() {
+        ui.settings().git_fetch_refspecs(&args.remote)
+    } else {
+        args.refspec.clone()
+    };
+    let refspecs: Vec<RefSpec> = refspecs
+        .iter()
+        .map(|spec| RefSpec::parse_fetch(&args.remote, spec))
+        .try_collect()
+        .map_err(|err| CommandError::UserError(err.to_string()))?;
+    let negotiation_tips = workspace_command
+        .resolve_revset(&ui.settings().git_fetch_negotiation_tips())?
+        .iter()
+        .map(|commit| commit.id().clone())
+        .collect_vec();
     let mut tx =
         workspace_command.start_transaction(&format!("fetch from git remote {}", &args.remote));
-    git::fetch(tx.mut_repo(), &git_repo, &args.remote)
+    let mut index_outdated = false;
+    if let Some(depth) = args.depth {
+        let fetch = git::fetch_with_depth(
+            tx.mut_repo(),
+            &git_repo,
+            &args.remote,
+            &refspecs,
+            &negotiation_tips,
+            depth,
+        )
+        .map_err(|err| CommandError::UserError(err.to_string()))?;
+        index_outdated = fetch.index_outdated;
+    } else {
+        with_remote_callbacks(ui.settings(), &git_repo, |callbacks| {
+            git::fetch_with_negotiation_tips(
+                tx.mut_repo(),
+                &git_repo,
+                &args.remote,
+                &refspecs,
+                &negotiation_tips,
+                callbacks,
+            )
+        })
         .map_err(|err| CommandError::UserError(err.to_string()))?;
+    }
     workspace_command.finish_transaction(ui, tx)?;
+    if index_outdated {
+        // The shallow boundary moved, so some indexed commits have different
+        // parents now
+        Arc::get_mut(workspace_command.repo_mut())
+            .unwrap()
+            .reindex();
+    }
     Ok(())
 }
 
@@ -4145,7 +8424,7 @@ fn cmd_git_clone(
         fs::create_dir(&wc_path).unwrap();
     }
 
-    let clone_result = do_git_clone(ui, command, source, &wc_path);
+    let clone_result = do_git_clone(ui, command, source, &wc_path, args.depth);
     if clone_result.is_err() {
         // Canonicalize because fs::remove_dir_all() doesn't seem to like e.g.
         // `/some/path/.`
@@ -4180,7 +8459,7 @@ fn cmd_git_clone(
                     workspace_command.workspace_id(),
                     ui.settings(),
                     &commit,
-                );
+                )?;
             }
             workspace_command.finish_transaction(ui, checkout_tx)?;
         }
@@ -4193,6 +8472,7 @@ fn do_git_clone(
     command: &CommandHelper,
     source: &str,
     wc_path: &Path,
+    depth: Option<u32>,
 ) -> Result<(WorkspaceCommandHelper, Option<String>), CommandError> {
     let (workspace, repo) = Workspace::init_internal_git(ui.settings(), wc_path)?;
     let git_repo = get_git_repo(repo.store())?;
@@ -4201,32 +8481,77 @@ fn do_git_clone(
     let remote_name = "origin";
     git_repo.remote(remote_name, source).unwrap();
     let mut fetch_tx = workspace_command.start_transaction("fetch from git remote into empty repo");
-    let maybe_default_branch =
-        git::fetch(fetch_tx.mut_repo(), &git_repo, remote_name).map_err(|err| match err {
-            GitFetchError::NoSuchRemote(_) => {
-                panic!("shouldn't happen as we just created the git remote")
-            }
-            GitFetchError::InternalGitError(err) => {
-                CommandError::UserError(format!("Fetch failed: {err}"))
-            }
-        })?;
+    let fetch_result = match depth {
+        Some(depth) => {
+            git::fetch_with_depth(fetch_tx.mut_repo(), &git_repo, remote_name, &[], &[], depth)
+                .map(|fetch| fetch.default_branch)
+        }
+        None => with_remote_callbacks(ui.settings(), &git_repo, |callbacks| {
+            git::fetch(fetch_tx.mut_repo(), &git_repo, remote_name, &[], callbacks)
+        }),
+    };
+    let maybe_default_branch = fetch_result.map_err(|err| match err {
+        GitFetchError::NoSuchRemote(_) => {
+            panic!("shouldn't happen as we just created the git remote")
+        }
+        GitFetchError::AuthenticationFailed(_)
+        | GitFetchError::HostKeyVerificationFailed(_)
+        | GitFetchError::GitCommandFailed(_) => CommandError::UserError(err.to_string()),
+        GitFetchError::InternalGitError(err) => {
+            CommandError::UserError(format!("Fetch failed: {err}"))
+        }
+    })?;
     workspace_command.finish_transaction(ui, fetch_tx)?;
     Ok((workspace_command, maybe_default_branch))
 }
 
+/// Picks the remote to push to when none was given: the single remote tracked
+/// by `branch_name`, or "origin".
+fn default_push_remote(view: &View, branch_name: Option<&str>) -> String {
+    let tracked_remotes = branch_name
+        .and_then(|branch_name| view.get_branch(branch_name))
+        .map(|branch_target| {
+            branch_target
+                .remote_targets
+                .keys()
+                .filter(|remote| !branch_target.untracked_remotes.contains(*remote))
+                .collect_vec()
+        })
+        .unwrap_or_default();
+    match tracked_remotes.as_slice() {
+        [remote] => remote.to_string(),
+        _ => "origin".to_string(),
+    }
+}
+
 fn cmd_git_push(
     ui: &mut Ui,
     command: &CommandHelper,
     args: &GitPushArgs,
 ) -> Result<(), CommandError> {
     let mut workspace_command = command.workspace_helper(ui)?;
+    let remote = match &args.remote {
+        Some(remote) => remote.clone(),
+        None => default_push_remote(workspace_command.repo().view(), args.branch.as_deref()),
+    };
+    let push_refspecs = if args.refspec.is_empty() {
+        ui.settings().git_push_refspecs(&remote)
+    } else {
+        args.refspec.clone()
+    };
+    let push_refspecs: Vec<RefSpec> = push_refspecs
+        .iter()
+        .map(|spec| RefSpec::parse_push(spec))
+        .try_collect()
+        .map_err(|err| CommandError::UserError(err.to_string()))?;
 
     let mut tx;
     let mut branch_updates = vec![];
     if let Some(branch_name) = &args.branch {
         if let Some(update) = branch_updates_for_push(
             workspace_command.repo().as_repo_ref(),
-            &args.remote,
+            &remote,
+            &push_refspecs,
             branch_name,
         )? {
             branch_updates.push((branch_name.clone(), update));
@@ -4234,54 +8559,99 @@ fn cmd_git_push(
             writeln!(
                 ui,
                 "Branch {}@{} already matches {}",
-                branch_name, &args.remote, branch_name
+                branch_name, &remote, branch_name
             )?;
         }
         tx = workspace_command.start_transaction(&format!(
             "push branch {branch_name} to git remote {}",
-            &args.remote
+            &remote
         ));
-    } else if let Some(change_str) = &args.change {
-        let commit = workspace_command.resolve_single_rev(change_str)?;
-        let branch_name = format!(
-            "{}{}",
-            ui.settings().push_branch_prefix(),
-            commit.change_id().hex()
-        );
-        if workspace_command
-            .repo()
-            .view()
-            .get_local_branch(&branch_name)
-            .is_none()
-        {
-            writeln!(
-                ui,
-                "Creating branch {} for revision {}",
-                branch_name, change_str
-            )?;
+    } else if !args.change.is_empty() {
+        let mut commits = vec![];
+        for change_str in &args.change {
+            commits.extend(workspace_command.resolve_revset(change_str)?);
+        }
+        let commits = commits
+            .into_iter()
+            .unique_by(|commit| commit.id().clone())
+            .collect_vec();
+        if commits.is_empty() {
+            return Err(UserError(
+                "No revisions to push (the revsets given to --change are empty)".to_string(),
+            ));
         }
+        let change_ids = commits
+            .iter()
+            .map(|commit| short_change_hash(commit.change_id()))
+            .join(", ");
         tx = workspace_command.start_transaction(&format!(
-            "push change {} to git remote {}",
-            commit.change_id().hex(),
-            &args.remote
+            "push {} {change_ids} to git remote {}",
+            if commits.len() == 1 {
+                "change"
+            } else {
+                "changes"
+            },
+            &remote
         ));
-        tx.mut_repo()
-            .set_local_branch(branch_name.clone(), RefTarget::Normal(commit.id().clone()));
-        if let Some(update) =
-            branch_updates_for_push(tx.mut_repo().as_repo_ref(), &args.remote, &branch_name)?
-        {
-            branch_updates.push((branch_name.clone(), update));
-        } else {
-            writeln!(
-                ui,
-                "Branch {}@{} already matches {}",
-                branch_name, &args.remote, branch_name
-            )?;
+        for commit in &commits {
+            let branch_name = push_branch_name(ui.settings(), tx.base_repo().view(), commit);
+            if tx
+                .base_repo()
+                .view()
+                .get_local_branch(&branch_name)
+                .is_none()
+            {
+                writeln!(
+                    ui,
+                    "Creating branch {} for revision {}",
+                    branch_name,
+                    short_commit_hash(commit.id())
+                )?;
+            }
+            tx.mut_repo()
+                .set_local_branch(branch_name.clone(), RefTarget::Normal(commit.id().clone()));
+            if let Some(update) = branch_updates_for_push(
+                tx.mut_repo().as_repo_ref(),
+                &remote,
+                &push_refspecs,
+                &branch_name,
+            )? {
+                branch_updates.push((branch_name.clone(), update));
+            } else {
+                writeln!(
+                    ui,
+                    "Branch {}@{} already matches {}",
+                    branch_name, &remote, branch_name
+                )?;
+            }
         }
+    } else if !args.refspec.is_empty() {
+        let view = workspace_command.repo().view();
+        for (branch_name, branch_target) in view.branches() {
+            if !push_refspecs
+                .iter()
+                .any(|refspec| refspec.map(branch_name).is_some())
+            {
+                continue;
+            }
+            let remote_ref = remote_ref_for_push(&push_refspecs, branch_name);
+            let push_action =
+                classify_push_to_remote_ref(view, branch_name, branch_target, &remote, &remote_ref);
+            if let BranchPushAction::Update(update) = push_action {
+                branch_updates.push((branch_name.clone(), update));
+            }
+        }
+        tx = workspace_command.start_transaction(&format!(
+            "push branches matching refspecs to git remote {}",
+            &remote
+        ));
     } else if args.all {
         // TODO: Is it useful to warn about conflicted branches?
-        for (branch_name, branch_target) in workspace_command.repo().view().branches() {
-            let push_action = classify_branch_push_action(branch_target, &args.remote);
+        let view = workspace_command.repo().view();
+        for (branch_name, branch_target) in view.branches() {
+            let remote_ref = remote_ref_for_push(&push_refspecs, branch_name);
+            let push_action =
+                classify_push_to_remote_ref(view, branch_name, branch_target, &remote, &remote_ref);
             match push_action {
                 BranchPushAction::AlreadyMatches => {}
                 BranchPushAction::LocalConflicted => {}
@@ -4292,7 +8662,7 @@ fn cmd_git_push(
             }
         }
         tx = workspace_command
-            .start_transaction(&format!("push all branches to git remote {}", &args.remote));
+            .start_transaction(&format!("push all branches to git remote {}", &remote));
     } else {
         match workspace_command
             .repo()
@@ -4306,9 +8676,17 @@ fn cmd_git_push(
             }
             Some(checkout) => {
                 let desired_target = Some(RefTarget::Normal(checkout.clone()));
-                for (branch_name, branch_target) in workspace_command.repo().view().branches() {
+                let view = workspace_command.repo().view();
+                for (branch_name, branch_target) in view.branches() {
                     if branch_target.local_target == desired_target {
-                        let push_action = classify_branch_push_action(branch_target, &args.remote);
+                        let remote_ref = remote_ref_for_push(&push_refspecs, branch_name);
+                        let push_action = classify_push_to_remote_ref(
+                            view,
+                            branch_name,
+                            branch_target,
+                            &remote,
+                            &remote_ref,
+                        );
                         match push_action {
                             BranchPushAction::AlreadyMatches => {}
                             BranchPushAction::LocalConflicted => {}
@@ -4326,12 +8704,12 @@ fn cmd_git_push(
         }
         tx = workspace_command.start_transaction(&format!(
             "push current branch(es) to git remote {}",
-            &args.remote
+            &remote
         ));
     }
 
     if branch_updates.is_empty() {
-        writeln!(ui, "Nothing changed.")?;
+        ui.write("Nothing changed.\n")?;
         return Ok(());
     }
 
@@ -4341,7 +8719,8 @@ fn cmd_git_push(
     let mut new_heads = vec![];
     let mut force_pushed_branches = hashset! {};
     for (branch_name, update) in &branch_updates {
-        let qualified_name = format!("refs/heads/{}", branch_name);
+        let qualified_name = remote_ref_for_push(&push_refspecs, branch_name);
+        let expected_current_target = (!args.force).then(|| update.old_target.clone());
         if let Some(new_target) = &update.new_target {
             new_heads.push(new_target.clone());
             let force = match &update.old_target {
@@ -4354,12 +8733,14 @@ fn cmd_git_push(
             ref_updates.push(GitRefUpdate {
                 qualified_name,
                 force,
+                expected_current_target,
                 new_target: Some(new_target.clone()),
             });
         } else {
             ref_updates.push(GitRefUpdate {
                 qualified_name,
                 force: false,
+                expected_current_target,
                 new_target: None,
             });
         }
@@ -4369,7 +8750,7 @@ fn cmd_git_push(
     // already been pushed.
     let mut old_heads = vec![];
     for branch_target in repo.view().branches().values() {
-        if let Some(old_head) = branch_target.remote_targets.get(&args.remote) {
+        if let Some(old_head) = branch_target.remote_targets.get(&remote) {
             old_heads.extend(old_head.adds());
         }
     }
@@ -4389,7 +8770,7 @@ fn cmd_git_push(
         {
             reasons.push("it has no author and/or committer set");
         }
-        if commit.tree().has_conflict() {
+        if !args.allow_conflicts && commit.tree().has_conflict() {
             reasons.push("it has conflicts");
         }
         if !reasons.is_empty() {
@@ -4401,21 +8782,27 @@ fn cmd_git_push(
         }
     }
 
-    writeln!(ui, "Branch changes to push to {}:", &args.remote)?;
+    writeln!(ui, "Branch changes to push to {}:", &remote)?;
     for (branch_name, update) in &branch_updates {
+        let remote_ref = remote_ref_for_push(&push_refspecs, branch_name);
+        let branch_label = match remote_ref.strip_prefix("refs/heads/") {
+            Some(remote_branch) if remote_branch == branch_name => branch_name.clone(),
+            Some(remote_branch) => format!("{branch_name} (as {remote_branch})"),
+            None => format!("{branch_name} (as {remote_ref})"),
+        };
         match (&update.old_target, &update.new_target) {
             (Some(old_target), Some(new_target)) => {
                 if force_pushed_branches.contains(branch_name) {
                     writeln!(
                         ui,
-                        "  Force branch {branch_name} from {} to {}",
+                        "  Force branch {branch_label} from {} to {}",
                         short_commit_hash(old_target),
                         short_commit_hash(new_target)
                     )?;
                 } else {
                     writeln!(
                         ui,
-                        "  Move branch {branch_name} from {} to {}",
+                        "  Move branch {branch_label} from {} to {}",
                         short_commit_hash(old_target),
                         short_commit_hash(new_target)
                     )?;
@@ -4424,14 +8811,14 @@ fn cmd_git_push(
             (Some(old_target), None) => {
                 writeln!(
                     ui,
-                    "  Delete branch {branch_name} from {}",
+                    "  Delete branch {branch_label} from {}",
                     short_commit_hash(old_target)
                 )?;
             }
             (None, Some(new_target)) => {
                 writeln!(
                     ui,
-                    "  Add branch {branch_name} to {}",
+                    "  Add branch {branch_label} to {}",
                     short_commit_hash(new_target)
                 )?;
             }
@@ -4447,22 +8834,106 @@ fn cmd_git_push(
     }
 
     let git_repo = get_git_repo(repo.store())?;
-    git::push_updates(&git_repo, &args.remote, &ref_updates)
-        .map_err(|err| CommandError::UserError(err.to_string()))?;
+    if let Some(hooks) = workspace_command.git_hooks() {
+        let remote_url = git_repo
+            .find_remote(&remote)
+            .ok()
+            .and_then(|git_remote| git_remote.url().map(str::to_owned))
+            .unwrap_or_else(|| remote.clone());
+        let pushed_refs = branch_updates
+            .iter()
+            .map(|(branch_name, update)| PushedRef {
+                local_ref: format!("refs/heads/{}", branch_name),
+                local_id: update.new_target.clone(),
+                remote_ref: remote_ref_for_push(&push_refspecs, branch_name),
+                remote_id: update.old_target.clone(),
+            })
+            .collect_vec();
+        hooks.pre_push(&remote, &remote_url, &pushed_refs)?;
+    }
+    with_remote_callbacks(ui.settings(), &git_repo, |callbacks| {
+        git::push_updates(&git_repo, &remote, &ref_updates, callbacks)
+    })
+    .map_err(|err| match err {
+        GitPushError::UnexpectedRemoteRefs(refs) => CommandError::UserError(format!(
+            "Refs have moved on the remote since they were last fetched: {} (run `jj git fetch` \
+             to see the changes, or push with --force to overwrite them)",
+            refs.join(", ")
+        )),
+        err => CommandError::UserError(err.to_string()),
+    })?;
     git::import_refs(tx.mut_repo(), &git_repo)?;
     workspace_command.finish_transaction(ui, tx)?;
     Ok(())
 }
 
+/// The name of the branch `jj git push --change` creates for the commit: the
+/// configured prefix followed by the change ID prefix. Branches created before
+/// the change ID was shortened keep being used.
+fn push_branch_name(settings: &UserSettings, view: &View, commit: &Commit) -> String {
+    let prefix = settings.push_branch_prefix();
+    let long_name = format!("{prefix}{}", commit.change_id().hex());
+    if view.get_local_branch(&long_name).is_some() {
+        return long_name;
+    }
+    format!("{prefix}{}", short_change_hash(commit.change_id()))
+}
+
+/// Returns the ref on the remote that the branch is pushed to: the destination
+/// of the first refspec matching the branch, or else the remote branch with the
+/// same name.
+fn remote_ref_for_push(refspecs: &[RefSpec], branch_name: &str) -> String {
+    refspecs
+        .iter()
+        .find_map(|refspec| refspec.map(branch_name))
+        .unwrap_or_else(|| format!("refs/heads/{}", branch_name))
+}
+
+/// Like `classify_branch_push_action()`, but compares the branch with the
+/// remote ref it's pushed to, which may be a branch with a different name.
+fn classify_push_to_remote_ref(
+    view: &View,
+    branch_name: &str,
+    branch_target: &BranchTarget,
+    remote_name: &str,
+    remote_ref: &str,
+) -> BranchPushAction {
+    let remote_branch = remote_ref.strip_prefix("refs/heads/");
+    if remote_branch == Some(branch_name) {
+        return classify_branch_push_action(branch_target, remote_name);
+    }
+    let remote_target = remote_branch
+        .and_then(|remote_branch| view.get_branch(remote_branch))
+        .and_then(|remote_branch_target| remote_branch_target.remote_targets.get(remote_name));
+    let mut target = BranchTarget {
+        local_target: branch_target.local_target.clone(),
+        ..Default::default()
+    };
+    if let Some(remote_target) = remote_target {
+        target
+            .remote_targets
+            .insert(remote_name.to_string(), remote_target.clone());
+    }
+    classify_branch_push_action(&target, remote_name)
+}
+
 fn branch_updates_for_push(
     repo: RepoRef,
     remote_name: &str,
+    refspecs: &[RefSpec],
     branch_name: &str,
 ) -> Result<Option<BranchPushUpdate>, CommandError> {
     let maybe_branch_target = repo.view().get_branch(branch_name);
     let branch_target = maybe_branch_target
         .ok_or_else(|| CommandError::UserError(format!("Branch {} doesn't exist", branch_name)))?;
-    let push_action = classify_branch_push_action(branch_target, remote_name);
+    let remote_ref = remote_ref_for_push(refspecs, branch_name);
+    let push_action = classify_push_to_remote_ref(
+        repo.view(),
+        branch_name,
+        branch_target,
+        remote_name,
+        &remote_ref,
+    );
 
     match push_action {
         BranchPushAction::AlreadyMatches => Ok(None),
@@ -4542,9 +9013,15 @@ pub fn run_command(
         Commands::Version(sub_args) => cmd_version(ui, command_helper, sub_args),
         Commands::Init(sub_args) => cmd_init(ui, command_helper, sub_args),
         Commands::Checkout(sub_args) => cmd_checkout(ui, command_helper, sub_args),
+        Commands::Track(sub_args) => cmd_track(ui, command_helper, sub_args),
         Commands::Untrack(sub_args) => cmd_untrack(ui, command_helper, sub_args),
         Commands::Files(sub_args) => cmd_files(ui, command_helper, sub_args),
         Commands::Print(sub_args) => cmd_print(ui, command_helper, sub_args),
+        Commands::Blame(sub_args) => cmd_blame(ui, command_helper, sub_args),
+        Commands::Grep(sub_args) => cmd_grep(ui, command_helper, sub_args),
+        Commands::Archive(sub_args) => cmd_archive(ui, command_helper, sub_args),
+        Commands::FormatPatch(sub_args) => cmd_format_patch(ui, command_helper, sub_args),
+        Commands::Apply(sub_args) => cmd_apply(ui, command_helper, sub_args),
         Commands::Diff(sub_args) => cmd_diff(ui, command_helper, sub_args),
         Commands::Show(sub_args) => cmd_show(ui, command_helper, sub_args),
         Commands::Status(sub_args) => cmd_status(ui, command_helper, sub_args),
@@ -4558,20 +9035,33 @@ pub fn run_command(
         Commands::Abandon(sub_args) => cmd_abandon(ui, command_helper, sub_args),
         Commands::Edit(sub_args) => cmd_edit(ui, command_helper, sub_args),
         Commands::New(sub_args) => cmd_new(ui, command_helper, sub_args),
+        Commands::Next(sub_args) => cmd_next(ui, command_helper, sub_args),
+        Commands::Prev(sub_args) => cmd_prev(ui, command_helper, sub_args),
         Commands::Move(sub_args) => cmd_move(ui, command_helper, sub_args),
         Commands::Squash(sub_args) => cmd_squash(ui, command_helper, sub_args),
         Commands::Unsquash(sub_args) => cmd_unsquash(ui, command_helper, sub_args),
+        Commands::Absorb(sub_args) => cmd_absorb(ui, command_helper, sub_args),
+        Commands::Fix(sub_args) => cmd_fix(ui, command_helper, sub_args),
+        Commands::Run(sub_args) => cmd_run(ui, command_helper, sub_args),
+        Commands::Bisect(sub_args) => cmd_bisect(ui, command_helper, sub_args),
         Commands::Restore(sub_args) => cmd_restore(ui, command_helper, sub_args),
         Commands::Touchup(sub_args) => cmd_touchup(ui, command_helper, sub_args),
         Commands::Split(sub_args) => cmd_split(ui, command_helper, sub_args),
         Commands::Merge(sub_args) => cmd_merge(ui, command_helper, sub_args),
         Commands::Rebase(sub_args) => cmd_rebase(ui, command_helper, sub_args),
+        Commands::Evolve(sub_args) => cmd_evolve(ui, command_helper, sub_args),
         Commands::Backout(sub_args) => cmd_backout(ui, command_helper, sub_args),
+        Commands::Parallelize(sub_args) => cmd_parallelize(ui, command_helper, sub_args),
+        Commands::Sign(sub_args) => cmd_sign(ui, command_helper, sub_args),
         Commands::Branch(sub_args) => cmd_branch(ui, command_helper, sub_args),
         Commands::Undo(sub_args) => cmd_op_undo(ui, command_helper, sub_args),
         Commands::Operation(sub_args) => cmd_operation(ui, command_helper, sub_args),
         Commands::Workspace(sub_args) => cmd_workspace(ui, command_helper, sub_args),
+        Commands::Stash(sub_args) => cmd_stash(ui, command_helper, sub_args),
+        Commands::Resolutions(sub_args) => cmd_resolutions(ui, command_helper, sub_args),
         Commands::Sparse(sub_args) => cmd_sparse(ui, command_helper, sub_args),
+        Commands::Gc(sub_args) => cmd_gc(ui, command_helper, sub_args),
+        Commands::Fsck(sub_args) => cmd_fsck(ui, command_helper, sub_args),
         Commands::Git(sub_args) => cmd_git(ui, command_helper, sub_args),
         Commands::Bench(sub_args) => cmd_bench(ui, command_helper, sub_args),
         Commands::Debug(sub_args) => cmd_debug(ui, command_helper, sub_args),
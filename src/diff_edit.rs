@@ -22,7 +22,7 @@ use config::ConfigError;
 use itertools::Itertools;
 use jujutsu_lib::backend::TreeId;
 use jujutsu_lib::gitignore::GitIgnoreFile;
-use jujutsu_lib::matchers::EverythingMatcher;
+use jujutsu_lib::matchers::{EverythingMatcher, Matcher};
 use jujutsu_lib::repo_path::RepoPath;
 use jujutsu_lib::settings::UserSettings;
 use jujutsu_lib::store::Store;
@@ -48,6 +48,12 @@ pub enum DiffEditError {
         #[source]
         source: std::io::Error,
     },
+    #[error("Error executing diff tool '{tool_binary}': {source}")]
+    ExecuteToolError {
+        tool_binary: String,
+        #[source]
+        source: std::io::Error,
+    },
     #[error("I/O error: {0:?}")]
     IoError(#[source] std::io::Error),
     #[error("Failed to snapshot changes: {0:?}")]
@@ -180,6 +186,60 @@ pub fn edit_diff(
     Ok(right_tree_state.snapshot(base_ignores)?)
 }
 
+/// Shows the differences between two trees by running an external diff tool on
+/// the trees checked out in two temporary directories. The tool's exit status
+/// is ignored since many diff tools exit with an error when the inputs differ.
+pub fn view_diff(
+    settings: &UserSettings,
+    tool_name: &str,
+    left_tree: &Tree,
+    right_tree: &Tree,
+    matcher: &dyn Matcher,
+) -> Result<(), DiffEditError> {
+    let store = left_tree.store();
+    let changed_files = left_tree
+        .diff(right_tree, matcher)
+        .map(|(path, _value)| path)
+        .collect_vec();
+
+    let temp_dir = tempfile::Builder::new()
+        .prefix("jj-diff-view-")
+        .tempdir()
+        .map_err(DiffEditError::SetUpDirError)?;
+    let left_wc_dir = temp_dir.path().join("left");
+    let left_state_dir = temp_dir.path().join("left_state");
+    let right_wc_dir = temp_dir.path().join("right");
+    let right_state_dir = temp_dir.path().join("right_state");
+    check_out(
+        store.clone(),
+        left_wc_dir.clone(),
+        left_state_dir,
+        left_tree,
+        changed_files.clone(),
+    )?;
+    set_readonly_recursively(&left_wc_dir).map_err(DiffEditError::SetUpDirError)?;
+    check_out(
+        store.clone(),
+        right_wc_dir.clone(),
+        right_state_dir,
+        right_tree,
+        changed_files,
+    )?;
+    set_readonly_recursively(&right_wc_dir).map_err(DiffEditError::SetUpDirError)?;
+
+    let tool = get_tool(settings, tool_name)?;
+    Command::new(&tool.program)
+        .args(&tool.diff_args)
+        .arg(&left_wc_dir)
+        .arg(&right_wc_dir)
+        .status()
+        .map_err(|e| DiffEditError::ExecuteToolError {
+            tool_binary: tool.program,
+            source: e,
+        })?;
+    Ok(())
+}
+
 /// Merge/diff tool loaded from the settings.
 #[derive(Clone, Debug, serde::Deserialize)]
 #[serde(rename_all = "kebab-case")]
@@ -189,6 +249,9 @@ struct MergeTool {
     /// Arguments to pass to the program when editing diffs.
     #[serde(default)]
     pub edit_args: Vec<String>,
+    /// Arguments to pass to the program when viewing diffs.
+    #[serde(default)]
+    pub diff_args: Vec<String>,
 }
 
 impl MergeTool {
@@ -196,6 +259,7 @@ impl MergeTool {
         MergeTool {
             program: program.to_owned(),
             edit_args: vec![],
+            diff_args: vec![],
         }
     }
 }
@@ -173,6 +173,8 @@ fn config_colors(user_settings: &UserSettings) -> HashMap<String, String> {
     result.insert(String::from("diff removed"), String::from("red"));
     result.insert(String::from("diff added"), String::from("green"));
     result.insert(String::from("diff modified"), String::from("cyan"));
+    result.insert(String::from("diff renamed"), String::from("cyan"));
+    result.insert(String::from("diff copied"), String::from("green"));
 
     result.insert(String::from("op-log id"), String::from("blue"));
     result.insert(String::from("op-log user"), String::from("yellow"));
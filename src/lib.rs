@@ -20,6 +20,8 @@ pub mod config;
 pub mod diff_edit;
 pub mod formatter;
 pub mod graphlog;
+pub mod patch;
+pub mod progress;
 pub mod template_parser;
 pub mod templater;
 pub mod ui;
@@ -0,0 +1,553 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+//! Parsing and applying patches in the unified diff format, optionally wrapped
+//! in emails like the ones produced by `git format-patch`.
+
+use thiserror::Error;
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PatchError {
+    #[error("Invalid patch on line {line}: {message}")]
+    InvalidPatch { line: usize, message: String },
+    #[error("Binary patches are not supported ({path})")]
+    BinaryPatch { path: String },
+    #[error("Patch does not apply to {path}")]
+    DoesNotApply { path: String },
+}
+
+/// A patch to a set of files, along with the commit metadata from the email
+/// headers, if there were any.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct PatchMessage {
+    /// The author's name and email address
+    pub author: Option<(String, String)>,
+    /// The date in RFC 2822 format
+    pub date: Option<String>,
+    pub description: Option<String>,
+    pub files: Vec<FilePatch>,
+}
+
+/// The changes to a single file. The paths are relative to the repo root and
+/// `None` for a file that's created or deleted.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old_path: Option<String>,
+    pub new_path: Option<String>,
+    /// Whether the file is executable after the patch, if specified
+    pub executable: Option<bool>,
+    pub hunks: Vec<Hunk>,
+}
+
+impl FilePatch {
+    /// The path to use in error messages
+    pub fn display_path(&self) -> &str {
+        self.new_path
+            .as_deref()
+            .or(self.old_path.as_deref())
+            .unwrap_or_default()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Hunk {
+    /// The 1-based line number where the hunk starts in the old file
+    pub old_start: usize,
+    pub old_lines: Vec<Vec<u8>>,
+    pub new_lines: Vec<Vec<u8>>,
+}
+
+struct Lines<'input> {
+    lines: Vec<&'input [u8]>,
+    pos: usize,
+}
+
+impl<'input> Lines<'input> {
+    fn peek(&self) -> Option<&'input [u8]> {
+        self.lines.get(self.pos).copied()
+    }
+
+    fn next(&mut self) -> Option<&'input [u8]> {
+        let line = self.peek();
+        self.pos += 1;
+        line
+    }
+
+    fn error(&self, message: impl Into<String>) -> PatchError {
+        PatchError::InvalidPatch {
+            line: self.pos,
+            message: message.into(),
+        }
+    }
+}
+
+fn line_str(line: &[u8]) -> String {
+    String::from_utf8_lossy(line).trim_end().to_string()
+}
+
+/// Parses a plain diff, or an mbox with one or more emails containing diffs.
+pub fn parse_patches(input: &[u8]) -> Result<Vec<PatchMessage>, PatchError> {
+    let mut lines = Lines {
+        lines: input.split_inclusive(|b| *b == b'\n').collect(),
+        pos: 0,
+    };
+    let mut messages = vec![];
+    if !is_mbox_separator(lines.peek()) {
+        messages.push(PatchMessage::default());
+    }
+    while let Some(line) = lines.peek() {
+        if is_mbox_separator(Some(line)) {
+            lines.next();
+            messages.push(parse_email_headers_and_body(&mut lines)?);
+        } else if line.starts_with(b"diff --git ") || line.starts_with(b"--- ") {
+            let file = parse_file_patch(&mut lines)?;
+            messages.last_mut().unwrap().files.push(file);
+        } else {
+            // Ignore other text between the diffs, like a diffstat or signature
+            lines.next();
+        }
+    }
+    Ok(messages)
+}
+
+fn is_mbox_separator(line: Option<&[u8]>) -> bool {
+    line.map_or(false, |line| line.starts_with(b"From "))
+}
+
+fn parse_email_headers_and_body(lines: &mut Lines) -> Result<PatchMessage, PatchError> {
+    let mut headers: Vec<(String, String)> = vec![];
+    while let Some(line) = lines.next() {
+        let line = line_str(line);
+        if line.is_empty() {
+            break;
+        } else if line.starts_with(|c: char| c.is_whitespace()) {
+            // Continuation of the previous header
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim_start());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.to_ascii_lowercase(), value.trim().to_string()));
+        } else {
+            return Err(lines.error("Invalid email header"));
+        }
+    }
+    let mut message = PatchMessage::default();
+    let mut subject = String::new();
+    for (name, value) in headers {
+        match name.as_str() {
+            "from" => {
+                message.author = Some(match value.rsplit_once('<') {
+                    Some((name, email)) => (
+                        name.trim().trim_matches('"').to_string(),
+                        email.trim_end_matches('>').to_string(),
+                    ),
+                    None => (String::new(), value),
+                });
+            }
+            "date" => message.date = Some(value),
+            "subject" => subject = strip_subject_prefix(&value).to_string(),
+            _ => {}
+        }
+    }
+    let mut body = String::new();
+    while let Some(line) = lines.peek() {
+        if line == b"---\n"
+            || line.starts_with(b"diff --git ")
+            || line.starts_with(b"--- ")
+            || is_mbox_separator(Some(line))
+        {
+            break;
+        }
+        body.push_str(&String::from_utf8_lossy(line));
+        lines.next();
+    }
+    let body = body.trim();
+    let mut description = subject;
+    if !body.is_empty() {
+        description.push_str("\n\n");
+        description.push_str(body);
+    }
+    if !description.is_empty() {
+        description.push('\n');
+    }
+    message.description = Some(description);
+    Ok(message)
+}
+
+/// Removes tags like `[PATCH 1/2]` from the start of the subject.
+fn strip_subject_prefix(mut subject: &str) -> &str {
+    while subject.starts_with('[') {
+        match subject.find(']') {
+            Some(end) => subject = subject[end + 1..].trim_start(),
+            None => break,
+        }
+    }
+    subject
+}
+
+/// Parses a path from a diff header, removing the `a/` or `b/` prefix.
+/// Returns `None` for `/dev/null`.
+fn parse_path(path: &str) -> Option<String> {
+    // Some tools add a timestamp after a tab
+    let path = path.split('\t').next().unwrap().trim_end();
+    if path == "/dev/null" {
+        return None;
+    }
+    let path = path
+        .strip_prefix("a/")
+        .or_else(|| path.strip_prefix("b/"))
+        .unwrap_or(path);
+    Some(path.to_string())
+}
+
+fn parse_mode(lines: &Lines, mode: &str) -> Result<bool, PatchError> {
+    match mode.trim() {
+        "100644" => Ok(false),
+        "100755" => Ok(true),
+        _ => Err(lines.error(format!("Unsupported file mode {}", mode.trim()))),
+    }
+}
+
+fn parse_file_patch(lines: &mut Lines) -> Result<FilePatch, PatchError> {
+    let mut file = FilePatch::default();
+    let first_line = line_str(lines.peek().unwrap());
+    if let Some(paths) = first_line.strip_prefix("diff --git ") {
+        lines.next();
+        // Paths with spaces are ambiguous here, but they're also given by the
+        // `---`/`+++` or `rename` lines when the file's content changed
+        if let Some((old_path, new_path)) = paths.split_once(' ') {
+            file.old_path = parse_path(old_path);
+            file.new_path = parse_path(new_path);
+        }
+        while let Some(line) = lines.peek() {
+            let line = line_str(line);
+            if line.starts_with("--- ") || line.starts_with("diff --git ") {
+                break;
+            } else if let Some(mode) = line.strip_prefix("new file mode ") {
+                file.old_path = None;
+                file.executable = Some(parse_mode(lines, mode)?);
+            } else if line.starts_with("deleted file mode ") {
+                file.new_path = None;
+            } else if let Some(mode) = line.strip_prefix("new mode ") {
+                file.executable = Some(parse_mode(lines, mode)?);
+            } else if let Some(path) = line.strip_prefix("rename from ") {
+                file.old_path = Some(path.to_string());
+            } else if let Some(path) = line.strip_prefix("rename to ") {
+                file.new_path = Some(path.to_string());
+            } else if line.starts_with("Binary files ") || line == "GIT binary patch" {
+                return Err(PatchError::BinaryPatch {
+                    path: file.display_path().to_string(),
+                });
+            } else if !(line.starts_with("old mode ")
+                || line.starts_with("index ")
+                || line.starts_with("similarity index ")
+                || line.starts_with("dissimilarity index "))
+            {
+                // The end of this file's extended headers
+                return Ok(file);
+            }
+            lines.next();
+        }
+    }
+    match lines.peek() {
+        Some(line) if line.starts_with(b"--- ") => {
+            lines.next();
+            file.old_path = parse_path(&line_str(&line[4..]));
+        }
+        _ => return Ok(file),
+    }
+    match lines.next() {
+        Some(line) if line.starts_with(b"+++ ") => {
+            file.new_path = parse_path(&line_str(&line[4..]));
+        }
+        _ => return Err(lines.error("Expected a line starting with '+++'")),
+    }
+    while let Some(line) = lines.peek() {
+        if !line.starts_with(b"@@ ") {
+            break;
+        }
+        file.hunks.push(parse_hunk(lines)?);
+    }
+    Ok(file)
+}
+
+/// Parses a `start,count` range from a hunk header.
+fn parse_range(range: &str) -> Option<(usize, usize)> {
+    match range.split_once(',') {
+        Some((start, count)) => Some((start.parse().ok()?, count.parse().ok()?)),
+        None => Some((range.parse().ok()?, 1)),
+    }
+}
+
+fn parse_hunk(lines: &mut Lines) -> Result<Hunk, PatchError> {
+    let header = line_str(lines.next().unwrap());
+    let mut parts = header.split(' ');
+    let (old_start, old_count, new_count) = match (parts.nth(1), parts.next()) {
+        (Some(old), Some(new)) if old.starts_with('-') && new.starts_with('+') => {
+            match (parse_range(&old[1..]), parse_range(&new[1..])) {
+                (Some((old_start, old_count)), Some((_, new_count))) => {
+                    (old_start, old_count, new_count)
+                }
+                _ => return Err(lines.error("Invalid hunk header")),
+            }
+        }
+        _ => return Err(lines.error("Invalid hunk header")),
+    };
+    let mut hunk = Hunk {
+        old_start,
+        old_lines: vec![],
+        new_lines: vec![],
+    };
+    // Which sides the previous line was on, for "\ No newline at end of file"
+    let mut last_line_sides = (false, false);
+    while hunk.old_lines.len() < old_count
+        || hunk.new_lines.len() < new_count
+        || lines.peek().map_or(false, |line| line.starts_with(b"\\"))
+    {
+        let line = lines
+            .next()
+            .ok_or_else(|| lines.error("Unexpected end of hunk"))?;
+        let content = line.get(1..).unwrap_or_default().to_vec();
+        match line.first() {
+            Some(b' ') => {
+                hunk.old_lines.push(content.clone());
+                hunk.new_lines.push(content);
+                last_line_sides = (true, true);
+            }
+            // Some email clients strip the space from empty context lines
+            Some(b'\n') => {
+                hunk.old_lines.push(b"\n".to_vec());
+                hunk.new_lines.push(b"\n".to_vec());
+                last_line_sides = (true, true);
+            }
+            Some(b'-') => {
+                hunk.old_lines.push(content);
+                last_line_sides = (true, false);
+            }
+            Some(b'+') => {
+                hunk.new_lines.push(content);
+                last_line_sides = (false, true);
+            }
+            Some(b'\\') => {
+                if last_line_sides.0 {
+                    strip_newline(hunk.old_lines.last_mut());
+                }
+                if last_line_sides.1 {
+                    strip_newline(hunk.new_lines.last_mut());
+                }
+            }
+            _ => return Err(lines.error("Invalid line in hunk")),
+        }
+        if hunk.old_lines.len() > old_count || hunk.new_lines.len() > new_count {
+            return Err(lines.error("Hunk is longer than its header says"));
+        }
+    }
+    Ok(hunk)
+}
+
+fn strip_newline(line: Option<&mut Vec<u8>>) {
+    if let Some(line) = line {
+        if line.ends_with(b"\n") {
+            line.pop();
+        }
+    }
+}
+
+/// Applies the hunks to the content of a file. If the lines the hunk replaces
+/// aren't at the expected position, the closest position where they are found
+/// is used instead.
+pub fn apply_hunks(content: &[u8], file: &FilePatch) -> Result<Vec<u8>, PatchError> {
+    let lines = content.split_inclusive(|b| *b == b'\n').collect::<Vec<_>>();
+    let mut result = vec![];
+    // The number of lines of the old content that have been processed
+    let mut pos = 0;
+    for hunk in &file.hunks {
+        // An empty old range gives the line after which to insert
+        let expected = if hunk.old_lines.is_empty() {
+            hunk.old_start
+        } else {
+            hunk.old_start.saturating_sub(1)
+        };
+        let start = find_hunk_start(&lines, pos, expected, &hunk.old_lines).ok_or_else(|| {
+            PatchError::DoesNotApply {
+                path: file.display_path().to_string(),
+            }
+        })?;
+        for line in &lines[pos..start] {
+            result.extend_from_slice(line);
+        }
+        for line in &hunk.new_lines {
+            result.extend_from_slice(line);
+        }
+        pos = start + hunk.old_lines.len();
+    }
+    for line in &lines[pos..] {
+        result.extend_from_slice(line);
+    }
+    Ok(result)
+}
+
+fn find_hunk_start(
+    lines: &[&[u8]],
+    min_start: usize,
+    expected: usize,
+    old_lines: &[Vec<u8>],
+) -> Option<usize> {
+    let max_start = lines.len().checked_sub(old_lines.len())?;
+    if min_start > max_start {
+        return None;
+    }
+    let expected = expected.clamp(min_start, max_start);
+    let matches_at = |start: usize| {
+        lines[start..start + old_lines.len()]
+            .iter()
+            .zip(old_lines)
+            .all(|(line, old_line)| *line == old_line.as_slice())
+    };
+    for distance in 0..=(max_start - min_start) {
+        if expected >= min_start + distance && matches_at(expected - distance) {
+            return Some(expected - distance);
+        }
+        if expected + distance <= max_start && matches_at(expected + distance) {
+            return Some(expected + distance);
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn apply(content: &str, patch: &str) -> Result<String, PatchError> {
+        let messages = parse_patches(patch.as_bytes())?;
+        let result = apply_hunks(content.as_bytes(), &messages[0].files[0])?;
+        Ok(String::from_utf8(result).unwrap())
+    }
+
+    #[test]
+    fn test_parse_mbox() {
+        let messages = parse_patches(
+            b"From 1234 Mon Sep 17 00:00:00 2001
+From: \"Some One\" <some.one@example.com>
+Date: Sat, 3 Feb 2001 04:05:07 +0700
+Subject: [PATCH 1/2] A long
+ subject
+
+The body.
+---
+ file | 1 +
+diff --git a/file b/file
+new file mode 100755
+--- /dev/null
++++ b/file
+@@ -0,0 +1 @@
++a
+--
+2.38.0
+
+From 5678 Mon Sep 17 00:00:00 2001
+Subject: [PATCH 2/2] Delete file
+
+diff --git a/file b/file
+deleted file mode 100755
+--- a/file
++++ /dev/null
+@@ -1 +0,0 @@
+-a
+",
+        )
+        .unwrap();
+        assert_eq!(
+            messages,
+            vec![
+                PatchMessage {
+                    author: Some(("Some One".to_string(), "some.one@example.com".to_string())),
+                    date: Some("Sat, 3 Feb 2001 04:05:07 +0700".to_string()),
+                    description: Some("A long subject\n\nThe body.\n".to_string()),
+                    files: vec![FilePatch {
+                        old_path: None,
+                        new_path: Some("file".to_string()),
+                        executable: Some(true),
+                        hunks: vec![Hunk {
+                            old_start: 0,
+                            old_lines: vec![],
+                            new_lines: vec![b"a\n".to_vec()],
+                        }],
+                    }],
+                },
+                PatchMessage {
+                    author: None,
+                    date: None,
+                    description: Some("Delete file\n".to_string()),
+                    files: vec![FilePatch {
+                        old_path: Some("file".to_string()),
+                        new_path: None,
+                        executable: None,
+                        hunks: vec![Hunk {
+                            old_start: 1,
+                            old_lines: vec![b"a\n".to_vec()],
+                            new_lines: vec![],
+                        }],
+                    }],
+                }
+            ]
+        );
+    }
+
+    #[test]
+    fn test_apply_hunks() {
+        let content = "1\n2\n3\n4\n5\n6\n";
+        // At the expected position
+        assert_eq!(
+            apply(
+                content,
+                "--- a/f\n+++ b/f\n@@ -2,2 +2,2 @@\n 2\n-3\n+three\n"
+            ),
+            Ok("1\n2\nthree\n4\n5\n6\n".to_string())
+        );
+        // Offset from the expected position
+        assert_eq!(
+            apply(
+                content,
+                "--- a/f\n+++ b/f\n@@ -1,2 +1,2 @@\n 4\n-5\n+five\n"
+            ),
+            Ok("1\n2\n3\n4\nfive\n6\n".to_string())
+        );
+        // Insertion at the end
+        assert_eq!(
+            apply(content, "--- a/f\n+++ b/f\n@@ -6,0 +7 @@\n+7\n"),
+            Ok("1\n2\n3\n4\n5\n6\n7\n".to_string())
+        );
+        // Missing newline at the end of the file
+        assert_eq!(
+            apply(
+                content,
+                "--- a/f\n+++ b/f\n@@ -6 +6 @@\n-6\n+6\n\\ No newline at end of file\n"
+            ),
+            Ok("1\n2\n3\n4\n5\n6".to_string())
+        );
+        // Context that doesn't match
+        assert_eq!(
+            apply(
+                content,
+                "--- a/f\n+++ b/f\n@@ -2,2 +2,2 @@\n 2\n-4\n+four\n"
+            ),
+            Err(PatchError::DoesNotApply {
+                path: "f".to_string()
+            })
+        );
+    }
+}
@@ -0,0 +1,229 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::{self, Write};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use jujutsu_lib::git::{self, TransferStage};
+use jujutsu_lib::working_copy::{Progress, ProgressCallback, ProgressStage};
+
+/// Don't show progress for operations that finish faster than this.
+const INITIAL_DELAY: Duration = Duration::from_millis(250);
+/// Don't redraw the progress bar more often than this.
+const UPDATE_INTERVAL: Duration = Duration::from_millis(50);
+const BAR_WIDTH: usize = 30;
+
+struct ProgressState {
+    start: Instant,
+    next_draw: Instant,
+    drawn: bool,
+}
+
+/// Returns a callback that draws a progress bar on the terminal for long
+/// checkouts and snapshots.
+pub fn terminal_progress_callback() -> ProgressCallback {
+    let now = Instant::now();
+    let state = Mutex::new(ProgressState {
+        start: now,
+        next_draw: now,
+        drawn: false,
+    });
+    Arc::new(move |progress: &Progress| {
+        let mut state = state.lock().unwrap();
+        let now = Instant::now();
+        let mut stderr = io::stderr();
+        if progress.processed == 0 {
+            state.start = now;
+            state.next_draw = now + INITIAL_DELAY;
+        }
+        if progress.processed >= progress.total {
+            if state.drawn {
+                // Clear the line so later output doesn't get mixed with the bar
+                write!(stderr, "\r\x1b[K").ok();
+                stderr.flush().ok();
+                state.drawn = false;
+            }
+            return;
+        }
+        if now < state.next_draw {
+            return;
+        }
+        state.next_draw = now + UPDATE_INTERVAL;
+        write!(stderr, "\r\x1b[K{}", format_progress(progress)).ok();
+        stderr.flush().ok();
+        state.drawn = true;
+    })
+}
+
+fn format_progress(progress: &Progress) -> String {
+    let label = match progress.stage {
+        ProgressStage::CheckingOut => "Checking out",
+        ProgressStage::Snapshotting => "Snapshotting",
+    };
+    let filled = (progress.processed.min(progress.total) * BAR_WIDTH)
+        .checked_div(progress.total)
+        .unwrap_or(BAR_WIDTH);
+    format!(
+        "{} [{}{}] {}/{} files",
+        label,
+        "=".repeat(filled),
+        " ".repeat(BAR_WIDTH - filled),
+        progress.processed,
+        progress.total
+    )
+}
+
+/// Draws the progress of fetching from or pushing to a Git remote on the
+/// terminal. The line is cleared when it's dropped.
+pub struct GitProgressRenderer {
+    state: ProgressState,
+}
+
+impl GitProgressRenderer {
+    pub fn new() -> Self {
+        let now = Instant::now();
+        GitProgressRenderer {
+            state: ProgressState {
+                start: now,
+                next_draw: now + INITIAL_DELAY,
+                drawn: false,
+            },
+        }
+    }
+
+    pub fn update(&mut self, progress: &git::Progress) {
+        let now = Instant::now();
+        if now < self.state.next_draw {
+            return;
+        }
+        self.state.next_draw = now + UPDATE_INTERVAL;
+        let elapsed = now.duration_since(self.state.start);
+        let mut stderr = io::stderr();
+        write!(stderr, "\r\x1b[K{}", format_git_progress(progress, elapsed)).ok();
+        stderr.flush().ok();
+        self.state.drawn = true;
+    }
+}
+
+impl Default for GitProgressRenderer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Drop for GitProgressRenderer {
+    fn drop(&mut self) {
+        if self.state.drawn {
+            let mut stderr = io::stderr();
+            write!(stderr, "\r\x1b[K").ok();
+            stderr.flush().ok();
+        }
+    }
+}
+
+fn format_git_progress(progress: &git::Progress, elapsed: Duration) -> String {
+    let label = match progress.stage {
+        TransferStage::Receiving => "Receiving objects",
+        TransferStage::ResolvingDeltas => "Resolving deltas",
+        TransferStage::Sending => "Sending objects",
+    };
+    let percent = (progress.processed.min(progress.total) * 100)
+        .checked_div(progress.total)
+        .unwrap_or(100);
+    let mut text = format!(
+        "{label}: {percent:3}% ({}/{})",
+        progress.processed, progress.total
+    );
+    if progress.stage != TransferStage::ResolvingDeltas {
+        let rate = (progress.bytes as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+        text.push_str(&format!(
+            ", {} | {}/s",
+            format_bytes(progress.bytes as u64),
+            format_bytes(rate)
+        ));
+    }
+    text
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut size = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    format!("{size:.2} {}", UNITS[unit])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_progress() {
+        let progress = |stage, processed, total| Progress {
+            stage,
+            processed,
+            total,
+        };
+        assert_eq!(
+            format_progress(&progress(ProgressStage::CheckingOut, 0, 10)),
+            "Checking out [                              ] 0/10 files"
+        );
+        assert_eq!(
+            format_progress(&progress(ProgressStage::Snapshotting, 5, 10)),
+            "Snapshotting [===============               ] 5/10 files"
+        );
+        assert_eq!(
+            format_progress(&progress(ProgressStage::Snapshotting, 3, 3)),
+            "Snapshotting [==============================] 3/3 files"
+        );
+    }
+
+    #[test]
+    fn test_format_git_progress() {
+        let progress = |stage, processed, total, bytes| git::Progress {
+            stage,
+            processed,
+            total,
+            bytes,
+        };
+        assert_eq!(
+            format_git_progress(
+                &progress(TransferStage::Receiving, 450, 1000, 3 * 1024 * 1024),
+                Duration::from_secs(2)
+            ),
+            "Receiving objects:  45% (450/1000), 3.00 MiB | 1.50 MiB/s"
+        );
+        assert_eq!(
+            format_git_progress(
+                &progress(TransferStage::ResolvingDeltas, 30, 100, 3 * 1024 * 1024),
+                Duration::from_secs(2)
+            ),
+            "Resolving deltas:  30% (30/100)"
+        );
+        assert_eq!(
+            format_git_progress(
+                &progress(TransferStage::Sending, 3, 3, 512),
+                Duration::from_secs(1)
+            ),
+            "Sending objects: 100% (3/3), 512 B | 512 B/s"
+        );
+    }
+}
@@ -12,11 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use std::cell::Cell;
+
 use chrono::{FixedOffset, TimeZone, Utc};
 use jujutsu_lib::backend::{CommitId, Signature};
-use jujutsu_lib::commit::Commit;
-use jujutsu_lib::op_store::WorkspaceId;
+use jujutsu_lib::op_store::{OperationId, WorkspaceId};
+use jujutsu_lib::operation::Operation;
 use jujutsu_lib::repo::RepoRef;
+use jujutsu_lib::settings::UserSettings;
+use jujutsu_lib::signing::Verifier;
 use pest::iterators::{Pair, Pairs};
 use pest::Parser;
 use pest_derive::Parser;
@@ -25,10 +29,13 @@ use crate::formatter::PlainTextFormatter;
 use crate::templater::{
     AuthorProperty, BranchProperty, ChangeIdProperty, CommitIdKeyword, CommitterProperty,
     ConditionalTemplate, ConflictProperty, ConstantTemplateProperty, DescriptionProperty,
-    DivergentProperty, DynamicLabelTemplate, GitRefsProperty, IsGitHeadProperty,
-    IsWorkingCopyProperty, LabelTemplate, ListTemplate, LiteralTemplate, OpenProperty,
-    StringPropertyTemplate, TagProperty, Template, TemplateFunction, TemplateProperty,
-    WorkingCopiesProperty,
+    DivergentProperty, DynamicLabelTemplate, GitNoteProperty, GitRefsProperty,
+    IsCurrentOperationProperty, IsGitHeadProperty, IsWorkingCopyProperty, LabelTemplate,
+    LazyCommit, ListTemplate, LiteralTemplate, LoadCommitTemplate, OpenProperty,
+    OperationDescriptionProperty, OperationHostnameProperty, OperationIdProperty,
+    OperationTagsProperty, OperationTimeProperty, OperationUsernameProperty,
+    SignatureStatusProperty, SignedProperty, StringPropertyTemplate, TagProperty, Template,
+    TemplateFunction, TemplateProperty, WorkingCopiesProperty,
 };
 
 #[derive(Parser)]
@@ -233,8 +240,9 @@ impl<'a, I: 'a> Property<'a, I> {
 fn parse_commit_keyword<'a>(
     repo: RepoRef<'a>,
     workspace_id: &WorkspaceId,
+    settings: &UserSettings,
     pair: Pair<Rule>,
-) -> (Property<'a, Commit>, String) {
+) -> (Property<'a, LazyCommit>, String) {
     assert_eq!(pair.as_rule(), Rule::identifier);
     let property = match pair.as_str() {
         "description" => Property::String(Box::new(DescriptionProperty)),
@@ -251,9 +259,16 @@ fn parse_commit_keyword<'a>(
         "branches" => Property::String(Box::new(BranchProperty { repo })),
         "tags" => Property::String(Box::new(TagProperty { repo })),
         "git_refs" => Property::String(Box::new(GitRefsProperty { repo })),
+        "git_note" => Property::String(Box::new(GitNoteProperty {
+            git_repo: repo.store().git_repo(),
+        })),
         "is_git_head" => Property::Boolean(Box::new(IsGitHeadProperty::new(repo))),
         "divergent" => Property::Boolean(Box::new(DivergentProperty::new(repo))),
         "conflict" => Property::Boolean(Box::new(ConflictProperty)),
+        "signed" => Property::Boolean(Box::new(SignedProperty)),
+        "signature_status" => Property::String(Box::new(SignatureStatusProperty {
+            verifier: Verifier::from_settings(settings),
+        })),
         name => panic!("unexpected identifier: {}", name),
     };
     (property, pair.as_str().to_string())
@@ -279,17 +294,20 @@ fn coerce_to_string<'a, I: 'a>(
     }
 }
 
-fn parse_boolean_commit_property<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+/// Parses a keyword of the template's context type into a property and the
+/// label to apply to it.
+type KeywordParser<'p, 'a, C> = dyn Fn(Pair<Rule>) -> (Property<'a, C>, String) + 'p;
+
+fn parse_boolean_property<'a, C: 'a>(
+    parse_keyword: &KeywordParser<'_, 'a, C>,
     pair: Pair<Rule>,
-) -> Box<dyn TemplateProperty<Commit, bool> + 'a> {
+) -> Box<dyn TemplateProperty<C, bool> + 'a> {
     let mut inner = pair.into_inner();
     let pair = inner.next().unwrap();
     let _method = inner.next().unwrap();
     assert!(inner.next().is_none());
     match pair.as_rule() {
-        Rule::identifier => match parse_commit_keyword(repo, workspace_id, pair.clone()).0 {
+        Rule::identifier => match parse_keyword(pair.clone()).0 {
             Property::Boolean(property) => property,
             _ => panic!("cannot yet use this as boolean: {:?}", pair),
         },
@@ -297,11 +315,10 @@ fn parse_boolean_commit_property<'a>(
     }
 }
 
-fn parse_commit_term<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+fn parse_term<'a, C: 'a>(
+    parse_keyword: &KeywordParser<'_, 'a, C>,
     pair: Pair<Rule>,
-) -> Box<dyn Template<Commit> + 'a> {
+) -> Box<dyn Template<C> + 'a> {
     assert_eq!(pair.as_rule(), Rule::term);
     if pair.as_str().is_empty() {
         Box::new(LiteralTemplate(String::new()))
@@ -326,7 +343,7 @@ fn parse_commit_term<'a>(
                 }
             }
             Rule::identifier => {
-                let (term_property, labels) = parse_commit_keyword(repo, workspace_id, expr);
+                let (term_property, labels) = parse_keyword(expr);
                 let property = parse_method_chain(maybe_method, term_property);
                 let string_property = coerce_to_string(property);
                 Box::new(LabelTemplate::new(
@@ -342,9 +359,8 @@ fn parse_commit_term<'a>(
                 match name {
                     "label" => {
                         let label_pair = inner.next().unwrap();
-                        let label_template = parse_commit_template_rule(
-                            repo,
-                            workspace_id,
+                        let label_template = parse_template_rule(
+                            parse_keyword,
                             label_pair.into_inner().next().unwrap(),
                         );
                         let arg_template = match inner.next() {
@@ -354,14 +370,14 @@ fn parse_commit_term<'a>(
                         if inner.next().is_some() {
                             panic!("label() accepts only two arguments")
                         }
-                        let content: Box<dyn Template<Commit> + 'a> =
-                            parse_commit_template_rule(repo, workspace_id, arg_template);
-                        let get_labels = move |commit: &Commit| -> String {
+                        let content: Box<dyn Template<C> + 'a> =
+                            parse_template_rule(parse_keyword, arg_template);
+                        let get_labels = move |context: &C| -> String {
                             let mut buf: Vec<u8> = vec![];
                             {
                                 let writer = Box::new(&mut buf);
                                 let mut formatter = PlainTextFormatter::new(writer);
-                                label_template.format(commit, &mut formatter).unwrap();
+                                label_template.format(context, &mut formatter).unwrap();
                             }
                             String::from_utf8(buf).unwrap()
                         };
@@ -370,16 +386,15 @@ fn parse_commit_term<'a>(
                     "if" => {
                         let condition_pair = inner.next().unwrap();
                         let condition_template = condition_pair.into_inner().next().unwrap();
-                        let condition =
-                            parse_boolean_commit_property(repo, workspace_id, condition_template);
+                        let condition = parse_boolean_property(parse_keyword, condition_template);
 
                         let true_template = match inner.next() {
                             None => panic!("if() requires at least two arguments"),
-                            Some(pair) => parse_commit_template_rule(repo, workspace_id, pair),
+                            Some(pair) => parse_template_rule(parse_keyword, pair),
                         };
                         let false_template = inner
                             .next()
-                            .map(|pair| parse_commit_template_rule(repo, workspace_id, pair));
+                            .map(|pair| parse_template_rule(parse_keyword, pair));
                         if inner.next().is_some() {
                             panic!("if() accepts at most three arguments")
                         }
@@ -397,23 +412,22 @@ fn parse_commit_term<'a>(
     }
 }
 
-fn parse_commit_template_rule<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+fn parse_template_rule<'a, C: 'a>(
+    parse_keyword: &KeywordParser<'_, 'a, C>,
     pair: Pair<Rule>,
-) -> Box<dyn Template<Commit> + 'a> {
+) -> Box<dyn Template<C> + 'a> {
     match pair.as_rule() {
         Rule::template => {
             let mut inner = pair.into_inner();
-            let formatter = parse_commit_template_rule(repo, workspace_id, inner.next().unwrap());
+            let formatter = parse_template_rule(parse_keyword, inner.next().unwrap());
             assert!(inner.next().is_none());
             formatter
         }
-        Rule::term => parse_commit_term(repo, workspace_id, pair),
+        Rule::term => parse_term(parse_keyword, pair),
         Rule::list => {
-            let mut formatters: Vec<Box<dyn Template<Commit>>> = vec![];
+            let mut formatters: Vec<Box<dyn Template<C>>> = vec![];
             for inner_pair in pair.into_inner() {
-                formatters.push(parse_commit_template_rule(repo, workspace_id, inner_pair));
+                formatters.push(parse_template_rule(parse_keyword, inner_pair));
             }
             Box::new(ListTemplate(formatters))
         }
@@ -421,11 +435,10 @@ fn parse_commit_template_rule<'a>(
     }
 }
 
-pub fn parse_commit_template<'a>(
-    repo: RepoRef<'a>,
-    workspace_id: &WorkspaceId,
+fn parse_template<'a, C: 'a>(
+    parse_keyword: &KeywordParser<'_, 'a, C>,
     template_text: &str,
-) -> Box<dyn Template<Commit> + 'a> {
+) -> Box<dyn Template<C> + 'a> {
     let mut pairs: Pairs<Rule> = TemplateParser::parse(Rule::template, template_text).unwrap();
 
     let first_pair = pairs.next().unwrap();
@@ -437,5 +450,73 @@ pub fn parse_commit_template<'a>(
         first_pair.as_span().end()
     );
 
-    parse_commit_template_rule(repo, workspace_id, first_pair)
+    parse_template_rule(parse_keyword, first_pair)
+}
+
+/// Keywords whose values come from the commit object rather than from the
+/// index or the view, so the commit needs to be read to format them.
+const COMMIT_OBJECT_KEYWORDS: &[&str] = &[
+    "description",
+    "author",
+    "committer",
+    "open",
+    "conflict",
+    "signed",
+    "signature_status",
+];
+
+pub fn parse_commit_template<'a>(
+    repo: RepoRef<'a>,
+    workspace_id: &WorkspaceId,
+    settings: &UserSettings,
+    template_text: &str,
+) -> Box<dyn Template<LazyCommit> + 'a> {
+    let uses_commit_object = Cell::new(false);
+    let template = parse_template(
+        &|pair| {
+            if COMMIT_OBJECT_KEYWORDS.contains(&pair.as_str()) {
+                uses_commit_object.set(true);
+            }
+            parse_commit_keyword(repo, workspace_id, settings, pair)
+        },
+        template_text,
+    );
+    if uses_commit_object.get() {
+        Box::new(LoadCommitTemplate::new(template))
+    } else {
+        template
+    }
+}
+
+fn parse_operation_keyword<'a>(
+    current_op_id: &OperationId,
+    pair: Pair<Rule>,
+) -> (Property<'a, Operation>, String) {
+    assert_eq!(pair.as_rule(), Rule::identifier);
+    let property = match pair.as_str() {
+        "id" => Property::String(Box::new(OperationIdProperty)),
+        "description" => Property::String(Box::new(OperationDescriptionProperty)),
+        "hostname" => Property::String(Box::new(OperationHostnameProperty)),
+        "username" => Property::String(Box::new(OperationUsernameProperty)),
+        "start_time" => Property::String(Box::new(OperationTimeProperty { end: false })),
+        "end_time" => Property::String(Box::new(OperationTimeProperty { end: true })),
+        "tags" => Property::String(Box::new(OperationTagsProperty)),
+        "current_operation" => Property::Boolean(Box::new(IsCurrentOperationProperty {
+            current_op_id: current_op_id.clone(),
+        })),
+        name => panic!("unexpected identifier: {}", name),
+    };
+    (property, pair.as_str().to_string())
+}
+
+/// Parses a template for `jj op log`. `current_op_id` is the operation the
+/// repo was loaded at.
+pub fn parse_operation_template<'a>(
+    current_op_id: &OperationId,
+    template_text: &str,
+) -> Box<dyn Template<Operation> + 'a> {
+    parse_template(
+        &|pair| parse_operation_keyword(current_op_id, pair),
+        template_text,
+    )
 }
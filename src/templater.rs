@@ -13,16 +13,25 @@
 // limitations under the License.
 
 use std::borrow::BorrowMut;
+use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::io;
-use std::ops::{Add, AddAssign};
+use std::ops::Add;
+use std::rc::Rc;
+use std::sync::Arc;
 
+use chrono::{FixedOffset, TimeZone, Utc};
 use itertools::Itertools;
-use jujutsu_lib::backend::{ChangeId, CommitId, Signature};
+use jujutsu_lib::backend::{BackendResult, ChangeId, CommitId, Signature, Timestamp};
 use jujutsu_lib::commit::Commit;
-use jujutsu_lib::op_store::WorkspaceId;
+use jujutsu_lib::git;
+use jujutsu_lib::index::IndexEntry;
+use jujutsu_lib::op_store::{OperationId, WorkspaceId};
+use jujutsu_lib::operation::Operation;
 use jujutsu_lib::repo::RepoRef;
 use jujutsu_lib::revset::RevsetExpression;
+use jujutsu_lib::signing::{SigStatus, Verifier};
+use jujutsu_lib::store::Store;
 
 use crate::formatter::Formatter;
 
@@ -160,19 +169,146 @@ impl<'a, C> Template<C> for StringPropertyTemplate<'a, C> {
     }
 }
 
+/// The context of commit templates. Keywords that only need the commit's ids
+/// (e.g. `commit_id` and `branches`) don't load the commit from the store, so
+/// templates using only such keywords are fast even for many commits.
+pub struct LazyCommit {
+    id: CommitId,
+    change_id: ChangeId,
+    commit: RefCell<Option<Commit>>,
+    batch: Option<Rc<CommitBatch>>,
+    store: Arc<Store>,
+}
+
+impl LazyCommit {
+    pub fn from_commit(commit: Commit) -> Self {
+        LazyCommit {
+            id: commit.id().clone(),
+            change_id: commit.change_id().clone(),
+            store: commit.store().clone(),
+            commit: RefCell::new(Some(commit)),
+            batch: None,
+        }
+    }
+
+    /// Contexts for the commits of `index_entries`. The commits are all read
+    /// together, in parallel, the first time one of them is needed.
+    pub fn batch_from_index_entries(
+        store: &Arc<Store>,
+        index_entries: &[IndexEntry],
+    ) -> Vec<LazyCommit> {
+        let batch = Rc::new(CommitBatch {
+            store: store.clone(),
+            ids: index_entries
+                .iter()
+                .map(|entry| entry.commit_id())
+                .collect(),
+            commits: RefCell::new(None),
+        });
+        index_entries
+            .iter()
+            .map(|entry| LazyCommit {
+                id: entry.commit_id(),
+                change_id: entry.change_id(),
+                commit: RefCell::new(None),
+                batch: Some(batch.clone()),
+                store: store.clone(),
+            })
+            .collect()
+    }
+
+    pub fn id(&self) -> &CommitId {
+        &self.id
+    }
+
+    pub fn change_id(&self) -> &ChangeId {
+        &self.change_id
+    }
+
+    /// The commit, which is read from the store if it hasn't been yet.
+    pub fn commit(&self) -> BackendResult<Commit> {
+        let mut commit = self.commit.borrow_mut();
+        if let Some(commit) = commit.as_ref() {
+            return Ok(commit.clone());
+        }
+        let loaded = match &self.batch {
+            Some(batch) => batch.take(&self.id)?,
+            None => self.store.get_commit(&self.id)?,
+        };
+        Ok(commit.insert(loaded).clone())
+    }
+
+    /// The commit, which must have been read already. Properties that need the
+    /// commit object are only extracted inside a `LoadCommitTemplate`, which
+    /// reads it first.
+    fn loaded_commit(&self) -> Commit {
+        self.commit
+            .borrow()
+            .clone()
+            .expect("commit should have been read before formatting")
+    }
+}
+
+struct CommitBatch {
+    store: Arc<Store>,
+    ids: Vec<CommitId>,
+    commits: RefCell<Option<HashMap<CommitId, BackendResult<Commit>>>>,
+}
+
+impl CommitBatch {
+    /// Reads all the commits in the batch the first time one of them is needed,
+    /// and hands out each commit (or the error reading it) once.
+    fn take(&self, id: &CommitId) -> BackendResult<Commit> {
+        let mut commits = self.commits.borrow_mut();
+        let commits = commits.get_or_insert_with(|| {
+            self.ids
+                .iter()
+                .cloned()
+                .zip(self.store.get_commits(&self.ids))
+                .collect()
+        });
+        match commits.remove(id) {
+            Some(result) => result,
+            None => self.store.get_commit(id),
+        }
+    }
+}
+
+/// Reads the commit before formatting the wrapped template, for templates
+/// that use keywords needing the commit object. An error reading it is
+/// returned as an I/O error wrapping the `BackendError`.
+pub struct LoadCommitTemplate<'a> {
+    template: Box<dyn Template<LazyCommit> + 'a>,
+}
+
+impl<'a> LoadCommitTemplate<'a> {
+    pub fn new(template: Box<dyn Template<LazyCommit> + 'a>) -> Self {
+        LoadCommitTemplate { template }
+    }
+}
+
+impl Template<LazyCommit> for LoadCommitTemplate<'_> {
+    fn format(&self, context: &LazyCommit, formatter: &mut dyn Formatter) -> io::Result<()> {
+        context
+            .commit()
+            .map_err(|err| io::Error::new(io::ErrorKind::Other, err))?;
+        self.template.format(context, formatter)
+    }
+}
+
 pub struct ChangeIdProperty;
 
-impl TemplateProperty<Commit, String> for ChangeIdProperty {
-    fn extract(&self, context: &Commit) -> String {
+impl TemplateProperty<LazyCommit, String> for ChangeIdProperty {
+    fn extract(&self, context: &LazyCommit) -> String {
         context.change_id().hex()
     }
 }
 
 pub struct DescriptionProperty;
 
-impl TemplateProperty<Commit, String> for DescriptionProperty {
-    fn extract(&self, context: &Commit) -> String {
-        let description = context.description().to_owned();
+impl TemplateProperty<LazyCommit, String> for DescriptionProperty {
+    fn extract(&self, context: &LazyCommit) -> String {
+        let description = context.loaded_commit().description().to_owned();
         if description.ends_with('\n') {
             description
         } else if description.is_empty() {
@@ -185,25 +321,25 @@ impl TemplateProperty<Commit, String> for DescriptionProperty {
 
 pub struct AuthorProperty;
 
-impl TemplateProperty<Commit, Signature> for AuthorProperty {
-    fn extract(&self, context: &Commit) -> Signature {
-        context.author().clone()
+impl TemplateProperty<LazyCommit, Signature> for AuthorProperty {
+    fn extract(&self, context: &LazyCommit) -> Signature {
+        context.loaded_commit().author().clone()
     }
 }
 
 pub struct CommitterProperty;
 
-impl TemplateProperty<Commit, Signature> for CommitterProperty {
-    fn extract(&self, context: &Commit) -> Signature {
-        context.committer().clone()
+impl TemplateProperty<LazyCommit, Signature> for CommitterProperty {
+    fn extract(&self, context: &LazyCommit) -> Signature {
+        context.loaded_commit().committer().clone()
     }
 }
 
 pub struct OpenProperty;
 
-impl TemplateProperty<Commit, bool> for OpenProperty {
-    fn extract(&self, context: &Commit) -> bool {
-        context.is_open()
+impl TemplateProperty<LazyCommit, bool> for OpenProperty {
+    fn extract(&self, context: &LazyCommit) -> bool {
+        context.loaded_commit().is_open()
     }
 }
 
@@ -211,8 +347,8 @@ pub struct WorkingCopiesProperty<'a> {
     pub repo: RepoRef<'a>,
 }
 
-impl TemplateProperty<Commit, String> for WorkingCopiesProperty<'_> {
-    fn extract(&self, context: &Commit) -> String {
+impl TemplateProperty<LazyCommit, String> for WorkingCopiesProperty<'_> {
+    fn extract(&self, context: &LazyCommit) -> String {
         let wc_commit_ids = self.repo.view().wc_commit_ids();
         if wc_commit_ids.len() <= 1 {
             return "".to_string();
@@ -232,8 +368,8 @@ pub struct IsWorkingCopyProperty<'a> {
     pub workspace_id: WorkspaceId,
 }
 
-impl TemplateProperty<Commit, bool> for IsWorkingCopyProperty<'_> {
-    fn extract(&self, context: &Commit) -> bool {
+impl TemplateProperty<LazyCommit, bool> for IsWorkingCopyProperty<'_> {
+    fn extract(&self, context: &LazyCommit) -> bool {
         Some(context.id()) == self.repo.view().get_wc_commit_id(&self.workspace_id)
     }
 }
@@ -242,8 +378,8 @@ pub struct BranchProperty<'a> {
     pub repo: RepoRef<'a>,
 }
 
-impl TemplateProperty<Commit, String> for BranchProperty<'_> {
-    fn extract(&self, context: &Commit) -> String {
+impl TemplateProperty<LazyCommit, String> for BranchProperty<'_> {
+    fn extract(&self, context: &LazyCommit) -> String {
         let mut names = vec![];
         for (branch_name, branch_target) in self.repo.view().branches() {
             let local_target = branch_target.local_target.as_ref();
@@ -274,8 +410,8 @@ pub struct TagProperty<'a> {
     pub repo: RepoRef<'a>,
 }
 
-impl TemplateProperty<Commit, String> for TagProperty<'_> {
-    fn extract(&self, context: &Commit) -> String {
+impl TemplateProperty<LazyCommit, String> for TagProperty<'_> {
+    fn extract(&self, context: &LazyCommit) -> String {
         let mut names = vec![];
         for (tag_name, target) in self.repo.view().tags() {
             if target.has_add(context.id()) {
@@ -294,8 +430,8 @@ pub struct GitRefsProperty<'a> {
     pub repo: RepoRef<'a>,
 }
 
-impl TemplateProperty<Commit, String> for GitRefsProperty<'_> {
-    fn extract(&self, context: &Commit) -> String {
+impl TemplateProperty<LazyCommit, String> for GitRefsProperty<'_> {
+    fn extract(&self, context: &LazyCommit) -> String {
         // TODO: We should keep a map from commit to ref names so we don't have to walk
         // all refs here.
         let mut names = vec![];
@@ -312,6 +448,20 @@ impl TemplateProperty<Commit, String> for GitRefsProperty<'_> {
     }
 }
 
+/// The Git note attached to the commit in the default notes ref.
+pub struct GitNoteProperty {
+    pub git_repo: Option<git2::Repository>,
+}
+
+impl TemplateProperty<LazyCommit, String> for GitNoteProperty {
+    fn extract(&self, context: &LazyCommit) -> String {
+        self.git_repo
+            .as_ref()
+            .and_then(|git_repo| git::get_note(git_repo, None, context.id()))
+            .unwrap_or_default()
+    }
+}
+
 pub struct IsGitHeadProperty<'a> {
     repo: RepoRef<'a>,
 }
@@ -322,8 +472,8 @@ impl<'a> IsGitHeadProperty<'a> {
     }
 }
 
-impl TemplateProperty<Commit, bool> for IsGitHeadProperty<'_> {
-    fn extract(&self, context: &Commit) -> bool {
+impl TemplateProperty<LazyCommit, bool> for IsGitHeadProperty<'_> {
+    fn extract(&self, context: &LazyCommit) -> bool {
         self.repo.view().git_head().as_ref() == Some(context.id())
     }
 }
@@ -334,36 +484,140 @@ pub struct DivergentProperty {
 
 impl DivergentProperty {
     pub fn new(repo: RepoRef) -> Self {
-        // TODO: Create a persistent index from change id to commit ids.
-        let mut commit_count_by_change: HashMap<ChangeId, i32> = HashMap::new();
-        for index_entry in RevsetExpression::all().evaluate(repo, None).unwrap().iter() {
-            let change_id = index_entry.change_id();
-            commit_count_by_change
-                .entry(change_id)
-                .or_default()
-                .add_assign(1);
-        }
-        let mut divergent_changes = HashSet::new();
-        for (change_id, count) in commit_count_by_change {
-            if count > 1 {
-                divergent_changes.insert(change_id);
-            }
-        }
+        let divergent_changes = RevsetExpression::divergent()
+            .evaluate(repo, None)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.change_id())
+            .collect();
         Self { divergent_changes }
     }
 }
 
-impl TemplateProperty<Commit, bool> for DivergentProperty {
-    fn extract(&self, context: &Commit) -> bool {
+impl TemplateProperty<LazyCommit, bool> for DivergentProperty {
+    fn extract(&self, context: &LazyCommit) -> bool {
         self.divergent_changes.contains(context.change_id())
     }
 }
 
 pub struct ConflictProperty;
 
-impl TemplateProperty<Commit, bool> for ConflictProperty {
-    fn extract(&self, context: &Commit) -> bool {
-        context.tree().has_conflict()
+impl TemplateProperty<LazyCommit, bool> for ConflictProperty {
+    fn extract(&self, context: &LazyCommit) -> bool {
+        context.loaded_commit().tree().has_conflict()
+    }
+}
+
+pub struct SignedProperty;
+
+impl TemplateProperty<LazyCommit, bool> for SignedProperty {
+    fn extract(&self, context: &LazyCommit) -> bool {
+        context.loaded_commit().secure_sig().is_some()
+    }
+}
+
+/// The result of verifying the commit's signature, or an empty string if it's
+/// not signed.
+pub struct SignatureStatusProperty {
+    pub verifier: Verifier,
+}
+
+impl TemplateProperty<LazyCommit, String> for SignatureStatusProperty {
+    fn extract(&self, context: &LazyCommit) -> String {
+        match context.loaded_commit().secure_sig() {
+            None => String::new(),
+            Some(secure_sig) => {
+                let status = match self.verifier.verify(secure_sig) {
+                    Ok(verification) => verification.status,
+                    Err(_) => SigStatus::Unknown,
+                };
+                status.as_str().to_string()
+            }
+        }
+    }
+}
+
+pub fn format_timestamp(timestamp: &Timestamp) -> String {
+    let utc = Utc
+        .timestamp(
+            timestamp.timestamp.0 as i64 / 1000,
+            (timestamp.timestamp.0 % 1000) as u32 * 1000000,
+        )
+        .with_timezone(&FixedOffset::east(timestamp.tz_offset * 60));
+    utc.format("%Y-%m-%d %H:%M:%S.%3f %:z").to_string()
+}
+
+pub struct OperationIdProperty;
+
+impl TemplateProperty<Operation, String> for OperationIdProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context.id().hex()
+    }
+}
+
+pub struct OperationDescriptionProperty;
+
+impl TemplateProperty<Operation, String> for OperationDescriptionProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context.store_operation().metadata.description.clone()
+    }
+}
+
+pub struct OperationHostnameProperty;
+
+impl TemplateProperty<Operation, String> for OperationHostnameProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context.store_operation().metadata.hostname.clone()
+    }
+}
+
+pub struct OperationUsernameProperty;
+
+impl TemplateProperty<Operation, String> for OperationUsernameProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context.store_operation().metadata.username.clone()
+    }
+}
+
+/// The time the operation started or, if `end` is set, ended.
+pub struct OperationTimeProperty {
+    pub end: bool,
+}
+
+impl TemplateProperty<Operation, String> for OperationTimeProperty {
+    fn extract(&self, context: &Operation) -> String {
+        let metadata = &context.store_operation().metadata;
+        if self.end {
+            format_timestamp(&metadata.end_time)
+        } else {
+            format_timestamp(&metadata.start_time)
+        }
+    }
+}
+
+/// The operation's tags as `key: value` lines, sorted by key.
+pub struct OperationTagsProperty;
+
+impl TemplateProperty<Operation, String> for OperationTagsProperty {
+    fn extract(&self, context: &Operation) -> String {
+        context
+            .store_operation()
+            .metadata
+            .tags
+            .iter()
+            .sorted()
+            .map(|(key, value)| format!("{}: {}", key, value))
+            .join("\n")
+    }
+}
+
+pub struct IsCurrentOperationProperty {
+    pub current_op_id: OperationId,
+}
+
+impl TemplateProperty<Operation, bool> for IsCurrentOperationProperty {
+    fn extract(&self, context: &Operation) -> bool {
+        context.id() == &self.current_op_id
     }
 }
 
@@ -438,8 +692,8 @@ impl CommitIdKeyword {
     }
 }
 
-impl TemplateProperty<Commit, CommitId> for CommitIdKeyword {
-    fn extract(&self, context: &Commit) -> CommitId {
+impl TemplateProperty<LazyCommit, CommitId> for CommitIdKeyword {
+    fn extract(&self, context: &LazyCommit) -> CommitId {
         context.id().clone()
     }
 }
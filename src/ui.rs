@@ -12,13 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use std::io::Write;
+use std::io::{LineWriter, Write};
 use std::path::{Component, Path, PathBuf};
+use std::process::{Child, Command, Stdio};
 use std::str::FromStr;
 use std::sync::{Mutex, MutexGuard};
 use std::{fmt, io};
 
 use atty::Stream;
+use itertools::Itertools;
 use jujutsu_lib::commit::Commit;
 use jujutsu_lib::op_store::WorkspaceId;
 use jujutsu_lib::repo::RepoRef;
@@ -26,11 +28,13 @@ use jujutsu_lib::repo_path::{RepoPath, RepoPathComponent, RepoPathJoin};
 use jujutsu_lib::settings::UserSettings;
 
 use crate::formatter::{ColorFormatter, Formatter, PlainTextFormatter};
-use crate::templater::TemplateFormatter;
+use crate::templater::{LazyCommit, TemplateFormatter};
 
 pub struct Ui<'a> {
     cwd: PathBuf,
     color: bool,
+    paginate: bool,
+    pager: Option<Child>,
     stdout_formatter: Mutex<Box<dyn Formatter + 'a>>,
     stderr_formatter: Mutex<Box<dyn Formatter + 'a>>,
     settings: UserSettings,
@@ -101,9 +105,15 @@ impl<'stdout> Ui<'stdout> {
     ) -> Ui<'stdout> {
         let stdout_formatter = Mutex::new(new_formatter(&settings, color, stdout));
         let stderr_formatter = Mutex::new(new_formatter(&settings, color, stderr));
+        let paginate = settings
+            .config()
+            .get_string("ui.paginate")
+            .map_or(true, |value| value != "never");
         Ui {
             cwd,
             color,
+            paginate,
+            pager: None,
             stdout_formatter,
             stderr_formatter,
             settings,
@@ -135,6 +145,65 @@ impl<'stdout> Ui<'stdout> {
         }
     }
 
+    /// Don't start a pager, even if a command asks for one.
+    pub fn disable_pager(&mut self) {
+        self.paginate = false;
+    }
+
+    /// Sends the rest of the output to stdout through a pager (`ui.pager`,
+    /// `$PAGER`, or `less -FRX` by default), if stdout is a terminal. Output is
+    /// passed on to the pager as it's written, so it shows the first screen
+    /// right away.
+    ///
+    /// Like `reset_color_for_terminal()`, this assumes that ui was created
+    /// `for_terminal()`.
+    pub fn request_pager(&mut self) {
+        if !self.paginate || self.pager.is_some() || !atty::is(Stream::Stdout) {
+            return;
+        }
+        let pager = self
+            .settings
+            .config()
+            .get_string("ui.pager")
+            .unwrap_or_else(|_| "less -FRX".to_string());
+        let args = pager.split_whitespace().collect_vec();
+        if args.is_empty() {
+            return;
+        }
+        match Command::new(args[0])
+            .args(&args[1..])
+            .stdin(Stdio::piped())
+            .spawn()
+        {
+            Ok(mut child) => {
+                let stdin = child.stdin.take().unwrap();
+                *self.stdout_formatter.get_mut().unwrap() =
+                    new_formatter(&self.settings, self.color, Box::new(LineWriter::new(stdin)));
+                self.pager = Some(child);
+            }
+            Err(err) => {
+                // Show the output without a pager instead
+                let _ =
+                    self.write_warn(format!("warning: Failed to start pager '{pager}': {err}\n"));
+            }
+        }
+    }
+
+    /// Waits for the pager to exit, if one was started. Returns whether one
+    /// was.
+    pub fn finalize_pager(&mut self) -> bool {
+        match self.pager.take() {
+            None => false,
+            Some(mut child) => {
+                // Closing the pager's input tells it that there's no more output
+                *self.stdout_formatter.get_mut().unwrap() =
+                    new_formatter(&self.settings, self.color, Box::new(io::stdout()));
+                let _ = child.wait();
+                true
+            }
+        }
+    }
+
     pub fn cwd(&self) -> &Path {
         &self.cwd
     }
@@ -207,11 +276,15 @@ impl<'stdout> Ui<'stdout> {
                     String::from(r#"commit_id.short() " " description.first_line()"#)
                 }
             });
-        let template =
-            crate::template_parser::parse_commit_template(repo, workspace_id, &template_string);
+        let template = crate::template_parser::parse_commit_template(
+            repo,
+            workspace_id,
+            &self.settings,
+            &template_string,
+        );
         let mut formatter = self.stdout_formatter();
         let mut template_writer = TemplateFormatter::new(template, formatter.as_mut());
-        template_writer.format(commit)?;
+        template_writer.format(&LazyCommit::from_commit(commit.clone()))?;
         Ok(())
     }
 
@@ -50,12 +50,18 @@ fn main() {
     let args: Args = Args::parse();
     let edit_script_path = PathBuf::from(std::env::var_os("DIFF_EDIT_SCRIPT").unwrap());
     let edit_script = String::from_utf8(std::fs::read(&edit_script_path).unwrap()).unwrap();
-    for instruction in edit_script.split('\0') {
+    let instructions = edit_script.split('\0').collect_vec();
+    for (i, instruction) in instructions.iter().enumerate() {
         let (command, payload) = instruction.split_once('\n').unwrap_or((instruction, ""));
         let parts = command.split(' ').collect_vec();
         match parts.as_slice() {
             [""] => {}
             ["fail"] => exit(1),
+            ["next_invocation"] => {
+                // Save the remaining instructions for the next invocation
+                std::fs::write(&edit_script_path, instructions[i + 1..].join("\0")).unwrap();
+                break;
+            }
             ["files-before", ..] => {
                 let expected = parts[1..].iter().copied().map(str::to_string).collect();
                 let actual = files_recursively(&args.before);
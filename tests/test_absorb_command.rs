@@ -0,0 +1,139 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_absorb() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "x\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    std::fs::write(repo_path.join("file1"), "1\n2\n3\n4\n5\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    // The line added between lines from "a" and "b" is ambiguous
+    std::fs::write(repo_path.join("file1"), "1\nTWO\n3\nNEW\n4\nFIVE\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "y\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["absorb"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Absorbed changes into 2 revisions:
+      578906dbfec2 b
+      d2a3dd8b51cf a
+    Working copy now at: 2e2bcafb62a5 (no description set)
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 2e2bcafb62a5 (no description set)
+    o 578906dbfec2 b
+    o d2a3dd8b51cf a
+    o 000000000000 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "-r", "@--"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    new file mode 100644
+    index 0000000000..230b143ae0
+    --- /dev/null
+    +++ b/file1
+    @@ -1,0 +1,3 @@
+    +1
+    +TWO
+    +3
+    diff --git a/file2 b/file2
+    new file mode 100644
+    index 0000000000..975fbec825
+    --- /dev/null
+    +++ b/file2
+    @@ -1,0 +1,1 @@
+    +y
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 230b143ae0...06a9507edc 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,3 +1,5 @@
+     1
+     TWO
+     3
+    +4
+    +FIVE
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index 06a9507edc...e168b707d9 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,5 +1,6 @@
+     1
+     TWO
+     3
+    +NEW
+     4
+     FIVE
+    "###);
+
+    // Nothing left that can be absorbed
+    let stdout = test_env.jj_cmd_success(&repo_path, &["absorb"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing changed.
+    "###);
+}
+
+#[test]
+fn test_absorb_paths() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["absorb", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Absorbed changes into 1 revisions:
+      e5c8ea4048e6 (no description set)
+    Working copy now at: f531c862d8db (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    "###);
+
+    // Changes aren't moved into revisions outside the destination set
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["absorb", "--into", "none()"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No revisions to absorb changes into
+    "###);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &["log", "-T", "commit_id.short() \" \" description"],
+    )
+}
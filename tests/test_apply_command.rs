@@ -0,0 +1,178 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_apply_diff() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "x\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(
+        test_env.env_root().join("patch.diff"),
+        "\
+--- a/file1
++++ b/file1
+@@ -2,2 +2,2 @@
+ b
+-c
++C
+--- a/file2
++++ /dev/null
+@@ -1 +0,0 @@
+-x
+--- /dev/null
++++ b/file3
+@@ -0,0 +1 @@
++new
+",
+    )
+    .unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["apply", "../patch.diff"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 023250225578 (no description set)
+    Added 1 files, modified 1 files, removed 1 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file1 b/file1
+    index de980441c3...6dcce7d0cf 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,3 +1,3 @@
+     a
+     b
+    -c
+    +C
+    diff --git a/file2 b/file2
+    deleted file mode 100644
+    index 587be6b4c3..0000000000
+    --- a/file2
+    +++ /dev/null
+    @@ -1,1 +1,0 @@
+    -x
+    diff --git a/file3 b/file3
+    new file mode 100644
+    index 0000000000..3e757656cf
+    --- /dev/null
+    +++ b/file3
+    @@ -1,0 +1,1 @@
+    +new
+    "###);
+
+    // The patch no longer applies
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["apply", "../patch.diff"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Patch does not apply to file1
+    "###);
+}
+
+#[test]
+fn test_apply_format_patch_round_trip() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "Add file\n\nWith a body."]);
+    std::fs::write(repo_path.join("file"), "a\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "Modify file"]);
+    std::fs::write(repo_path.join("file"), "a\nB").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["format-patch", "-r", "@--:@-", "-o", "../patches"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    ../patches/0001-Add-file.patch
+    ../patches/0002-Modify-file.patch
+    "###);
+
+    // Apply the patches in a new branch, with a change in the working copy
+    test_env.jj_cmd_success(&repo_path, &["checkout", "root"]);
+    std::fs::write(repo_path.join("other"), "other\n").unwrap();
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "apply",
+            "--commit",
+            "../patches/0001-Add-file.patch",
+            "../patches/0002-Modify-file.patch",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Created bd873d4b040e Add file
+    Created 7c12b0e79251 Modify file
+    Working copy now at: 7642c6fa0416 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 7642c6fa0416 Test User 2001-02-03 04:05:12.000 +07:00 (no description set)
+    o 7c12b0e79251 Test User 2001-02-03 04:05:09.000 +07:00 Modify file
+    o bd873d4b040e Test User 2001-02-03 04:05:07.000 +07:00 Add file
+    | o da3f0bd888ce Test User 2001-02-03 04:05:09.000 +07:00 Modify file
+    | o 7a556a6a04cb Test User 2001-02-03 04:05:07.000 +07:00 Add file
+    |/  
+    o 000000000000  1970-01-01 00:00:00.000 +00:00 (no description set)
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file")).unwrap(),
+        "a\nB"
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A other
+    "###);
+}
+
+#[test]
+fn test_apply_from_stdin() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let assert = test_env
+        .jj_cmd(&repo_path, &["apply"])
+        .write_stdin("--- /dev/null\n+++ b/file\n@@ -0,0 +1 @@\n+a\n")
+        .assert()
+        .success();
+    insta::assert_snapshot!(common::get_stdout_string(&assert), @r###"
+    Working copy now at: 4ebf85a935e3 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["apply", "-"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No patches found
+    "###);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-T",
+            r#"commit_id.short() " " author.name() " " author.timestamp() " " description.first_line()"#,
+        ],
+    )
+}
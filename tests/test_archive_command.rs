@@ -0,0 +1,133 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::io::Read;
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn set_up(test_env: &TestEnvironment) -> std::path::PathBuf {
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    std::fs::write(repo_path.join("dir").join("file2"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file3"), "c\n").unwrap();
+    repo_path
+}
+
+fn list_tar(reader: impl Read) -> String {
+    let mut archive = tar::Archive::new(reader);
+    let mut output = String::new();
+    for entry in archive.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let path = entry.path().unwrap().to_str().unwrap().to_owned();
+        let mut content = String::new();
+        entry.read_to_string(&mut content).unwrap();
+        output.push_str(&format!("{}: {}", path, content));
+    }
+    output
+}
+
+#[test]
+fn test_archive_tar() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["archive", "-r", "@-", "-o", "out.tar"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Archived 2 files
+    "###);
+    let file = std::fs::File::open(repo_path.join("out.tar")).unwrap();
+    insta::assert_snapshot!(list_tar(file), @r###"
+    dir/file2: b
+    file1: a
+    "###);
+
+    // Paths can be filtered
+    test_env.jj_cmd_success(&repo_path, &["archive", "-o", "../out.tgz", "dir", "file3"]);
+    let file = std::fs::File::open(test_env.env_root().join("out.tgz")).unwrap();
+    insta::assert_snapshot!(list_tar(flate2::read::GzDecoder::new(file)), @r###"
+    dir/file2: b
+    file3: c
+    "###);
+}
+
+#[test]
+fn test_archive_zip() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    let output_path = test_env.env_root().join("out.zip");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["archive", "-r", "@-", "-o", output_path.to_str().unwrap()],
+    );
+    let mut archive = zip::ZipArchive::new(std::fs::File::open(output_path).unwrap()).unwrap();
+    let mut output = String::new();
+    for i in 0..archive.len() {
+        let mut file = archive.by_index(i).unwrap();
+        let mut content = String::new();
+        file.read_to_string(&mut content).unwrap();
+        output.push_str(&format!("{}: {}", file.name(), content));
+    }
+    insta::assert_snapshot!(output, @r###"
+    dir/file2: b
+    file1: a
+    "###);
+}
+
+#[test]
+fn test_archive_dir() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    let output_dir = test_env.env_root().join("out");
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["archive", "--output-dir", output_dir.to_str().unwrap()],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Archived 3 files
+    "###);
+    assert_eq!(read(&output_dir.join("dir").join("file2")), "b\n");
+    assert_eq!(read(&output_dir.join("file3")), "c\n");
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &["archive", "--output-dir", output_dir.to_str().unwrap()],
+    );
+    insta::assert_snapshot!(stderr.replace(output_dir.to_str().unwrap(), "$OUT"), @r###"
+    Error: Output directory $OUT is not empty
+    "###);
+}
+
+#[test]
+fn test_archive_unknown_format() {
+    let test_env = TestEnvironment::default();
+    let repo_path = set_up(&test_env);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["archive", "-o", "out.rar"]);
+    insta::assert_snapshot!(stderr.replace(repo_path.to_str().unwrap(), "$REPO"), @r###"
+    Error: Can't tell the archive format of $REPO/out.rar; use a name ending in .tar, .tar.gz, .tgz, or .zip
+    "###);
+    test_env.jj_cmd_cli_error(&repo_path, &["archive"]);
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap()
+}
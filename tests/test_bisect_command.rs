@@ -0,0 +1,167 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn create_stack(test_env: &TestEnvironment, repo_path: &Path, num_commits: u32) {
+    for i in 1..=num_commits {
+        test_env.jj_cmd_success(repo_path, &["new", "-m", &format!("commit {i}")]);
+        std::fs::write(repo_path.join("file"), format!("{i}\n")).unwrap();
+    }
+    test_env.jj_cmd_success(repo_path, &["new"]);
+}
+
+#[test]
+fn test_bisect_interactive() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    create_stack(&test_env, &repo_path, 6);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "bad", "@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Marked 7362216d5a27 commit 6 as bad
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "good", "root"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Marked 000000000000 (no description set) as good
+    Bisecting: 6 revisions left to test
+    Working copy now at: 65ef2e3fd629 (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    // Marks the revision being tested by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "good"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Marked 1e65182cebc3 commit 3 as good
+    Bisecting: 2 revisions left to test
+    Working copy now at: ad92f43f62e6 (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "bad"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Marked 3fbcf695bd0b commit 5 as bad
+    Bisecting: 1 revisions left to test
+    Working copy now at: 2cc228d4afb6 (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "bad"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Marked 04a0eb9c59be commit 4 as bad
+    The first bad revision is: 04a0eb9c59be commit 4
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "reset"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Cleared the bisection state
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["bisect", "reset"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Not bisecting
+    "###);
+}
+
+#[test]
+fn test_bisect_merge() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "base"]);
+    for args in [
+        vec!["new", "-m", "l1"],
+        vec!["new", "-m", "l2"],
+        vec!["new", "-m", "r1", "description(base)"],
+        vec!["new", "-m", "r2"],
+        vec!["new", "-m", "r3"],
+        vec!["new", "-m", "merge", "description(l2)", "description(r3)"],
+        vec!["new", "-m", "c1"],
+        vec!["new", "-m", "c2"],
+    ] {
+        test_env.jj_cmd_success(&repo_path, &args);
+    }
+
+    // The merge has 6 of the 8 candidates as ancestors, so r3 (with 3) splits
+    // them most evenly
+    test_env.jj_cmd_success(&repo_path, &["bisect", "bad", "description(c2)"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["bisect", "good", "description(base)"]);
+    assert!(stdout.contains("Bisecting: 7 revisions left to test"));
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-r", "@-", "--no-graph", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    r3
+    "###);
+}
+
+#[test]
+fn test_bisect_bad_ancestor_of_good() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    create_stack(&test_env, &repo_path, 2);
+
+    test_env.jj_cmd_success(&repo_path, &["bisect", "good", "@-"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["bisect", "bad", "@--"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The bad revision is an ancestor of a good revision
+    "###);
+}
+
+#[cfg(unix)]
+#[test]
+fn test_bisect_run() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    create_stack(&test_env, &repo_path, 6);
+
+    let stderr = test_env.jj_cmd_failure(
+        &repo_path,
+        &[
+            "bisect",
+            "run",
+            "--command",
+            "sh",
+            "-c",
+            "test $(cat file) -lt 4",
+        ],
+    );
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Mark a bad and a good revision with `jj bisect bad` and `jj bisect good` first
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["bisect", "bad", "@-"]);
+    test_env.jj_cmd_success(&repo_path, &["bisect", "good", "root"]);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "bisect",
+            "run",
+            "--command",
+            "sh",
+            "-c",
+            "test $(cat file) -lt 4",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    1e65182cebc3 commit 3: good
+    3fbcf695bd0b commit 5: bad
+    04a0eb9c59be commit 4: bad
+    The first bad revision is: 04a0eb9c59be commit 4
+    "###);
+}
@@ -0,0 +1,112 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+/// Drops the change ids from the output since they're random.
+fn strip_change_ids(stdout: &str) -> String {
+    stdout
+        .lines()
+        .map(|line| format!("{}\n", line.split_once(' ').unwrap().1))
+        .collect()
+}
+
+#[test]
+fn test_blame() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "a\nb\nc\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file"), "a\nB\nc\nd\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("other"), "unrelated\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["blame", "file"]);
+    let stdout = strip_change_ids(&stdout);
+    insta::assert_snapshot!(stdout, @r###"
+    bf8e1c93bf88 1: a
+    8783899a1227 2: B
+    bf8e1c93bf88 3: c
+    8783899a1227 4: d
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["annotate", "-r", "@--", "file"]);
+    let stdout = strip_change_ids(&stdout);
+    insta::assert_snapshot!(stdout, @r###"
+    bf8e1c93bf88 1: a
+    bf8e1c93bf88 2: b
+    bf8e1c93bf88 3: c
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["blame", "nonexistent"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No such path
+    "###);
+}
+
+#[test]
+fn test_blame_merge() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "a\nb\nc\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "base"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file"), "left\na\nb\nc\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "right", "@-"]);
+    std::fs::write(repo_path.join("file"), "a\nb\nc\nright\n").unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "-m", "merge", "@", "description(left)"],
+    );
+    std::fs::write(repo_path.join("file"), "left\na\nb\nc\nright\nmerge").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["blame", "file"]);
+    let stdout = strip_change_ids(&stdout);
+    insta::assert_snapshot!(stdout, @r###"
+    ce8c5a7e5d87 1: left
+    05e781ca7af1 2: a
+    05e781ca7af1 3: b
+    05e781ca7af1 4: c
+    47378575f234 5: right
+    223215e8f597 6: merge
+    "###);
+}
+
+#[test]
+fn test_blame_rename() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("old"), "a\nb\nc\n").unwrap();
+    std::fs::write(repo_path.join("unrelated"), "x\ny\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("old")).unwrap();
+    std::fs::remove_file(repo_path.join("unrelated")).unwrap();
+    std::fs::write(repo_path.join("new"), "a\nb\nc\nd\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["blame", "new"]);
+    let stdout = strip_change_ids(&stdout);
+    insta::assert_snapshot!(stdout, @r###"
+    82e9a64b68ce 1: a
+    82e9a64b68ce 2: b
+    82e9a64b68ce 3: c
+    a2f3e96e3531 4: d
+    "###);
+}
@@ -55,6 +55,96 @@ fn test_branch_multiple_names() {
     "###);
 }
 
+#[test]
+fn test_branch_track_untrack() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    let first_oid = git_repo
+        .commit(
+            Some("refs/heads/feature"),
+            &signature,
+            &signature,
+            "first",
+            &empty_tree,
+            &[],
+        )
+        .unwrap();
+
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "add",
+            "origin",
+            git_repo_path.to_str().unwrap(),
+        ],
+    );
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: eafa497979dd first
+    "###);
+
+    // An untracked remote branch is listed even if it's at the local target
+    test_env.jj_cmd_success(&repo_path, &["branch", "untrack", "feature@origin"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: eafa497979dd first
+      @origin (untracked): eafa497979dd first
+    "###);
+
+    // Fetching doesn't move the local branch if the remote branch is untracked
+    let first_commit = git_repo.find_commit(first_oid).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/feature"),
+            &signature,
+            &signature,
+            "second",
+            &empty_tree,
+            &[&first_commit],
+        )
+        .unwrap();
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: eafa497979dd first
+      @origin (untracked) (ahead by 1 commits): d48dba0488c9 second
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["branch", "track", "feature@origin"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: eafa497979dd first
+      @origin (ahead by 1 commits): d48dba0488c9 second
+    "###);
+
+    // Tracking a remote branch creates the local branch if it doesn't exist
+    test_env.jj_cmd_success(&repo_path, &["branch", "delete", "feature"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "track", "feature@origin"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: d48dba0488c9 second
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["branch", "track", "feature"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Remote branch must be specified as <branch>@<remote>: feature
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["branch", "untrack", "other@origin"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No such remote branch: other@origin
+    "###);
+}
+
 fn get_log_output(test_env: &TestEnvironment, cwd: &Path) -> String {
     test_env.jj_cmd_success(cwd, &["log", "-T", r#"branches " " commit_id.short()"#])
 }
@@ -14,7 +14,7 @@
 
 use std::path::Path;
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, TestEnvironment};
 
 pub mod common;
 
@@ -88,6 +88,55 @@ fn test_checkout() {
     "###);
 }
 
+#[test]
+fn test_checkout_resume_interrupted() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "contents1\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "contents2\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "files"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "add files"]);
+    test_env.jj_cmd_success(&repo_path, &["checkout", "root"]);
+
+    // An untracked file in the way makes the checkout fail after it has written
+    // some of the files
+    test_env.add_config(br#"snapshot.auto-track = "none""#);
+    std::fs::write(repo_path.join("file2"), "untracked\n").unwrap();
+    let assert = test_env
+        .jj_cmd(&repo_path, &["checkout", "files"])
+        .assert()
+        .code(255);
+    let stderr =
+        get_stderr_string(&assert).replace(&test_env.env_root().display().to_string(), "$TEST_ENV");
+    insta::assert_snapshot!(stderr, @r###"
+    Internal error: Failed to check out commit 8caab9daf5b10ea7c0359fcfecfec0a89da30241: Failed to open file $TEST_ENV/repo/file2 for writing: Os { code: 17, kind: AlreadyExists, message: "File exists" }
+    "###);
+
+    // The next command finishes the checkout. The file that was in the way is
+    // kept and shows up as modified.
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Resumed interrupted update of the working copy
+    Parent commit: 3fedb46c926d add files
+    Working copy : ba39ea3fe30d (no description set)
+    Working copy changes:
+    M file2
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file1")).unwrap(),
+        "contents1\n"
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 3fedb46c926d add files
+    Working copy : ba39ea3fe30d (no description set)
+    Working copy changes:
+    M file2
+    "###);
+}
+
 fn get_log_output(test_env: &TestEnvironment, cwd: &Path) -> String {
     test_env.jj_cmd_success(cwd, &["log", "-T", r#"commit_id " " description"#])
 }
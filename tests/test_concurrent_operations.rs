@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::common::TestEnvironment;
+use itertools::Itertools;
+use regex::Regex;
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -29,9 +32,18 @@ fn test_concurrent_operation_divergence() {
     );
 
     // We should be informed about the concurrent modification
-    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
-    insta::assert_snapshot!(stdout, @r###"
+    let assert = test_env
+        .jj_cmd(&repo_path, &["log", "-T", "description"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Concurrent operations changed the working-copy commit of workspace 'default' differently. Keeping the one from the operation that finished first.
+    "###);
+    insta::assert_snapshot!(redact_op_ids(&get_stdout_string(&assert)), @r###"
     Concurrent modification detected, resolving automatically.
+    Merging these operations:
+      $OP_ID describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+      $OP_ID describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
     o message 2
     | @ message 1
     |/  
@@ -39,6 +51,58 @@ fn test_concurrent_operation_divergence() {
     "###);
 }
 
+#[test]
+fn test_concurrent_rewrite_divergent_change() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "message 1"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-m", "message 2", "--at-op", "@-"],
+    );
+
+    let assert = test_env
+        .jj_cmd(
+            &repo_path,
+            &["log", "-r", "divergent()", "-T", "description"],
+        )
+        .assert()
+        .success();
+    insta::assert_snapshot!(redact_op_ids(&get_stdout_string(&assert)), @r###"
+    Concurrent modification detected, resolving automatically.
+    Merging these operations:
+      $OP_ID describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+      $OP_ID describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    o message 2
+    ~ 
+    @ message 1
+    ~
+    "###);
+    // Change ids are random, so replace the divergent one with a placeholder
+    let change_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "change_id"],
+    );
+    let short_change_id = &change_id[..12];
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    let stdout = stdout.replace(short_change_id, "$CHANGE_ID");
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 50a629dc6316 message 1
+    These changes are divergent:
+      $CHANGE_ID (2 commits)
+      Use `jj log -r 'divergent()'` to see details. Use `jj abandon` on the commits you don't want to keep.
+    The working copy is clean
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-r", "divergent()", "-m", "3"]);
+    let stderr = stderr.replace(short_change_id, "$CHANGE_ID");
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Revset "divergent()" resolved to more than one revision because change $CHANGE_ID is divergent. Use a commit id to select one of its commits.
+    "###);
+}
+
 #[test]
 fn test_concurrent_operations_auto_rebase() {
     let test_env = TestEnvironment::default();
@@ -57,12 +121,108 @@ fn test_concurrent_operations_auto_rebase() {
     );
 
     // We should be informed about the concurrent modification
-    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "commit_id \" \" description"]);
-    insta::assert_snapshot!(stdout, @r###"
+    let assert = test_env
+        .jj_cmd(&repo_path, &["log", "-T", "commit_id \" \" description"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Concurrent operations changed the working-copy commit of workspace 'default' differently. Keeping the one from the operation that finished first.
+    "###);
+    insta::assert_snapshot!(redact_op_ids(&get_stdout_string(&assert)), @r###"
     Concurrent modification detected, resolving automatically.
+    Merging these operations:
+      $OP_ID describe commit 2062e7d6f1f46b4fe1453040d691931e77a88f7c
+      $OP_ID new empty commit
     Rebased 1 descendant commits onto commits rewritten by other operation
     o 4eeb7d76372418118a91c34f09e5e3936f0deeb5 new child
     @ 14176aeadc0259b2150fc7374969e74b1552a498 rewritten
     o 0000000000000000000000000000000000000000 (no description set)
     "###);
 }
+
+#[test]
+fn test_concurrent_operations_conflicting_ref() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // Create two sibling commits on top of "first", so neither of the concurrent
+    // branch moves below is a fast-forward of the other
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    let second_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-r", "@", "-T", "commit_id"],
+    );
+    test_env.jj_cmd_success(&repo_path, &["new", "@-", "-m", "third"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "foo", "-r", "@-"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "foo", "-r", "@"]);
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["branch", "set", "foo", "-r", &second_id, "--at-op", "@-"],
+    );
+
+    // The branch was moved differently by the two operations, so it's left
+    // conflicted and we're told so
+    let assert = test_env
+        .jj_cmd(&repo_path, &["branch", "list"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Concurrent operations changed some refs differently. These refs are now conflicted:
+      branch foo
+    "###);
+    let stdout = redact_op_ids(&get_stdout_string(&assert));
+    insta::assert_snapshot!(stdout.lines().take(4).join("\n"), @r###"
+    Concurrent modification detected, resolving automatically.
+    Merging these operations:
+      $OP_ID point branch foo to commit c082b5dc8556f7e6cfaddc0062e94d912e96211e
+      $OP_ID point branch foo to commit e4c4886e135d7ed53a5e65bcc279346e5718b1e1
+    "###);
+    assert!(stdout.contains("foo (conflicted):"));
+}
+
+#[test]
+fn test_concurrent_working_copy_lock() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let lock_path = repo_path.join(".jj/working_copy/working_copy.lock");
+
+    // Pretend that another command holds the working copy's lock
+    std::fs::write(&lock_path, "").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    let stderr = stderr.replace(&test_env.env_root().display().to_string(), "$TEST_ENV");
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Another jj command is using the working copy. Wait for it to finish, or run the command with --wait.
+    If no other command is running, a command may have crashed. Delete the lock file $TEST_ENV/repo/./.jj/working_copy/working_copy.lock to recover.
+    "###);
+
+    // With a timeout, the command gives up if the lock isn't released in time
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status", "--wait=1"]);
+    assert!(stderr.starts_with("Error: Another jj command is using the working copy."));
+    assert!(lock_path.exists());
+
+    // With --wait, the command runs once the lock is released
+    let release_lock = std::thread::spawn({
+        let lock_path = lock_path.clone();
+        move || {
+            std::thread::sleep(std::time::Duration::from_millis(200));
+            std::fs::remove_file(lock_path).unwrap();
+        }
+    });
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status", "--wait"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 000000000000 (no description set)
+    Working copy : 230dd059e1b0 (no description set)
+    The working copy is clean
+    "###);
+    release_lock.join().unwrap();
+    assert!(!lock_path.exists());
+}
+
+fn redact_op_ids(output: &str) -> String {
+    // Operation ids depend on the time and host the operations ran at
+    let op_id_regex = Regex::new(r"(?m)^  [0-9a-f]{12} ").unwrap();
+    op_id_regex.replace_all(output, "  $$OP_ID ").to_string()
+}
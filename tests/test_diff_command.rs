@@ -42,20 +42,20 @@ fn test_diff_basic() {
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
     insta::assert_snapshot!(stdout, @r###"
-    R file1
     M file2
-    A file3
+    R file1 -> file3
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 1 -
+    file2 | 1 +
+    file3 | 1 +
+    3 files changed, 2 insertions(+), 1 deletions(-)
     "###);
 
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
     insta::assert_snapshot!(stdout, @r###"
-    diff --git a/file1 b/file1
-    deleted file mode 100644
-    index 257cc5642c..0000000000
-    --- a/file1
-    +++ /dev/null
-    @@ -1,1 +1,0 @@
-    -foo
     diff --git a/file2 b/file2
     index 257cc5642c...3bd1f0e297 100644
     --- a/file2
@@ -63,13 +63,40 @@ fn test_diff_basic() {
     @@ -1,1 +1,2 @@
      foo
     +bar
-    diff --git a/file3 b/file3
-    new file mode 100644
-    index 0000000000..257cc5642c
-    --- /dev/null
-    +++ b/file3
-    @@ -1,0 +1,1 @@
-    +foo
+    diff --git a/file1 b/file3
+    similarity index 100%
+    rename from file1
+    rename to file3
+    "###);
+}
+
+#[test]
+fn test_diff_paths_snapshot() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+
+    // Only the given paths are snapshotted
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file1
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "--no-commit-working-copy"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file1
+    "###);
+
+    // Other commands snapshot the rest
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file1
+    M file2
     "###);
 }
 
@@ -313,3 +340,223 @@ fn test_color_words_diff_missing_newline() {
        9     : I
     "###);
 }
+
+#[test]
+fn test_diff_stat_scaled() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n".repeat(200)).unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n".repeat(10)).unwrap();
+    std::fs::write(repo_path.join("file3"), "").unwrap();
+
+    // The histogram is scaled down to fit in 80 columns
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--stat"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1 | 200 +++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++++
+    file2 |  10 +++
+    file3 |   0
+    3 files changed, 210 insertions(+), 0 deletions(-)
+    "###);
+}
+
+#[test]
+fn test_diff_tool() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo\n").unwrap();
+
+    let edit_script = test_env.set_up_fake_diff_editor();
+    let diff_tool_path = assert_cmd::cargo::cargo_bin("fake-diff-editor");
+    let diff_tool_path = diff_tool_path.to_str().unwrap();
+
+    // Only the changed files are passed to the tool
+    std::fs::write(
+        &edit_script,
+        "files-before file1 file2\0files-after file2 file3",
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--tool", diff_tool_path]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // The paths restrict the files passed to the tool
+    std::fs::write(&edit_script, "files-before file2\0files-after file2").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--tool", diff_tool_path, "file2"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // The tool's exit status is ignored
+    std::fs::write(&edit_script, "fail").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--tool", diff_tool_path]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // The configured tool is used by default, but not if a format is requested
+    test_env.add_config(format!("ui.diff-tool = {diff_tool_path:?}").as_bytes());
+    std::fs::write(
+        &edit_script,
+        "files-before file1 file2\0files-after file2 file3",
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    R file1 -> file3
+    "###);
+
+    // Both --tool and a format
+    test_env.jj_cmd_cli_error(&repo_path, &["diff", "--tool", diff_tool_path, "--git"]);
+
+    // Nonexistent tool
+    let stderr =
+        test_env.jj_cmd_failure(&repo_path, &["diff", "--tool", "this-tool-does-not-exist"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to show diff: Error executing diff tool 'this-tool-does-not-exist': No such file or directory (os error 2)
+    "###);
+}
+
+#[test]
+fn test_diff_git_renames() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "bar\n").unwrap();
+    std::fs::write(repo_path.join("empty1"), "").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::rename(repo_path.join("file1"), repo_path.join("renamed1")).unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    std::fs::write(repo_path.join("renamed2"), "bar\nbaz\n").unwrap();
+    std::fs::rename(repo_path.join("empty1"), repo_path.join("empty2")).unwrap();
+
+    // Renames of non-empty files are detected, also if the contents changed
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/empty1 b/empty1
+    deleted file mode 100644
+    index e69de29bb2..0000000000
+    --- a/empty1
+    +++ /dev/null
+    diff --git a/empty2 b/empty2
+    new file mode 100644
+    index 0000000000..e69de29bb2
+    --- /dev/null
+    +++ b/empty2
+    diff --git a/file1 b/renamed1
+    similarity index 100%
+    rename from file1
+    rename to renamed1
+    diff --git a/file2 b/renamed2
+    similarity index 50%
+    rename from file2
+    rename to renamed2
+    index 5716ca5987...e2994c5ae2 100644
+    --- a/file2
+    +++ b/renamed2
+    @@ -1,1 +1,2 @@
+     bar
+    +baz
+    "###);
+}
+
+#[test]
+fn test_diff_rename_config() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\nb\nc\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::remove_file(repo_path.join("file1")).unwrap();
+    std::fs::write(repo_path.join("renamed1"), "a\nb\nC\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo\nbar\n").unwrap();
+    std::fs::write(repo_path.join("copied2"), "foo\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A copied2
+    M file2
+    R file1 -> renamed1
+    "###);
+
+    test_env.add_config(
+        br#"[diff]
+    copies = true
+    "#,
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    C file2 -> copied2
+    M file2
+    R file1 -> renamed1
+    "###);
+
+    test_env.add_config(
+        br#"[diff]
+    rename-similarity = 100
+    "#,
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    C file2 -> copied2
+    R file1
+    M file2
+    A renamed1
+    "###);
+
+    test_env.add_config(
+        br#"[diff]
+    renames = false
+    "#,
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A copied2
+    R file1
+    M file2
+    A renamed1
+    "###);
+}
+
+#[test]
+fn test_diff_resolved_conflict() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "base\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file"), "left\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "right", "@-"]);
+    std::fs::write(repo_path.join("file"), "right\n").unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "-m", "merge", "@", "description(left)"],
+    );
+    std::fs::write(repo_path.join("file"), "resolved\n").unwrap();
+
+    // The resolution is shown as the changes from each side of the conflict
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff"]);
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines[0], "Resolved conflict in file:");
+    assert!(lines[1].starts_with("Side #1 ("));
+    assert!(lines[3].starts_with("Side #2 ("));
+    assert_eq!(lines.len(), 5);
+    assert!(stdout.contains("   1    1: leftresolved\n"));
+    assert!(stdout.contains("   1    1: rightresolved\n"));
+
+    // The Git format shows the conflict markers
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--git"]);
+    assert!(stdout.contains("\n-<<<<<<<"));
+    assert!(stdout.contains("\n+resolved\n"));
+}
@@ -0,0 +1,67 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_eol_conversion_input_output() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"core.eol-conversion = "input-output"
+core.eol-conversion-patterns = ["*.txt"]
+"#,
+    );
+
+    // CRLF is committed as LF, except in files that don't match the patterns
+    std::fs::write(repo_path.join("file.txt"), "a\r\nb\r\n").unwrap();
+    std::fs::write(repo_path.join("file.bat"), "a\r\nb\r\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file.txt"]);
+    assert_eq!(stdout, "a\nb\n");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file.bat"]);
+    assert_eq!(stdout, "a\r\nb\r\n");
+
+    // LF is written as CRLF on checkout
+    std::fs::remove_file(repo_path.join("file.txt")).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["restore", "--from", "@-"]);
+    let contents = std::fs::read_to_string(repo_path.join("file.txt")).unwrap();
+    assert_eq!(contents, "a\r\nb\r\n");
+
+    // The converted file isn't considered modified
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @"");
+}
+
+#[test]
+fn test_eol_conversion_input() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"core.eol-conversion = "input""#);
+
+    std::fs::write(repo_path.join("file"), "a\r\nb\r\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file"]);
+    assert_eq!(stdout, "a\nb\n");
+
+    // Files are written as they are in the commit
+    std::fs::remove_file(repo_path.join("file")).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["restore", "--from", "@-"]);
+    let contents = std::fs::read_to_string(repo_path.join("file")).unwrap();
+    assert_eq!(contents, "a\nb\n");
+}
@@ -0,0 +1,114 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &["log", "-T", r#"commit_id.short() " " description"#],
+    )
+}
+
+#[test]
+fn test_no_auto_rebase_flag() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "c"]);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-r", "@--", "-m", "a2", "--no-auto-rebase"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Left 2 descendant commits in place (use `jj evolve` to rebase them)
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    o 52c93e256a2e a2
+    | @ 4a7ec0d38a37 c
+    | o ae426da34c53 b
+    | o bcd64d31e699 a
+    |/  
+    o 000000000000 (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["evolve"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 2 descendant commits
+    Working copy now at: 3277c3eb4dc7 c
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 3277c3eb4dc7 c
+    o 06f0c2182f9e b
+    o 52c93e256a2e a2
+    o 000000000000 (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["evolve"]);
+    insta::assert_snapshot!(stdout, @r###"
+    No revisions need to be rebased
+    "###);
+}
+
+#[test]
+fn test_no_auto_rebase_config() {
+    let test_env = TestEnvironment::default();
+    test_env.add_config(
+        br#"[ui]
+auto-rebase = false"#,
+    );
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+
+    // Editing the grandparent leaves the parent and the working copy in place
+    let stdout = test_env.jj_cmd_success(&repo_path, &["describe", "-r", "@--", "-m", "a"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Left 2 descendant commits in place (use `jj evolve` to rebase them)
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    o 58f87f0a181b a
+    | @ d5ada988376c (no description set)
+    | o 0e91d9b82b87 (no description set)
+    | o 4ebf85a935e3 (no description set)
+    |/  
+    o 000000000000 (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["evolve"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 2 descendant commits
+    Working copy now at: 7c0e7760c0b7 (no description set)
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 7c0e7760c0b7 (no description set)
+    o 703f89033213 (no description set)
+    o 58f87f0a181b a
+    o 000000000000 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file"]);
+    insta::assert_snapshot!(stdout, @r###"
+    b
+    "###);
+}
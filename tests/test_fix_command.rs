@@ -0,0 +1,107 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use itertools::Itertools;
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+fn set_up_fake_formatter(test_env: &TestEnvironment, args: &[&str]) {
+    let formatter_path = assert_cmd::cargo::cargo_bin("fake-formatter");
+    assert!(formatter_path.is_file());
+    // Simplified TOML escaping, hoping that there are no '"' or control characters
+    // in it
+    let escaped_formatter_path = formatter_path.to_str().unwrap().replace('\\', r"\\");
+    let command = [format!(r#""{escaped_formatter_path}""#)]
+        .into_iter()
+        .chain(args.iter().map(|arg| format!(r#""{arg}""#)))
+        .join(", ");
+    test_env.add_config(
+        format!(
+            r###"
+        [fix.tools.upper]
+        command = [{command}]
+        extensions = ["txt"]
+        "###
+        )
+        .as_bytes(),
+    );
+}
+
+#[test]
+fn test_fix() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["fix"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No fix tools are configured (see the `fix.tools` config table)
+    "###);
+
+    set_up_fake_formatter(&test_env, &["--uppercase"]);
+    std::fs::write(repo_path.join("file.txt"), "a\n").unwrap();
+    std::fs::write(repo_path.join("file.rs"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("other.txt"), "b\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["fix", "-r", "all() ~ root"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Fixed 2 commits
+    Working copy now at: 1cb01396581b (no description set)
+    Added 0 files, modified 2 files, removed 0 files
+    "###);
+    // Only files with a matching extension are fixed
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file.txt"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file.rs"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A other.txt
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("other.txt")).unwrap(),
+        "B\n"
+    );
+
+    // Nothing left to fix
+    let stdout = test_env.jj_cmd_success(&repo_path, &["fix", "-r", "all() ~ root"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing changed.
+    "###);
+}
+
+#[test]
+fn test_fix_failing_tool() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    set_up_fake_formatter(&test_env, &["--fail"]);
+    std::fs::write(repo_path.join("file.txt"), "a\n").unwrap();
+    let assert = test_env.jj_cmd(&repo_path, &["fix"]).assert().success();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    Nothing changed.
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    fake-formatter: failing as requested
+    Fix tool 'upper' failed on file.txt; leaving it unchanged
+    "###);
+}
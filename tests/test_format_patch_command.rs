@@ -0,0 +1,103 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_format_patch() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["describe", "-m", "Add file1\n\nThis is the body."],
+    );
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "Modify file1 (and add file2)"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "c\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["format-patch", "-r", "@-:@", "-o", "../patches"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    ../patches/0001-Add-file1.patch
+    ../patches/0002-Modify-file1-and-add-file2.patch
+    "###);
+    let patch = std::fs::read_to_string(
+        test_env
+            .env_root()
+            .join("patches")
+            .join("0001-Add-file1.patch"),
+    )
+    .unwrap();
+    insta::assert_snapshot!(patch, @r###"
+    From 84a7d232698badb6ef40835dcce48bd79b0a5cbf Mon Sep 17 00:00:00 2001
+    From: Test User <test.user@example.com>
+    Date: Sat, 3 Feb 2001 04:05:07 +0700
+    Subject: [PATCH 1/2] Add file1
+
+    This is the body.
+    ---
+    diff --git a/file1 b/file1
+    new file mode 100644
+    index 0000000000..7898192261
+    --- /dev/null
+    +++ b/file1
+    @@ -1,0 +1,1 @@
+    +a
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["format-patch", "--stdout"]);
+    insta::assert_snapshot!(stdout, @r###"
+    From 095597a081eda6984127d9fe5d71d24eb52bfe9b Mon Sep 17 00:00:00 2001
+    From: Test User <test.user@example.com>
+    Date: Sat, 3 Feb 2001 04:05:09 +0700
+    Subject: [PATCH] Modify file1 (and add file2)
+
+    ---
+    diff --git a/file1 b/file1
+    index 7898192261...6178079822 100644
+    --- a/file1
+    +++ b/file1
+    @@ -1,1 +1,1 @@
+    -a
+    +b
+    diff --git a/file2 b/file2
+    new file mode 100644
+    index 0000000000..f2ad6c76f0
+    --- /dev/null
+    +++ b/file2
+    @@ -1,0 +1,1 @@
+    +c
+    "###);
+}
+
+#[test]
+fn test_format_patch_merge() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "@", "@-"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["format-patch", "--stdout"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Can't create a patch for merge commit 521ab9d8499a
+    "###);
+}
@@ -0,0 +1,67 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_fsck() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents 1\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["commit", "-m", "first"]);
+    std::fs::write(repo_path.join("file"), "contents 2\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["commit", "-m", "second"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["fsck"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Checked 13 objects
+    "###);
+
+    // Replace the file in the first commit with the one in the second, and remove
+    // the working-copy commit
+    let objects_dir = repo_path.join(".jj/repo/store/git/objects");
+    let contents1_path = objects_dir.join("80/2b1c4ed7b06162b2ce09b7db72a576695b96e5");
+    let contents2_path = objects_dir.join("07/6e8e37a712d8a66c0c3d1a103050dc509ca6ff");
+    std::fs::remove_file(&contents1_path).unwrap();
+    std::fs::copy(&contents2_path, &contents1_path).unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-r", "@", "-T", "commit_id"]);
+    let wc_commit_id = stdout
+        .lines()
+        .next()
+        .unwrap()
+        .trim_start_matches(&['@', ' '][..]);
+    std::fs::remove_file(
+        objects_dir
+            .join(&wc_commit_id[..2])
+            .join(&wc_commit_id[2..]),
+    )
+    .unwrap();
+    let assert = test_env.jj_cmd(&repo_path, &["fsck"]).assert().code(1);
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout).to_string();
+    let stderr = String::from_utf8_lossy(&assert.get_output().stderr).to_string();
+    insta::assert_snapshot!(stdout, @r###"
+    Checked 12 objects
+    Missing commit c128d162d4c93c7a2b555bc2d9caecb6955b5b83
+      Used by commits: c128d162d4c9
+    Corrupt file 802b1c4ed7b06162b2ce09b7db72a576695b96e5
+      Used by commits: 5949a18f50d9 ad542ae87b7c f26237e884bc
+    "###);
+    insta::assert_snapshot!(stderr, @r###"
+    Hint: Restore the objects from a backup or another clone of the repo, or abandon the affected commits with `jj abandon`.
+    Error: Found 2 corrupt or missing objects
+    "###);
+}
@@ -0,0 +1,82 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_watchman() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    // A fake watchman that always reports "file2" as the only changed file
+    let program = test_env.env_root().join("fake-watchman");
+    std::fs::write(
+        &program,
+        r#"#!/bin/sh
+request=$(cat)
+case "$request" in
+  '["watch-project"'*) echo '{"watch": "/root"}' ;;
+  '["clock"'*) echo '{"clock": "c:1"}' ;;
+  '["query","/root",{"fields":["name"],"since":"c:1"}]')
+    echo '{"clock": "c:2", "is_fresh_instance": false, "files": ["file2"]}' ;;
+  *) echo '{"error": "unexpected request"}' ;;
+esac
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    test_env.add_config(
+        format!(
+            "core.fsmonitor = \"watchman\"\ncore.watchman.program = \"{}\"\n",
+            program.to_str().unwrap()
+        )
+        .as_bytes(),
+    );
+
+    // Without a clock, the whole working copy is snapshotted
+    std::fs::write(repo_path.join("file1"), "1").unwrap();
+    std::fs::write(repo_path.join("file2"), "2").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["new"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 9efd1975f16d (no description set)
+    "###);
+
+    // Only the files reported by watchman are snapshotted
+    std::fs::write(repo_path.join("file1"), "1 changed").unwrap();
+    std::fs::write(repo_path.join("file2"), "2 changed").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    M file2
+    "###);
+}
+
+#[test]
+fn test_fsmonitor_unknown() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(b"core.fsmonitor = \"inotify\"\n");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: Unknown filesystem monitor in core.fsmonitor: inotify
+    "###);
+}
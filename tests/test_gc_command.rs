@@ -0,0 +1,65 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_gc() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    // Write an object that isn't referenced from anywhere
+    let orphan_path = repo_path.join(".jj/repo/store/files/0123456789abcdef");
+    std::fs::write(&orphan_path, "orphan").unwrap();
+
+    // Recently written objects are kept by default
+    let stdout = test_env.jj_cmd_success(&repo_path, &["gc"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Removed 0 objects (0 bytes)
+    "###);
+    assert!(orphan_path.exists());
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["gc", "--dry-run", "--min-age=0"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Would remove 1 objects (6 bytes)
+    "###);
+    assert!(orphan_path.exists());
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["gc", "--min-age=0"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Removed 1 objects (6 bytes)
+    "###);
+    assert!(!orphan_path.exists());
+
+    // The committed file is still there
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@-", "file"]);
+    insta::assert_snapshot!(stdout, @r###"
+    contents
+    "###);
+}
+
+#[test]
+fn test_gc_git_backend() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["gc"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The git backend doesn't support garbage collection
+    "###);
+}
@@ -78,3 +78,44 @@ fn test_git_clone() {
     Error: Destination path exists and is not an empty directory
     "###);
 }
+
+#[test]
+fn test_git_clone_depth() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("source");
+    let git_repo = git2::Repository::init(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    let first_oid = git_repo
+        .commit(None, &signature, &signature, "first", &empty_tree, &[])
+        .unwrap();
+    let first_commit = git_repo.find_commit(first_oid).unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/main"),
+            &signature,
+            &signature,
+            "second",
+            &empty_tree,
+            &[&first_commit],
+        )
+        .unwrap();
+    git_repo.set_head("refs/heads/main").unwrap();
+
+    // Only the last commit is cloned, and it becomes a child of the root commit
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", "source", "clone", "--depth", "1"],
+    );
+    let stdout = test_env.jj_cmd_success(
+        &test_env.env_root().join("clone"),
+        &["log", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ (no description set)
+    o second
+    ~
+    "###);
+}
@@ -53,9 +53,6 @@ fn test_git_colocated_rebase_on_import() {
     std::fs::write(workspace_root.join("file"), "modified").unwrap();
     test_env.jj_cmd_success(&workspace_root, &["branch", "set", "master"]);
     test_env.jj_cmd_success(&workspace_root, &["close", "-m", "modify a file"]);
-    // TODO: We shouldn't need this command here to trigger an import of the
-    // refs/heads/master we just exported
-    test_env.jj_cmd_success(&workspace_root, &["st"]);
 
     // Move `master` backwards, which should cause the working copy to be rebased
     // off of the old position.
@@ -72,10 +69,43 @@ fn test_git_colocated_rebase_on_import() {
         test_env.jj_cmd_success(&workspace_root, &["log", "-T", "commit_id \" \" branches"]);
     insta::assert_snapshot!(stdout, @r###"
     Rebased 1 descendant commits off of commits rewritten from git
-    Working copy now at: a64f325e0516 (no description set)
+    Working copy now at: fe9ec31ee98d (no description set)
     Added 0 files, modified 1 files, removed 0 files
-    @ a64f325e05167129f3488f85a570f22a8940634f 
+    @ fe9ec31ee98dda327a3502bb884b962d646ce43f 
     o f0f3ab56bfa927e3a65c2ac9a513693d438e271b master
-    o 0000000000000000000000000000000000000000 
+    o 0000000000000000000000000000000000000000
+    "###);
+}
+
+#[test]
+fn test_git_colocated_init_git() {
+    let test_env = TestEnvironment::default();
+    let workspace_root = test_env.env_root().join("repo");
+    let git_repo = git2::Repository::init(&workspace_root).unwrap();
+
+    // `jj init --git` in an existing Git checkout shares the working copy with it
+    // instead of creating an internal Git repo
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["init", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Initialized repo in "."
+    "###);
+    assert!(!workspace_root.join(".jj/repo/store/git").exists());
+
+    std::fs::write(workspace_root.join("new-file"), "contents").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "add a file"]);
+    test_env.jj_cmd_success(&workspace_root, &["branch", "create", "main", "-r", "@-"]);
+    let stdout =
+        test_env.jj_cmd_success(&workspace_root, &["log", "-T", "commit_id \" \" branches"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ 2588800a4ee68926773f1e9c44dcc50ada923650 
+    o 172b1cbfe88c97cbd1b1c8a98a48e729a4540e85 main master
+    o 0000000000000000000000000000000000000000
     "###);
+    let main_oid = git_repo
+        .find_branch("main", git2::BranchType::Local)
+        .unwrap()
+        .get()
+        .target()
+        .unwrap();
+    assert_eq!(git_repo.head().unwrap().target().unwrap(), main_oid);
 }
@@ -0,0 +1,145 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::TcpListener;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+/// Starts an HTTP server that asks for basic authentication, and records the
+/// `Authorization` headers it gets. Requests with credentials get a 404.
+fn start_auth_server() -> (String, Arc<Mutex<Vec<String>>>) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let url = format!("http://{}/repo.git", listener.local_addr().unwrap());
+    let authorizations = Arc::new(Mutex::new(vec![]));
+    let server_authorizations = authorizations.clone();
+    std::thread::spawn(move || {
+        for stream in listener.incoming() {
+            let mut stream = stream.unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut authorization = None;
+            loop {
+                let mut line = String::new();
+                if reader.read_line(&mut line).unwrap() == 0 || line.trim_end().is_empty() {
+                    break;
+                }
+                if let Some((name, value)) = line.trim_end().split_once(": ") {
+                    if name.eq_ignore_ascii_case("authorization") {
+                        authorization = Some(value.to_string());
+                    }
+                }
+            }
+            let response = match authorization {
+                None => {
+                    "HTTP/1.1 401 Unauthorized\r\nWWW-Authenticate: Basic realm=\"test\"\r\n\
+                     Content-Length: 0\r\nConnection: close\r\n\r\n"
+                }
+                Some(authorization) => {
+                    server_authorizations.lock().unwrap().push(authorization);
+                    "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                }
+            };
+            stream.write_all(response.as_bytes()).unwrap();
+        }
+    });
+    (url, authorizations)
+}
+
+fn set_up(test_env: &TestEnvironment, url: &str) -> PathBuf {
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["git", "remote", "add", "origin", url]);
+    repo_path
+}
+
+#[test]
+fn test_git_fetch_askpass() {
+    let mut test_env = TestEnvironment::default();
+    let (url, authorizations) = start_auth_server();
+    let repo_path = set_up(&test_env, &url);
+    let askpass_path = test_env.env_root().join("askpass");
+    let prompts_path = test_env.env_root().join("prompts");
+    std::fs::write(
+        &askpass_path,
+        format!(
+            r#"#!/bin/sh
+echo "$1" >>"{}"
+case "$1" in
+  Username*) echo user ;;
+  Password*) echo secret ;;
+esac
+"#,
+            prompts_path.to_str().unwrap()
+        ),
+    )
+    .unwrap();
+    std::fs::set_permissions(&askpass_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    test_env.add_env_var("GIT_ASKPASS", askpass_path.to_str().unwrap());
+
+    // The server only responds with a 404 after getting the credentials
+    test_env.jj_cmd_failure(&repo_path, &["git", "fetch"]);
+    let prompts = std::fs::read_to_string(&prompts_path).unwrap();
+    insta::assert_snapshot!(prompts.replace(&url, "$URL"), @r###"
+    Username for '$URL': 
+    Password for '$URL':
+    "###);
+    // "user:secret"
+    assert_eq!(
+        *authorizations.lock().unwrap(),
+        vec!["Basic dXNlcjpzZWNyZXQ=".to_string()]
+    );
+}
+
+#[test]
+fn test_git_fetch_credential_helper() {
+    let test_env = TestEnvironment::default();
+    let (url, authorizations) = start_auth_server();
+    let repo_path = set_up(&test_env, &url);
+    let git_repo = git2::Repository::open(repo_path.join(".jj/repo/store/git")).unwrap();
+    git_repo
+        .config()
+        .unwrap()
+        .set_str(
+            "credential.helper",
+            "!f() { echo username=helper; echo password=pw; }; f",
+        )
+        .unwrap();
+
+    test_env.jj_cmd_failure(&repo_path, &["git", "fetch"]);
+    // "helper:pw"
+    assert_eq!(
+        *authorizations.lock().unwrap(),
+        vec!["Basic aGVscGVyOnB3".to_string()]
+    );
+}
+
+#[test]
+fn test_git_fetch_no_credentials() {
+    let test_env = TestEnvironment::default();
+    let (url, authorizations) = start_auth_server();
+    let repo_path = set_up(&test_env, &url);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["git", "fetch"]);
+    insta::assert_snapshot!(stderr.replace(&url, "$URL"), @r###"
+    Error: Authentication with the remote failed: No valid credentials for $URL (configure a Git credential helper or set GIT_ASKPASS)
+    "###);
+    assert!(authorizations.lock().unwrap().is_empty());
+}
@@ -0,0 +1,168 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::{Path, PathBuf};
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn set_up() -> (TestEnvironment, PathBuf) {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    let git_repo = git2::Repository::init_bare(&git_repo_path).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let empty_tree_oid = git_repo.treebuilder(None).unwrap().write().unwrap();
+    let empty_tree = git_repo.find_tree(empty_tree_oid).unwrap();
+    for branch in ["main", "feature/a", "feature/b", "other"] {
+        git_repo
+            .commit(
+                Some(&format!("refs/heads/{branch}")),
+                &signature,
+                &signature,
+                &format!("commit on {branch}"),
+                &empty_tree,
+                &[],
+            )
+            .unwrap();
+    }
+
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "remote",
+            "add",
+            "origin",
+            git_repo_path.to_str().unwrap(),
+        ],
+    );
+    (test_env, repo_path)
+}
+
+fn get_branch_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(repo_path, &["branch", "list"])
+}
+
+#[test]
+fn test_git_fetch_all() {
+    let (test_env, repo_path) = set_up();
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @r###"
+    feature/a: b24723dc2bfa commit on feature/a
+    feature/b: 8d0e578a8285 commit on feature/b
+    main: cbfb2b4d66c7 commit on main
+    other: 02eacbcd0511 commit on other
+    "###);
+}
+
+#[test]
+fn test_git_fetch_refspecs() {
+    let (test_env, repo_path) = set_up();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "git",
+            "fetch",
+            "--refspec",
+            "main:upstream",
+            "--refspec",
+            "feature/*",
+        ],
+    );
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @r###"
+    feature/a: b24723dc2bfa commit on feature/a
+    feature/b: 8d0e578a8285 commit on feature/b
+    main: cbfb2b4d66c7 commit on main
+    upstream: cbfb2b4d66c7 commit on main
+    "###);
+}
+
+#[test]
+fn test_git_fetch_configured_refspecs() {
+    let (test_env, repo_path) = set_up();
+    test_env.add_config(
+        br#"[git.remotes.origin]
+    fetch = ["other"]
+    "#,
+    );
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch"]);
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @r###"
+    other: 02eacbcd0511 commit on other
+    "###);
+
+    // Refspecs on the command line override the configured ones
+    test_env.jj_cmd_success(&repo_path, &["git", "fetch", "--refspec", "main"]);
+    insta::assert_snapshot!(get_branch_output(&test_env, &repo_path), @r###"
+    main: cbfb2b4d66c7 commit on main
+    other: 02eacbcd0511 commit on other
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["git", "fetch", "--refspec", "a:b*"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid refspec 'a:b*'
+    "###);
+}
+
+#[test]
+fn test_git_fetch_depth() {
+    let (test_env, repo_path) = set_up();
+    let git_repo = git2::Repository::open(test_env.env_root().join("git-repo")).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    for description in ["second on main", "third on main"] {
+        let parent = git_repo
+            .find_reference("refs/heads/main")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        git_repo
+            .commit(
+                Some("refs/heads/main"),
+                &signature,
+                &signature,
+                description,
+                &parent.tree().unwrap(),
+                &[&parent],
+            )
+            .unwrap();
+    }
+
+    // Only the tip of the branch is fetched, and it becomes a child of the root
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["git", "fetch", "--refspec", "main", "--depth", "1"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description", "-r", ":main"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o third on main
+    o (no description set)
+    "###);
+
+    // Fetching with a larger depth deepens the history
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["git", "fetch", "--refspec", "main", "--depth", "3"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description", "-r", ":main"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o third on main
+    o second on main
+    o commit on main
+    o (no description set)
+    "###);
+}
@@ -0,0 +1,192 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+fn set_up() -> (TestEnvironment, PathBuf) {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    test_env.add_config(b"[git]\nrun-hooks = true\n");
+    let repo_path = test_env.env_root().join("repo");
+    (test_env, repo_path)
+}
+
+fn write_hook(repo_path: &Path, name: &str, script: &str) {
+    let hooks_dir = repo_path.join(".jj/repo/store/git/hooks");
+    std::fs::create_dir_all(&hooks_dir).unwrap();
+    let hook_path = hooks_dir.join(name);
+    std::fs::write(&hook_path, format!("#!/bin/sh\n{script}")).unwrap();
+    std::fs::set_permissions(&hook_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(repo_path, &["log", "-T", "description"])
+}
+
+#[test]
+fn test_hooks_not_run_by_default() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    write_hook(&repo_path, "commit-msg", "exit 1\n");
+    write_hook(&repo_path, "pre-commit", "exit 1\n");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "second"]);
+}
+
+#[test]
+fn test_commit_msg_hook() {
+    let (test_env, repo_path) = set_up();
+    write_hook(
+        &repo_path,
+        "commit-msg",
+        r#"grep -q "^WIP" "$1" && echo "no WIP commits" >&2 && exit 1
+echo "Checked-by: hook" >>"$1"
+"#,
+    );
+
+    // The hook can edit the description
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first\n"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ first
+    | Checked-by: hook
+    o (no description set)
+    "###);
+
+    // The hook can reject the description
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-m", "WIP: second"]);
+    insta::assert_snapshot!(stderr, @r###"
+    no WIP commits
+    Error: The commit-msg hook failed (exit status: 1)
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["close", "-m", "WIP: second"]);
+    insta::assert_snapshot!(stderr, @r###"
+    no WIP commits
+    Error: The commit-msg hook failed (exit status: 1)
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ first
+    | Checked-by: hook
+    o (no description set)
+    "###);
+}
+
+#[test]
+fn test_pre_commit_hook() {
+    let (test_env, repo_path) = set_up();
+    write_hook(
+        &repo_path,
+        "pre-commit",
+        r#"grep -q TODO file && echo "file has TODOs" >&2 && exit 1
+exit 0
+"#,
+    );
+
+    // The hook sees the working-copy contents
+    std::fs::write(repo_path.join("file"), "TODO\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["close", "-m", "first"]);
+    insta::assert_snapshot!(stderr, @r###"
+    file has TODOs
+    Error: The pre-commit hook failed (exit status: 1)
+    "###);
+    std::fs::write(repo_path.join("file"), "done\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "first"]);
+
+    // The hook isn't run when closing a commit that's not checked out
+    std::fs::write(repo_path.join("file"), "TODO\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "@-"]);
+    insta::assert_snapshot!(
+        test_env.jj_cmd_success(
+            &repo_path,
+            &["log", "-T", r#"description.first_line() " " open"#]
+        ),
+        @r###"
+    @ (no description set) true
+    o second false
+    o first false
+    o (no description set) false
+    "###);
+}
+
+#[test]
+fn test_pre_push_hook() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    git2::Repository::init_bare(&git_repo_path).unwrap();
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &["git", "clone", git_repo_path.to_str().unwrap(), "repo"],
+    );
+    test_env.add_config(b"[git]\nrun-hooks = true\n");
+    let repo_path = test_env.env_root().join("repo");
+    let hook_output = test_env.env_root().join("hook-output");
+    write_hook(
+        &repo_path,
+        "pre-push",
+        &format!(
+            r#"echo "$@" >"{0}"
+cat >>"{0}"
+test -f "{1}" && echo "push rejected" >&2 && exit 1
+exit 0
+"#,
+            hook_output.to_str().unwrap(),
+            test_env.env_root().join("reject").to_str().unwrap()
+        ),
+    );
+
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main", "-r", "@-"]);
+    std::fs::write(test_env.env_root().join("reject"), "").unwrap();
+    let assert = test_env
+        .jj_cmd(&repo_path, &["git", "push", "--branch", "main"])
+        .assert()
+        .failure();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    Branch changes to push to origin:
+      Add branch main to 85a1e2839620
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    push rejected
+    Error: The pre-push hook failed (exit status: 1)
+    "###);
+    // The hook gets the remote and the refs to push, like in Git
+    let hook_output = std::fs::read_to_string(&hook_output).unwrap();
+    insta::assert_snapshot!(
+        hook_output.replace(git_repo_path.to_str().unwrap(), "$REMOTE_URL"),
+        @r###"
+    origin $REMOTE_URL
+    refs/heads/main 85a1e2839620cf0b354d1ccb970927d040c2a4a7 refs/heads/main 0000000000000000000000000000000000000000
+    "###);
+    assert!(git2::Repository::open(&git_repo_path)
+        .unwrap()
+        .find_reference("refs/heads/main")
+        .is_err());
+
+    std::fs::remove_file(test_env.env_root().join("reject")).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["git", "push", "--branch", "main"]);
+    assert!(git2::Repository::open(&git_repo_path)
+        .unwrap()
+        .find_reference("refs/heads/main")
+        .is_ok());
+}
@@ -14,7 +14,9 @@
 
 use std::path::PathBuf;
 
-use crate::common::TestEnvironment;
+use regex::Regex;
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
 
 pub mod common;
 
@@ -167,6 +169,116 @@ fn test_git_push_all() {
     "###);
 }
 
+#[test]
+fn test_git_push_refspecs() {
+    let (test_env, workspace_root) = set_up();
+    test_env.jj_cmd_success(&workspace_root, &["branch", "create", "my-branch"]);
+    test_env.jj_cmd_success(&workspace_root, &["describe", "-m", "foo"]);
+    // Only the branches matching the refspecs are pushed
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--refspec", "my-branch:renamed"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Add branch my-branch (as renamed) to ba36d7ef35d1
+    "###);
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    branch1: a3ccc578ea7b description 1
+    branch2: 7fd4b07286b3 description 2
+    my-branch: ba36d7ef35d1 foo
+    renamed: ba36d7ef35d1 foo
+    "###);
+    // The branch is compared with the branch it was pushed to
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--refspec", "my-branch:renamed"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing changed.
+    "###);
+
+    // Configured refspecs are used when pushing branches by other means
+    test_env.add_config(
+        br#"[git.remotes.origin]
+    push = ["my-*:pushed-*"]
+    "#,
+    );
+    test_env.jj_cmd_success(&workspace_root, &["describe", "-m", "bar"]);
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--branch", "my-branch", "--dry-run"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Add branch my-branch (as pushed-branch) to b5fdc0c0ca53
+    Dry-run requested, not pushing.
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--refspec", "+main"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid refspec '+main'
+    "###);
+}
+
+#[test]
+fn test_git_push_moved_remote_branch() {
+    let (test_env, workspace_root) = set_up();
+    // Someone else moves branch1 on the remote
+    let git_repo = git2::Repository::open(test_env.env_root().join("git-repo")).unwrap();
+    let signature =
+        git2::Signature::new("Some One", "some.one@example.com", &git2::Time::new(0, 0)).unwrap();
+    let parent = git_repo
+        .find_reference("refs/heads/branch1")
+        .unwrap()
+        .peel_to_commit()
+        .unwrap();
+    git_repo
+        .commit(
+            Some("refs/heads/branch1"),
+            &signature,
+            &signature,
+            "pushed by someone else",
+            &parent.tree().unwrap(),
+            &[&parent],
+        )
+        .unwrap();
+    test_env.jj_cmd_success(
+        &workspace_root,
+        &["describe", "branch1", "-m", "modified branch1 commit"],
+    );
+
+    // The push is refused since the remote branch isn't where we last saw it. The
+    // changes are listed before connecting to the remote, so they're still printed.
+    let assert = test_env
+        .jj_cmd(&workspace_root, &["git", "push", "--branch", "branch1"])
+        .assert()
+        .failure();
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    Branch changes to push to origin:
+      Force branch branch1 from a3ccc578ea7b to 5d0d85ed3da7
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: Refs have moved on the remote since they were last fetched: refs/heads/branch1 (run `jj git fetch` to see the changes, or push with --force to overwrite them)
+    "###);
+
+    // It can be overwritten with --force
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--branch", "branch1", "--force"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Branch changes to push to origin:
+      Force branch branch1 from a3ccc578ea7b to 5d0d85ed3da7
+    "###);
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    branch1: 5d0d85ed3da7 modified branch1 commit
+    branch2: 7fd4b07286b3 description 2
+    "###);
+}
+
 #[test]
 fn test_git_push_unsnapshotted_change() {
     let (test_env, workspace_root) = set_up();
@@ -177,6 +289,34 @@ fn test_git_push_unsnapshotted_change() {
     test_env.jj_cmd_success(&workspace_root, &["git", "push", "--change", "@"]);
 }
 
+#[test]
+fn test_git_push_multiple_changes() {
+    let (test_env, workspace_root) = set_up();
+    test_env.jj_cmd_success(&workspace_root, &["describe", "-m", "foo"]);
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["new", "-m", "bar"]);
+    std::fs::write(workspace_root.join("file"), "modified").unwrap();
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["git", "push", "--change", "root..@"]);
+    assert_eq!(stdout.matches("Creating branch push-").count(), 2);
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["branch", "list"]);
+    let push_branch_pattern = Regex::new(r"^push-[0-9a-f]{12}: ").unwrap();
+    assert_eq!(
+        stdout
+            .lines()
+            .filter(|line| push_branch_pattern.is_match(line))
+            .count(),
+        2
+    );
+
+    // Pushing the same revisions again reuses the branches
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--change", "@-", "--change", "@"],
+    );
+    assert!(!stdout.contains("Creating branch"));
+    insta::assert_snapshot!(stdout.lines().last().unwrap(), @"Nothing changed.");
+}
+
 #[test]
 fn test_git_push_conflict() {
     let (test_env, workspace_root) = set_up();
@@ -190,7 +330,48 @@ fn test_git_push_conflict() {
     test_env.jj_cmd_success(&workspace_root, &["close", "-m", "third"]);
     let stderr = test_env.jj_cmd_failure(&workspace_root, &["git", "push", "--all"]);
     insta::assert_snapshot!(stderr, @r###"
-    Error: Won't push commit 50ccff1aeab0 since it has conflicts
+    Error: Won't push commit e4b41c4e598f since it has conflicts
+    "###);
+}
+
+#[test]
+fn test_git_push_conflict_round_trip() {
+    let (test_env, workspace_root) = set_up();
+    std::fs::write(workspace_root.join("file"), "first\n").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "first"]);
+    std::fs::write(workspace_root.join("file"), "second\n").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "second"]);
+    std::fs::write(workspace_root.join("file"), "third\n").unwrap();
+    test_env.jj_cmd_success(&workspace_root, &["rebase", "-r", "@", "-d", "@--"]);
+    test_env.jj_cmd_success(&workspace_root, &["branch", "set", "my-branch"]);
+    test_env.jj_cmd_success(&workspace_root, &["close", "-m", "third"]);
+    test_env.jj_cmd_success(
+        &workspace_root,
+        &["git", "push", "--branch", "my-branch", "--allow-conflicts"],
+    );
+
+    // The conflict can be read from a fresh clone
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &[
+            "git",
+            "clone",
+            test_env.env_root().join("git-repo").to_str().unwrap(),
+            "jj-repo2",
+        ],
+    );
+    let stdout = test_env.jj_cmd_success(
+        &test_env.env_root().join("jj-repo2"),
+        &["print", "-r", "my-branch", "file"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    <<<<<<<
+    %%%%%%% Changes from d80cf39f9de9 second to 941fb4de3e10 first
+    -second
+    +first
+    +++++++ Contents of 87282ddf0490 (no description set)
+    third
+    >>>>>>>
     "###);
 }
 
@@ -65,3 +65,60 @@ fn test_gitignores() {
     A file3
     "###);
 }
+
+#[test]
+fn test_gitignores_default_excludes_file() {
+    let test_env = TestEnvironment::default();
+    let workspace_root = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+
+    // Without core.excludesFile, Git reads ignores from ~/.config/git/ignore
+    let git_config_dir = test_env.home_dir().join(".config").join("git");
+    std::fs::create_dir_all(&git_config_dir).unwrap();
+    std::fs::write(git_config_dir.join("ignore"), "*.o\n").unwrap();
+
+    std::fs::create_dir(workspace_root.join("dir")).unwrap();
+    std::fs::write(workspace_root.join("dir").join(".gitignore"), "target/\n").unwrap();
+    std::fs::create_dir(workspace_root.join("dir").join("target")).unwrap();
+    std::fs::write(workspace_root.join("dir").join("target").join("out"), "").unwrap();
+    std::fs::write(workspace_root.join("dir").join("main.c"), "").unwrap();
+    std::fs::write(workspace_root.join("dir").join("main.o"), "").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A dir/.gitignore
+    A dir/main.c
+    "###);
+}
+
+#[test]
+fn test_gitignores_external_git_repo() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    git2::Repository::init(&git_repo_path).unwrap();
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &[
+            "init",
+            "repo",
+            "--git-repo",
+            git_repo_path.to_str().unwrap(),
+        ],
+    );
+    let workspace_root = test_env.env_root().join("repo");
+
+    // The backing Git repo's info/exclude applies even though the working copy
+    // isn't shared with it
+    std::fs::write(
+        git_repo_path.join(".git").join("info").join("exclude"),
+        "*.log\n",
+    )
+    .unwrap();
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    std::fs::write(workspace_root.join("build.log"), "contents").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file
+    "###);
+}
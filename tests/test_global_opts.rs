@@ -161,10 +161,14 @@ fn test_help() {
         -r, --revision <REVISION>    The revision to touch up [default: @]
 
     GLOBAL OPTIONS:
+            --allow-large-files              Snapshot new files even if they're too large
             --at-operation <AT_OPERATION>    Operation to load the repo at [default: @] [aliases: at-op]
             --color <WHEN>                   When to colorize output (always, never, auto)
         -h, --help                           Print help information, more help with --help than with -h
+            --no-auto-rebase                 Don't rebase descendants of rewritten commits
             --no-commit-working-copy         Don't commit the working copy
+            --no-pager                       Don't send long output to a pager
         -R, --repository <REPOSITORY>        Path to repository to operate on
+            --wait[=<SECONDS>]               Wait for other commands using the working copy to finish
     "###);
 }
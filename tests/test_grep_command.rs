@@ -0,0 +1,62 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_grep() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("file1"), "foo\nbar\nfoobar\n").unwrap();
+    std::fs::write(repo_path.join("dir").join("file2"), "bar\nfood").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "foo\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["grep", "fo+"]);
+    insta::assert_snapshot!(stdout, @r###"
+    dir/file2:2:food
+    file1:1:foo
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["grep", "^bar$", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    dir/file2:1:bar
+    file1:2:bar
+    "###);
+    // The newline at the end of the file doesn't start another line
+    let stdout = test_env.jj_cmd_success(&repo_path, &["grep", "^$"]);
+    insta::assert_snapshot!(stdout, @"");
+    // Paths are relative to the current directory
+    let stdout = test_env.jj_cmd_success(&repo_path.join("dir"), &["grep", "foo", "."]);
+    insta::assert_snapshot!(stdout, @r###"
+    file2:2:food
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["grep", "bar", "-r", "@|@-", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a54cc0e5dfa6:file1:2:bar
+    a54cc0e5dfa6:file1:3:foobar
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["grep", "("]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Invalid regular expression: regex parse error:
+        (
+        ^
+    error: unclosed group
+    "###);
+}
@@ -112,6 +112,59 @@ fn test_init_git_external() {
     "###);
 }
 
+#[test]
+fn test_init_git_external_worktree() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    init_git_repo(&git_repo_path);
+    let git_repo = git2::Repository::open(&git_repo_path).unwrap();
+    let parent = git_repo.head().unwrap().peel_to_commit().unwrap();
+    let git_signature = parent.author();
+    git_repo
+        .commit(
+            Some("refs/heads/other-branch"),
+            &git_signature,
+            &git_signature,
+            "Commit in worktree",
+            &parent.tree().unwrap(),
+            &[&parent],
+        )
+        .unwrap();
+    let other_branch = git_repo.find_reference("refs/heads/other-branch").unwrap();
+    let worktree_path = test_env.env_root().join("git-worktree");
+    let mut worktree_options = git2::WorktreeAddOptions::new();
+    worktree_options.reference(Some(&other_branch));
+    git_repo
+        .worktree("git-worktree", &worktree_path, Some(&worktree_options))
+        .unwrap();
+
+    test_env.jj_cmd_success(
+        test_env.env_root(),
+        &[
+            "init",
+            "repo",
+            "--git-repo",
+            worktree_path.to_str().unwrap(),
+        ],
+    );
+
+    // The jj repo is backed by the main Git repo, not the worktree's Git dir
+    let workspace_root = test_env.env_root().join("repo");
+    let store_path = workspace_root.join(".jj").join("repo").join("store");
+    let git_target_file_contents = std::fs::read_to_string(store_path.join("git_target")).unwrap();
+    assert!(git_target_file_contents
+        .replace('\\', "/")
+        .trim_end_matches('/')
+        .ends_with("/git-repo/.git"));
+
+    // The worktree's HEAD got checked out instead of the main repo's
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["log", "--no-graph", "-r", "@-", "-T", "description"],
+    );
+    insta::assert_snapshot!(stdout, @"Commit in worktree");
+}
+
 #[test]
 fn test_init_git_colocated() {
     let test_env = TestEnvironment::default();
@@ -166,3 +219,76 @@ fn test_init_local() {
     assert!(store_path.join("symlinks").is_dir());
     assert!(store_path.join("conflicts").is_dir());
 }
+
+#[test]
+fn test_init_bare() {
+    let test_env = TestEnvironment::default();
+    let git_repo_path = test_env.env_root().join("git-repo");
+    init_git_repo(&git_repo_path);
+
+    let stdout = test_env.jj_cmd_success(
+        test_env.env_root(),
+        &[
+            "init",
+            "repo",
+            "--bare",
+            "--git-repo",
+            git_repo_path.to_str().unwrap(),
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Initialized bare repo in "repo"
+    "###);
+
+    let workspace_root = test_env.env_root().join("repo");
+    let jj_path = workspace_root.join(".jj");
+    assert!(jj_path.join("repo").is_dir());
+    assert!(!jj_path.join("working_copy").exists());
+
+    // The Git refs were imported, but nothing was checked out
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["log"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o 8d698d4a8ee1 d3866db7e30a git.user@example.com 1970-01-01 01:02:03.000 +01:00 my-branch   HEAD@git
+    | My commit message
+    o 000000000000 000000000000  1970-01-01 00:00:00.000 +00:00   
+      (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &workspace_root,
+        &["diff", "--from", "root", "--to", "my-branch"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    Added regular file some-file:
+            1: some content
+    "###);
+    assert!(!workspace_root.join("some-file").exists());
+
+    // Commands that need a working copy fail
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["new", "my-branch"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: This command needs a working copy, but this is a bare repo.
+    "###);
+    let stderr = test_env.jj_cmd_failure(&workspace_root, &["sparse", "--list"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: This command needs a working copy, but this is a bare repo.
+    "###);
+}
+
+#[test]
+fn test_init_bare_local() {
+    let test_env = TestEnvironment::default();
+    let stdout = test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--bare"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Initialized bare repo in "repo"
+    "###);
+
+    let workspace_root = test_env.env_root().join("repo");
+    assert!(!workspace_root.join(".jj").join("working_copy").exists());
+    // Files in the workspace root aren't snapshotted
+    std::fs::write(workspace_root.join("file"), "contents").unwrap();
+    let stdout = test_env.jj_cmd_success(&workspace_root, &["log"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o 000000000000 000000000000  1970-01-01 00:00:00.000 +00:00   
+      (no description set)
+    "###);
+}
@@ -154,6 +154,50 @@ fn test_log_reversed() {
     first
     second
     "###);
+
+    // The order can be reversed by default in the config
+    test_env.add_config(br#"ui.log-reversed = true"#);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    o (no description set)
+    o first
+    @ second
+    "###);
+}
+
+#[test]
+fn test_log_limit() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "c"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description", "--limit=2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ c
+    o b
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-T", "description", "-n2", "--reversed"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    o b
+    @ c
+    "###);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-Tdescription", "-n2", "--no-graph", "--reversed"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    b
+    c
+    "###);
 }
 
 #[test]
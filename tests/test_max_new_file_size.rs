@@ -0,0 +1,63 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_max_new_file_size() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.max-new-file-size = "10B""#);
+
+    // A new file above the limit is refused
+    std::fs::write(repo_path.join("small"), "0123456789").unwrap();
+    std::fs::write(repo_path.join("large"), "01234567890").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["files"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to snapshot the working copy: New file large of size 11 bytes exceeds snapshot.max-new-file-size (10 bytes)
+    Ignore the file, raise the limit, or run the command with --allow-large-files to snapshot it anyway.
+    "###);
+
+    // It can be snapshotted anyway with the flag
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files", "--allow-large-files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    large
+    small
+    "###);
+
+    // The limit doesn't apply to files that are already tracked
+    std::fs::write(repo_path.join("small"), "01234567890").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A large
+    A small
+    "###);
+}
+
+#[test]
+fn test_max_new_file_size_disabled() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(b"snapshot.max-new-file-size = 0");
+
+    std::fs::write(repo_path.join("large"), vec![b'x'; 2 * 1024 * 1024]).unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    large
+    "###);
+}
@@ -0,0 +1,100 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &["log", "-T", r#"change_id " " description " " branches"#],
+    )
+}
+
+#[test]
+fn test_migrate_store() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    std::fs::write(repo_path.join("file"), "contents 1\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    std::fs::write(repo_path.join("file"), "contents 2\n").unwrap();
+    let log_before = get_log_output(&test_env, &repo_path);
+    let op_log_before = test_env.jj_cmd_success(&repo_path, &["op", "log"]);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["debug", "migrate-store", "--backend=git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Migrated 5 commits and 7 operations to the 'git' backend
+    "###);
+    assert!(repo_path.join(".jj/repo/store/git").is_dir());
+    // Change ids, descriptions and branches are preserved
+    assert_eq!(get_log_output(&test_env, &repo_path), log_before);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: 5949a18f50d9 first
+    Working copy : 267c0b9b4e30 second
+    Working copy changes:
+    M file
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-r", "@-", "--git"]);
+    insta::assert_snapshot!(stdout, @r###"
+    diff --git a/file b/file
+    new file mode 100644
+    index 0000000000..802b1c4ed7
+    --- /dev/null
+    +++ b/file
+    @@ -1,0 +1,1 @@
+    +contents 1
+    "###);
+    // The operations are preserved, although their ids have changed
+    let op_log = test_env.jj_cmd_success(&repo_path, &["op", "log"]);
+    assert_eq!(op_log.lines().count(), op_log_before.lines().count());
+
+    // And back again
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["debug", "migrate-store", "--backend=local"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Migrated 5 commits and 7 operations to the 'local' backend
+    "###);
+    assert!(!repo_path.join(".jj/repo/store/git").exists());
+    assert_eq!(get_log_output(&test_env, &repo_path), log_before);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "-r", "@", "file"]);
+    insta::assert_snapshot!(stdout, @r###"
+    contents 2
+    "###);
+}
+
+#[test]
+fn test_migrate_store_errors() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr =
+        test_env.jj_cmd_failure(&repo_path, &["debug", "migrate-store", "--backend=local"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The repo already uses the 'local' backend
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["workspace", "add", "../secondary"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["debug", "migrate-store", "--backend=git"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Can't migrate a repo with multiple workspaces
+    "###);
+}
@@ -97,6 +97,44 @@ fn test_new_merge() {
     test_env.jj_cmd_failure(&repo_path, &["new", "@", "root"]);
 }
 
+#[test]
+fn test_merge_dry_run() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "base\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "base"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "base"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file1"), "left\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "left"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "base", "-m", "right"]);
+    std::fs::write(repo_path.join("file1"), "right\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "right"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "base", "-m", "other"]);
+    std::fs::write(repo_path.join("file2"), "other\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "other"]);
+    let log_before = get_log_output(&test_env, &repo_path);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["merge", "--dry-run", "left", "right"]);
+    insta::assert_snapshot!(stdout, @r###"
+    The merge would have conflicts at these paths:
+      file1
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["merge", "--dry-run", "left", "other"]);
+    insta::assert_snapshot!(stdout, @r###"
+    The merge would not have conflicts
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["new", "--dry-run", "right", "other"]);
+    insta::assert_snapshot!(stdout, @r###"
+    The merge would not have conflicts
+    "###);
+
+    // Nothing was created
+    assert_eq!(get_log_output(&test_env, &repo_path), log_before);
+}
+
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
     test_env.jj_cmd_success(repo_path, &["log", "-T", "commit_id \" \" description"])
 }
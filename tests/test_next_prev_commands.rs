@@ -0,0 +1,121 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_next_prev() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "third"]);
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 8866677748c1 (no description set)
+    o ade2b7accfb6 third
+    o e4c4886e135d second
+    o 85a1e2839620 first
+    o 000000000000 (no description set)
+    "###);
+
+    // Walk down the stack, one change at a time
+    let stdout = test_env.jj_cmd_success(&repo_path, &["prev"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Working copy now at: 47e1bc45dba0 (no description set)
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 47e1bc45dba0 (no description set)
+    | o ade2b7accfb6 third
+    |/  
+    o e4c4886e135d second
+    o 85a1e2839620 first
+    o 000000000000 (no description set)
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["prev"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ d7fd0ae0d3a3 (no description set)
+    | o ade2b7accfb6 third
+    | o e4c4886e135d second
+    |/  
+    o 85a1e2839620 first
+    o 000000000000 (no description set)
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["prev", "2"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No parent revision found
+    "###);
+
+    // And back up again
+    test_env.jj_cmd_success(&repo_path, &["next", "2"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 3756af672eb4 (no description set)
+    o ade2b7accfb6 third
+    o e4c4886e135d second
+    o 85a1e2839620 first
+    o 000000000000 (no description set)
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["next"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No child revision found
+    "###);
+
+    // Edit the revisions directly
+    test_env.jj_cmd_success(&repo_path, &["prev", "--edit"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ ade2b7accfb6 third
+    o e4c4886e135d second
+    o 85a1e2839620 first
+    o 000000000000 (no description set)
+    "###);
+    test_env.jj_cmd_success(&repo_path, &["prev", "--edit"]);
+    test_env.jj_cmd_success(&repo_path, &["next", "--edit"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ ade2b7accfb6 third
+    o e4c4886e135d second
+    o 85a1e2839620 first
+    o 000000000000 (no description set)
+    "###);
+}
+
+#[test]
+fn test_next_ambiguous() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "@-", "-m", "third"]);
+    test_env.jj_cmd_success(&repo_path, &["edit", "@-"]);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["next", "--edit"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Ambiguous child revision; choose one of:
+      806bf377202a (third)
+      e4c4886e135d (second)
+    "###);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &["log", "-T", "commit_id.short() \" \" description"],
+    )
+}
@@ -36,7 +36,7 @@ fn test_obslog_with_or_without_diff() {
     insta::assert_snapshot!(stdout, @r###"
     @ 1daafc17fefb test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     | my description
-    o 813918f7b4e6 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
+    o 5a0462cd05a4 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
     | my description
     o 8f02f5470c55 test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     | my description
@@ -51,11 +51,10 @@ fn test_obslog_with_or_without_diff() {
     @ 1daafc17fefb test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     | my description
     | Resolved conflict in file1:
-    |    1    1: <<<<<<<resolved
-    |    2     : %%%%%%%
-    |    3     : +bar
-    |    4     : >>>>>>>
-    o 813918f7b4e6 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
+    | Side #1 (8f02f5470c55 my description):
+    |    1    1: fooresolved
+    |    2     : bar
+    o 5a0462cd05a4 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
     | my description
     o 8f02f5470c55 test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     | my description
@@ -73,7 +72,7 @@ fn test_obslog_with_or_without_diff() {
     insta::assert_snapshot!(stdout, @r###"
     1daafc17fefb test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     my description
-    813918f7b4e6 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
+    5a0462cd05a4 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
     my description
     8f02f5470c55 test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     my description
@@ -87,16 +86,16 @@ fn test_obslog_with_or_without_diff() {
     1daafc17fefb test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     my description
     diff --git a/file1 b/file1
-    index e155302a24...2ab19ae607 100644
+    index a95365f132...2ab19ae607 100644
     --- a/file1
     +++ b/file1
     @@ -1,4 +1,1 @@
     -<<<<<<<
-    -%%%%%%%
+    -%%%%%%% Changes from a75cc5f7570a (no description set) to 8f02f5470c55 my description
     -+bar
     ->>>>>>>
     +resolved
-    813918f7b4e6 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
+    5a0462cd05a4 test.user@example.com 2001-02-03 04:05:08.000 +07:00    conflict
     my description
     8f02f5470c55 test.user@example.com 2001-02-03 04:05:08.000 +07:00   
     my description
@@ -0,0 +1,76 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use regex::Regex;
+
+use crate::common::{get_stderr_string, TestEnvironment};
+
+pub mod common;
+
+#[test]
+fn test_post_operation_hook() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(
+        br#"[hooks]
+post-operation = ["sh", "-c", "cat > ../hook-input"]
+"#,
+    );
+    let hook_input_path = test_env.env_root().join("hook-input");
+
+    // The hook gets a summary of what the operation changed
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    let hook_input = std::fs::read_to_string(&hook_input_path).unwrap();
+    insta::assert_snapshot!(redact_op_ids(&hook_input), @r###"{"added_heads":[],"operation":{"description":"create branch main pointing to commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22","id":"$OP_ID","parents":["$OP_ID"]},"refs":[{"kind":"branch","name":"main","new":"230dd059e1b059aefc0da06a2e5a7dbf22362f22","old":null,"remote":null}],"removed_heads":[],"working_copies":{}}"###);
+
+    // Commands that don't change the repo don't run the hook
+    std::fs::remove_file(&hook_input_path).unwrap();
+    test_env.jj_cmd_success(&repo_path, &["log"]);
+    assert!(!hook_input_path.exists());
+
+    // Snapshotting the working copy runs the hook too
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["status"]);
+    let hook_input = std::fs::read_to_string(&hook_input_path).unwrap();
+    assert!(hook_input.contains(r#""description":"commit working copy""#));
+    assert!(hook_input.contains(r#""working_copies":{"default":"#));
+}
+
+#[test]
+fn test_post_operation_hook_failure() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"hooks.post-operation = ["false"]"#);
+
+    // The operation was already committed, so the command still succeeds
+    let assert = test_env
+        .jj_cmd(&repo_path, &["describe", "-m", "first"])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: The post-operation hook failed (exit status: 1)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    assert!(stdout.contains("first"));
+}
+
+fn redact_op_ids(output: &str) -> String {
+    // Operation ids depend on the time and host the operations ran at
+    let op_id_regex = Regex::new(r"[0-9a-f]{128}").unwrap();
+    op_id_regex.replace_all(output, "$$OP_ID").to_string()
+}
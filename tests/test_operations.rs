@@ -14,7 +14,7 @@
 
 use std::path::Path;
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, TestEnvironment};
 
 pub mod common;
 
@@ -83,16 +83,302 @@ fn test_op_log() {
     insta::assert_snapshot!(test_env.jj_cmd_failure(&repo_path, &["log", "--at-op", "@-"]), @r###"
     Error: The "@-" expression resolved to more than one operation
     "###);
-    test_env.jj_cmd_success(&repo_path, &["st"]);
+    let assert = test_env.jj_cmd(&repo_path, &["st"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Concurrent operations changed the working-copy commit of workspace 'default' differently. Keeping the one from the operation that finished first.
+    "###);
     insta::assert_snapshot!(test_env.jj_cmd_failure(&repo_path, &["log", "--at-op", "@-"]), @r###"
     Error: The "@-" expression resolved to more than one operation
     "###);
 }
 
+#[test]
+fn test_op_log_template_and_filters() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(b"[operation.tags]\nticket = \"123\"\n");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+
+    let template = r#"if(current_operation, "current ") description"#;
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", template]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ current describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    o add workspace 'default'
+    o initialize repo
+    "###);
+
+    // The configured tags are recorded on new operations
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "-T", "description", "--tag", "ticket=123"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "-T", "description", "--tag", "ticket"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "-T", "description", "--tag", "ticket=456"],
+    );
+    insta::assert_snapshot!(stdout, @"");
+
+    // Filtering by host
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log", "-T", "hostname"]);
+    let hostname = stdout.lines().next().unwrap()[2..].to_string();
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["op", "log", "-T", "description", "--hostname", &hostname],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ describe commit 230dd059e1b059aefc0da06a2e5a7dbf22362f22
+    o add workspace 'default'
+    o initialize repo
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "op",
+            "log",
+            "-T",
+            "description",
+            "--hostname",
+            "no-such-host",
+        ],
+    );
+    insta::assert_snapshot!(stdout, @"");
+}
+
+#[test]
+fn test_op_at_time() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    let time = chrono::Local::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, false);
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+
+    // Selects the latest operation that ended at or before the time
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-T", "description", "-r", "@", "--at-op", &time],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ first
+    ~
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-T", "description", "-r", "@", "--at-op", "@{now}"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ second
+    ~
+    "###);
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["log", "--at-op", "@{1.week.ago}"]);
+    assert!(stderr.starts_with(r#"Error: No operation ended at or before "@{1.week.ago}""#));
+
+    // `op restore` takes a time too
+    test_env.jj_cmd_success(&repo_path, &["op", "restore", &time]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description", "-r", "@"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ first
+    ~
+    "###);
+}
+
+#[test]
+fn test_op_export_import_view() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "main"]);
+    // Make another clone of the same store
+    let other_path = test_env.env_root().join("other");
+    copy_dir(&repo_path, &other_path);
+
+    // Importing the view replaces the branches and the working-copy commit
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "feature"]);
+    test_env.jj_cmd_success(&repo_path, &["op", "export-view", "../view"]);
+    test_env.jj_cmd_success(&other_path, &["branch", "delete", "main"]);
+    test_env.jj_cmd_success(&other_path, &["branch", "create", "local"]);
+    test_env.jj_cmd_success(&other_path, &["op", "import-view", "../view"]);
+    let stdout = test_env.jj_cmd_success(&other_path, &["branch", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    feature: 230dd059e1b0 (no description set)
+    main: 230dd059e1b0 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&other_path, &["op", "log", "-T", "description"]);
+    assert!(stdout.starts_with("@ import view from ../view\n"));
+
+    // The commits must already be in the repo
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "new"]);
+    test_env.jj_cmd_success(&repo_path, &["op", "export-view", "../view"]);
+    let stderr = test_env.jj_cmd_failure(&other_path, &["op", "import-view", "../view"]);
+    assert!(stderr.starts_with("Error: Commit "));
+    assert!(stderr.contains("from the view snapshot isn't in this repo"));
+
+    // Other files are rejected
+    std::fs::write(test_env.env_root().join("not-a-view"), "foo").unwrap();
+    let stderr = test_env.jj_cmd_failure(&other_path, &["op", "import-view", "../not-a-view"]);
+    let stderr = stderr.replace(&test_env.env_root().display().to_string(), "$TEST_ENV");
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to read $TEST_ENV/other/../not-a-view: Not a view snapshot
+    "###);
+}
+
+fn copy_dir(from: &Path, to: &Path) {
+    std::fs::create_dir(to).unwrap();
+    for entry in std::fs::read_dir(from).unwrap() {
+        let entry = entry.unwrap();
+        let to = to.join(entry.file_name());
+        if entry.file_type().unwrap().is_dir() {
+            copy_dir(&entry.path(), &to);
+        } else {
+            std::fs::copy(entry.path(), to).unwrap();
+        }
+    }
+}
+
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path, op_id: &str) -> String {
     test_env.jj_cmd_success(repo_path, &["log", "-T", "commit_id", "--at-op", op_id])
 }
 
+#[test]
+fn test_op_show() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"template.commit_summary = "description.first_line()""#);
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log"]);
+    let first_describe_id = stdout[2..14].to_string();
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "show"]);
+    insta::assert_snapshot!(redact_op_show(&stdout), @r###"
+    Working copy of default:
+      - first
+      + second
+    Heads:
+      - first
+      + second
+    "###);
+
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "foo"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "show"]);
+    insta::assert_snapshot!(redact_op_show(&stdout), @r###"
+    Branch foo:
+      + second
+    "###);
+
+    // Can show an earlier operation
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "show", &first_describe_id]);
+    insta::assert_snapshot!(redact_op_show(&stdout), @r###"
+    Working copy of default:
+      - (no description set)
+      + first
+    Heads:
+      - (no description set)
+      + first
+    "###);
+}
+
+#[test]
+fn test_op_abandon() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "third"]);
+    let op_ids = get_op_ids(&test_env, &repo_path);
+    assert_eq!(op_ids.len(), 5);
+
+    // Abandon everything before the last operation except the root operation
+    let stdout =
+        test_env.jj_cmd_success(&repo_path, &["op", "abandon", &format!("..{}", op_ids[1])]);
+    insta::assert_snapshot!(stdout, @r###"
+    Abandoned 3 operations and rewrote 1 operations on top of them.
+    "###);
+    let new_op_ids = get_op_ids(&test_env, &repo_path);
+    assert_eq!(new_op_ids.len(), 2);
+    assert_eq!(new_op_ids[1], op_ids[4]);
+    // The working copy was updated to the rewritten operation
+    let stdout = test_env.jj_cmd_success(&repo_path, &["log", "-T", "description"]);
+    insta::assert_snapshot!(stdout, @r###"
+    @ third
+    o (no description set)
+    "###);
+
+    // The current operation can't be abandoned
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["op", "abandon", "@"]);
+    assert!(stderr.starts_with("Error: Cannot abandon the current operation"));
+}
+
+#[test]
+fn test_op_abandon_expired() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["op", "abandon", "--expired"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Operations don't expire. Set `operation.expire-after-days` to expire them.
+    "###);
+
+    test_env.add_config(br#"operation.expire-after-days = 0"#);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "second"]);
+    // Operations are only expired when asked to
+    let op_ids = get_op_ids(&test_env, &repo_path);
+    assert_eq!(op_ids.len(), 4);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "abandon", "--expired"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Abandoned 2 operations and rewrote 1 operations on top of them.
+    "###);
+    let new_op_ids = get_op_ids(&test_env, &repo_path);
+    assert_eq!(new_op_ids.len(), 2);
+    // The rewritten operation shares the index of the original one instead of
+    // the index being rebuilt
+    let index_file_for_op = |op_id_prefix: &str| {
+        let operations_dir = repo_path.join(".jj/repo/index/operations");
+        std::fs::read_dir(operations_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap())
+            .find(|entry| {
+                entry
+                    .file_name()
+                    .to_str()
+                    .unwrap()
+                    .starts_with(op_id_prefix)
+            })
+            .map(|entry| std::fs::read(entry.path()).unwrap())
+    };
+    let old_index_file = index_file_for_op(&op_ids[0]);
+    assert!(old_index_file.is_some());
+    assert_eq!(index_file_for_op(&new_op_ids[0]), old_index_file);
+}
+
+/// Returns the operation ids in the operation log, newest first.
+fn get_op_ids(test_env: &TestEnvironment, repo_path: &Path) -> Vec<String> {
+    let stdout = test_env.jj_cmd_success(repo_path, &["op", "log"]);
+    stdout
+        .lines()
+        .filter(|line| line.starts_with("@ ") || line.starts_with("o "))
+        .map(|line| line[2..14].to_string())
+        .collect()
+}
+
 fn redact_op_log(stdout: &str) -> String {
     let mut lines = vec![];
     for line in stdout.lines() {
@@ -105,3 +391,10 @@ fn redact_op_log(stdout: &str) -> String {
     }
     lines.join("\n")
 }
+
+/// Drops the operation header (ID, user, timestamps, and description) from the
+/// output of `jj op show`.
+fn redact_op_show(stdout: &str) -> String {
+    let lines: Vec<_> = stdout.lines().skip(3).collect();
+    lines.join("\n") + "\n"
+}
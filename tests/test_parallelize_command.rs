@@ -0,0 +1,100 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_parallelize() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "c"]);
+    std::fs::write(repo_path.join("file3"), "c\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "d"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 751bffad8ab7 d
+    o c55e30e3c65b c
+    o 199e81259b87 b
+    o dab921f58255 a
+    o 000000000000 (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["parallelize", "@---:@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parallelized 3 commits
+    Working copy now at: 7e1329e2fd89 d
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @-.   7e1329e2fd89 d
+    |\ \  
+    o | | 7f8e1a7ce3dd c
+    | o | d43f8cff282d b
+    |/ /  
+    | o dab921f58255 a
+    |/  
+    o 000000000000 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "-r", "description(c)"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file3
+    "###);
+    // The merge commit on top still has all the changes
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    file1
+    file2
+    file3
+    "###);
+}
+
+#[test]
+fn test_parallelize_errors() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "a"]);
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "b"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "root", "-m", "c"]);
+
+    // Overlapping changes
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["parallelize", "description(a):"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: The changes in f5095ffcfa36 (b) overlap with the changes in the revisions before it
+    "###);
+
+    // Not a linear range
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["parallelize", "all() ~ root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Revisions must form a linear range, but f5095ffcfa36 is not the only parent of 31a341528992
+    "###);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &["log", "-T", "commit_id.short() \" \" description"],
+    )
+}
@@ -61,10 +61,10 @@ fn test_print() {
     let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file1"]);
     insta::assert_snapshot!(stdout, @r###"
     <<<<<<<
-    %%%%%%%
+    %%%%%%% Changes from 0ac173acd922 (no description set) to 414e1614246a (no description set)
     -b
     +a
-    +++++++
+    +++++++ Contents of 94e1bae2e305 (no description set)
     c
     >>>>>>>
     "###);
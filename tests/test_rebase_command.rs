@@ -348,6 +348,171 @@ fn test_rebase_with_descendants() {
     "###);
 }
 
+#[test]
+fn test_rebase_dry_run() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "base\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "base"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "base"]);
+    std::fs::write(repo_path.join("file"), "left\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "left"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "left"]);
+    test_env.jj_cmd_success(&repo_path, &["co", "base"]);
+    std::fs::write(repo_path.join("file"), "right\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "right"]);
+    test_env.jj_cmd_success(&repo_path, &["close", "-m", "right"]);
+    let log_before = get_log_output(&test_env, &repo_path);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["rebase", "-s", "right", "-d", "left", "--dry-run"],
+    );
+    assert!(stdout.starts_with("Would rebase 2 commits\n"), "{stdout}");
+    assert!(
+        stdout.contains(" (right) would cause conflicts at these paths:\n  file\n"),
+        "{stdout}"
+    );
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["rebase", "-r", "left", "-d", "base", "--dry-run"],
+    );
+    assert!(
+        stdout.ends_with("The rebase would not cause conflicts\n"),
+        "{stdout}"
+    );
+
+    // Nothing was rebased
+    assert_eq!(get_log_output(&test_env, &repo_path), log_before);
+
+    // Can't be combined with --interactive
+    test_env.jj_cmd_cli_error(&repo_path, &["rebase", "-i", "-d", "base", "--dry-run"]);
+}
+
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
     test_env.jj_cmd_success(repo_path, &["log", "-T", "branches"])
 }
+
+#[test]
+fn test_rebase_interactive() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let edit_script = test_env.set_up_fake_editor();
+
+    create_commit(&test_env, &repo_path, "a", &[]);
+    create_commit(&test_env, &repo_path, "b", &["a"]);
+    create_commit(&test_env, &repo_path, "c", &["b"]);
+    create_commit(&test_env, &repo_path, "d", &["c"]);
+    // Test the setup
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 
+    o d
+    o c
+    o b
+    o a
+    o
+    "###);
+
+    // The editor is initialized with the revisions to rebase, oldest first
+    let expected_todo = r#"pick 247da0ddee3d a
+pick 18db23c14b3c b
+pick 8949660d7fc7 c
+pick 2a4970a3803e d
+
+JJ: Rebase 4 revisions onto 000000000000
+JJ:
+JJ: Commands:
+JJ: p, pick <revision> = use the revision
+JJ: s, squash <revision> = fold the revision into the previous one
+JJ: f, fixup <revision> = like "squash", but discard the revision's description
+JJ: d, drop <revision> = remove the revision
+JJ:
+JJ: The lines can be reordered. Removing a line also drops the revision.
+JJ: Lines starting with "JJ:" are ignored.
+"#;
+    // Reorder, squash, and drop (by removing the line for "b")
+    let new_todo = "pick 8949660d7fc7 c\npick 247da0ddee3d a\nsquash 2a4970a3803e d\n";
+    std::fs::write(
+        &edit_script,
+        format!("expect\n{expected_todo}\0write\n{new_todo}"),
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["rebase", "-i", "-b", "d", "-d", "root"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 4 commits
+    Working copy now at: d44ef0e41ffc (no description set)
+    Added 0 files, modified 0 files, removed 1 files
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 
+    o a b d
+    o c
+    o
+    "###);
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "--no-graph", "-T", "description", "-r", "d"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    a
+
+    d
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files", "-r", "d"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a
+    c
+    d
+    "###);
+}
+
+#[test]
+fn test_rebase_interactive_invalid() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    let edit_script = test_env.set_up_fake_editor();
+
+    create_commit(&test_env, &repo_path, "a", &[]);
+    create_commit(&test_env, &repo_path, "b", &["a"]);
+    create_commit(&test_env, &repo_path, "c", &["a"]);
+    create_commit(&test_env, &repo_path, "d", &["b", "c"]);
+
+    // Both -i and -s
+    test_env.jj_cmd_cli_error(&repo_path, &["rebase", "-i", "-s", "a", "-d", "root"]);
+
+    // Non-linear history
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "-i", "-b", "d", "-d", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot rebase non-linear history interactively (revision 73862f5c6376 is a merge or has a parent outside the range)
+    "###);
+
+    // Squash without a previous revision
+    std::fs::write(
+        &edit_script,
+        "write\nsquash 247da0ddee3d a\npick 18db23c14b3c b\n",
+    )
+    .unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "-i", "-b", "b", "-d", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Cannot squash revision 247da0ddee3d without a previous revision
+    "###);
+
+    // Unknown action
+    std::fs::write(&edit_script, "write\nedit 247da0ddee3d a\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "-i", "-b", "b", "-d", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Unknown rebase action: edit
+    "###);
+
+    // Revision not in the list
+    std::fs::write(&edit_script, "write\npick 000000000000\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["rebase", "-i", "-b", "b", "-d", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Revision 000000000000 is not in the list of revisions to rebase
+    "###);
+}
@@ -0,0 +1,125 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use regex::Regex;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_reuse_resolutions() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"merge.reuse-resolutions = true"#);
+
+    std::fs::write(repo_path.join("file"), "a\nbase\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file"), "a\nleft\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "right", "@-"]);
+    std::fs::write(repo_path.join("file"), "a\nright\nb\n").unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "-m", "merge", "@", "description(left)"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["resolutions", "list"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // Resolving the conflict records the resolution
+    std::fs::write(repo_path.join("file"), "a\nleft and right\nb\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["resolutions", "list"]);
+    insta::assert_snapshot!(redact_resolution_ids(&stdout), @r###"
+    $ID: 2-sided conflict resolved to 1 lines
+    "###);
+
+    // The same conflict is resolved the same way when it appears again
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "description(right)", "description(left)"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(!stdout.contains("unresolved conflicts"));
+    let content = std::fs::read_to_string(repo_path.join("file")).unwrap();
+    assert_eq!(content, "a\nleft and right\nb\n");
+
+    // Forgotten resolutions aren't used anymore
+    let stdout = test_env.jj_cmd_success(&repo_path, &["resolutions", "forget", "--all"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Forgot 1 resolutions
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["resolutions", "list"]);
+    insta::assert_snapshot!(stdout, @"");
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "description(right)", "description(left)"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(stdout.contains("unresolved conflicts"));
+}
+
+#[test]
+fn test_reuse_resolutions_after_cached_merge() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "a\nbase\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file"), "a\nleft\nb\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "right", "@-"]);
+    std::fs::write(repo_path.join("file"), "a\nright\nb\n").unwrap();
+    // The conflicted merge is cached before resolutions are recorded
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "-m", "merge", "@", "description(left)"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(stdout.contains("unresolved conflicts"));
+
+    test_env.add_config(br#"merge.reuse-resolutions = true"#);
+    std::fs::write(repo_path.join("file"), "a\nleft and right\nb\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["resolutions", "list"]);
+    insta::assert_snapshot!(redact_resolution_ids(&stdout), @r###"
+    $ID: 2-sided conflict resolved to 1 lines
+    "###);
+
+    // Merging the same trees again uses the new resolution instead of the cached
+    // conflict
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "description(right)", "description(left)"],
+    );
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(!stdout.contains("unresolved conflicts"));
+    let content = std::fs::read_to_string(repo_path.join("file")).unwrap();
+    assert_eq!(content, "a\nleft and right\nb\n");
+}
+
+#[test]
+fn test_resolutions_disabled() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["resolutions", "list"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Conflict resolutions are not recorded. Set `merge.reuse-resolutions = true` to record them.
+    "###);
+}
+
+fn redact_resolution_ids(output: &str) -> String {
+    let id_regex = Regex::new(r"(?m)^[0-9a-f]{12}").unwrap();
+    id_regex.replace_all(output, "$$ID").to_string()
+}
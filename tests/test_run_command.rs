@@ -0,0 +1,67 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::{get_stderr_string, get_stdout_string, TestEnvironment};
+
+pub mod common;
+
+#[cfg(unix)]
+#[test]
+fn test_run() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    std::fs::write(repo_path.join("file1"), "first\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    std::fs::write(repo_path.join("file1"), "second\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "second\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &["run", "-r", "all() ~ root", "--", "cat", "file1"],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    first
+    3ecca5dfd6d3 first: passed
+    second
+    b951dbbadf5c second: passed
+    "###);
+
+    // The working copy is not affected by the command
+    let assert = test_env
+        .jj_cmd(
+            &repo_path,
+            &[
+                "run",
+                "-r",
+                "all() ~ root",
+                "--",
+                "sh",
+                "-c",
+                "rm file1 && test -f file2",
+            ],
+        )
+        .assert()
+        .code(1);
+    insta::assert_snapshot!(get_stdout_string(&assert), @r###"
+    3ecca5dfd6d3 first: failed (exit status: 1)
+    b951dbbadf5c second: passed
+    "###);
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    Error: The command failed on 1 of 2 revisions
+    "###);
+    assert!(repo_path.join("file1").exists());
+}
@@ -0,0 +1,259 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+#![cfg(unix)]
+
+use std::os::unix::fs::PermissionsExt;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+/// Configures a fake gpg that signs with a signature listing its arguments.
+/// When verifying, the result depends on the key in the signature.
+fn set_up_fake_gpg(test_env: &TestEnvironment) {
+    let program = test_env.env_root().join("fake-gpg");
+    std::fs::write(
+        &program,
+        r#"#!/bin/sh
+cat >/dev/null
+if [ "$3" != "--verify" ]; then
+  echo "fake signature $*"
+  exit 0
+fi
+case "$(cat "$4")" in
+  *bad-key*) echo "[GNUPG:] BADSIG BAD0 Bad Key" ;;
+  *unknown-key*) echo "[GNUPG:] ERRSIG UNKNOWN0 1 8 00 0 9" ;;
+  *never-key*) echo "[GNUPG:] GOODSIG NEVER0 Never Trusted"
+    echo "[GNUPG:] TRUST_NEVER 0" ;;
+  *) echo "[GNUPG:] GOODSIG GOOD0 Some One" ;;
+esac
+"#,
+    )
+    .unwrap();
+    std::fs::set_permissions(&program, std::fs::Permissions::from_mode(0o755)).unwrap();
+    test_env.add_config(
+        format!(
+            "[signing]\nbackend = \"gpg\"\nbackends.gpg.program = \"{}\"\n",
+            program.to_str().unwrap()
+        )
+        .as_bytes(),
+    );
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &["log", "-T", r#"description.first_line() " " signed"#],
+    )
+}
+
+#[test]
+fn test_sign() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["sign"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Config error: No signing backend configured (set `signing.backend` to "gpg" or "ssh")
+    "###);
+
+    set_up_fake_gpg(&test_env);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sign", "-r", "@-", "--key", "my-key"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Signed 1 commits
+    Rebased 1 descendant commits
+    Working copy now at: 5c237693856b second
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second false
+    o first true
+    o (no description set) false
+    "###);
+
+    // The signature is stored in the Git commit
+    let git_repo = git2::Repository::open(repo_path.join(".jj/repo/store/git")).unwrap();
+    let commit_id = test_env.jj_cmd_success(
+        &repo_path,
+        &["log", "-r", "@-", "--no-graph", "-T", "commit_id"],
+    );
+    let (sig, _data) = git_repo
+        .extract_signature(&git2::Oid::from_str(&commit_id).unwrap(), None)
+        .unwrap();
+    insta::assert_snapshot!(sig.as_str().unwrap(), @r###"
+    fake signature --detach-sign --armor --local-user my-key
+    "###);
+
+    // Signing a range signs parents before their children
+    let stdout = test_env.jj_cmd_success(&repo_path, &["sign", "-r", "root..@"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Signed 2 commits
+    Working copy now at: 74182a854f95 second
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second true
+    o first true
+    o (no description set) false
+    "###);
+}
+
+#[test]
+fn test_sign_all() {
+    let test_env = TestEnvironment::default();
+    // Use the local backend to test its signature storage
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo"]);
+    let repo_path = test_env.env_root().join("repo");
+    set_up_fake_gpg(&test_env);
+    test_env.add_config(b"signing.sign-all = true\n");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second true
+    o first true
+    o (no description set) false
+    "###);
+}
+
+#[test]
+fn test_sign_rewrite() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    set_up_fake_gpg(&test_env);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["sign", "-r", "@-"]);
+
+    // Rewriting a signed commit signs the new commit, but rebasing its unsigned
+    // descendants doesn't sign them
+    let stdout = test_env.jj_cmd_success(&repo_path, &["describe", "-r", "@-", "-m", "first!"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits
+    Working copy now at: a3c2d8434da9 second
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second false
+    o first! true
+    o (no description set) false
+    "###);
+}
+
+#[test]
+fn test_verify() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    set_up_fake_gpg(&test_env);
+    for key in ["bad-key", "unknown-key", "never-key", "good-key"] {
+        test_env.jj_cmd_success(&repo_path, &["new", "-m", key]);
+        test_env.jj_cmd_success(&repo_path, &["sign", "--key", key]);
+    }
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "unsigned"]);
+
+    let stdout = test_env.jj_cmd_success(
+        &repo_path,
+        &[
+            "log",
+            "-T",
+            r#"description.first_line() " " signature_status"#,
+        ],
+    );
+    insta::assert_snapshot!(stdout, @r###"
+    @ unsigned 
+    o good-key good
+    o never-key untrusted
+    o unknown-key unknown
+    o bad-key bad
+    o (no description set) 
+    o (no description set)
+    "###);
+
+    // The change id is random, so leave it out
+    let stdout = test_env.jj_cmd_success(&repo_path, &["show", "@-"]);
+    let stdout = stdout
+        .lines()
+        .filter(|line| !line.starts_with("Change ID: "))
+        .join("\n");
+    insta::assert_snapshot!(stdout, @r###"
+    Commit ID: eaddea61d0455ab47093ab50876c31ab1348969f
+    Author: Test User <test.user@example.com> (2001-02-03 04:05:14.000 +07:00)
+    Committer: Test User <test.user@example.com> (2001-02-03 04:05:15.000 +07:00)
+    Signature: good
+
+    good-key
+    "###);
+}
+
+#[test]
+fn test_sign_failure() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(b"[signing]\nbackend = \"ssh\"\n");
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["sign"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to sign commit 230dd059e1b0: The ssh signing backend requires `signing.key` to be set
+    "###);
+}
+
+#[test]
+fn test_sign_all_failure() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(b"[signing]\nbackend = \"ssh\"\nsign-all = true\n");
+
+    // Commands that write commits fail instead of panicking, including when
+    // snapshotting the working copy
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-m", "first"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to sign the commit: The ssh signing backend requires `signing.key` to be set
+    "###);
+    std::fs::write(repo_path.join("file"), "contents").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["status"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to sign the commit: The ssh signing backend requires `signing.key` to be set
+    "###);
+}
+
+#[test]
+fn test_sign_rewrite_failure() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    set_up_fake_gpg(&test_env);
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["sign", "-r", "@-"]);
+
+    // Rewriting a signed commit fails if it can't be signed again
+    test_env.add_config(b"[signing]\nbackend = \"ssh\"\n");
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["describe", "-r", "@-", "-m", "first!"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Failed to sign the commit: The ssh signing backend requires `signing.key` to be set
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ second false
+    o first true
+    o (no description set) false
+    "###);
+}
@@ -58,3 +58,70 @@ fn test_split() {
     A file3
     "###);
 }
+
+#[test]
+fn test_split_into_more_parts() {
+    let mut test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "foo").unwrap();
+    std::fs::write(repo_path.join("file2"), "foo").unwrap();
+    std::fs::write(repo_path.join("file3"), "foo").unwrap();
+
+    let edit_script = test_env.set_up_fake_editor();
+    std::fs::write(edit_script, "").unwrap();
+    let diff_edit_script = test_env.set_up_fake_diff_editor();
+    std::fs::write(
+        &diff_edit_script,
+        [
+            "reset file2",
+            "reset file3",
+            "next_invocation",
+            "reset file3",
+        ]
+        .join("\0"),
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["split", "--into", "3"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Part 1: 9919839cbbec (no description set)
+    Part 2: 103e85a9d0cc (no description set)
+    Part 3: 7579852016f0 (no description set)
+    Working copy now at: 7579852016f0 (no description set)
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "-r", "@--"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file1
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "-r", "@-"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file2
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file3
+    "###);
+
+    // If the diff editor makes no changes in a later round, the remaining changes
+    // all go into that part
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    std::fs::write(
+        &diff_edit_script,
+        ["reset file3", "next_invocation", ""].join("\0"),
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["split", "--into", "4"]);
+    insta::assert_snapshot!(stdout, @r###"
+    First part: 69dea81b4105 (no description set)
+    Second part: c085a4171b4d (no description set)
+    Working copy now at: c085a4171b4d (no description set)
+    "###);
+
+    // Can't split into fewer than two parts
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["split", "--into", "1"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Can't split a revision into fewer than two parts
+    "###);
+}
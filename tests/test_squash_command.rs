@@ -231,6 +231,28 @@ fn test_squash_partial() {
     insta::assert_snapshot!(stdout, @r###"
     b
     "###);
+
+    // In interactive mode, only changes to the given paths are shown in the
+    // diff-editor
+    test_env.jj_cmd_success(&repo_path, &["undo"]);
+    std::fs::write(
+        &edit_script,
+        ["files-before file2", "files-after JJ-INSTRUCTIONS file2"].join("\0"),
+    )
+    .unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["squash", "-r", "b", "-i", "file2"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Rebased 1 descendant commits
+    Working copy now at: 3136a7f21757 (no description set)
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file1", "-r", "a"]);
+    insta::assert_snapshot!(stdout, @r###"
+    a
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file2", "-r", "a"]);
+    insta::assert_snapshot!(stdout, @r###"
+    b
+    "###);
 }
 
 fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
@@ -0,0 +1,144 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use std::path::Path;
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_stash_push_pop() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new"]);
+    std::fs::write(repo_path.join("file1"), "b\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stash", "push", "-m", "wip"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Saved working copy changes as stash 0: 711e3c8b109f wip
+    Working copy now at: 7ff0b4fa0ac2 (no description set)
+    Added 0 files, modified 1 files, removed 1 files
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 7ff0b4fa0ac2  (no description set)
+    | o 711e3c8b109f stash-0 wip
+    |/  
+    o 414e1614246a  (no description set)
+    o 000000000000  (no description set)
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file1")).unwrap(),
+        "a\n"
+    );
+    assert!(!repo_path.join("file2").exists());
+
+    // Nothing left to stash
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["stash", "push"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No changes to stash
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stash", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    0: 711e3c8b109f wip
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stash", "pop"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Restored stash 0
+    Working copy now at: 576aea5a41da wip
+    Added 1 files, modified 1 files, removed 0 files
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &repo_path), @r###"
+    @ 576aea5a41da  wip
+    o 414e1614246a  (no description set)
+    o 000000000000  (no description set)
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file1")).unwrap(),
+        "b\n"
+    );
+    assert_eq!(
+        std::fs::read_to_string(repo_path.join("file2")).unwrap(),
+        "b\n"
+    );
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["stash", "pop"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: There are no stashes
+    "###);
+}
+
+#[test]
+fn test_stash_named() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "a\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["stash", "push", "--name", "first"]);
+    std::fs::write(repo_path.join("file2"), "b\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["stash", "push"]);
+    std::fs::write(repo_path.join("file3"), "c\n").unwrap();
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["stash", "push", "--name", "first"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: Stash first already exists
+    "###);
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stash", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    0: 0cb24f0d501c (no description set)
+    first: 414e1614246a (no description set)
+    "###);
+
+    // Popping by name applies the stash on top of the current changes
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stash", "pop", "first"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Restored stash first
+    Working copy now at: f7293f6c3812 (no description set)
+    Added 1 files, modified 0 files, removed 0 files
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A file1
+    A file3
+    "###);
+
+    let stderr = test_env.jj_cmd_failure(&repo_path, &["stash", "pop", "first"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: No such stash: first
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["stash", "list"]);
+    insta::assert_snapshot!(stdout, @r###"
+    0: 0cb24f0d501c (no description set)
+    "###);
+}
+
+fn get_log_output(test_env: &TestEnvironment, repo_path: &Path) -> String {
+    test_env.jj_cmd_success(
+        repo_path,
+        &[
+            "log",
+            "-r",
+            "all()",
+            "-T",
+            r#"commit_id.short() " " branches " " description"#,
+        ],
+    )
+}
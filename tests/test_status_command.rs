@@ -0,0 +1,84 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_status_conflicts() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file1"), "base\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "base\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file1"), "left\n").unwrap();
+    std::fs::write(repo_path.join("file2"), "left\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "right", "@-"]);
+    std::fs::write(repo_path.join("file1"), "right\n").unwrap();
+    std::fs::remove_file(repo_path.join("file2")).unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "-m", "merge", "@", "description(left)"],
+    );
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Parent commit: b6a15a7902bd right
+    Working copy : 62ede4f5159d merge
+    Working copy changes:
+    M file1
+    A file2
+    There are unresolved conflicts at these paths:
+    file1    2-sided content conflict
+    file2    2-sided modify/delete conflict
+      Edit the conflict markers in the files to resolve the conflicts.
+    "###);
+}
+
+#[test]
+fn test_status_file_directory_conflict() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    std::fs::write(repo_path.join("file"), "base\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "left"]);
+    std::fs::write(repo_path.join("file"), "left\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "right", "@-"]);
+    std::fs::remove_file(repo_path.join("file")).unwrap();
+    std::fs::create_dir(repo_path.join("file")).unwrap();
+    std::fs::write(repo_path.join("file").join("a"), "a\n").unwrap();
+    test_env.jj_cmd_success(
+        &repo_path,
+        &["new", "-m", "merge", "@", "description(left)"],
+    );
+
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(stdout.contains("file    2-sided file/directory conflict\n"));
+    // The conflict is described in a regular file
+    let content = std::fs::read_to_string(repo_path.join("file")).unwrap();
+    assert!(content.starts_with("Conflict (file/directory):\n"));
+    assert!(content.contains("    left\n"));
+    assert!(content.contains("    file/a\n"));
+
+    // Replacing the description by the directory resolves the conflict
+    std::fs::remove_file(repo_path.join("file")).unwrap();
+    std::fs::create_dir(repo_path.join("file")).unwrap();
+    std::fs::write(repo_path.join("file").join("a"), "a\n").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["status"]);
+    assert!(!stdout.contains("unresolved conflicts"));
+}
@@ -126,9 +126,9 @@ fn test_touchup_merge() {
     .unwrap();
     let stdout = test_env.jj_cmd_success(&repo_path, &["touchup", "-r", "@-"]);
     insta::assert_snapshot!(stdout, @r###"
-    Created cb2b3b755c0a merge
+    Created 4803ec6a1874 merge
     Rebased 1 descendant commits
-    Working copy now at: 9c86af62d473 (no description set)
+    Working copy now at: b5bf04689dc3 (no description set)
     Added 0 files, modified 0 files, removed 1 files
     "###);
     let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "-s", "-r", "@-"]);
@@ -140,10 +140,10 @@ fn test_touchup_merge() {
     let stdout = test_env.jj_cmd_success(&repo_path, &["print", "file2"]);
     insta::assert_snapshot!(stdout, @r###"
     <<<<<<<
-    %%%%%%%
+    %%%%%%% Changes from 2375fa164210 (no description set) to 31efa657b426 (no description set)
     -a
     +c
-    +++++++
+    +++++++ Contents of a06a17414fe8 (no description set)
     b
     >>>>>>>
     "###);
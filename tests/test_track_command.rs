@@ -0,0 +1,89 @@
+// Copyright 2022 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::common::TestEnvironment;
+
+pub mod common;
+
+#[test]
+fn test_track_auto_track_none() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.auto-track = "none""#);
+
+    // New files are not tracked automatically
+    std::fs::write(repo_path.join("file1"), "1").unwrap();
+    std::fs::write(repo_path.join("file2"), "2").unwrap();
+    std::fs::create_dir(repo_path.join("dir")).unwrap();
+    std::fs::write(repo_path.join("dir").join("file3"), "3").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @"");
+
+    // They can be tracked explicitly, also by directory
+    let stdout = test_env.jj_cmd_success(&repo_path, &["track", "file1", "dir"]);
+    insta::assert_snapshot!(stdout, @"");
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    dir/file3
+    file1
+    "###);
+
+    // Tracked files are snapshotted as usual
+    std::fs::write(repo_path.join("file1"), "1 modified").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["diff", "--summary"]);
+    insta::assert_snapshot!(stdout, @r###"
+    A dir/file3
+    A file1
+    "###);
+
+    // Tracking an already tracked file does nothing
+    let stdout = test_env.jj_cmd_success(&repo_path, &["track", "file1"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing changed.
+    "###);
+
+    // Untracked files don't need to be ignored to be untracked
+    test_env.jj_cmd_success(&repo_path, &["untrack", "file1"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    dir/file3
+    "###);
+}
+
+#[test]
+fn test_track_auto_track_patterns() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+    test_env.add_config(br#"snapshot.auto-track = ["*.rs", "!/generated/"]"#);
+
+    std::fs::write(repo_path.join("main.rs"), "").unwrap();
+    std::fs::write(repo_path.join("notes.txt"), "").unwrap();
+    std::fs::create_dir(repo_path.join("generated")).unwrap();
+    std::fs::write(repo_path.join("generated").join("lib.rs"), "").unwrap();
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    main.rs
+    "###);
+
+    // Ignored files are not tracked even when asked to
+    std::fs::write(repo_path.join(".gitignore"), "*.txt\n").unwrap();
+    test_env.jj_cmd_success(&repo_path, &["track", "notes.txt", ".gitignore"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["files"]);
+    insta::assert_snapshot!(stdout, @r###"
+    .gitignore
+    main.rs
+    "###);
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::common::TestEnvironment;
+use crate::common::{get_stderr_string, TestEnvironment};
 
 pub mod common;
 
@@ -46,3 +46,35 @@ fn test_undo_rewrite_with_child() {
     o (no description set)
     "###);
 }
+
+#[test]
+fn test_undo_earlier_operation_conflicting_ref() {
+    // Test that undoing an operation whose ref changes were changed again by a
+    // later operation leaves the ref conflicted and says so
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "repo", "--git"]);
+    let repo_path = test_env.env_root().join("repo");
+
+    test_env.jj_cmd_success(&repo_path, &["describe", "-m", "first"]);
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "second"]);
+    test_env.jj_cmd_success(&repo_path, &["branch", "create", "foo", "-r", "@-"]);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["op", "log"]);
+    let op_id_hex = stdout[2..14].to_string();
+    test_env.jj_cmd_success(&repo_path, &["branch", "set", "foo", "-r", "@"]);
+
+    let assert = test_env
+        .jj_cmd(&repo_path, &["undo", &op_id_hex])
+        .assert()
+        .success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @r###"
+    warning: Later operations changed some of the refs the undone operation changed. These refs are now conflicted:
+      branch foo
+    "###);
+    let stdout = test_env.jj_cmd_success(&repo_path, &["branch", "list"]);
+    assert!(stdout.starts_with("foo (conflicted):"));
+
+    // Undoing an operation nothing later depends on doesn't warn
+    test_env.jj_cmd_success(&repo_path, &["new", "-m", "third"]);
+    let assert = test_env.jj_cmd(&repo_path, &["undo"]).assert().success();
+    insta::assert_snapshot!(get_stderr_string(&assert), @"");
+}
@@ -14,7 +14,7 @@
 
 use std::path::Path;
 
-use itertools::Itertools;
+use regex::Regex;
 
 use crate::common::TestEnvironment;
 
@@ -112,28 +112,79 @@ fn test_workspaces_conflicting_edits() {
     o 52601f748bf6cb00ad5389922f530f20a7ecffaa 
     o 0000000000000000000000000000000000000000 
     "###);
-    let stdout = get_log_output(&test_env, &secondary_path);
-    // It was detected that the working copy is now stale
-    // TODO: Since there was an uncommitted change in the working copy, it should
-    // have been committed first (causing divergence)
-    assert!(stdout.starts_with("The working copy is stale"));
-    insta::assert_snapshot!(stdout.lines().skip(1).join("\n"), @r###"
-    o 86bef7fee095bb5626d853c222764fc7c9fb88ac default@
-    | @ 8d8269a323a01a287236c4fd5f64dc9737febb5b secondary@
+    // It was detected that the working copy is now stale, and it isn't updated
+    // automatically, since that would lose the uncommitted change in it
+    let stderr = test_env.jj_cmd_failure(&secondary_path, &["log"]);
+    insta::assert_snapshot!(redact_op_ids(&stderr), @r###"
+    Error: The working copy is stale (not updated since operation <op>).
+    Run `jj workspace update-stale` to update it.
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(secondary_path.join("file")).unwrap(),
+        "changed in second\n"
+    );
+
+    // Updating the stale working copy merges the uncommitted change into it
+    let stdout = test_env.jj_cmd_success(&secondary_path, &["workspace", "update-stale"]);
+    insta::assert_snapshot!(redact_op_ids(&stdout), @r###"
+    Merged changes made since operation <op> into the working-copy commit
+    Working copy now at: 98ddd8c3016d (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    let contents = std::fs::read_to_string(secondary_path.join("file")).unwrap();
+    insta::assert_snapshot!(contents, @r###"
+    <<<<<<<
+    %%%%%%%
+    -contents
+    +changed in main
+    +++++++
+    changed in second
+    >>>>>>>
+    "###);
+    insta::assert_snapshot!(get_log_output(&test_env, &secondary_path), @r###"
+    @ 98ddd8c3016d5d01eacd714bd5348a639c010163 secondary@
+    | o 86bef7fee095bb5626d853c222764fc7c9fb88ac default@
     |/  
     o 52601f748bf6cb00ad5389922f530f20a7ecffaa 
-    o 0000000000000000000000000000000000000000 
+    o 0000000000000000000000000000000000000000
+    "###);
+    let stdout = test_env.jj_cmd_success(&secondary_path, &["workspace", "update-stale"]);
+    insta::assert_snapshot!(stdout, @r###"
+    Nothing to do (the working copy is not stale).
     "###);
+}
 
-    // The stale working copy should have been resolved by the previous command
-    let stdout = get_log_output(&test_env, &secondary_path);
-    assert!(!stdout.starts_with("The working copy is stale"));
+/// Test updating a stale workspace without uncommitted changes
+#[test]
+fn test_workspaces_update_stale_unchanged() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "main"]);
+    let main_path = test_env.env_root().join("main");
+    let secondary_path = test_env.env_root().join("secondary");
+
+    std::fs::write(main_path.join("file"), "contents\n").unwrap();
+    test_env.jj_cmd_success(&main_path, &["close", "-m", "initial"]);
+    test_env.jj_cmd_success(&main_path, &["workspace", "add", "../secondary"]);
+
+    // Rewrite the secondary workspace's parent from the main workspace
+    std::fs::write(main_path.join("file"), "changed in main\n").unwrap();
+    test_env.jj_cmd_success(&main_path, &["squash"]);
+
+    let stdout = test_env.jj_cmd_success(&secondary_path, &["workspace", "update-stale"]);
     insta::assert_snapshot!(stdout, @r###"
-    o 86bef7fee095bb5626d853c222764fc7c9fb88ac default@
-    | @ 8d8269a323a01a287236c4fd5f64dc9737febb5b secondary@
+    Working copy now at: cebfb531222c (no description set)
+    Added 0 files, modified 1 files, removed 0 files
+    "###);
+    assert_eq!(
+        std::fs::read_to_string(secondary_path.join("file")).unwrap(),
+        "changed in main\n"
+    );
+    insta::assert_snapshot!(get_log_output(&test_env, &secondary_path), @r###"
+    o 21a0bc34f0c2a44c357d9f13ede98651a250db15 default@
+    | @ cebfb531222ce244453df9fd7eb597c63ce3c50c secondary@
     |/  
-    o 52601f748bf6cb00ad5389922f530f20a7ecffaa 
-    o 0000000000000000000000000000000000000000 
+    o 67480590ad090edfc53a2c0a222be65fa3f2d722 
+    o 0000000000000000000000000000000000000000
     "###);
 }
 
@@ -197,6 +248,42 @@ fn test_workspaces_forget() {
     insta::assert_snapshot!(stdout, @"");
 }
 
+/// Test showing the workspace root
+#[test]
+fn test_workspaces_root() {
+    let test_env = TestEnvironment::default();
+    test_env.jj_cmd_success(test_env.env_root(), &["init", "--git", "main"]);
+    let main_path = test_env.env_root().join("main");
+    let subdir_path = main_path.join("subdir");
+    std::fs::create_dir(&subdir_path).unwrap();
+    std::fs::write(main_path.join("file"), "contents").unwrap();
+    test_env.jj_cmd_success(&main_path, &["close", "-m", "initial"]);
+    test_env.jj_cmd_success(&main_path, &["workspace", "add", "../secondary"]);
+    let secondary_path = test_env.env_root().join("secondary");
+
+    let expected = format!("{}\n", main_path.canonicalize().unwrap().display());
+    let stdout = test_env.jj_cmd_success(&main_path, &["workspace", "root"]);
+    assert_eq!(stdout, expected);
+    let stdout = test_env.jj_cmd_success(&subdir_path, &["workspace", "root"]);
+    assert_eq!(stdout, expected);
+
+    let stdout = test_env.jj_cmd_success(&secondary_path, &["workspace", "root"]);
+    assert_eq!(
+        stdout,
+        format!("{}\n", secondary_path.canonicalize().unwrap().display())
+    );
+
+    let stderr = test_env.jj_cmd_failure(test_env.env_root(), &["workspace", "root"]);
+    insta::assert_snapshot!(stderr, @r###"
+    Error: There is no jj repo in "."
+    "###);
+}
+
 fn get_log_output(test_env: &TestEnvironment, cwd: &Path) -> String {
     test_env.jj_cmd_success(cwd, &["log", "-T", r#"commit_id " " working_copies"#])
 }
+
+fn redact_op_ids(output: &str) -> String {
+    let regex = Regex::new("operation [0-9a-f]{12}").unwrap();
+    regex.replace_all(output, "operation <op>").to_string()
+}